@@ -0,0 +1,393 @@
+//! [`embassy-usb`] HID class integration for [`embedded_keyboard`].
+//!
+//! The keyboard is a boot protocol keyboard on an `embassy-usb`
+//! [`HidReaderWriter`], created with [`config`]. Its writer half is driven
+//! by [`write_reports`], which scans the [`Keyboard`] at a fixed period,
+//! runs the events through the keymap [`Engine`] and writes a
+//! [`BootKeyboardReport`] whenever the pressed keys change. Its reader
+//! half is driven by [`read_leds`], which hands the [`LedState`] set by the
+//! host to the application.
+//!
+//! Both are meant to be awaited from their own tasks, or joined in one.
+//!
+//! Applications which do more with the keyboard than send its reports
+//! over USB can instead hand it to [`run`], which owns the keyboard and
+//! keymap and publishes through the [`KeyboardChannels`] it is given: key
+//! events on a [`Channel`] for the application to consume, and reports on
+//! a [`Signal`], sent over USB by [`write_signaled_reports`]:
+//!
+//! ```ignore
+//! static CHANNELS: KeyboardChannels<CriticalSectionRawMutex, 8> = KeyboardChannels::new();
+//!
+//! #[embassy_executor::task]
+//! async fn keyboard(matrix: Matrix, engine: Engine<2, 4, 12>) {
+//!     let error = run(matrix, engine, Duration::from_millis(1), &CHANNELS).await;
+//!     defmt::error!("keyboard stopped: {}", error);
+//! }
+//!
+//! #[embassy_executor::task]
+//! async fn hid(mut writer: HidWriter<'static, Usb, INPUT_LEN>) {
+//!     let _ = write_signaled_reports(&mut writer, &CHANNELS.report).await;
+//! }
+//!
+//! #[embassy_executor::task]
+//! async fn blinky(mut led: Output<'static>) {
+//!     loop {
+//!         if let KeyEvent::KeyDown(_) = CHANNELS.events.receive().await {
+//!             led.toggle();
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! [`embassy-usb`]: embassy_usb
+//! [`HidReaderWriter`]: embassy_usb::class::hid::HidReaderWriter
+//! [`Channel`]: embassy_sync::channel::Channel
+//! [`Signal`]: embassy_sync::signal::Signal
+
+#![no_std]
+
+use core::convert::Infallible;
+
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::channel::Channel;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant, Ticker};
+use embassy_usb::class::hid::{Config, HidReader, HidWriter, ReportId, RequestHandler};
+use embassy_usb::control::OutResponse;
+use embassy_usb::driver::{Driver, EndpointError};
+use embedded_keyboard::engine::{Engine, LayerObserver};
+use embedded_keyboard::hid::{BootKeyboardReport, ChangeDetector, LedState};
+use embedded_keyboard::time::Clock;
+use embedded_keyboard::{KeyEvent, Keyboard};
+
+/// HID report descriptor of the keyboard.
+pub const DESCRIPTOR: &[u8] = &BootKeyboardReport::DESCRIPTOR;
+
+/// Size of the input report, for the writer half's `N` parameter.
+pub const INPUT_LEN: usize = BootKeyboardReport::LEN;
+
+/// Size of the LED output report, for the reader half's `N` parameter.
+pub const OUTPUT_LEN: usize = 1;
+
+/// Errors ending [`write_reports`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    /// Scanning the keyboard failed
+    Keyboard(E),
+    /// Writing a report failed
+    Usb(EndpointError),
+}
+
+/// HID class configuration for the keyboard, polled by the host every
+/// `poll_ms` milliseconds.
+#[must_use]
+pub fn config<'d>(poll_ms: u8) -> Config<'d> {
+    Config {
+        report_descriptor: DESCRIPTOR,
+        request_handler: None,
+        poll_ms,
+        max_packet_size: 8,
+    }
+}
+
+/// Scan `keyboard` every `period`, run its events through `engine` and
+/// write a report to `writer` whenever the pressed keys change.
+///
+/// The engine is fed the time of [`EmbassyClock`].
+/// Waits for the host to configure the device before the first report,
+/// which is sent even if nothing is pressed, so that restarting the task,
+/// e.g. on resume from suspend, brings the host up to date.
+///
+/// # Errors
+///
+/// Returns when a scan or a write fails. A write fails e.g. when the
+/// device is unplugged, after which the task can be restarted.
+pub async fn write_reports<'d, D, K, O, const LAYERS: usize, const ROWS: usize, const COLS: usize>(
+    writer: &mut HidWriter<'d, D, INPUT_LEN>,
+    keyboard: &mut K,
+    engine: &mut Engine<LAYERS, ROWS, COLS, O>,
+    period: Duration,
+) -> Result<Infallible, Error<K::Error>>
+where
+    D: Driver<'d>,
+    K: Keyboard,
+    O: LayerObserver,
+{
+    let mut ticker = Ticker::every(period);
+    let mut sent = ChangeDetector::new(BootKeyboardReport::new());
+    sent.force_report();
+
+    writer.ready().await;
+
+    loop {
+        let report = scan(keyboard, engine, |_| {}).map_err(Error::Keyboard)?;
+
+        if let Some(report) = sent.update(report) {
+            let mut buf = [0; INPUT_LEN];
+            report.serialize(&mut buf);
+            writer.write(&buf).await.map_err(Error::Usb)?;
+        }
+
+        ticker.next().await;
+    }
+}
+
+/// Read LED output reports from `reader`, handing every new LED state to
+/// `leds`.
+pub async fn read_leds<'d, D: Driver<'d>>(
+    reader: HidReader<'d, D, OUTPUT_LEN>,
+    leds: impl FnMut(LedState),
+) -> ! {
+    reader.run(false, &mut LedHandler(leds)).await
+}
+
+/// Channels through which [`run`] publishes the keyboard to the rest of
+/// the application, usually declared as a `static`.
+pub struct KeyboardChannels<M: RawMutex, const N: usize> {
+    /// Key events, in the order they are detected. Events are dropped
+    /// while the channel is full.
+    pub events: Channel<M, KeyEvent, N>,
+    /// Latest report, signaled whenever the pressed keys change.
+    pub report: Signal<M, BootKeyboardReport>,
+}
+
+impl<M: RawMutex, const N: usize> KeyboardChannels<M, N> {
+    /// Create empty channels.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            events: Channel::new(),
+            report: Signal::new(),
+        }
+    }
+}
+
+impl<M: RawMutex, const N: usize> Default for KeyboardChannels<M, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Scan `keyboard` every `period` and run its events through `engine`,
+/// publishing the events and reports on `channels`.
+///
+/// The engine is fed the time of [`EmbassyClock`]. The first report is
+/// signaled even if nothing is pressed, so that the host is brought up to
+/// date. Scanning never waits for the consumers: events are dropped while
+/// the event channel is full, and a report signaled before the previous
+/// one was taken replaces it.
+///
+/// Returns the keyboard's error when a scan fails.
+pub async fn run<
+    K,
+    O,
+    M,
+    const N: usize,
+    const LAYERS: usize,
+    const ROWS: usize,
+    const COLS: usize,
+>(
+    mut keyboard: K,
+    mut engine: Engine<LAYERS, ROWS, COLS, O>,
+    period: Duration,
+    channels: &KeyboardChannels<M, N>,
+) -> K::Error
+where
+    K: Keyboard,
+    O: LayerObserver,
+    M: RawMutex,
+{
+    let mut ticker = Ticker::every(period);
+    let mut sent = ChangeDetector::new(BootKeyboardReport::new());
+    sent.force_report();
+
+    loop {
+        let report = scan(&mut keyboard, &mut engine, |event| {
+            let _ = channels.events.try_send(event);
+        });
+
+        match report {
+            Ok(report) => {
+                if let Some(report) = sent.update(report) {
+                    channels.report.signal(report);
+                }
+            }
+            Err(error) => return error,
+        }
+
+        ticker.next().await;
+    }
+}
+
+/// Write every report signaled on `report` to `writer`, e.g. by [`run`].
+///
+/// Waits for the host to configure the device before the first report.
+///
+/// # Errors
+///
+/// Returns when a write fails, e.g. when the device is unplugged, after
+/// which the task can be restarted. The report which failed is lost, so
+/// the producer should be made to signal its next report regardless.
+pub async fn write_signaled_reports<'d, D: Driver<'d>, M: RawMutex>(
+    writer: &mut HidWriter<'d, D, INPUT_LEN>,
+    report: &Signal<M, BootKeyboardReport>,
+) -> Result<Infallible, EndpointError> {
+    writer.ready().await;
+
+    loop {
+        let report = report.wait().await;
+
+        let mut buf = [0; INPUT_LEN];
+        report.serialize(&mut buf);
+        writer.write(&buf).await?;
+    }
+}
+
+/// Scan `keyboard` once and run its events through `engine`, handing
+/// every event to `event`. Returns the report of the keys pressed.
+fn scan<K, O, const LAYERS: usize, const ROWS: usize, const COLS: usize>(
+    keyboard: &mut K,
+    engine: &mut Engine<LAYERS, ROWS, COLS, O>,
+    mut event: impl FnMut(KeyEvent),
+) -> Result<BootKeyboardReport, K::Error>
+where
+    K: Keyboard,
+    O: LayerObserver,
+{
+    let now = EmbassyClock.now();
+    let events = keyboard.scan()?;
+
+    engine.tick(now);
+    engine.events(events);
+    events
+        .iter()
+        .filter(|e| **e != KeyEvent::NoEvent)
+        .for_each(|e| event(*e));
+
+    let mut report = BootKeyboardReport::new();
+    report.extend(engine.usages());
+    Ok(report)
+}
+
+/// [`Clock`] reading the milliseconds since boot from `embassy-time`,
+/// truncated to `u32`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EmbassyClock;
+
+impl Clock for EmbassyClock {
+    fn now(&mut self) -> u32 {
+        u32::try_from(Instant::now().as_millis() & u64::from(u32::MAX)).unwrap_or_default()
+    }
+}
+
+/// Request handler passing LED output reports on.
+struct LedHandler<F>(F);
+
+impl<F: FnMut(LedState)> RequestHandler for LedHandler<F> {
+    fn set_report(&mut self, _id: ReportId, data: &[u8]) -> OutResponse {
+        match LedState::from_report(data) {
+            Some(leds) => {
+                (self.0)(leds);
+                OutResponse::Accepted
+            }
+            None => OutResponse::Rejected,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+    use embedded_keyboard::fake::FakeKeyboard;
+    use embedded_keyboard::{Action, Coordinate, ErrorKind, KeyCode, Keymap};
+
+    const A: Coordinate = Coordinate::new(0, 0);
+    const B: Coordinate = Coordinate::new(0, 1);
+    const KEYMAP: Keymap<1, 1, 2> =
+        Keymap::new([[[Action::Key(KeyCode::KA), Action::layer_tap(0, KeyCode::KB)]]]);
+
+    #[test]
+    fn run_publishes_events_and_reports() {
+        const SCANS: &[&[KeyEvent]] = &[
+            &[KeyEvent::KeyDown(A), KeyEvent::NoEvent],
+            &[],
+            &[],
+            &[KeyEvent::KeyUp(A), KeyEvent::KeyDown(B)],
+        ];
+
+        let channels = KeyboardChannels::<NoopRawMutex, 4>::new();
+        let mut keyboard = FakeKeyboard::new(SCANS);
+        keyboard.fail_at(2);
+
+        // The empty report, then A pressed, until the scan failing.
+        let error = embassy_futures::block_on(run(
+            &mut keyboard,
+            Engine::new(KEYMAP),
+            Duration::from_millis(1),
+            &channels,
+        ));
+        assert_eq!(error, ErrorKind::Other);
+        assert_eq!(channels.events.try_receive(), Ok(KeyEvent::KeyDown(A)));
+        assert!(channels.events.try_receive().is_err());
+
+        let mut pressed = BootKeyboardReport::new();
+        pressed.extend([KeyCode::KA.into()]);
+        assert_eq!(channels.report.try_take(), Some(pressed));
+
+        // A restarted task signals its first report again, with the
+        // pending hold-tap key waiting for its tapping term.
+        keyboard.fail_at(4);
+        let error = embassy_futures::block_on(run(
+            &mut keyboard,
+            Engine::new(KEYMAP),
+            Duration::from_millis(1),
+            &channels,
+        ));
+        assert_eq!(error, ErrorKind::Other);
+        assert_eq!(channels.events.try_receive(), Ok(KeyEvent::KeyUp(A)));
+        assert_eq!(channels.events.try_receive(), Ok(KeyEvent::KeyDown(B)));
+        assert!(channels.events.try_receive().is_err());
+        assert_eq!(channels.report.try_take(), Some(BootKeyboardReport::new()));
+    }
+
+    #[test]
+    fn channels_never_block_the_scan() {
+        const SCANS: &[&[KeyEvent]] = &[&[KeyEvent::KeyDown(A)], &[KeyEvent::KeyUp(A)]];
+
+        let channels = KeyboardChannels::<NoopRawMutex, 1>::default();
+        let mut keyboard = FakeKeyboard::new(SCANS);
+        keyboard.fail_at(2);
+
+        // Nobody consumes: the release is dropped, and the last report
+        // replaces the earlier ones.
+        let error = embassy_futures::block_on(run(
+            &mut keyboard,
+            Engine::new(KEYMAP),
+            Duration::from_millis(1),
+            &channels,
+        ));
+        assert_eq!(error, ErrorKind::Other);
+        assert_eq!(channels.events.try_receive(), Ok(KeyEvent::KeyDown(A)));
+        assert!(channels.events.try_receive().is_err());
+        assert_eq!(channels.report.try_take(), Some(BootKeyboardReport::new()));
+    }
+
+    #[test]
+    fn led_reports_handed_on() {
+        let mut leds = None;
+        let mut handler = LedHandler(|state| leds = Some(state));
+
+        assert_eq!(
+            handler.set_report(ReportId::Out(0), &[LedState::CAPS_LOCK]),
+            OutResponse::Accepted
+        );
+        assert_eq!(
+            handler.set_report(ReportId::Out(0), &[]),
+            OutResponse::Rejected
+        );
+        assert!(leds.is_some_and(|leds| leds.caps_lock()));
+    }
+}
@@ -0,0 +1,326 @@
+//! Analog keys: actuation points and Rapid Trigger.
+//!
+//! Analog switches, like Hall effect or inductive ones, report how far
+//! each key travelled rather than whether it is pressed. An
+//! [`AnalogMatrix`] backend samples that travel for every key, and
+//! [`AnalogKeyboard`] turns it into key events according to the
+//! [`Actuation`] of each key, so that the rest of the pipeline sees a
+//! regular [`Keyboard`].
+//!
+//! Travel is measured in hundredths of a millimeter from the rest
+//! position.
+
+use crate::{Coordinate, ErrorType, KeyEvent, Keyboard};
+
+/// Backend sampling the travel of every key of an analog matrix.
+pub trait AnalogMatrix<const ROWS: usize, const COLS: usize>: ErrorType {
+    /// Sample the travel of every key into `travel`, in hundredths of a
+    /// millimeter.
+    fn read(&mut self, travel: &mut [[u16; COLS]; ROWS]) -> Result<(), Self::Error>;
+}
+
+impl<T: AnalogMatrix<ROWS, COLS> + ?Sized, const ROWS: usize, const COLS: usize>
+    AnalogMatrix<ROWS, COLS> for &mut T
+{
+    #[inline]
+    fn read(&mut self, travel: &mut [[u16; COLS]; ROWS]) -> Result<(), Self::Error> {
+        T::read(self, travel)
+    }
+}
+
+/// Rapid Trigger sensitivities, in hundredths of a millimeter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RapidTrigger {
+    /// Downward travel pressing the key again after a release
+    pub press: u16,
+    /// Upward travel releasing a pressed key
+    pub release: u16,
+}
+
+/// When an analog key counts as pressed.
+///
+/// The key is pressed once it travels down to `point`, and released once
+/// it travels back up past `point` by more than `hysteresis`.
+///
+/// With Rapid Trigger, a pressed key is also released as soon as it
+/// travels up by the release sensitivity from the deepest point reached,
+/// wherever that happens, and pressed again as soon as it travels down by
+/// the press sensitivity from the highest point reached since, as long as
+/// it stayed below `point`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Actuation {
+    /// Travel pressing the key
+    pub point: u16,
+    /// Travel back up past `point` needed to release the key
+    pub hysteresis: u16,
+    /// Rapid Trigger sensitivities, if enabled
+    pub rapid_trigger: Option<RapidTrigger>,
+}
+
+impl Actuation {
+    /// Default actuation: pressed at 2 millimeters, released 0.1
+    /// millimeter above, without Rapid Trigger.
+    pub const DEFAULT: Self = Self {
+        point: 200,
+        hysteresis: 10,
+        rapid_trigger: None,
+    };
+}
+
+impl Default for Actuation {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Digital state of an analog key.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+struct AnalogKey {
+    pressed: bool,
+    /// Deepest travel while pressed, highest travel while released.
+    extreme: u16,
+}
+
+impl AnalogKey {
+    /// Whether the key is pressed at `travel`.
+    const fn next(&self, travel: u16, actuation: &Actuation) -> bool {
+        let released = travel.saturating_add(actuation.hysteresis) < actuation.point;
+
+        match (self.pressed, actuation.rapid_trigger) {
+            (false, _) if travel < actuation.point => false,
+            // Came back up past the actuation point since the release.
+            (false, _) if self.extreme < actuation.point => true,
+            (false, None) => true,
+            (false, Some(rt)) => travel >= self.extreme.saturating_add(rt.press),
+            (true, _) if released => false,
+            (true, None) => true,
+            (true, Some(rt)) => travel.saturating_add(rt.release) > self.extreme,
+        }
+    }
+
+    /// Sample `travel`, returning whether the key changed state.
+    fn update(&mut self, travel: u16, actuation: &Actuation) -> bool {
+        let pressed = self.next(travel, actuation);
+        let changed = pressed != self.pressed;
+
+        self.extreme = match (changed, pressed) {
+            (true, _) => travel,
+            (false, true) => self.extreme.max(travel),
+            (false, false) => self.extreme.min(travel),
+        };
+        self.pressed = pressed;
+
+        changed
+    }
+}
+
+/// [`Keyboard`] over an [`AnalogMatrix`], with an [`Actuation`] per key.
+///
+/// Every scan samples the travel of all keys and reports up to `N` keys
+/// which changed state. Keys beyond that keep their previous state and
+/// are reported by the next scans.
+#[derive(Debug)]
+pub struct AnalogKeyboard<M, const ROWS: usize, const COLS: usize, const N: usize> {
+    matrix: M,
+    actuation: [[Actuation; COLS]; ROWS],
+    keys: [[AnalogKey; COLS]; ROWS],
+    travel: [[u16; COLS]; ROWS],
+    report: [KeyEvent; N],
+}
+
+impl<M: AnalogMatrix<ROWS, COLS>, const ROWS: usize, const COLS: usize, const N: usize>
+    AnalogKeyboard<M, ROWS, COLS, N>
+{
+    /// Create a keyboard over `matrix`, with the default actuation for
+    /// every key.
+    pub fn new(matrix: M) -> Self {
+        Self {
+            matrix,
+            actuation: [[Actuation::DEFAULT; COLS]; ROWS],
+            keys: [[AnalogKey::default(); COLS]; ROWS],
+            travel: [[0; COLS]; ROWS],
+            report: [KeyEvent::NoEvent; N],
+        }
+    }
+
+    /// Actuation of the key at `coordinate`, if it is within the matrix.
+    pub fn actuation(&self, coordinate: Coordinate) -> Option<Actuation> {
+        self.actuation
+            .get(coordinate.row())?
+            .get(coordinate.col())
+            .copied()
+    }
+
+    /// Set the actuation of the key at `coordinate`. Coordinates outside
+    /// the matrix are ignored.
+    pub fn set_actuation(&mut self, coordinate: Coordinate, actuation: Actuation) {
+        if let Some(slot) = self
+            .actuation
+            .get_mut(coordinate.row())
+            .and_then(|row| row.get_mut(coordinate.col()))
+        {
+            *slot = actuation;
+        }
+    }
+
+    /// Set the actuation of every key.
+    pub fn set_all_actuations(&mut self, actuation: Actuation) {
+        self.actuation = [[actuation; COLS]; ROWS];
+    }
+
+    /// Travel of every key sampled by the last scan.
+    pub const fn travel(&self) -> &[[u16; COLS]; ROWS] {
+        &self.travel
+    }
+
+    /// Backend sampling the keys.
+    pub fn matrix_mut(&mut self) -> &mut M {
+        &mut self.matrix
+    }
+
+    /// Destroys this instance and returns the backend.
+    pub fn destroy(self) -> M {
+        self.matrix
+    }
+}
+
+impl<M: AnalogMatrix<ROWS, COLS>, const ROWS: usize, const COLS: usize, const N: usize> ErrorType
+    for AnalogKeyboard<M, ROWS, COLS, N>
+{
+    type Error = M::Error;
+}
+
+impl<M: AnalogMatrix<ROWS, COLS>, const ROWS: usize, const COLS: usize, const N: usize> Keyboard
+    for AnalogKeyboard<M, ROWS, COLS, N>
+{
+    fn scan(&mut self) -> Result<&[KeyEvent], Self::Error> {
+        self.matrix.read(&mut self.travel)?;
+
+        let mut len = 0;
+        for (row, keys) in self.keys.iter_mut().enumerate() {
+            for (col, key) in keys.iter_mut().enumerate() {
+                if len == N {
+                    break;
+                }
+
+                if !key.update(self.travel[row][col], &self.actuation[row][col]) {
+                    continue;
+                }
+
+                let coordinate = Coordinate::new(row, col);
+                self.report[len] = if key.pressed {
+                    KeyEvent::KeyDown(coordinate)
+                } else {
+                    KeyEvent::KeyUp(coordinate)
+                };
+                len += 1;
+            }
+        }
+
+        Ok(&self.report[..len])
+    }
+
+    /// Whether any key is pressed.
+    fn activity(&self) -> bool {
+        self.keys.iter().flatten().any(|key| key.pressed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::convert::Infallible;
+
+    struct Travel([[u16; 2]; 1]);
+
+    impl ErrorType for Travel {
+        type Error = Infallible;
+    }
+
+    impl AnalogMatrix<1, 2> for Travel {
+        fn read(&mut self, travel: &mut [[u16; 2]; 1]) -> Result<(), Infallible> {
+            *travel = self.0;
+            Ok(())
+        }
+    }
+
+    fn changes(actuation: Actuation, travel: &[u16]) -> std::vec::Vec<bool> {
+        let mut key = AnalogKey::default();
+        travel
+            .iter()
+            .filter_map(|t| key.update(*t, &actuation).then_some(key.pressed))
+            .collect()
+    }
+
+    #[test]
+    fn actuation_point() {
+        let actuation = Actuation::DEFAULT;
+
+        // Pressed at 2mm, released below 1.9mm.
+        assert_eq!(changes(actuation, &[150, 199, 200, 300, 195, 190]), [true]);
+        assert_eq!(
+            changes(actuation, &[200, 189, 195, 200]),
+            [true, false, true]
+        );
+    }
+
+    #[test]
+    fn rapid_trigger() {
+        let actuation = Actuation {
+            rapid_trigger: Some(RapidTrigger {
+                press: 20,
+                release: 30,
+            }),
+            ..Actuation::DEFAULT
+        };
+
+        let presses = changes(
+            actuation,
+            // Down to 3.5mm, up 0.3mm, down 0.2mm, then all the way up
+            // and back down to the actuation point.
+            &[100, 250, 350, 330, 320, 330, 340, 100, 200],
+        );
+        assert_eq!(presses, [true, false, true, false, true]);
+    }
+
+    #[test]
+    fn analog_keyboard() {
+        let mut keyboard = AnalogKeyboard::<_, 1, 2, 1>::new(Travel([[0, 0]]));
+        keyboard.set_actuation(
+            Coordinate::new(0, 1),
+            Actuation {
+                point: 100,
+                ..Actuation::DEFAULT
+            },
+        );
+        assert_eq!(
+            keyboard.actuation(Coordinate::new(0, 1)).unwrap().point,
+            100
+        );
+        assert_eq!(keyboard.actuation(Coordinate::new(1, 0)), None);
+
+        keyboard.matrix_mut().0 = [[150, 150]];
+        assert_eq!(
+            keyboard.scan().unwrap(),
+            [KeyEvent::KeyDown(Coordinate::new(0, 1))]
+        );
+        assert!(keyboard.activity());
+
+        // One event per scan, the second key is reported next.
+        keyboard.matrix_mut().0 = [[250, 0]];
+        assert_eq!(
+            keyboard.scan().unwrap(),
+            [KeyEvent::KeyDown(Coordinate::new(0, 0))]
+        );
+        assert_eq!(
+            keyboard.scan().unwrap(),
+            [KeyEvent::KeyUp(Coordinate::new(0, 1))]
+        );
+        assert_eq!(keyboard.scan().unwrap(), []);
+    }
+}
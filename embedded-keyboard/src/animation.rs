@@ -0,0 +1,356 @@
+//! Non-reactive lighting animations, independent of the LEDs they drive.
+//!
+//! [`Animation`] holds the selected [`AnimationMode`], speed and
+//! brightness, changed by [`Action::Animation`] keys or directly. Each
+//! frame, [`Animation::frame`] gives a [`Frame`] that tells the level of a
+//! single-color LED, see [`Frame::level`], or the color of an RGB LED,
+//! see [`Frame::color`], from its position along the board.
+//!
+//! Positions go from 0 at the left edge of the board to 255 at the right
+//! edge, e.g. derived from the [`Geometry`](crate::Geometry) of the keys.
+//! Single-color backlights, like a [`Backlight`](crate::backlight::Backlight),
+//! can use position 0 throughout.
+
+use crate::rgb::Hsv;
+use crate::Action;
+
+/// Change to an [`Animation`], sent by [`Action::Animation`] keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum AnimationCommand {
+    /// Turn the animation on
+    On,
+    /// Turn the animation off
+    Off,
+    /// Turn the animation on or off
+    Toggle,
+    /// Switch to the next mode
+    Next,
+    /// Switch to the previous mode
+    Previous,
+    /// Increase the brightness
+    BrightnessUp,
+    /// Decrease the brightness
+    BrightnessDown,
+    /// Speed the animation up
+    SpeedUp,
+    /// Slow the animation down
+    SpeedDown,
+}
+
+impl AnimationCommand {
+    /// Every command, in the order of their encoding.
+    pub const ALL: [Self; 9] = [
+        Self::On,
+        Self::Off,
+        Self::Toggle,
+        Self::Next,
+        Self::Previous,
+        Self::BrightnessUp,
+        Self::BrightnessDown,
+        Self::SpeedUp,
+        Self::SpeedDown,
+    ];
+
+    /// QMK name of the command's keycode.
+    pub const fn qmk_name(self) -> &'static str {
+        match self {
+            Self::On => "LM_ON",
+            Self::Off => "LM_OFF",
+            Self::Toggle => "LM_TOGG",
+            Self::Next => "LM_NEXT",
+            Self::Previous => "LM_PREV",
+            Self::BrightnessUp => "LM_BRIU",
+            Self::BrightnessDown => "LM_BRID",
+            Self::SpeedUp => "LM_SPDU",
+            Self::SpeedDown => "LM_SPDD",
+        }
+    }
+}
+
+/// Animation mode.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum AnimationMode {
+    /// Steady at the brightness
+    #[default]
+    Static,
+    /// Fading in and out
+    Breathing,
+    /// Steady, cycling through the hues on RGB LEDs
+    Rainbow,
+    /// Bands of light sweeping across the board, and of hues on RGB LEDs
+    Wave,
+}
+
+impl AnimationMode {
+    /// Every mode, in the order [`AnimationCommand::Next`] goes through
+    /// them.
+    pub const ALL: [Self; 4] = [Self::Static, Self::Breathing, Self::Rainbow, Self::Wave];
+
+    const fn step(self, forward: bool) -> Self {
+        let len = Self::ALL.len() as u8;
+        let raw = if forward {
+            (self as u8 + 1) % len
+        } else {
+            (self as u8 + len - 1) % len
+        };
+
+        Self::ALL[raw as usize]
+    }
+}
+
+/// Position of an animation at time `now` going at `speed`, going around
+/// the whole `u8` range in about 2 seconds at speed 128.
+pub const fn phase(now: u32, speed: u8) -> u8 {
+    (now.wrapping_mul(speed as u32 + 16) >> 10) as u8
+}
+
+/// Level rising from 0 to 255 over the first half of the `phase` range,
+/// and falling back over the second.
+pub const fn triangle(phase: u8) -> u8 {
+    let half = if phase < 128 { phase } else { 255 - phase };
+
+    (half as u16 * 255 / 127) as u8
+}
+
+/// Animation settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Animation {
+    on: bool,
+    mode: AnimationMode,
+    speed: u8,
+    brightness: u8,
+    held: u16,
+}
+
+impl Animation {
+    /// Change of one brightness or speed command.
+    pub const STEP: u8 = 17;
+
+    /// Create an animation in `mode`, on, at full brightness and half
+    /// speed.
+    pub const fn new(mode: AnimationMode) -> Self {
+        Self {
+            on: true,
+            mode,
+            speed: 128,
+            brightness: 255,
+            held: 0,
+        }
+    }
+
+    /// Whether the animation is on.
+    pub const fn is_on(&self) -> bool {
+        self.on
+    }
+
+    /// Turn the animation on or off.
+    pub fn set_on(&mut self, on: bool) {
+        self.on = on;
+    }
+
+    /// Selected mode.
+    pub const fn mode(&self) -> AnimationMode {
+        self.mode
+    }
+
+    /// Select a mode.
+    pub fn set_mode(&mut self, mode: AnimationMode) {
+        self.mode = mode;
+    }
+
+    /// Speed of the animation.
+    pub const fn speed(&self) -> u8 {
+        self.speed
+    }
+
+    /// Set the speed of the animation.
+    pub fn set_speed(&mut self, speed: u8) {
+        self.speed = speed;
+    }
+
+    /// Brightness of the animation.
+    pub const fn brightness(&self) -> u8 {
+        self.brightness
+    }
+
+    /// Set the brightness of the animation.
+    pub fn set_brightness(&mut self, brightness: u8) {
+        self.brightness = brightness;
+    }
+
+    /// Apply a command. The mode wraps around, brightness and speed stop
+    /// at their limits.
+    pub fn command(&mut self, command: AnimationCommand) {
+        match command {
+            AnimationCommand::On => self.on = true,
+            AnimationCommand::Off => self.on = false,
+            AnimationCommand::Toggle => self.on = !self.on,
+            AnimationCommand::Next => self.mode = self.mode.step(true),
+            AnimationCommand::Previous => self.mode = self.mode.step(false),
+            AnimationCommand::BrightnessUp => {
+                self.brightness = self.brightness.saturating_add(Self::STEP);
+            }
+            AnimationCommand::BrightnessDown => {
+                self.brightness = self.brightness.saturating_sub(Self::STEP);
+            }
+            AnimationCommand::SpeedUp => self.speed = self.speed.saturating_add(Self::STEP),
+            AnimationCommand::SpeedDown => self.speed = self.speed.saturating_sub(Self::STEP),
+        }
+    }
+
+    /// Update the held actions, e.g. from
+    /// [`Engine::actions`](crate::engine::Engine::actions), applying the
+    /// animation commands pressed since the last call, and returning
+    /// whether any was. Commands apply once per press, however long the
+    /// key is held.
+    pub fn update(&mut self, actions: impl IntoIterator<Item = Action>) -> bool {
+        let held = actions
+            .into_iter()
+            .filter_map(|action| match action {
+                Action::Animation(command) => Some(command),
+                _ => None,
+            })
+            .fold(0u16, |held, command| held | 1 << command as u8);
+        let pressed = held & !self.held;
+        self.held = held;
+
+        for command in AnimationCommand::ALL {
+            if pressed & 1 << command as u8 != 0 {
+                self.command(command);
+            }
+        }
+
+        pressed != 0
+    }
+
+    /// Frame of the animation at time `now`.
+    pub const fn frame(&self, now: u32) -> Frame {
+        Frame {
+            on: self.on,
+            mode: self.mode,
+            brightness: self.brightness,
+            phase: phase(now, self.speed),
+        }
+    }
+}
+
+impl Default for Animation {
+    fn default() -> Self {
+        Self::new(AnimationMode::default())
+    }
+}
+
+/// An [`Animation`] at one point in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Frame {
+    on: bool,
+    mode: AnimationMode,
+    brightness: u8,
+    phase: u8,
+}
+
+impl Frame {
+    /// Level, from 0 to 255, of a single-color LED at `position`.
+    pub const fn level(&self, position: u8) -> u8 {
+        if !self.on {
+            return 0;
+        }
+
+        let scale = match self.mode {
+            AnimationMode::Static | AnimationMode::Rainbow => 255,
+            AnimationMode::Breathing => triangle(self.phase),
+            AnimationMode::Wave => triangle(self.phase.wrapping_sub(position)),
+        };
+
+        (self.brightness as u16 * scale as u16 / 255) as u8
+    }
+
+    /// Color of an RGB LED at `position`, starting from `base` and scaling
+    /// its value by the brightness.
+    ///
+    /// Rainbow and wave modes shift the hue instead of the level, so that
+    /// the wave is one of hues at a steady brightness.
+    pub const fn color(&self, base: Hsv, position: u8) -> Hsv {
+        let (hue, scale) = match self.mode {
+            AnimationMode::Static => (base.hue, self.level(position)),
+            AnimationMode::Breathing => (base.hue, self.level(position)),
+            AnimationMode::Rainbow => (base.hue.wrapping_add(self.phase), self.level(position)),
+            AnimationMode::Wave => (
+                base.hue.wrapping_add(self.phase).wrapping_add(position),
+                if self.on { self.brightness } else { 0 },
+            ),
+        };
+
+        Hsv::new(hue, base.sat, (base.val as u16 * scale as u16 / 255) as u8)
+    }
+
+    /// Scale a 16-bit brightness, e.g. of a
+    /// [`Backlight`](crate::backlight::Backlight), by the level at
+    /// `position`.
+    pub const fn scale(&self, value: u16, position: u8) -> u16 {
+        (value as u32 * self.level(position) as u32 / 255) as u16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeyCode;
+
+    #[test]
+    fn commands() {
+        let mut animation = Animation::default();
+
+        assert!(animation.update([Action::Animation(AnimationCommand::Previous)]));
+        assert!(!animation.update([
+            Action::Animation(AnimationCommand::Previous),
+            Action::Key(KeyCode::KA)
+        ]));
+        assert_eq!(animation.mode(), AnimationMode::Wave);
+
+        animation.update([]);
+        animation.update([
+            Action::Animation(AnimationCommand::Next),
+            Action::Animation(AnimationCommand::BrightnessDown),
+        ]);
+        assert_eq!(animation.mode(), AnimationMode::Static);
+        assert_eq!(animation.brightness(), 238);
+        assert_eq!(animation.frame(0).level(0), 238);
+
+        animation.command(AnimationCommand::Toggle);
+        assert_eq!(animation.frame(0).level(0), 0);
+        assert_eq!(animation.frame(0).scale(u16::MAX, 0), 0);
+    }
+
+    #[test]
+    fn modes() {
+        let mut animation = Animation::new(AnimationMode::Breathing);
+        let base = Hsv::new(10, 255, 200);
+
+        assert_eq!(animation.frame(0).level(0), 0);
+        // Half way through the cycle, at full brightness.
+        assert_eq!(animation.frame(910).level(0), 255);
+        assert_eq!(animation.frame(910).color(base, 0), base);
+        assert_eq!(animation.frame(910).scale(1000, 0), 1000);
+
+        animation.set_mode(AnimationMode::Wave);
+        let frame = animation.frame(910);
+        assert_eq!(frame.level(0), 255);
+        assert_eq!(frame.level(127), 0);
+        assert_eq!(frame.color(base, 1), Hsv::new(138, 255, 200));
+
+        animation.set_mode(AnimationMode::Rainbow);
+        assert_eq!(
+            animation.frame(910).color(base, 50),
+            Hsv::new(137, 255, 200)
+        );
+    }
+}
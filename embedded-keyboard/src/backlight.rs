@@ -0,0 +1,327 @@
+//! Single-channel backlight with stepped brightness levels.
+//!
+//! [`Backlight`] keeps the brightness level and whether the backlight is
+//! on, changed by [`Action::Backlight`] keys or directly, and fades
+//! between brightnesses when ticked from the scan loop. With the
+//! `embedded-hal` feature, `Backlight::apply` drives a PWM channel.
+//!
+//! The brightness goes from 0 when off to [`u16::MAX`] at the highest
+//! level, so it can be used as the duty cycle fraction of any PWM
+//! resolution.
+
+use crate::time::is_due;
+use crate::Action;
+
+/// Milliseconds between brightness steps while fading.
+pub const FADE_INTERVAL: u32 = 8;
+
+/// Change to a [`Backlight`], sent by [`Action::Backlight`] keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum BacklightCommand {
+    /// Turn the backlight on
+    On,
+    /// Turn the backlight off
+    Off,
+    /// Turn the backlight on or off
+    Toggle,
+    /// Decrease the brightness by one level
+    Down,
+    /// Increase the brightness by one level
+    Up,
+    /// Increase the brightness by one level, wrapping around to off
+    Step,
+}
+
+impl BacklightCommand {
+    /// Every command, in the order of their encoding.
+    pub const ALL: [Self; 6] = [
+        Self::On,
+        Self::Off,
+        Self::Toggle,
+        Self::Down,
+        Self::Up,
+        Self::Step,
+    ];
+
+    /// QMK name of the command's keycode.
+    pub const fn qmk_name(self) -> &'static str {
+        match self {
+            Self::On => "BL_ON",
+            Self::Off => "BL_OFF",
+            Self::Toggle => "BL_TOGG",
+            Self::Down => "BL_DOWN",
+            Self::Up => "BL_UP",
+            Self::Step => "BL_STEP",
+        }
+    }
+}
+
+/// Backlight brightness state.
+///
+/// The brightness is one of `levels` steps above off. Commands change the
+/// target brightness right away; the output brightness then follows it,
+/// either at once or, with a fade time set, over successive calls to
+/// [`Backlight::tick`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Backlight {
+    levels: u8,
+    level: u8,
+    on: bool,
+    held: u8,
+    fade: u16,
+    current: u16,
+    last: Option<u32>,
+}
+
+impl Backlight {
+    /// Create a backlight with `levels` brightness levels above off, at
+    /// the highest level and turned off. At least one level is used.
+    pub const fn new(levels: u8) -> Self {
+        let levels = if levels == 0 { 1 } else { levels };
+
+        Self {
+            levels,
+            level: levels,
+            on: false,
+            held: 0,
+            fade: 0,
+            current: 0,
+            last: None,
+        }
+    }
+
+    /// Number of brightness levels above off.
+    pub const fn levels(&self) -> u8 {
+        self.levels
+    }
+
+    /// Current brightness level, from 0 to [`Backlight::levels`]. The
+    /// level is kept while the backlight is off.
+    pub const fn level(&self) -> u8 {
+        self.level
+    }
+
+    /// Set the brightness level, capped to [`Backlight::levels`].
+    pub fn set_level(&mut self, level: u8) {
+        self.level = level.min(self.levels);
+    }
+
+    /// Whether the backlight is on.
+    pub const fn is_on(&self) -> bool {
+        self.on
+    }
+
+    /// Turn the backlight on or off.
+    pub fn set_on(&mut self, on: bool) {
+        self.on = on;
+    }
+
+    /// Time, in milliseconds, a fade across the whole brightness range
+    /// takes. Smaller changes take proportionally less.
+    pub const fn fade(&self) -> u16 {
+        self.fade
+    }
+
+    /// Set the fade time, see [`Backlight::fade`]. 0 disables fading.
+    pub fn set_fade(&mut self, milliseconds: u16) {
+        self.fade = milliseconds;
+    }
+
+    /// Apply a command.
+    ///
+    /// Turning the backlight on at level 0 brings it to the highest level,
+    /// and stepping past the highest level turns it off.
+    pub fn command(&mut self, command: BacklightCommand) {
+        match command {
+            BacklightCommand::On => {
+                if self.level == 0 {
+                    self.level = self.levels;
+                }
+                self.on = true;
+            }
+            BacklightCommand::Off => self.on = false,
+            BacklightCommand::Toggle if self.on => self.command(BacklightCommand::Off),
+            BacklightCommand::Toggle => self.command(BacklightCommand::On),
+            BacklightCommand::Down => self.level = self.level.saturating_sub(1),
+            BacklightCommand::Up => {
+                self.level = (self.level + 1).min(self.levels);
+                self.on = true;
+            }
+            BacklightCommand::Step if self.on && self.level == self.levels => {
+                self.level = 0;
+                self.on = false;
+            }
+            BacklightCommand::Step => {
+                self.level = if self.on { self.level + 1 } else { 1 };
+                self.on = true;
+            }
+        }
+    }
+
+    /// Update the held actions, e.g. from
+    /// [`Engine::actions`](crate::engine::Engine::actions), applying the
+    /// backlight commands pressed since the last call. Commands keep
+    /// applying once per press, however long the key is held.
+    pub fn update(&mut self, actions: impl IntoIterator<Item = Action>) {
+        let held = actions
+            .into_iter()
+            .filter_map(|action| match action {
+                Action::Backlight(command) => Some(command),
+                _ => None,
+            })
+            .fold(0u8, |held, command| held | 1 << command as u8);
+        let pressed = held & !self.held;
+        self.held = held;
+
+        for command in BacklightCommand::ALL {
+            if pressed & 1 << command as u8 != 0 {
+                self.command(command);
+            }
+        }
+    }
+
+    /// Brightness the backlight is heading for.
+    pub fn target(&self) -> u16 {
+        if !self.on {
+            return 0;
+        }
+
+        (u32::from(self.level) * u32::from(u16::MAX) / u32::from(self.levels)) as u16
+    }
+
+    /// Brightness to output now.
+    pub const fn brightness(&self) -> u16 {
+        self.current
+    }
+
+    /// Move the output brightness towards the target, returning whether
+    /// it changed. Meant to be called on every scan: the first call after
+    /// a change starts the fade, which then progresses with the time
+    /// elapsed.
+    pub fn tick(&mut self, now: u32) -> bool {
+        let target = self.target();
+
+        if self.current == target {
+            self.last = None;
+            return false;
+        }
+
+        if self.fade == 0 {
+            self.current = target;
+            self.last = None;
+            return true;
+        }
+
+        let Some(last) = self.last else {
+            self.last = Some(now);
+            return false;
+        };
+
+        if !is_due(now, last) {
+            return false;
+        }
+
+        let elapsed = now.wrapping_sub(last);
+
+        let step = u64::from(elapsed) * u64::from(u16::MAX) / u64::from(self.fade);
+        let step = step.min(u64::from(u16::MAX)) as u16;
+        if step == 0 {
+            return false;
+        }
+
+        self.current = if self.current < target {
+            self.current.saturating_add(step).min(target)
+        } else {
+            self.current.saturating_sub(step).max(target)
+        };
+        self.last = (self.current != target).then_some(now);
+        true
+    }
+
+    /// Time of the next fade step, if fading.
+    pub fn next_deadline(&self) -> Option<u32> {
+        self.last.map(|last| last.wrapping_add(FADE_INTERVAL))
+    }
+
+    /// Set the duty cycle of `pwm` to the output brightness.
+    #[cfg(feature = "embedded-hal")]
+    pub fn apply<P: embedded_hal::pwm::SetDutyCycle>(&self, pwm: &mut P) -> Result<(), P::Error> {
+        pwm.set_duty_cycle_fraction(self.current, u16::MAX)
+    }
+}
+
+impl Default for Backlight {
+    fn default() -> Self {
+        Self::new(3)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeyCode;
+
+    #[test]
+    fn levels() {
+        let mut backlight = Backlight::new(3);
+        assert!(!backlight.is_on());
+        assert_eq!(backlight.target(), 0);
+
+        backlight.update([Action::Backlight(BacklightCommand::Toggle)]);
+        assert!(backlight.is_on());
+        assert_eq!(backlight.target(), u16::MAX);
+
+        // Held, not pressed again.
+        backlight.update([
+            Action::Backlight(BacklightCommand::Toggle),
+            Action::Key(KeyCode::KA),
+        ]);
+        assert!(backlight.is_on());
+
+        backlight.update([]);
+        backlight.update([Action::Backlight(BacklightCommand::Down)]);
+        assert_eq!(backlight.level(), 2);
+        assert_eq!(backlight.target(), 0xaaaa);
+
+        backlight.command(BacklightCommand::Step);
+        assert_eq!(backlight.level(), 3);
+        backlight.command(BacklightCommand::Step);
+        assert!(!backlight.is_on());
+        assert_eq!(backlight.level(), 0);
+        backlight.command(BacklightCommand::Step);
+        assert_eq!((backlight.is_on(), backlight.level()), (true, 1));
+
+        backlight.command(BacklightCommand::Down);
+        backlight.command(BacklightCommand::Off);
+        backlight.command(BacklightCommand::On);
+        assert_eq!(backlight.level(), 3);
+
+        assert!(backlight.tick(0));
+        assert_eq!(backlight.brightness(), u16::MAX);
+        assert!(!backlight.tick(1));
+    }
+
+    #[test]
+    fn fade() {
+        let mut backlight = Backlight::new(1);
+        backlight.set_fade(100);
+        backlight.command(BacklightCommand::On);
+
+        assert!(!backlight.tick(1000));
+        assert_eq!(backlight.next_deadline(), Some(1000 + FADE_INTERVAL));
+        assert!(backlight.tick(1050));
+        assert_eq!(backlight.brightness(), 0x7fff);
+
+        backlight.command(BacklightCommand::Off);
+        assert!(backlight.tick(1060));
+        assert_eq!(backlight.brightness(), 0x6666);
+        assert!(backlight.tick(1200));
+        assert_eq!(backlight.brightness(), 0);
+        assert_eq!(backlight.next_deadline(), None);
+        assert!(!backlight.tick(1300));
+    }
+}
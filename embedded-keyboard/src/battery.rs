@@ -0,0 +1,203 @@
+//! Battery level reporting for wireless keyboards.
+//!
+//! A [`BatteryMonitor`] measures the charge left, e.g. by sampling the
+//! battery voltage through an ADC and converting it with [`percent`].
+//! Measuring costs power, so [`Battery`] samples it only every so often,
+//! scheduled off the scan loop's timestamps, and tells the application
+//! when the level changed, which then publishes it: through a
+//! [`BatteryService`] over BLE, or in a
+//! [`BatteryReport`](crate::hid::BatteryReport) over HID.
+
+use crate::time::is_due;
+use crate::ErrorType;
+
+/// Source of the battery level.
+pub trait BatteryMonitor: ErrorType {
+    /// Measure the battery level, in percent from 0 to 100.
+    fn level(&mut self) -> Result<u8, Self::Error>;
+}
+
+impl<T: BatteryMonitor + ?Sized> BatteryMonitor for &mut T {
+    #[inline]
+    fn level(&mut self) -> Result<u8, Self::Error> {
+        T::level(self)
+    }
+}
+
+/// Battery Level characteristic of a BLE Battery Service, implemented on
+/// top of a BLE stack such as `nrf-softdevice` or TrouBLE.
+pub trait BatteryService {
+    /// Error type
+    type Error;
+
+    /// UUID of the Battery Service.
+    const SERVICE_UUID: u16 = 0x180f;
+
+    /// UUID of the Battery Level characteristic.
+    const LEVEL_UUID: u16 = 0x2a19;
+
+    /// Notify the host of a new battery `level`, in percent.
+    fn notify_level(&mut self, level: u8) -> Result<(), Self::Error>;
+}
+
+/// Battery level in percent of a battery at `millivolts`, interpolated
+/// linearly between `empty` and `full` millivolts.
+pub const fn percent(millivolts: u16, empty: u16, full: u16) -> u8 {
+    if millivolts <= empty || full <= empty {
+        return 0;
+    }
+    if millivolts >= full {
+        return 100;
+    }
+
+    ((millivolts - empty) as u32 * 100 / (full - empty) as u32) as u8
+}
+
+/// Periodic battery sampling.
+///
+/// The application calls [`Battery::tick`] from its scan loop, at the
+/// latest by [`Battery::next_deadline`]. The first tick samples the
+/// battery at once, the next ones every `interval` milliseconds, and the
+/// level is returned whenever it changed, to be published to the host.
+pub struct Battery<M> {
+    monitor: M,
+    interval: u32,
+    level: Option<u8>,
+    last: Option<u32>,
+}
+
+impl<M: BatteryMonitor> Battery<M> {
+    /// Default sampling interval: a minute.
+    pub const INTERVAL: u32 = 60_000;
+
+    /// Sample `monitor` every `interval` milliseconds.
+    pub const fn new(monitor: M, interval: u32) -> Self {
+        Self {
+            monitor,
+            interval,
+            level: None,
+            last: None,
+        }
+    }
+
+    /// Mutable access to the monitor.
+    pub fn monitor_mut(&mut self) -> &mut M {
+        &mut self.monitor
+    }
+
+    /// Destroy the sampler, returning the monitor.
+    pub fn destroy(self) -> M {
+        self.monitor
+    }
+
+    /// Sampling interval, in milliseconds.
+    pub const fn interval(&self) -> u32 {
+        self.interval
+    }
+
+    /// Change the sampling interval, taking effect from the last sample.
+    pub fn set_interval(&mut self, interval: u32) {
+        self.interval = interval;
+    }
+
+    /// Last battery level sampled, in percent, if any.
+    pub const fn level(&self) -> Option<u8> {
+        self.level
+    }
+
+    /// Sample the battery at the next tick, e.g. when a host connects and
+    /// needs the level.
+    pub fn request(&mut self) {
+        self.last = None;
+    }
+
+    /// Advance time to `now`, sampling the battery if due, and returning
+    /// the level if it changed.
+    ///
+    /// # Errors
+    ///
+    /// Returns the monitor's error if sampling failed; sampling is retried
+    /// at the next interval.
+    pub fn tick(&mut self, now: u32) -> Result<Option<u8>, M::Error> {
+        if let Some(last) = self.last {
+            if !is_due(now, last.wrapping_add(self.interval)) {
+                return Ok(None);
+            }
+        }
+
+        self.last = Some(now);
+        let level = self.monitor.level()?.min(100);
+
+        if self.level == Some(level) {
+            return Ok(None);
+        }
+
+        self.level = Some(level);
+        Ok(Some(level))
+    }
+
+    /// Time at which the battery is next sampled.
+    pub fn next_deadline(&self) -> Option<u32> {
+        self.last.map(|last| last.wrapping_add(self.interval))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Error, ErrorKind};
+
+    #[derive(Debug)]
+    struct Unreadable;
+
+    impl Error for Unreadable {
+        fn kind(&self) -> ErrorKind {
+            ErrorKind::Other
+        }
+    }
+
+    /// Monitor returning the level it was last set to.
+    struct Level(Option<u8>);
+
+    impl ErrorType for Level {
+        type Error = Unreadable;
+    }
+
+    impl BatteryMonitor for Level {
+        fn level(&mut self) -> Result<u8, Unreadable> {
+            self.0.ok_or(Unreadable)
+        }
+    }
+
+    #[test]
+    fn samples_periodically() {
+        let mut battery = Battery::new(Level(Some(90)), 1_000);
+        assert_eq!(battery.next_deadline(), None);
+
+        assert_eq!(battery.tick(500).unwrap(), Some(90));
+        assert_eq!(battery.next_deadline(), Some(1_500));
+        battery.monitor_mut().0 = Some(89);
+        assert_eq!(battery.tick(1_499).unwrap(), None);
+        assert_eq!(battery.level(), Some(90));
+        assert_eq!(battery.tick(1_500).unwrap(), Some(89));
+
+        // Unchanged levels are not published again.
+        assert_eq!(battery.tick(2_500).unwrap(), None);
+        battery.request();
+        assert_eq!(battery.tick(2_600).unwrap(), None);
+
+        battery.monitor_mut().0 = None;
+        assert!(battery.tick(3_600).is_err());
+        battery.monitor_mut().0 = Some(200);
+        assert_eq!(battery.tick(4_000).unwrap(), None);
+        assert_eq!(battery.tick(4_600).unwrap(), Some(100));
+    }
+
+    #[test]
+    fn percent_from_voltage() {
+        assert_eq!(percent(3_000, 3_300, 4_200), 0);
+        assert_eq!(percent(3_750, 3_300, 4_200), 50);
+        assert_eq!(percent(4_300, 3_300, 4_200), 100);
+        assert_eq!(percent(4_000, 4_200, 3_300), 0);
+    }
+}
@@ -0,0 +1,324 @@
+//! Debouncing of raw key samples.
+//!
+//! Switches bounce when they close and open, so a single press reads as
+//! a burst of presses and releases. A [`KeyDebouncer`] filters the
+//! samples of a key, one per scan, into its debounced state. Every
+//! scanner filters its samples through it, be it a GPIO matrix, a
+//! hardware snapshot or the [`Replay`](crate::replay::Replay) of a
+//! recording, so that the same samples always give the same key events.
+
+use crate::handoff::KeyState;
+
+/// How a [`KeyDebouncer`] filters the samples of a key before changing
+/// its state.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DebounceMode {
+    /// Count up on pressed samples and down on released ones, changing
+    /// state when the count reaches either end: isolated glitches only
+    /// delay a change.
+    #[default]
+    Integrator,
+
+    /// Change state after as many samples in a row as there are debounce
+    /// scans: any glitch starts the count over, as preferred in noisy
+    /// environments.
+    Consecutive,
+
+    /// Follow every sample, ignoring the debounce scans, so that each
+    /// bounce is reported as a key event: for switches debounced in
+    /// hardware, e.g. by an RC filter, or to observe the bounce of a
+    /// switch. Masked keys and warm-up scans still apply.
+    Raw,
+}
+
+/// Debounce state of a single key.
+///
+/// The debounce counts passed to [`KeyDebouncer::debounce`] are numbers
+/// of samples, from 1 to 127.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct KeyDebouncer {
+    state: i8,
+    pressed: bool,
+    changed: bool,
+}
+
+impl KeyDebouncer {
+    /// Default number of samples before a key changes state.
+    pub const DEBOUNCE: i8 = 3;
+
+    const MINIMUM: i8 = 0;
+
+    /// Create a released key.
+    pub const fn new() -> Self {
+        Self {
+            state: Self::MINIMUM,
+            pressed: false,
+            changed: false,
+        }
+    }
+
+    /// Create a released key from `state`, e.g. saved in a
+    /// [`Handoff`](crate::handoff::Handoff), its counter capped at
+    /// `press`.
+    ///
+    /// Keys held before are restored released: being held, they are
+    /// pressed again as soon as they are sampled.
+    pub fn restore(state: KeyState, press: i8) -> Self {
+        let counter = i8::try_from(state.counter()).unwrap_or(i8::MAX);
+        Self {
+            state: counter.min(press),
+            pressed: false,
+            changed: false,
+        }
+    }
+
+    /// Debounce state of the key, e.g. to save in a
+    /// [`Handoff`](crate::handoff::Handoff).
+    pub fn state(&self) -> KeyState {
+        KeyState::new(self.state.unsigned_abs(), self.pressed)
+    }
+
+    /// Whether the key is pressed.
+    pub const fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+
+    /// Whether the last sample changed the state of the key.
+    pub const fn changed(&self) -> bool {
+        self.changed
+    }
+
+    /// Whether the key is released with no sample counted towards a press,
+    /// rather than pressed or being debounced.
+    pub const fn is_idle(&self) -> bool {
+        !self.pressed && self.state == Self::MINIMUM
+    }
+
+    /// Filter `sample` with the integrator, changing state after
+    /// `debounce` samples.
+    pub fn update(&mut self, sample: bool, debounce: i8) -> bool {
+        self.debounce(sample, debounce, debounce, DebounceMode::Integrator)
+    }
+
+    /// Filter `sample` with `mode`, pressing the key after `press`
+    /// samples and releasing it after `release` samples, and return
+    /// whether it is pressed.
+    #[inline]
+    pub fn debounce(&mut self, sample: bool, press: i8, release: i8, mode: DebounceMode) -> bool {
+        // Most keys sit released and idle on most scans.
+        if !sample && self.is_idle() {
+            self.changed = false;
+            return false;
+        }
+
+        let previous_pressed = self.pressed;
+        match mode {
+            DebounceMode::Integrator => self.integrate(sample, press, release),
+            DebounceMode::Consecutive => self.count(sample, press, release),
+            DebounceMode::Raw => {
+                self.pressed = sample;
+                self.state = Self::MINIMUM;
+            }
+        }
+        self.changed = self.pressed != previous_pressed;
+
+        self.pressed
+    }
+
+    /// Start the count over where `mode` expects it after a change of
+    /// mode, keeping the state of the key.
+    pub fn restart(&mut self, mode: DebounceMode, release: i8) {
+        self.state = match (mode, self.pressed) {
+            (DebounceMode::Integrator, true) => release,
+            _ => Self::MINIMUM,
+        };
+    }
+
+    /// Take back a press made by the last sample, so that it is made
+    /// again by the next one if the key is still sampled pressed.
+    pub fn hold_back(&mut self) {
+        if self.changed && self.pressed {
+            self.pressed = false;
+            self.changed = false;
+        }
+    }
+
+    /// A released key is pressed once its counter climbs to `press`, then
+    /// the counter starts over from `release`, and the key is released
+    /// once it drains down to the minimum.
+    fn integrate(&mut self, sample: bool, press: i8, release: i8) {
+        let maximum = if self.pressed { release } else { press };
+
+        let current = self.state.saturating_add(if sample { 1 } else { -1 });
+        self.state = current.clamp(Self::MINIMUM, maximum);
+
+        if !self.pressed && sample && self.state == press {
+            self.pressed = true;
+            self.state = release;
+        } else if self.pressed && self.state == Self::MINIMUM {
+            self.pressed = false;
+        }
+    }
+
+    /// The counter counts the samples in a row disagreeing with the state
+    /// of the key, which changes once they reach `press` or `release`.
+    fn count(&mut self, sample: bool, press: i8, release: i8) {
+        if sample == self.pressed {
+            self.state = Self::MINIMUM;
+            return;
+        }
+
+        self.state = self.state.saturating_add(1);
+        if self.state >= if self.pressed { release } else { press } {
+            self.pressed = sample;
+            self.state = Self::MINIMUM;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_creation() {
+        let key = KeyDebouncer::default();
+        assert_eq!(
+            key,
+            KeyDebouncer {
+                state: 0,
+                pressed: false,
+                changed: false
+            }
+        );
+    }
+
+    #[test]
+    fn update_state_once() {
+        let mut key = KeyDebouncer::default();
+        key.update(false, KeyDebouncer::DEBOUNCE);
+        assert_eq!(
+            key,
+            KeyDebouncer {
+                state: 0,
+                pressed: false,
+                changed: false
+            }
+        );
+
+        let mut key = KeyDebouncer::default();
+        key.update(true, KeyDebouncer::DEBOUNCE);
+        assert_eq!(
+            key,
+            KeyDebouncer {
+                state: 1,
+                pressed: false,
+                changed: false
+            }
+        );
+    }
+
+    #[test]
+    fn state_never_goes_over_maximum() {
+        let mut key = KeyDebouncer::default();
+
+        for _ in 0..10 {
+            key.update(true, KeyDebouncer::DEBOUNCE);
+        }
+
+        assert_eq!(
+            key,
+            KeyDebouncer {
+                state: KeyDebouncer::DEBOUNCE,
+                pressed: true,
+                changed: false
+            }
+        );
+    }
+
+    #[test]
+    fn state_filters_through_integrator() {
+        let mut key = KeyDebouncer::default();
+        let input = [
+            false, false, false, true, true, false, true, false, false, true, true, false, true,
+            true, true, false, true, true, false, false, true, true, true, false, true, true, true,
+            true, true, false, false, false, true, false, true, false, false, true, true, false,
+            false, false, true, false, true, true, false, true, true, false, false, false, false,
+            true, true, false, false, false,
+        ];
+        let state = [
+            0, 0, 0, 1, 2, 1, 2, 1, 0, 1, 2, 1, 2, 3, 3, 2, 3, 3, 2, 1, 2, 3, 3, 2, 3, 3, 3, 3, 3,
+            2, 1, 0, 1, 0, 1, 0, 0, 1, 2, 1, 0, 0, 1, 0, 1, 2, 1, 2, 3, 2, 1, 0, 0, 1, 2, 1, 0, 0,
+        ];
+        let pressed = [
+            false, false, false, false, false, false, false, false, false, false, false, false,
+            false, true, true, true, true, true, true, true, true, true, true, true, true, true,
+            true, true, true, true, true, false, false, false, false, false, false, false, false,
+            false, false, false, false, false, false, false, false, false, true, true, true, false,
+            false, false, false, false, false, false,
+        ];
+        let changed = [
+            false, false, false, false, false, false, false, false, false, false, false, false,
+            false, true, false, false, false, false, false, false, false, false, false, false,
+            false, false, false, false, false, false, false, true, false, false, false, false,
+            false, false, false, false, false, false, false, false, false, false, false, false,
+            true, false, false, true, false, false, false, false, false, false,
+        ];
+
+        let expected = state.iter().zip(pressed.iter()).zip(changed.iter());
+        for (i, ((s, p), c)) in input.iter().zip(expected) {
+            key.update(*i, KeyDebouncer::DEBOUNCE);
+            assert_eq!(
+                key,
+                KeyDebouncer {
+                    state: *s,
+                    pressed: *p,
+                    changed: *c
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn asymmetric_debounce() {
+        let mut key = KeyDebouncer::default();
+
+        // Pressed on the first high sample, and the counter starts over
+        // from the release debounce.
+        assert!(key.debounce(true, 1, 4, DebounceMode::Integrator));
+        assert!(key.changed);
+        assert_eq!(key.state, 4);
+
+        // Chatter on release holds the key down until the counter drains.
+        for sample in [false, false, true, false, false] {
+            assert!(key.debounce(sample, 1, 4, DebounceMode::Integrator));
+        }
+        assert!(!key.debounce(false, 1, 4, DebounceMode::Integrator));
+        assert!(key.changed);
+
+        assert!(key.debounce(true, 1, 4, DebounceMode::Integrator));
+    }
+
+    #[test]
+    fn consecutive_debounce() {
+        let mode = DebounceMode::Consecutive;
+        let mut key = KeyDebouncer::default();
+
+        // A glitch starts the count over, where the integrator would only
+        // have been delayed.
+        for sample in [true, true, false, true, true] {
+            assert!(!key.debounce(sample, 3, 2, mode));
+        }
+        assert!(key.debounce(true, 3, 2, mode));
+        assert!(key.changed);
+        assert_eq!(key.state, 0);
+
+        assert!(key.debounce(false, 3, 2, mode));
+        assert!(key.debounce(true, 3, 2, mode));
+        assert!(key.debounce(false, 3, 2, mode));
+        assert!(!key.debounce(false, 3, 2, mode));
+        assert!(key.changed);
+    }
+}
@@ -0,0 +1,356 @@
+//! Device health diagnostics.
+//!
+//! [`ScanStats`] keeps track of how well the keyboard is scanning: how
+//! often, how many scans failed, and which keys look stuck. The figures
+//! can be reported to the host in a [`StatsReport`].
+//!
+//! Faults the host cannot be told about, like a lost transport, are
+//! [`DiagnosticEvent`]s, which [`DiagnosticFeedback`] turns into blink or
+//! beep codes so that headless keypads can surface them to technicians.
+
+use crate::hid::StatsReport;
+use crate::{Coordinate, KeyEvent};
+
+/// Length of the window the scan rate is measured over, in milliseconds.
+const RATE_WINDOW: u32 = 1000;
+
+/// Matrix scanning statistics.
+///
+/// Fed by the application after every scan, along with the events the
+/// scan produced, and every time a scan fails. Time is measured in
+/// milliseconds by the same wrapping timestamp as the
+/// [`Engine`](crate::engine::Engine).
+pub struct ScanStats<const ROWS: usize, const COLS: usize> {
+    now: u32,
+    window_start: u32,
+    window_scans: u32,
+    scan_rate: u16,
+    errors: u32,
+    stuck_after: u32,
+    pressed_since: [[Option<u32>; COLS]; ROWS],
+}
+
+impl<const ROWS: usize, const COLS: usize> ScanStats<ROWS, COLS> {
+    /// Create statistics considering keys held down for longer than
+    /// `stuck_after` milliseconds stuck.
+    pub const fn new(stuck_after: u32) -> Self {
+        Self {
+            now: 0,
+            window_start: 0,
+            window_scans: 0,
+            scan_rate: 0,
+            errors: 0,
+            stuck_after,
+            pressed_since: [[None; COLS]; ROWS],
+        }
+    }
+
+    /// Record a successful scan at `now`, which produced `events`.
+    pub fn scan(&mut self, now: u32, events: &[KeyEvent]) {
+        self.advance(now);
+        self.window_scans += 1;
+
+        for event in events {
+            let (coordinate, since) = match *event {
+                KeyEvent::KeyDown(c) => (c, Some(now)),
+                KeyEvent::KeyUp(c) => (c, None),
+                KeyEvent::NoEvent => continue,
+            };
+
+            if let Some(slot) = self
+                .pressed_since
+                .get_mut(coordinate.row())
+                .and_then(|row| row.get_mut(coordinate.col()))
+            {
+                *slot = since;
+            }
+        }
+    }
+
+    /// Record a failed scan at `now`.
+    pub fn error(&mut self, now: u32) {
+        self.advance(now);
+        self.errors = self.errors.saturating_add(1);
+    }
+
+    /// Scans per second, over the last complete measurement window.
+    pub const fn scan_rate(&self) -> u16 {
+        self.scan_rate
+    }
+
+    /// Number of failed scans.
+    pub const fn errors(&self) -> u32 {
+        self.errors
+    }
+
+    /// Keys held down for longer than the stuck threshold.
+    pub fn stuck_keys(&self) -> impl Iterator<Item = Coordinate> + '_ {
+        self.pressed_since
+            .iter()
+            .enumerate()
+            .flat_map(move |(row, keys)| {
+                keys.iter().enumerate().filter_map(move |(col, since)| {
+                    since
+                        .filter(|since| self.now.wrapping_sub(*since) > self.stuck_after)
+                        .map(|_| Coordinate::new(row, col))
+                })
+            })
+    }
+
+    /// Feature report carrying these statistics.
+    pub fn report(&self) -> StatsReport {
+        StatsReport::new(
+            self.scan_rate,
+            self.errors,
+            self.stuck_keys().count(),
+            self.stuck_keys().next(),
+        )
+    }
+
+    fn advance(&mut self, now: u32) {
+        self.now = now;
+
+        let elapsed = now.wrapping_sub(self.window_start);
+        if elapsed >= RATE_WINDOW {
+            let rate = u64::from(self.window_scans) * u64::from(RATE_WINDOW) / u64::from(elapsed);
+            self.scan_rate = u16::try_from(rate).unwrap_or(u16::MAX);
+            self.window_start = now;
+            self.window_scans = 0;
+        }
+    }
+}
+
+/// Fault worth signalling to whoever is in front of the keyboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum DiagnosticEvent {
+    /// More keys were pressed than a report can carry
+    Overflow = 0,
+    /// The connection to the host, or to a matrix backend, was lost
+    BackendLost,
+    /// The battery is running low
+    LowBattery,
+    /// Scanning the matrix failed
+    ScanError,
+}
+
+impl DiagnosticEvent {
+    /// Number of diagnostic events.
+    pub const COUNT: usize = 4;
+}
+
+/// Blink or beep code: a number of pulses of equal length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Pattern {
+    pulses: u8,
+    on: u16,
+    off: u16,
+}
+
+impl Pattern {
+    /// Create a pattern of `pulses` pulses, each `on` milliseconds long and
+    /// followed by `off` milliseconds of silence.
+    pub const fn new(pulses: u8, on: u16, off: u16) -> Self {
+        Self { pulses, on, off }
+    }
+
+    /// Number of pulses.
+    pub const fn pulses(&self) -> u8 {
+        self.pulses
+    }
+
+    /// Length of a pulse, in milliseconds.
+    pub const fn on(&self) -> u16 {
+        self.on
+    }
+
+    /// Length of the silence after a pulse, in milliseconds.
+    pub const fn off(&self) -> u16 {
+        self.off
+    }
+
+    /// Length of the whole pattern, in milliseconds.
+    pub const fn duration(&self) -> u32 {
+        self.pulses as u32 * self.period()
+    }
+
+    /// Whether the output is on `elapsed` milliseconds into the pattern,
+    /// or `None` once the pattern is over.
+    pub const fn is_on(&self, elapsed: u32) -> Option<bool> {
+        if elapsed >= self.duration() {
+            None
+        } else {
+            Some(elapsed % self.period() < self.on as u32)
+        }
+    }
+
+    /// Milliseconds into the pattern at which the output next changes
+    /// after `elapsed`, or `None` once the pattern is over.
+    const fn next_edge(&self, elapsed: u32) -> Option<u32> {
+        if elapsed >= self.duration() {
+            return None;
+        }
+
+        let start = elapsed - elapsed % self.period();
+        if elapsed - start < self.on as u32 {
+            Some(start + self.on as u32)
+        } else {
+            Some(start + self.period())
+        }
+    }
+
+    const fn period(&self) -> u32 {
+        self.on as u32 + self.off as u32
+    }
+}
+
+/// Feedback patterns for [`DiagnosticEvent`]s.
+///
+/// Drives a single output, like a status LED or a buzzer: the application
+/// passes every diagnostic event to [`DiagnosticFeedback::event`] and
+/// sets the output to [`DiagnosticFeedback::output`], at the latest by
+/// [`DiagnosticFeedback::next_deadline`]. An event interrupts the pattern
+/// of the previous one. Time is measured in milliseconds by the same
+/// wrapping timestamp as the [`Engine`](crate::engine::Engine).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiagnosticFeedback {
+    patterns: [Option<Pattern>; DiagnosticEvent::COUNT],
+    playing: Option<(Pattern, u32)>,
+}
+
+impl DiagnosticFeedback {
+    /// Create feedback without a pattern for any event.
+    pub const fn silent() -> Self {
+        Self {
+            patterns: [None; DiagnosticEvent::COUNT],
+            playing: None,
+        }
+    }
+
+    /// Builder-style setter for the pattern of `event`.
+    #[must_use]
+    pub const fn with(mut self, event: DiagnosticEvent, pattern: Option<Pattern>) -> Self {
+        self.patterns[event as usize] = pattern;
+        self
+    }
+
+    /// Pattern of `event`.
+    pub const fn pattern(&self, event: DiagnosticEvent) -> Option<Pattern> {
+        self.patterns[event as usize]
+    }
+
+    /// Change the pattern of `event`.
+    pub fn set_pattern(&mut self, event: DiagnosticEvent, pattern: Option<Pattern>) {
+        self.patterns[event as usize] = pattern;
+    }
+
+    /// Start the pattern of `event` at `now`.
+    pub fn event(&mut self, now: u32, event: DiagnosticEvent) {
+        if let Some(pattern) = self.pattern(event) {
+            self.playing = Some((pattern, now));
+        }
+    }
+
+    /// Whether the output should be on at `now`.
+    pub fn output(&mut self, now: u32) -> bool {
+        let Some((pattern, start)) = self.playing else {
+            return false;
+        };
+
+        match pattern.is_on(now.wrapping_sub(start)) {
+            Some(on) => on,
+            None => {
+                self.playing = None;
+                false
+            }
+        }
+    }
+
+    /// Time at which the output next changes, if a pattern is playing.
+    pub fn next_deadline(&self, now: u32) -> Option<u32> {
+        let (pattern, start) = self.playing?;
+
+        pattern
+            .next_edge(now.wrapping_sub(start))
+            .map(|edge| start.wrapping_add(edge))
+    }
+}
+
+impl Default for DiagnosticFeedback {
+    fn default() -> Self {
+        Self::silent()
+            .with(DiagnosticEvent::Overflow, Some(Pattern::new(2, 100, 100)))
+            .with(
+                DiagnosticEvent::BackendLost,
+                Some(Pattern::new(3, 500, 250)),
+            )
+            .with(DiagnosticEvent::LowBattery, Some(Pattern::new(1, 1000, 0)))
+            .with(DiagnosticEvent::ScanError, Some(Pattern::new(4, 100, 100)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scan_rate_and_errors() {
+        let mut stats = ScanStats::<1, 1>::new(10_000);
+
+        for now in (0..1000).step_by(2) {
+            stats.scan(now, &[]);
+        }
+        stats.error(1000);
+        assert_eq!(stats.scan_rate(), 500);
+        assert_eq!(stats.errors(), 1);
+    }
+
+    #[test]
+    fn stuck_keys() {
+        let mut stats = ScanStats::<2, 2>::new(1000);
+        let key = Coordinate::new(1, 0);
+
+        stats.scan(
+            0,
+            &[
+                KeyEvent::KeyDown(key),
+                KeyEvent::KeyDown(Coordinate::new(0, 1)),
+            ],
+        );
+        stats.scan(500, &[KeyEvent::KeyUp(Coordinate::new(0, 1))]);
+        assert_eq!(stats.stuck_keys().count(), 0);
+
+        stats.scan(1001, &[]);
+        assert!(stats.stuck_keys().eq([key]));
+
+        stats.scan(1002, &[KeyEvent::KeyUp(key)]);
+        assert_eq!(stats.stuck_keys().count(), 0);
+    }
+
+    #[test]
+    fn blink_codes() {
+        let mut feedback = DiagnosticFeedback::silent()
+            .with(DiagnosticEvent::Overflow, Some(Pattern::new(2, 100, 50)));
+
+        feedback.event(0, DiagnosticEvent::LowBattery);
+        assert!(!feedback.output(0));
+        assert_eq!(feedback.next_deadline(0), None);
+
+        let start = u32::MAX - 10;
+        feedback.event(start, DiagnosticEvent::Overflow);
+        assert!(feedback.output(start));
+        assert_eq!(feedback.next_deadline(start), Some(start.wrapping_add(100)));
+        assert!(!feedback.output(start.wrapping_add(100)));
+        assert_eq!(
+            feedback.next_deadline(start.wrapping_add(100)),
+            Some(start.wrapping_add(150))
+        );
+        assert!(feedback.output(start.wrapping_add(200)));
+        assert!(!feedback.output(start.wrapping_add(300)));
+        assert_eq!(feedback.next_deadline(start.wrapping_add(300)), None);
+    }
+}
@@ -0,0 +1,428 @@
+use super::Typematic;
+use crate::scancode::{set1_break, set1_make, ScanCodes};
+use crate::time::is_due;
+use crate::KeyCode;
+
+/// Notification that a byte is waiting in the output buffer, the
+/// equivalent of raising IRQ1.
+pub trait DataReady {
+    /// Called every time a new byte becomes available to the host.
+    fn data_ready(&mut self);
+}
+
+impl DataReady for () {
+    #[inline]
+    fn data_ready(&mut self) {}
+}
+
+impl<F: FnMut()> DataReady for F {
+    #[inline]
+    fn data_ready(&mut self) {
+        self();
+    }
+}
+
+/// Scan code output buffer of an embedded controller.
+///
+/// Fed with the keys pressed after every scan, e.g. from
+/// [`Engine::keycodes`](crate::engine::Engine::keycodes), it queues the
+/// make and break codes of every key pressed or released since, up to
+/// `N` bytes. The host takes them one byte at a time with
+/// [`EcKeyboard::read`], and `irq` is notified whenever the next byte is
+/// ready, either because it was queued into an empty buffer or because
+/// the host took the one before it.
+///
+/// Scan codes are never split: if a key's code does not fit in the
+/// buffer, the change is not recorded, so that the next update retries
+/// it once the host has caught up.
+///
+/// Like a PS/2 keyboard, the last key pressed repeats its make code while
+/// held, at the [`Typematic`] rate set by the host, as long as the
+/// application calls [`EcKeyboard::tick`] after every update, at the
+/// latest by [`EcKeyboard::next_deadline`]. Time is measured in
+/// milliseconds by the same wrapping timestamp as the
+/// [`Engine`](crate::engine::Engine).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EcKeyboard<const N: usize, I = ()> {
+    buffer: [u8; N],
+    head: usize,
+    len: usize,
+    pressed: [u32; 8],
+    irq: I,
+    overflowed: bool,
+    typematic: Typematic,
+    repeating: Option<KeyCode>,
+    repeat_at: Option<u32>,
+}
+
+impl<const N: usize> EcKeyboard<N> {
+    /// Create an empty buffer without data ready notifications, for
+    /// hosts polling [`EcKeyboard::data_ready`].
+    pub const fn new() -> Self {
+        Self::with_irq(())
+    }
+}
+
+impl<const N: usize, I: DataReady> EcKeyboard<N, I> {
+    /// Create an empty buffer notifying `irq` when data is ready.
+    pub const fn with_irq(irq: I) -> Self {
+        const {
+            assert!(N >= ScanCodes::MAX_LEN);
+        }
+
+        Self {
+            buffer: [0; N],
+            head: 0,
+            len: 0,
+            pressed: [0; 8],
+            irq,
+            overflowed: false,
+            typematic: Typematic::DEFAULT,
+            repeating: None,
+            repeat_at: None,
+        }
+    }
+
+    /// Mutable access to the data ready notification.
+    pub fn irq_mut(&mut self) -> &mut I {
+        &mut self.irq
+    }
+
+    /// Whether a byte is waiting for the host, i.e. the OBF flag.
+    pub const fn data_ready(&self) -> bool {
+        self.len != 0
+    }
+
+    /// Number of queued bytes.
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether no bytes are queued.
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Typematic rate and delay of the repeating key.
+    pub const fn typematic(&self) -> Typematic {
+        self.typematic
+    }
+
+    /// Change the typematic rate and delay, e.g. to
+    /// [`Ps2Keyboard::typematic`](super::Ps2Keyboard::typematic) after
+    /// every command of the host.
+    pub fn set_typematic(&mut self, typematic: Typematic) {
+        self.typematic = typematic;
+    }
+
+    /// Whether `code` is recorded as pressed.
+    pub fn is_pressed(&self, code: KeyCode) -> bool {
+        index(code).is_some_and(|(word, bit)| self.pressed[word] & bit != 0)
+    }
+
+    /// Queue the make code of `code`, unless it is already pressed or has
+    /// no scan code.
+    ///
+    /// Returns `false` if the make code did not fit in the buffer.
+    pub fn press(&mut self, code: KeyCode) -> bool {
+        self.change(code, true)
+    }
+
+    /// Queue the break code of `code`, unless it is not pressed.
+    ///
+    /// Returns `false` if the break code did not fit in the buffer.
+    pub fn release(&mut self, code: KeyCode) -> bool {
+        self.change(code, false)
+    }
+
+    /// Update the set of pressed keys, queueing break codes for every key
+    /// no longer pressed, then make codes for every newly pressed one.
+    ///
+    /// Returns `false` if some codes did not fit in the buffer.
+    pub fn update(&mut self, pressed: impl IntoIterator<Item = KeyCode>) -> bool {
+        let mut current = [0u32; 8];
+        for (word, bit) in pressed.into_iter().filter_map(index) {
+            current[word] |= bit;
+        }
+
+        let mut fits = true;
+
+        for (word, &bits) in current.iter().enumerate() {
+            let mut released = self.pressed[word] & !bits;
+
+            while released != 0 {
+                let bit = released.trailing_zeros();
+                released &= released - 1;
+                fits &= self.release(code(word, bit));
+            }
+        }
+
+        for (word, &bits) in current.iter().enumerate() {
+            let mut pressed = bits & !self.pressed[word];
+
+            while pressed != 0 {
+                let bit = pressed.trailing_zeros();
+                pressed &= pressed - 1;
+                fits &= self.press(code(word, bit));
+            }
+        }
+
+        fits
+    }
+
+    /// Queue a byte other than a scan code, e.g. a response from
+    /// [`Ps2Keyboard::command`](super::Ps2Keyboard::command).
+    ///
+    /// Returns `false` if the buffer is full.
+    pub fn respond(&mut self, byte: u8) -> bool {
+        if self.queue(&[byte]) {
+            true
+        } else {
+            self.overflowed = true;
+            false
+        }
+    }
+
+    /// Take the next byte for the host, as on a read of port `0x60`.
+    pub fn read(&mut self) -> Option<u8> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let byte = self.buffer[self.head];
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+
+        if !self.is_empty() {
+            self.irq.data_ready();
+        }
+
+        Some(byte)
+    }
+
+    /// Repeat the make code of the last key pressed at `now`, if it is
+    /// due.
+    ///
+    /// Returns `false` if the make code did not fit in the buffer, in
+    /// which case it is retried on the next tick.
+    pub fn tick(&mut self, now: u32) -> bool {
+        let Some(code) = self.repeating else {
+            return true;
+        };
+
+        let Some(at) = self.repeat_at else {
+            self.repeat_at = Some(now.wrapping_add(u32::from(self.typematic.delay())));
+            return true;
+        };
+
+        if !is_due(now, at) {
+            return true;
+        }
+
+        let Some(codes) = set1_make(code) else {
+            return true;
+        };
+
+        if !self.queue(&codes) {
+            self.overflowed = true;
+            return false;
+        }
+
+        self.repeat_at = Some(now.wrapping_add(u32::from(self.typematic.period())));
+        true
+    }
+
+    /// Time at which the repeating key next repeats, if known.
+    pub const fn next_deadline(&self) -> Option<u32> {
+        self.repeat_at
+    }
+
+    /// Drop every queued byte and forget every pressed key, e.g. when the
+    /// host resets the keyboard.
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+        self.pressed = [0; 8];
+        self.repeating = None;
+        self.repeat_at = None;
+    }
+
+    /// Whether codes did not fit in the buffer since the last call,
+    /// clearing the flag.
+    pub fn take_overflow(&mut self) -> bool {
+        core::mem::take(&mut self.overflowed)
+    }
+
+    fn change(&mut self, code: KeyCode, press: bool) -> bool {
+        let Some((word, bit)) = index(code) else {
+            return true;
+        };
+
+        if (self.pressed[word] & bit != 0) == press {
+            return true;
+        }
+
+        let codes = if press {
+            set1_make(code)
+        } else {
+            set1_break(code)
+        };
+        let Some(codes) = codes else {
+            return true;
+        };
+
+        if !self.queue(&codes) {
+            self.overflowed = true;
+            return false;
+        }
+
+        self.pressed[word] ^= bit;
+
+        if press {
+            // Keys without a break code, like Pause, do not repeat.
+            let repeats = set1_break(code).is_some_and(|codes| !codes.is_empty());
+            self.repeating = repeats.then_some(code);
+            self.repeat_at = None;
+        } else if self.repeating == Some(code) {
+            self.repeating = None;
+            self.repeat_at = None;
+        }
+
+        true
+    }
+
+    fn queue(&mut self, bytes: &[u8]) -> bool {
+        if bytes.len() > N - self.len {
+            return false;
+        }
+
+        let was_empty = self.is_empty();
+
+        for byte in bytes {
+            self.buffer[(self.head + self.len) % N] = *byte;
+            self.len += 1;
+        }
+
+        if was_empty && !self.is_empty() {
+            self.irq.data_ready();
+        }
+
+        true
+    }
+}
+
+impl<const N: usize> Default for EcKeyboard<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Word and bit of `code` in a pressed key bitmap, if it fits in one.
+fn index(code: KeyCode) -> Option<(usize, u32)> {
+    let code = u8::try_from(u16::from(code)).ok()?;
+    Some((usize::from(code / 32), 1 << (code % 32)))
+}
+
+/// Key code at `word` and `bit` of a pressed key bitmap.
+fn code(word: usize, bit: u32) -> KeyCode {
+    u16::try_from(word * 32)
+        .ok()
+        .and_then(|usage| KeyCode::try_from(usage + bit as u16).ok())
+        .unwrap_or(KeyCode::NoEvent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+    use std::vec::Vec;
+
+    fn read_all<const N: usize, I: DataReady>(ec: &mut EcKeyboard<N, I>) -> Vec<u8> {
+        core::iter::from_fn(|| ec.read()).collect()
+    }
+
+    #[test]
+    fn make_and_break_codes() {
+        let mut ec = EcKeyboard::<16>::new();
+
+        assert!(ec.update([KeyCode::KA, KeyCode::KpRightControl, KeyCode::KpHexadecimal]));
+        assert!(ec.is_pressed(KeyCode::KA));
+        assert!(ec.update([KeyCode::KpRightControl, KeyCode::KB]));
+        assert!(ec.update([KeyCode::KpRightControl, KeyCode::KB]));
+        assert!(!ec.is_pressed(KeyCode::KA));
+        assert!(ec.data_ready());
+
+        assert_eq!(read_all(&mut ec), [0x1e, 0xe0, 0x1d, 0x9e, 0x30]);
+        assert!(!ec.data_ready());
+
+        assert!(ec.update([]));
+        assert_eq!(read_all(&mut ec), [0xb0, 0xe0, 0x9d]);
+    }
+
+    #[test]
+    fn irq_per_byte() {
+        let raised = Cell::new(0);
+        let mut ec = EcKeyboard::<8, _>::with_irq(|| raised.set(raised.get() + 1));
+
+        assert!(ec.press(KeyCode::KUpArrow));
+        assert!(ec.press(KeyCode::KA));
+        assert_eq!(raised.get(), 1);
+
+        assert_eq!(ec.read(), Some(0xe0));
+        assert_eq!(ec.read(), Some(0x48));
+        assert_eq!(ec.read(), Some(0x1e));
+        assert_eq!(ec.read(), None);
+        assert_eq!(raised.get(), 3);
+    }
+
+    #[test]
+    fn overflow_is_retried() {
+        let mut ec = EcKeyboard::<6>::new();
+
+        assert!(ec.press(KeyCode::KPrintScreen));
+        assert!(!ec.update([KeyCode::KPrintScreen, KeyCode::KUpArrow, KeyCode::KA]));
+        assert!(ec.take_overflow());
+        assert!(!ec.take_overflow());
+        assert!(ec.is_pressed(KeyCode::KA));
+        assert!(!ec.is_pressed(KeyCode::KUpArrow));
+
+        assert_eq!(read_all(&mut ec), [0xe0, 0x2a, 0xe0, 0x37, 0x1e]);
+        assert!(ec.update([KeyCode::KPrintScreen, KeyCode::KUpArrow, KeyCode::KA]));
+        assert_eq!(read_all(&mut ec), [0xe0, 0x48]);
+
+        ec.clear();
+        assert!(!ec.is_pressed(KeyCode::KA));
+        assert!(ec.is_empty());
+    }
+
+    #[test]
+    fn typematic_repeat() {
+        let mut ec = EcKeyboard::<16>::new();
+        // 250 ms delay, 33 ms period.
+        ec.set_typematic(Typematic::from_bits(0x00));
+
+        let start = u32::MAX - 100;
+        assert!(ec.update([KeyCode::KA]));
+        assert!(ec.tick(start));
+        assert_eq!(ec.next_deadline(), Some(start.wrapping_add(250)));
+        assert!(ec.tick(start.wrapping_add(249)));
+        assert_eq!(read_all(&mut ec), [0x1e]);
+
+        assert!(ec.tick(start.wrapping_add(250)));
+        assert!(ec.tick(start.wrapping_add(283)));
+        assert_eq!(read_all(&mut ec), [0x1e, 0x1e]);
+
+        // The last key pressed repeats; releasing another one does not
+        // stop it.
+        assert!(ec.update([KeyCode::KA, KeyCode::KUpArrow]));
+        assert!(ec.update([KeyCode::KUpArrow]));
+        assert!(ec.tick(start.wrapping_add(300)));
+        assert!(ec.tick(start.wrapping_add(550)));
+        assert_eq!(read_all(&mut ec), [0xe0, 0x48, 0x9e, 0xe0, 0x48]);
+
+        assert!(ec.update([]));
+        assert!(ec.tick(start.wrapping_add(1000)));
+        assert_eq!(ec.next_deadline(), None);
+        assert_eq!(read_all(&mut ec), [0xe0, 0xc8]);
+    }
+}
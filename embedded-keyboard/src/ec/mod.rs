@@ -0,0 +1,19 @@
+//! Embedded controller keyboard interface.
+//!
+//! On PC platforms the embedded controller (EC) usually emulates the 8042
+//! keyboard controller of the original PC/AT: the host reads scan codes
+//! one byte at a time from its output buffer, and is told a byte is
+//! waiting by the output buffer full (OBF) status flag and IRQ1.
+//! [`EcKeyboard`] is the keyboard side of that interface, turning pressed
+//! keys into a queue of Scan Code Set 1 bytes, and [`Ps2Keyboard`] answers
+//! the commands the host sends the keyboard through the same interface.
+//! [`PowerButton`] keeps the power key on the matrix out of both, and
+//! classifies its presses by length for the platform's power sequencing.
+
+mod keyboard;
+mod power;
+mod ps2;
+
+pub use self::keyboard::*;
+pub use self::power::*;
+pub use self::ps2::*;
@@ -0,0 +1,257 @@
+use crate::processor::Processor;
+use crate::{Coordinate, KeyEvent};
+
+/// How long the power button was held.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PressLength {
+    /// Released before the long press duration, e.g. to suspend
+    Short,
+    /// Held for the long press duration, e.g. to offer shutting down
+    Long,
+    /// Held for the very long press duration, e.g. to force the platform
+    /// off
+    VeryLong,
+}
+
+/// Power button event, reported to a [`PowerHook`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PowerEvent {
+    /// The button was pressed
+    Pressed,
+    /// The button is still held after the long press duration
+    Long,
+    /// The button is still held after the very long press duration
+    VeryLong,
+    /// The button was released after being held for the given length
+    Released(PressLength),
+}
+
+/// Receives the [`PowerEvent`]s of a [`PowerButton`]. Closures taking the
+/// time and the event implement this trait.
+pub trait PowerHook {
+    /// Called when `event` happened at `now`.
+    fn power(&mut self, now: u32, event: PowerEvent);
+}
+
+impl PowerHook for () {
+    #[inline]
+    fn power(&mut self, _now: u32, _event: PowerEvent) {}
+}
+
+impl<F: FnMut(u32, PowerEvent)> PowerHook for F {
+    #[inline]
+    fn power(&mut self, now: u32, event: PowerEvent) {
+        self(now, event);
+    }
+}
+
+/// Power button on the key matrix.
+///
+/// As a [`Processor`] ahead of the keymap, it takes the events of its key
+/// out of the stream, so that the power button never reaches the host
+/// through HID or scan codes whatever the keymap says, and classifies its
+/// presses instead. `hook` learns about the press at once, about long and
+/// very long presses as soon as their duration elapses while the button
+/// is still held, provided the processor is ticked by its
+/// [`Processor::next_deadline`], and about the release with the length
+/// reached.
+pub struct PowerButton<H = ()> {
+    key: Coordinate,
+    long: u16,
+    very_long: u16,
+    held: Option<(u32, PressLength)>,
+    hook: H,
+}
+
+impl PowerButton {
+    /// Default long press duration, in milliseconds.
+    pub const LONG: u16 = 1_000;
+
+    /// Default very long press duration, in milliseconds.
+    pub const VERY_LONG: u16 = 4_000;
+
+    /// Create a power button on `key`.
+    pub const fn new(key: Coordinate) -> Self {
+        Self::with_hook(key, ())
+    }
+}
+
+impl<H: PowerHook> PowerButton<H> {
+    /// Create a power button on `key` reporting its events to `hook`.
+    pub const fn with_hook(key: Coordinate, hook: H) -> Self {
+        Self {
+            key,
+            long: PowerButton::LONG,
+            very_long: PowerButton::VERY_LONG,
+            held: None,
+            hook,
+        }
+    }
+
+    /// Mutable access to the hook.
+    pub fn hook_mut(&mut self) -> &mut H {
+        &mut self.hook
+    }
+
+    /// Coordinate of the power button.
+    pub const fn key(&self) -> Coordinate {
+        self.key
+    }
+
+    /// Long and very long press durations, in milliseconds.
+    pub const fn durations(&self) -> (u16, u16) {
+        (self.long, self.very_long)
+    }
+
+    /// Change the long and very long press durations, in milliseconds.
+    /// The very long duration is raised to the long one if shorter.
+    pub fn set_durations(&mut self, long: u16, very_long: u16) {
+        self.long = long;
+        self.very_long = very_long.max(long);
+    }
+
+    /// Whether the button is held.
+    pub const fn is_pressed(&self) -> bool {
+        self.held.is_some()
+    }
+
+    /// Length reached by the current press, if the button is held.
+    pub fn length(&self) -> Option<PressLength> {
+        self.held.map(|(_, length)| length)
+    }
+
+    /// Step to the next press length if its duration elapsed by `now`.
+    fn advance(&mut self, now: u32) {
+        let Some((since, length)) = self.held else {
+            return;
+        };
+        let elapsed = now.wrapping_sub(since);
+
+        if length < PressLength::Long && elapsed >= u32::from(self.long) {
+            self.held = Some((since, PressLength::Long));
+            self.hook.power(now, PowerEvent::Long);
+        }
+        if length < PressLength::VeryLong && elapsed >= u32::from(self.very_long) {
+            self.held = Some((since, PressLength::VeryLong));
+            self.hook.power(now, PowerEvent::VeryLong);
+        }
+    }
+}
+
+impl<H: PowerHook> Processor for PowerButton<H> {
+    fn event(&mut self, now: u32, event: KeyEvent, mut emit: impl FnMut(KeyEvent)) {
+        match event {
+            KeyEvent::KeyDown(c) if c == self.key => {
+                if self.held.is_none() {
+                    self.held = Some((now, PressLength::Short));
+                    self.hook.power(now, PowerEvent::Pressed);
+                }
+            }
+            KeyEvent::KeyUp(c) if c == self.key => {
+                self.advance(now);
+                if let Some((_, length)) = self.held.take() {
+                    self.hook.power(now, PowerEvent::Released(length));
+                }
+            }
+            _ => emit(event),
+        }
+    }
+
+    fn tick(&mut self, now: u32, _emit: impl FnMut(KeyEvent)) {
+        self.advance(now);
+    }
+
+    fn next_deadline(&self) -> Option<u32> {
+        let (since, length) = self.held?;
+        let after = match length {
+            PressLength::Short => self.long,
+            PressLength::Long => self.very_long,
+            PressLength::VeryLong => return None,
+        };
+
+        Some(since.wrapping_add(u32::from(after)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    fn run(processor: &mut impl Processor, now: u32, events: &[KeyEvent]) -> Vec<KeyEvent> {
+        let mut out = Vec::new();
+        processor.tick(now, |e| out.push(e));
+        for event in events {
+            processor.event(now, *event, |e| out.push(e));
+        }
+        out
+    }
+
+    #[test]
+    fn classifies_presses() {
+        let power = Coordinate::new(5, 0);
+        let other = KeyEvent::KeyDown(Coordinate::new(0, 0));
+        let mut events = Vec::new();
+        let mut button = PowerButton::with_hook(power, |now, event| events.push((now, event)));
+
+        // Never reaches the keymap.
+        assert_eq!(
+            run(&mut button, 0, &[KeyEvent::KeyDown(power), other]),
+            [other]
+        );
+        assert!(button.is_pressed());
+        assert_eq!(button.next_deadline(), Some(1_000));
+        assert_eq!(run(&mut button, 500, &[KeyEvent::KeyUp(power)]), []);
+
+        run(&mut button, 1_000, &[KeyEvent::KeyDown(power)]);
+        run(&mut button, 2_000, &[]);
+        assert_eq!(button.length(), Some(PressLength::Long));
+        assert_eq!(button.next_deadline(), Some(5_000));
+        run(&mut button, 2_500, &[KeyEvent::KeyUp(power)]);
+
+        run(&mut button, 10_000, &[KeyEvent::KeyDown(power)]);
+        run(&mut button, 14_000, &[]);
+        assert_eq!(button.next_deadline(), None);
+        run(&mut button, 20_000, &[KeyEvent::KeyUp(power)]);
+        assert!(!button.is_pressed());
+
+        assert_eq!(
+            events,
+            [
+                (0, PowerEvent::Pressed),
+                (500, PowerEvent::Released(PressLength::Short)),
+                (1_000, PowerEvent::Pressed),
+                (2_000, PowerEvent::Long),
+                (2_500, PowerEvent::Released(PressLength::Long)),
+                (10_000, PowerEvent::Pressed),
+                (14_000, PowerEvent::Long),
+                (14_000, PowerEvent::VeryLong),
+                (20_000, PowerEvent::Released(PressLength::VeryLong)),
+            ]
+        );
+    }
+
+    #[test]
+    fn release_without_tick() {
+        let power = Coordinate::new(0, 0);
+        let mut events = Vec::new();
+        let mut button = PowerButton::with_hook(power, |_, event| events.push(event));
+        button.set_durations(100, 50);
+        assert_eq!(button.durations(), (100, 100));
+
+        button.event(0, KeyEvent::KeyDown(power), |_| {});
+        button.event(150, KeyEvent::KeyUp(power), |_| {});
+
+        assert_eq!(
+            events,
+            [
+                PowerEvent::Pressed,
+                PowerEvent::Long,
+                PowerEvent::VeryLong,
+                PowerEvent::Released(PressLength::VeryLong),
+            ]
+        );
+    }
+}
@@ -0,0 +1,397 @@
+use crate::hid::LedState;
+use crate::KeyboardLeds;
+
+/// Acknowledge, sent for every command and argument accepted.
+pub const ACK: u8 = 0xfa;
+/// Request to send the last command or argument again, sent for every
+/// one rejected.
+pub const RESEND: u8 = 0xfe;
+/// Basic assurance test passed, sent after a reset.
+pub const BAT_PASSED: u8 = 0xaa;
+/// Response to [`Ps2Command::Echo`].
+pub const ECHO: u8 = 0xee;
+/// Keyboard ID of an MF2 keyboard, sent after [`Ps2Command::Identify`].
+pub const KEYBOARD_ID: [u8; 2] = [0xab, 0x83];
+
+/// Typematic rate and delay, as set by the host with
+/// [`Ps2Command::SetTypematic`].
+///
+/// The byte holds the delay before a held key starts repeating in bits 5
+/// and 6, in steps of 250 milliseconds, and the repeat rate in bits 0 to
+/// 4, from 30 down to 2 repeats per second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Typematic(u8);
+
+impl Typematic {
+    /// Typematic byte after a reset: 500 milliseconds, 10.9 repeats per
+    /// second.
+    pub const DEFAULT: Self = Self(0x2b);
+
+    /// Create typematic settings from the argument of the command. Bit 7
+    /// is ignored.
+    pub const fn from_bits(bits: u8) -> Self {
+        Self(bits & 0x7f)
+    }
+
+    /// Argument of the command.
+    pub const fn bits(&self) -> u8 {
+        self.0
+    }
+
+    /// Delay before a held key starts repeating, in milliseconds.
+    pub const fn delay(&self) -> u16 {
+        250 * (1 + ((self.0 >> 5) & 0x03) as u16)
+    }
+
+    /// Time between repeats, in milliseconds, rounded down.
+    pub const fn period(&self) -> u16 {
+        let mantissa = 8 + (self.0 & 0x07) as u32;
+        let exponent = (self.0 >> 3) & 0x03;
+
+        // Periods are multiples of 4.17 milliseconds.
+        ((mantissa << exponent) * 417 / 100) as u16
+    }
+}
+
+impl Default for Typematic {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Command sent by the host to the keyboard, once complete with its
+/// argument.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Ps2Command {
+    /// `0xed`: set the indicator LEDs
+    SetLeds(LedState),
+    /// `0xee`: echo
+    Echo,
+    /// `0xf0`: select scan code set 1 to 3, or report the current one
+    /// when `None`
+    ScanCodeSet(Option<u8>),
+    /// `0xf2`: identify the keyboard
+    Identify,
+    /// `0xf3`: set the typematic rate and delay
+    SetTypematic(Typematic),
+    /// `0xf4`: enable scanning
+    Enable,
+    /// `0xf5`: disable scanning and restore the defaults
+    Disable,
+    /// `0xf6`: restore the defaults
+    SetDefault,
+    /// `0xfe`: send the last response again
+    Resend,
+    /// `0xff`: reset and run the basic assurance test
+    Reset,
+}
+
+/// Command awaiting its argument.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pending {
+    None,
+    SetLeds,
+    ScanCodeSet,
+    SetTypematic,
+}
+
+/// Device side of the PS/2 keyboard command set.
+///
+/// Transport-agnostic: the application passes every byte the host writes
+/// to the keyboard, e.g. through port `0x60` of an emulated 8042, to
+/// [`Ps2Keyboard::command`], and sends the responses back to the host,
+/// e.g. with [`EcKeyboard::respond`](super::EcKeyboard::respond). LEDs set
+/// by the host are shown on `leds` right away; everything else is state
+/// for the application to act on, in particular applying the
+/// [`typematic`](Ps2Keyboard::typematic) settings to the key repeat of
+/// [`EcKeyboard::set_typematic`](super::EcKeyboard::set_typematic), not
+/// feeding scan codes while scanning is disabled, and dropping queued ones
+/// on [`Ps2Command::Reset`] and [`Ps2Command::Disable`].
+///
+/// Responses are the keyboard's own; translating them is up to the
+/// controller. Scan codes are translated to Set 1 regardless of the
+/// selected set, which is only recorded and reported back.
+#[derive(Debug)]
+pub struct Ps2Keyboard<L> {
+    leds: L,
+    led_state: LedState,
+    pending: Pending,
+    scan_code_set: u8,
+    typematic: Typematic,
+    scanning: bool,
+    last: u8,
+}
+
+impl<L: KeyboardLeds> Ps2Keyboard<L> {
+    /// Create a keyboard in its reset state, driving `leds`.
+    pub const fn new(leds: L) -> Self {
+        Self {
+            leds,
+            led_state: LedState::from_bits(0),
+            pending: Pending::None,
+            scan_code_set: 2,
+            typematic: Typematic::DEFAULT,
+            scanning: true,
+            last: BAT_PASSED,
+        }
+    }
+
+    /// Mutable access to the LEDs.
+    pub fn leds_mut(&mut self) -> &mut L {
+        &mut self.leds
+    }
+
+    /// LED state last set by the host.
+    pub const fn led_state(&self) -> LedState {
+        self.led_state
+    }
+
+    /// Scan code set selected by the host.
+    pub const fn scan_code_set(&self) -> u8 {
+        self.scan_code_set
+    }
+
+    /// Typematic rate and delay set by the host.
+    pub const fn typematic(&self) -> Typematic {
+        self.typematic
+    }
+
+    /// Whether scanning is enabled.
+    pub const fn scanning(&self) -> bool {
+        self.scanning
+    }
+
+    /// Handle a byte written by the host, passing the responses to
+    /// `respond`.
+    ///
+    /// Returns the command once it is complete with its argument, or an
+    /// error if the LEDs could not be set.
+    pub fn command(
+        &mut self,
+        byte: u8,
+        mut respond: impl FnMut(u8),
+    ) -> Result<Option<Ps2Command>, L::Error> {
+        let mut send = |this: &mut Self, byte: u8| {
+            if byte != RESEND {
+                this.last = byte;
+            }
+            respond(byte);
+        };
+
+        let pending = core::mem::replace(&mut self.pending, Pending::None);
+
+        // Commands are accepted in place of an argument, except for the
+        // values which are valid arguments.
+        let command = match (pending, byte) {
+            (Pending::SetLeds, 0x00..=0x07) => {
+                // Scroll Lock comes first on PS/2, Num Lock in HID.
+                let leds = LedState::from_bits(
+                    [
+                        LedState::SCROLL_LOCK,
+                        LedState::NUM_LOCK,
+                        LedState::CAPS_LOCK,
+                    ]
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| byte & (1 << i) != 0)
+                    .fold(0, |bits, (_, bit)| bits | bit),
+                );
+
+                send(self, ACK);
+                self.set_leds(leds)?;
+                Ps2Command::SetLeds(leds)
+            }
+            (Pending::ScanCodeSet, 0x00) => {
+                send(self, ACK);
+                send(self, self.scan_code_set);
+                Ps2Command::ScanCodeSet(None)
+            }
+            (Pending::ScanCodeSet, 0x01..=0x03) => {
+                self.scan_code_set = byte;
+                send(self, ACK);
+                Ps2Command::ScanCodeSet(Some(byte))
+            }
+            (Pending::SetTypematic, 0x00..=0x7f) => {
+                self.typematic = Typematic::from_bits(byte);
+                send(self, ACK);
+                Ps2Command::SetTypematic(self.typematic)
+            }
+            (_, 0xed) => {
+                self.pending = Pending::SetLeds;
+                send(self, ACK);
+                return Ok(None);
+            }
+            (_, 0xee) => {
+                send(self, ECHO);
+                Ps2Command::Echo
+            }
+            (_, 0xf0) => {
+                self.pending = Pending::ScanCodeSet;
+                send(self, ACK);
+                return Ok(None);
+            }
+            (_, 0xf2) => {
+                send(self, ACK);
+                for byte in KEYBOARD_ID {
+                    send(self, byte);
+                }
+                Ps2Command::Identify
+            }
+            (_, 0xf3) => {
+                self.pending = Pending::SetTypematic;
+                send(self, ACK);
+                return Ok(None);
+            }
+            (_, 0xf4) => {
+                self.scanning = true;
+                send(self, ACK);
+                Ps2Command::Enable
+            }
+            (_, 0xf5) => {
+                self.defaults();
+                self.scanning = false;
+                send(self, ACK);
+                Ps2Command::Disable
+            }
+            (_, 0xf6) => {
+                self.defaults();
+                send(self, ACK);
+                Ps2Command::SetDefault
+            }
+            (_, 0xfe) => {
+                // Still awaiting the argument whose request was lost.
+                self.pending = pending;
+                send(self, self.last);
+                Ps2Command::Resend
+            }
+            (_, 0xff) => {
+                self.defaults();
+                self.scanning = true;
+                send(self, ACK);
+                self.set_leds(LedState::default())?;
+                send(self, BAT_PASSED);
+                Ps2Command::Reset
+            }
+            (_, _) => {
+                self.pending = pending;
+                send(self, RESEND);
+                return Ok(None);
+            }
+        };
+
+        Ok(Some(command))
+    }
+
+    fn defaults(&mut self) {
+        self.scan_code_set = 2;
+        self.typematic = Typematic::DEFAULT;
+    }
+
+    fn set_leds(&mut self, leds: LedState) -> Result<(), L::Error> {
+        self.led_state = leds;
+        self.leds.set_leds(leds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ErrorType;
+    use core::convert::Infallible;
+    use std::vec::Vec;
+
+    #[derive(Default)]
+    struct Indicators(LedState);
+
+    impl ErrorType for Indicators {
+        type Error = Infallible;
+    }
+
+    impl KeyboardLeds for Indicators {
+        fn set_leds(&mut self, leds: LedState) -> Result<(), Infallible> {
+            self.0 = leds;
+            Ok(())
+        }
+    }
+
+    fn send(ps2: &mut Ps2Keyboard<Indicators>, bytes: &[u8]) -> (Vec<u8>, Option<Ps2Command>) {
+        let mut responses = Vec::new();
+        let mut command = None;
+
+        for byte in bytes {
+            command = ps2.command(*byte, |b| responses.push(b)).unwrap();
+        }
+
+        (responses, command)
+    }
+
+    #[test]
+    fn set_leds() {
+        let mut ps2 = Ps2Keyboard::new(Indicators::default());
+
+        assert_eq!(send(&mut ps2, &[0xed]), (vec![ACK], None));
+        let (responses, command) = send(&mut ps2, &[0x05]);
+        let leds = LedState::from_bits(LedState::SCROLL_LOCK | LedState::CAPS_LOCK);
+        assert_eq!(responses, [ACK]);
+        assert_eq!(command, Some(Ps2Command::SetLeds(leds)));
+        assert_eq!(ps2.leds_mut().0, leds);
+
+        // Invalid arguments are rejected until a valid one arrives.
+        assert_eq!(send(&mut ps2, &[0xed, 0x80]), (vec![ACK, RESEND], None));
+        assert_eq!(
+            send(&mut ps2, &[0xfe]),
+            (vec![ACK], Some(Ps2Command::Resend))
+        );
+        assert_eq!(
+            send(&mut ps2, &[0x02]).1,
+            Some(Ps2Command::SetLeds(LedState::from_bits(LedState::NUM_LOCK)))
+        );
+    }
+
+    #[test]
+    fn scan_code_set_and_typematic() {
+        let mut ps2 = Ps2Keyboard::new(Indicators::default());
+
+        assert_eq!(send(&mut ps2, &[0xf0, 0x00]).0, [ACK, ACK, 2]);
+        assert_eq!(
+            send(&mut ps2, &[0xf0, 0x01]),
+            (vec![ACK, ACK], Some(Ps2Command::ScanCodeSet(Some(1))))
+        );
+        assert_eq!(ps2.scan_code_set(), 1);
+
+        assert_eq!(ps2.typematic().delay(), 500);
+        assert_eq!(ps2.typematic().period(), 91);
+        send(&mut ps2, &[0xf3, 0x60]);
+        assert_eq!(ps2.typematic().delay(), 1000);
+        assert_eq!(ps2.typematic().period(), 33);
+
+        assert_eq!(send(&mut ps2, &[0xf2]).0, [ACK, 0xab, 0x83]);
+        assert_eq!(
+            send(&mut ps2, &[0xee]),
+            (vec![ECHO], Some(Ps2Command::Echo))
+        );
+        assert_eq!(send(&mut ps2, &[0x42]), (vec![RESEND], None));
+    }
+
+    #[test]
+    fn enable_disable_and_reset() {
+        let mut ps2 = Ps2Keyboard::new(Indicators::default());
+        send(&mut ps2, &[0xed, 0x07, 0xf0, 0x03]);
+
+        assert_eq!(send(&mut ps2, &[0xf5]).1, Some(Ps2Command::Disable));
+        assert!(!ps2.scanning());
+        assert_eq!(ps2.scan_code_set(), 2);
+        assert_eq!(send(&mut ps2, &[0xf4]).1, Some(Ps2Command::Enable));
+        assert!(ps2.scanning());
+
+        assert_eq!(
+            send(&mut ps2, &[0xff]),
+            (vec![ACK, BAT_PASSED], Some(Ps2Command::Reset))
+        );
+        assert_eq!(ps2.leds_mut().0, LedState::default());
+        assert_eq!(send(&mut ps2, &[0xfe]).0, [BAT_PASSED]);
+    }
+}
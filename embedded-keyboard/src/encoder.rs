@@ -0,0 +1,192 @@
+//! Rotary encoders mapped onto the keymap.
+//!
+//! Each direction of an encoder is given a coordinate of its own in the
+//! keymap, outside of the key matrix, e.g. on an extra row. Turning the
+//! encoder taps the key at that coordinate, so that what a step does is
+//! set per layer like any other key, as a keycode, a consumer usage such
+//! as volume up or down, or any other [`Action`](crate::Action).
+//!
+//! [`EncoderKeys`] turns the steps of up to `N` encoders, decoded by a
+//! driver, into those taps: a press on one scan and the release on the
+//! next, so that every step reaches the host as its own report.
+
+use crate::{Coordinate, KeyEvent};
+
+/// Direction of an encoder step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Direction {
+    /// Clockwise, seen from the knob
+    Clockwise,
+    /// Counter-clockwise, seen from the knob
+    CounterClockwise,
+}
+
+/// Keymap coordinates of the two directions of an encoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EncoderCoordinates {
+    /// Coordinate tapped by clockwise steps
+    pub clockwise: Coordinate,
+    /// Coordinate tapped by counter-clockwise steps
+    pub counter_clockwise: Coordinate,
+}
+
+impl EncoderCoordinates {
+    /// Create the coordinates of an encoder.
+    pub const fn new(clockwise: Coordinate, counter_clockwise: Coordinate) -> Self {
+        Self {
+            clockwise,
+            counter_clockwise,
+        }
+    }
+
+    /// Coordinate tapped by steps in `direction`.
+    pub const fn get(&self, direction: Direction) -> Coordinate {
+        match direction {
+            Direction::Clockwise => self.clockwise,
+            Direction::CounterClockwise => self.counter_clockwise,
+        }
+    }
+}
+
+/// Taps keymap coordinates for the steps of `N` encoders.
+///
+/// Steps taken faster than one per two scans are counted and tapped in
+/// turn, up to [`EncoderKeys::MAX_PENDING`] per encoder and direction;
+/// steps beyond that are dropped. A step in the other direction cancels a
+/// pending one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EncoderKeys<const N: usize> {
+    coordinates: [EncoderCoordinates; N],
+    pending: [i8; N],
+    pressed: Option<Coordinate>,
+    next: usize,
+    report: [KeyEvent; 2],
+}
+
+impl<const N: usize> EncoderKeys<N> {
+    /// Most steps counted per encoder and direction.
+    pub const MAX_PENDING: i8 = 16;
+
+    /// Create taps for encoders with the given coordinates.
+    pub const fn new(coordinates: [EncoderCoordinates; N]) -> Self {
+        Self {
+            coordinates,
+            pending: [0; N],
+            pressed: None,
+            next: 0,
+            report: [KeyEvent::NoEvent; 2],
+        }
+    }
+
+    /// Record a step of the encoder at `index`. Unknown encoders are
+    /// ignored.
+    pub fn step(&mut self, index: usize, direction: Direction) {
+        let Some(pending) = self.pending.get_mut(index) else {
+            return;
+        };
+
+        *pending = match direction {
+            Direction::Clockwise => pending.saturating_add(1).min(Self::MAX_PENDING),
+            Direction::CounterClockwise => pending.saturating_sub(1).max(-Self::MAX_PENDING),
+        };
+    }
+
+    /// Whether steps are waiting to be tapped, or a tap to be released.
+    pub fn is_busy(&self) -> bool {
+        self.pressed.is_some() || self.pending.iter().any(|pending| *pending != 0)
+    }
+
+    /// Events of this scan: the release of the coordinate pressed on the
+    /// previous scan, if any, and otherwise the press of the next step.
+    /// Encoders take turns so that one turned quickly does not starve the
+    /// others.
+    pub fn events(&mut self) -> &[KeyEvent] {
+        if let Some(coordinate) = self.pressed.take() {
+            self.report[0] = KeyEvent::KeyUp(coordinate);
+            return &self.report[..1];
+        }
+
+        let ready = (0..N)
+            .map(|offset| (self.next + offset) % N)
+            .find(|index| self.pending[*index] != 0);
+        let Some(index) = ready else {
+            return &[];
+        };
+
+        let pending = &mut self.pending[index];
+        let direction = if *pending > 0 {
+            *pending -= 1;
+            Direction::Clockwise
+        } else {
+            *pending += 1;
+            Direction::CounterClockwise
+        };
+
+        let coordinate = self.coordinates[index].get(direction);
+        self.next = (index + 1) % N;
+        self.pressed = Some(coordinate);
+        self.report[0] = KeyEvent::KeyDown(coordinate);
+
+        &self.report[..1]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VOLUME: EncoderCoordinates =
+        EncoderCoordinates::new(Coordinate::new(4, 0), Coordinate::new(4, 1));
+    const SCROLL: EncoderCoordinates =
+        EncoderCoordinates::new(Coordinate::new(4, 2), Coordinate::new(4, 3));
+
+    #[test]
+    fn taps() {
+        let mut keys = EncoderKeys::new([VOLUME, SCROLL]);
+        assert_eq!(keys.events(), []);
+
+        keys.step(0, Direction::Clockwise);
+        keys.step(0, Direction::Clockwise);
+        keys.step(1, Direction::CounterClockwise);
+        keys.step(2, Direction::Clockwise);
+        assert!(keys.is_busy());
+
+        let taps = [
+            KeyEvent::KeyDown(VOLUME.clockwise),
+            KeyEvent::KeyUp(VOLUME.clockwise),
+            KeyEvent::KeyDown(SCROLL.counter_clockwise),
+            KeyEvent::KeyUp(SCROLL.counter_clockwise),
+            KeyEvent::KeyDown(VOLUME.clockwise),
+            KeyEvent::KeyUp(VOLUME.clockwise),
+        ];
+        for tap in taps {
+            assert_eq!(keys.events(), [tap]);
+        }
+
+        assert!(!keys.is_busy());
+        assert_eq!(keys.events(), []);
+    }
+
+    #[test]
+    fn reversal_cancels() {
+        let mut keys = EncoderKeys::new([VOLUME]);
+
+        for _ in 0..20 {
+            keys.step(0, Direction::CounterClockwise);
+        }
+        keys.step(0, Direction::Clockwise);
+
+        let mut taps = 0;
+        while keys.is_busy() {
+            if keys.events() == [KeyEvent::KeyDown(VOLUME.counter_clockwise)] {
+                taps += 1;
+            }
+        }
+        assert_eq!(taps, EncoderKeys::<1>::MAX_PENDING - 1);
+    }
+}
@@ -0,0 +1,1262 @@
+//! Keymap engine.
+//!
+//! The [`Engine`] turns the [`KeyEvent`]s produced by a [`Keyboard`]
+//! into the set of keycodes currently pressed, resolving every key
+//! through the active layers of its [`Keymap`].
+//!
+//! [`Keyboard`]: crate::Keyboard
+
+use crate::host::Keystroke;
+use crate::{Action, Coordinate, Hold, HoldTap, KeyCode, KeyEvent, Keymap, Usage};
+
+/// Layers active in an [`Engine`].
+///
+/// The default layer is always active. On top of it, layers can be held
+/// active by momentary keys or toggled on, either from the keymap or by
+/// the application, or follow from a [`TriLayer`] rule. Keys resolve on the
+/// highest active layer.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LayerState {
+    default: u8,
+    toggled: u32,
+    momentary: u32,
+    tri: u32,
+}
+
+impl LayerState {
+    /// Maximum number of layers that can be tracked.
+    pub const MAX_LAYERS: usize = 32;
+
+    /// Default layer.
+    pub const fn default_layer(&self) -> u8 {
+        self.default
+    }
+
+    /// Bitmap of all active layers, including the default layer.
+    pub const fn active(&self) -> u32 {
+        self.toggled | self.momentary | self.tri | Self::bit(self.default)
+    }
+
+    /// Whether `layer` is active.
+    pub const fn is_active(&self, layer: u8) -> bool {
+        self.active() & Self::bit(layer) != 0
+    }
+
+    /// Bitmap of the layers toggled on, from the keymap or by the
+    /// application.
+    pub const fn toggled(&self) -> u32 {
+        self.toggled
+    }
+
+    /// Highest active layer.
+    pub const fn highest(&self) -> u8 {
+        // active() always has at least the default layer set.
+        (u32::BITS - 1 - self.active().leading_zeros()) as u8
+    }
+
+    /// Iterate over the active layers, from the highest down to the lowest.
+    pub fn iter(&self) -> impl Iterator<Item = u8> {
+        let active = self.active();
+
+        (0..Self::MAX_LAYERS as u8)
+            .rev()
+            .filter(move |layer| active & Self::bit(*layer) != 0)
+    }
+
+    const fn bit(layer: u8) -> u32 {
+        if (layer as usize) < Self::MAX_LAYERS {
+            1 << layer
+        } else {
+            0
+        }
+    }
+}
+
+/// Tri-layer rule.
+///
+/// The classic "Lower", "Raise" and "Adjust" arrangement: while both the
+/// `lower` and the `raise` layer are active, the `adjust` layer is active
+/// as well, no matter how the two were activated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TriLayer {
+    /// First of the two layers
+    pub lower: u8,
+    /// Second of the two layers
+    pub raise: u8,
+    /// Layer active while both others are
+    pub adjust: u8,
+}
+
+impl TriLayer {
+    /// Create a tri-layer rule.
+    pub const fn new(lower: u8, raise: u8, adjust: u8) -> Self {
+        Self {
+            lower,
+            raise,
+            adjust,
+        }
+    }
+}
+
+/// Receives layer state changes from an [`Engine`].
+///
+/// This lets firmware drive layer indicator LEDs or displays as the layer
+/// state changes, instead of polling it. Closures taking the previous and
+/// the new state implement this trait.
+pub trait LayerObserver {
+    /// Called after the layer state changed from `previous` to `current`.
+    fn layers_changed(&mut self, previous: LayerState, current: LayerState);
+}
+
+impl LayerObserver for () {
+    #[inline]
+    fn layers_changed(&mut self, _previous: LayerState, _current: LayerState) {}
+}
+
+impl<F: FnMut(LayerState, LayerState)> LayerObserver for F {
+    #[inline]
+    fn layers_changed(&mut self, previous: LayerState, current: LayerState) {
+        self(previous, current);
+    }
+}
+
+/// Axis the keyboard is mirrored across while swap-hands is active.
+///
+/// For a board whose halves sit side by side in the matrix this is
+/// [`SwapAxis::Columns`]; split keyboards which stack the matrix of one
+/// half below the other need [`SwapAxis::Rows`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SwapAxis {
+    /// Swap the first column with the last one
+    #[default]
+    Columns,
+    /// Swap the first row with the last one
+    Rows,
+}
+
+impl SwapAxis {
+    /// Mirror `coordinate` across this axis of a `rows` by `cols` matrix.
+    pub const fn mirror(
+        self,
+        coordinate: Coordinate,
+        rows: usize,
+        cols: usize,
+    ) -> Option<Coordinate> {
+        match self {
+            Self::Columns => coordinate.mirror_cols(cols),
+            Self::Rows => coordinate.mirror_rows(rows),
+        }
+    }
+}
+
+/// Default tapping term in milliseconds.
+pub const DEFAULT_TAPPING_TERM: u16 = 200;
+
+/// How an undecided hold-tap key reacts to other keys.
+///
+/// Whatever the policy, a hold-tap key released within the tapping term
+/// is tapped, and one held past it is held. The policies differ in when
+/// other keys pressed in the meantime decide it as held early.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum HoldTapPolicy {
+    /// Only the tapping term decides
+    #[default]
+    TapPreferred,
+    /// Held as soon as another key is pressed and released while it is
+    /// down
+    PermissiveHold,
+    /// Held as soon as another key is pressed while it is down
+    HoldOnOtherKeyPress,
+}
+
+/// Number of events that can be held back while a hold-tap key is
+/// undecided.
+const QUEUE_LEN: usize = 16;
+
+/// Number of keys that can be tapped between two ticks: a release per
+/// event held back, and the taps of the hold-tap keys deciding them.
+const TAPS_LEN: usize = 2 * QUEUE_LEN;
+
+/// Hold-tap key waiting for a decision.
+#[derive(Debug, Clone, Copy)]
+struct Pending {
+    coordinate: Coordinate,
+    hold_tap: HoldTap,
+    since: u32,
+}
+
+/// Keymap engine.
+///
+/// The action of a key is resolved when the key is pressed, on the highest
+/// active layer where it is not [`Action::Transparent`]. It is remembered
+/// until the key is released, so that layer changes in between do not
+/// change what the key does, even when the layer the key was resolved on
+/// is no longer active by the time it is released.
+///
+/// While swap-hands is active, pressed coordinates are mirrored across the
+/// configured [`SwapAxis`] before they are looked up in the keymap, so
+/// that one hand can reach the keys of the other.
+///
+/// Time is measured in milliseconds by a free-running, wrapping `u32`
+/// timestamp supplied by the application through [`Engine::tick`]. Events
+/// are considered to happen at the time of the last tick.
+pub struct Engine<const LAYERS: usize, const ROWS: usize, const COLS: usize, O = ()> {
+    keymap: Keymap<LAYERS, ROWS, COLS>,
+    layers: LayerState,
+    held: [[Option<Action>; COLS]; ROWS],
+    observer: O,
+    now: u32,
+    tapping_term: u16,
+    pending: Option<Pending>,
+    queue: [(KeyEvent, u32); QUEUE_LEN],
+    queued: usize,
+    taps: [Action; TAPS_LEN],
+    tapped: usize,
+    swap_axis: SwapAxis,
+    swap_toggled: bool,
+    tri_layer: Option<TriLayer>,
+    policy: HoldTapPolicy,
+    retro_tapping: bool,
+    retro: Option<(Coordinate, KeyCode)>,
+}
+
+impl<const LAYERS: usize, const ROWS: usize, const COLS: usize> Engine<LAYERS, ROWS, COLS> {
+    /// Create an engine for `keymap`.
+    pub fn new(keymap: Keymap<LAYERS, ROWS, COLS>) -> Self {
+        Self::with_observer(keymap, ())
+    }
+}
+
+impl<const LAYERS: usize, const ROWS: usize, const COLS: usize, O: LayerObserver>
+    Engine<LAYERS, ROWS, COLS, O>
+{
+    /// Create an engine for `keymap` notifying `observer` of layer state
+    /// changes.
+    pub fn with_observer(keymap: Keymap<LAYERS, ROWS, COLS>, observer: O) -> Self {
+        const {
+            assert!(LAYERS <= LayerState::MAX_LAYERS);
+        }
+
+        Self {
+            keymap,
+            layers: LayerState::default(),
+            held: [[None; COLS]; ROWS],
+            observer,
+            now: 0,
+            tapping_term: DEFAULT_TAPPING_TERM,
+            pending: None,
+            queue: [(KeyEvent::NoEvent, 0); QUEUE_LEN],
+            queued: 0,
+            taps: [Action::NoOp; TAPS_LEN],
+            tapped: 0,
+            swap_axis: SwapAxis::Columns,
+            swap_toggled: false,
+            tri_layer: None,
+            policy: HoldTapPolicy::TapPreferred,
+            retro_tapping: false,
+            retro: None,
+        }
+    }
+
+    /// Destroys this instance and returns the keymap and observer back to
+    /// the caller.
+    pub fn destroy(self) -> (Keymap<LAYERS, ROWS, COLS>, O) {
+        (self.keymap, self.observer)
+    }
+
+    /// Keymap of this engine.
+    pub const fn keymap(&self) -> &Keymap<LAYERS, ROWS, COLS> {
+        &self.keymap
+    }
+
+    /// Mutable keymap of this engine, for remapping keys at runtime.
+    ///
+    /// Keys already held keep the action they were pressed with.
+    pub fn keymap_mut(&mut self) -> &mut Keymap<LAYERS, ROWS, COLS> {
+        &mut self.keymap
+    }
+
+    /// Current layer state.
+    pub const fn layers(&self) -> LayerState {
+        self.layers
+    }
+
+    /// Observer of this engine.
+    pub fn observer_mut(&mut self) -> &mut O {
+        &mut self.observer
+    }
+
+    /// Time, in milliseconds, a hold-tap key has to be held down before it
+    /// counts as held.
+    pub const fn tapping_term(&self) -> u16 {
+        self.tapping_term
+    }
+
+    /// Change the tapping term.
+    pub fn set_tapping_term(&mut self, milliseconds: u16) {
+        self.tapping_term = milliseconds;
+    }
+
+    /// Policy deciding hold-tap keys.
+    pub const fn hold_tap_policy(&self) -> HoldTapPolicy {
+        self.policy
+    }
+
+    /// Change the policy deciding hold-tap keys.
+    pub fn set_hold_tap_policy(&mut self, policy: HoldTapPolicy) {
+        self.policy = policy;
+    }
+
+    /// Whether retro tapping is enabled.
+    pub const fn retro_tapping(&self) -> bool {
+        self.retro_tapping
+    }
+
+    /// Enable or disable retro tapping: a hold-tap key held past the
+    /// tapping term, then released without any other key pressed, is
+    /// tapped on release after all.
+    pub fn set_retro_tapping(&mut self, enabled: bool) {
+        self.retro_tapping = enabled;
+    }
+
+    /// Change the default layer. Layers outside the keymap are ignored.
+    pub fn set_default_layer(&mut self, layer: u8) {
+        if usize::from(layer) < LAYERS {
+            self.update_layers(|state| state.default = layer);
+        }
+    }
+
+    /// Turn `layer` on until it is turned off again.
+    pub fn activate_layer(&mut self, layer: u8) {
+        self.update_layers(|state| state.toggled |= LayerState::bit(layer));
+    }
+
+    /// Turn `layer` off. Layers held by momentary keys stay active until
+    /// those are released.
+    pub fn deactivate_layer(&mut self, layer: u8) {
+        self.update_layers(|state| state.toggled &= !LayerState::bit(layer));
+    }
+
+    /// Toggle `layer` on or off.
+    pub fn toggle_layer(&mut self, layer: u8) {
+        self.update_layers(|state| state.toggled ^= LayerState::bit(layer));
+    }
+
+    /// Restore the default layer and the toggled layers, e.g. from a
+    /// [`Handoff`](crate::handoff::Handoff). Layers held by keys are not
+    /// affected.
+    pub fn restore_layers(&mut self, default: u8, toggled: u32) {
+        self.update_layers(|state| {
+            if usize::from(default) < LAYERS {
+                state.default = default;
+            }
+            state.toggled = toggled;
+        });
+    }
+
+    /// Tri-layer rule of this engine, if any.
+    pub const fn tri_layer(&self) -> Option<TriLayer> {
+        self.tri_layer
+    }
+
+    /// Change the tri-layer rule, or remove it with `None`.
+    pub fn set_tri_layer(&mut self, tri_layer: Option<TriLayer>) {
+        self.tri_layer = tri_layer;
+        self.update_layers(|_| {});
+    }
+
+    /// Axis the keyboard is mirrored across while swap-hands is active.
+    pub const fn swap_axis(&self) -> SwapAxis {
+        self.swap_axis
+    }
+
+    /// Change the swap-hands axis.
+    pub fn set_swap_axis(&mut self, axis: SwapAxis) {
+        self.swap_axis = axis;
+    }
+
+    /// Whether swap-hands is active, either toggled on or held by a key.
+    pub fn swap_hands(&self) -> bool {
+        self.swap_toggled
+            || self
+                .held
+                .iter()
+                .flatten()
+                .any(|action| *action == Some(Action::SwapHands))
+    }
+
+    /// Turn swap-hands on or off. Keys holding swap-hands keep it active
+    /// until they are released.
+    pub fn set_swap_hands(&mut self, on: bool) {
+        self.swap_toggled = on;
+    }
+
+    /// Advance time to `now`, in milliseconds.
+    ///
+    /// This releases keys that were tapped since the previous tick and
+    /// resolves hold-tap keys whose tapping term has expired. It should be
+    /// called once per scan, before feeding the scan's events, or at the
+    /// latest by [`Engine::next_deadline`].
+    pub fn tick(&mut self, now: u32) {
+        self.now = now;
+        self.tapped = 0;
+
+        while let Some(pending) = self.pending {
+            if now.wrapping_sub(pending.since) < u32::from(self.tapping_term) {
+                break;
+            }
+
+            self.decide(true);
+        }
+    }
+
+    /// Time at which the engine next needs to be ticked, if anything is
+    /// waiting on time to pass.
+    ///
+    /// Async executors can sleep until then instead of ticking the engine
+    /// at a fixed rate; new key events need to be fed regardless.
+    pub fn next_deadline(&self) -> Option<u32> {
+        let deadlines = [
+            // Taps are released on the next tick.
+            (self.tapped > 0).then(|| self.now.wrapping_add(1)),
+            self.pending
+                .map(|p| p.since.wrapping_add(u32::from(self.tapping_term))),
+        ];
+
+        deadlines
+            .into_iter()
+            .flatten()
+            .min_by_key(|deadline| deadline.wrapping_sub(self.now))
+    }
+
+    /// Process a single key event.
+    pub fn event(&mut self, event: KeyEvent) {
+        // Padding and repeated presses of a key already down change
+        // nothing, and must not take room in the queue or interrupt a
+        // hold-tap key.
+        match event {
+            KeyEvent::NoEvent => return,
+            KeyEvent::KeyDown(coordinate) if self.is_down(coordinate) => return,
+            KeyEvent::KeyDown(_) | KeyEvent::KeyUp(_) => {}
+        }
+
+        if self.pending.is_none() && self.queued == 0 {
+            self.process(event, self.now);
+            return;
+        }
+
+        if let (Some(pending), KeyEvent::KeyUp(coordinate)) = (self.pending, event) {
+            if pending.coordinate == coordinate {
+                self.decide(false);
+                return;
+            }
+        }
+
+        if self.queued == QUEUE_LEN {
+            // Out of room to hold events back, decide now.
+            self.decide(true);
+            self.event(event);
+            return;
+        }
+
+        self.queue[self.queued] = (event, self.now);
+        self.queued += 1;
+
+        if self.interrupted() {
+            self.decide(true);
+        }
+    }
+
+    /// Process every event of a scan.
+    pub fn events(&mut self, events: &[KeyEvent]) {
+        for event in events {
+            self.event(*event);
+        }
+    }
+
+    /// Iterate over the keycodes currently pressed. Keys mapped to
+    /// [`Action::Chord`] press their modifiers along with their keycode.
+    pub fn keycodes(&self) -> impl Iterator<Item = KeyCode> + '_ {
+        self.pressed()
+            .filter_map(|action| match action {
+                Action::Key(code) => Some(Keystroke::plain(*code)),
+                Action::Chord(keystroke) => Some(*keystroke),
+                _ => None,
+            })
+            .flat_map(|keystroke| keystroke.keycodes())
+            .filter(|code| *code != KeyCode::NoEvent)
+    }
+
+    /// Iterate over the usages currently pressed: the keycodes of
+    /// [`Engine::keycodes`] along with the raw [`Usage`]s of keys mapped to
+    /// [`Action::Usage`].
+    pub fn usages(&self) -> impl Iterator<Item = Usage> + '_ {
+        self.pressed()
+            .filter_map(|action| match action {
+                Action::Usage(usage) => Some(*usage),
+                _ => None,
+            })
+            .chain(self.keycodes().map(Usage::from))
+    }
+
+    /// Iterate over the actions of the keys currently pressed, e.g. for
+    /// [`Backlight::update`](crate::backlight::Backlight::update).
+    pub fn actions(&self) -> impl Iterator<Item = Action> + '_ {
+        self.held.iter().flatten().filter_map(|action| *action)
+    }
+
+    /// Actions of the keys held, then of the keys tapped since the last
+    /// tick.
+    fn pressed(&self) -> impl Iterator<Item = &Action> + '_ {
+        self.held
+            .iter()
+            .flatten()
+            .flatten()
+            .chain(&self.taps[..self.tapped])
+    }
+
+    /// Action mapped to `coordinate` on the highest active layer, falling
+    /// through transparent keys to the layers below.
+    fn resolve(&self, coordinate: Coordinate) -> Option<Action> {
+        let mut layers = self.layers.iter();
+
+        loop {
+            let Some(layer) = layers.next() else {
+                // Transparent all the way down.
+                return Some(Action::NoOp);
+            };
+
+            match self.keymap.action(layer.into(), coordinate)? {
+                Action::Transparent => continue,
+                action => return Some(action),
+            }
+        }
+    }
+
+    fn process(&mut self, event: KeyEvent, time: u32) {
+        match event {
+            KeyEvent::KeyDown(coordinate) => self.press(coordinate, time),
+            KeyEvent::KeyUp(coordinate) => self.release(coordinate),
+            KeyEvent::NoEvent => {}
+        }
+    }
+
+    fn press(&mut self, coordinate: Coordinate, time: u32) {
+        if self.held_mut(coordinate).is_some_and(|slot| slot.is_some()) {
+            return;
+        }
+
+        // Any other key press rules out retro tapping.
+        self.retro = None;
+
+        // Held actions stay at the physical coordinate so that the release
+        // finds them whatever happened to swap-hands in between.
+        let lookup = if self.swap_hands() {
+            self.swap_axis.mirror(coordinate, ROWS, COLS)
+        } else {
+            Some(coordinate)
+        };
+        let Some(action) = lookup.and_then(|c| self.resolve(c)) else {
+            return;
+        };
+
+        if let Some(slot) = self.held_mut(coordinate) {
+            *slot = Some(action);
+        }
+        trace!("engine: press {} as {}", coordinate, action);
+
+        match action {
+            Action::MomentaryLayer(_) => self.update_momentary(),
+            Action::ToggleLayer(layer) => self.toggle_layer(layer),
+            Action::HoldTap(hold_tap) => {
+                self.pending = Some(Pending {
+                    coordinate,
+                    hold_tap,
+                    since: time,
+                });
+            }
+            Action::ToggleSwapHands => self.swap_toggled = !self.swap_toggled,
+            Action::Key(_)
+            | Action::Usage(_)
+            | Action::Chord(_)
+            | Action::NoOp
+            | Action::Transparent
+            | Action::SwapHands
+            | Action::Backlight(_)
+            | Action::Rgb(_)
+            | Action::Animation(_) => {}
+        }
+    }
+
+    fn release(&mut self, coordinate: Coordinate) {
+        let Some(action) = self.held_mut(coordinate).and_then(Option::take) else {
+            return;
+        };
+        trace!("engine: release {} as {}", coordinate, action);
+
+        if let Action::MomentaryLayer(_) = action {
+            self.update_momentary();
+        }
+
+        if let Some((retro, tap)) = self.retro {
+            if retro == coordinate {
+                self.retro = None;
+                self.tap(Action::Key(tap));
+            }
+        }
+    }
+
+    /// Decide the pending hold-tap key as held or tapped, then replay the
+    /// events held back while it was undecided.
+    fn decide(&mut self, hold: bool) {
+        debug!("engine: hold-tap decided, hold: {}", hold);
+        self.settle(hold);
+        self.replay();
+    }
+
+    /// Decide the pending hold-tap key as held or tapped.
+    fn settle(&mut self, hold: bool) {
+        let Some(pending) = self.pending.take() else {
+            return;
+        };
+
+        let action = if hold {
+            Some(match pending.hold_tap.hold {
+                Hold::Key(code) => Action::Key(code),
+                Hold::Layer(layer) => Action::MomentaryLayer(layer),
+            })
+        } else {
+            self.tap(Action::Key(pending.hold_tap.tap));
+            None
+        };
+
+        if hold && self.retro_tapping {
+            self.retro = Some((pending.coordinate, pending.hold_tap.tap));
+        }
+
+        if let Some(slot) = self.held_mut(pending.coordinate) {
+            *slot = action;
+        }
+
+        if let Some(Action::MomentaryLayer(_)) = action {
+            self.update_momentary();
+        }
+    }
+
+    /// Replay held back events until they run out or another hold-tap key
+    /// is left undecided.
+    fn replay(&mut self) {
+        while self.queued > 0 {
+            if let Some(pending) = self.pending {
+                // A held back release of the pending key means it was
+                // tapped; everything else waits for the decision.
+                let release = KeyEvent::KeyUp(pending.coordinate);
+                match self.queue[..self.queued]
+                    .iter()
+                    .position(|(event, _)| *event == release)
+                {
+                    Some(i) => {
+                        self.dequeue(i);
+                        self.settle(false);
+                        continue;
+                    }
+                    None if self.interrupted() => {
+                        self.settle(true);
+                        continue;
+                    }
+                    None => break,
+                }
+            }
+
+            let (event, time) = self.dequeue(0);
+            if let KeyEvent::KeyUp(coordinate) = event {
+                // The press may have been replayed by this very call, and
+                // never been reported: report the key until the next tick.
+                if let Some(Some(action)) = self.held_mut(coordinate).map(|slot| *slot) {
+                    self.tap(action);
+                }
+            }
+            self.process(event, time);
+        }
+    }
+
+    /// Whether the events held back decide the pending hold-tap key as
+    /// held under the current policy.
+    fn interrupted(&self) -> bool {
+        let queue = &self.queue[..self.queued];
+
+        match self.policy {
+            HoldTapPolicy::TapPreferred => false,
+            HoldTapPolicy::PermissiveHold => queue.iter().enumerate().any(|(i, (event, _))| {
+                matches!(event, KeyEvent::KeyDown(c)
+                    if queue[i..].iter().any(|(e, _)| *e == KeyEvent::KeyUp(*c)))
+            }),
+            HoldTapPolicy::HoldOnOtherKeyPress => queue
+                .iter()
+                .any(|(event, _)| matches!(event, KeyEvent::KeyDown(_))),
+        }
+    }
+
+    /// Keep reporting `action` until the next tick, if it reports
+    /// anything.
+    fn tap(&mut self, action: Action) {
+        if !matches!(action, Action::Key(_) | Action::Chord(_) | Action::Usage(_)) {
+            return;
+        }
+
+        if let Some(tap) = self.taps.get_mut(self.tapped) {
+            *tap = action;
+            self.tapped += 1;
+        }
+    }
+
+    fn dequeue(&mut self, index: usize) -> (KeyEvent, u32) {
+        let entry = self.queue[index];
+        self.queue.copy_within(index + 1..self.queued, index);
+        self.queued -= 1;
+        entry
+    }
+
+    /// Whether the key at `coordinate` is down, as of the last event
+    /// queued for it, or else as held.
+    fn is_down(&self, coordinate: Coordinate) -> bool {
+        let queued = self.queue[..self.queued]
+            .iter()
+            .rev()
+            .find_map(|(event, _)| match event {
+                KeyEvent::KeyDown(c) if *c == coordinate => Some(true),
+                KeyEvent::KeyUp(c) if *c == coordinate => Some(false),
+                _ => None,
+            });
+
+        queued.unwrap_or_else(|| {
+            self.held
+                .get(coordinate.row())
+                .and_then(|keys| keys.get(coordinate.col()))
+                .is_some_and(Option::is_some)
+        })
+    }
+
+    fn held_mut(&mut self, coordinate: Coordinate) -> Option<&mut Option<Action>> {
+        self.held
+            .get_mut(coordinate.row())?
+            .get_mut(coordinate.col())
+    }
+
+    /// Recompute the momentary layers from the keys currently held, so that
+    /// a layer stays active as long as any key holding it is.
+    fn update_momentary(&mut self) {
+        let momentary = self
+            .held
+            .iter()
+            .flatten()
+            .fold(0, |mask, action| match action {
+                Some(Action::MomentaryLayer(layer)) => mask | LayerState::bit(*layer),
+                _ => mask,
+            });
+
+        self.update_layers(|state| state.momentary = momentary);
+    }
+
+    fn update_layers(&mut self, update: impl FnOnce(&mut LayerState)) {
+        let previous = self.layers;
+        update(&mut self.layers);
+
+        // Layers the keymap does not have can never be active.
+        let mask = if LAYERS >= LayerState::MAX_LAYERS {
+            u32::MAX
+        } else {
+            (1 << LAYERS) - 1
+        };
+        self.layers.toggled &= mask;
+        self.layers.momentary &= mask;
+
+        self.layers.tri = 0;
+        if let Some(tri) = self.tri_layer {
+            if self.layers.is_active(tri.lower) && self.layers.is_active(tri.raise) {
+                self.layers.tri = LayerState::bit(tri.adjust) & mask;
+            }
+        }
+
+        if self.layers != previous {
+            debug!("engine: layers {} -> {}", previous, self.layers);
+            self.observer.layers_changed(previous, self.layers);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEYMAP: Keymap<3, 1, 4> = crate::keymap! {
+        { [KA KB (1) {Action::ToggleLayer(2)}] }
+        { [K1 K2 (1) (2)] }
+        { [KF1 KF2 KF3 {Action::ToggleLayer(2)}] }
+    };
+
+    fn down(col: usize) -> KeyEvent {
+        KeyEvent::KeyDown(Coordinate::new(0, col))
+    }
+
+    fn up(col: usize) -> KeyEvent {
+        KeyEvent::KeyUp(Coordinate::new(0, col))
+    }
+
+    #[test]
+    fn keys_resolve_on_highest_layer() {
+        let mut engine = Engine::new(KEYMAP);
+
+        engine.event(down(0));
+        assert!(engine.keycodes().eq([KeyCode::KA]));
+
+        engine.events(&[down(2), down(1)]);
+        assert_eq!(engine.layers().highest(), 1);
+        assert!(engine.keycodes().eq([KeyCode::KA, KeyCode::K2]));
+
+        // Keys keep the action they were pressed with.
+        engine.events(&[up(2), up(0)]);
+        assert_eq!(engine.layers().highest(), 0);
+        assert!(engine.keycodes().eq([KeyCode::K2]));
+
+        engine.event(up(1));
+        assert_eq!(engine.keycodes().count(), 0);
+    }
+
+    #[test]
+    fn toggle_layers_from_keymap() {
+        let mut engine = Engine::new(KEYMAP);
+
+        engine.events(&[down(3), up(3)]);
+        assert!(engine.layers().is_active(2));
+        engine.events(&[down(0), up(0)]);
+        engine.event(down(0));
+        assert!(engine.keycodes().eq([KeyCode::KF1]));
+
+        engine.events(&[up(0), down(3), up(3)]);
+        assert!(!engine.layers().is_active(2));
+    }
+
+    #[test]
+    fn observer_sees_every_change() {
+        let mut changes = Vec::new();
+        let mut engine = Engine::with_observer(KEYMAP, |prev: LayerState, cur: LayerState| {
+            changes.push((prev.active(), cur.active()));
+        });
+
+        engine.event(down(2));
+        engine.event(down(0));
+        engine.event(up(0));
+        engine.event(up(2));
+        engine.set_default_layer(2);
+        engine.set_default_layer(5);
+        engine.activate_layer(1);
+        engine.activate_layer(1);
+        engine.activate_layer(7);
+        engine.deactivate_layer(1);
+
+        assert_eq!(
+            changes,
+            [
+                (0b001, 0b011),
+                (0b011, 0b001),
+                (0b001, 0b100),
+                (0b100, 0b110),
+                (0b110, 0b100)
+            ]
+        );
+    }
+
+    #[test]
+    fn momentary_layer_held_by_two_keys() {
+        let keymap: Keymap<2, 1, 2> = crate::keymap! {
+            { [(1) (1)] }
+            { [(1) (1)] }
+        };
+        let mut engine = Engine::new(keymap);
+
+        engine.events(&[down(0), down(1), up(0)]);
+        assert!(engine.layers().is_active(1));
+        engine.event(up(1));
+        assert!(!engine.layers().is_active(1));
+    }
+
+    const HOLD_TAP: Keymap<2, 1, 4> = crate::keymap! {
+        {
+            [
+                {Action::mod_tap(KeyCode::KpLeftShift, KeyCode::KA)}
+                {Action::layer_tap(1, KeyCode::KB)}
+                KC
+                KD
+            ]
+        }
+        { [K1 K2 K3 K4] }
+    };
+
+    #[test]
+    fn hold_tap_tapped() {
+        let mut engine = Engine::new(HOLD_TAP);
+
+        engine.tick(1000);
+        engine.event(down(0));
+        assert_eq!(engine.keycodes().count(), 0);
+        assert_eq!(engine.next_deadline(), Some(1200));
+
+        engine.tick(1100);
+        engine.event(up(0));
+        assert!(engine.keycodes().eq([KeyCode::KA]));
+        assert_eq!(engine.next_deadline(), Some(1101));
+
+        engine.tick(1101);
+        assert_eq!(engine.keycodes().count(), 0);
+        assert_eq!(engine.next_deadline(), None);
+    }
+
+    #[test]
+    fn hold_tap_held() {
+        let mut engine = Engine::new(HOLD_TAP);
+
+        engine.tick(0);
+        engine.event(down(0));
+        engine.tick(150);
+        engine.event(down(2));
+        assert_eq!(engine.keycodes().count(), 0);
+
+        engine.tick(200);
+        assert!(engine.keycodes().eq([KeyCode::KpLeftShift, KeyCode::KC]));
+
+        engine.events(&[up(2), up(0)]);
+        assert_eq!(engine.keycodes().count(), 0);
+    }
+
+    #[test]
+    fn layer_tap_held_resolves_queued_keys_on_layer() {
+        let mut engine = Engine::new(HOLD_TAP);
+        engine.set_tapping_term(100);
+
+        engine.tick(0);
+        engine.event(down(1));
+        engine.tick(50);
+        engine.event(down(3));
+        engine.tick(100);
+        assert!(engine.layers().is_active(1));
+        assert!(engine.keycodes().eq([KeyCode::K4]));
+
+        engine.events(&[up(1), up(3)]);
+        assert!(!engine.layers().is_active(1));
+    }
+
+    #[test]
+    fn queued_hold_tap_tapped_during_replay() {
+        let mut engine = Engine::new(HOLD_TAP);
+
+        engine.tick(0);
+        engine.event(down(0));
+        engine.tick(10);
+        engine.events(&[down(1), up(1)]);
+        engine.tick(20);
+        engine.event(up(0));
+
+        assert!(engine.keycodes().eq([KeyCode::KA, KeyCode::KB]));
+        assert_eq!(engine.next_deadline(), Some(21));
+    }
+
+    /// Keycodes reported after each of `steps`, every step ticking the
+    /// engine at its time, then feeding its events.
+    fn reports(engine: &mut Engine<2, 1, 4>, steps: &[(u32, &[KeyEvent])]) -> Vec<Vec<KeyCode>> {
+        steps
+            .iter()
+            .map(|(now, events)| {
+                engine.tick(*now);
+                engine.events(events);
+                engine.keycodes().collect()
+            })
+            .collect()
+    }
+
+    /// Another key tapped while a hold-tap key is undecided.
+    const ROLL: [(u32, &[KeyEvent]); 5] = [
+        (0, &[KeyEvent::KeyDown(Coordinate::new(0, 0))]),
+        (10, &[KeyEvent::KeyDown(Coordinate::new(0, 2))]),
+        (20, &[KeyEvent::KeyUp(Coordinate::new(0, 2))]),
+        (30, &[KeyEvent::KeyUp(Coordinate::new(0, 0))]),
+        (31, &[]),
+    ];
+
+    #[test]
+    fn replayed_taps_reported() {
+        let mut engine = Engine::new(HOLD_TAP);
+
+        assert_eq!(
+            reports(&mut engine, &ROLL),
+            [
+                vec![],
+                vec![],
+                vec![],
+                vec![KeyCode::KA, KeyCode::KC],
+                vec![]
+            ]
+        );
+        assert_eq!(engine.next_deadline(), None);
+    }
+
+    #[test]
+    fn deadline_wraps_around() {
+        let mut engine = Engine::new(HOLD_TAP);
+
+        engine.tick(u32::MAX - 50);
+        engine.event(down(0));
+        assert_eq!(engine.next_deadline(), Some(149));
+
+        engine.tick(148);
+        assert_eq!(engine.keycodes().count(), 0);
+        engine.tick(149);
+        assert!(engine.keycodes().eq([KeyCode::KpLeftShift]));
+    }
+
+    const TRANSPARENT: Keymap<3, 1, 3> = crate::keymap! {
+        { [(1) KA KB] }
+        { [_______ _______ XXXXXXX] }
+        { [_______ K1 _______] }
+    };
+
+    #[test]
+    fn transparent_falls_through_active_layers() {
+        let mut engine = Engine::new(TRANSPARENT);
+
+        engine.events(&[down(0), down(1), down(2)]);
+        assert!(engine.layers().is_active(1));
+        assert!(engine.keycodes().eq([KeyCode::KA]));
+        engine.events(&[up(1), up(2)]);
+
+        engine.activate_layer(2);
+        engine.events(&[down(1), down(2)]);
+        assert!(engine.keycodes().eq([KeyCode::K1]));
+        engine.events(&[up(1), up(2)]);
+
+        // Layer 2 is transparent, layer 1 blocks with a no-op.
+        engine.event(down(2));
+        assert_eq!(engine.keycodes().count(), 0);
+    }
+
+    #[test]
+    fn release_after_layer_deactivated() {
+        let mut engine = Engine::new(TRANSPARENT);
+        engine.activate_layer(2);
+
+        // Pressed on layer 2, released once it is gone.
+        engine.events(&[down(1), down(0)]);
+        engine.deactivate_layer(2);
+        engine.event(up(0));
+        assert!(engine.keycodes().eq([KeyCode::K1]));
+        engine.event(up(1));
+        assert_eq!(engine.keycodes().count(), 0);
+
+        // Fell through to layer 0 while layer 1 was held, released after.
+        engine.events(&[down(0), down(1), up(0)]);
+        assert!(engine.keycodes().eq([KeyCode::KA]));
+        engine.event(up(1));
+        assert_eq!(engine.keycodes().count(), 0);
+    }
+
+    #[test]
+    fn swap_hands_mirrors_lookup() {
+        let keymap: Keymap<1, 2, 4> = crate::keymap! {
+            { [KA KB KC KD] [{Action::SwapHands} {Action::ToggleSwapHands} KE KF] }
+        };
+        let mut engine = Engine::new(keymap);
+        let hold = Coordinate::new(1, 0);
+
+        engine.events(&[KeyEvent::KeyDown(hold), down(0)]);
+        assert!(engine.swap_hands());
+        assert!(engine.keycodes().eq([KeyCode::KD]));
+
+        // Released at the physical coordinate after swap-hands ends.
+        engine.events(&[KeyEvent::KeyUp(hold), up(0)]);
+        assert!(!engine.swap_hands());
+        assert_eq!(engine.keycodes().count(), 0);
+
+        let toggle = Coordinate::new(1, 1);
+        engine.events(&[KeyEvent::KeyDown(toggle), KeyEvent::KeyUp(toggle)]);
+        assert!(engine.swap_hands());
+        engine.set_swap_axis(SwapAxis::Rows);
+        engine.event(down(2));
+        assert!(engine.keycodes().eq([KeyCode::KE]));
+        engine.event(up(2));
+
+        engine.set_swap_hands(false);
+        engine.event(down(2));
+        assert!(engine.keycodes().eq([KeyCode::KC]));
+    }
+
+    #[test]
+    fn tri_layer_follows_lower_and_raise() {
+        let keymap: Keymap<4, 1, 3> = crate::keymap! {
+            { [(1) (2) KA] }
+            { [_______ _______ KB] }
+            { [_______ _______ KC] }
+            { [_______ _______ KD] }
+        };
+        let mut engine = Engine::new(keymap);
+        engine.set_tri_layer(Some(TriLayer::new(1, 2, 3)));
+
+        engine.events(&[down(0), down(1), down(2)]);
+        assert!(engine.layers().is_active(3));
+        assert!(engine.keycodes().eq([KeyCode::KD]));
+
+        engine.events(&[up(2), up(0)]);
+        assert!(!engine.layers().is_active(3));
+
+        // Toggled layers count as well.
+        engine.activate_layer(1);
+        assert!(engine.layers().is_active(3));
+        engine.set_tri_layer(None);
+        assert!(!engine.layers().is_active(3));
+    }
+
+    #[test]
+    fn permissive_hold() {
+        let mut engine = Engine::new(HOLD_TAP);
+        engine.set_hold_tap_policy(HoldTapPolicy::PermissiveHold);
+
+        engine.tick(0);
+        engine.events(&[down(1), down(3)]);
+        assert!(!engine.layers().is_active(1));
+
+        engine.event(up(3));
+        assert!(engine.layers().is_active(1));
+        engine.event(up(1));
+
+        // Rolling over into the other key still taps.
+        engine.tick(1);
+        engine.events(&[down(1), down(3), up(1)]);
+        assert!(engine.keycodes().eq([KeyCode::KD, KeyCode::KB]));
+    }
+
+    #[test]
+    fn permissive_hold_reports_modified_tap() {
+        let mut engine = Engine::new(HOLD_TAP);
+        engine.set_hold_tap_policy(HoldTapPolicy::PermissiveHold);
+
+        assert_eq!(
+            reports(&mut engine, &ROLL),
+            [
+                vec![],
+                vec![],
+                vec![KeyCode::KpLeftShift, KeyCode::KC],
+                vec![],
+                vec![]
+            ]
+        );
+    }
+
+    #[test]
+    fn hold_on_other_key_press() {
+        let mut engine = Engine::new(HOLD_TAP);
+        engine.set_hold_tap_policy(HoldTapPolicy::HoldOnOtherKeyPress);
+
+        engine.tick(0);
+        engine.events(&[down(1), down(3)]);
+        assert!(engine.layers().is_active(1));
+        assert!(engine.keycodes().eq([KeyCode::K4]));
+        assert_eq!(engine.next_deadline(), None);
+    }
+
+    #[test]
+    fn retro_tapping() {
+        let mut engine = Engine::new(HOLD_TAP);
+        engine.set_retro_tapping(true);
+        assert!(engine.retro_tapping());
+
+        engine.tick(0);
+        engine.event(down(0));
+        engine.tick(300);
+        assert!(engine.keycodes().eq([KeyCode::KpLeftShift]));
+        engine.event(up(0));
+        assert!(engine.keycodes().eq([KeyCode::KA]));
+
+        // Not when another key was pressed while held.
+        engine.tick(1000);
+        engine.event(down(0));
+        engine.tick(1300);
+        engine.events(&[down(2), up(2), up(0)]);
+        engine.tick(1301);
+        assert_eq!(engine.keycodes().count(), 0);
+    }
+
+    #[test]
+    fn repeated_events_do_not_interrupt_hold_tap() {
+        let mut engine = Engine::new(HOLD_TAP);
+        engine.set_hold_tap_policy(HoldTapPolicy::HoldOnOtherKeyPress);
+
+        // A key held since before the hold-tap key, repeated on every
+        // scan along with padding, neither fills the queue nor counts as
+        // another key press.
+        engine.tick(0);
+        engine.event(down(2));
+        engine.event(down(0));
+        for now in 1..40 {
+            engine.tick(now);
+            engine.events(&[down(2), KeyEvent::NoEvent, down(0)]);
+        }
+        assert!(engine.keycodes().eq([KeyCode::KC]));
+
+        engine.event(up(0));
+        assert!(engine.keycodes().eq([KeyCode::KC, KeyCode::KA]));
+    }
+
+    #[test]
+    fn repeated_press_keeps_retro_tapping() {
+        let mut engine = Engine::new(HOLD_TAP);
+        engine.set_retro_tapping(true);
+
+        engine.tick(0);
+        engine.event(down(0));
+        engine.tick(300);
+        engine.event(down(0));
+        assert!(engine.keycodes().eq([KeyCode::KpLeftShift]));
+
+        engine.event(up(0));
+        assert!(engine.keycodes().eq([KeyCode::KA]));
+    }
+
+    #[test]
+    fn raw_usages() {
+        let power = Usage::keyboard(0x66);
+        let keymap: Keymap<1, 1, 2> = crate::keymap! {
+            { [KA {Action::Usage(power)}] }
+        };
+        let mut engine = Engine::new(keymap);
+
+        engine.events(&[down(0), down(1)]);
+        assert!(engine.keycodes().eq([KeyCode::KA]));
+        assert!(engine.usages().eq([power, Usage::from(KeyCode::KA)]));
+    }
+
+    #[test]
+    fn chord_restores_held_modifiers() {
+        let keymap: Keymap<1, 1, 2> = crate::keymap! {
+            { [KpLeftShift {Action::chord(0x0a, KeyCode::KS)}] }
+        };
+        let mut engine = Engine::new(keymap);
+
+        engine.events(&[down(1)]);
+        assert!(engine
+            .keycodes()
+            .eq([KeyCode::KpLeftShift, KeyCode::KpLeftGUI, KeyCode::KS]));
+
+        engine.events(&[down(0), up(1)]);
+        assert!(engine.keycodes().eq([KeyCode::KpLeftShift]));
+
+        engine.events(&[up(0)]);
+        assert_eq!(engine.keycodes().count(), 0);
+    }
+}
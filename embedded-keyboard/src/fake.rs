@@ -0,0 +1,152 @@
+//! Fake keyboard for testing integrations against the [`Keyboard`] trait.
+//!
+//! [`FakeKeyboard`] needs neither a matrix nor `std`, so that crates built
+//! on top of this one, such as USB glue or EC firmware, can test how they
+//! drive a [`Keyboard`] and its [`KeyboardLeds`] with a known sequence of
+//! scans, including failing ones.
+
+use crate::hid::LedState;
+use crate::{ErrorKind, ErrorType, KeyEvent, Keyboard, KeyboardLeds};
+
+/// Keyboard returning a programmed sequence of scans.
+///
+/// Every [`Keyboard::scan`] returns the next scan of the sequence, and no
+/// event once it is exhausted. A failure can be injected at any scan, and
+/// the LED state last set is recorded.
+///
+/// ```
+/// use embedded_keyboard::fake::FakeKeyboard;
+/// use embedded_keyboard::{Coordinate, KeyEvent, Keyboard};
+///
+/// const A: Coordinate = Coordinate::new(0, 0);
+/// let mut keyboard = FakeKeyboard::new(&[&[KeyEvent::KeyDown(A)], &[], &[KeyEvent::KeyUp(A)]]);
+///
+/// assert_eq!(keyboard.scan().unwrap(), [KeyEvent::KeyDown(A)]);
+/// assert!(keyboard.activity());
+/// ```
+#[derive(Debug, Clone)]
+pub struct FakeKeyboard<'a> {
+    scans: &'a [&'a [KeyEvent]],
+    next: usize,
+    fail_at: Option<usize>,
+    held: usize,
+    leds: Option<LedState>,
+}
+
+impl<'a> FakeKeyboard<'a> {
+    /// Create a keyboard returning `scans` in turn.
+    pub const fn new(scans: &'a [&'a [KeyEvent]]) -> Self {
+        Self {
+            scans,
+            next: 0,
+            fail_at: None,
+            held: 0,
+            leds: None,
+        }
+    }
+
+    /// Make the scan at `index` in the sequence fail with
+    /// [`ErrorKind::Other`] instead, without consuming it, so that the
+    /// following scan returns it.
+    pub fn fail_at(&mut self, index: usize) {
+        self.fail_at = Some(index);
+    }
+
+    /// Number of scans performed so far.
+    pub const fn scans(&self) -> usize {
+        self.next
+    }
+
+    /// Whether every scan of the sequence was returned.
+    pub const fn is_done(&self) -> bool {
+        self.next >= self.scans.len()
+    }
+
+    /// Restart the sequence from its first scan, with every key released.
+    pub fn reset(&mut self) {
+        self.next = 0;
+        self.held = 0;
+    }
+
+    /// LED state last set, if any.
+    pub const fn leds(&self) -> Option<LedState> {
+        self.leds
+    }
+}
+
+impl ErrorType for FakeKeyboard<'_> {
+    type Error = ErrorKind;
+}
+
+impl Keyboard for FakeKeyboard<'_> {
+    fn scan(&mut self) -> Result<&[KeyEvent], ErrorKind> {
+        if self.fail_at == Some(self.next) {
+            self.fail_at = None;
+            return Err(ErrorKind::Other);
+        }
+
+        let scan = self.scans.get(self.next).copied().unwrap_or_default();
+        self.next = self.next.saturating_add(1);
+
+        for event in scan {
+            match event {
+                KeyEvent::KeyDown(_) => self.held += 1,
+                KeyEvent::KeyUp(_) => self.held = self.held.saturating_sub(1),
+                KeyEvent::NoEvent => {}
+            }
+        }
+
+        Ok(scan)
+    }
+
+    /// Whether more keys were pressed than released so far.
+    fn activity(&self) -> bool {
+        self.held > 0
+    }
+}
+
+impl KeyboardLeds for FakeKeyboard<'_> {
+    fn set_leds(&mut self, leds: LedState) -> Result<(), ErrorKind> {
+        self.leds = Some(leds);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Coordinate;
+
+    const A: KeyEvent = KeyEvent::KeyDown(Coordinate::new(0, 0));
+    const B: KeyEvent = KeyEvent::KeyDown(Coordinate::new(0, 1));
+    const UP: KeyEvent = KeyEvent::KeyUp(Coordinate::new(0, 0));
+
+    #[test]
+    fn programmed_scans() {
+        let mut keyboard = FakeKeyboard::new(&[&[A, B], &[UP, KeyEvent::NoEvent]]);
+        keyboard.fail_at(1);
+
+        assert_eq!(keyboard.scan(), Ok(&[A, B][..]));
+        assert!(keyboard.activity());
+        assert_eq!(keyboard.scan(), Err(ErrorKind::Other));
+        assert_eq!(keyboard.scan(), Ok(&[UP, KeyEvent::NoEvent][..]));
+        assert!(keyboard.activity());
+        assert!(keyboard.is_done());
+        assert_eq!(keyboard.scan(), Ok(&[][..]));
+        assert_eq!(keyboard.scans(), 3);
+
+        keyboard.reset();
+        assert!(!keyboard.activity());
+        assert_eq!(keyboard.scan(), Ok(&[A, B][..]));
+    }
+
+    #[test]
+    fn records_leds() {
+        let mut keyboard = FakeKeyboard::new(&[]);
+        assert_eq!(keyboard.leds(), None);
+
+        let leds = LedState::from_bits(LedState::CAPS_LOCK);
+        keyboard.set_leds(leds).unwrap();
+        assert_eq!(keyboard.leds(), Some(leds));
+    }
+}
@@ -0,0 +1,225 @@
+use crate::{Action, Coordinate, HoldTap, KeyCode, KeyCodeClass, Keymap};
+
+/// Feedback class of a key.
+///
+/// Haptic and audio drivers use the class of a key to pick how strongly
+/// to react to it, so that e.g. destructive keys can feel different from
+/// letters without the driver knowing anything about the layout.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum FeedbackClass {
+    /// No feedback at all
+    Silent = 0,
+    /// Anything not covered by a more specific class
+    #[default]
+    Other,
+    /// Letters and the space bar
+    Alpha,
+    /// Digits, on the number row and the keypad
+    Numeric,
+    /// Modifier keys
+    Modifier,
+    /// Arrows, paging and other cursor movement
+    Navigation,
+    /// Function keys
+    Function,
+    /// Keys which delete or discard, like Backspace and Delete
+    Destructive,
+}
+
+impl FeedbackClass {
+    /// Number of feedback classes.
+    pub const COUNT: usize = 8;
+
+    /// Class a keycode falls into by default.
+    pub fn of(code: KeyCode) -> Self {
+        use KeyCode::*;
+
+        match code {
+            NoEvent => return Self::Silent,
+            KSpaceBar => return Self::Alpha,
+            KTab => return Self::Navigation,
+            KBackspace | KDelete | KpBackspace | KEscape | KCut | KClear | KpClear
+            | KpClearEntry => return Self::Destructive,
+            Kp1 | Kp2 | Kp3 | Kp4 | Kp5 | Kp6 | Kp7 | Kp8 | Kp9 | Kp0 | Kp00 | Kp000 => {
+                return Self::Numeric
+            }
+            _ => {}
+        }
+
+        match code.class() {
+            KeyCodeClass::Letter => Self::Alpha,
+            KeyCodeClass::Digit => Self::Numeric,
+            KeyCodeClass::Modifier | KeyCodeClass::Lock => Self::Modifier,
+            KeyCodeClass::Navigation => Self::Navigation,
+            KeyCodeClass::Function => Self::Function,
+            KeyCodeClass::Status
+            | KeyCodeClass::Control
+            | KeyCodeClass::Punctuation
+            | KeyCodeClass::Keypad
+            | KeyCodeClass::Media
+            | KeyCodeClass::International
+            | KeyCodeClass::System => Self::Other,
+        }
+    }
+
+    /// Class an action falls into by default.
+    pub fn of_action(action: Action) -> Self {
+        match action {
+            Action::NoOp | Action::Transparent => Self::Silent,
+            Action::Key(code) | Action::HoldTap(HoldTap { tap: code, .. }) => Self::of(code),
+            Action::Chord(keystroke) => Self::of(keystroke.code()),
+            Action::Usage(usage) => match usage.keyboard_id().map(KeyCode::try_from) {
+                Some(Ok(code)) => Self::of(code),
+                _ => Self::Other,
+            },
+            Action::MomentaryLayer(_)
+            | Action::ToggleLayer(_)
+            | Action::SwapHands
+            | Action::ToggleSwapHands => Self::Modifier,
+            Action::Backlight(_) | Action::Rgb(_) | Action::Animation(_) => Self::Other,
+        }
+    }
+}
+
+/// Per-coordinate [`FeedbackClass`]es of a keyboard.
+///
+/// Meant to be declared as `const` data next to the keymap, or derived
+/// from it with [`FeedbackMap::from_keymap`] and then adjusted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeedbackMap<const ROWS: usize, const COLS: usize> {
+    classes: [[FeedbackClass; COLS]; ROWS],
+}
+
+impl<const ROWS: usize, const COLS: usize> FeedbackMap<ROWS, COLS> {
+    /// Create a map from per-coordinate classes.
+    pub const fn new(classes: [[FeedbackClass; COLS]; ROWS]) -> Self {
+        Self { classes }
+    }
+
+    /// Create a map assigning the same class to every key.
+    pub const fn uniform(class: FeedbackClass) -> Self {
+        Self::new([[class; COLS]; ROWS])
+    }
+
+    /// Derive a map from the default class of every action on `layer` of
+    /// `keymap`.
+    pub fn from_keymap<const LAYERS: usize>(
+        keymap: &Keymap<LAYERS, ROWS, COLS>,
+        layer: usize,
+    ) -> Self {
+        let mut map = Self::uniform(FeedbackClass::Other);
+
+        for (l, coordinate, action) in keymap.iter() {
+            if l == layer {
+                map.set(coordinate, FeedbackClass::of_action(action));
+            }
+        }
+
+        map
+    }
+
+    /// Class of the key at `coordinate`. Coordinates outside the matrix
+    /// are [`FeedbackClass::Silent`].
+    pub const fn class(&self, coordinate: Coordinate) -> FeedbackClass {
+        if coordinate.within(ROWS, COLS) {
+            self.classes[coordinate.row()][coordinate.col()]
+        } else {
+            FeedbackClass::Silent
+        }
+    }
+
+    /// Change the class of the key at `coordinate`. Coordinates outside the
+    /// matrix are ignored.
+    pub fn set(&mut self, coordinate: Coordinate, class: FeedbackClass) {
+        if let Some(c) = self
+            .classes
+            .get_mut(coordinate.row())
+            .and_then(|row| row.get_mut(coordinate.col()))
+        {
+            *c = class;
+        }
+    }
+}
+
+/// Feedback intensity for each [`FeedbackClass`].
+///
+/// Intensities are on an arbitrary `0..=255` scale which the feedback
+/// driver maps onto its own range, e.g. a PWM duty cycle or a haptic
+/// waveform amplitude.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FeedbackProfile {
+    intensities: [u8; FeedbackClass::COUNT],
+}
+
+impl FeedbackProfile {
+    /// Create a profile with the same intensity for every class except
+    /// [`FeedbackClass::Silent`].
+    pub const fn flat(intensity: u8) -> Self {
+        let mut intensities = [intensity; FeedbackClass::COUNT];
+        intensities[FeedbackClass::Silent as usize] = 0;
+
+        Self { intensities }
+    }
+
+    /// Builder-style setter for the intensity of `class`.
+    #[must_use]
+    pub const fn with(mut self, class: FeedbackClass, intensity: u8) -> Self {
+        self.intensities[class as usize] = intensity;
+        self
+    }
+
+    /// Intensity of `class`.
+    pub const fn intensity(&self, class: FeedbackClass) -> u8 {
+        self.intensities[class as usize]
+    }
+}
+
+impl Default for FeedbackProfile {
+    fn default() -> Self {
+        Self::flat(u8::MAX / 2)
+            .with(FeedbackClass::Modifier, u8::MAX / 4)
+            .with(FeedbackClass::Destructive, u8::MAX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classes_from_keymap() {
+        let keymap: Keymap<2, 1, 4> = crate::keymap! {
+            { [KA KBackspace KpLeftShift (1)] }
+            { [K1 KF1 KUpArrow KPause] }
+        };
+
+        let map = FeedbackMap::from_keymap(&keymap, 0);
+        assert_eq!(map.class(Coordinate::new(0, 0)), FeedbackClass::Alpha);
+        assert_eq!(map.class(Coordinate::new(0, 1)), FeedbackClass::Destructive);
+        assert_eq!(map.class(Coordinate::new(0, 2)), FeedbackClass::Modifier);
+        assert_eq!(map.class(Coordinate::new(0, 3)), FeedbackClass::Modifier);
+        assert_eq!(map.class(Coordinate::new(1, 0)), FeedbackClass::Silent);
+
+        let mut map = FeedbackMap::from_keymap(&keymap, 1);
+        assert_eq!(map.class(Coordinate::new(0, 0)), FeedbackClass::Numeric);
+        assert_eq!(map.class(Coordinate::new(0, 1)), FeedbackClass::Function);
+        assert_eq!(map.class(Coordinate::new(0, 2)), FeedbackClass::Navigation);
+        assert_eq!(map.class(Coordinate::new(0, 3)), FeedbackClass::Other);
+
+        map.set(Coordinate::new(0, 3), FeedbackClass::Destructive);
+        assert_eq!(map.class(Coordinate::new(0, 3)), FeedbackClass::Destructive);
+    }
+
+    #[test]
+    fn profile_intensities() {
+        let profile = FeedbackProfile::flat(10).with(FeedbackClass::Destructive, 200);
+        assert_eq!(profile.intensity(FeedbackClass::Alpha), 10);
+        assert_eq!(profile.intensity(FeedbackClass::Destructive), 200);
+        assert_eq!(profile.intensity(FeedbackClass::Silent), 0);
+    }
+}
@@ -0,0 +1,373 @@
+/// Key coordinates
+///
+/// A `Coordinate` names a key by its electrical position in the matrix:
+/// the row and column lines it sits on. Coordinates can be mapped to and
+/// from linear indices (for flat per-key tables such as LED maps) and
+/// transformed by mirroring and offsetting (for swap-hands and split
+/// keyboards).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Coordinate {
+    row: usize,
+    col: usize,
+}
+
+impl Coordinate {
+    /// Create a new `Coordinate' instance
+    pub const fn new(row: usize, col: usize) -> Self {
+        Self { row, col }
+    }
+
+    /// Row of this coordinate.
+    pub const fn row(&self) -> usize {
+        self.row
+    }
+
+    /// Column of this coordinate.
+    pub const fn col(&self) -> usize {
+        self.col
+    }
+
+    /// Linear, row-major index of this coordinate in a matrix `cols`
+    /// columns wide.
+    pub const fn index(&self, cols: usize) -> usize {
+        self.row * cols + self.col
+    }
+
+    /// Coordinate at the linear, row-major `index` of a matrix `cols`
+    /// columns wide.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cols` is zero.
+    pub const fn from_index(index: usize, cols: usize) -> Self {
+        Self::new(index / cols, index % cols)
+    }
+
+    /// Whether this coordinate lies within a `rows` by `cols` matrix.
+    pub const fn within(&self, rows: usize, cols: usize) -> bool {
+        self.row < rows && self.col < cols
+    }
+
+    /// Mirror this coordinate across the vertical axis of a matrix `cols`
+    /// columns wide, i.e. the first column becomes the last.
+    ///
+    /// Returns `None` if the coordinate lies outside the matrix.
+    pub const fn mirror_cols(&self, cols: usize) -> Option<Self> {
+        if self.col < cols {
+            Some(Self::new(self.row, cols - 1 - self.col))
+        } else {
+            None
+        }
+    }
+
+    /// Mirror this coordinate across the horizontal axis of a matrix `rows`
+    /// rows tall, i.e. the first row becomes the last.
+    ///
+    /// Returns `None` if the coordinate lies outside the matrix.
+    pub const fn mirror_rows(&self, rows: usize) -> Option<Self> {
+        if self.row < rows {
+            Some(Self::new(rows - 1 - self.row, self.col))
+        } else {
+            None
+        }
+    }
+
+    /// Move this coordinate by `rows` and `cols`.
+    ///
+    /// Returns `None` if the result would have a negative row or column,
+    /// or does not fit in a `usize`.
+    pub const fn offset(&self, rows: isize, cols: isize) -> Option<Self> {
+        match (
+            self.row.checked_add_signed(rows),
+            self.col.checked_add_signed(cols),
+        ) {
+            (Some(row), Some(col)) => Some(Self::new(row, col)),
+            _ => None,
+        }
+    }
+}
+
+impl From<(usize, usize)> for Coordinate {
+    #[inline]
+    fn from((row, col): (usize, usize)) -> Self {
+        Self::new(row, col)
+    }
+}
+
+impl From<(u8, u8)> for Coordinate {
+    #[inline]
+    fn from((row, col): (u8, u8)) -> Self {
+        Self::new(row.into(), col.into())
+    }
+}
+
+impl From<Coordinate> for (usize, usize) {
+    #[inline]
+    fn from(coordinate: Coordinate) -> Self {
+        (coordinate.row, coordinate.col)
+    }
+}
+
+/// Physical placement of a single key.
+///
+/// Positions and sizes are given in hundredths of a key unit (`100` is
+/// one "1u" key), measured from the top-left corner of the board to the
+/// top-left corner of the key, matching the convention used by layout
+/// editors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KeyPosition {
+    x: i16,
+    y: i16,
+    width: u16,
+    height: u16,
+}
+
+impl KeyPosition {
+    /// Size of a standard 1u key.
+    pub const UNIT: u16 = 100;
+
+    /// Create a key at `(x, y)` with the given `width` and `height`.
+    pub const fn new(x: i16, y: i16, width: u16, height: u16) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Create a standard 1u key at `(x, y)`.
+    pub const fn unit(x: i16, y: i16) -> Self {
+        Self::new(x, y, Self::UNIT, Self::UNIT)
+    }
+
+    /// Horizontal position of the key's left edge.
+    pub const fn x(&self) -> i16 {
+        self.x
+    }
+
+    /// Vertical position of the key's top edge.
+    pub const fn y(&self) -> i16 {
+        self.y
+    }
+
+    /// Width of the key.
+    pub const fn width(&self) -> u16 {
+        self.width
+    }
+
+    /// Height of the key.
+    pub const fn height(&self) -> u16 {
+        self.height
+    }
+
+    /// Center of the key as `(x, y)`.
+    pub const fn center(&self) -> (i32, i32) {
+        (
+            self.x as i32 + self.width as i32 / 2,
+            self.y as i32 + self.height as i32 / 2,
+        )
+    }
+
+    /// Squared distance between the centers of two keys.
+    pub const fn distance_squared(&self, other: &KeyPosition) -> u32 {
+        let (ax, ay) = self.center();
+        let (bx, by) = other.center();
+        let dx = ax.abs_diff(bx);
+        let dy = ay.abs_diff(by);
+
+        dx * dx + dy * dy
+    }
+
+    /// Distance between the centers of two keys, rounded down.
+    pub const fn distance(&self, other: &KeyPosition) -> u32 {
+        isqrt(self.distance_squared(other))
+    }
+}
+
+/// Physical layout of a keyboard.
+///
+/// Maps every matrix [`Coordinate`] to the [`KeyPosition`] of the key
+/// wired there, or `None` where the matrix has no key. It is meant to be
+/// declared as `const` data next to the keymap, and consumed by lighting
+/// effects that depend on physical distance and by host tools rendering
+/// the board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Geometry<const ROWS: usize, const COLS: usize> {
+    keys: [[Option<KeyPosition>; COLS]; ROWS],
+}
+
+impl<const ROWS: usize, const COLS: usize> Geometry<ROWS, COLS> {
+    /// Create a geometry from per-coordinate key positions.
+    pub const fn new(keys: [[Option<KeyPosition>; COLS]; ROWS]) -> Self {
+        Self { keys }
+    }
+
+    /// Create a geometry for a plain grid of 1u keys, where every matrix
+    /// coordinate sits at the matching physical position.
+    pub const fn grid() -> Self {
+        let mut keys = [[None; COLS]; ROWS];
+        let mut row = 0;
+
+        while row < ROWS {
+            let mut col = 0;
+
+            while col < COLS {
+                keys[row][col] = Some(KeyPosition::unit(
+                    (col * KeyPosition::UNIT as usize) as i16,
+                    (row * KeyPosition::UNIT as usize) as i16,
+                ));
+                col += 1;
+            }
+
+            row += 1;
+        }
+
+        Self { keys }
+    }
+
+    /// Position of the key at `coordinate`, if there is one.
+    pub const fn key(&self, coordinate: Coordinate) -> Option<KeyPosition> {
+        if coordinate.within(ROWS, COLS) {
+            self.keys[coordinate.row][coordinate.col]
+        } else {
+            None
+        }
+    }
+
+    /// Distance between the keys at `a` and `b`, if both exist.
+    pub fn distance(&self, a: Coordinate, b: Coordinate) -> Option<u32> {
+        Some(self.key(a)?.distance(&self.key(b)?))
+    }
+
+    /// Iterate over every key of the board along with its coordinate.
+    pub fn iter(&self) -> impl Iterator<Item = (Coordinate, KeyPosition)> + '_ {
+        self.keys.iter().enumerate().flat_map(|(row, keys)| {
+            keys.iter()
+                .enumerate()
+                .filter_map(move |(col, key)| key.map(|k| (Coordinate::new(row, col), k)))
+        })
+    }
+
+    /// Size of the bounding box of all keys as `(width, height)`.
+    pub fn bounds(&self) -> (u16, u16) {
+        self.iter().fold((0, 0), |(w, h), (_, k)| {
+            let right = i32::from(k.x) + i32::from(k.width);
+            let bottom = i32::from(k.y) + i32::from(k.height);
+            (
+                w.max(u16::try_from(right).unwrap_or(0)),
+                h.max(u16::try_from(bottom).unwrap_or(0)),
+            )
+        })
+    }
+}
+
+/// Integer square root, rounded down.
+const fn isqrt(n: u32) -> u32 {
+    if n < 2 {
+        return n;
+    }
+
+    let mut x = n;
+    let mut y = x.div_ceil(2);
+
+    while y < x {
+        x = y;
+        y = (x + n / x) / 2;
+    }
+
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accessors() {
+        let c = Coordinate::new(2, 5);
+        assert_eq!(c.row(), 2);
+        assert_eq!(c.col(), 5);
+        assert_eq!(<(usize, usize)>::from(c), (2, 5));
+        assert_eq!(Coordinate::from((2u8, 5u8)), c);
+        assert_eq!(Coordinate::from((2usize, 5usize)), c);
+    }
+
+    #[test]
+    fn index_round_trip() {
+        for index in 0..(4 * 6) {
+            let c = Coordinate::from_index(index, 6);
+            assert!(c.within(4, 6));
+            assert_eq!(c.index(6), index);
+        }
+
+        assert_eq!(Coordinate::new(1, 2).index(6), 8);
+    }
+
+    #[test]
+    fn mirroring() {
+        let c = Coordinate::new(1, 0);
+        assert_eq!(c.mirror_cols(6), Some(Coordinate::new(1, 5)));
+        assert_eq!(c.mirror_rows(4), Some(Coordinate::new(2, 0)));
+        assert_eq!(c.mirror_cols(6).unwrap().mirror_cols(6), Some(c));
+        assert_eq!(Coordinate::new(0, 6).mirror_cols(6), None);
+        assert_eq!(Coordinate::new(4, 0).mirror_rows(4), None);
+    }
+
+    #[test]
+    fn offsets() {
+        let c = Coordinate::new(1, 2);
+        assert_eq!(c.offset(0, 6), Some(Coordinate::new(1, 8)));
+        assert_eq!(c.offset(-1, -2), Some(Coordinate::new(0, 0)));
+        assert_eq!(c.offset(-2, 0), None);
+        assert_eq!(c.offset(0, -3), None);
+    }
+
+    #[test]
+    fn key_distance() {
+        let a = KeyPosition::unit(0, 0);
+        let b = KeyPosition::unit(300, 400);
+        assert_eq!(a.center(), (50, 50));
+        assert_eq!(a.distance_squared(&b), 250_000);
+        assert_eq!(a.distance(&b), 500);
+        assert_eq!(b.distance(&a), 500);
+        assert_eq!(a.distance(&a), 0);
+    }
+
+    #[test]
+    fn grid_geometry() {
+        const GEOMETRY: Geometry<2, 3> = Geometry::grid();
+
+        assert_eq!(
+            GEOMETRY.key(Coordinate::new(1, 2)),
+            Some(KeyPosition::unit(200, 100))
+        );
+        assert_eq!(GEOMETRY.key(Coordinate::new(2, 0)), None);
+        assert_eq!(GEOMETRY.iter().count(), 6);
+        assert_eq!(GEOMETRY.bounds(), (300, 200));
+        assert_eq!(
+            GEOMETRY.distance(Coordinate::new(0, 0), Coordinate::new(0, 2)),
+            Some(200)
+        );
+    }
+
+    #[test]
+    fn sparse_geometry() {
+        let spacebar = KeyPosition::new(0, 100, 625, 100);
+        let geometry = Geometry::new([
+            [Some(KeyPosition::unit(0, 0)), None],
+            [None, Some(spacebar)],
+        ]);
+
+        assert_eq!(geometry.iter().count(), 2);
+        assert_eq!(geometry.bounds(), (625, 200));
+        assert_eq!(
+            geometry.distance(Coordinate::new(0, 0), Coordinate::new(0, 1)),
+            None
+        );
+    }
+}
@@ -0,0 +1,226 @@
+//! State handed from one firmware image to the next.
+//!
+//! When a bootloader or updater swaps firmware images in the field, the
+//! new image would start with every key released and every toggled layer
+//! off, which the user notices as dropped keys or a keyboard that
+//! suddenly types on the wrong layer. The outgoing image instead writes a
+//! [`Handoff`] to memory the bootloader preserves, e.g. a RAM section
+//! outside both images, and the incoming image restores the scanner and
+//! the engine from it.
+
+use crate::crc::Crc16;
+
+/// Debounce state of a single key, as exported by a scanner.
+///
+/// Packs the debounce counter, up to 127, and whether the key is pressed
+/// into a byte.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct KeyState(u8);
+
+impl KeyState {
+    const PRESSED: u8 = 0x80;
+
+    /// Key released with its debounce counter at zero.
+    pub const RELEASED: Self = Self(0);
+
+    /// Create a key state. Counters above 127 saturate.
+    pub const fn new(counter: u8, pressed: bool) -> Self {
+        let counter = if counter > 0x7f { 0x7f } else { counter };
+        Self(counter | if pressed { Self::PRESSED } else { 0 })
+    }
+
+    /// Debounce counter.
+    pub const fn counter(&self) -> u8 {
+        self.0 & !Self::PRESSED
+    }
+
+    /// Whether the key is pressed.
+    pub const fn pressed(&self) -> bool {
+        self.0 & Self::PRESSED != 0
+    }
+}
+
+/// Errors produced while decoding a [`Handoff`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum HandoffError {
+    /// No handoff was left, e.g. after a cold boot
+    NotFound,
+    /// The handoff has a different version or dimensions
+    Incompatible,
+    /// The handoff failed its checksum
+    Corrupted,
+    /// The buffer is too small for the handoff
+    BufferTooSmall,
+}
+
+/// Keyboard state carried across a firmware swap.
+///
+/// Only the state that cannot be rebuilt is carried: keys held across the
+/// swap are reported pressed again by the restored scanner, which brings
+/// momentary layers and held keys back in the engine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Handoff<const ROWS: usize, const COLS: usize> {
+    /// Debounce state of every key
+    pub keys: [[KeyState; COLS]; ROWS],
+    /// Default layer, see [`LayerState::default_layer`]
+    ///
+    /// [`LayerState::default_layer`]: crate::engine::LayerState::default_layer
+    pub default_layer: u8,
+    /// Toggled layers, see [`LayerState::toggled`]
+    ///
+    /// [`LayerState::toggled`]: crate::engine::LayerState::toggled
+    pub toggled_layers: u32,
+    /// Where the settings in use are stored, e.g. the offset of a
+    /// `SettingsStorage`, so that the new image loads the same ones
+    pub settings: u32,
+}
+
+impl<const ROWS: usize, const COLS: usize> Handoff<ROWS, COLS> {
+    /// Magic number identifying a handoff.
+    pub const MAGIC: [u8; 4] = *b"EKHO";
+    /// Version of the handoff layout.
+    pub const VERSION: u8 = 1;
+    /// Length of the encoded handoff in bytes.
+    pub const LEN: usize = HEADER_LEN + ROWS * COLS + 2;
+
+    /// Encode the handoff into `buf`, returning the number of bytes
+    /// written.
+    pub fn to_bytes(&self, buf: &mut [u8]) -> Result<usize, HandoffError> {
+        let (Ok(rows), Ok(cols)) = (u8::try_from(ROWS), u8::try_from(COLS)) else {
+            return Err(HandoffError::Incompatible);
+        };
+        let buf = buf
+            .get_mut(..Self::LEN)
+            .ok_or(HandoffError::BufferTooSmall)?;
+        let (body, crc) = buf.split_at_mut(Self::LEN - 2);
+
+        body[..4].copy_from_slice(&Self::MAGIC);
+        body[4] = Self::VERSION;
+        body[5] = rows;
+        body[6] = cols;
+        body[7] = self.default_layer;
+        body[8..12].copy_from_slice(&self.toggled_layers.to_le_bytes());
+        body[12..16].copy_from_slice(&self.settings.to_le_bytes());
+
+        for (byte, key) in body[HEADER_LEN..]
+            .iter_mut()
+            .zip(self.keys.iter().flatten())
+        {
+            *byte = key.0;
+        }
+
+        crc.copy_from_slice(&checksum(body).to_le_bytes());
+
+        Ok(Self::LEN)
+    }
+
+    /// Decode a handoff from `buf`.
+    pub fn from_bytes(buf: &[u8]) -> Result<Self, HandoffError> {
+        if buf.get(..4) != Some(&Self::MAGIC[..]) {
+            return Err(HandoffError::NotFound);
+        }
+
+        let buf = buf.get(..Self::LEN).ok_or(HandoffError::Incompatible)?;
+        let (body, crc) = buf.split_at(Self::LEN - 2);
+
+        if body[4] != Self::VERSION || usize::from(body[5]) != ROWS || usize::from(body[6]) != COLS
+        {
+            return Err(HandoffError::Incompatible);
+        }
+
+        if checksum(body).to_le_bytes() != crc {
+            return Err(HandoffError::Corrupted);
+        }
+
+        let mut keys = [[KeyState::RELEASED; COLS]; ROWS];
+        for (key, byte) in keys.iter_mut().flatten().zip(&body[HEADER_LEN..]) {
+            *key = KeyState(*byte);
+        }
+
+        Ok(Self {
+            keys,
+            default_layer: body[7],
+            toggled_layers: u32::from_le_bytes([body[8], body[9], body[10], body[11]]),
+            settings: u32::from_le_bytes([body[12], body[13], body[14], body[15]]),
+        })
+    }
+}
+
+const HEADER_LEN: usize = 16;
+
+fn checksum(bytes: &[u8]) -> u16 {
+    let mut crc = Crc16::new();
+    crc.update(bytes);
+    crc.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::Engine;
+    use crate::{KeyCode, Keymap};
+
+    #[test]
+    fn round_trip() {
+        let mut keys = [[KeyState::RELEASED; 3]; 2];
+        keys[1][2] = KeyState::new(200, true);
+        assert_eq!(keys[1][2].counter(), 0x7f);
+
+        let handoff = Handoff {
+            keys,
+            default_layer: 1,
+            toggled_layers: 0b100,
+            settings: 0x0001_0000,
+        };
+        let mut buf = [0; Handoff::<2, 3>::LEN];
+
+        assert_eq!(handoff.to_bytes(&mut buf), Ok(24));
+        assert_eq!(Handoff::<2, 3>::from_bytes(&buf), Ok(handoff));
+        assert_eq!(
+            Handoff::<3, 2>::from_bytes(&buf),
+            Err(HandoffError::Incompatible)
+        );
+
+        buf[16] ^= 0x01;
+        assert_eq!(
+            Handoff::<2, 3>::from_bytes(&buf),
+            Err(HandoffError::Corrupted)
+        );
+        assert_eq!(
+            Handoff::<2, 3>::from_bytes(&[0; 24]),
+            Err(HandoffError::NotFound)
+        );
+        assert_eq!(
+            handoff.to_bytes(&mut [0; 23]),
+            Err(HandoffError::BufferTooSmall)
+        );
+    }
+
+    #[test]
+    fn restore_engine_layers() {
+        let keymap: Keymap<3, 1, 1> = crate::keymap! {
+            { [KA] }
+            { [KB] }
+            { [KC] }
+        };
+        let mut old = Engine::new(keymap);
+        old.set_default_layer(1);
+        old.activate_layer(2);
+
+        let layers = old.layers();
+        let handoff = Handoff::<1, 1> {
+            keys: [[KeyState::RELEASED]],
+            default_layer: layers.default_layer(),
+            toggled_layers: layers.toggled(),
+            settings: 0,
+        };
+
+        let mut new = Engine::new(keymap);
+        new.restore_layers(handoff.default_layer, handoff.toggled_layers);
+        assert_eq!(new.layers(), layers);
+        assert!(new.keycodes().eq([] as [KeyCode; 0]));
+    }
+}
@@ -0,0 +1,72 @@
+/// Battery strength of a wireless keyboard.
+///
+/// The report carries the Battery Strength usage of the Generic Device
+/// Controls page, which hosts show as the keyboard's battery level. On the
+/// wire the report is laid out as:
+///
+/// | Byte | Contents                     |
+/// |------|------------------------------|
+/// | 0    | Battery strength, in percent |
+///
+/// New levels, e.g. as returned by
+/// [`Battery::tick`](crate::battery::Battery::tick), are sent as they
+/// come, since the battery is only sampled every so often.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BatteryReport {
+    level: u8,
+}
+
+impl BatteryReport {
+    /// Length of the serialized report in bytes.
+    pub const LEN: usize = 1;
+
+    /// HID report descriptor matching this report.
+    pub const DESCRIPTOR: [u8; 19] = [
+        0x05, 0x06, // Usage Page (Generic Device Controls)
+        0x09, 0x01, // Usage (Background/Nonuser Controls)
+        0xa1, 0x01, // Collection (Application)
+        0x09, 0x20, //   Usage (Battery Strength)
+        0x15, 0x00, //   Logical Minimum (0)
+        0x25, 0x64, //   Logical Maximum (100)
+        0x75, 0x08, //   Report Size (8)
+        0x95, 0x01, //   Report Count (1)
+        0x81, 0x02, //   Input (Data, Variable, Absolute)
+        0xc0, //       End Collection
+    ];
+
+    /// Create a report of the battery at `level` percent, capped at 100.
+    pub const fn new(level: u8) -> Self {
+        Self {
+            level: if level > 100 { 100 } else { level },
+        }
+    }
+
+    /// Battery level, in percent.
+    pub const fn level(&self) -> u8 {
+        self.level
+    }
+
+    /// Serialize the report into `buf`, returning the number of bytes
+    /// written, or `None` if `buf` is shorter than [`Self::LEN`].
+    pub fn serialize(&self, buf: &mut [u8]) -> Option<usize> {
+        *buf.first_mut()? = self.level;
+
+        Some(Self::LEN)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serialize_level() {
+        let mut buf = [0xff; 2];
+        assert_eq!(BatteryReport::new(42).serialize(&mut buf), Some(1));
+        assert_eq!(buf, [42, 0xff]);
+
+        assert_eq!(BatteryReport::new(150).level(), 100);
+        assert_eq!(BatteryReport::default().serialize(&mut []), None);
+    }
+}
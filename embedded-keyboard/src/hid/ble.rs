@@ -0,0 +1,241 @@
+use super::{BootKeyboardReport, ChangeDetector, ConsumerReport, DescriptorBuilder, LedState};
+use crate::Usage;
+
+/// Report ID of the keyboard report in [`BleKeyboard::REPORT_MAP`] and
+/// [`CompositeReporter::DESCRIPTOR`](super::CompositeReporter::DESCRIPTOR).
+pub const KEYBOARD_REPORT_ID: u8 = 1;
+
+/// Report ID of the consumer control report in [`BleKeyboard::REPORT_MAP`]
+/// and [`CompositeReporter::DESCRIPTOR`](super::CompositeReporter::DESCRIPTOR).
+pub const CONSUMER_REPORT_ID: u8 = 2;
+
+/// Type of a report, as declared in its report reference.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum ReportType {
+    /// Sent by the device
+    Input = 1,
+    /// Sent by the host
+    Output = 2,
+    /// Read and written by the host on demand
+    Feature = 3,
+}
+
+/// Report reference of a Report characteristic.
+///
+/// HID-over-GATT exposes every report as its own characteristic, told
+/// apart by the value of its Report Reference descriptor (UUID `0x2908`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ReportReference {
+    id: u8,
+    report_type: ReportType,
+}
+
+impl ReportReference {
+    /// Keyboard input report.
+    pub const KEYBOARD_INPUT: Self = Self::new(KEYBOARD_REPORT_ID, ReportType::Input);
+    /// Keyboard LED output report.
+    pub const KEYBOARD_OUTPUT: Self = Self::new(KEYBOARD_REPORT_ID, ReportType::Output);
+    /// Consumer control input report.
+    pub const CONSUMER_INPUT: Self = Self::new(CONSUMER_REPORT_ID, ReportType::Input);
+
+    /// Create a report reference.
+    pub const fn new(id: u8, report_type: ReportType) -> Self {
+        Self { id, report_type }
+    }
+
+    /// Report ID.
+    pub const fn id(&self) -> u8 {
+        self.id
+    }
+
+    /// Report type.
+    pub const fn report_type(&self) -> ReportType {
+        self.report_type
+    }
+
+    /// Value of the Report Reference descriptor.
+    pub const fn to_bytes(&self) -> [u8; 2] {
+        [self.id, self.report_type as u8]
+    }
+}
+
+/// Report characteristics of a HID-over-GATT service, implemented on top
+/// of a BLE stack such as `nrf-softdevice` or TrouBLE.
+pub trait HidService {
+    /// Error type
+    type Error;
+
+    /// Notify the host of a new value of the input report characteristic
+    /// with `reference`.
+    fn notify(&mut self, reference: ReportReference, data: &[u8]) -> Result<(), Self::Error>;
+}
+
+/// Keyboard with consumer controls over HID-over-GATT.
+///
+/// Transport-agnostic: the application declares the HID service with
+/// [`BleKeyboard::REPORT_MAP`] and one Report characteristic per
+/// [`ReportReference`], then feeds every set of pressed usages to
+/// [`BleKeyboard::update`] and every write of the host to the output
+/// report characteristic to [`BleKeyboard::output`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BleKeyboard {
+    keyboard: ChangeDetector<BootKeyboardReport>,
+    consumer: ChangeDetector<ConsumerReport<1>>,
+    leds: LedState,
+    leds_changed: bool,
+}
+
+impl BleKeyboard {
+    /// Report Map characteristic value: the keyboard and the consumer
+    /// control report descriptors, with their report IDs.
+    pub const REPORT_MAP: [u8; 92] = DescriptorBuilder::new()
+        .collection(Some(KEYBOARD_REPORT_ID), &BootKeyboardReport::DESCRIPTOR)
+        .collection(Some(CONSUMER_REPORT_ID), &ConsumerReport::<1>::DESCRIPTOR)
+        .build();
+
+    /// Create a keyboard with nothing pressed.
+    pub const fn new() -> Self {
+        Self {
+            keyboard: ChangeDetector::new(BootKeyboardReport::new()),
+            consumer: ChangeDetector::new(ConsumerReport::<1>::new()),
+            leds: LedState::from_bits(0),
+            leds_changed: false,
+        }
+    }
+
+    /// LED state last written by the host.
+    pub const fn leds(&self) -> LedState {
+        self.leds
+    }
+
+    /// LED state set by the host, if it changed since the last call.
+    /// Meant for driving indicators through
+    /// [`KeyboardLeds`](crate::KeyboardLeds).
+    pub fn take_leds(&mut self) -> Option<LedState> {
+        core::mem::take(&mut self.leds_changed).then_some(self.leds)
+    }
+
+    /// Notify the host of both reports on the next update, even if they
+    /// did not change, e.g. after reconnecting.
+    pub fn force_report(&mut self) {
+        self.keyboard.force_report();
+        self.consumer.force_report();
+    }
+
+    /// Update the pressed usages, e.g. from
+    /// [`Engine::usages`](crate::engine::Engine::usages), notifying the
+    /// host of the reports which changed. Reports which could not be
+    /// notified are retried on the next update.
+    pub fn update<S: HidService>(
+        &mut self,
+        service: &mut S,
+        usages: impl IntoIterator<Item = Usage>,
+    ) -> Result<(), S::Error> {
+        let mut keyboard = BootKeyboardReport::new();
+        let mut consumer = ConsumerReport::<1>::new();
+
+        for usage in usages {
+            keyboard.extend([usage]);
+            consumer.extend([usage]);
+        }
+
+        if let Some(keyboard) = self.keyboard.update(keyboard) {
+            let mut buf = [0; BootKeyboardReport::LEN];
+            keyboard.serialize(&mut buf);
+            trace!("ble: keyboard report {=[u8]:x}", buf);
+            if let Err(e) = service.notify(ReportReference::KEYBOARD_INPUT, &buf) {
+                self.keyboard.force_report();
+                return Err(e);
+            }
+        }
+
+        if let Some(consumer) = self.consumer.update(consumer) {
+            let mut buf = [0; ConsumerReport::<1>::LEN];
+            consumer.serialize(&mut buf);
+            trace!("ble: consumer report {=[u8]:x}", buf);
+            if let Err(e) = service.notify(ReportReference::CONSUMER_INPUT, &buf) {
+                self.consumer.force_report();
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handle a write of the host to the report characteristic with
+    /// `reference`.
+    pub fn output(&mut self, reference: ReportReference, data: &[u8]) {
+        if reference == ReportReference::KEYBOARD_OUTPUT {
+            if let Some(leds) = LedState::from_report(data) {
+                self.leds_changed |= leds != self.leds;
+                self.leds = leds;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeyCode;
+    use std::vec::Vec;
+
+    struct Service(Vec<(ReportReference, Vec<u8>)>);
+
+    impl HidService for Service {
+        type Error = ();
+
+        fn notify(&mut self, reference: ReportReference, data: &[u8]) -> Result<(), ()> {
+            self.0.push((reference, data.to_vec()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn report_map_with_ids() {
+        let map = BleKeyboard::REPORT_MAP;
+        assert_eq!(&map[4..8], &[0xa1, 0x01, 0x85, KEYBOARD_REPORT_ID]);
+        assert_eq!(&map[71..75], &[0xa1, 0x01, 0x85, CONSUMER_REPORT_ID]);
+        assert_eq!(ReportReference::CONSUMER_INPUT.to_bytes(), [2, 1]);
+    }
+
+    #[test]
+    fn notify_changed_reports() {
+        let mut keyboard = BleKeyboard::new();
+        let mut service = Service(Vec::new());
+        let mute = Usage::new(Usage::CONSUMER_PAGE, 0xe2);
+
+        keyboard
+            .update(&mut service, [Usage::from(KeyCode::KA), mute])
+            .unwrap();
+        keyboard
+            .update(&mut service, [Usage::from(KeyCode::KA)])
+            .unwrap();
+        assert_eq!(
+            service.0,
+            [
+                (
+                    ReportReference::KEYBOARD_INPUT,
+                    [0, 0, 4, 0, 0, 0, 0, 0].to_vec()
+                ),
+                (ReportReference::CONSUMER_INPUT, [0xe2, 0].to_vec()),
+                (ReportReference::CONSUMER_INPUT, [0, 0].to_vec()),
+            ]
+        );
+
+        keyboard.force_report();
+        keyboard
+            .update(&mut service, [Usage::from(KeyCode::KA)])
+            .unwrap();
+        assert_eq!(service.0.len(), 5);
+
+        keyboard.output(ReportReference::KEYBOARD_OUTPUT, &[LedState::CAPS_LOCK]);
+        assert!(keyboard.leds().caps_lock());
+        assert_eq!(keyboard.take_leds(), Some(keyboard.leds()));
+        assert_eq!(keyboard.take_leds(), None);
+    }
+}
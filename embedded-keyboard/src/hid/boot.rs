@@ -0,0 +1,274 @@
+use super::rollover::{press_slot, release_slot};
+use super::{modifier_bit, RolloverPolicy};
+use crate::{KeyCode, Usage};
+
+/// Keyboard report in the boot protocol format.
+///
+/// The 8-byte report every BIOS and host understands, with room for six
+/// simultaneously pressed keys besides the modifiers (6KRO). On the wire
+/// the report is laid out as:
+///
+/// | Byte | Contents                        |
+/// |------|---------------------------------|
+/// | 0    | Modifier bitmap (`0xe0..=0xe7`) |
+/// | 1    | Reserved                        |
+/// | 2..8 | Usages                          |
+///
+/// Pressing a seventh key rolls the report over: as the HID specification
+/// requires, every usage slot then reports `ErrorRollOver` (`0x01`),
+/// while the modifiers are still reported. A rolled over report stays so
+/// until cleared, so reports are meant to be built afresh from the set of
+/// pressed usages. Another [`RolloverPolicy`] can keep reporting six of
+/// the keys instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BootKeyboardReport {
+    modifiers: u8,
+    keys: [u8; 6],
+    rolled_over: bool,
+    policy: RolloverPolicy,
+}
+
+impl BootKeyboardReport {
+    /// Length of the serialized report in bytes.
+    pub const LEN: usize = 8;
+
+    /// HID report descriptor matching this report, including the LED
+    /// output report of the boot protocol.
+    pub const DESCRIPTOR: [u8; 65] = [
+        0x05, 0x01, //       Usage Page (Generic Desktop)
+        0x09, 0x06, //       Usage (Keyboard)
+        0xa1, 0x01, //       Collection (Application)
+        0x05, 0x07, //         Usage Page (Keyboard/Keypad)
+        0x19, 0xe0, //         Usage Minimum (Left Control)
+        0x29, 0xe7, //         Usage Maximum (Right GUI)
+        0x15, 0x00, //         Logical Minimum (0)
+        0x25, 0x01, //         Logical Maximum (1)
+        0x75, 0x01, //         Report Size (1)
+        0x95, 0x08, //         Report Count (8)
+        0x81, 0x02, //         Input (Data, Variable, Absolute)
+        0x75, 0x08, //         Report Size (8)
+        0x95, 0x01, //         Report Count (1)
+        0x81, 0x01, //         Input (Constant)
+        0x05, 0x08, //         Usage Page (LEDs)
+        0x19, 0x01, //         Usage Minimum (Num Lock)
+        0x29, 0x05, //         Usage Maximum (Kana)
+        0x75, 0x01, //         Report Size (1)
+        0x95, 0x05, //         Report Count (5)
+        0x91, 0x02, //         Output (Data, Variable, Absolute)
+        0x75, 0x03, //         Report Size (3)
+        0x95, 0x01, //         Report Count (1)
+        0x91, 0x01, //         Output (Constant)
+        0x05, 0x07, //         Usage Page (Keyboard/Keypad)
+        0x19, 0x00, //         Usage Minimum (0)
+        0x2a, 0xff, 0x00, //   Usage Maximum (0xff)
+        0x15, 0x00, //         Logical Minimum (0)
+        0x26, 0xff, 0x00, //   Logical Maximum (0xff)
+        0x75, 0x08, //         Report Size (8)
+        0x95, 0x06, //         Report Count (6)
+        0x81, 0x00, //         Input (Data, Array, Absolute)
+        0xc0, //             End Collection
+    ];
+
+    /// Create an empty report, rolling over with
+    /// [`RolloverPolicy::ErrorRollOver`].
+    pub const fn new() -> Self {
+        Self::with_rollover(RolloverPolicy::ErrorRollOver)
+    }
+
+    /// Create an empty report handling more than six keys with `policy`.
+    pub const fn with_rollover(policy: RolloverPolicy) -> Self {
+        Self {
+            modifiers: 0,
+            keys: [0; 6],
+            rolled_over: false,
+            policy,
+        }
+    }
+
+    /// How more than six keys are handled.
+    pub const fn rollover(&self) -> RolloverPolicy {
+        self.policy
+    }
+
+    /// Create a report from a set of pressed usages. Usages beyond the
+    /// capacity of the report roll it over.
+    pub fn from_usages(usages: impl IntoIterator<Item = u16>) -> Self {
+        let mut report = Self::new();
+
+        for usage in usages {
+            report.press(usage);
+        }
+
+        report
+    }
+
+    /// Add pressed usages to the report, e.g. from
+    /// [`Engine::usages`](crate::engine::Engine::usages). Usages on other
+    /// pages than the Keyboard/Keypad page are skipped.
+    ///
+    /// Returns `false` if some usages could not be added.
+    pub fn extend(&mut self, usages: impl IntoIterator<Item = Usage>) -> bool {
+        let mut fits = true;
+
+        for usage in usages.into_iter().filter_map(|usage| usage.keyboard_id()) {
+            fits &= self.press(usage);
+        }
+
+        fits
+    }
+
+    /// Add a pressed usage to the report.
+    ///
+    /// Returns `false` if the report is full, in which case it follows its
+    /// [`RolloverPolicy`], or the usage does not fit in the 8-bit usages of
+    /// the boot protocol.
+    pub fn press(&mut self, usage: u16) -> bool {
+        if let Some(bit) = modifier_bit(usage) {
+            self.modifiers |= bit;
+            return true;
+        }
+
+        let Ok(usage) = u8::try_from(usage) else {
+            return false;
+        };
+
+        if usage == 0 || self.keys.contains(&usage) {
+            return true;
+        }
+
+        let fits = press_slot(&mut self.keys, usage, self.policy);
+        if !fits && self.policy == RolloverPolicy::ErrorRollOver {
+            self.rolled_over = true;
+        }
+
+        fits
+    }
+
+    /// Remove a usage from the report. A rolled over report stays rolled
+    /// over.
+    pub fn release(&mut self, usage: u16) {
+        if let Some(bit) = modifier_bit(usage) {
+            self.modifiers &= !bit;
+            return;
+        }
+
+        if let Ok(usage) = u8::try_from(usage) {
+            release_slot(&mut self.keys, usage, self.policy);
+        }
+    }
+
+    /// Remove every usage from the report.
+    pub fn clear(&mut self) {
+        *self = Self::with_rollover(self.policy);
+    }
+
+    /// Modifier bitmap.
+    pub const fn modifiers(&self) -> u8 {
+        self.modifiers
+    }
+
+    /// Whether more keys were pressed than the report can carry.
+    pub const fn is_rolled_over(&self) -> bool {
+        self.rolled_over
+    }
+
+    /// Pressed non-modifier usages, in slot order, or `ErrorRollOver` in
+    /// every slot if the report rolled over.
+    pub fn usages(&self) -> impl Iterator<Item = u16> + '_ {
+        self.slots().into_iter().filter(|k| *k != 0).map(u16::from)
+    }
+
+    /// Serialize the report into `buf`, returning the number of bytes
+    /// written, or `None` if `buf` is shorter than [`Self::LEN`].
+    pub fn serialize(&self, buf: &mut [u8]) -> Option<usize> {
+        let buf = buf.get_mut(..Self::LEN)?;
+
+        buf[0] = self.modifiers;
+        buf[1] = 0;
+        buf[2..].copy_from_slice(&self.slots());
+
+        Some(Self::LEN)
+    }
+
+    /// Usage slots as sent on the wire.
+    fn slots(&self) -> [u8; 6] {
+        if self.rolled_over {
+            [KeyCode::ErrorRollOver as u8; 6]
+        } else {
+            self.keys
+        }
+    }
+}
+
+impl Default for BootKeyboardReport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn six_keys_and_modifiers() {
+        let mut report = BootKeyboardReport::from_usages((0x04..0x0a).chain([0x00e0, 0x00e5]));
+
+        assert!(!report.press(0x0100));
+        assert!(report.press(KeyCode::KpRightGUI.into()));
+        assert!(!report.is_rolled_over());
+
+        let mut buf = [0xaa; 8];
+        assert_eq!(report.serialize(&mut buf), Some(8));
+        assert_eq!(buf, [0xa1, 0x00, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09]);
+
+        report.release(0x0006);
+        report.release(0x00e0);
+        assert_eq!(report.modifiers(), 0xa0);
+        assert!(report.extend([Usage::from(KeyCode::KZ)]));
+        assert!(report.usages().eq([0x04, 0x05, 0x1d, 0x07, 0x08, 0x09]));
+    }
+
+    #[test]
+    fn error_roll_over() {
+        let mut report = BootKeyboardReport::from_usages((0x04..0x0a).chain([0x00e1]));
+
+        assert!(!report.press(KeyCode::KZ.into()));
+        assert!(report.is_rolled_over());
+        assert!(report.press(0x0004));
+        assert!(report.usages().eq([0x01; 6]));
+
+        let mut buf = [0xaa; 8];
+        report.serialize(&mut buf);
+        assert_eq!(buf, [0x02, 0x00, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01]);
+
+        report.clear();
+        assert!(!report.is_rolled_over());
+        assert_eq!(report.usages().count(), 0);
+    }
+
+    #[test]
+    fn keep_oldest_or_newest() {
+        let mut report = BootKeyboardReport::with_rollover(RolloverPolicy::KeepOldest);
+        assert!(report.extend((0x04..0x0a).map(Usage::keyboard)));
+        assert!(!report.press(0x1d));
+        assert!(!report.is_rolled_over());
+        assert!(report.usages().eq(0x04..0x0a));
+
+        let mut report = BootKeyboardReport::with_rollover(RolloverPolicy::KeepNewest);
+        assert!(report.extend((0x04..0x0a).map(Usage::keyboard)));
+        assert!(!report.press(0x1d));
+        assert!(!report.is_rolled_over());
+        assert!(report.usages().eq([0x05, 0x06, 0x07, 0x08, 0x09, 0x1d]));
+
+        // Releases keep the slots in press order.
+        report.release(0x06);
+        assert!(report.press(0x1e));
+        assert!(!report.press(0x1f));
+        assert!(report.usages().eq([0x07, 0x08, 0x09, 0x1d, 0x1e, 0x1f]));
+
+        report.clear();
+        assert_eq!(report.rollover(), RolloverPolicy::KeepNewest);
+    }
+}
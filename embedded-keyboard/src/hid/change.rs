@@ -0,0 +1,69 @@
+/// Holds back reports identical to the last one sent.
+///
+/// Hosts keep the last report they received, so sending an unchanged
+/// one only wastes USB bandwidth, or radio time and power over BLE. Every
+/// report built is passed through [`ChangeDetector::update`], and only
+/// the ones it returns are sent. After the host may have lost track, e.g.
+/// on resume from suspend, or when a report could not be sent,
+/// [`ChangeDetector::force_report`] lets the next report through
+/// regardless.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ChangeDetector<R> {
+    last: R,
+    forced: bool,
+}
+
+impl<R: PartialEq + Copy> ChangeDetector<R> {
+    /// Create a detector assuming the host last received `initial`,
+    /// usually an empty report.
+    pub const fn new(initial: R) -> Self {
+        Self {
+            last: initial,
+            forced: false,
+        }
+    }
+
+    /// Last report let through.
+    pub const fn last(&self) -> &R {
+        &self.last
+    }
+
+    /// Return `report` if it has to be sent, because it differs from the
+    /// last one or a report was forced.
+    pub fn update(&mut self, report: R) -> Option<R> {
+        if report == self.last && !self.forced {
+            return None;
+        }
+
+        self.last = report;
+        self.forced = false;
+        Some(report)
+    }
+
+    /// Let the next report through even if it is unchanged.
+    pub fn force_report(&mut self) {
+        self.forced = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hid::BootKeyboardReport;
+
+    #[test]
+    fn only_changes_and_forced() {
+        let mut detector = ChangeDetector::new(BootKeyboardReport::new());
+        let pressed = BootKeyboardReport::from_usages([0x04]);
+
+        assert_eq!(detector.update(BootKeyboardReport::new()), None);
+        assert_eq!(detector.update(pressed), Some(pressed));
+        assert_eq!(detector.update(pressed), None);
+
+        detector.force_report();
+        assert_eq!(detector.update(pressed), Some(pressed));
+        assert_eq!(detector.update(pressed), None);
+        assert_eq!(detector.last(), &pressed);
+    }
+}
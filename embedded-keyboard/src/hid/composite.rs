@@ -0,0 +1,386 @@
+use super::{
+    BootKeyboardReport, ChangeDetector, ConsumerReport, DescriptorBuilder, LedState, MouseReport,
+    NkroKeyboardReport, SystemControl, SystemControlReport, CONSUMER_REPORT_ID, KEYBOARD_REPORT_ID,
+};
+use crate::Usage;
+
+/// Report ID of the system control report in
+/// [`CompositeReporter::DESCRIPTOR`].
+pub const SYSTEM_REPORT_ID: u8 = 3;
+
+/// Report ID of the mouse report in [`CompositeReporter::DESCRIPTOR`].
+pub const MOUSE_REPORT_ID: u8 = 4;
+
+/// Report ID of the NKRO keyboard report in
+/// [`CompositeReporter::DESCRIPTOR`].
+pub const NKRO_REPORT_ID: u8 = 5;
+
+const KEYBOARD: u8 = 1 << 0;
+const NKRO: u8 = 1 << 1;
+const CONSUMER: u8 = 1 << 2;
+const SYSTEM: u8 = 1 << 3;
+const MOUSE: u8 = 1 << 4;
+
+/// Keyboard, NKRO keyboard, consumer control, system control and mouse
+/// reports of a composite HID device, multiplexed behind report IDs.
+///
+/// The device declares [`CompositeReporter::DESCRIPTOR`] as its report
+/// descriptor. After every scan, the application feeds the pressed
+/// usages to [`CompositeReporter::update`], then sends the reports that
+/// changed one by one as returned by [`CompositeReporter::next_report`],
+/// each prefixed with its report ID. If a report could not be sent,
+/// [`CompositeReporter::force_report`] sends every report again.
+///
+/// Keys are reported in the 6KRO keyboard report, or in the NKRO report
+/// of `BYTES` bytes once [`CompositeReporter::set_nkro`] enabled it; the
+/// other one stays empty. Up to `N` consumer usages are reported at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CompositeReporter<const BYTES: usize, const N: usize> {
+    keyboard: ChangeDetector<BootKeyboardReport>,
+    nkro: ChangeDetector<NkroKeyboardReport<BYTES>>,
+    consumer: ChangeDetector<ConsumerReport<N>>,
+    system: SystemControl,
+    system_report: SystemControlReport,
+    mouse: ChangeDetector<MouseReport>,
+    pointer_buttons: u8,
+    nkro_enabled: bool,
+    pending: u8,
+    leds: LedState,
+    leds_changed: bool,
+}
+
+impl<const BYTES: usize, const N: usize> CompositeReporter<BYTES, N> {
+    /// Report descriptor of the composite device.
+    pub const DESCRIPTOR: [u8; 226] = DescriptorBuilder::new()
+        .collection(Some(KEYBOARD_REPORT_ID), &BootKeyboardReport::DESCRIPTOR)
+        .collection(Some(CONSUMER_REPORT_ID), &ConsumerReport::<N>::DESCRIPTOR)
+        .collection(Some(SYSTEM_REPORT_ID), &SystemControlReport::DESCRIPTOR)
+        .collection(Some(MOUSE_REPORT_ID), &MouseReport::DESCRIPTOR)
+        .collection(
+            Some(NKRO_REPORT_ID),
+            &NkroKeyboardReport::<BYTES>::DESCRIPTOR,
+        )
+        .build();
+
+    /// Length of the longest report, including its report ID.
+    pub const MAX_LEN: usize = 1 + max(
+        max(BootKeyboardReport::LEN, NkroKeyboardReport::<BYTES>::LEN),
+        max(ConsumerReport::<N>::LEN, MouseReport::LEN),
+    );
+
+    /// Create a reporter with nothing pressed, reporting keys in the 6KRO
+    /// keyboard report.
+    pub const fn new() -> Self {
+        Self {
+            keyboard: ChangeDetector::new(BootKeyboardReport::new()),
+            nkro: ChangeDetector::new(NkroKeyboardReport::new()),
+            consumer: ChangeDetector::new(ConsumerReport::new()),
+            system: SystemControl::new(),
+            system_report: SystemControlReport::new(),
+            mouse: ChangeDetector::new(MouseReport::new()),
+            pointer_buttons: 0,
+            nkro_enabled: false,
+            pending: 0,
+            leds: LedState::from_bits(0),
+            leds_changed: false,
+        }
+    }
+
+    /// Whether keys are reported in the NKRO report.
+    pub const fn nkro(&self) -> bool {
+        self.nkro_enabled
+    }
+
+    /// Report keys in the NKRO report rather than the 6KRO one, or the
+    /// other way around. Takes effect on the next update, which also
+    /// clears the report no longer used.
+    pub fn set_nkro(&mut self, enabled: bool) {
+        self.nkro_enabled = enabled;
+    }
+
+    /// LED state last written by the host.
+    pub const fn leds(&self) -> LedState {
+        self.leds
+    }
+
+    /// LED state set by the host, if it changed since the last call.
+    /// Meant for driving indicators through
+    /// [`KeyboardLeds`](crate::KeyboardLeds).
+    pub fn take_leds(&mut self) -> Option<LedState> {
+        core::mem::take(&mut self.leds_changed).then_some(self.leds)
+    }
+
+    /// Whether reports are waiting to be sent.
+    pub const fn pending(&self) -> bool {
+        self.pending != 0
+    }
+
+    /// Send every report on the next update, even if it did not change,
+    /// e.g. after resuming from suspend.
+    pub fn force_report(&mut self) {
+        self.keyboard.force_report();
+        self.nkro.force_report();
+        self.consumer.force_report();
+        self.mouse.force_report();
+    }
+
+    /// Update the pressed usages, e.g. from
+    /// [`Engine::usages`](crate::engine::Engine::usages), queueing the
+    /// reports which changed.
+    ///
+    /// Returns `false` if some usages did not fit in their report.
+    pub fn update(&mut self, usages: impl IntoIterator<Item = Usage>) -> bool {
+        let mut keyboard = BootKeyboardReport::new();
+        let mut nkro = NkroKeyboardReport::new();
+        let mut consumer = ConsumerReport::new();
+        // Starts out with the motion waiting to be sent, if any.
+        let mut mouse = *self.mouse.last();
+        let mut buttons = MouseReport::new();
+        let mut system = [None; 3];
+        let mut fits = true;
+
+        for usage in usages {
+            fits &= if self.nkro_enabled {
+                nkro.extend([usage])
+            } else {
+                keyboard.extend([usage])
+            };
+            fits &= consumer.extend([usage]);
+            buttons.extend([usage]);
+
+            if usage.page() == Usage::GENERIC_DESKTOP_PAGE {
+                if let Some(slot) = system.iter_mut().find(|slot| slot.is_none()) {
+                    *slot = Some(usage);
+                }
+            }
+        }
+
+        let pressed = buttons.buttons() | self.pointer_buttons;
+        for button in 1..=8 {
+            if pressed & (1 << (button - 1)) != 0 {
+                mouse.press(button);
+            } else {
+                mouse.release(button);
+            }
+        }
+
+        if self.keyboard.update(keyboard).is_some() {
+            self.pending |= KEYBOARD;
+        }
+        if self.nkro.update(nkro).is_some() {
+            self.pending |= NKRO;
+        }
+        if self.consumer.update(consumer).is_some() {
+            self.pending |= CONSUMER;
+        }
+        // A one-shot report waiting to be sent must not be overwritten by
+        // the next one.
+        if self.pending & SYSTEM == 0 {
+            if let Some(report) = self.system.update(system.into_iter().flatten()) {
+                self.system_report = report;
+                self.pending |= SYSTEM;
+            }
+        }
+        if self.mouse.update(mouse).is_some() {
+            self.pending |= MOUSE;
+        }
+
+        fits
+    }
+
+    /// Queue a mouse report moving the pointer by `x` and `y` and the
+    /// wheel by `wheel`, along with the pressed buttons. Motion queued but
+    /// not yet sent is replaced.
+    pub fn move_mouse(&mut self, x: i8, y: i8, wheel: i8) {
+        let mut mouse = *self.mouse.last();
+        mouse.set_motion(x, y, wheel);
+
+        self.mouse.update(mouse);
+        self.pending |= MOUSE;
+    }
+
+    /// Queue a mouse report from a pointing device passed through, e.g. a
+    /// [`Ps2Mouse`](crate::ps2::Ps2Mouse): the device's `buttons`, in the
+    /// layout of [`MouseReport::buttons`], are pressed along with those of
+    /// mouse keys until its next report, and the pointer moves as in
+    /// [`CompositeReporter::move_mouse`].
+    pub fn pointer(&mut self, buttons: u8, x: i8, y: i8, wheel: i8) {
+        let mut mouse = *self.mouse.last();
+        for button in 1..=8 {
+            let bit = 1 << (button - 1);
+            if buttons & bit != 0 {
+                mouse.press(button);
+            } else if self.pointer_buttons & bit != 0 {
+                mouse.release(button);
+            }
+        }
+        self.pointer_buttons = buttons;
+
+        mouse.set_motion(x, y, wheel);
+        if self.mouse.update(mouse).is_some() || mouse.moves() {
+            self.pending |= MOUSE;
+        }
+    }
+
+    /// Serialize the next report to send into `buf`, prefixed with its
+    /// report ID, and return its length, or `None` if no report is
+    /// waiting or `buf` is shorter than [`Self::MAX_LEN`].
+    pub fn next_report(&mut self, buf: &mut [u8]) -> Option<usize> {
+        if self.pending == 0 || buf.len() < Self::MAX_LEN {
+            return None;
+        }
+
+        let next = 1 << self.pending.trailing_zeros();
+        self.pending &= !next;
+
+        let (id, report) = buf.split_at_mut(1);
+        let (report_id, len) = match next {
+            KEYBOARD => (KEYBOARD_REPORT_ID, self.keyboard.last().serialize(report)),
+            NKRO => (NKRO_REPORT_ID, self.nkro.last().serialize(report)),
+            CONSUMER => (CONSUMER_REPORT_ID, self.consumer.last().serialize(report)),
+            SYSTEM => (SYSTEM_REPORT_ID, self.system_report.serialize(report)),
+            _ => {
+                let len = self.mouse.last().serialize(report);
+
+                // Motion is relative, so it is sent only once.
+                let mut mouse = *self.mouse.last();
+                mouse.set_motion(0, 0, 0);
+                self.mouse = ChangeDetector::new(mouse);
+
+                (MOUSE_REPORT_ID, len)
+            }
+        };
+
+        id[0] = report_id;
+        len.map(|len| len + 1)
+    }
+
+    /// Handle an output report written by the host, prefixed with its
+    /// report ID.
+    pub fn output(&mut self, report: &[u8]) {
+        let Some((&id, data)) = report.split_first() else {
+            return;
+        };
+
+        if id == KEYBOARD_REPORT_ID || id == NKRO_REPORT_ID {
+            if let Some(leds) = LedState::from_report(data) {
+                self.leds_changed |= leds != self.leds;
+                self.leds = leds;
+            }
+        }
+    }
+}
+
+impl<const BYTES: usize, const N: usize> Default for CompositeReporter<BYTES, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+const fn max(a: usize, b: usize) -> usize {
+    if a > b {
+        a
+    } else {
+        b
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeyCode;
+    use std::vec::Vec;
+
+    fn reports<const BYTES: usize, const N: usize>(
+        reporter: &mut CompositeReporter<BYTES, N>,
+    ) -> Vec<Vec<u8>> {
+        let mut buf = [0; 32];
+        core::iter::from_fn(|| {
+            reporter
+                .next_report(&mut buf)
+                .map(|len| buf[..len].to_vec())
+        })
+        .collect()
+    }
+
+    #[test]
+    fn descriptor_report_ids() {
+        let descriptor = CompositeReporter::<16, 2>::DESCRIPTOR;
+        let ids: Vec<u8> = descriptor
+            .windows(4)
+            .filter(|item| item[..3] == [0xa1, 0x01, 0x85])
+            .map(|item| item[3])
+            .collect();
+
+        assert_eq!(ids, [1, 2, 3, 4, 5]);
+        assert_eq!(CompositeReporter::<16, 2>::MAX_LEN, 18);
+    }
+
+    #[test]
+    fn only_changed_reports() {
+        let mut reporter = CompositeReporter::<4, 2>::new();
+        let mute = Usage::new(Usage::CONSUMER_PAGE, 0xe2);
+        let sleep = Usage::new(Usage::GENERIC_DESKTOP_PAGE, 0x82);
+
+        assert!(reporter.update([Usage::from(KeyCode::KA), mute, sleep]));
+        assert_eq!(
+            reports(&mut reporter),
+            [
+                [1, 0, 0, 4, 0, 0, 0, 0, 0].to_vec(),
+                [2, 0xe2, 0, 0, 0].to_vec(),
+                [3, 0x82].to_vec(),
+            ]
+        );
+
+        assert!(reporter.update([Usage::from(KeyCode::KA), mute, sleep]));
+        assert_eq!(reports(&mut reporter), [[3, 0].to_vec()]);
+        assert!(reporter.update([Usage::from(KeyCode::KA), mute, sleep]));
+        assert!(!reporter.pending());
+
+        reporter.set_nkro(true);
+        assert!(reporter.update([Usage::from(KeyCode::KA)]));
+        assert_eq!(
+            reports(&mut reporter),
+            [
+                [1, 0, 0, 0, 0, 0, 0, 0, 0].to_vec(),
+                [5, 0, 0x10, 0, 0, 0].to_vec(),
+                [2, 0, 0, 0, 0].to_vec(),
+            ]
+        );
+    }
+
+    #[test]
+    fn mouse_motion_sent_once() {
+        let mut reporter = CompositeReporter::<4, 1>::new();
+        let button = Usage::new(Usage::BUTTON_PAGE, 2);
+
+        reporter.move_mouse(5, -128, 0);
+        assert!(reporter.update([button]));
+        assert_eq!(reports(&mut reporter), [[4, 0x02, 5, 0x81, 0].to_vec()]);
+
+        assert!(reporter.update([button]));
+        assert!(!reporter.pending());
+        assert!(reporter.update([]));
+        assert_eq!(reports(&mut reporter), [[4, 0, 0, 0, 0].to_vec()]);
+
+        // Pointing stick buttons combine with mouse keys.
+        reporter.pointer(0x01, 0, 3, 0);
+        assert_eq!(reports(&mut reporter), [[4, 0x01, 0, 3, 0].to_vec()]);
+        assert!(reporter.update([button]));
+        assert_eq!(reports(&mut reporter), [[4, 0x03, 0, 0, 0].to_vec()]);
+        reporter.pointer(0x00, 0, 0, 0);
+        assert_eq!(reports(&mut reporter), [[4, 0x02, 0, 0, 0].to_vec()]);
+        reporter.pointer(0x00, 0, 0, 0);
+        assert!(!reporter.pending());
+        assert!(reporter.update([]));
+        reports(&mut reporter);
+
+        reporter.output(&[NKRO_REPORT_ID, LedState::CAPS_LOCK]);
+        assert_eq!(
+            reporter.take_leds(),
+            Some(LedState::from_bits(LedState::CAPS_LOCK))
+        );
+        assert_eq!(reporter.take_leds(), None);
+    }
+}
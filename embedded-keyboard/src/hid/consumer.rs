@@ -0,0 +1,169 @@
+use crate::Usage;
+
+/// Consumer control report, for media and application launch keys.
+///
+/// Carries up to `N` usages of the Consumer page, which is how hosts
+/// expect volume, playback and similar keys. On the wire the report is
+/// laid out as:
+///
+/// | Byte     | Contents                             |
+/// |----------|--------------------------------------|
+/// | 0..2 * N | Consumer usages, little endian, or 0 |
+///
+/// Usages pressed beyond the `N`th are dropped. Like the keyboard
+/// reports, the report is meant to be passed through a
+/// [`ChangeDetector`](super::ChangeDetector), so that it is only sent
+/// when the pressed usages change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ConsumerReport<const N: usize> {
+    usages: [u16; N],
+}
+
+impl<const N: usize> ConsumerReport<N> {
+    /// Length of the serialized report in bytes.
+    pub const LEN: usize = 2 * N;
+
+    /// HID report descriptor matching this report.
+    pub const DESCRIPTOR: [u8; 23] = Self::descriptor();
+
+    /// Create an empty report.
+    pub const fn new() -> Self {
+        Self { usages: [0; N] }
+    }
+
+    /// Add pressed usages to the report, e.g. from
+    /// [`Engine::usages`](crate::engine::Engine::usages). Usages on other
+    /// pages than the Consumer page are skipped.
+    ///
+    /// Returns `false` if the report is full and some usages could not be
+    /// added.
+    pub fn extend(&mut self, usages: impl IntoIterator<Item = Usage>) -> bool {
+        let mut fits = true;
+
+        for usage in usages {
+            if usage.page() == Usage::CONSUMER_PAGE {
+                fits &= self.press(usage.id());
+            }
+        }
+
+        fits
+    }
+
+    /// Add a pressed usage to the report.
+    ///
+    /// Returns `false` if the report is full and the usage could not be
+    /// added.
+    pub fn press(&mut self, usage: u16) -> bool {
+        if usage == 0 || self.usages.contains(&usage) {
+            return true;
+        }
+
+        match self.usages.iter_mut().find(|u| **u == 0) {
+            Some(slot) => {
+                *slot = usage;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove a usage from the report.
+    pub fn release(&mut self, usage: u16) {
+        if let Some(slot) = self.usages.iter_mut().find(|u| **u == usage) {
+            *slot = 0;
+        }
+    }
+
+    /// Remove every usage from the report.
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Pressed usages, in slot order.
+    pub fn usages(&self) -> impl Iterator<Item = u16> + '_ {
+        self.usages.iter().copied().filter(|u| *u != 0)
+    }
+
+    /// Serialize the report into `buf`, returning the number of bytes
+    /// written, or `None` if `buf` is shorter than [`Self::LEN`].
+    pub fn serialize(&self, buf: &mut [u8]) -> Option<usize> {
+        let buf = buf.get_mut(..Self::LEN)?;
+
+        for (bytes, usage) in buf.chunks_exact_mut(2).zip(self.usages.iter()) {
+            bytes.copy_from_slice(&usage.to_le_bytes());
+        }
+
+        Some(Self::LEN)
+    }
+
+    const fn descriptor() -> [u8; 23] {
+        let count = const {
+            assert!(N > 0 && N <= u8::MAX as usize);
+            N as u8
+        };
+
+        [
+            0x05, 0x0c, //       Usage Page (Consumer)
+            0x09, 0x01, //       Usage (Consumer Control)
+            0xa1, 0x01, //       Collection (Application)
+            0x15, 0x00, //         Logical Minimum (0)
+            0x26, 0xff, 0x03, //   Logical Maximum (0x3ff)
+            0x19, 0x00, //         Usage Minimum (0)
+            0x2a, 0xff, 0x03, //   Usage Maximum (0x3ff)
+            0x75, 0x10, //         Report Size (16)
+            0x95, count, //        Report Count (N)
+            0x81, 0x00, //         Input (Data, Array, Absolute)
+            0xc0, //             End Collection
+        ]
+    }
+}
+
+impl<const N: usize> Default for ConsumerReport<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hid::ChangeDetector;
+    use crate::KeyCode;
+
+    #[test]
+    fn single_consumer_usage() {
+        let mut report = ConsumerReport::<1>::new();
+        let volume_up = Usage::new(Usage::CONSUMER_PAGE, 0xe9);
+        let mute = Usage::new(Usage::CONSUMER_PAGE, 0xe2);
+
+        assert!(report.extend([Usage::from(KeyCode::KA), volume_up]));
+        assert!(report.usages().eq([0xe9]));
+        assert!(!report.extend([mute]));
+
+        let mut buf = [0; 2];
+        assert_eq!(report.serialize(&mut buf), Some(2));
+        assert_eq!(buf, [0xe9, 0x00]);
+
+        report.release(0xe9);
+        assert_eq!(report.usages().next(), None);
+    }
+
+    #[test]
+    fn usage_array() {
+        let mut detector = ChangeDetector::new(ConsumerReport::<3>::new());
+        let mut report = ConsumerReport::<3>::new();
+
+        assert!(report.extend([
+            Usage::new(Usage::CONSUMER_PAGE, 0xcd),
+            Usage::new(Usage::CONSUMER_PAGE, 0x0192),
+        ]));
+        assert_eq!(detector.update(report), Some(report));
+        assert_eq!(detector.update(report), None);
+
+        let mut buf = [0xaa; 7];
+        assert_eq!(report.serialize(&mut buf), Some(6));
+        assert_eq!(buf, [0xcd, 0x00, 0x92, 0x01, 0x00, 0x00, 0xaa]);
+        assert_eq!(ConsumerReport::<3>::DESCRIPTOR[19], 3);
+    }
+}
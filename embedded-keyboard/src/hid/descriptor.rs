@@ -0,0 +1,104 @@
+/// Report ID item, inserted into a descriptor by
+/// [`DescriptorBuilder::collection`].
+const REPORT_ID: u8 = 0x85;
+
+/// Collection item, without the size bits.
+const COLLECTION: u8 = 0xa0;
+
+/// Const builder concatenating report descriptors into the descriptor of
+/// a composite device, e.g. a keyboard with consumer controls.
+///
+/// The length of the descriptor is checked at compile time, so that it
+/// is always built from the report descriptors it was declared with:
+///
+/// ```
+/// use embedded_keyboard::hid::{ConsumerReport, DescriptorBuilder, NkroKeyboardReport};
+///
+/// type Keyboard = NkroKeyboardReport<16>;
+///
+/// const LEN: usize = DescriptorBuilder::<0>::collection_len(Some(1), &Keyboard::DESCRIPTOR)
+///     + DescriptorBuilder::<0>::collection_len(Some(2), &ConsumerReport::<4>::DESCRIPTOR);
+///
+/// const DESCRIPTOR: [u8; LEN] = DescriptorBuilder::new()
+///     .collection(Some(1), &Keyboard::DESCRIPTOR)
+///     .collection(Some(2), &ConsumerReport::<4>::DESCRIPTOR)
+///     .build();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DescriptorBuilder<const LEN: usize> {
+    bytes: [u8; LEN],
+    len: usize,
+}
+
+impl<const LEN: usize> DescriptorBuilder<LEN> {
+    /// Create an empty descriptor.
+    pub const fn new() -> Self {
+        Self {
+            bytes: [0; LEN],
+            len: 0,
+        }
+    }
+
+    /// Length `descriptor` takes up in a composite descriptor, with
+    /// `report_id`.
+    pub const fn collection_len(report_id: Option<u8>, descriptor: &[u8]) -> usize {
+        match report_id {
+            Some(_) => descriptor.len() + 2,
+            None => descriptor.len(),
+        }
+    }
+
+    /// Append the report descriptor `descriptor`, declaring its reports
+    /// with `report_id` right after it opens its first collection.
+    /// Report IDs are required for every report as soon as a device has
+    /// more than one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the descriptor does not fit.
+    #[must_use]
+    pub const fn collection(mut self, report_id: Option<u8>, descriptor: &[u8]) -> Self {
+        let mut id = report_id;
+        let mut i = 0;
+
+        while i < descriptor.len() {
+            let prefix = descriptor[i];
+            let size = match prefix & 0x03 {
+                3 => 4,
+                size => size as usize,
+            };
+
+            let end = i + 1 + size;
+            while i < end && i < descriptor.len() {
+                self.bytes[self.len] = descriptor[i];
+                self.len += 1;
+                i += 1;
+            }
+
+            if let (COLLECTION, Some(report_id)) = (prefix & 0xfc, id) {
+                self.bytes[self.len] = REPORT_ID;
+                self.bytes[self.len + 1] = report_id;
+                self.len += 2;
+                id = None;
+            }
+        }
+
+        self
+    }
+
+    /// The descriptor.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the descriptor is shorter than `LEN`.
+    pub const fn build(self) -> [u8; LEN] {
+        assert!(self.len == LEN, "report descriptor length mismatch");
+        self.bytes
+    }
+}
+
+impl<const LEN: usize> Default for DescriptorBuilder<LEN> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
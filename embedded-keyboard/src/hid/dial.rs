@@ -0,0 +1,236 @@
+use crate::encoder::Direction;
+
+/// Relative consumer controls of a dial, for jog wheels and volume dials.
+///
+/// Rather than usages pressed and released like
+/// [`ConsumerReport`](super::ConsumerReport), the report carries how far
+/// the AC Pan and Volume linear controls moved since the last report. On
+/// the wire the report is laid out as:
+///
+/// | Byte | Contents                 |
+/// |------|--------------------------|
+/// | 0    | AC Pan, signed, relative |
+/// | 1    | Volume, signed, relative |
+///
+/// Like [`MouseReport`](super::MouseReport) motion, the movement is sent
+/// once per report, so reports are sent as returned by [`Dial::report`]
+/// rather than through a [`ChangeDetector`](super::ChangeDetector).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DialReport {
+    pan: i8,
+    volume: i8,
+}
+
+impl DialReport {
+    /// Length of the serialized report in bytes.
+    pub const LEN: usize = 2;
+
+    /// HID report descriptor matching this report.
+    pub const DESCRIPTOR: [u8; 22] = [
+        0x05, 0x0c, //       Usage Page (Consumer)
+        0x09, 0x01, //       Usage (Consumer Control)
+        0xa1, 0x01, //       Collection (Application)
+        0x0a, 0x38, 0x02, //   Usage (AC Pan)
+        0x09, 0xe0, //         Usage (Volume)
+        0x15, 0x81, //         Logical Minimum (-127)
+        0x25, 0x7f, //         Logical Maximum (127)
+        0x75, 0x08, //         Report Size (8)
+        0x95, 0x02, //         Report Count (2)
+        0x81, 0x06, //         Input (Data, Variable, Relative)
+        0xc0, //             End Collection
+    ];
+
+    /// Create a report without movement.
+    pub const fn new() -> Self {
+        Self { pan: 0, volume: 0 }
+    }
+
+    /// Movement of `control` since the last report.
+    pub const fn get(&self, control: DialControl) -> i8 {
+        match control {
+            DialControl::Pan => self.pan,
+            DialControl::Volume => self.volume,
+        }
+    }
+
+    /// Set the movement of `control`. Movement of -128 is reported as
+    /// -127.
+    pub fn set(&mut self, control: DialControl, value: i8) {
+        let value = value.max(-127);
+
+        match control {
+            DialControl::Pan => self.pan = value,
+            DialControl::Volume => self.volume = value,
+        }
+    }
+
+    /// Serialize the report into `buf`, returning the number of bytes
+    /// written, or `None` if `buf` is shorter than [`Self::LEN`].
+    pub fn serialize(&self, buf: &mut [u8]) -> Option<usize> {
+        buf.get_mut(..Self::LEN)?
+            .copy_from_slice(&[self.pan.to_le_bytes()[0], self.volume.to_le_bytes()[0]]);
+
+        Some(Self::LEN)
+    }
+}
+
+/// Linear control of a [`DialReport`] moved by a [`Dial`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DialControl {
+    /// AC Pan, scrolling sideways
+    Pan,
+    /// Volume
+    Volume,
+}
+
+/// Acceleration of a [`Dial`] turned quickly.
+///
+/// Each step following the previous one within `interval` milliseconds
+/// moves the control by one more than the previous step, up to `max`;
+/// a slower step moves it by one again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Acceleration {
+    /// Time between steps, in milliseconds, under which they accelerate
+    pub interval: u16,
+    /// Most a single step moves the control
+    pub max: u8,
+}
+
+/// High-resolution dial, like a jog wheel or a volume dial, moving a
+/// consumer linear control.
+///
+/// Steps of the encoder, e.g. from a driver decoding it, are fed to
+/// [`Dial::step`] and accumulate until the next [`Dial::report`].
+/// Clockwise steps move the control up, or to the right for AC Pan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Dial {
+    control: DialControl,
+    acceleration: Option<Acceleration>,
+    speed: u8,
+    last: Option<u32>,
+    pending: i16,
+}
+
+impl Dial {
+    /// Create a dial moving `control`, without acceleration.
+    pub const fn new(control: DialControl) -> Self {
+        Self {
+            control,
+            acceleration: None,
+            speed: 1,
+            last: None,
+            pending: 0,
+        }
+    }
+
+    /// Control moved by the dial.
+    pub const fn control(&self) -> DialControl {
+        self.control
+    }
+
+    /// Enable or disable acceleration.
+    pub fn set_acceleration(&mut self, acceleration: Option<Acceleration>) {
+        self.acceleration = acceleration;
+        self.speed = 1;
+    }
+
+    /// Record a step at time `now`, in milliseconds.
+    pub fn step(&mut self, direction: Direction, now: u32) {
+        let quick = match (self.acceleration, self.last) {
+            (Some(acceleration), Some(last)) => {
+                now.wrapping_sub(last) < u32::from(acceleration.interval)
+            }
+            _ => false,
+        };
+
+        self.speed = match self.acceleration {
+            Some(acceleration) if quick => {
+                self.speed.saturating_add(1).min(acceleration.max.max(1))
+            }
+            _ => 1,
+        };
+        self.last = Some(now);
+
+        let amount = i16::from(self.speed);
+        self.pending = match direction {
+            Direction::Clockwise => self.pending.saturating_add(amount),
+            Direction::CounterClockwise => self.pending.saturating_sub(amount),
+        };
+    }
+
+    /// Whether movement is waiting to be reported.
+    pub const fn pending(&self) -> bool {
+        self.pending != 0
+    }
+
+    /// Report of the movement since the last one, if any. Movement beyond
+    /// the range of a report is kept for the next ones.
+    pub fn report(&mut self) -> Option<DialReport> {
+        if self.pending == 0 {
+            return None;
+        }
+
+        let value = self.pending.clamp(-127, 127);
+        self.pending -= value;
+
+        let mut report = DialReport::new();
+        report.set(self.control, value as i8);
+        Some(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn incremental_reports() {
+        let mut dial = Dial::new(DialControl::Volume);
+        assert_eq!(dial.report(), None);
+
+        dial.step(Direction::Clockwise, 0);
+        dial.step(Direction::Clockwise, 1);
+        dial.step(Direction::CounterClockwise, 2);
+        let report = dial.report().unwrap();
+        assert_eq!(report.get(DialControl::Volume), 1);
+        assert_eq!(report.get(DialControl::Pan), 0);
+        assert!(!dial.pending());
+
+        let mut buf = [0; 2];
+        dial.step(Direction::CounterClockwise, 3);
+        assert_eq!(dial.report().unwrap().serialize(&mut buf), Some(2));
+        assert_eq!(buf, [0x00, 0xff]);
+    }
+
+    #[test]
+    fn acceleration() {
+        let mut dial = Dial::new(DialControl::Pan);
+        dial.set_acceleration(Some(Acceleration {
+            interval: 20,
+            max: 3,
+        }));
+
+        for now in [0, 10, 20, 30] {
+            dial.step(Direction::Clockwise, now);
+        }
+        // 1, 2, 3 and 3 again.
+        assert_eq!(dial.report().unwrap().get(DialControl::Pan), 9);
+
+        dial.step(Direction::Clockwise, 100);
+        assert_eq!(dial.report().unwrap().get(DialControl::Pan), 1);
+
+        for now in 0..100 {
+            dial.step(Direction::CounterClockwise, 200 + now);
+        }
+        assert_eq!(dial.report().unwrap().get(DialControl::Pan), -127);
+        assert_eq!(dial.report().unwrap().get(DialControl::Pan), -127);
+        assert_eq!(dial.report().unwrap().get(DialControl::Pan), -43);
+        assert_eq!(dial.report(), None);
+    }
+}
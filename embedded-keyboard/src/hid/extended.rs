@@ -0,0 +1,279 @@
+use super::rollover::{press_slot, release_slot};
+use super::{modifier_bit, RolloverPolicy};
+use crate::{KeyCode, Usage};
+
+/// Keyboard report carrying full 16-bit usages.
+///
+/// The boot keyboard report only has room for 8-bit usages, which cuts
+/// off vendor-defined usages and anything else beyond `0xff` on the
+/// Keyboard/Keypad page. This report keeps the modifier byte of the boot
+/// format, followed by up to `N` 16-bit usages.
+///
+/// On the wire the report is laid out as:
+///
+/// | Byte          | Contents                        |
+/// |---------------|---------------------------------|
+/// | 0             | Modifier bitmap (`0xe0..=0xe7`) |
+/// | 1             | Reserved                        |
+/// | 2..2 + 2 * N  | Usages, little endian           |
+///
+/// Keys pressed beyond the `N` slots are dropped, unless another
+/// [`RolloverPolicy`] is chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ExtendedKeyboardReport<const N: usize> {
+    modifiers: u8,
+    usages: [u16; N],
+    rolled_over: bool,
+    policy: RolloverPolicy,
+}
+
+impl<const N: usize> ExtendedKeyboardReport<N> {
+    /// Length of the serialized report in bytes.
+    pub const LEN: usize = 2 + 2 * N;
+
+    /// HID report descriptor matching this report.
+    pub const DESCRIPTOR: [u8; 47] = Self::descriptor();
+
+    /// Create an empty report, dropping keys beyond its capacity with
+    /// [`RolloverPolicy::KeepOldest`].
+    pub const fn new() -> Self {
+        Self::with_rollover(RolloverPolicy::KeepOldest)
+    }
+
+    /// Create an empty report handling more than `N` keys with `policy`.
+    pub const fn with_rollover(policy: RolloverPolicy) -> Self {
+        Self {
+            modifiers: 0,
+            usages: [0; N],
+            rolled_over: false,
+            policy,
+        }
+    }
+
+    /// How more than `N` keys are handled.
+    pub const fn rollover(&self) -> RolloverPolicy {
+        self.policy
+    }
+
+    /// Create a report from a set of pressed usages. Usages beyond the
+    /// capacity of the report are dropped.
+    pub fn from_usages(usages: impl IntoIterator<Item = u16>) -> Self {
+        let mut report = Self::new();
+
+        for usage in usages {
+            report.press(usage);
+        }
+
+        report
+    }
+
+    /// Add pressed usages to the report, e.g. from
+    /// [`Engine::usages`](crate::engine::Engine::usages). Usages on other
+    /// pages than the Keyboard/Keypad page are skipped.
+    ///
+    /// Returns `false` if the report is full and some usages could not be
+    /// added.
+    pub fn extend(&mut self, usages: impl IntoIterator<Item = Usage>) -> bool {
+        let mut fits = true;
+
+        for usage in usages.into_iter().filter_map(|usage| usage.keyboard_id()) {
+            fits &= self.press(usage);
+        }
+
+        fits
+    }
+
+    /// Add a pressed usage to the report.
+    ///
+    /// Returns `false` if the report is full, in which case it follows its
+    /// [`RolloverPolicy`].
+    pub fn press(&mut self, usage: u16) -> bool {
+        if let Some(bit) = modifier_bit(usage) {
+            self.modifiers |= bit;
+            return true;
+        }
+
+        if usage == 0 || self.usages.contains(&usage) {
+            return true;
+        }
+
+        let fits = press_slot(&mut self.usages, usage, self.policy);
+        if !fits && self.policy == RolloverPolicy::ErrorRollOver {
+            self.rolled_over = true;
+        }
+
+        fits
+    }
+
+    /// Remove a usage from the report.
+    pub fn release(&mut self, usage: u16) {
+        if let Some(bit) = modifier_bit(usage) {
+            self.modifiers &= !bit;
+            return;
+        }
+
+        release_slot(&mut self.usages, usage, self.policy);
+    }
+
+    /// Remove every usage from the report.
+    pub fn clear(&mut self) {
+        *self = Self::with_rollover(self.policy);
+    }
+
+    /// Modifier bitmap.
+    pub const fn modifiers(&self) -> u8 {
+        self.modifiers
+    }
+
+    /// Whether more keys were pressed than the report can carry, with
+    /// [`RolloverPolicy::ErrorRollOver`].
+    pub const fn is_rolled_over(&self) -> bool {
+        self.rolled_over
+    }
+
+    /// Pressed non-modifier usages, in slot order, or `ErrorRollOver` in
+    /// every slot if the report rolled over.
+    pub fn usages(&self) -> impl Iterator<Item = u16> + '_ {
+        self.slots().into_iter().filter(|u| *u != 0)
+    }
+
+    /// Serialize the report into `buf`, returning the number of bytes
+    /// written, or `None` if `buf` is shorter than [`Self::LEN`].
+    pub fn serialize(&self, buf: &mut [u8]) -> Option<usize> {
+        let buf = buf.get_mut(..Self::LEN)?;
+        let (header, usages) = buf.split_at_mut(2);
+
+        header[0] = self.modifiers;
+        header[1] = 0;
+
+        for (bytes, usage) in usages.chunks_exact_mut(2).zip(self.slots()) {
+            bytes.copy_from_slice(&usage.to_le_bytes());
+        }
+
+        Some(Self::LEN)
+    }
+
+    /// Usage slots as sent on the wire.
+    fn slots(&self) -> [u16; N] {
+        if self.rolled_over {
+            [KeyCode::ErrorRollOver as u16; N]
+        } else {
+            self.usages
+        }
+    }
+
+    const fn descriptor() -> [u8; 47] {
+        let count = const {
+            assert!(N > 0 && N <= u8::MAX as usize);
+            N as u8
+        };
+
+        [
+            0x05, 0x01, //       Usage Page (Generic Desktop)
+            0x09, 0x06, //       Usage (Keyboard)
+            0xa1, 0x01, //       Collection (Application)
+            0x05, 0x07, //         Usage Page (Keyboard/Keypad)
+            0x19, 0xe0, //         Usage Minimum (Left Control)
+            0x29, 0xe7, //         Usage Maximum (Right GUI)
+            0x15, 0x00, //         Logical Minimum (0)
+            0x25, 0x01, //         Logical Maximum (1)
+            0x75, 0x01, //         Report Size (1)
+            0x95, 0x08, //         Report Count (8)
+            0x81, 0x02, //         Input (Data, Variable, Absolute)
+            0x75, 0x08, //         Report Size (8)
+            0x95, 0x01, //         Report Count (1)
+            0x81, 0x01, //         Input (Constant)
+            0x19, 0x00, //         Usage Minimum (0)
+            0x2a, 0xff, 0xff, //   Usage Maximum (0xffff)
+            0x15, 0x00, //         Logical Minimum (0)
+            0x27, 0xff, 0xff, 0x00, 0x00, // Logical Maximum (0xffff)
+            0x75, 0x10, //         Report Size (16)
+            0x95, count, //        Report Count (N)
+            0x81, 0x00, //         Input (Data, Array, Absolute)
+            0xc0, //             End Collection
+        ]
+    }
+}
+
+impl<const N: usize> Default for ExtendedKeyboardReport<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn press_and_release() {
+        let mut report = ExtendedKeyboardReport::<3>::new();
+
+        assert!(report.press(KeyCode::KA.into()));
+        assert!(report.press(KeyCode::KpLeftShift.into()));
+        assert!(report.press(0x0100));
+        assert!(report.press(KeyCode::KF24.into()));
+        assert!(!report.press(KeyCode::KB.into()));
+        assert!(report.press(KeyCode::KA.into()));
+
+        assert_eq!(report.modifiers(), 0x02);
+        assert!(report.usages().eq([0x0004, 0x0100, 0x0073]));
+
+        report.release(0x0100);
+        report.release(KeyCode::KpLeftShift.into());
+        assert_eq!(report.modifiers(), 0x00);
+        assert!(report.usages().eq([0x0004, 0x0073]));
+        assert!(report.press(KeyCode::KB.into()));
+        assert!(report.usages().eq([0x0004, 0x0005, 0x0073]));
+    }
+
+    #[test]
+    fn extend_with_usages() {
+        let mut report = ExtendedKeyboardReport::<2>::new();
+
+        assert!(report.extend([
+            Usage::from(KeyCode::KpLeftGUI),
+            Usage::keyboard(0x66),
+            Usage::new(Usage::CONSUMER_PAGE, 0xe9),
+        ]));
+        assert_eq!(report.modifiers(), 0x08);
+        assert!(report.usages().eq([0x0066]));
+        assert!(!report.extend([Usage::keyboard(0x04), Usage::keyboard(0x05)]));
+    }
+
+    #[test]
+    fn error_roll_over() {
+        let mut report = ExtendedKeyboardReport::<2>::with_rollover(RolloverPolicy::ErrorRollOver);
+
+        assert!(!report.extend([0x04, 0x05, 0x06].map(Usage::keyboard)));
+        assert!(report.is_rolled_over());
+        assert!(report.usages().eq([0x0001; 2]));
+
+        let mut buf = [0xaa; 6];
+        report.serialize(&mut buf);
+        assert_eq!(buf, [0x00, 0x00, 0x01, 0x00, 0x01, 0x00]);
+
+        report.clear();
+        assert!(!report.is_rolled_over());
+    }
+
+    #[test]
+    fn serialize_report() {
+        let report = ExtendedKeyboardReport::<2>::from_usages([0x00e1, 0x1234, 0x0004]);
+        let mut buf = [0xaa; 8];
+
+        assert_eq!(report.serialize(&mut buf), Some(6));
+        assert_eq!(buf, [0x02, 0x00, 0x34, 0x12, 0x04, 0x00, 0xaa, 0xaa]);
+        assert_eq!(report.serialize(&mut buf[..5]), None);
+    }
+
+    #[test]
+    fn descriptor_report_count() {
+        let descriptor = ExtendedKeyboardReport::<10>::DESCRIPTOR;
+        assert_eq!(
+            descriptor[descriptor.len() - 5..],
+            [0x95, 10, 0x81, 0x00, 0xc0]
+        );
+    }
+}
@@ -0,0 +1,382 @@
+use super::{BootKeyboardReport, ChangeDetector, LedState};
+
+/// Power state the host put a [`HidI2cKeyboard`] in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PowerState {
+    /// Reporting keys
+    #[default]
+    On,
+    /// Not reporting keys until woken up by the host
+    Sleep,
+}
+
+/// Kind of transaction the I²C host started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum I2cTransaction {
+    /// The host writes to the device
+    Write,
+    /// The host reads from the device
+    Read,
+}
+
+/// I²C peripheral operating as a target, i.e. addressed by a host.
+pub trait I2cTarget {
+    /// Error type
+    type Error;
+
+    /// Wait for the host to address the device.
+    fn listen(&mut self) -> Result<I2cTransaction, Self::Error>;
+
+    /// Receive the bytes of a write transaction into `buf`, returning how
+    /// many the host wrote.
+    fn receive(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error>;
+
+    /// Send `data` in response to a read transaction.
+    fn respond(&mut self, data: &[u8]) -> Result<(), Self::Error>;
+}
+
+/// Boot protocol keyboard speaking the HID-over-I²C protocol.
+///
+/// This is how keyboards attached to an embedded controller usually talk
+/// to the SoC of a laptop. The host reads the HID descriptor and the
+/// report descriptor from their registers, sends commands like reset and
+/// set power to the command register, and reads input reports from the
+/// input register whenever the device asserts its interrupt line.
+///
+/// The application feeds every report built from the scanner's events to
+/// [`HidI2cKeyboard::report`], drives the interrupt line (active low)
+/// from [`HidI2cKeyboard::interrupt`], and passes every transaction to
+/// [`HidI2cKeyboard::serve`], or to [`HidI2cKeyboard::write`] and
+/// [`HidI2cKeyboard::read`] if its I²C peripheral is interrupt driven.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct HidI2cKeyboard {
+    vendor_id: u16,
+    product_id: u16,
+    version: u16,
+    report: ChangeDetector<BootKeyboardReport>,
+    pending: bool,
+    reset: bool,
+    register: Option<u16>,
+    power: PowerState,
+    leds: LedState,
+    leds_changed: bool,
+}
+
+impl HidI2cKeyboard {
+    /// Register the HID descriptor is read from, to be declared to the
+    /// host in ACPI or the device tree.
+    pub const HID_DESCRIPTOR_REGISTER: u16 = 0x0001;
+    /// Register the report descriptor is read from.
+    pub const REPORT_DESCRIPTOR_REGISTER: u16 = 0x0002;
+    /// Register input reports are read from.
+    pub const INPUT_REGISTER: u16 = 0x0003;
+    /// Register output reports are written to.
+    pub const OUTPUT_REGISTER: u16 = 0x0004;
+    /// Register commands are written to.
+    pub const COMMAND_REGISTER: u16 = 0x0005;
+    /// Register carrying the data of commands.
+    pub const DATA_REGISTER: u16 = 0x0006;
+
+    /// Length of the HID descriptor in bytes.
+    pub const HID_DESCRIPTOR_LEN: usize = 30;
+
+    const INPUT_LEN: usize = 2 + BootKeyboardReport::LEN;
+    const OUTPUT_LEN: usize = 2 + 1;
+    const RESPONSE_LEN: usize = BootKeyboardReport::DESCRIPTOR.len();
+
+    const RESET: u8 = 0x01;
+    const GET_REPORT: u8 = 0x02;
+    const SET_REPORT: u8 = 0x03;
+    const SET_POWER: u8 = 0x08;
+
+    const INPUT_REPORT: u8 = 0x01;
+    const OUTPUT_REPORT: u8 = 0x02;
+
+    /// Create a keyboard identifying itself with the given USB style IDs.
+    pub const fn new(vendor_id: u16, product_id: u16, version: u16) -> Self {
+        Self {
+            vendor_id,
+            product_id,
+            version,
+            report: ChangeDetector::new(BootKeyboardReport::new()),
+            pending: false,
+            reset: false,
+            register: None,
+            power: PowerState::On,
+            leds: LedState::from_bits(0),
+            leds_changed: false,
+        }
+    }
+
+    /// HID descriptor, as read by the host.
+    pub fn hid_descriptor(&self) -> [u8; Self::HID_DESCRIPTOR_LEN] {
+        let fields = [
+            Self::HID_DESCRIPTOR_LEN as u16,
+            0x0100,
+            BootKeyboardReport::DESCRIPTOR.len() as u16,
+            Self::REPORT_DESCRIPTOR_REGISTER,
+            Self::INPUT_REGISTER,
+            Self::INPUT_LEN as u16,
+            Self::OUTPUT_REGISTER,
+            Self::OUTPUT_LEN as u16,
+            Self::COMMAND_REGISTER,
+            Self::DATA_REGISTER,
+            self.vendor_id,
+            self.product_id,
+            self.version,
+        ];
+
+        let mut descriptor = [0; Self::HID_DESCRIPTOR_LEN];
+        for (bytes, field) in descriptor.chunks_exact_mut(2).zip(fields) {
+            bytes.copy_from_slice(&field.to_le_bytes());
+        }
+
+        descriptor
+    }
+
+    /// Power state set by the host.
+    pub const fn power(&self) -> PowerState {
+        self.power
+    }
+
+    /// LED state set by the host.
+    pub const fn leds(&self) -> LedState {
+        self.leds
+    }
+
+    /// LED state set by the host, if it changed since the last call.
+    /// Meant for driving indicators through
+    /// [`KeyboardLeds`](crate::KeyboardLeds).
+    pub fn take_leds(&mut self) -> Option<LedState> {
+        core::mem::take(&mut self.leds_changed).then_some(self.leds)
+    }
+
+    /// Whether the interrupt line should be asserted, because an input
+    /// report or the completion of a reset is waiting to be read.
+    pub fn interrupt(&self) -> bool {
+        self.reset || (self.pending && self.power == PowerState::On)
+    }
+
+    /// Update the pressed keys. The report is made available to the host
+    /// if it differs from the previous one.
+    pub fn report(&mut self, report: BootKeyboardReport) {
+        if self.report.update(report).is_some() {
+            trace!("i2c: keyboard report {}", report);
+            self.pending = true;
+        }
+    }
+
+    /// Make the current report available to the host again, e.g. on
+    /// resume from suspend.
+    pub fn force_report(&mut self) {
+        self.pending = true;
+    }
+
+    /// Serve a single transaction of the host.
+    pub fn serve<T: I2cTarget>(&mut self, target: &mut T) -> Result<(), T::Error> {
+        let mut buf = [0; Self::RESPONSE_LEN];
+
+        match target.listen()? {
+            I2cTransaction::Write => {
+                let len = target.receive(&mut buf)?;
+                self.write(&buf[..len.min(buf.len())]);
+            }
+            I2cTransaction::Read => {
+                let len = self.read(&mut buf);
+                target.respond(&buf[..len])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handle `data` written by the host: a register address, optionally
+    /// followed by a command or an output report.
+    pub fn write(&mut self, data: &[u8]) {
+        let Some((register, rest)) = split_u16(data) else {
+            return;
+        };
+
+        self.register = None;
+
+        match register {
+            Self::COMMAND_REGISTER => self.command(rest),
+            Self::OUTPUT_REGISTER => self.output(rest),
+            register => self.register = Some(register),
+        }
+    }
+
+    /// Fill `buf` with the response to a read by the host, returning the
+    /// number of bytes written.
+    pub fn read(&mut self, buf: &mut [u8]) -> usize {
+        match self.register.take() {
+            Some(Self::HID_DESCRIPTOR_REGISTER) => copy(buf, &self.hid_descriptor()),
+            Some(Self::REPORT_DESCRIPTOR_REGISTER) => copy(buf, &BootKeyboardReport::DESCRIPTOR),
+            Some(Self::DATA_REGISTER) => copy(buf, &self.input_report()),
+            // Anything else reads the input register.
+            _ if self.reset => {
+                self.reset = false;
+                copy(buf, &[0, 0])
+            }
+            _ if self.pending => {
+                self.pending = false;
+                copy(buf, &self.input_report())
+            }
+            _ => copy(buf, &[0, 0]),
+        }
+    }
+
+    fn command(&mut self, command: &[u8]) {
+        let [report, opcode, rest @ ..] = command else {
+            return;
+        };
+        let report_type = (report >> 4) & 0x03;
+
+        match opcode & 0x0f {
+            Self::RESET => {
+                *self = Self::new(self.vendor_id, self.product_id, self.version);
+                self.reset = true;
+            }
+            Self::GET_REPORT if report_type == Self::INPUT_REPORT => {
+                self.register = Some(Self::DATA_REGISTER);
+            }
+            Self::SET_REPORT if report_type == Self::OUTPUT_REPORT => {
+                // Skip the data register address.
+                if let Some(report) = rest.get(2..) {
+                    self.output(report);
+                }
+            }
+            Self::SET_POWER => {
+                self.power = if report & 0x03 == 0 {
+                    PowerState::On
+                } else {
+                    PowerState::Sleep
+                };
+            }
+            _ => {}
+        }
+    }
+
+    fn output(&mut self, data: &[u8]) {
+        if let Some(leds) = split_u16(data).and_then(|(_, report)| LedState::from_report(report)) {
+            self.leds_changed |= leds != self.leds;
+            self.leds = leds;
+        }
+    }
+
+    fn input_report(&self) -> [u8; Self::INPUT_LEN] {
+        let mut report = [0; Self::INPUT_LEN];
+        report[..2].copy_from_slice(&(Self::INPUT_LEN as u16).to_le_bytes());
+        self.report.last().serialize(&mut report[2..]);
+        report
+    }
+}
+
+/// Split a little endian `u16` off the front of `data`.
+fn split_u16(data: &[u8]) -> Option<(u16, &[u8])> {
+    match data {
+        [lo, hi, rest @ ..] => Some((u16::from_le_bytes([*lo, *hi]), rest)),
+        _ => None,
+    }
+}
+
+/// Copy as much of `data` as fits into `buf`.
+fn copy(buf: &mut [u8], data: &[u8]) -> usize {
+    let len = buf.len().min(data.len());
+    buf[..len].copy_from_slice(&data[..len]);
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_register(keyboard: &mut HidI2cKeyboard, register: u16, buf: &mut [u8]) -> usize {
+        keyboard.write(&register.to_le_bytes());
+        keyboard.read(buf)
+    }
+
+    #[test]
+    fn descriptors() {
+        let mut keyboard = HidI2cKeyboard::new(0x045e, 0x0001, 0x0100);
+        let mut buf = [0; 80];
+
+        let len = read_register(
+            &mut keyboard,
+            HidI2cKeyboard::HID_DESCRIPTOR_REGISTER,
+            &mut buf,
+        );
+        assert_eq!(len, 30);
+        assert_eq!(&buf[..6], &[30, 0, 0x00, 0x01, 65, 0]);
+        assert_eq!(&buf[20..24], &[0x5e, 0x04, 0x01, 0x00]);
+
+        let len = read_register(
+            &mut keyboard,
+            HidI2cKeyboard::REPORT_DESCRIPTOR_REGISTER,
+            &mut buf,
+        );
+        assert_eq!(&buf[..len], &BootKeyboardReport::DESCRIPTOR);
+    }
+
+    #[test]
+    fn reset_and_input_reports() {
+        let mut keyboard = HidI2cKeyboard::new(0x045e, 0x0001, 0x0100);
+        let mut buf = [0; 16];
+
+        keyboard.write(&[0x05, 0x00, 0x00, 0x01]);
+        assert!(keyboard.interrupt());
+        assert_eq!(keyboard.read(&mut buf), 2);
+        assert_eq!(&buf[..2], &[0, 0]);
+        assert!(!keyboard.interrupt());
+
+        keyboard.report(BootKeyboardReport::from_usages([0x04]));
+        assert!(keyboard.interrupt());
+        assert_eq!(keyboard.read(&mut buf), 10);
+        assert_eq!(&buf[..10], &[10, 0, 0, 0, 0x04, 0, 0, 0, 0, 0]);
+        keyboard.report(BootKeyboardReport::from_usages([0x04]));
+        assert!(!keyboard.interrupt());
+        keyboard.force_report();
+        assert!(keyboard.interrupt());
+        assert_eq!(keyboard.read(&mut buf), 10);
+
+        // Get Report of the input report, answered through the data register.
+        keyboard.write(&[0x05, 0x00, 0x10, 0x02, 0x06, 0x00]);
+        assert_eq!(keyboard.read(&mut buf), 10);
+        assert_eq!(buf[4], 0x04);
+
+        // Asleep, reports are held back until woken up.
+        keyboard.write(&[0x05, 0x00, 0x01, 0x08]);
+        assert_eq!(keyboard.power(), PowerState::Sleep);
+        keyboard.report(BootKeyboardReport::new());
+        assert!(!keyboard.interrupt());
+        keyboard.write(&[0x05, 0x00, 0x00, 0x08]);
+        assert!(keyboard.interrupt());
+    }
+
+    #[test]
+    fn led_output_reports() {
+        let mut keyboard = HidI2cKeyboard::new(0x045e, 0x0001, 0x0100);
+
+        keyboard.write(&[0x04, 0x00, 0x03, 0x00, LedState::CAPS_LOCK]);
+        assert!(keyboard.leds().caps_lock());
+        assert_eq!(keyboard.take_leds(), Some(keyboard.leds()));
+        assert_eq!(keyboard.take_leds(), None);
+
+        // Set Report of the output report through the command register.
+        keyboard.write(&[
+            0x05,
+            0x00,
+            0x20,
+            0x03,
+            0x06,
+            0x00,
+            0x03,
+            0x00,
+            LedState::NUM_LOCK,
+        ]);
+        assert!(keyboard.leds().num_lock() && !keyboard.leds().caps_lock());
+    }
+}
@@ -0,0 +1,73 @@
+/// Keyboard LED state, as set by the host in the LED output report.
+///
+/// The output report is a single byte with one bit per LED, in the order
+/// of the LED usage page: Num Lock first.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LedState(u8);
+
+impl LedState {
+    /// Num Lock bit.
+    pub const NUM_LOCK: u8 = 0x01;
+    /// Caps Lock bit.
+    pub const CAPS_LOCK: u8 = 0x02;
+    /// Scroll Lock bit.
+    pub const SCROLL_LOCK: u8 = 0x04;
+    /// Compose bit.
+    pub const COMPOSE: u8 = 0x08;
+    /// Kana bit.
+    pub const KANA: u8 = 0x10;
+
+    /// Create LED state from the bits of the output report.
+    pub const fn from_bits(bits: u8) -> Self {
+        Self(bits)
+    }
+
+    /// Parse an LED output report, or `None` if `report` is empty.
+    pub fn from_report(report: &[u8]) -> Option<Self> {
+        report.first().map(|bits| Self(*bits))
+    }
+
+    /// Bits of the output report.
+    pub const fn bits(&self) -> u8 {
+        self.0
+    }
+
+    /// Whether Num Lock is on.
+    pub const fn num_lock(&self) -> bool {
+        self.0 & Self::NUM_LOCK != 0
+    }
+
+    /// Whether Caps Lock is on.
+    pub const fn caps_lock(&self) -> bool {
+        self.0 & Self::CAPS_LOCK != 0
+    }
+
+    /// Whether Scroll Lock is on.
+    pub const fn scroll_lock(&self) -> bool {
+        self.0 & Self::SCROLL_LOCK != 0
+    }
+
+    /// Whether Compose is on.
+    pub const fn compose(&self) -> bool {
+        self.0 & Self::COMPOSE != 0
+    }
+
+    /// Whether Kana is on.
+    pub const fn kana(&self) -> bool {
+        self.0 & Self::KANA != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_output_report() {
+        let leds = LedState::from_report(&[0x03]).unwrap();
+        assert!(leds.num_lock() && leds.caps_lock());
+        assert!(!leds.scroll_lock() && !leds.compose() && !leds.kana());
+        assert_eq!(LedState::from_report(&[]), None);
+    }
+}
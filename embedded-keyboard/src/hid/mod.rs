@@ -0,0 +1,58 @@
+//! HID report builders and report descriptors.
+//!
+//! Each report type knows its own report descriptor, so that the bytes
+//! sent to the host can never drift apart from the layout the host was
+//! told to expect.
+
+mod battery;
+mod ble;
+mod boot;
+mod change;
+mod composite;
+mod consumer;
+mod descriptor;
+mod dial;
+mod extended;
+mod i2c;
+mod led;
+mod mouse;
+mod nkro;
+mod protocol;
+mod rollover;
+mod stats;
+mod system;
+mod throttle;
+
+pub use self::battery::*;
+pub use self::ble::*;
+pub use self::boot::*;
+pub use self::change::*;
+pub use self::composite::*;
+pub use self::consumer::*;
+pub use self::descriptor::*;
+pub use self::dial::*;
+pub use self::extended::*;
+pub use self::i2c::*;
+pub use self::led::*;
+pub use self::mouse::*;
+pub use self::nkro::*;
+pub use self::protocol::*;
+pub use self::rollover::*;
+pub use self::stats::*;
+pub use self::system::*;
+pub use self::throttle::*;
+
+/// First modifier usage on the Keyboard/Keypad page (Left Control).
+pub(crate) const MODIFIER_MIN: u16 = 0x00e0;
+
+/// Last modifier usage on the Keyboard/Keypad page (Right GUI).
+pub(crate) const MODIFIER_MAX: u16 = 0x00e7;
+
+/// Bit in the modifier byte for `usage`, if it is a modifier.
+pub(crate) const fn modifier_bit(usage: u16) -> Option<u8> {
+    if usage >= MODIFIER_MIN && usage <= MODIFIER_MAX {
+        Some(1 << (usage - MODIFIER_MIN))
+    } else {
+        None
+    }
+}
@@ -0,0 +1,138 @@
+use crate::Usage;
+
+/// Mouse report, for mouse keys and pointing devices built into a
+/// keyboard.
+///
+/// Buttons are usages 1 to 8 of the Button page, motion is relative. On
+/// the wire the report is laid out as:
+///
+/// | Byte | Contents         |
+/// |------|------------------|
+/// | 0    | Button bitmap    |
+/// | 1    | X motion, signed |
+/// | 2    | Y motion, signed |
+/// | 3    | Wheel, signed    |
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MouseReport {
+    buttons: u8,
+    x: i8,
+    y: i8,
+    wheel: i8,
+}
+
+impl MouseReport {
+    /// Length of the serialized report in bytes.
+    pub const LEN: usize = 4;
+
+    /// HID report descriptor matching this report.
+    pub const DESCRIPTOR: [u8; 46] = [
+        0x05, 0x01, //       Usage Page (Generic Desktop)
+        0x09, 0x02, //       Usage (Mouse)
+        0xa1, 0x01, //       Collection (Application)
+        0x09, 0x01, //         Usage (Pointer)
+        0xa1, 0x00, //         Collection (Physical)
+        0x05, 0x09, //           Usage Page (Button)
+        0x19, 0x01, //           Usage Minimum (1)
+        0x29, 0x08, //           Usage Maximum (8)
+        0x15, 0x00, //           Logical Minimum (0)
+        0x25, 0x01, //           Logical Maximum (1)
+        0x75, 0x01, //           Report Size (1)
+        0x95, 0x08, //           Report Count (8)
+        0x81, 0x02, //           Input (Data, Variable, Absolute)
+        0x05, 0x01, //           Usage Page (Generic Desktop)
+        0x09, 0x30, //           Usage (X)
+        0x09, 0x31, //           Usage (Y)
+        0x09, 0x38, //           Usage (Wheel)
+        0x15, 0x81, //           Logical Minimum (-127)
+        0x25, 0x7f, //           Logical Maximum (127)
+        0x75, 0x08, //           Report Size (8)
+        0x95, 0x03, //           Report Count (3)
+        0x81, 0x06, //           Input (Data, Variable, Relative)
+        0xc0, //               End Collection
+        0xc0, //             End Collection
+    ];
+
+    /// Create a report without buttons or motion.
+    pub const fn new() -> Self {
+        Self {
+            buttons: 0,
+            x: 0,
+            y: 0,
+            wheel: 0,
+        }
+    }
+
+    /// Add pressed buttons to the report, e.g. from
+    /// [`Engine::usages`](crate::engine::Engine::usages). Usages on other
+    /// pages than the Button page are skipped.
+    ///
+    /// Returns `false` if some usages are not among the eight buttons.
+    pub fn extend(&mut self, usages: impl IntoIterator<Item = Usage>) -> bool {
+        let mut fits = true;
+
+        for usage in usages {
+            if usage.page() == Usage::BUTTON_PAGE {
+                fits &= self.press(usage.id());
+            }
+        }
+
+        fits
+    }
+
+    /// Press button `button`, from 1 to 8.
+    ///
+    /// Returns `false` if there is no such button.
+    pub fn press(&mut self, button: u16) -> bool {
+        match button {
+            1..=8 => {
+                self.buttons |= 1 << (button - 1);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Release button `button`.
+    pub fn release(&mut self, button: u16) {
+        if let 1..=8 = button {
+            self.buttons &= !(1 << (button - 1));
+        }
+    }
+
+    /// Button bitmap.
+    pub const fn buttons(&self) -> u8 {
+        self.buttons
+    }
+
+    /// Set the motion since the last report. Motion of -128 is reported
+    /// as -127.
+    pub fn set_motion(&mut self, x: i8, y: i8, wheel: i8) {
+        self.x = x.max(-127);
+        self.y = y.max(-127);
+        self.wheel = wheel.max(-127);
+    }
+
+    /// Motion since the last report.
+    pub const fn motion(&self) -> (i8, i8, i8) {
+        (self.x, self.y, self.wheel)
+    }
+
+    /// Whether the report carries any motion.
+    pub const fn moves(&self) -> bool {
+        self.x != 0 || self.y != 0 || self.wheel != 0
+    }
+
+    /// Serialize the report into `buf`, returning the number of bytes
+    /// written, or `None` if `buf` is shorter than [`Self::LEN`].
+    pub fn serialize(&self, buf: &mut [u8]) -> Option<usize> {
+        buf.get_mut(..Self::LEN)?.copy_from_slice(&[
+            self.buttons,
+            self.x.to_le_bytes()[0],
+            self.y.to_le_bytes()[0],
+            self.wheel.to_le_bytes()[0],
+        ]);
+
+        Some(Self::LEN)
+    }
+}
@@ -0,0 +1,221 @@
+use super::modifier_bit;
+use crate::Usage;
+
+/// Keyboard report with a bitmap of pressed usages.
+///
+/// Unlike the array of the boot protocol, the bitmap has a bit for every
+/// usage from `0` up to `8 * BYTES - 1`, so that any number of keys can
+/// be pressed at once (NKRO). On the wire the report is laid out as:
+///
+/// | Byte         | Contents                               |
+/// |--------------|----------------------------------------|
+/// | 0            | Modifier bitmap (`0xe0..=0xe7`)        |
+/// | 1..1 + BYTES | Usage bitmap, usage `n` in bit `n % 8` |
+///
+/// `BYTES` must be between 1 and 32; 29 bytes cover every usage up to
+/// the modifiers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct NkroKeyboardReport<const BYTES: usize> {
+    modifiers: u8,
+    bitmap: [u8; BYTES],
+}
+
+impl<const BYTES: usize> NkroKeyboardReport<BYTES> {
+    /// Length of the serialized report in bytes.
+    pub const LEN: usize = 1 + BYTES;
+
+    /// HID report descriptor matching this report, including the LED
+    /// output report.
+    pub const DESCRIPTOR: [u8; 59] = Self::descriptor();
+
+    /// Create an empty report.
+    pub const fn new() -> Self {
+        Self {
+            modifiers: 0,
+            bitmap: [0; BYTES],
+        }
+    }
+
+    /// Create a report from a set of pressed usages. Usages beyond the
+    /// bitmap are dropped.
+    pub fn from_usages(usages: impl IntoIterator<Item = u16>) -> Self {
+        let mut report = Self::new();
+
+        for usage in usages {
+            report.press(usage);
+        }
+
+        report
+    }
+
+    /// Add pressed usages to the report, e.g. from
+    /// [`Engine::usages`](crate::engine::Engine::usages). Usages on other
+    /// pages than the Keyboard/Keypad page are skipped.
+    ///
+    /// Returns `false` if some usages are beyond the bitmap.
+    pub fn extend(&mut self, usages: impl IntoIterator<Item = Usage>) -> bool {
+        let mut fits = true;
+
+        for usage in usages.into_iter().filter_map(|usage| usage.keyboard_id()) {
+            fits &= self.press(usage);
+        }
+
+        fits
+    }
+
+    /// Add a pressed usage to the report.
+    ///
+    /// Returns `false` if the usage is beyond the bitmap.
+    pub fn press(&mut self, usage: u16) -> bool {
+        if let Some(bit) = modifier_bit(usage) {
+            self.modifiers |= bit;
+            return true;
+        }
+
+        match self.bitmap.get_mut(usize::from(usage / 8)) {
+            Some(byte) => {
+                *byte |= 1 << (usage % 8);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove a usage from the report.
+    pub fn release(&mut self, usage: u16) {
+        if let Some(bit) = modifier_bit(usage) {
+            self.modifiers &= !bit;
+            return;
+        }
+
+        if let Some(byte) = self.bitmap.get_mut(usize::from(usage / 8)) {
+            *byte &= !(1 << (usage % 8));
+        }
+    }
+
+    /// Remove every usage from the report.
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Modifier bitmap.
+    pub const fn modifiers(&self) -> u8 {
+        self.modifiers
+    }
+
+    /// Pressed non-modifier usages, in ascending order.
+    pub fn usages(&self) -> impl Iterator<Item = u16> + '_ {
+        (0..8 * BYTES)
+            .filter(|usage| self.bitmap[usage / 8] & (1 << (usage % 8)) != 0)
+            .filter_map(|usage| u16::try_from(usage).ok())
+    }
+
+    /// Serialize the report into `buf`, returning the number of bytes
+    /// written, or `None` if `buf` is shorter than [`Self::LEN`].
+    pub fn serialize(&self, buf: &mut [u8]) -> Option<usize> {
+        let buf = buf.get_mut(..Self::LEN)?;
+
+        buf[0] = self.modifiers;
+        buf[1..].copy_from_slice(&self.bitmap);
+
+        Some(Self::LEN)
+    }
+
+    const fn descriptor() -> [u8; 59] {
+        let usages = const {
+            assert!(BYTES > 0 && BYTES <= 32);
+            (8 * BYTES) as u16
+        };
+        let [count_lo, count_hi] = usages.to_le_bytes();
+        let [max_lo, max_hi] = (usages - 1).to_le_bytes();
+
+        [
+            0x05, 0x01, //       Usage Page (Generic Desktop)
+            0x09, 0x06, //       Usage (Keyboard)
+            0xa1, 0x01, //       Collection (Application)
+            0x05, 0x07, //         Usage Page (Keyboard/Keypad)
+            0x19, 0xe0, //         Usage Minimum (Left Control)
+            0x29, 0xe7, //         Usage Maximum (Right GUI)
+            0x15, 0x00, //         Logical Minimum (0)
+            0x25, 0x01, //         Logical Maximum (1)
+            0x75, 0x01, //         Report Size (1)
+            0x95, 0x08, //         Report Count (8)
+            0x81, 0x02, //         Input (Data, Variable, Absolute)
+            0x05, 0x08, //         Usage Page (LEDs)
+            0x19, 0x01, //         Usage Minimum (Num Lock)
+            0x29, 0x05, //         Usage Maximum (Kana)
+            0x75, 0x01, //         Report Size (1)
+            0x95, 0x05, //         Report Count (5)
+            0x91, 0x02, //         Output (Data, Variable, Absolute)
+            0x75, 0x03, //         Report Size (3)
+            0x95, 0x01, //         Report Count (1)
+            0x91, 0x01, //         Output (Constant)
+            0x05, 0x07, //         Usage Page (Keyboard/Keypad)
+            0x19, 0x00, //         Usage Minimum (0)
+            0x2a, max_lo, max_hi, // Usage Maximum (8 * BYTES - 1)
+            0x15, 0x00, //         Logical Minimum (0)
+            0x25, 0x01, //         Logical Maximum (1)
+            0x75, 0x01, //         Report Size (1)
+            0x96, count_lo, count_hi, // Report Count (8 * BYTES)
+            0x81, 0x02, //         Input (Data, Variable, Absolute)
+            0xc0, //             End Collection
+        ]
+    }
+}
+
+impl<const BYTES: usize> Default for NkroKeyboardReport<BYTES> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hid::{ConsumerReport, DescriptorBuilder};
+    use crate::KeyCode;
+
+    #[test]
+    fn bitmap() {
+        let mut report = NkroKeyboardReport::<4>::new();
+
+        assert!(report.extend([
+            Usage::from(KeyCode::KA),
+            Usage::from(KeyCode::KpRightShift),
+            Usage::from(KeyCode::K1),
+        ]));
+        assert!(!report.press(KeyCode::KEnter.into()));
+        assert_eq!(report.modifiers(), 0x20);
+        assert!(report.usages().eq([0x04, 0x1e]));
+
+        let mut buf = [0xaa; 6];
+        assert_eq!(report.serialize(&mut buf), Some(5));
+        assert_eq!(buf, [0x20, 0x10, 0x00, 0x00, 0x40, 0xaa]);
+
+        report.release(KeyCode::KA.into());
+        assert!(report.usages().eq([0x1e]));
+    }
+
+    #[test]
+    fn descriptor_sizes() {
+        let descriptor = NkroKeyboardReport::<32>::DESCRIPTOR;
+        assert_eq!(descriptor[44..47], [0x2a, 0xff, 0x00]);
+        assert_eq!(descriptor[53..56], [0x96, 0x00, 0x01]);
+    }
+
+    #[test]
+    fn composite_descriptor() {
+        const KEYBOARD: [u8; 59] = NkroKeyboardReport::<16>::DESCRIPTOR;
+        const LEN: usize = DescriptorBuilder::<0>::collection_len(Some(1), &KEYBOARD)
+            + DescriptorBuilder::<0>::collection_len(None, &ConsumerReport::<4>::DESCRIPTOR);
+        const DESCRIPTOR: [u8; LEN] = DescriptorBuilder::new()
+            .collection(Some(1), &KEYBOARD)
+            .collection(None, &ConsumerReport::<4>::DESCRIPTOR)
+            .build();
+
+        assert_eq!(DESCRIPTOR[4..8], [0xa1, 0x01, 0x85, 0x01]);
+        assert_eq!(DESCRIPTOR[8..61], KEYBOARD[6..]);
+        assert_eq!(DESCRIPTOR[61..], ConsumerReport::<4>::DESCRIPTOR);
+    }
+}
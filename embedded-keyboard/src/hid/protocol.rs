@@ -0,0 +1,126 @@
+use super::{BootKeyboardReport, ExtendedKeyboardReport, RolloverPolicy};
+use crate::Usage;
+
+/// HID protocol selected by the host with `SET_PROTOCOL`.
+///
+/// Hosts parsing the report descriptor use the report protocol, while a
+/// BIOS or boot loader may select the boot protocol, and then only
+/// understands [`BootKeyboardReport`]s.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum Protocol {
+    /// Boot protocol, 6KRO with 8-bit usages
+    Boot = 0,
+    /// Report protocol, as described by the report descriptor
+    #[default]
+    Report = 1,
+}
+
+impl Protocol {
+    /// Protocol from the value of a `SET_PROTOCOL` request.
+    pub const fn from_raw(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Boot),
+            1 => Some(Self::Report),
+            _ => None,
+        }
+    }
+}
+
+/// Keyboard report in the format of the selected [`Protocol`].
+///
+/// In report protocol, this is an [`ExtendedKeyboardReport`] with room for
+/// `N` keys; in boot protocol, a [`BootKeyboardReport`], whatever `N`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum KeyboardReport<const N: usize> {
+    /// Boot protocol report
+    Boot(BootKeyboardReport),
+    /// Report protocol report
+    Report(ExtendedKeyboardReport<N>),
+}
+
+impl<const N: usize> KeyboardReport<N> {
+    /// Create an empty report for `protocol`.
+    pub const fn new(protocol: Protocol) -> Self {
+        match protocol {
+            Protocol::Boot => Self::Boot(BootKeyboardReport::new()),
+            Protocol::Report => Self::Report(ExtendedKeyboardReport::new()),
+        }
+    }
+
+    /// Create an empty report for `protocol`, handling more keys than it
+    /// can carry with `policy`.
+    pub const fn with_rollover(protocol: Protocol, policy: RolloverPolicy) -> Self {
+        match protocol {
+            Protocol::Boot => Self::Boot(BootKeyboardReport::with_rollover(policy)),
+            Protocol::Report => Self::Report(ExtendedKeyboardReport::with_rollover(policy)),
+        }
+    }
+
+    /// Protocol of the report.
+    pub const fn protocol(&self) -> Protocol {
+        match self {
+            Self::Boot(_) => Protocol::Boot,
+            Self::Report(_) => Protocol::Report,
+        }
+    }
+
+    /// Length of the serialized report in bytes.
+    pub const fn serialized_len(&self) -> usize {
+        match self {
+            Self::Boot(_) => BootKeyboardReport::LEN,
+            Self::Report(_) => ExtendedKeyboardReport::<N>::LEN,
+        }
+    }
+
+    /// Add pressed usages to the report, see
+    /// [`BootKeyboardReport::extend`] and
+    /// [`ExtendedKeyboardReport::extend`].
+    pub fn extend(&mut self, usages: impl IntoIterator<Item = Usage>) -> bool {
+        match self {
+            Self::Boot(report) => report.extend(usages),
+            Self::Report(report) => report.extend(usages),
+        }
+    }
+
+    /// Serialize the report into `buf`, returning the number of bytes
+    /// written, or `None` if `buf` is too short.
+    pub fn serialize(&self, buf: &mut [u8]) -> Option<usize> {
+        match self {
+            Self::Boot(report) => report.serialize(buf),
+            Self::Report(report) => report.serialize(buf),
+        }
+    }
+}
+
+impl<const N: usize> Default for KeyboardReport<N> {
+    fn default() -> Self {
+        Self::new(Protocol::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeyCode;
+
+    #[test]
+    fn format_follows_protocol() {
+        let usages = (0x04..0x0c).map(Usage::keyboard);
+        let mut buf = [0; 32];
+
+        let mut report = KeyboardReport::<10>::default();
+        assert!(report.extend(usages.clone()));
+        assert_eq!(report.serialize(&mut buf), Some(22));
+
+        let mut report = KeyboardReport::<10>::new(Protocol::from_raw(0).unwrap());
+        assert!(!report.extend(usages.chain([Usage::from(KeyCode::KpLeftShift)])));
+        assert_eq!(report.serialized_len(), 8);
+        assert_eq!(report.serialize(&mut buf), Some(8));
+        assert_eq!(buf[..8], [0x02, 0, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01]);
+        assert_eq!(Protocol::from_raw(2), None);
+    }
+}
@@ -0,0 +1,89 @@
+/// What a keyboard report does with a key pressed when its usage slots
+/// are all taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RolloverPolicy {
+    /// Report `ErrorRollOver` in every slot, as the HID specification
+    /// requires, until the report is cleared. The safe choice for BIOS
+    /// compatibility.
+    ErrorRollOver,
+    /// Keep the keys already reported and drop the new one.
+    KeepOldest,
+    /// Drop the key reported the longest and report the new one, as
+    /// gaming firmware usually prefers.
+    ///
+    /// Slots are kept in the order keys were pressed in, so the key
+    /// dropped is the one pressed first when the report is updated as
+    /// keys are pressed and released. A report built afresh from a set of
+    /// usages keeps the last usages of the set instead.
+    KeepNewest,
+}
+
+/// Put `usage` in the first free one of `slots`, following `policy` if
+/// there is none. Returns `false` if a usage was dropped.
+pub(crate) fn press_slot<T: Copy + Default + PartialEq>(
+    slots: &mut [T],
+    usage: T,
+    policy: RolloverPolicy,
+) -> bool {
+    if let Some(slot) = slots.iter_mut().find(|slot| **slot == T::default()) {
+        *slot = usage;
+        return true;
+    }
+
+    if let (RolloverPolicy::KeepNewest, Some(last)) = (policy, slots.len().checked_sub(1)) {
+        slots.copy_within(1.., 0);
+        slots[last] = usage;
+    }
+
+    false
+}
+
+/// Free the slot of `usage`, if any. With [`RolloverPolicy::KeepNewest`],
+/// the slots after it move up, so that they stay in press order.
+pub(crate) fn release_slot<T: Copy + Default + PartialEq>(
+    slots: &mut [T],
+    usage: T,
+    policy: RolloverPolicy,
+) {
+    if usage == T::default() {
+        return;
+    }
+    let Some(index) = slots.iter().position(|slot| *slot == usage) else {
+        return;
+    };
+
+    if policy == RolloverPolicy::KeepNewest {
+        slots[index..].rotate_left(1);
+        if let Some(last) = slots.last_mut() {
+            *last = T::default();
+        }
+    } else {
+        slots[index] = T::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policies_on_full_slots() {
+        let mut slots = [1u8, 2, 3];
+        assert!(!press_slot(&mut slots, 4, RolloverPolicy::ErrorRollOver));
+        assert!(!press_slot(&mut slots, 4, RolloverPolicy::KeepOldest));
+        assert_eq!(slots, [1, 2, 3]);
+
+        assert!(!press_slot(&mut slots, 4, RolloverPolicy::KeepNewest));
+        assert_eq!(slots, [2, 3, 4]);
+
+        release_slot(&mut slots, 3, RolloverPolicy::KeepNewest);
+        assert_eq!(slots, [2, 4, 0]);
+        assert!(press_slot(&mut slots, 5, RolloverPolicy::KeepNewest));
+        assert_eq!(slots, [2, 4, 5]);
+
+        release_slot(&mut slots, 2, RolloverPolicy::KeepOldest);
+        assert_eq!(slots, [0, 4, 5]);
+    }
+}
@@ -0,0 +1,108 @@
+use crate::Coordinate;
+
+/// Feature report carrying device health statistics.
+///
+/// Standard host tools can read it with a plain HID Get Feature request,
+/// without a custom driver. It lives on a vendor-defined usage page, and
+/// is laid out as:
+///
+/// | Byte | Contents                                       |
+/// |------|------------------------------------------------|
+/// | 0..2 | Scans per second, little endian                |
+/// | 2..4 | Failed scans, little endian, saturating        |
+/// | 4    | Number of stuck keys, saturating               |
+/// | 5    | Row of the first stuck key, `0xff` if none     |
+/// | 6    | Column of the first stuck key, `0xff` if none  |
+/// | 7    | Reserved                                       |
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct StatsReport {
+    scan_rate: u16,
+    errors: u16,
+    stuck_keys: u8,
+    stuck_key: Option<(u8, u8)>,
+}
+
+impl StatsReport {
+    /// Length of the serialized report in bytes.
+    pub const LEN: usize = 8;
+
+    /// HID report descriptor matching this report.
+    pub const DESCRIPTOR: [u8; 44] = [
+        0x06, 0x00, 0xff, //   Usage Page (Vendor Defined 0xFF00)
+        0x09, 0x01, //         Usage (0x01)
+        0xa1, 0x01, //         Collection (Application)
+        0x09, 0x02, //           Usage (Scan Rate)
+        0x09, 0x03, //           Usage (Errors)
+        0x15, 0x00, //           Logical Minimum (0)
+        0x27, 0xff, 0xff, 0x00, 0x00, // Logical Maximum (0xffff)
+        0x75, 0x10, //           Report Size (16)
+        0x95, 0x02, //           Report Count (2)
+        0xb1, 0x02, //           Feature (Data, Variable, Absolute)
+        0x09, 0x04, //           Usage (Stuck Keys)
+        0x09, 0x05, //           Usage (Stuck Row)
+        0x09, 0x06, //           Usage (Stuck Column)
+        0x26, 0xff, 0x00, //     Logical Maximum (0xff)
+        0x75, 0x08, //           Report Size (8)
+        0x95, 0x03, //           Report Count (3)
+        0xb1, 0x02, //           Feature (Data, Variable, Absolute)
+        0x95, 0x01, //           Report Count (1)
+        0xb1, 0x01, //           Feature (Constant)
+        0xc0, //               End Collection
+    ];
+
+    /// Create a report from scanning statistics.
+    ///
+    /// Counts too large for the report saturate.
+    pub fn new(
+        scan_rate: u16,
+        errors: u32,
+        stuck_keys: usize,
+        stuck_key: Option<Coordinate>,
+    ) -> Self {
+        Self {
+            scan_rate,
+            errors: u16::try_from(errors).unwrap_or(u16::MAX),
+            stuck_keys: u8::try_from(stuck_keys).unwrap_or(u8::MAX),
+            stuck_key: stuck_key.map(|c| {
+                (
+                    u8::try_from(c.row()).unwrap_or(u8::MAX),
+                    u8::try_from(c.col()).unwrap_or(u8::MAX),
+                )
+            }),
+        }
+    }
+
+    /// Serialize the report into `buf`, returning the number of bytes
+    /// written, or `None` if `buf` is shorter than [`Self::LEN`].
+    pub fn serialize(&self, buf: &mut [u8]) -> Option<usize> {
+        let buf = buf.get_mut(..Self::LEN)?;
+        let (row, col) = self.stuck_key.unwrap_or((0xff, 0xff));
+
+        buf[0..2].copy_from_slice(&self.scan_rate.to_le_bytes());
+        buf[2..4].copy_from_slice(&self.errors.to_le_bytes());
+        buf[4] = self.stuck_keys;
+        buf[5] = row;
+        buf[6] = col;
+        buf[7] = 0;
+
+        Some(Self::LEN)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serialize_report() {
+        let report = StatsReport::new(1000, 70_000, 2, Some(Coordinate::new(3, 4)));
+        let mut buf = [0xaa; 8];
+
+        assert_eq!(report.serialize(&mut buf), Some(8));
+        assert_eq!(buf, [0xe8, 0x03, 0xff, 0xff, 2, 3, 4, 0]);
+
+        StatsReport::new(0, 0, 0, None).serialize(&mut buf);
+        assert_eq!(buf, [0, 0, 0, 0, 0, 0xff, 0xff, 0]);
+    }
+}
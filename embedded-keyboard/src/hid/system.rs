@@ -0,0 +1,182 @@
+use crate::Usage;
+
+/// System Power Down usage on the Generic Desktop page.
+pub const SYSTEM_POWER_DOWN: u16 = 0x81;
+/// System Sleep usage on the Generic Desktop page.
+pub const SYSTEM_SLEEP: u16 = 0x82;
+/// System Wake Up usage on the Generic Desktop page.
+pub const SYSTEM_WAKE_UP: u16 = 0x83;
+
+/// System control report, for power, sleep and wake keys.
+///
+/// A single byte holding the usage of the Generic Desktop page being
+/// asserted, from [`SYSTEM_POWER_DOWN`] to [`SYSTEM_WAKE_UP`], or 0.
+/// These usages are one-shot controls: the host acts on the usage being
+/// asserted, not on it being held, so reports are best built by
+/// [`SystemControl`] rather than straight from the pressed usages.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SystemControlReport {
+    usage: u8,
+}
+
+impl SystemControlReport {
+    /// Length of the serialized report in bytes.
+    pub const LEN: usize = 1;
+
+    /// HID report descriptor matching this report.
+    pub const DESCRIPTOR: [u8; 23] = [
+        0x05, 0x01, //       Usage Page (Generic Desktop)
+        0x09, 0x80, //       Usage (System Control)
+        0xa1, 0x01, //       Collection (Application)
+        0x19, 0x81, //         Usage Minimum (System Power Down)
+        0x29, 0x83, //         Usage Maximum (System Wake Up)
+        0x16, 0x81, 0x00, //   Logical Minimum (0x81)
+        0x26, 0x83, 0x00, //   Logical Maximum (0x83)
+        0x75, 0x08, //         Report Size (8)
+        0x95, 0x01, //         Report Count (1)
+        0x81, 0x00, //         Input (Data, Array, Absolute)
+        0xc0, //             End Collection
+    ];
+
+    /// Create an empty report.
+    pub const fn new() -> Self {
+        Self { usage: 0 }
+    }
+
+    /// Create a report asserting `usage`, or `None` if it is not a system
+    /// control usage.
+    pub const fn asserting(usage: u16) -> Option<Self> {
+        match usage {
+            SYSTEM_POWER_DOWN..=SYSTEM_WAKE_UP => Some(Self { usage: usage as u8 }),
+            _ => None,
+        }
+    }
+
+    /// Asserted usage, if any.
+    pub const fn usage(&self) -> Option<u16> {
+        if self.usage == 0 {
+            None
+        } else {
+            Some(self.usage as u16)
+        }
+    }
+
+    /// Serialize the report into `buf`, returning the number of bytes
+    /// written, or `None` if `buf` is empty.
+    pub fn serialize(&self, buf: &mut [u8]) -> Option<usize> {
+        *buf.first_mut()? = self.usage;
+
+        Some(Self::LEN)
+    }
+}
+
+/// One-shot system control.
+///
+/// Turns the pressed usages into the system control reports to send:
+/// once a system control usage is pressed, it is asserted in one report
+/// and cleared in the next, however long the key is held, and asserted
+/// again only after the key was released. Usages pressed together are
+/// asserted one after the other.
+///
+/// [`SystemControl::update`] returns every report to send, so there is
+/// no need for a [`ChangeDetector`](super::ChangeDetector).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SystemControl {
+    held: u8,
+    pending: u8,
+    asserted: bool,
+}
+
+impl SystemControl {
+    /// Create a system control with nothing pressed.
+    pub const fn new() -> Self {
+        Self {
+            held: 0,
+            pending: 0,
+            asserted: false,
+        }
+    }
+
+    /// Update the pressed usages, e.g. from
+    /// [`Engine::usages`](crate::engine::Engine::usages), returning the
+    /// report to send, if any. Usages on other pages than the Generic
+    /// Desktop page are skipped.
+    pub fn update(
+        &mut self,
+        usages: impl IntoIterator<Item = Usage>,
+    ) -> Option<SystemControlReport> {
+        let held = usages
+            .into_iter()
+            .filter(|usage| usage.page() == Usage::GENERIC_DESKTOP_PAGE)
+            .filter_map(|usage| SystemControlReport::asserting(usage.id()))
+            .filter_map(|report| report.usage())
+            .fold(0, |held, usage| held | 1 << (usage - SYSTEM_POWER_DOWN));
+
+        self.pending |= held & !self.held;
+        self.held = held;
+
+        if core::mem::take(&mut self.asserted) {
+            return Some(SystemControlReport::new());
+        }
+
+        if self.pending == 0 {
+            return None;
+        }
+
+        let bit = self.pending.trailing_zeros();
+        self.pending &= !(1 << bit);
+        self.asserted = true;
+
+        SystemControlReport::asserting(SYSTEM_POWER_DOWN + bit as u16)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeyCode;
+
+    fn system(id: u16) -> Usage {
+        Usage::new(Usage::GENERIC_DESKTOP_PAGE, id)
+    }
+
+    #[test]
+    fn one_shot() {
+        let mut control = SystemControl::new();
+        let sleep = SystemControlReport::asserting(SYSTEM_SLEEP);
+
+        assert_eq!(control.update([Usage::from(KeyCode::KA)]), None);
+        assert_eq!(control.update([system(SYSTEM_SLEEP)]), sleep);
+        assert_eq!(
+            control.update([system(SYSTEM_SLEEP)]),
+            Some(SystemControlReport::new())
+        );
+        assert_eq!(control.update([system(SYSTEM_SLEEP)]), None);
+        assert_eq!(control.update([]), None);
+        assert_eq!(control.update([system(SYSTEM_SLEEP)]), sleep);
+
+        let mut buf = [0xaa; 2];
+        assert_eq!(sleep.unwrap().serialize(&mut buf), Some(1));
+        assert_eq!(buf, [0x82, 0xaa]);
+    }
+
+    #[test]
+    fn pressed_together() {
+        let mut control = SystemControl::new();
+
+        assert_eq!(
+            control.update([system(SYSTEM_WAKE_UP), system(SYSTEM_POWER_DOWN)]),
+            SystemControlReport::asserting(SYSTEM_POWER_DOWN)
+        );
+        assert_eq!(control.update([]), Some(SystemControlReport::new()));
+        assert_eq!(
+            control.update([]),
+            SystemControlReport::asserting(SYSTEM_WAKE_UP)
+        );
+        assert_eq!(control.update([]), Some(SystemControlReport::new()));
+        assert_eq!(control.update([]), None);
+        assert_eq!(SystemControlReport::asserting(0x84), None);
+    }
+}
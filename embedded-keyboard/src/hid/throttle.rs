@@ -0,0 +1,175 @@
+/// Limits the rate at which reports are sent, without losing any.
+///
+/// Hosts poll devices at a fixed interval, e.g. every `bInterval`
+/// milliseconds on USB, and a report replaced before the host polled it
+/// is lost to the host: a key pressed and released within one interval
+/// would never be seen. Every report built is passed to
+/// [`ReportThrottle::push`], which queues it if it differs from the one
+/// before, and reports are sent as [`ReportThrottle::poll`] returns them,
+/// at most one per interval and in order. Time is measured in
+/// milliseconds by the same wrapping timestamp as the
+/// [`Engine`](crate::engine::Engine).
+///
+/// Up to `N` reports are queued. Once full, the newest queued report is
+/// replaced by the next one, so that the host still ends up with the
+/// latest state, and the overflow is flagged until
+/// [`ReportThrottle::take_overflow`] is called.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ReportThrottle<R, const N: usize> {
+    interval: u16,
+    sent_at: Option<u32>,
+    last: R,
+    queue: [R; N],
+    head: usize,
+    len: usize,
+    overflowed: bool,
+}
+
+impl<R: PartialEq + Copy, const N: usize> ReportThrottle<R, N> {
+    /// Create a throttle sending a report at most every `interval`
+    /// milliseconds, assuming the host last received `initial`, usually
+    /// an empty report.
+    pub const fn new(interval: u16, initial: R) -> Self {
+        const {
+            assert!(N > 0);
+        }
+
+        Self {
+            interval,
+            sent_at: None,
+            last: initial,
+            queue: [initial; N],
+            head: 0,
+            len: 0,
+            overflowed: false,
+        }
+    }
+
+    /// Minimum time between reports, in milliseconds.
+    pub const fn interval(&self) -> u16 {
+        self.interval
+    }
+
+    /// Change the minimum time between reports, e.g. once the host
+    /// configured the polling interval of the endpoint.
+    pub fn set_interval(&mut self, interval: u16) {
+        self.interval = interval;
+    }
+
+    /// Number of reports waiting to be sent.
+    pub const fn pending(&self) -> usize {
+        self.len
+    }
+
+    /// Queue `report`, unless it is identical to the report before it.
+    pub fn push(&mut self, report: R) {
+        let newest = match self.len {
+            0 => self.last,
+            len => self.queue[(self.head + len - 1) % N],
+        };
+
+        if report == newest {
+            return;
+        }
+
+        if self.len == N {
+            self.queue[(self.head + N - 1) % N] = report;
+            self.overflowed = true;
+        } else {
+            self.queue[(self.head + self.len) % N] = report;
+            self.len += 1;
+        }
+    }
+
+    /// Next report to send at `now`, if any is queued and the interval
+    /// since the last one elapsed.
+    pub fn poll(&mut self, now: u32) -> Option<R> {
+        if self.len == 0 {
+            return None;
+        }
+
+        if let Some(sent_at) = self.sent_at {
+            if now.wrapping_sub(sent_at) < u32::from(self.interval) {
+                return None;
+            }
+        }
+
+        let report = self.queue[self.head];
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        self.last = report;
+        self.sent_at = Some(now);
+
+        Some(report)
+    }
+
+    /// Time at which the next queued report may be sent, if any is
+    /// queued.
+    pub fn next_deadline(&self, now: u32) -> Option<u32> {
+        if self.len == 0 {
+            return None;
+        }
+
+        match self.sent_at {
+            Some(sent_at) if now.wrapping_sub(sent_at) < u32::from(self.interval) => {
+                Some(sent_at.wrapping_add(u32::from(self.interval)))
+            }
+            _ => Some(now),
+        }
+    }
+
+    /// Whether reports were replaced in a full queue since the last call,
+    /// clearing the flag.
+    pub fn take_overflow(&mut self) -> bool {
+        core::mem::take(&mut self.overflowed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hid::BootKeyboardReport;
+
+    #[test]
+    fn tap_within_one_interval() {
+        let mut throttle = ReportThrottle::<_, 4>::new(8, BootKeyboardReport::new());
+        let pressed = BootKeyboardReport::from_usages([0x04]);
+        let start = u32::MAX - 3;
+
+        throttle.push(BootKeyboardReport::new());
+        assert_eq!(throttle.poll(start), None);
+
+        throttle.push(pressed);
+        throttle.push(pressed);
+        throttle.push(BootKeyboardReport::new());
+        assert_eq!(throttle.pending(), 2);
+
+        assert_eq!(throttle.poll(start), Some(pressed));
+        assert_eq!(throttle.poll(start.wrapping_add(7)), None);
+        assert_eq!(
+            throttle.next_deadline(start.wrapping_add(7)),
+            Some(start.wrapping_add(8))
+        );
+        assert_eq!(
+            throttle.poll(start.wrapping_add(8)),
+            Some(BootKeyboardReport::new())
+        );
+        assert_eq!(throttle.next_deadline(start.wrapping_add(8)), None);
+    }
+
+    #[test]
+    fn overflow_keeps_latest() {
+        let mut throttle = ReportThrottle::<u8, 2>::new(1, 0);
+
+        for report in 1..=4 {
+            throttle.push(report);
+        }
+        assert!(throttle.take_overflow());
+        assert!(!throttle.take_overflow());
+
+        assert_eq!(throttle.poll(0), Some(1));
+        assert_eq!(throttle.poll(1), Some(4));
+        assert_eq!(throttle.poll(2), None);
+    }
+}
@@ -0,0 +1,420 @@
+//! Host keyboard layouts.
+//!
+//! Keyboards report key positions, not characters: which character a
+//! keycode types depends on the layout the host is configured with. To
+//! type text, e.g. for macros, firmware has to know that layout. A
+//! [`HostLayout`] maps characters to the [`Keystroke`] typing them, and
+//! [`keystrokes`] turns a whole string into keystrokes.
+//!
+//! Characters typed with dead keys, which take two keystrokes, are not
+//! supported.
+
+use crate::{KeyCode, Usage};
+
+/// Key pressed together with modifiers.
+///
+/// Typing a character on a [`HostLayout`] takes a keystroke, and
+/// [`Action::Chord`](crate::Action::Chord) keys press one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Keystroke {
+    modifiers: u8,
+    code: KeyCode,
+}
+
+impl Keystroke {
+    /// Left Shift in the modifier bitmap.
+    pub const SHIFT: u8 = 0x02;
+    /// Right Alt, a.k.a. AltGr, in the modifier bitmap.
+    pub const ALT_GR: u8 = 0x40;
+
+    /// Keystroke of `code` with the `modifiers` bitmap, in the layout of
+    /// the HID modifier byte.
+    pub const fn new(modifiers: u8, code: KeyCode) -> Self {
+        Self { modifiers, code }
+    }
+
+    /// Keystroke of `code` alone.
+    pub const fn plain(code: KeyCode) -> Self {
+        Self::new(0, code)
+    }
+
+    /// Keystroke of `code` with Shift.
+    pub const fn shifted(code: KeyCode) -> Self {
+        Self::new(Self::SHIFT, code)
+    }
+
+    /// Keystroke of `code` with AltGr.
+    pub const fn alt_gr(code: KeyCode) -> Self {
+        Self::new(Self::ALT_GR, code)
+    }
+
+    /// Modifier bitmap, in the layout of the HID modifier byte.
+    pub const fn modifiers(&self) -> u8 {
+        self.modifiers
+    }
+
+    /// Keycode.
+    pub const fn code(&self) -> KeyCode {
+        self.code
+    }
+
+    /// Keycodes pressed for this keystroke, modifiers first.
+    pub fn keycodes(&self) -> impl Iterator<Item = KeyCode> {
+        let modifiers = self.modifiers;
+
+        (0..8)
+            .filter(move |bit| modifiers & (1 << bit) != 0)
+            .filter_map(|bit| KeyCode::try_from(0x00e0 + bit).ok())
+            .chain(core::iter::once(self.code))
+    }
+
+    /// Usages pressed for this keystroke, modifiers first.
+    pub fn usages(&self) -> impl Iterator<Item = Usage> {
+        self.keycodes().map(Usage::from)
+    }
+}
+
+/// Layout the host interprets keycodes with.
+pub trait HostLayout {
+    /// Keystroke typing `c`, if it can be typed with a single keystroke.
+    fn keystroke(&self, c: char) -> Option<Keystroke>;
+}
+
+impl<T: HostLayout + ?Sized> HostLayout for &T {
+    #[inline]
+    fn keystroke(&self, c: char) -> Option<Keystroke> {
+        T::keystroke(self, c)
+    }
+}
+
+/// Keystrokes typing `text` on `layout`, or the characters the layout
+/// cannot type.
+pub fn keystrokes<'a, L: HostLayout + ?Sized>(
+    layout: &'a L,
+    text: &'a str,
+) -> impl Iterator<Item = Result<Keystroke, char>> + 'a {
+    text.chars().map(|c| layout.keystroke(c).ok_or(c))
+}
+
+/// Keys typing the same characters on every supported layout.
+fn common(c: char) -> Option<Keystroke> {
+    use KeyCode::*;
+
+    Some(Keystroke::plain(match c {
+        ' ' => KSpaceBar,
+        '\n' => KEnter,
+        '\t' => KTab,
+        '\x08' => KBackspace,
+        '\x1b' => KEscape,
+        _ => return None,
+    }))
+}
+
+/// Letter key in QWERTY position of the lower case letter `c`.
+fn letter(c: char) -> Option<KeyCode> {
+    if !c.is_ascii_lowercase() {
+        return None;
+    }
+
+    KeyCode::try_from(u16::from(c as u8 - b'a') + 0x0004).ok()
+}
+
+/// Digit key on the number row.
+fn digit(c: char) -> Option<KeyCode> {
+    match c {
+        '0' => Some(KeyCode::K0),
+        '1'..='9' => KeyCode::try_from(u16::from(c as u8 - b'1') + 0x001e).ok(),
+        _ => None,
+    }
+}
+
+/// Keystroke of a letter, with `swap` applied to the lower case letter
+/// first for layouts moving letters around.
+fn letter_keystroke(c: char, swap: impl Fn(char) -> char) -> Option<Keystroke> {
+    let upper = c.is_ascii_uppercase();
+    let code = letter(swap(c.to_ascii_lowercase()))?;
+
+    Some(if upper {
+        Keystroke::shifted(code)
+    } else {
+        Keystroke::plain(code)
+    })
+}
+
+/// US English layout.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Us;
+
+impl HostLayout for Us {
+    fn keystroke(&self, c: char) -> Option<Keystroke> {
+        use KeyCode::*;
+
+        if let Some(k) = common(c).or_else(|| letter_keystroke(c, |c| c)) {
+            return Some(k);
+        }
+
+        if let Some(code) = digit(c) {
+            return Some(Keystroke::plain(code));
+        }
+
+        Some(match c {
+            '!' => Keystroke::shifted(K1),
+            '@' => Keystroke::shifted(K2),
+            '#' => Keystroke::shifted(K3),
+            '$' => Keystroke::shifted(K4),
+            '%' => Keystroke::shifted(K5),
+            '^' => Keystroke::shifted(K6),
+            '&' => Keystroke::shifted(K7),
+            '*' => Keystroke::shifted(K8),
+            '(' => Keystroke::shifted(K9),
+            ')' => Keystroke::shifted(K0),
+            '-' => Keystroke::plain(KDash),
+            '_' => Keystroke::shifted(KDash),
+            '=' => Keystroke::plain(KEqual),
+            '+' => Keystroke::shifted(KEqual),
+            '[' => Keystroke::plain(KLeftBracket),
+            '{' => Keystroke::shifted(KLeftBracket),
+            ']' => Keystroke::plain(KRightBracket),
+            '}' => Keystroke::shifted(KRightBracket),
+            '\\' => Keystroke::plain(KBackslash),
+            '|' => Keystroke::shifted(KBackslash),
+            ';' => Keystroke::plain(KSemiColon),
+            ':' => Keystroke::shifted(KSemiColon),
+            '\'' => Keystroke::plain(KQuote),
+            '"' => Keystroke::shifted(KQuote),
+            '`' => Keystroke::plain(KGrave),
+            '~' => Keystroke::shifted(KGrave),
+            ',' => Keystroke::plain(KComma),
+            '<' => Keystroke::shifted(KComma),
+            '.' => Keystroke::plain(KDot),
+            '>' => Keystroke::shifted(KDot),
+            '/' => Keystroke::plain(KSlash),
+            '?' => Keystroke::shifted(KSlash),
+            _ => return None,
+        })
+    }
+}
+
+/// UK English layout.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Uk;
+
+impl HostLayout for Uk {
+    fn keystroke(&self, c: char) -> Option<Keystroke> {
+        use KeyCode::*;
+
+        Some(match c {
+            '"' => Keystroke::shifted(K2),
+            '£' => Keystroke::shifted(K3),
+            '@' => Keystroke::shifted(KQuote),
+            '#' => Keystroke::plain(KNonUSPound),
+            '~' => Keystroke::shifted(KNonUSPound),
+            '\\' => Keystroke::plain(KNonUSBackslash),
+            '|' => Keystroke::shifted(KNonUSBackslash),
+            '¬' => Keystroke::shifted(KGrave),
+            '€' => Keystroke::alt_gr(K4),
+            _ => return Us.keystroke(c),
+        })
+    }
+}
+
+/// German layout (QWERTZ).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct German;
+
+impl HostLayout for German {
+    fn keystroke(&self, c: char) -> Option<Keystroke> {
+        use KeyCode::*;
+
+        let swap = |c| match c {
+            'y' => 'z',
+            'z' => 'y',
+            c => c,
+        };
+
+        if let Some(k) = common(c).or_else(|| letter_keystroke(c, swap)) {
+            return Some(k);
+        }
+
+        if let Some(code) = digit(c) {
+            return Some(Keystroke::plain(code));
+        }
+
+        Some(match c {
+            '!' => Keystroke::shifted(K1),
+            '"' => Keystroke::shifted(K2),
+            '§' => Keystroke::shifted(K3),
+            '$' => Keystroke::shifted(K4),
+            '%' => Keystroke::shifted(K5),
+            '&' => Keystroke::shifted(K6),
+            '/' => Keystroke::shifted(K7),
+            '(' => Keystroke::shifted(K8),
+            ')' => Keystroke::shifted(K9),
+            '=' => Keystroke::shifted(K0),
+            '{' => Keystroke::alt_gr(K7),
+            '[' => Keystroke::alt_gr(K8),
+            ']' => Keystroke::alt_gr(K9),
+            '}' => Keystroke::alt_gr(K0),
+            'ß' => Keystroke::plain(KDash),
+            '?' => Keystroke::shifted(KDash),
+            '\\' => Keystroke::alt_gr(KDash),
+            'ü' => Keystroke::plain(KLeftBracket),
+            'Ü' => Keystroke::shifted(KLeftBracket),
+            '+' => Keystroke::plain(KRightBracket),
+            '*' => Keystroke::shifted(KRightBracket),
+            '~' => Keystroke::alt_gr(KRightBracket),
+            'ö' => Keystroke::plain(KSemiColon),
+            'Ö' => Keystroke::shifted(KSemiColon),
+            'ä' => Keystroke::plain(KQuote),
+            'Ä' => Keystroke::shifted(KQuote),
+            '#' => Keystroke::plain(KNonUSPound),
+            '\'' => Keystroke::shifted(KNonUSPound),
+            '°' => Keystroke::shifted(KGrave),
+            '<' => Keystroke::plain(KNonUSBackslash),
+            '>' => Keystroke::shifted(KNonUSBackslash),
+            '|' => Keystroke::alt_gr(KNonUSBackslash),
+            ',' => Keystroke::plain(KComma),
+            ';' => Keystroke::shifted(KComma),
+            '.' => Keystroke::plain(KDot),
+            ':' => Keystroke::shifted(KDot),
+            '-' => Keystroke::plain(KSlash),
+            '_' => Keystroke::shifted(KSlash),
+            '@' => Keystroke::alt_gr(KQ),
+            '€' => Keystroke::alt_gr(KE),
+            _ => return None,
+        })
+    }
+}
+
+/// French layout (AZERTY).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct French;
+
+impl HostLayout for French {
+    fn keystroke(&self, c: char) -> Option<Keystroke> {
+        use KeyCode::*;
+
+        // `m` sits where QWERTY has `;`, and is handled below.
+        let swap = |c| match c {
+            'a' => 'q',
+            'q' => 'a',
+            'z' => 'w',
+            'w' => 'z',
+            c => c,
+        };
+
+        if let Some(k) = common(c).or_else(|| {
+            (!c.eq_ignore_ascii_case(&'m'))
+                .then(|| letter_keystroke(c, swap))
+                .flatten()
+        }) {
+            return Some(k);
+        }
+
+        // Digits are shifted on the number row.
+        if let Some(code) = digit(c) {
+            return Some(Keystroke::shifted(code));
+        }
+
+        Some(match c {
+            'm' => Keystroke::plain(KSemiColon),
+            'M' => Keystroke::shifted(KSemiColon),
+            '&' => Keystroke::plain(K1),
+            'é' => Keystroke::plain(K2),
+            '"' => Keystroke::plain(K3),
+            '\'' => Keystroke::plain(K4),
+            '(' => Keystroke::plain(K5),
+            '-' => Keystroke::plain(K6),
+            'è' => Keystroke::plain(K7),
+            '_' => Keystroke::plain(K8),
+            'ç' => Keystroke::plain(K9),
+            'à' => Keystroke::plain(K0),
+            '#' => Keystroke::alt_gr(K3),
+            '{' => Keystroke::alt_gr(K4),
+            '[' => Keystroke::alt_gr(K5),
+            '|' => Keystroke::alt_gr(K6),
+            '\\' => Keystroke::alt_gr(K8),
+            '@' => Keystroke::alt_gr(K0),
+            ')' => Keystroke::plain(KDash),
+            '°' => Keystroke::shifted(KDash),
+            ']' => Keystroke::alt_gr(KDash),
+            '=' => Keystroke::plain(KEqual),
+            '+' => Keystroke::shifted(KEqual),
+            '}' => Keystroke::alt_gr(KEqual),
+            '$' => Keystroke::plain(KRightBracket),
+            '£' => Keystroke::shifted(KRightBracket),
+            'ù' => Keystroke::plain(KQuote),
+            '%' => Keystroke::shifted(KQuote),
+            '*' => Keystroke::plain(KNonUSPound),
+            'µ' => Keystroke::shifted(KNonUSPound),
+            '<' => Keystroke::plain(KNonUSBackslash),
+            '>' => Keystroke::shifted(KNonUSBackslash),
+            ',' => Keystroke::plain(KM),
+            '?' => Keystroke::shifted(KM),
+            ';' => Keystroke::plain(KComma),
+            '.' => Keystroke::shifted(KComma),
+            ':' => Keystroke::plain(KDot),
+            '/' => Keystroke::shifted(KDot),
+            '!' => Keystroke::plain(KSlash),
+            '§' => Keystroke::shifted(KSlash),
+            '€' => Keystroke::alt_gr(KE),
+            _ => return None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    #[test]
+    fn us_ascii() {
+        let strokes: Result<Vec<_>, _> = keystrokes(&Us, "Hi @1\n").collect();
+        assert_eq!(
+            strokes.unwrap(),
+            [
+                Keystroke::shifted(KeyCode::KH),
+                Keystroke::plain(KeyCode::KI),
+                Keystroke::plain(KeyCode::KSpaceBar),
+                Keystroke::shifted(KeyCode::K2),
+                Keystroke::plain(KeyCode::K1),
+                Keystroke::plain(KeyCode::KEnter),
+            ]
+        );
+
+        // Every printable ASCII character can be typed.
+        assert!((' '..='~').all(|c| Us.keystroke(c).is_some()));
+        assert_eq!(Us.keystroke('é'), None);
+    }
+
+    #[test]
+    fn non_us_layouts() {
+        assert_eq!(German.keystroke('@'), Some(Keystroke::alt_gr(KeyCode::KQ)));
+        assert_eq!(German.keystroke('z'), Some(Keystroke::plain(KeyCode::KY)));
+        assert_eq!(German.keystroke('Y'), Some(Keystroke::shifted(KeyCode::KZ)));
+        assert_eq!(Uk.keystroke('@'), Some(Keystroke::shifted(KeyCode::KQuote)));
+        assert_eq!(Uk.keystroke('a'), Us.keystroke('a'));
+        assert_eq!(French.keystroke('a'), Some(Keystroke::plain(KeyCode::KQ)));
+        assert_eq!(
+            French.keystroke('M'),
+            Some(Keystroke::shifted(KeyCode::KSemiColon))
+        );
+        assert_eq!(French.keystroke('1'), Some(Keystroke::shifted(KeyCode::K1)));
+
+        let layout: &dyn HostLayout = &German;
+        assert_eq!(keystrokes(&layout, "^").collect::<Vec<_>>(), [Err('^')]);
+    }
+
+    #[test]
+    fn keystroke_usages() {
+        let stroke = Keystroke::new(Keystroke::SHIFT | Keystroke::ALT_GR, KeyCode::K7);
+        assert!(stroke.usages().eq([
+            Usage::keyboard(0xe1),
+            Usage::keyboard(0xe6),
+            Usage::from(KeyCode::K7)
+        ]));
+    }
+}
@@ -0,0 +1,226 @@
+//! Minimal JSON reader for the keymap and layout importers.
+//!
+//! Object keys may also be left unquoted, as in the raw data of
+//! keyboard-layout-editor.
+
+use std::string::String;
+use std::vec::Vec;
+
+/// JSON value, keeping only what the importers need.
+pub(crate) enum Value {
+    String(String),
+    Number(f64),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+    /// Booleans and null
+    Other,
+}
+
+impl Value {
+    /// Value of the field `key` of an object.
+    pub(crate) fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Self::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// The string, if this is one.
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    /// The number, if this is one.
+    pub(crate) fn as_number(&self) -> Option<f64> {
+        match self {
+            Self::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+}
+
+/// Parse `json` as a single value, returning the byte offset of the
+/// error if it is not valid.
+pub(crate) fn parse(json: &str) -> Result<Value, usize> {
+    let mut parser = Parser { json, offset: 0 };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+
+    if parser.offset == json.len() {
+        Ok(value)
+    } else {
+        Err(parser.offset)
+    }
+}
+
+/// Recursive descent JSON parser.
+struct Parser<'a> {
+    json: &'a str,
+    offset: usize,
+}
+
+impl Parser<'_> {
+    fn value(&mut self) -> Result<Value, usize> {
+        self.skip_whitespace();
+
+        match self.peek() {
+            Some('"') => self.string().map(Value::String),
+            Some('[') => {
+                self.offset += 1;
+                let mut values = Vec::new();
+                if !self.eat(']') {
+                    loop {
+                        values.push(self.value()?);
+                        if self.eat(']') {
+                            break;
+                        }
+                        self.expect(',')?;
+                    }
+                }
+                Ok(Value::Array(values))
+            }
+            Some('{') => {
+                self.offset += 1;
+                let mut fields = Vec::new();
+                if !self.eat('}') {
+                    loop {
+                        self.skip_whitespace();
+                        let key = match self.peek() {
+                            Some('"') => self.string()?,
+                            _ => String::from(self.token()),
+                        };
+                        if key.is_empty() {
+                            return Err(self.offset);
+                        }
+                        self.expect(':')?;
+                        fields.push((key, self.value()?));
+                        if self.eat('}') {
+                            break;
+                        }
+                        self.expect(',')?;
+                    }
+                }
+                Ok(Value::Object(fields))
+            }
+            _ => {
+                let start = self.offset;
+                let token = self.token();
+
+                if matches!(token, "true" | "false" | "null") {
+                    return Ok(Value::Other);
+                }
+                match token.parse() {
+                    Ok(number) if !token.starts_with(char::is_alphabetic) => {
+                        Ok(Value::Number(number))
+                    }
+                    _ => Err(start),
+                }
+            }
+        }
+    }
+
+    /// Consume a bare word: a number, literal or unquoted key.
+    fn token(&mut self) -> &str {
+        let rest = &self.json[self.offset..];
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || "+-._".contains(c)))
+            .unwrap_or(rest.len());
+        self.offset += len;
+
+        &rest[..len]
+    }
+
+    fn string(&mut self) -> Result<String, usize> {
+        self.expect('"')?;
+        let mut string = String::new();
+
+        loop {
+            let c = self.peek().ok_or(self.offset)?;
+            self.offset += c.len_utf8();
+
+            match c {
+                '"' => return Ok(string),
+                '\\' => {
+                    let escape = self.peek().ok_or(self.offset)?;
+                    self.offset += 1;
+                    string.push(match escape {
+                        '"' | '\\' | '/' => escape,
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'u' => {
+                            let c = self
+                                .json
+                                .get(self.offset..self.offset + 4)
+                                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                                .and_then(char::from_u32)
+                                .ok_or(self.offset)?;
+                            self.offset += 4;
+                            c
+                        }
+                        _ => return Err(self.offset),
+                    });
+                }
+                c if c.is_control() => return Err(self.offset),
+                c => string.push(c),
+            }
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.json[self.offset..];
+        self.offset += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.json[self.offset..].chars().next()
+    }
+
+    /// Consume `c` after whitespace, if it comes next.
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        let next = self.peek() == Some(c);
+        if next {
+            self.offset += c.len_utf8();
+        }
+        next
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), usize> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.offset)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_values() {
+        let value = parse(r#" {"a": [1, -2.5e1, true, null], b: "x\"é\n", "c": {}} "#)
+            .ok()
+            .unwrap();
+
+        let Some(Value::Array(a)) = value.get("a") else {
+            panic!("expected an array");
+        };
+        assert_eq!(a[1].as_number(), Some(-25.0));
+        assert!(matches!(a[2], Value::Other));
+        assert_eq!(value.get("b").and_then(Value::as_str), Some("x\"é\n"));
+        assert!(matches!(value.get("c"), Some(Value::Object(fields)) if fields.is_empty()));
+
+        assert_eq!(parse("[1, 2,]").err(), Some(6));
+        assert_eq!(parse("[1] 2").err(), Some(4));
+        assert_eq!(parse(r#"{"a" 1}"#).err(), Some(5));
+        assert_eq!(parse("[nope]").err(), Some(1));
+    }
+}
@@ -0,0 +1,558 @@
+//! Conversion of keyberon keymaps.
+//!
+//! Firmware moving over from [keyberon](https://docs.rs/keyberon/0.1) keeps
+//! its keymap: its [`Action`](kb::action::Action)s and
+//! [`Layers`](kb::layout::Layers) convert to this crate's [`Action`]s and
+//! [`Keymap`], and back, as do the matrix [`Event`]s
+//! fed to a keyberon [`Layout`](kb::layout::Layout) and [`KeyEvent`]s.
+//!
+//! | keyberon                               | embedded-keyboard               |
+//! |----------------------------------------|---------------------------------|
+//! | `NoOp`, `Trans`                        | `NoOp`, `Transparent`           |
+//! | `KeyCode(k)`                           | `Key(k)`                        |
+//! | `MultipleKeyCodes`, modifiers and a key | `Chord`                        |
+//! | `Layer(n)`                             | `MomentaryLayer(n)`             |
+//! | `HoldTap` holding a modifier or layer  | `HoldTap`                       |
+//!
+//! Other actions have no counterpart and fail to convert. Hold-taps of
+//! this crate share the tapping term of the [`Engine`](crate::engine::Engine)
+//! instead of a timeout of their own: keyberon hold-taps lose their
+//! timeout, and get [`DEFAULT_TAPPING_TERM`] when converted back. Chords
+//! only convert from keyberon, whose keys list `'static` slices.
+//!
+//! ```
+//! use embedded_keyboard::keyberon::keymap;
+//! use embedded_keyboard::{Action, Coordinate, KeyCode, Keymap};
+//! use keyberon::action::{k, l, Action::HoldTap};
+//! use keyberon::key_code::KeyCode::{LShift, A, B};
+//! use keyberon::layout::Layers;
+//!
+//! static LAYERS: Layers = &[
+//!     &[&[k(A), l(1)]],
+//!     &[&[HoldTap { timeout: 200, hold: &k(LShift), tap: &k(B) }, k(B)]],
+//! ];
+//!
+//! let keymap: Keymap<2, 1, 2> = keymap(LAYERS)?;
+//! assert_eq!(
+//!     keymap.action(1, Coordinate::new(0, 0)),
+//!     Some(Action::mod_tap(KeyCode::KpLeftShift, KeyCode::KB))
+//! );
+//! # Ok::<(), embedded_keyboard::keyberon::KeyberonError>(())
+//! ```
+
+use ::keyberon as kb;
+use kb::action::Action as KbAction;
+use kb::key_code::KeyCode as K;
+use kb::layout::Event;
+
+use crate::engine::DEFAULT_TAPPING_TERM;
+use crate::host::Keystroke;
+use crate::{Action, Coordinate, Hold, HoldTap, KeyCode, KeyEvent, Keymap};
+
+/// Errors produced while converting from or to keyberon types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum KeyberonError {
+    /// The keycode, action or event has no counterpart
+    Unsupported,
+    /// The layers are not of the size of the keymap
+    SizeMismatch,
+}
+
+impl core::fmt::Display for KeyberonError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Unsupported => write!(f, "no counterpart in the other crate"),
+            Self::SizeMismatch => write!(f, "layers not of the size of the keymap"),
+        }
+    }
+}
+
+/// Every keyberon keycode.
+const CODES: [K; 193] = [
+    K::No,
+    K::ErrorRollOver,
+    K::PostFail,
+    K::ErrorUndefined,
+    K::A,
+    K::B,
+    K::C,
+    K::D,
+    K::E,
+    K::F,
+    K::G,
+    K::H,
+    K::I,
+    K::J,
+    K::K,
+    K::L,
+    K::M,
+    K::N,
+    K::O,
+    K::P,
+    K::Q,
+    K::R,
+    K::S,
+    K::T,
+    K::U,
+    K::V,
+    K::W,
+    K::X,
+    K::Y,
+    K::Z,
+    K::Kb1,
+    K::Kb2,
+    K::Kb3,
+    K::Kb4,
+    K::Kb5,
+    K::Kb6,
+    K::Kb7,
+    K::Kb8,
+    K::Kb9,
+    K::Kb0,
+    K::Enter,
+    K::Escape,
+    K::BSpace,
+    K::Tab,
+    K::Space,
+    K::Minus,
+    K::Equal,
+    K::LBracket,
+    K::RBracket,
+    K::Bslash,
+    K::NonUsHash,
+    K::SColon,
+    K::Quote,
+    K::Grave,
+    K::Comma,
+    K::Dot,
+    K::Slash,
+    K::CapsLock,
+    K::F1,
+    K::F2,
+    K::F3,
+    K::F4,
+    K::F5,
+    K::F6,
+    K::F7,
+    K::F8,
+    K::F9,
+    K::F10,
+    K::F11,
+    K::F12,
+    K::PScreen,
+    K::ScrollLock,
+    K::Pause,
+    K::Insert,
+    K::Home,
+    K::PgUp,
+    K::Delete,
+    K::End,
+    K::PgDown,
+    K::Right,
+    K::Left,
+    K::Down,
+    K::Up,
+    K::NumLock,
+    K::KpSlash,
+    K::KpAsterisk,
+    K::KpMinus,
+    K::KpPlus,
+    K::KpEnter,
+    K::Kp1,
+    K::Kp2,
+    K::Kp3,
+    K::Kp4,
+    K::Kp5,
+    K::Kp6,
+    K::Kp7,
+    K::Kp8,
+    K::Kp9,
+    K::Kp0,
+    K::KpDot,
+    K::NonUsBslash,
+    K::Application,
+    K::Power,
+    K::KpEqual,
+    K::F13,
+    K::F14,
+    K::F15,
+    K::F16,
+    K::F17,
+    K::F18,
+    K::F19,
+    K::F20,
+    K::F21,
+    K::F22,
+    K::F23,
+    K::F24,
+    K::Execute,
+    K::Help,
+    K::Menu,
+    K::Select,
+    K::Stop,
+    K::Again,
+    K::Undo,
+    K::Cut,
+    K::Copy,
+    K::Paste,
+    K::Find,
+    K::Mute,
+    K::VolUp,
+    K::VolDown,
+    K::LockingCapsLock,
+    K::LockingNumLock,
+    K::LockingScrollLock,
+    K::KpComma,
+    K::KpEqualSign,
+    K::Intl1,
+    K::Intl2,
+    K::Intl3,
+    K::Intl4,
+    K::Intl5,
+    K::Intl6,
+    K::Intl7,
+    K::Intl8,
+    K::Intl9,
+    K::Lang1,
+    K::Lang2,
+    K::Lang3,
+    K::Lang4,
+    K::Lang5,
+    K::Lang6,
+    K::Lang7,
+    K::Lang8,
+    K::Lang9,
+    K::AltErase,
+    K::SysReq,
+    K::Cancel,
+    K::Clear,
+    K::Prior,
+    K::Return,
+    K::Separator,
+    K::Out,
+    K::Oper,
+    K::ClearAgain,
+    K::CrSel,
+    K::ExSel,
+    K::LCtrl,
+    K::LShift,
+    K::LAlt,
+    K::LGui,
+    K::RCtrl,
+    K::RShift,
+    K::RAlt,
+    K::RGui,
+    K::MediaPlayPause,
+    K::MediaStopCD,
+    K::MediaPreviousSong,
+    K::MediaNextSong,
+    K::MediaEjectCD,
+    K::MediaVolUp,
+    K::MediaVolDown,
+    K::MediaMute,
+    K::MediaWWW,
+    K::MediaBack,
+    K::MediaForward,
+    K::MediaStop,
+    K::MediaFind,
+    K::MediaScrollUp,
+    K::MediaScrollDown,
+    K::MediaEdit,
+    K::MediaSleep,
+    K::MeidaCoffee,
+    K::MediaRefresh,
+    K::MediaCalc,
+];
+
+/// A keycode action for every keyberon keycode, for hold-taps to refer to.
+static KEYS: [KbAction; CODES.len()] = {
+    let mut keys = [KbAction::NoOp; CODES.len()];
+    let mut i = 0;
+    while i < keys.len() {
+        keys[i] = KbAction::KeyCode(CODES[i]);
+        i += 1;
+    }
+    keys
+};
+
+/// A layer action for every layer, for hold-taps to refer to.
+static LAYERS: [KbAction; 256] = {
+    let mut layers = [KbAction::NoOp; 256];
+    let mut i = 0;
+    while i < layers.len() {
+        layers[i] = KbAction::Layer(i);
+        i += 1;
+    }
+    layers
+};
+
+impl TryFrom<K> for KeyCode {
+    type Error = KeyberonError;
+
+    fn try_from(code: K) -> Result<Self, Self::Error> {
+        Self::try_from(u16::from(code as u8)).map_err(|_| KeyberonError::Unsupported)
+    }
+}
+
+impl TryFrom<KeyCode> for K {
+    type Error = KeyberonError;
+
+    fn try_from(code: KeyCode) -> Result<Self, Self::Error> {
+        key_action(code).and_then(|action| match action {
+            KbAction::KeyCode(code) => Ok(*code),
+            _ => Err(KeyberonError::Unsupported),
+        })
+    }
+}
+
+impl TryFrom<KbAction> for Action {
+    type Error = KeyberonError;
+
+    fn try_from(action: KbAction) -> Result<Self, Self::Error> {
+        Ok(match action {
+            KbAction::NoOp => Self::NoOp,
+            KbAction::Trans => Self::Transparent,
+            KbAction::KeyCode(code) => Self::Key(code.try_into()?),
+            KbAction::MultipleKeyCodes(codes) => Self::Chord(chord(codes)?),
+            KbAction::Layer(layer) => Self::MomentaryLayer(layer_number(layer)?),
+            KbAction::HoldTap { hold, tap, .. } => {
+                let hold = match *hold {
+                    KbAction::KeyCode(code) => Hold::Key(code.try_into()?),
+                    KbAction::Layer(layer) => Hold::Layer(layer_number(layer)?),
+                    _ => return Err(KeyberonError::Unsupported),
+                };
+                let KbAction::KeyCode(tap) = *tap else {
+                    return Err(KeyberonError::Unsupported);
+                };
+                Self::HoldTap(HoldTap {
+                    hold,
+                    tap: tap.try_into()?,
+                })
+            }
+            _ => return Err(KeyberonError::Unsupported),
+        })
+    }
+}
+
+impl TryFrom<Action> for KbAction {
+    type Error = KeyberonError;
+
+    fn try_from(action: Action) -> Result<Self, Self::Error> {
+        Ok(match action {
+            Action::NoOp => Self::NoOp,
+            Action::Transparent => Self::Trans,
+            Action::Key(code) => *key_action(code)?,
+            Action::MomentaryLayer(layer) => Self::Layer(layer.into()),
+            Action::HoldTap(HoldTap { hold, tap }) => Self::HoldTap {
+                timeout: DEFAULT_TAPPING_TERM,
+                hold: match hold {
+                    Hold::Key(code) => key_action(code)?,
+                    Hold::Layer(layer) => &LAYERS[usize::from(layer)],
+                },
+                tap: key_action(tap)?,
+            },
+            _ => return Err(KeyberonError::Unsupported),
+        })
+    }
+}
+
+impl From<Event> for KeyEvent {
+    fn from(event: Event) -> Self {
+        match event {
+            Event::Press(row, col) => Self::KeyDown(Coordinate::from((row, col))),
+            Event::Release(row, col) => Self::KeyUp(Coordinate::from((row, col))),
+        }
+    }
+}
+
+impl TryFrom<KeyEvent> for Event {
+    type Error = KeyberonError;
+
+    /// Convert a key event, failing for [`KeyEvent::NoEvent`] and keys
+    /// beyond row or column 255.
+    fn try_from(event: KeyEvent) -> Result<Self, Self::Error> {
+        let coordinates = |c: Coordinate| {
+            let row = u8::try_from(c.row()).map_err(|_| KeyberonError::Unsupported)?;
+            let col = u8::try_from(c.col()).map_err(|_| KeyberonError::Unsupported)?;
+            Ok((row, col))
+        };
+
+        match event {
+            KeyEvent::KeyDown(c) => coordinates(c).map(|(row, col)| Self::Press(row, col)),
+            KeyEvent::KeyUp(c) => coordinates(c).map(|(row, col)| Self::Release(row, col)),
+            KeyEvent::NoEvent => Err(KeyberonError::Unsupported),
+        }
+    }
+}
+
+/// Convert keyberon `layers`, indexed by layer, row and column, into a
+/// keymap of the same size.
+///
+/// # Errors
+///
+/// Fails if the layers are not `LAYERS` by `ROWS` by `COLS`, or hold an
+/// action without a counterpart.
+pub fn keymap<const LAYERS: usize, const ROWS: usize, const COLS: usize>(
+    layers: &[&[&[KbAction]]],
+) -> Result<Keymap<LAYERS, ROWS, COLS>, KeyberonError> {
+    if layers.len() != LAYERS
+        || layers
+            .iter()
+            .flat_map(|rows| rows.iter())
+            .any(|cols| cols.len() != COLS)
+        || layers.iter().any(|rows| rows.len() != ROWS)
+    {
+        return Err(KeyberonError::SizeMismatch);
+    }
+
+    let mut keymap = Keymap::new([[[Action::NoOp; COLS]; ROWS]; LAYERS]);
+    for (layer, rows) in layers.iter().enumerate() {
+        for (row, cols) in rows.iter().enumerate() {
+            for (col, action) in cols.iter().enumerate() {
+                if let Some(slot) = keymap.action_mut(layer, Coordinate::new(row, col)) {
+                    *slot = (*action).try_into()?;
+                }
+            }
+        }
+    }
+
+    Ok(keymap)
+}
+
+/// Convert `keymap` into keyberon layers, indexed by layer, row and
+/// column, to be sliced into keyberon [`Layers`](kb::layout::Layers).
+///
+/// # Errors
+///
+/// Fails if the keymap holds an action without a counterpart.
+pub fn layers<const LAYERS: usize, const ROWS: usize, const COLS: usize>(
+    keymap: &Keymap<LAYERS, ROWS, COLS>,
+) -> Result<[[[KbAction; COLS]; ROWS]; LAYERS], KeyberonError> {
+    let mut layers = [[[KbAction::NoOp; COLS]; ROWS]; LAYERS];
+    for (layer, coordinate, action) in keymap.iter() {
+        layers[layer][coordinate.row()][coordinate.col()] = action.try_into()?;
+    }
+
+    Ok(layers)
+}
+
+/// Keycode action of the keyberon keycode for `code`.
+fn key_action(code: KeyCode) -> Result<&'static KbAction, KeyberonError> {
+    let usage = u8::try_from(u16::from(code)).map_err(|_| KeyberonError::Unsupported)?;
+    CODES
+        .iter()
+        .position(|code| *code as u8 == usage)
+        .map(|i| &KEYS[i])
+        .ok_or(KeyberonError::Unsupported)
+}
+
+/// Keystroke of the modifiers and single key of `codes`.
+fn chord(codes: &[K]) -> Result<Keystroke, KeyberonError> {
+    let mut modifiers = 0;
+    let mut key = None;
+
+    for code in codes {
+        if code.is_modifier() {
+            modifiers |= code.as_modifier_bit();
+        } else if key.replace(KeyCode::try_from(*code)?).is_some() {
+            return Err(KeyberonError::Unsupported);
+        }
+    }
+
+    key.map(|key| Keystroke::new(modifiers, key))
+        .ok_or(KeyberonError::Unsupported)
+}
+
+/// Layer number of this crate for keyberon `layer`.
+fn layer_number(layer: usize) -> Result<u8, KeyberonError> {
+    u8::try_from(layer).map_err(|_| KeyberonError::Unsupported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keymap;
+    use kb::action::{d, k, l, m};
+
+    const KEYMAP: Keymap<2, 1, 3> = keymap! {
+        { [KA {Action::mod_tap(KeyCode::KpLeftShift, KeyCode::KB)} (1)] }
+        { [_______ {Action::layer_tap(1, KeyCode::KSpaceBar)} XXXXXXX] }
+    };
+
+    #[test]
+    fn keymap_round_trip() {
+        static MOD_TAP: KbAction = KbAction::HoldTap {
+            timeout: DEFAULT_TAPPING_TERM,
+            hold: &k(K::LShift),
+            tap: &k(K::B),
+        };
+
+        let converted = layers(&KEYMAP).unwrap();
+        let rows: [[&[KbAction]; 1]; 2] = [[&converted[0][0]], [&converted[1][0]]];
+        let layers: [&[&[KbAction]]; 2] = [&rows[0], &rows[1]];
+
+        assert_eq!(converted[0], [[k(K::A), MOD_TAP, l(1)]]);
+        assert_eq!(keymap(&layers), Ok(KEYMAP));
+    }
+
+    #[test]
+    fn actions_round_trip() {
+        static HOLD_TAP: KbAction = KbAction::HoldTap {
+            timeout: DEFAULT_TAPPING_TERM,
+            hold: &l(2),
+            tap: &k(K::Escape),
+        };
+
+        for action in [KbAction::NoOp, KbAction::Trans, k(K::RGui), l(3), HOLD_TAP] {
+            let converted = Action::try_from(action).unwrap();
+            assert_eq!(KbAction::try_from(converted), Ok(action));
+        }
+    }
+
+    #[test]
+    fn chords_from_keyberon() {
+        assert_eq!(
+            Action::try_from(m(&[K::LCtrl, K::LShift, K::Escape])),
+            Ok(Action::chord(0x03, KeyCode::KEscape))
+        );
+        assert_eq!(
+            Action::try_from(m(&[K::A, K::B])),
+            Err(KeyberonError::Unsupported)
+        );
+        assert_eq!(
+            KbAction::try_from(Action::chord(0x03, KeyCode::KEscape)),
+            Err(KeyberonError::Unsupported)
+        );
+    }
+
+    #[test]
+    fn unsupported_actions() {
+        assert_eq!(Action::try_from(d(1)), Err(KeyberonError::Unsupported));
+        assert_eq!(
+            Action::try_from(k(K::MediaCalc)),
+            Err(KeyberonError::Unsupported)
+        );
+        assert_eq!(
+            KbAction::try_from(Action::ToggleLayer(1)),
+            Err(KeyberonError::Unsupported)
+        );
+        assert_eq!(
+            keymap::<2, 1, 3>(&[&[&[KbAction::NoOp; 3]]]),
+            Err(KeyberonError::SizeMismatch)
+        );
+    }
+
+    #[test]
+    fn events_round_trip() {
+        let down = KeyEvent::KeyDown(Coordinate::new(2, 7));
+        let up = KeyEvent::KeyUp(Coordinate::new(0, 255));
+
+        assert_eq!(Event::try_from(down), Ok(Event::Press(2, 7)));
+        assert_eq!(KeyEvent::from(Event::try_from(up).unwrap()), up);
+        assert_eq!(
+            Event::try_from(KeyEvent::KeyDown(Coordinate::new(256, 0))),
+            Err(KeyberonError::Unsupported)
+        );
+    }
+}
@@ -1,6 +1,11 @@
+use core::ops::RangeInclusive;
+
+use crate::geometry::Coordinate;
+
 /// Key Events
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum KeyEvent {
     /// No event to report
     NoEvent,
@@ -10,24 +15,10 @@ pub enum KeyEvent {
     KeyUp(Coordinate),
 }
 
-/// Key coordinates
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
-#[cfg_attr(feature = "defmt", derive(defmt::Format))]
-pub struct Coordinate {
-    row: usize,
-    col: usize,
-}
-
-impl Coordinate {
-    /// Create a new `Coordinate' instance
-    pub fn new(row: usize, col: usize) -> Self {
-        Self { row, col }
-    }
-}
-
 /// Representation for all Keycodes.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u16)]
 #[non_exhaustive]
 pub enum KeyCode {
@@ -276,3 +267,634 @@ pub enum KeyCode {
     KpRightGUI = 0x00e7,
     // e8 - ffff: Reserved
 }
+
+impl KeyCode {
+    /// Status usages reported instead of keys: no event and the error codes.
+    pub const STATUS: RangeInclusive<u16> = 0x0000..=0x0003;
+    /// Letters `A` to `Z`.
+    pub const LETTERS: RangeInclusive<u16> = 0x0004..=0x001d;
+    /// Digits `1` to `0` on the number row.
+    pub const DIGITS: RangeInclusive<u16> = 0x001e..=0x0027;
+    /// Enter, Escape, Backspace, Tab and the space bar.
+    pub const CONTROL: RangeInclusive<u16> = 0x0028..=0x002c;
+    /// Punctuation on the main block, `-` to `/`.
+    pub const PUNCTUATION: RangeInclusive<u16> = 0x002d..=0x0038;
+    /// Function keys `F1` to `F12`.
+    pub const FUNCTION_LOW: RangeInclusive<u16> = 0x003a..=0x0045;
+    /// Function keys `F13` to `F24`.
+    pub const FUNCTION_HIGH: RangeInclusive<u16> = 0x0068..=0x0073;
+    /// Insert, Home, Page Up, Delete, End and Page Down.
+    pub const EDITING: RangeInclusive<u16> = 0x0049..=0x004e;
+    /// Arrow keys.
+    pub const ARROWS: RangeInclusive<u16> = 0x004f..=0x0052;
+    /// Keypad keys, from Keypad `/` to Keypad `.`.
+    pub const KEYPAD: RangeInclusive<u16> = 0x0054..=0x0063;
+    /// Extended keypad keys, from Keypad `00` to Keypad Hexadecimal.
+    pub const KEYPAD_EXTENDED: RangeInclusive<u16> = 0x00b0..=0x00dd;
+    /// International and language keys.
+    pub const INTERNATIONAL: RangeInclusive<u16> = 0x0087..=0x0098;
+    /// Modifier keys.
+    pub const MODIFIERS: RangeInclusive<u16> = 0x00e0..=0x00e7;
+
+    /// Class this keycode belongs to.
+    #[inline]
+    pub fn class(self) -> KeyCodeClass {
+        KeyCodeClass::of(self)
+    }
+
+    /// Whether this keycode is one of the eight modifiers.
+    #[inline]
+    pub fn is_modifier(self) -> bool {
+        Self::MODIFIERS.contains(&(self as u16))
+    }
+
+    /// QMK name of this keycode, if QMK has one.
+    ///
+    /// QMK reuses parts of the usage range for its own keycodes, so the
+    /// error usages and the extended keypad have no name.
+    pub const fn qmk_name(self) -> Option<&'static str> {
+        Some(match self {
+            Self::NoEvent => "KC_NO",
+            Self::KA => "KC_A",
+            Self::KB => "KC_B",
+            Self::KC => "KC_C",
+            Self::KD => "KC_D",
+            Self::KE => "KC_E",
+            Self::KF => "KC_F",
+            Self::KG => "KC_G",
+            Self::KH => "KC_H",
+            Self::KI => "KC_I",
+            Self::KJ => "KC_J",
+            Self::KK => "KC_K",
+            Self::KL => "KC_L",
+            Self::KM => "KC_M",
+            Self::KN => "KC_N",
+            Self::KO => "KC_O",
+            Self::KP => "KC_P",
+            Self::KQ => "KC_Q",
+            Self::KR => "KC_R",
+            Self::KS => "KC_S",
+            Self::KT => "KC_T",
+            Self::KU => "KC_U",
+            Self::KV => "KC_V",
+            Self::KW => "KC_W",
+            Self::KX => "KC_X",
+            Self::KY => "KC_Y",
+            Self::KZ => "KC_Z",
+            Self::K1 => "KC_1",
+            Self::K2 => "KC_2",
+            Self::K3 => "KC_3",
+            Self::K4 => "KC_4",
+            Self::K5 => "KC_5",
+            Self::K6 => "KC_6",
+            Self::K7 => "KC_7",
+            Self::K8 => "KC_8",
+            Self::K9 => "KC_9",
+            Self::K0 => "KC_0",
+            Self::KEnter => "KC_ENT",
+            Self::KEscape => "KC_ESC",
+            Self::KBackspace => "KC_BSPC",
+            Self::KTab => "KC_TAB",
+            Self::KSpaceBar => "KC_SPC",
+            Self::KDash => "KC_MINS",
+            Self::KEqual => "KC_EQL",
+            Self::KLeftBracket => "KC_LBRC",
+            Self::KRightBracket => "KC_RBRC",
+            Self::KBackslash => "KC_BSLS",
+            Self::KNonUSPound => "KC_NUHS",
+            Self::KSemiColon => "KC_SCLN",
+            Self::KQuote => "KC_QUOT",
+            Self::KGrave => "KC_GRV",
+            Self::KComma => "KC_COMM",
+            Self::KDot => "KC_DOT",
+            Self::KSlash => "KC_SLSH",
+            Self::KCapsLock => "KC_CAPS",
+            Self::KF1 => "KC_F1",
+            Self::KF2 => "KC_F2",
+            Self::KF3 => "KC_F3",
+            Self::KF4 => "KC_F4",
+            Self::KF5 => "KC_F5",
+            Self::KF6 => "KC_F6",
+            Self::KF7 => "KC_F7",
+            Self::KF8 => "KC_F8",
+            Self::KF9 => "KC_F9",
+            Self::KF10 => "KC_F10",
+            Self::KF11 => "KC_F11",
+            Self::KF12 => "KC_F12",
+            Self::KPrintScreen => "KC_PSCR",
+            Self::KScrollLock => "KC_SCRL",
+            Self::KPause => "KC_PAUS",
+            Self::KInsert => "KC_INS",
+            Self::KHome => "KC_HOME",
+            Self::KPageUp => "KC_PGUP",
+            Self::KDelete => "KC_DEL",
+            Self::KEnd => "KC_END",
+            Self::KPageDown => "KC_PGDN",
+            Self::KRightArrow => "KC_RGHT",
+            Self::KLeftArrow => "KC_LEFT",
+            Self::KDownArrow => "KC_DOWN",
+            Self::KUpArrow => "KC_UP",
+            Self::KpNumLock => "KC_NUM",
+            Self::KpSlash => "KC_PSLS",
+            Self::KpAsterisk => "KC_PAST",
+            Self::KpMinus => "KC_PMNS",
+            Self::KpPlus => "KC_PPLS",
+            Self::KpEnter => "KC_PENT",
+            Self::Kp1 => "KC_P1",
+            Self::Kp2 => "KC_P2",
+            Self::Kp3 => "KC_P3",
+            Self::Kp4 => "KC_P4",
+            Self::Kp5 => "KC_P5",
+            Self::Kp6 => "KC_P6",
+            Self::Kp7 => "KC_P7",
+            Self::Kp8 => "KC_P8",
+            Self::Kp9 => "KC_P9",
+            Self::Kp0 => "KC_P0",
+            Self::KpDot => "KC_PDOT",
+            Self::KNonUSBackslash => "KC_NUBS",
+            Self::KApplication => "KC_APP",
+            Self::KpEqual => "KC_PEQL",
+            Self::KF13 => "KC_F13",
+            Self::KF14 => "KC_F14",
+            Self::KF15 => "KC_F15",
+            Self::KF16 => "KC_F16",
+            Self::KF17 => "KC_F17",
+            Self::KF18 => "KC_F18",
+            Self::KF19 => "KC_F19",
+            Self::KF20 => "KC_F20",
+            Self::KF21 => "KC_F21",
+            Self::KF22 => "KC_F22",
+            Self::KF23 => "KC_F23",
+            Self::KF24 => "KC_F24",
+            Self::KExecute => "KC_EXEC",
+            Self::KHelp => "KC_HELP",
+            Self::KMenu => "KC_MENU",
+            Self::KSelect => "KC_SLCT",
+            Self::KStop => "KC_STOP",
+            Self::KAgain => "KC_AGIN",
+            Self::KUndo => "KC_UNDO",
+            Self::KCut => "KC_CUT",
+            Self::KCopy => "KC_COPY",
+            Self::KPaste => "KC_PSTE",
+            Self::KFind => "KC_FIND",
+            Self::KMute => "KC_KB_MUTE",
+            Self::KVolumeUp => "KC_KB_VOLUME_UP",
+            Self::KVolumeDown => "KC_KB_VOLUME_DOWN",
+            Self::KLockingCapsLock => "KC_LCAP",
+            Self::KLockingNumLock => "KC_LNUM",
+            Self::KLockingScrollLock => "KC_LSCR",
+            Self::KpComma => "KC_PCMM",
+            Self::KpEqualAS400 => "KC_KP_EQUAL_AS400",
+            Self::KIntl1 => "KC_INT1",
+            Self::KIntl2 => "KC_INT2",
+            Self::KIntl3 => "KC_INT3",
+            Self::KIntl4 => "KC_INT4",
+            Self::KIntl5 => "KC_INT5",
+            Self::KIntl6 => "KC_INT6",
+            Self::KIntl7 => "KC_INT7",
+            Self::KIntl8 => "KC_INT8",
+            Self::KIntl9 => "KC_INT9",
+            Self::KLang1 => "KC_LNG1",
+            Self::KLang2 => "KC_LNG2",
+            Self::KLang3 => "KC_LNG3",
+            Self::KLang4 => "KC_LNG4",
+            Self::KLang5 => "KC_LNG5",
+            Self::KLang6 => "KC_LNG6",
+            Self::KLang7 => "KC_LNG7",
+            Self::KLang8 => "KC_LNG8",
+            Self::KLang9 => "KC_LNG9",
+            Self::KAltErase => "KC_ERAS",
+            Self::KSysReq => "KC_SYRQ",
+            Self::KCancel => "KC_CNCL",
+            Self::KClear => "KC_CLR",
+            Self::KPrior => "KC_PRIR",
+            Self::KReturn => "KC_RETN",
+            Self::KSeparator => "KC_SEPR",
+            Self::KOut => "KC_OUT",
+            Self::KOper => "KC_OPER",
+            Self::KClearAgain => "KC_CLAG",
+            Self::KCrSel => "KC_CRSL",
+            Self::KExSel => "KC_EXSL",
+            Self::KpLeftControl => "KC_LCTL",
+            Self::KpLeftShift => "KC_LSFT",
+            Self::KpLeftAlt => "KC_LALT",
+            Self::KpLeftGUI => "KC_LGUI",
+            Self::KpRightControl => "KC_RCTL",
+            Self::KpRightShift => "KC_RSFT",
+            Self::KpRightAlt => "KC_RALT",
+            Self::KpRightGUI => "KC_RGUI",
+            _ => return None,
+        })
+    }
+}
+
+/// Coarse grouping of [`KeyCode`]s.
+///
+/// `KeyCode` is `#[non_exhaustive]`, so matching on it downstream always
+/// needs a wildcard arm, which silently swallows keycodes added later.
+/// Matching on the class of a keycode instead is exhaustive: new keycodes
+/// land in one of these classes and are handled by the existing arms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum KeyCodeClass {
+    /// No event and the error codes, see [`KeyCode::STATUS`]
+    Status,
+    /// See [`KeyCode::LETTERS`]
+    Letter,
+    /// See [`KeyCode::DIGITS`]
+    Digit,
+    /// See [`KeyCode::CONTROL`]
+    Control,
+    /// See [`KeyCode::PUNCTUATION`], plus Non-US `\`
+    Punctuation,
+    /// See [`KeyCode::FUNCTION_LOW`] and [`KeyCode::FUNCTION_HIGH`]
+    Function,
+    /// See [`KeyCode::EDITING`] and [`KeyCode::ARROWS`]
+    Navigation,
+    /// Caps, Num and Scroll Lock, including their locking variants
+    Lock,
+    /// See [`KeyCode::KEYPAD`] and [`KeyCode::KEYPAD_EXTENDED`], plus the
+    /// other keypad keys outside of those ranges
+    Keypad,
+    /// Mute and volume keys
+    Media,
+    /// See [`KeyCode::INTERNATIONAL`]
+    International,
+    /// See [`KeyCode::MODIFIERS`]
+    Modifier,
+    /// System and application keys, and anything else
+    System,
+}
+
+impl KeyCodeClass {
+    /// Class of `code`.
+    pub fn of(code: KeyCode) -> Self {
+        use KeyCode::*;
+
+        let usage = code as u16;
+
+        match code {
+            KCapsLock | KScrollLock | KpNumLock | KLockingCapsLock | KLockingNumLock
+            | KLockingScrollLock => return Self::Lock,
+            KNonUSBackslash => return Self::Punctuation,
+            KpEqual | KpComma | KpEqualAS400 => return Self::Keypad,
+            KMute | KVolumeUp | KVolumeDown => return Self::Media,
+            _ => {}
+        }
+
+        let ranges = [
+            (KeyCode::STATUS, Self::Status),
+            (KeyCode::LETTERS, Self::Letter),
+            (KeyCode::DIGITS, Self::Digit),
+            (KeyCode::CONTROL, Self::Control),
+            (KeyCode::PUNCTUATION, Self::Punctuation),
+            (KeyCode::FUNCTION_LOW, Self::Function),
+            (KeyCode::FUNCTION_HIGH, Self::Function),
+            (KeyCode::EDITING, Self::Navigation),
+            (KeyCode::ARROWS, Self::Navigation),
+            (KeyCode::KEYPAD, Self::Keypad),
+            (KeyCode::KEYPAD_EXTENDED, Self::Keypad),
+            (KeyCode::INTERNATIONAL, Self::International),
+            (KeyCode::MODIFIERS, Self::Modifier),
+        ];
+
+        ranges
+            .into_iter()
+            .find(|(range, _)| range.contains(&usage))
+            .map_or(Self::System, |(_, class)| class)
+    }
+}
+
+/// HID usage on any usage page.
+///
+/// Escape hatch for keys [`KeyCode`] does not enumerate, such as the
+/// Keyboard Power usage or anything on the Consumer page. Report builders
+/// pass usages on the pages they cover through as they are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Usage {
+    page: u16,
+    id: u16,
+}
+
+impl Usage {
+    /// Generic Desktop usage page, home of the system control usages.
+    pub const GENERIC_DESKTOP_PAGE: u16 = 0x01;
+    /// Keyboard/Keypad usage page, the page of every [`KeyCode`].
+    pub const KEYBOARD_PAGE: u16 = 0x07;
+    /// Button usage page, the buttons of a mouse.
+    pub const BUTTON_PAGE: u16 = 0x09;
+    /// Consumer usage page.
+    pub const CONSUMER_PAGE: u16 = 0x0c;
+
+    /// Create a usage `id` on usage `page`.
+    pub const fn new(page: u16, id: u16) -> Self {
+        Self { page, id }
+    }
+
+    /// Create a usage `id` on the Keyboard/Keypad page.
+    pub const fn keyboard(id: u16) -> Self {
+        Self::new(Self::KEYBOARD_PAGE, id)
+    }
+
+    /// Usage page.
+    pub const fn page(&self) -> u16 {
+        self.page
+    }
+
+    /// Usage id within the page.
+    pub const fn id(&self) -> u16 {
+        self.id
+    }
+
+    /// Usage id, if this usage is on the Keyboard/Keypad page.
+    pub const fn keyboard_id(&self) -> Option<u16> {
+        if self.page == Self::KEYBOARD_PAGE {
+            Some(self.id)
+        } else {
+            None
+        }
+    }
+}
+
+impl From<KeyCode> for Usage {
+    #[inline]
+    fn from(code: KeyCode) -> Self {
+        Self::keyboard(code as u16)
+    }
+}
+
+impl From<KeyCode> for u16 {
+    #[inline]
+    fn from(code: KeyCode) -> Self {
+        code as u16
+    }
+}
+
+impl TryFrom<u16> for KeyCode {
+    type Error = u16;
+
+    /// Convert a HID Keyboard/Keypad page usage into a `KeyCode`,
+    /// returning the usage back if it has no corresponding variant.
+    fn try_from(usage: u16) -> Result<Self, Self::Error> {
+        Ok(match usage {
+            0x0000 => Self::NoEvent,
+            0x0001 => Self::ErrorRollOver,
+            0x0002 => Self::PostFail,
+            0x0003 => Self::ErrorUndefined,
+            0x0004 => Self::KA,
+            0x0005 => Self::KB,
+            0x0006 => Self::KC,
+            0x0007 => Self::KD,
+            0x0008 => Self::KE,
+            0x0009 => Self::KF,
+            0x000a => Self::KG,
+            0x000b => Self::KH,
+            0x000c => Self::KI,
+            0x000d => Self::KJ,
+            0x000e => Self::KK,
+            0x000f => Self::KL,
+            0x0010 => Self::KM,
+            0x0011 => Self::KN,
+            0x0012 => Self::KO,
+            0x0013 => Self::KP,
+            0x0014 => Self::KQ,
+            0x0015 => Self::KR,
+            0x0016 => Self::KS,
+            0x0017 => Self::KT,
+            0x0018 => Self::KU,
+            0x0019 => Self::KV,
+            0x001a => Self::KW,
+            0x001b => Self::KX,
+            0x001c => Self::KY,
+            0x001d => Self::KZ,
+            0x001e => Self::K1,
+            0x001f => Self::K2,
+            0x0020 => Self::K3,
+            0x0021 => Self::K4,
+            0x0022 => Self::K5,
+            0x0023 => Self::K6,
+            0x0024 => Self::K7,
+            0x0025 => Self::K8,
+            0x0026 => Self::K9,
+            0x0027 => Self::K0,
+            0x0028 => Self::KEnter,
+            0x0029 => Self::KEscape,
+            0x002a => Self::KBackspace,
+            0x002b => Self::KTab,
+            0x002c => Self::KSpaceBar,
+            0x002d => Self::KDash,
+            0x002e => Self::KEqual,
+            0x002f => Self::KLeftBracket,
+            0x0030 => Self::KRightBracket,
+            0x0031 => Self::KBackslash,
+            0x0032 => Self::KNonUSPound,
+            0x0033 => Self::KSemiColon,
+            0x0034 => Self::KQuote,
+            0x0035 => Self::KGrave,
+            0x0036 => Self::KComma,
+            0x0037 => Self::KDot,
+            0x0038 => Self::KSlash,
+            0x0039 => Self::KCapsLock,
+            0x003a => Self::KF1,
+            0x003b => Self::KF2,
+            0x003c => Self::KF3,
+            0x003d => Self::KF4,
+            0x003e => Self::KF5,
+            0x003f => Self::KF6,
+            0x0040 => Self::KF7,
+            0x0041 => Self::KF8,
+            0x0042 => Self::KF9,
+            0x0043 => Self::KF10,
+            0x0044 => Self::KF11,
+            0x0045 => Self::KF12,
+            0x0046 => Self::KPrintScreen,
+            0x0047 => Self::KScrollLock,
+            0x0048 => Self::KPause,
+            0x0049 => Self::KInsert,
+            0x004a => Self::KHome,
+            0x004b => Self::KPageUp,
+            0x004c => Self::KDelete,
+            0x004d => Self::KEnd,
+            0x004e => Self::KPageDown,
+            0x004f => Self::KRightArrow,
+            0x0050 => Self::KLeftArrow,
+            0x0051 => Self::KDownArrow,
+            0x0052 => Self::KUpArrow,
+            0x0053 => Self::KpNumLock,
+            0x0054 => Self::KpSlash,
+            0x0055 => Self::KpAsterisk,
+            0x0056 => Self::KpMinus,
+            0x0057 => Self::KpPlus,
+            0x0058 => Self::KpEnter,
+            0x0059 => Self::Kp1,
+            0x005a => Self::Kp2,
+            0x005b => Self::Kp3,
+            0x005c => Self::Kp4,
+            0x005d => Self::Kp5,
+            0x005e => Self::Kp6,
+            0x005f => Self::Kp7,
+            0x0060 => Self::Kp8,
+            0x0061 => Self::Kp9,
+            0x0062 => Self::Kp0,
+            0x0063 => Self::KpDot,
+            0x0064 => Self::KNonUSBackslash,
+            0x0065 => Self::KApplication,
+            0x0067 => Self::KpEqual,
+            0x0068 => Self::KF13,
+            0x0069 => Self::KF14,
+            0x006a => Self::KF15,
+            0x006b => Self::KF16,
+            0x006c => Self::KF17,
+            0x006d => Self::KF18,
+            0x006e => Self::KF19,
+            0x006f => Self::KF20,
+            0x0070 => Self::KF21,
+            0x0071 => Self::KF22,
+            0x0072 => Self::KF23,
+            0x0073 => Self::KF24,
+            0x0074 => Self::KExecute,
+            0x0075 => Self::KHelp,
+            0x0076 => Self::KMenu,
+            0x0077 => Self::KSelect,
+            0x0078 => Self::KStop,
+            0x0079 => Self::KAgain,
+            0x007a => Self::KUndo,
+            0x007b => Self::KCut,
+            0x007c => Self::KCopy,
+            0x007d => Self::KPaste,
+            0x007e => Self::KFind,
+            0x007f => Self::KMute,
+            0x0080 => Self::KVolumeUp,
+            0x0081 => Self::KVolumeDown,
+            0x0082 => Self::KLockingCapsLock,
+            0x0083 => Self::KLockingNumLock,
+            0x0084 => Self::KLockingScrollLock,
+            0x0085 => Self::KpComma,
+            0x0086 => Self::KpEqualAS400,
+            0x0087 => Self::KIntl1,
+            0x0088 => Self::KIntl2,
+            0x0089 => Self::KIntl3,
+            0x008a => Self::KIntl4,
+            0x008b => Self::KIntl5,
+            0x008c => Self::KIntl6,
+            0x008d => Self::KIntl7,
+            0x008e => Self::KIntl8,
+            0x008f => Self::KIntl9,
+            0x0090 => Self::KLang1,
+            0x0091 => Self::KLang2,
+            0x0092 => Self::KLang3,
+            0x0093 => Self::KLang4,
+            0x0094 => Self::KLang5,
+            0x0095 => Self::KLang6,
+            0x0096 => Self::KLang7,
+            0x0097 => Self::KLang8,
+            0x0098 => Self::KLang9,
+            0x0099 => Self::KAltErase,
+            0x009a => Self::KSysReq,
+            0x009b => Self::KCancel,
+            0x009c => Self::KClear,
+            0x009d => Self::KPrior,
+            0x009e => Self::KReturn,
+            0x009f => Self::KSeparator,
+            0x00a0 => Self::KOut,
+            0x00a1 => Self::KOper,
+            0x00a2 => Self::KClearAgain,
+            0x00a3 => Self::KCrSel,
+            0x00a4 => Self::KExSel,
+            0x00b0 => Self::Kp00,
+            0x00b1 => Self::Kp000,
+            0x00b2 => Self::KpThousandsSeparator,
+            0x00b3 => Self::KpDecimalSeparator,
+            0x00b4 => Self::KpCurrencyUnit,
+            0x00b5 => Self::KpSubunit,
+            0x00b6 => Self::KpLeftParenthesis,
+            0x00b7 => Self::KpRightParenthesis,
+            0x00b8 => Self::KpLeftBrace,
+            0x00b9 => Self::KpRightBrace,
+            0x00ba => Self::KpTab,
+            0x00bb => Self::KpBackspace,
+            0x00bc => Self::KpA,
+            0x00bd => Self::KpB,
+            0x00be => Self::KpC,
+            0x00bf => Self::KpD,
+            0x00c0 => Self::KpE,
+            0x00c1 => Self::KpF,
+            0x00c2 => Self::KpXor,
+            0x00c3 => Self::KpCaret,
+            0x00c4 => Self::KpPercent,
+            0x00c5 => Self::KpLessThan,
+            0x00c6 => Self::KpGreaterThan,
+            0x00c7 => Self::KpAmpersand,
+            0x00c8 => Self::KpDoubleAmpersand,
+            0x00c9 => Self::KpVerticalPipe,
+            0x00ca => Self::KpDoubleVerticalPipe,
+            0x00cb => Self::KpColon,
+            0x00cc => Self::KpPound,
+            0x00cd => Self::KpSpace,
+            0x00ce => Self::KpAt,
+            0x00cf => Self::KpExclamationMark,
+            0x00d0 => Self::KpMemoryStore,
+            0x00d1 => Self::KpMemoryRecall,
+            0x00d2 => Self::KpMemoryClear,
+            0x00d3 => Self::KpMemoryAdd,
+            0x00d4 => Self::KpMemorySubtract,
+            0x00d5 => Self::KpMemoryMultiply,
+            0x00d6 => Self::KpMemoryDivide,
+            0x00d7 => Self::KpPlusMinus,
+            0x00d8 => Self::KpClear,
+            0x00d9 => Self::KpClearEntry,
+            0x00da => Self::KpBinary,
+            0x00db => Self::KpOctal,
+            0x00dc => Self::KpDecimal,
+            0x00dd => Self::KpHexadecimal,
+            0x00e0 => Self::KpLeftControl,
+            0x00e1 => Self::KpLeftShift,
+            0x00e2 => Self::KpLeftAlt,
+            0x00e3 => Self::KpLeftGUI,
+            0x00e4 => Self::KpRightControl,
+            0x00e5 => Self::KpRightShift,
+            0x00e6 => Self::KpRightAlt,
+            0x00e7 => Self::KpRightGUI,
+            _ => return Err(usage),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classes() {
+        assert_eq!(KeyCode::ErrorRollOver.class(), KeyCodeClass::Status);
+        assert_eq!(KeyCode::KQ.class(), KeyCodeClass::Letter);
+        assert_eq!(KeyCode::K0.class(), KeyCodeClass::Digit);
+        assert_eq!(KeyCode::KTab.class(), KeyCodeClass::Control);
+        assert_eq!(KeyCode::KNonUSBackslash.class(), KeyCodeClass::Punctuation);
+        assert_eq!(KeyCode::KF13.class(), KeyCodeClass::Function);
+        assert_eq!(KeyCode::KDelete.class(), KeyCodeClass::Navigation);
+        assert_eq!(KeyCode::KpNumLock.class(), KeyCodeClass::Lock);
+        assert_eq!(KeyCode::KpEqual.class(), KeyCodeClass::Keypad);
+        assert_eq!(KeyCode::KpHexadecimal.class(), KeyCodeClass::Keypad);
+        assert_eq!(KeyCode::KVolumeUp.class(), KeyCodeClass::Media);
+        assert_eq!(KeyCode::KLang9.class(), KeyCodeClass::International);
+        assert_eq!(KeyCode::KpRightGUI.class(), KeyCodeClass::Modifier);
+        assert_eq!(KeyCode::KPrintScreen.class(), KeyCodeClass::System);
+        assert!(KeyCode::KpLeftShift.is_modifier());
+        assert!(!KeyCode::KCapsLock.is_modifier());
+    }
+
+    #[test]
+    fn raw_round_trip() {
+        for usage in 0..=u16::from(u8::MAX) {
+            if let Ok(code) = KeyCode::try_from(usage) {
+                assert_eq!(u16::from(code), usage);
+            }
+        }
+
+        assert_eq!(KeyCode::try_from(0x0066), Err(0x0066));
+    }
+}
@@ -0,0 +1,916 @@
+use core::fmt;
+use core::str::FromStr;
+
+use crate::{
+    animation::AnimationCommand, backlight::BacklightCommand, host::Keystroke, rgb::RgbCommand,
+    Coordinate, KeyCode, Usage,
+};
+
+/// What a key does when pressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum Action {
+    /// Do nothing, and do not fall through to lower layers
+    NoOp,
+    /// Fall through to the next lower active layer
+    Transparent,
+    /// Report a keycode to the host while held
+    Key(KeyCode),
+    /// Report an arbitrary usage to the host while held
+    Usage(Usage),
+    /// Report a key together with modifiers while held, e.g. GUI+Shift+S
+    /// for a screenshot key. Modifiers held by other keys are left alone.
+    Chord(Keystroke),
+    /// Activate a layer while held
+    MomentaryLayer(u8),
+    /// Toggle a layer on press
+    ToggleLayer(u8),
+    /// Act as one thing when tapped and another when held
+    HoldTap(HoldTap),
+    /// Mirror the keyboard while held, see [`SwapAxis`](crate::engine::SwapAxis)
+    SwapHands,
+    /// Toggle mirroring the keyboard on press
+    ToggleSwapHands,
+    /// Change the backlight on press, see [`Backlight`](crate::backlight::Backlight)
+    Backlight(BacklightCommand),
+    /// Change the per-key lighting on press, see [`RgbMatrix`](crate::rgb::RgbMatrix)
+    Rgb(RgbCommand),
+    /// Change the lighting animation on press, see [`Animation`](crate::animation::Animation)
+    Animation(AnimationCommand),
+}
+
+/// What a [`HoldTap`] key does once it is held.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Hold {
+    /// Report a keycode, typically a modifier, while held
+    Key(KeyCode),
+    /// Activate a layer while held
+    Layer(u8),
+}
+
+/// Key sending `tap` when tapped, and acting as `hold` when held.
+///
+/// A key is considered held once it has been down for longer than the
+/// engine's tapping term, and tapped if it is released before that.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HoldTap {
+    /// Behaviour when held
+    pub hold: Hold,
+    /// Keycode sent when tapped
+    pub tap: KeyCode,
+}
+
+impl Action {
+    const NO_OP: u16 = 0x0000;
+    const TRANSPARENT: u16 = 0x0001;
+    const MOD_TAP: u16 = 0x2000;
+    const LAYER_TAP: u16 = 0x4000;
+    const MOMENTARY_LAYER: u16 = 0x5220;
+    const TOGGLE_LAYER: u16 = 0x5260;
+    const LAYER_MASK: u16 = 0x001f;
+    const SWAP_HANDS_TOGGLE: u16 = 0x56f0;
+    const SWAP_HANDS_MOMENTARY: u16 = 0x56f2;
+    const BACKLIGHT: u16 = 0x7800;
+    const RGB: u16 = 0x7820;
+    const ANIMATION: u16 = 0x7840;
+
+    /// QMK names of the modifiers, in the order of the modifier bitmap, as
+    /// a held modifier and as a chord.
+    const MOD_NAMES: [&'static str; 8] = [
+        "MOD_LCTL", "MOD_LSFT", "MOD_LALT", "MOD_LGUI", "MOD_RCTL", "MOD_RSFT", "MOD_RALT",
+        "MOD_RGUI",
+    ];
+    const CHORD_NAMES: [&'static str; 8] = [
+        "LCTL", "LSFT", "LALT", "LGUI", "RCTL", "RSFT", "RALT", "RGUI",
+    ];
+
+    /// QMK keycodes of system and consumer control usages, with their QMK
+    /// names.
+    const SPECIAL_KEYCODES: [(u16, Usage, &'static str); 26] = [
+        (
+            0x00a5,
+            Usage::new(Usage::GENERIC_DESKTOP_PAGE, 0x81),
+            "KC_PWR",
+        ),
+        (
+            0x00a6,
+            Usage::new(Usage::GENERIC_DESKTOP_PAGE, 0x82),
+            "KC_SLEP",
+        ),
+        (
+            0x00a7,
+            Usage::new(Usage::GENERIC_DESKTOP_PAGE, 0x83),
+            "KC_WAKE",
+        ),
+        (0x00a8, Usage::new(Usage::CONSUMER_PAGE, 0x00e2), "KC_MUTE"),
+        (0x00a9, Usage::new(Usage::CONSUMER_PAGE, 0x00e9), "KC_VOLU"),
+        (0x00aa, Usage::new(Usage::CONSUMER_PAGE, 0x00ea), "KC_VOLD"),
+        (0x00ab, Usage::new(Usage::CONSUMER_PAGE, 0x00b5), "KC_MNXT"),
+        (0x00ac, Usage::new(Usage::CONSUMER_PAGE, 0x00b6), "KC_MPRV"),
+        (0x00ad, Usage::new(Usage::CONSUMER_PAGE, 0x00b7), "KC_MSTP"),
+        (0x00ae, Usage::new(Usage::CONSUMER_PAGE, 0x00cd), "KC_MPLY"),
+        (0x00af, Usage::new(Usage::CONSUMER_PAGE, 0x0183), "KC_MSEL"),
+        (0x00b0, Usage::new(Usage::CONSUMER_PAGE, 0x00b8), "KC_EJCT"),
+        (0x00b1, Usage::new(Usage::CONSUMER_PAGE, 0x018a), "KC_MAIL"),
+        (0x00b2, Usage::new(Usage::CONSUMER_PAGE, 0x0192), "KC_CALC"),
+        (0x00b3, Usage::new(Usage::CONSUMER_PAGE, 0x0194), "KC_MYCM"),
+        (0x00b4, Usage::new(Usage::CONSUMER_PAGE, 0x0221), "KC_WSCH"),
+        (0x00b5, Usage::new(Usage::CONSUMER_PAGE, 0x0223), "KC_WHOM"),
+        (0x00b6, Usage::new(Usage::CONSUMER_PAGE, 0x0224), "KC_WBAK"),
+        (0x00b7, Usage::new(Usage::CONSUMER_PAGE, 0x0225), "KC_WFWD"),
+        (0x00b8, Usage::new(Usage::CONSUMER_PAGE, 0x0226), "KC_WSTP"),
+        (0x00b9, Usage::new(Usage::CONSUMER_PAGE, 0x0227), "KC_WREF"),
+        (0x00ba, Usage::new(Usage::CONSUMER_PAGE, 0x022a), "KC_WFAV"),
+        (0x00bb, Usage::new(Usage::CONSUMER_PAGE, 0x00b3), "KC_MFFD"),
+        (0x00bc, Usage::new(Usage::CONSUMER_PAGE, 0x00b4), "KC_MRWD"),
+        (0x00bd, Usage::new(Usage::CONSUMER_PAGE, 0x006f), "KC_BRIU"),
+        (0x00be, Usage::new(Usage::CONSUMER_PAGE, 0x0070), "KC_BRID"),
+    ];
+
+    /// Key sending `tap` when tapped, and the `modifier` keycode when held.
+    pub const fn mod_tap(modifier: KeyCode, tap: KeyCode) -> Self {
+        Self::HoldTap(HoldTap {
+            hold: Hold::Key(modifier),
+            tap,
+        })
+    }
+
+    /// Key reporting `code` together with the `modifiers` bitmap, in the
+    /// layout of the HID modifier byte, while held.
+    pub const fn chord(modifiers: u8, code: KeyCode) -> Self {
+        Self::Chord(Keystroke::new(modifiers, code))
+    }
+
+    /// Key sending `tap` when tapped, and activating `layer` when held.
+    pub const fn layer_tap(layer: u8, tap: KeyCode) -> Self {
+        Self::HoldTap(HoldTap {
+            hold: Hold::Layer(layer),
+            tap,
+        })
+    }
+
+    /// Encode this action as a 16-bit value.
+    ///
+    /// The encoding follows QMK's keycode ranges, so that keymaps stored or
+    /// exchanged in this form are understood by existing configuration
+    /// tools. Returns `None` for actions that have no such encoding, like
+    /// layers above 31.
+    ///
+    /// As in QMK, `0x0000` and `0x0001` encode [`Action::NoOp`] and
+    /// [`Action::Transparent`], so the status keycodes
+    /// [`KeyCode::ErrorRollOver`] and up cannot be encoded, and
+    /// [`KeyCode::NoEvent`] decodes as [`Action::NoOp`]. QMK also uses the
+    /// keycodes from `0x00a5` to `0x00df` for system, consumer and mouse
+    /// keys rather than the usages of the same value, so that system and
+    /// consumer [`Action::Usage`]s like power, volume and playback keys
+    /// encode in this range, and the keypad keycodes [`KeyCode::Kp00`] and
+    /// up cannot be encoded.
+    pub const fn to_raw(self) -> Option<u16> {
+        match self {
+            Self::NoOp | Self::Key(KeyCode::NoEvent) => Some(Self::NO_OP),
+            Self::Transparent => Some(Self::TRANSPARENT),
+            Self::SwapHands => Some(Self::SWAP_HANDS_MOMENTARY),
+            Self::ToggleSwapHands => Some(Self::SWAP_HANDS_TOGGLE),
+            Self::Backlight(command) => Some(Self::BACKLIGHT | command as u16),
+            Self::Rgb(command) => Some(Self::RGB | command as u16),
+            Self::Animation(command) => Some(Self::ANIMATION | command as u16),
+            Self::Key(KeyCode::ErrorRollOver | KeyCode::PostFail | KeyCode::ErrorUndefined) => None,
+            Self::Key(code) if Self::is_special(code as u16) => None,
+            Self::Key(code) => Some(code as u16),
+            // Usages QMK encodes as basic keycodes
+            Self::Usage(usage) => match usage.keyboard_id() {
+                Some(id @ (0x0004..=0x00a4 | 0x00e0..=0x00e7)) => Some(id),
+                Some(_) => None,
+                None => match Self::special_keycode(usage) {
+                    Some((raw, _)) => Some(raw),
+                    None => None,
+                },
+            },
+            Self::Chord(keystroke) => {
+                let code = keystroke.code() as u16;
+                let modifiers = keystroke.modifiers();
+
+                // QMK cannot mix left and right modifiers in one keycode.
+                let mods = match (modifiers & 0x0f, modifiers >> 4) {
+                    (0, 0) => return None,
+                    (left, 0) => left as u16,
+                    (0, right) => 0x10 | right as u16,
+                    _ => return None,
+                };
+
+                if code > 0xff || code < 0x04 {
+                    return None;
+                }
+
+                Some(mods << 8 | code)
+            }
+            Self::MomentaryLayer(layer) if layer as u16 <= Self::LAYER_MASK => {
+                Some(Self::MOMENTARY_LAYER | layer as u16)
+            }
+            Self::ToggleLayer(layer) if layer as u16 <= Self::LAYER_MASK => {
+                Some(Self::TOGGLE_LAYER | layer as u16)
+            }
+            Self::MomentaryLayer(_) | Self::ToggleLayer(_) => None,
+            Self::HoldTap(HoldTap { hold, tap }) => {
+                let tap = tap as u16;
+
+                if tap > 0xff {
+                    return None;
+                }
+
+                match hold {
+                    Hold::Key(modifier) => match Self::qmk_mods(modifier) {
+                        Some(mods) => Some(Self::MOD_TAP | mods << 8 | tap),
+                        None => None,
+                    },
+                    Hold::Layer(layer) if layer < 16 => {
+                        Some(Self::LAYER_TAP | (layer as u16) << 8 | tap)
+                    }
+                    Hold::Layer(_) => None,
+                }
+            }
+        }
+    }
+
+    /// Decode an action from its 16-bit encoding, see [`Action::to_raw`].
+    pub fn from_raw(raw: u16) -> Option<Self> {
+        let layer = (raw & Self::LAYER_MASK) as u8;
+
+        match raw {
+            Self::NO_OP => return Some(Self::NoOp),
+            Self::TRANSPARENT => return Some(Self::Transparent),
+            Self::SWAP_HANDS_MOMENTARY => return Some(Self::SwapHands),
+            Self::SWAP_HANDS_TOGGLE => return Some(Self::ToggleSwapHands),
+            _ => {}
+        }
+
+        if let Some(command) = raw
+            .checked_sub(Self::BACKLIGHT)
+            .and_then(|index| BacklightCommand::ALL.get(usize::from(index)))
+        {
+            return Some(Self::Backlight(*command));
+        }
+
+        if let Some(command) = raw
+            .checked_sub(Self::RGB)
+            .and_then(|index| RgbCommand::ALL.get(usize::from(index)))
+        {
+            return Some(Self::Rgb(*command));
+        }
+
+        if let Some(command) = raw
+            .checked_sub(Self::ANIMATION)
+            .and_then(|index| AnimationCommand::ALL.get(usize::from(index)))
+        {
+            return Some(Self::Animation(*command));
+        }
+
+        match raw & !Self::LAYER_MASK {
+            Self::MOMENTARY_LAYER => return Some(Self::MomentaryLayer(layer)),
+            Self::TOGGLE_LAYER => return Some(Self::ToggleLayer(layer)),
+            _ => {}
+        }
+
+        let tap = || KeyCode::try_from(raw & 0x00ff).ok();
+
+        match raw & 0xf000 {
+            0x2000 | 0x3000 => Some(Self::mod_tap(
+                Self::qmk_modifier(((raw >> 8) & 0x1f) as u8)?,
+                tap()?,
+            )),
+            Self::LAYER_TAP => Some(Self::layer_tap(((raw >> 8) & 0x0f) as u8, tap()?)),
+            0x0000 | 0x1000 if raw > 0x00ff => {
+                let mods = (raw >> 8) as u8;
+                let modifiers = if mods & 0x10 == 0 {
+                    mods
+                } else {
+                    (mods & 0x0f) << 4
+                };
+
+                Some(Self::chord(modifiers, tap()?))
+            }
+            _ if Self::is_special(raw) => Self::SPECIAL_KEYCODES
+                .iter()
+                .find(|(keycode, _, _)| *keycode == raw)
+                .map(|(_, usage, _)| Self::Usage(*usage)),
+            _ => match KeyCode::try_from(raw) {
+                Ok(code) => Some(Self::Key(code)),
+                Err(id @ 0x0004..=0x00a4) => Some(Self::Usage(Usage::keyboard(id))),
+                Err(_) => None,
+            },
+        }
+    }
+
+    /// Whether QMK uses `raw` for a system, consumer or mouse key rather
+    /// than the usage of the same value.
+    const fn is_special(raw: u16) -> bool {
+        raw >= 0x00a5 && raw <= 0x00df
+    }
+
+    /// QMK keycode and name of a system or consumer control usage.
+    const fn special_keycode(usage: Usage) -> Option<(u16, &'static str)> {
+        let mut i = 0;
+        while i < Self::SPECIAL_KEYCODES.len() {
+            let (keycode, special, name) = Self::SPECIAL_KEYCODES[i];
+            if special.page() == usage.page() && special.id() == usage.id() {
+                return Some((keycode, name));
+            }
+            i += 1;
+        }
+
+        None
+    }
+
+    /// QMK modifier bits of a single modifier keycode.
+    const fn qmk_mods(modifier: KeyCode) -> Option<u16> {
+        let usage = modifier as u16;
+
+        if usage < 0x00e0 || usage > 0x00e7 {
+            return None;
+        }
+
+        let index = usage - 0x00e0;
+        let right = if index >= 4 { 0x10 } else { 0x00 };
+
+        Some(right | 1 << (index & 0x03))
+    }
+
+    /// Single modifier keycode for QMK modifier bits.
+    fn qmk_modifier(mods: u8) -> Option<KeyCode> {
+        let bits = mods & 0x0f;
+
+        if bits.count_ones() != 1 {
+            return None;
+        }
+
+        let right = if mods & 0x10 == 0 { 0 } else { 4 };
+        KeyCode::try_from(0x00e0 + u16::from(right + bits.trailing_zeros() as u8)).ok()
+    }
+}
+
+/// Formats the action as the QMK keycode expression for it, e.g. `KC_A`,
+/// `MO(1)` or `LT(2,KC_SPC)`, as used in QMK and VIA keymap files.
+///
+/// Keycodes without a QMK name are written as their 16-bit encoding, and
+/// actions QMK cannot express as `KC_NO`.
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(raw) = self.to_raw() else {
+            return f.write_str("KC_NO");
+        };
+
+        match *self {
+            Self::NoOp => f.write_str("KC_NO"),
+            Self::Transparent => f.write_str("KC_TRNS"),
+            Self::Key(code) => match code.qmk_name() {
+                Some(name) => f.write_str(name),
+                None => write!(f, "{raw:#06x}"),
+            },
+            Self::Usage(usage) => match Self::special_keycode(usage) {
+                Some((_, name)) => f.write_str(name),
+                None => write!(f, "{raw:#06x}"),
+            },
+            Self::Chord(keystroke) => match keystroke.code().qmk_name() {
+                Some(name) => {
+                    let modifiers = keystroke.modifiers();
+                    let bits = (0..8).filter(|bit| modifiers & (1 << bit) != 0);
+
+                    for bit in bits.clone() {
+                        write!(f, "{}(", Self::CHORD_NAMES[bit])?;
+                    }
+                    f.write_str(name)?;
+                    for _ in bits {
+                        f.write_str(")")?;
+                    }
+
+                    Ok(())
+                }
+                None => write!(f, "{raw:#06x}"),
+            },
+            Self::MomentaryLayer(layer) => write!(f, "MO({layer})"),
+            Self::ToggleLayer(layer) => write!(f, "TG({layer})"),
+            Self::HoldTap(HoldTap { hold, tap }) => match (hold, tap.qmk_name()) {
+                (Hold::Layer(layer), Some(tap)) => write!(f, "LT({layer},{tap})"),
+                (Hold::Key(modifier), Some(tap)) => {
+                    // Encodable, so `modifier` is one of the modifiers.
+                    let index = usize::from(modifier as u16 - 0x00e0);
+                    write!(f, "MT({},{tap})", Self::MOD_NAMES[index])
+                }
+                _ => write!(f, "{raw:#06x}"),
+            },
+            Self::SwapHands => f.write_str("SH_MON"),
+            Self::ToggleSwapHands => f.write_str("SH_TOGG"),
+            Self::Backlight(command) => f.write_str(command.qmk_name()),
+            Self::Rgb(command) => f.write_str(command.qmk_name()),
+            Self::Animation(command) => f.write_str(command.qmk_name()),
+        }
+    }
+}
+
+/// QMK keycode expression which could not be parsed into an [`Action`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ParseActionError;
+
+impl fmt::Display for ParseActionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid QMK keycode")
+    }
+}
+
+/// Parses a QMK keycode expression, as formatted by the
+/// [`Display`](fmt::Display) implementation, e.g. from a QMK or VIA keymap
+/// file.
+///
+/// Besides what is formatted, the `XXXXXXX` and `_______` aliases, the
+/// mod-tap shorthands like `LSFT_T(KC_A)` and spaces after commas are
+/// understood.
+impl FromStr for Action {
+    type Err = ParseActionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        match s {
+            "KC_NO" | "XXXXXXX" => return Ok(Self::NoOp),
+            "KC_TRNS" | "_______" => return Ok(Self::Transparent),
+            "SH_MON" => return Ok(Self::SwapHands),
+            "SH_TOGG" => return Ok(Self::ToggleSwapHands),
+            _ => {}
+        }
+
+        if let Some(hex) = s.strip_prefix("0x") {
+            let raw = u16::from_str_radix(hex, 16).map_err(|_| ParseActionError)?;
+            return Self::from_raw(raw).ok_or(ParseActionError);
+        }
+
+        if let Some((name, args)) = s.strip_suffix(')').and_then(|s| s.split_once('(')) {
+            return Self::parse_call(name, args);
+        }
+
+        if let Some(code) = (0..=0x00e7)
+            .filter_map(|raw| KeyCode::try_from(raw).ok())
+            .find(|code| code.qmk_name() == Some(s))
+        {
+            return Ok(Self::Key(code));
+        }
+
+        if let Some((_, usage, _)) = Self::SPECIAL_KEYCODES
+            .iter()
+            .find(|(_, _, name)| *name == s)
+        {
+            return Ok(Self::Usage(*usage));
+        }
+
+        let commands = BacklightCommand::ALL
+            .iter()
+            .map(|c| (c.qmk_name(), Self::Backlight(*c)))
+            .chain(
+                RgbCommand::ALL
+                    .iter()
+                    .map(|c| (c.qmk_name(), Self::Rgb(*c))),
+            )
+            .chain(
+                AnimationCommand::ALL
+                    .iter()
+                    .map(|c| (c.qmk_name(), Self::Animation(*c))),
+            );
+        for (name, action) in commands {
+            if name == s {
+                return Ok(action);
+            }
+        }
+
+        Err(ParseActionError)
+    }
+}
+
+impl Action {
+    /// Parse the QMK keycode expression `name(args)`.
+    fn parse_call(name: &str, args: &str) -> Result<Self, ParseActionError> {
+        let layer = |arg: &str| arg.trim().parse::<u8>().map_err(|_| ParseActionError);
+        let key = |arg: &str| match arg.parse()? {
+            Self::Key(code) => Ok(code),
+            _ => Err(ParseActionError),
+        };
+        let modifier = |index: u16| KeyCode::try_from(0x00e0 + index).ok();
+
+        match name {
+            "MO" => return Ok(Self::MomentaryLayer(layer(args)?)),
+            "TG" => return Ok(Self::ToggleLayer(layer(args)?)),
+            "LT" | "MT" => {
+                let (hold, tap) = args.split_once(',').ok_or(ParseActionError)?;
+                let tap = key(tap)?;
+
+                if name == "LT" {
+                    return Ok(Self::layer_tap(layer(hold)?, tap));
+                }
+
+                return Self::position(&Self::MOD_NAMES, hold.trim())
+                    .and_then(modifier)
+                    .map(|modifier| Self::mod_tap(modifier, tap))
+                    .ok_or(ParseActionError);
+            }
+            _ => {}
+        }
+
+        if let Some(modifier) = name
+            .strip_suffix("_T")
+            .and_then(|name| Self::position(&Self::CHORD_NAMES, name))
+            .and_then(modifier)
+        {
+            return Ok(Self::mod_tap(modifier, key(args)?));
+        }
+
+        let bit = Self::position(&Self::CHORD_NAMES, name).ok_or(ParseActionError)?;
+        let (modifiers, code) = match args.parse()? {
+            Self::Key(code) => (0, code),
+            Self::Chord(keystroke) => (keystroke.modifiers(), keystroke.code()),
+            _ => return Err(ParseActionError),
+        };
+
+        Ok(Self::chord(modifiers | 1 << bit, code))
+    }
+
+    /// Index of `name` among the modifier `names`.
+    fn position(names: &[&str; 8], name: &str) -> Option<u16> {
+        (0..8).find(|&i| names[usize::from(i)] == name)
+    }
+}
+
+impl Default for Action {
+    #[inline]
+    fn default() -> Self {
+        Self::NoOp
+    }
+}
+
+impl From<KeyCode> for Action {
+    #[inline]
+    fn from(code: KeyCode) -> Self {
+        Self::Key(code)
+    }
+}
+
+/// Layered mapping from matrix [`Coordinate`]s to [`Action`]s.
+///
+/// Layers are indexed from `0`, the base layer, upwards. Each layer is
+/// laid out row-major, matching [`Coordinate::row`] and
+/// [`Coordinate::col`]. Use the [`keymap!`](crate::keymap!) macro to
+/// declare one without spelling out the nested arrays by hand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keymap<const LAYERS: usize, const ROWS: usize, const COLS: usize> {
+    layers: [[[Action; COLS]; ROWS]; LAYERS],
+}
+
+impl<const LAYERS: usize, const ROWS: usize, const COLS: usize> Keymap<LAYERS, ROWS, COLS> {
+    /// Create a keymap from its layers.
+    pub const fn new(layers: [[[Action; COLS]; ROWS]; LAYERS]) -> Self {
+        Self { layers }
+    }
+
+    /// Number of layers in this keymap.
+    pub const fn layers(&self) -> usize {
+        LAYERS
+    }
+
+    /// Action mapped to `coordinate` on `layer`, if both exist.
+    pub const fn action(&self, layer: usize, coordinate: Coordinate) -> Option<Action> {
+        if layer < LAYERS && coordinate.within(ROWS, COLS) {
+            Some(self.layers[layer][coordinate.row()][coordinate.col()])
+        } else {
+            None
+        }
+    }
+
+    /// Mutable access to the action mapped to `coordinate` on `layer`, for
+    /// remapping keys at runtime.
+    pub fn action_mut(&mut self, layer: usize, coordinate: Coordinate) -> Option<&mut Action> {
+        self.layers
+            .get_mut(layer)?
+            .get_mut(coordinate.row())?
+            .get_mut(coordinate.col())
+    }
+
+    /// Iterate over every action of every layer as
+    /// `(layer, coordinate, action)`, in storage order.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, Coordinate, Action)> + '_ {
+        self.layers.iter().enumerate().flat_map(|(layer, rows)| {
+            rows.iter().enumerate().flat_map(move |(row, cols)| {
+                cols.iter()
+                    .enumerate()
+                    .map(move |(col, action)| (layer, Coordinate::new(row, col), *action))
+            })
+        })
+    }
+}
+
+/// Declare a [`Keymap`] by laying out its layers visually.
+///
+/// Each layer is a `{ ... }` block of rows, and each row a `[ ... ]` list
+/// of keys. A key is one of:
+///
+/// - a [`KeyCode`] variant name, e.g. `KA` or `KEnter`;
+/// - `_______` for [`Action::Transparent`] and `XXXXXXX` for
+///   [`Action::NoOp`], as in QMK keymaps;
+/// - a layer number in parentheses, e.g. `(1)`, which activates that layer
+///   while held;
+/// - any expression evaluating to an [`Action`], in braces, e.g.
+///   `{Action::ToggleLayer(2)}`.
+///
+/// Rows of the wrong length or layers with the wrong number of rows are
+/// rejected at compile time.
+///
+/// ```
+/// use embedded_keyboard::{keymap, Action, Keymap};
+///
+/// const KEYMAP: Keymap<2, 2, 3> = keymap! {
+///     {
+///         [KA  KB  KC]
+///         [K1  K2  (1)]
+///     }
+///     {
+///         [KF1 KF2 KF3]
+///         [{Action::ToggleLayer(1)} XXXXXXX _______]
+///     }
+/// };
+/// ```
+#[macro_export]
+macro_rules! keymap {
+    (@action _______) => {
+        $crate::Action::Transparent
+    };
+    (@action XXXXXXX) => {
+        $crate::Action::NoOp
+    };
+    (@action ($layer:expr)) => {
+        $crate::Action::MomentaryLayer($layer)
+    };
+    (@action {$action:expr}) => {
+        $action
+    };
+    (@action $key:ident) => {
+        $crate::Action::Key($crate::KeyCode::$key)
+    };
+    ($({$([$($key:tt)*])*})*) => {
+        $crate::Keymap::new([$([$([$($crate::keymap!(@action $key)),*]),*]),*])
+    };
+}
+
+#[cfg(feature = "serde")]
+mod serde_impl {
+    use super::{Action, Keymap};
+    use core::fmt;
+    use serde::{
+        de::{Error, SeqAccess, Visitor},
+        ser::SerializeTuple,
+        Deserialize, Deserializer, Serialize, Serializer,
+    };
+
+    // Arrays of const generic length have no serde implementation, so the
+    // keymap is (de)serialized as a flat tuple of its actions.
+
+    impl<const LAYERS: usize, const ROWS: usize, const COLS: usize> Serialize
+        for Keymap<LAYERS, ROWS, COLS>
+    {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut tuple = serializer.serialize_tuple(LAYERS * ROWS * COLS)?;
+
+            for (_, _, action) in self.iter() {
+                tuple.serialize_element(&action)?;
+            }
+
+            tuple.end()
+        }
+    }
+
+    struct KeymapVisitor<const LAYERS: usize, const ROWS: usize, const COLS: usize>;
+
+    impl<'de, const LAYERS: usize, const ROWS: usize, const COLS: usize> Visitor<'de>
+        for KeymapVisitor<LAYERS, ROWS, COLS>
+    {
+        type Value = Keymap<LAYERS, ROWS, COLS>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "a tuple of {} actions", LAYERS * ROWS * COLS)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut layers = [[[Action::default(); COLS]; ROWS]; LAYERS];
+            for (n, action) in layers.iter_mut().flatten().flatten().enumerate() {
+                *action = seq
+                    .next_element()?
+                    .ok_or_else(|| Error::invalid_length(n, &self))?;
+            }
+
+            Ok(Keymap::new(layers))
+        }
+    }
+
+    impl<'de, const LAYERS: usize, const ROWS: usize, const COLS: usize> Deserialize<'de>
+        for Keymap<LAYERS, ROWS, COLS>
+    {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_tuple(LAYERS * ROWS * COLS, KeymapVisitor)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEYMAP: Keymap<2, 2, 3> = crate::keymap! {
+        {
+            [KA KB KC]
+            [K1 K2 (1)]
+        }
+        {
+            [KF1 KF2 KF3]
+            [{Action::ToggleLayer(0)} _______ XXXXXXX]
+        }
+    };
+
+    #[test]
+    fn keymap_macro_layout() {
+        assert_eq!(KEYMAP.layers(), 2);
+        assert_eq!(
+            KEYMAP.action(0, Coordinate::new(0, 2)),
+            Some(Action::Key(KeyCode::KC))
+        );
+        assert_eq!(
+            KEYMAP.action(0, Coordinate::new(1, 2)),
+            Some(Action::MomentaryLayer(1))
+        );
+        assert_eq!(
+            KEYMAP.action(1, Coordinate::new(1, 0)),
+            Some(Action::ToggleLayer(0))
+        );
+        assert_eq!(KEYMAP.action(2, Coordinate::new(0, 0)), None);
+        assert_eq!(KEYMAP.action(0, Coordinate::new(2, 0)), None);
+    }
+
+    #[test]
+    fn remap_key() {
+        let mut keymap = KEYMAP;
+        *keymap.action_mut(1, Coordinate::new(1, 2)).unwrap() = Action::Key(KeyCode::KZ);
+
+        assert_eq!(
+            keymap.action(1, Coordinate::new(1, 2)),
+            Some(Action::Key(KeyCode::KZ))
+        );
+        assert!(keymap.action_mut(0, Coordinate::new(0, 3)).is_none());
+    }
+
+    #[test]
+    fn raw_encoding() {
+        for (_, _, action) in KEYMAP.iter() {
+            assert_eq!(Action::from_raw(action.to_raw().unwrap()), Some(action));
+        }
+
+        assert_eq!(Action::Key(KeyCode::KA).to_raw(), Some(0x0004));
+        assert_eq!(Action::MomentaryLayer(1).to_raw(), Some(0x5221));
+        assert_eq!(Action::ToggleLayer(32).to_raw(), None);
+        assert_eq!(
+            Action::mod_tap(KeyCode::KpRightShift, KeyCode::KA).to_raw(),
+            Some(0x3204)
+        );
+        assert_eq!(
+            Action::from_raw(0x2104),
+            Some(Action::mod_tap(KeyCode::KpLeftControl, KeyCode::KA))
+        );
+        assert_eq!(
+            Action::from_raw(0x4329),
+            Some(Action::layer_tap(3, KeyCode::KEscape))
+        );
+        assert_eq!(Action::from_raw(0x2304), None);
+        assert_eq!(
+            Action::from_raw(0x00a5),
+            Some(Action::Usage(Usage::new(Usage::GENERIC_DESKTOP_PAGE, 0x81)))
+        );
+        assert_eq!(Action::from_raw(0x0000), Some(Action::NoOp));
+        assert_eq!(Action::from_raw(0x0001), Some(Action::Transparent));
+        assert_eq!(Action::from_raw(0x56f2), Some(Action::SwapHands));
+        assert_eq!(
+            Action::from_raw(0x7802),
+            Some(Action::Backlight(BacklightCommand::Toggle))
+        );
+        assert_eq!(Action::from_raw(0x7806), None);
+        assert_eq!(
+            Action::Backlight(BacklightCommand::Step).to_raw(),
+            Some(0x7805)
+        );
+        assert_eq!(Action::Backlight(BacklightCommand::Up).to_string(), "BL_UP");
+        assert_eq!(
+            Action::from_raw(0x7828),
+            Some(Action::Rgb(RgbCommand::ValDown))
+        );
+        assert_eq!(Action::Rgb(RgbCommand::SpeedUp).to_raw(), Some(0x7829));
+        assert_eq!(
+            Action::from_raw(0x7843),
+            Some(Action::Animation(AnimationCommand::Next))
+        );
+        assert_eq!(Action::from_raw(0x7849), None);
+        assert_eq!(
+            Action::from_raw(0x0066),
+            Some(Action::Usage(Usage::keyboard(0x66)))
+        );
+        assert_eq!(Action::Usage(Usage::new(0x0c, 0x30)).to_raw(), None);
+        assert_eq!(Action::Key(KeyCode::NoEvent).to_raw(), Some(0x0000));
+        assert_eq!(Action::Key(KeyCode::ErrorRollOver).to_raw(), None);
+
+        let screenshot = Action::chord(0x0a, KeyCode::KS);
+        assert_eq!(screenshot.to_raw(), Some(0x0a16));
+        assert_eq!(Action::from_raw(0x0a16), Some(screenshot));
+        assert_eq!(
+            Action::from_raw(0x1204),
+            Some(Action::chord(0x20, KeyCode::KA))
+        );
+        assert_eq!(Action::chord(0x22, KeyCode::KA).to_raw(), None);
+        assert_eq!(Action::chord(0x00, KeyCode::KA).to_raw(), None);
+        assert_eq!(screenshot.to_string(), "LSFT(LGUI(KC_S))");
+    }
+
+    #[test]
+    fn parse_qmk_keycodes() {
+        let actions = [
+            Action::NoOp,
+            Action::Transparent,
+            Action::Key(KeyCode::KEscape),
+            Action::Key(KeyCode::KpRightGUI),
+            Action::Usage(Usage::new(Usage::CONSUMER_PAGE, 0xcd)),
+            Action::Usage(Usage::keyboard(0x66)),
+            Action::chord(0x0a, KeyCode::KS),
+            Action::chord(0x20, KeyCode::KA),
+            Action::MomentaryLayer(3),
+            Action::ToggleLayer(31),
+            Action::layer_tap(2, KeyCode::KSpaceBar),
+            Action::mod_tap(KeyCode::KpRightShift, KeyCode::KEnter),
+            Action::SwapHands,
+            Action::ToggleSwapHands,
+            Action::Backlight(BacklightCommand::Step),
+            Action::Rgb(RgbCommand::SpeedUp),
+            Action::Animation(AnimationCommand::Next),
+        ];
+        for action in actions {
+            assert_eq!(action.to_string().parse(), Ok(action), "{action}");
+        }
+
+        assert_eq!("XXXXXXX".parse(), Ok(Action::NoOp));
+        assert_eq!("_______".parse(), Ok(Action::Transparent));
+        assert_eq!(
+            "LSFT_T(KC_A)".parse(),
+            Ok(Action::mod_tap(KeyCode::KpLeftShift, KeyCode::KA))
+        );
+        assert_eq!(
+            " LT(1, KC_SPC) ".parse(),
+            Ok(Action::layer_tap(1, KeyCode::KSpaceBar))
+        );
+
+        for invalid in [
+            "KC_NOPE",
+            "MO(x)",
+            "LT(1,KC_MUTE)",
+            "MT(MOD_HYPR,KC_A)",
+            "FOO(KC_A)",
+            "0xzz",
+        ] {
+            assert_eq!(
+                invalid.parse::<Action>(),
+                Err(ParseActionError),
+                "{invalid}"
+            );
+        }
+    }
+
+    #[test]
+    fn consumer_keycodes() {
+        let play = Action::Usage(Usage::new(Usage::CONSUMER_PAGE, 0xcd));
+
+        assert_eq!(Action::from_raw(0x00ae), Some(play));
+        assert_eq!(play.to_raw(), Some(0x00ae));
+        assert_eq!(play.to_string(), "KC_MPLY");
+        assert_eq!(Action::from_raw(0x00c0), None);
+        assert_eq!(Action::Key(KeyCode::Kp00).to_raw(), None);
+    }
+
+    #[test]
+    fn iterate_in_storage_order() {
+        let mut iter = KEYMAP.iter();
+        assert_eq!(
+            iter.next(),
+            Some((0, Coordinate::new(0, 0), Action::Key(KeyCode::KA)))
+        );
+        assert_eq!(
+            iter.nth(4),
+            Some((0, Coordinate::new(1, 2), Action::MomentaryLayer(1)))
+        );
+        assert_eq!(iter.count(), 6);
+    }
+}
@@ -0,0 +1,414 @@
+//! Import of physical layouts drawn in keyboard-layout-editor.
+//!
+//! [keyboard-layout-editor](http://www.keyboard-layout-editor.com) (KLE)
+//! layouts describe where every key of a board sits and how large it is,
+//! row by row. [`KleLayout`] parses a layout, either downloaded as JSON or
+//! copied from the editor's raw data, and maps it onto the matrix as the
+//! [`Geometry`] used by lighting effects and host tools.
+//!
+//! Keys are mapped onto the matrix following the VIA convention: the
+//! top-left legend of each key is its `row,col` matrix coordinate. Keys
+//! without one, like decals and labels, are skipped.
+//!
+//! Geometries can be imported at runtime, or from a build script writing
+//! the [`GeometrySource`] of the geometry to a file included by the
+//! firmware:
+//!
+//! ```
+//! use embedded_keyboard::kle::{GeometrySource, KleLayout};
+//! use embedded_keyboard::{Coordinate, Geometry, KeyPosition};
+//!
+//! // Escape, and a 2u backspace half a unit lower.
+//! let raw = r#"["0,0", {x: 1, y: 0.5, w: 2}, "0,1\nBackspace"]"#;
+//!
+//! let geometry: Geometry<1, 2> = KleLayout::from_json(raw)?.geometry()?;
+//! assert_eq!(
+//!     geometry.key(Coordinate::new(0, 1)),
+//!     Some(KeyPosition::new(200, 50, 200, 100))
+//! );
+//!
+//! // In a build script, to be `include!`d as the value of a constant.
+//! let source = GeometrySource::new(&geometry).to_string();
+//! assert!(source.starts_with("::embedded_keyboard::Geometry::new("));
+//! # Ok::<(), embedded_keyboard::kle::KleError>(())
+//! ```
+
+use core::fmt;
+use std::format;
+use std::string::String;
+use std::vec::Vec;
+
+use crate::json::{self, Value};
+use crate::{Coordinate, Geometry, KeyPosition};
+
+/// Error importing a KLE layout.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KleError {
+    /// The layout is not valid JSON, from the given byte offset
+    Json {
+        /// Byte offset of the error
+        offset: usize,
+    },
+    /// The layout is not a list of rows of keys
+    NotALayout,
+    /// A key of the layout lies outside the matrix
+    OutsideMatrix {
+        /// Matrix coordinate of the key
+        coordinate: Coordinate,
+    },
+}
+
+impl fmt::Display for KleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Json { offset } => write!(f, "invalid JSON at byte {offset}"),
+            Self::NotALayout => f.write_str("not a list of rows of keys"),
+            Self::OutsideMatrix { coordinate } => write!(
+                f,
+                "key {},{} outside the matrix",
+                coordinate.row(),
+                coordinate.col()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for KleError {}
+
+/// Physical layout parsed from keyboard-layout-editor.
+///
+/// Rotated keys are placed with their center where the rotation takes
+/// it, but unrotated, since a [`KeyPosition`] is an upright rectangle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KleLayout {
+    name: String,
+    keys: Vec<(Coordinate, KeyPosition)>,
+}
+
+impl KleLayout {
+    /// Parse a layout, as downloaded from the editor or copied from its raw
+    /// data.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `json` is not a layout.
+    pub fn from_json(json: &str) -> Result<Self, KleError> {
+        // The raw data lists the rows without the enclosing array.
+        let rows = match json::parse(json) {
+            Ok(Value::Array(rows)) if rows.iter().any(|row| matches!(row, Value::Array(_))) => rows,
+            Ok(Value::Array(_)) | Err(_) => match json::parse(&format!("[{json}]")) {
+                Ok(Value::Array(rows)) => rows,
+                _ => {
+                    return Err(KleError::Json {
+                        offset: json::parse(json).err().unwrap_or(0),
+                    })
+                }
+            },
+            Ok(_) => return Err(KleError::NotALayout),
+        };
+
+        let mut layout = Self {
+            name: String::new(),
+            keys: Vec::new(),
+        };
+        let mut cursor = Cursor::default();
+
+        for (index, row) in rows.iter().enumerate() {
+            match row {
+                Value::Array(items) => {
+                    for item in items {
+                        match item {
+                            Value::String(legends) => {
+                                if let Some(key) = cursor.key(legends) {
+                                    layout.keys.push(key);
+                                }
+                            }
+                            Value::Object(_) => cursor.apply(item),
+                            _ => return Err(KleError::NotALayout),
+                        }
+                    }
+                    cursor.next_row();
+                }
+                Value::Object(_) if index == 0 => {
+                    let name = row.get("name").and_then(Value::as_str).unwrap_or("");
+                    layout.name = String::from(name);
+                }
+                _ => return Err(KleError::NotALayout),
+            }
+        }
+
+        Ok(layout)
+    }
+
+    /// Name of the layout, from its metadata.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Matrix coordinate and position of every key, in layout order.
+    ///
+    /// Alternative keys of VIA layout options share the coordinate of the
+    /// key they replace.
+    pub fn keys(&self) -> &[(Coordinate, KeyPosition)] {
+        &self.keys
+    }
+
+    /// Geometry of the layout on a `ROWS` × `COLS` matrix.
+    ///
+    /// Where several keys share a coordinate, the first one listed is
+    /// kept, i.e. the default of VIA layout options.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KleError::OutsideMatrix`] if a key lies outside the
+    /// matrix.
+    pub fn geometry<const ROWS: usize, const COLS: usize>(
+        &self,
+    ) -> Result<Geometry<ROWS, COLS>, KleError> {
+        let mut keys = [[None; COLS]; ROWS];
+
+        for &(coordinate, position) in &self.keys {
+            if !coordinate.within(ROWS, COLS) {
+                return Err(KleError::OutsideMatrix { coordinate });
+            }
+            keys[coordinate.row()][coordinate.col()].get_or_insert(position);
+        }
+
+        Ok(Geometry::new(keys))
+    }
+}
+
+/// Position and properties of the next key, in key units, as the layout
+/// is read.
+struct Cursor {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    /// Rotation in degrees, clockwise, around `(rx, ry)`
+    rotation: f64,
+    rx: f64,
+    ry: f64,
+    decal: bool,
+}
+
+impl Default for Cursor {
+    fn default() -> Self {
+        Self {
+            x: 0.0,
+            y: 0.0,
+            width: 1.0,
+            height: 1.0,
+            rotation: 0.0,
+            rx: 0.0,
+            ry: 0.0,
+            decal: false,
+        }
+    }
+}
+
+impl Cursor {
+    /// Apply the properties of `object` to the next keys.
+    fn apply(&mut self, object: &Value) {
+        let number = |key| object.get(key).and_then(Value::as_number);
+
+        if let Some(r) = number("r") {
+            self.rotation = r;
+        }
+        // A new rotation origin starts a new cluster of rows from it.
+        if let Some(rx) = number("rx") {
+            self.rx = rx;
+            (self.x, self.y) = (self.rx, self.ry);
+        }
+        if let Some(ry) = number("ry") {
+            self.ry = ry;
+            (self.x, self.y) = (self.rx, self.ry);
+        }
+        self.x += number("x").unwrap_or(0.0);
+        self.y += number("y").unwrap_or(0.0);
+        if let Some(w) = number("w") {
+            self.width = w;
+        }
+        if let Some(h) = number("h") {
+            self.height = h;
+        }
+        if object.get("d").is_some() {
+            self.decal = true;
+        }
+    }
+
+    /// Place a key with `legends` and move past it, returning its matrix
+    /// coordinate and position if it is wired to the matrix.
+    fn key(&mut self, legends: &str) -> Option<(Coordinate, KeyPosition)> {
+        let (mut cx, mut cy) = (self.x + self.width / 2.0, self.y + self.height / 2.0);
+        if self.rotation != 0.0 {
+            let (sin, cos) = self.rotation.to_radians().sin_cos();
+            let (dx, dy) = (cx - self.rx, cy - self.ry);
+            cx = self.rx + dx * cos - dy * sin;
+            cy = self.ry + dx * sin + dy * cos;
+        }
+
+        let position = KeyPosition::new(
+            hundredths(cx - self.width / 2.0),
+            hundredths(cy - self.height / 2.0),
+            hundredths(self.width).unsigned_abs(),
+            hundredths(self.height).unsigned_abs(),
+        );
+        let decal = self.decal;
+
+        self.x += self.width;
+        (self.width, self.height, self.decal) = (1.0, 1.0, false);
+
+        if decal {
+            return None;
+        }
+        let (row, col) = legends.lines().next()?.split_once(',')?;
+        let coordinate = Coordinate::new(row.trim().parse().ok()?, col.trim().parse().ok()?);
+
+        Some((coordinate, position))
+    }
+
+    fn next_row(&mut self) {
+        self.x = self.rx;
+        self.y += 1.0;
+    }
+}
+
+/// Convert key units to hundredths of a unit.
+fn hundredths(units: f64) -> i16 {
+    (units * f64::from(KeyPosition::UNIT)).round() as i16
+}
+
+/// Rust source of a [`Geometry`], as a constant expression, e.g. for a
+/// build script to generate.
+pub struct GeometrySource<'a, const ROWS: usize, const COLS: usize> {
+    geometry: &'a Geometry<ROWS, COLS>,
+}
+
+impl<'a, const ROWS: usize, const COLS: usize> GeometrySource<'a, ROWS, COLS> {
+    /// Rust expression of `geometry`.
+    pub fn new(geometry: &'a Geometry<ROWS, COLS>) -> Self {
+        Self { geometry }
+    }
+}
+
+impl<const ROWS: usize, const COLS: usize> fmt::Display for GeometrySource<'_, ROWS, COLS> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("::embedded_keyboard::Geometry::new([\n")?;
+
+        for row in 0..ROWS {
+            f.write_str("    [")?;
+            for col in 0..COLS {
+                if col > 0 {
+                    f.write_str(", ")?;
+                }
+                match self.geometry.key(Coordinate::new(row, col)) {
+                    Some(key) => write!(
+                        f,
+                        "Some(::embedded_keyboard::KeyPosition::new({}, {}, {}, {}))",
+                        key.x(),
+                        key.y(),
+                        key.width(),
+                        key.height()
+                    )?,
+                    None => f.write_str("None")?,
+                }
+            }
+            f.write_str("],\n")?;
+        }
+
+        f.write_str("])")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::string::ToString;
+
+    // Downloaded layout: metadata, a 1.5u tab under a 2u backspace, an
+    // ISO enter, a decal and a VIA layout option splitting the backspace.
+    const JSON: &str = r#"[
+        {"name": "pad", "author": ""},
+        ["0,0", {"w": 2}, "0,1\n\n\n0,0", {"x": 0.5}, "0,1\n\n\n0,1", "0,2\n\n\n0,1"],
+        [{"w": 1.5}, "1,0\nTab", {"x": 0.25, "w": 1.25, "h": 2, "w2": 1.5, "h2": 1, "x2": -0.25},
+         "1,1", {"d": true}, "2,2"],
+        [{"y": -0.5, "a": 7}, "Fn"]
+    ]"#;
+
+    #[test]
+    fn import_layout() {
+        let layout = KleLayout::from_json(JSON).unwrap();
+        assert_eq!(layout.name(), "pad");
+        assert_eq!(
+            layout.keys(),
+            [
+                (Coordinate::new(0, 0), KeyPosition::new(0, 0, 100, 100)),
+                (Coordinate::new(0, 1), KeyPosition::new(100, 0, 200, 100)),
+                (Coordinate::new(0, 1), KeyPosition::new(350, 0, 100, 100)),
+                (Coordinate::new(0, 2), KeyPosition::new(450, 0, 100, 100)),
+                (Coordinate::new(1, 0), KeyPosition::new(0, 100, 150, 100)),
+                (Coordinate::new(1, 1), KeyPosition::new(175, 100, 125, 200)),
+            ]
+        );
+
+        let geometry: Geometry<2, 3> = layout.geometry().unwrap();
+        assert_eq!(
+            geometry.key(Coordinate::new(0, 1)),
+            Some(KeyPosition::new(100, 0, 200, 100))
+        );
+        assert_eq!(geometry.key(Coordinate::new(1, 2)), None);
+        assert_eq!(geometry.bounds(), (550, 300));
+
+        assert_eq!(
+            layout.geometry::<2, 2>(),
+            Err(KleError::OutsideMatrix {
+                coordinate: Coordinate::new(0, 2)
+            })
+        );
+    }
+
+    #[test]
+    fn import_raw_data() {
+        // Raw data of a rotated thumb cluster: rows without the enclosing
+        // array and unquoted keys.
+        let raw = r#"["0,0","0,1"],
+            [{r:90,rx:3,ry:1,y:-1},"1,0"],
+            ["1,1"]"#;
+        let layout = KleLayout::from_json(raw).unwrap();
+
+        // Rotating by a quarter turn clockwise around (3, 1) takes the
+        // centers (3.5, 0.5) and (3.5, 1.5) to (3.5, 1.5) and (2.5, 1.5).
+        assert_eq!(
+            layout.keys()[2..],
+            [
+                (Coordinate::new(1, 0), KeyPosition::new(300, 100, 100, 100)),
+                (Coordinate::new(1, 1), KeyPosition::new(200, 100, 100, 100)),
+            ]
+        );
+
+        assert_eq!(
+            KleLayout::from_json("[").err(),
+            Some(KleError::Json { offset: 1 })
+        );
+        assert_eq!(
+            KleLayout::from_json(r#"{"name": "pad"}"#),
+            Err(KleError::NotALayout)
+        );
+        assert_eq!(KleLayout::from_json("[[1]]"), Err(KleError::NotALayout));
+    }
+
+    #[test]
+    fn geometry_source() {
+        let geometry = Geometry::<1, 2>::new([[Some(KeyPosition::new(-25, 0, 150, 100)), None]]);
+
+        assert_eq!(
+            GeometrySource::new(&geometry).to_string(),
+            concat!(
+                "::embedded_keyboard::Geometry::new([\n",
+                "    [Some(::embedded_keyboard::KeyPosition::new(-25, 0, 150, 100)), None],\n",
+                "])"
+            )
+        );
+    }
+}
@@ -2,11 +2,68 @@
 //! controllers of all kinds.
 
 #![doc(html_root_url = "https://docs.rs/embedded-keyboard/latest")]
-#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
 
+#[macro_use]
+mod fmt;
+
+mod crc;
+mod feedback;
+mod geometry;
+#[cfg(feature = "std")]
+mod json;
 mod keycode;
+mod keymap;
+mod queue;
+mod settings;
 
+pub use crate::feedback::*;
+pub use crate::geometry::*;
 pub use crate::keycode::*;
+pub use crate::keymap::*;
+pub use crate::queue::*;
+pub use crate::settings::*;
+
+pub mod analog;
+pub mod animation;
+pub mod backlight;
+pub mod battery;
+pub mod debounce;
+pub mod diagnostics;
+pub mod ec;
+pub mod encoder;
+pub mod engine;
+pub mod fake;
+pub mod handoff;
+pub mod hid;
+pub mod host;
+pub mod link;
+pub mod matrix;
+pub mod merge;
+pub mod power;
+pub mod processor;
+pub mod ps2;
+pub mod replay;
+pub mod rgb;
+pub mod rtic;
+pub mod scancode;
+pub mod spi;
+pub mod split;
+pub mod time;
+pub mod via;
+
+#[cfg(feature = "std")]
+pub mod kle;
+#[cfg(feature = "std")]
+pub mod qmk;
+#[cfg(feature = "std")]
+pub mod sim;
+
+#[cfg(feature = "embedded-storage")]
+pub mod storage;
+
+#[cfg(feature = "keyberon")]
+pub mod keyberon;
 
 /// Keyboard error.
 pub trait Error: core::fmt::Debug {
@@ -70,8 +127,37 @@ impl Error for core::convert::Infallible {
     }
 }
 
+/// Keyboard
 pub trait Keyboard: ErrorType {
+    /// Scan the keyboard, returning the key events detected.
     fn scan(&mut self) -> Result<&[KeyEvent], Self::Error>;
+
+    /// Whether the last scan observed any raw activity, i.e. any key
+    /// sensed as pressed, even if it did not (yet) result in an event.
+    ///
+    /// Power management can use this to decide when the keyboard has
+    /// gone idle. Implementations that cannot tell always report
+    /// activity, so that they are never considered idle.
+    #[inline]
+    fn activity(&self) -> bool {
+        true
+    }
+}
+
+/// Indicator LEDs of a keyboard, driven by the LED state the host sets,
+/// e.g. as returned by [`HidI2cKeyboard::take_leds`].
+///
+/// [`HidI2cKeyboard::take_leds`]: crate::hid::HidI2cKeyboard::take_leds
+pub trait KeyboardLeds: ErrorType {
+    /// Show `leds` on the indicators.
+    fn set_leds(&mut self, leds: hid::LedState) -> Result<(), Self::Error>;
+}
+
+impl<T: KeyboardLeds + ?Sized> KeyboardLeds for &mut T {
+    #[inline]
+    fn set_leds(&mut self, leds: hid::LedState) -> Result<(), Self::Error> {
+        T::set_leds(self, leds)
+    }
 }
 
 impl<T: Keyboard + ?Sized> Keyboard for &mut T {
@@ -79,4 +165,9 @@ impl<T: Keyboard + ?Sized> Keyboard for &mut T {
     fn scan(&mut self) -> Result<&[KeyEvent], Self::Error> {
         T::scan(self)
     }
+
+    #[inline]
+    fn activity(&self) -> bool {
+        T::activity(self)
+    }
 }
@@ -0,0 +1,292 @@
+//! Integrity checked framing for proprietary links.
+//!
+//! Keyboards talking to their host over UART, I²C or similar links
+//! without a checksum of their own can wrap every frame of key data in
+//! a rolling counter and a CRC. A [`FrameSender`] builds such frames and
+//! a [`FrameReceiver`], the reference implementation of the receiving
+//! end, checks them, so that corrupted, replayed and lost frames are
+//! detected rather than acted upon.
+//!
+//! A frame is laid out as:
+//!
+//! | Byte        | Contents                                            |
+//! |-------------|-----------------------------------------------------|
+//! | 0           | Rolling counter, incremented with every frame       |
+//! | 1           | Payload length `n`                                  |
+//! | 2..2+n      | Payload                                             |
+//! | 2+n..4+n    | CRC-16/CCITT-FALSE of bytes `0..2+n`, little endian |
+//!
+//! Key events are carried as [`EVENT_LEN`] bytes each, see
+//! [`encode_event`].
+
+use crate::crc::Crc16;
+use crate::{Coordinate, KeyEvent};
+
+/// Bytes a frame adds to its payload.
+pub const OVERHEAD: usize = 4;
+
+/// Longest payload a frame can carry.
+pub const MAX_PAYLOAD: usize = u8::MAX as usize;
+
+/// Errors produced while building or checking a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FrameError {
+    /// The payload is longer than [`MAX_PAYLOAD`]
+    PayloadTooLong,
+    /// The buffer cannot hold the frame
+    BufferTooSmall,
+    /// The frame is shorter than its header says
+    Truncated,
+    /// The frame failed its checksum
+    Corrupted,
+    /// The frame's counter is not newer than the last accepted frame's
+    Replayed,
+}
+
+/// Frame accepted by a [`FrameReceiver`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Frame<'a> {
+    counter: u8,
+    lost: u8,
+    payload: &'a [u8],
+}
+
+impl<'a> Frame<'a> {
+    /// Rolling counter of the frame.
+    pub const fn counter(&self) -> u8 {
+        self.counter
+    }
+
+    /// Number of frames lost between the previously accepted frame and
+    /// this one.
+    pub const fn lost(&self) -> u8 {
+        self.lost
+    }
+
+    /// Payload of the frame.
+    pub const fn payload(&self) -> &'a [u8] {
+        self.payload
+    }
+}
+
+/// Sending end of a link.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FrameSender {
+    counter: u8,
+}
+
+impl FrameSender {
+    /// Create a sender starting its counter at zero.
+    pub const fn new() -> Self {
+        Self { counter: 0 }
+    }
+
+    /// Counter of the next frame.
+    pub const fn counter(&self) -> u8 {
+        self.counter
+    }
+
+    /// Write a frame carrying `payload` into `buf`, returning the length
+    /// of the frame.
+    pub fn write(&mut self, payload: &[u8], buf: &mut [u8]) -> Result<usize, FrameError> {
+        let len = u8::try_from(payload.len()).map_err(|_| FrameError::PayloadTooLong)?;
+        let frame = buf
+            .get_mut(..payload.len() + OVERHEAD)
+            .ok_or(FrameError::BufferTooSmall)?;
+        let (body, crc) = frame.split_at_mut(payload.len() + 2);
+
+        body[0] = self.counter;
+        body[1] = len;
+        body[2..].copy_from_slice(payload);
+        crc.copy_from_slice(&checksum(body).to_le_bytes());
+
+        self.counter = self.counter.wrapping_add(1);
+
+        Ok(frame.len())
+    }
+}
+
+/// Receiving end of a link.
+///
+/// Accepts frames whose counter is up to 127 ahead of the last accepted
+/// frame, reporting the frames skipped over as lost, and rejects the
+/// others as replayed. The first frame, and the first one after
+/// [`FrameReceiver::reset`], is accepted whatever its counter.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FrameReceiver {
+    last: Option<u8>,
+}
+
+impl FrameReceiver {
+    /// Create a receiver accepting any counter on its first frame.
+    pub const fn new() -> Self {
+        Self { last: None }
+    }
+
+    /// Resynchronize with the sender, e.g. after it restarted.
+    pub fn reset(&mut self) {
+        self.last = None;
+    }
+
+    /// Check the frame at the start of `buf` and return it, if it is
+    /// intact and newer than the last accepted frame.
+    pub fn receive<'a>(&mut self, buf: &'a [u8]) -> Result<Frame<'a>, FrameError> {
+        let len = usize::from(*buf.get(1).ok_or(FrameError::Truncated)?);
+        let frame = buf.get(..len + OVERHEAD).ok_or(FrameError::Truncated)?;
+        let (body, crc) = frame.split_at(len + 2);
+
+        if checksum(body).to_le_bytes() != crc {
+            return Err(FrameError::Corrupted);
+        }
+
+        let counter = body[0];
+        let lost = match self.last {
+            Some(last) => match counter.wrapping_sub(last) {
+                ahead @ 1..=0x7f => ahead - 1,
+                _ => return Err(FrameError::Replayed),
+            },
+            None => 0,
+        };
+
+        self.last = Some(counter);
+
+        Ok(Frame {
+            counter,
+            lost,
+            payload: &body[2..],
+        })
+    }
+}
+
+/// Length of an encoded key event.
+pub const EVENT_LEN: usize = 2;
+
+/// Encode a key event as its row, with bit 7 set if the key was pressed,
+/// followed by its column.
+///
+/// Returns `None` for [`KeyEvent::NoEvent`], and for keys beyond row 127
+/// or column 255.
+pub fn encode_event(event: KeyEvent) -> Option<[u8; EVENT_LEN]> {
+    let (pressed, coordinate) = match event {
+        KeyEvent::KeyDown(c) => (0x80, c),
+        KeyEvent::KeyUp(c) => (0x00, c),
+        KeyEvent::NoEvent => return None,
+    };
+
+    let row = u8::try_from(coordinate.row())
+        .ok()
+        .filter(|row| *row < 0x80)?;
+    let col = u8::try_from(coordinate.col()).ok()?;
+
+    Some([pressed | row, col])
+}
+
+/// Decode a key event encoded by [`encode_event`].
+pub fn decode_event(bytes: [u8; EVENT_LEN]) -> KeyEvent {
+    let coordinate = Coordinate::new(usize::from(bytes[0] & 0x7f), usize::from(bytes[1]));
+
+    if bytes[0] & 0x80 == 0 {
+        KeyEvent::KeyUp(coordinate)
+    } else {
+        KeyEvent::KeyDown(coordinate)
+    }
+}
+
+/// Iterator over the key events encoded in a payload.
+#[derive(Debug, Clone)]
+pub struct KeyEvents<'a> {
+    bytes: core::slice::ChunksExact<'a, u8>,
+}
+
+impl<'a> KeyEvents<'a> {
+    /// Decode the key events in `bytes`. A trailing partial event is
+    /// ignored.
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes: bytes.chunks_exact(EVENT_LEN),
+        }
+    }
+}
+
+impl Iterator for KeyEvents<'_> {
+    type Item = KeyEvent;
+
+    fn next(&mut self) -> Option<KeyEvent> {
+        self.bytes
+            .next()
+            .map(|bytes| decode_event([bytes[0], bytes[1]]))
+    }
+}
+
+fn checksum(bytes: &[u8]) -> u16 {
+    let mut crc = Crc16::new();
+    crc.update(bytes);
+    crc.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut sender = FrameSender::new();
+        let mut receiver = FrameReceiver::new();
+        let mut buf = [0; 16];
+
+        let len = sender.write(&[1, 2, 3], &mut buf).unwrap();
+        assert_eq!(len, 3 + OVERHEAD);
+        assert_eq!(&buf[..5], &[0, 3, 1, 2, 3]);
+
+        let frame = receiver.receive(&buf[..len]).unwrap();
+        assert_eq!(frame.payload(), &[1, 2, 3]);
+        assert_eq!(frame.lost(), 0);
+
+        assert_eq!(
+            sender.write(&[0; 13], &mut buf),
+            Err(FrameError::BufferTooSmall)
+        );
+        assert_eq!(
+            receiver.receive(&buf[..len - 1]),
+            Err(FrameError::Truncated)
+        );
+    }
+
+    #[test]
+    fn corrupted_replayed_and_lost() {
+        let mut sender = FrameSender::new();
+        let mut receiver = FrameReceiver::new();
+        let mut first = [0; 8];
+        let mut buf = [0; 8];
+
+        sender.write(&[0x04], &mut first).unwrap();
+        assert!(receiver.receive(&first).is_ok());
+        assert_eq!(receiver.receive(&first), Err(FrameError::Replayed));
+
+        sender.write(&[0x05], &mut buf).unwrap();
+        buf[2] ^= 0x01;
+        assert_eq!(receiver.receive(&buf), Err(FrameError::Corrupted));
+
+        sender.write(&[0x06], &mut buf).unwrap();
+        let frame = receiver.receive(&buf).unwrap();
+        assert_eq!(frame.counter(), 2);
+        assert_eq!(frame.lost(), 1);
+
+        receiver.reset();
+        assert!(receiver.receive(&first).is_ok());
+    }
+
+    #[test]
+    fn key_events() {
+        let down = KeyEvent::KeyDown(Coordinate::new(127, 255));
+        let up = KeyEvent::KeyUp(Coordinate::new(0, 3));
+
+        assert_eq!(encode_event(down), Some([0xff, 0xff]));
+        assert_eq!(encode_event(up), Some([0x00, 0x03]));
+        assert_eq!(encode_event(KeyEvent::KeyUp(Coordinate::new(128, 0))), None);
+        assert_eq!(encode_event(KeyEvent::NoEvent), None);
+        assert!(KeyEvents::new(&[0xff, 0xff, 0x00, 0x03, 0x01]).eq([down, up]));
+    }
+}
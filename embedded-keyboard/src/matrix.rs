@@ -0,0 +1,238 @@
+//! Bit-packed matrix state.
+//!
+//! The canonical wire format of the pressed state of a whole key matrix,
+//! shared by the [`split`](crate::split) links, factory tests and debug
+//! tooling:
+//!
+//! | Byte     | Contents                                       |
+//! |----------|------------------------------------------------|
+//! | 0        | Format version, [`VERSION`]                    |
+//! | 1        | Number of rows `r`                             |
+//! | 2        | Number of columns `c`                          |
+//! | 3..3+n   | Bitmap of pressed keys, `n` = ⌈`r`·`c`/8⌉ bytes |
+//!
+//! The bitmap holds a bit per key in row-major order, the first key in
+//! bit 0 of the first byte. Unused bits of the last byte are zero.
+//!
+//! A [`MatrixSnapshotSource`] is hardware scanning the matrix by itself,
+//! like the keyboard scan peripheral of an embedded controller, and
+//! yielding raw snapshots of it for the usual debounce to run on.
+
+use crate::{Coordinate, ErrorType, KeyEvent};
+
+/// Hardware scanning a key matrix by itself, like the keyboard scan
+/// peripherals of embedded controllers such as the Nuvoton NPCX or ITE
+/// ones, or a PIO state machine.
+///
+/// Such a source replaces the GPIO scanner of a keyboard: its snapshots
+/// are the raw levels of the matrix, which the scanner then masks and
+/// debounces as it does the pins, so that keymaps, reports and the rest
+/// of the pipeline are unchanged.
+///
+/// Bit `y` of `levels[x]` is the level of row `y` read while column `x`
+/// was driven active, `1` being high, for matrices of up to 32 rows.
+pub trait MatrixSnapshotSource<const COLS: usize>: ErrorType {
+    /// Write the latest snapshot of the matrix to `levels`, returning
+    /// whether it was captured since the last read. `levels` is left
+    /// unchanged if there is no new snapshot yet.
+    fn read(&mut self, levels: &mut [u32; COLS]) -> Result<bool, Self::Error>;
+}
+
+impl<T: MatrixSnapshotSource<COLS> + ?Sized, const COLS: usize> MatrixSnapshotSource<COLS>
+    for &mut T
+{
+    #[inline]
+    fn read(&mut self, levels: &mut [u32; COLS]) -> Result<bool, Self::Error> {
+        T::read(self, levels)
+    }
+}
+
+/// Version of the format written by [`MatrixState::encode`].
+pub const VERSION: u8 = 1;
+
+/// Bytes the header adds to the bitmap.
+pub const HEADER_LEN: usize = 3;
+
+/// Length of an encoded `rows` by `cols` matrix.
+pub const fn encoded_len(rows: usize, cols: usize) -> usize {
+    HEADER_LEN + (rows * cols).div_ceil(8)
+}
+
+/// Errors produced while encoding or decoding a matrix state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MatrixError {
+    /// The buffer cannot hold the encoded state
+    BufferTooSmall,
+    /// The encoded state is shorter than its header says
+    Truncated,
+    /// The encoded state uses an unknown version of the format
+    UnsupportedVersion(u8),
+    /// The encoded state is of a matrix of another size
+    SizeMismatch {
+        /// Number of rows of the encoded matrix
+        rows: u8,
+        /// Number of columns of the encoded matrix
+        cols: u8,
+    },
+}
+
+/// Pressed state of every key of a `ROWS` by `COLS` matrix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MatrixState<const ROWS: usize, const COLS: usize> {
+    pressed: [[bool; COLS]; ROWS],
+}
+
+impl<const ROWS: usize, const COLS: usize> MatrixState<ROWS, COLS> {
+    /// Length of the encoded state.
+    pub const ENCODED_LEN: usize = encoded_len(ROWS, COLS);
+
+    /// Create a state with nothing pressed.
+    pub const fn new() -> Self {
+        const {
+            assert!(ROWS <= u8::MAX as usize && COLS <= u8::MAX as usize);
+        }
+
+        Self {
+            pressed: [[false; COLS]; ROWS],
+        }
+    }
+
+    /// Whether the key at `coordinate` is pressed.
+    pub fn is_pressed(&self, coordinate: Coordinate) -> bool {
+        self.pressed
+            .get(coordinate.row())
+            .and_then(|row| row.get(coordinate.col()))
+            .is_some_and(|pressed| *pressed)
+    }
+
+    /// Change the state of the key at `coordinate`. Keys outside the
+    /// matrix are ignored.
+    pub fn set(&mut self, coordinate: Coordinate, pressed: bool) {
+        if let Some(key) = self
+            .pressed
+            .get_mut(coordinate.row())
+            .and_then(|row| row.get_mut(coordinate.col()))
+        {
+            *key = pressed;
+        }
+    }
+
+    /// Apply key `events`. Keys outside the matrix are ignored.
+    pub fn update(&mut self, events: impl IntoIterator<Item = KeyEvent>) {
+        for event in events {
+            match event {
+                KeyEvent::KeyDown(c) => self.set(c, true),
+                KeyEvent::KeyUp(c) => self.set(c, false),
+                KeyEvent::NoEvent => {}
+            }
+        }
+    }
+
+    /// Pressed keys, in row-major order.
+    pub fn pressed(&self) -> impl Iterator<Item = Coordinate> + '_ {
+        self.pressed.iter().enumerate().flat_map(|(row, keys)| {
+            keys.iter()
+                .enumerate()
+                .filter(|(_, pressed)| **pressed)
+                .map(move |(col, _)| Coordinate::new(row, col))
+        })
+    }
+
+    /// Encode the state into `buf`, returning its length.
+    pub fn encode(&self, buf: &mut [u8]) -> Result<usize, MatrixError> {
+        let encoded = buf
+            .get_mut(..Self::ENCODED_LEN)
+            .ok_or(MatrixError::BufferTooSmall)?;
+        let (header, bitmap) = encoded.split_at_mut(HEADER_LEN);
+
+        header.copy_from_slice(&[VERSION, ROWS as u8, COLS as u8]);
+        bitmap.fill(0);
+
+        for coordinate in self.pressed() {
+            let index = coordinate.index(COLS);
+            bitmap[index / 8] |= 1 << (index % 8);
+        }
+
+        Ok(Self::ENCODED_LEN)
+    }
+
+    /// Decode a state encoded by [`MatrixState::encode`] at the start of
+    /// `bytes`.
+    pub fn decode(bytes: &[u8]) -> Result<Self, MatrixError> {
+        let header = bytes.get(..HEADER_LEN).ok_or(MatrixError::Truncated)?;
+
+        if header[0] != VERSION {
+            return Err(MatrixError::UnsupportedVersion(header[0]));
+        }
+        if usize::from(header[1]) != ROWS || usize::from(header[2]) != COLS {
+            return Err(MatrixError::SizeMismatch {
+                rows: header[1],
+                cols: header[2],
+            });
+        }
+
+        let bitmap = bytes
+            .get(HEADER_LEN..Self::ENCODED_LEN)
+            .ok_or(MatrixError::Truncated)?;
+        let mut state = Self::new();
+
+        for (row, keys) in state.pressed.iter_mut().enumerate() {
+            for (col, pressed) in keys.iter_mut().enumerate() {
+                let index = Coordinate::new(row, col).index(COLS);
+                *pressed = bitmap[index / 8] & (1 << (index % 8)) != 0;
+            }
+        }
+
+        Ok(state)
+    }
+}
+
+impl<const ROWS: usize, const COLS: usize> Default for MatrixState<ROWS, COLS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut state = MatrixState::<2, 5>::new();
+        let mut buf = [0; 8];
+
+        state.update([
+            KeyEvent::KeyDown(Coordinate::new(0, 0)),
+            KeyEvent::KeyDown(Coordinate::new(1, 4)),
+            KeyEvent::KeyDown(Coordinate::new(2, 0)),
+        ]);
+        assert_eq!(state.encode(&mut buf), Ok(5));
+        assert_eq!(&buf[..5], &[VERSION, 2, 5, 0x01, 0x02]);
+        assert_eq!(MatrixState::decode(&buf), Ok(state));
+        assert!(state
+            .pressed()
+            .eq([Coordinate::new(0, 0), Coordinate::new(1, 4)]));
+
+        assert_eq!(
+            state.encode(&mut buf[..4]),
+            Err(MatrixError::BufferTooSmall)
+        );
+        assert_eq!(
+            MatrixState::<2, 5>::decode(&buf[..4]),
+            Err(MatrixError::Truncated)
+        );
+        assert_eq!(
+            MatrixState::<5, 2>::decode(&buf),
+            Err(MatrixError::SizeMismatch { rows: 2, cols: 5 })
+        );
+
+        buf[0] = 2;
+        assert_eq!(
+            MatrixState::<2, 5>::decode(&buf),
+            Err(MatrixError::UnsupportedVersion(2))
+        );
+    }
+}
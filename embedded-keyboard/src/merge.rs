@@ -0,0 +1,241 @@
+//! Keyboards merged into one.
+//!
+//! A keyboard is not always a single matrix: switches may be wired
+//! straight to pins next to the matrix, like the push switch of a rotary
+//! encoder, or live on another half of a split keyboard.
+//! [`MergedKeyboard`] scans two [`Keyboard`]s as one, placing each at an
+//! offset of its own, so that the keymap and everything after it see a
+//! single matrix. Chaining merged keyboards merges any number of them.
+
+use crate::{Coordinate, Error, ErrorKind, ErrorType, KeyEvent, Keyboard};
+
+/// Error of a [`MergedKeyboard`], telling which keyboard failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MergeError<A, B> {
+    /// Scanning the first keyboard failed
+    First(A),
+    /// Scanning the second keyboard failed
+    Second(B),
+}
+
+impl<A: Error, B: Error> Error for MergeError<A, B> {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::First(e) => e.kind(),
+            Self::Second(e) => e.kind(),
+        }
+    }
+}
+
+/// Two keyboards merged into one [`Keyboard`].
+///
+/// Every scan scans the first keyboard, then the second, and places their
+/// keys at their offsets. Keys that cannot be placed, as their row or
+/// column would overflow, are dropped.
+///
+/// When a keyboard fails, the events the first keyboard produced in the
+/// same scan are kept and returned by the next scan, so that no press or
+/// release is lost to an error, e.g. of the link to the other half of a
+/// split keyboard.
+///
+/// Events beyond `N` in a single scan are dropped, so `N` must hold the
+/// most events both keyboards produce at once.
+///
+/// ```
+/// use embedded_keyboard::fake::FakeKeyboard;
+/// use embedded_keyboard::merge::MergedKeyboard;
+/// use embedded_keyboard::{Coordinate, KeyEvent, Keyboard};
+///
+/// const KEY: KeyEvent = KeyEvent::KeyDown(Coordinate::new(0, 0));
+/// let matrix = FakeKeyboard::new(&[&[KEY]]);
+/// let push = FakeKeyboard::new(&[&[KEY]]);
+/// let mut keyboard =
+///     MergedKeyboard::<_, _, 2>::new(matrix, Coordinate::new(0, 0), push, Coordinate::new(4, 0));
+///
+/// assert_eq!(
+///     keyboard.scan().unwrap(),
+///     [KEY, KeyEvent::KeyDown(Coordinate::new(4, 0))]
+/// );
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MergedKeyboard<A, B, const N: usize> {
+    first: A,
+    second: B,
+    first_offset: Coordinate,
+    second_offset: Coordinate,
+    events: [KeyEvent; N],
+    len: usize,
+    // Events of the first keyboard from a failed scan, at the start of
+    // `events`.
+    kept: usize,
+}
+
+impl<A: Keyboard, B: Keyboard, const N: usize> MergedKeyboard<A, B, N> {
+    /// Merge `first` and `second`, placing their keys at row 0, column 0
+    /// at `first_offset` and `second_offset` respectively.
+    pub const fn new(
+        first: A,
+        first_offset: Coordinate,
+        second: B,
+        second_offset: Coordinate,
+    ) -> Self {
+        Self {
+            first,
+            second,
+            first_offset,
+            second_offset,
+            events: [KeyEvent::NoEvent; N],
+            len: 0,
+            kept: 0,
+        }
+    }
+
+    /// First keyboard.
+    pub fn first_mut(&mut self) -> &mut A {
+        &mut self.first
+    }
+
+    /// Second keyboard.
+    pub fn second_mut(&mut self) -> &mut B {
+        &mut self.second
+    }
+
+    /// Destroys this instance and returns both keyboards.
+    pub fn destroy(self) -> (A, B) {
+        (self.first, self.second)
+    }
+}
+
+impl<A: Keyboard, B: Keyboard, const N: usize> ErrorType for MergedKeyboard<A, B, N> {
+    type Error = MergeError<A::Error, B::Error>;
+}
+
+impl<A: Keyboard, B: Keyboard, const N: usize> Keyboard for MergedKeyboard<A, B, N> {
+    fn scan(&mut self) -> Result<&[KeyEvent], Self::Error> {
+        let kept = core::mem::take(&mut self.kept);
+
+        let first = match self.first.scan() {
+            Ok(first) => first,
+            Err(e) => {
+                self.kept = kept;
+                return Err(MergeError::First(e));
+            }
+        };
+        let len = merge(&mut self.events, kept, first, self.first_offset);
+
+        // The first keyboard's events are consumed: keep them for the
+        // next scan if the second keyboard fails.
+        let second = match self.second.scan() {
+            Ok(second) => second,
+            Err(e) => {
+                self.kept = len;
+                return Err(MergeError::Second(e));
+            }
+        };
+        self.len = merge(&mut self.events, len, second, self.second_offset);
+
+        Ok(&self.events[..self.len])
+    }
+
+    fn activity(&self) -> bool {
+        self.first.activity() || self.second.activity()
+    }
+}
+
+/// `coordinate` of a keyboard placed at `at` in the merged matrix, or
+/// `None` if it would not fit a [`Coordinate`].
+pub(crate) fn place(coordinate: Coordinate, at: Coordinate) -> Option<Coordinate> {
+    let rows = isize::try_from(at.row()).ok()?;
+    let cols = isize::try_from(at.col()).ok()?;
+    coordinate.offset(rows, cols)
+}
+
+/// Append `events`, placed at `at`, to the first `len` of `merged`,
+/// returning the new length. Events whose keys cannot be placed are
+/// dropped.
+fn merge(merged: &mut [KeyEvent], len: usize, events: &[KeyEvent], at: Coordinate) -> usize {
+    let events = events.iter().filter_map(|event| match *event {
+        KeyEvent::KeyDown(c) => place(c, at).map(KeyEvent::KeyDown),
+        KeyEvent::KeyUp(c) => place(c, at).map(KeyEvent::KeyUp),
+        KeyEvent::NoEvent => None,
+    });
+
+    let mut len = len;
+    for (slot, event) in merged[len..].iter_mut().zip(events) {
+        *slot = event;
+        len += 1;
+    }
+
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake::FakeKeyboard;
+
+    const KEY: Coordinate = Coordinate::new(0, 1);
+
+    #[test]
+    fn keys_placed_at_offsets() {
+        let matrix = FakeKeyboard::new(&[&[KeyEvent::KeyDown(KEY)], &[KeyEvent::KeyUp(KEY)]]);
+        let push = FakeKeyboard::new(&[&[KeyEvent::KeyDown(KEY), KeyEvent::NoEvent], &[]]);
+        let mut keyboard = MergedKeyboard::<_, _, 2>::new(
+            matrix,
+            Coordinate::new(0, 0),
+            push,
+            Coordinate::new(2, 3),
+        );
+
+        assert_eq!(
+            keyboard.scan(),
+            Ok(&[
+                KeyEvent::KeyDown(KEY),
+                KeyEvent::KeyDown(Coordinate::new(2, 4))
+            ][..])
+        );
+        assert!(keyboard.activity());
+        assert_eq!(keyboard.scan(), Ok(&[KeyEvent::KeyUp(KEY)][..]));
+        assert!(keyboard.activity());
+
+        keyboard.second_mut().fail_at(2);
+        assert_eq!(keyboard.scan(), Err(MergeError::Second(ErrorKind::Other)));
+        assert_eq!(keyboard.scan(), Ok(&[][..]));
+    }
+
+    #[test]
+    fn events_kept_when_second_fails() {
+        let first = FakeKeyboard::new(&[&[KeyEvent::KeyDown(KEY)], &[KeyEvent::KeyUp(KEY)]]);
+        let mut second = FakeKeyboard::new(&[&[], &[], &[KeyEvent::KeyDown(KEY)]]);
+        second.fail_at(1);
+        let mut keyboard = MergedKeyboard::<_, _, 2>::new(
+            first,
+            Coordinate::new(0, 0),
+            second,
+            Coordinate::new(2, 3),
+        );
+
+        assert_eq!(keyboard.scan(), Ok(&[KeyEvent::KeyDown(KEY)][..]));
+        assert_eq!(keyboard.scan(), Err(MergeError::Second(ErrorKind::Other)));
+        assert_eq!(keyboard.scan(), Ok(&[KeyEvent::KeyUp(KEY)][..]));
+        assert_eq!(
+            keyboard.scan(),
+            Ok(&[KeyEvent::KeyDown(Coordinate::new(2, 4))][..])
+        );
+    }
+
+    #[test]
+    fn unplaceable_keys_dropped() {
+        let first = FakeKeyboard::new(&[&[KeyEvent::KeyDown(KEY)]]);
+        let second = FakeKeyboard::new(&[&[KeyEvent::KeyDown(KEY)]]);
+        let mut keyboard = MergedKeyboard::<_, _, 2>::new(
+            first,
+            Coordinate::new(0, 0),
+            second,
+            Coordinate::new(0, usize::MAX),
+        );
+
+        assert_eq!(keyboard.scan(), Ok(&[KeyEvent::KeyDown(KEY)][..]));
+    }
+}
@@ -0,0 +1,244 @@
+//! Power management of the scan loop.
+//!
+//! Scanning the matrix at full rate keeps the MCU awake; on battery
+//! powered keyboards, slowing down once the keyboard sits idle is the
+//! biggest saving there is. [`IdleManager`] tracks activity after every
+//! scan and steps the [`ScanRate`] down after configurable idle times,
+//! back up to full rate as soon as a key is touched, and tells the
+//! application through a [`ScanRateHook`] so that it can reprogram its
+//! scan timer, or arm pin interrupts.
+
+/// How often the keyboard is scanned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ScanRate {
+    /// Scanning every [`IdlePolicy::full_interval`] milliseconds
+    Full,
+    /// Scanning every [`IdlePolicy::slow_interval`] milliseconds
+    Slow,
+    /// Not scanning; waiting for a pin interrupt, reported with
+    /// [`IdleManager::wake`]
+    Interrupt,
+}
+
+/// Scan intervals, and idle times after which they change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IdlePolicy {
+    /// Scan interval while active, in milliseconds
+    pub full_interval: u16,
+    /// Scan interval once idle, in milliseconds
+    pub slow_interval: u16,
+    /// Idle time before scanning slows down, in milliseconds
+    pub slow_after: u32,
+    /// Idle time before switching to interrupts, in milliseconds, or
+    /// `None` to keep scanning slowly
+    pub interrupt_after: Option<u32>,
+}
+
+impl IdlePolicy {
+    /// Default policy: 1 millisecond scans, slowing down to 20
+    /// milliseconds after 5 seconds idle, and switching to interrupts
+    /// after a minute.
+    pub const DEFAULT: Self = Self {
+        full_interval: 1,
+        slow_interval: 20,
+        slow_after: 5_000,
+        interrupt_after: Some(60_000),
+    };
+}
+
+impl Default for IdlePolicy {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Told by an [`IdleManager`] when the scan rate changes. Closures taking
+/// the time, the new rate and its interval implement this trait.
+pub trait ScanRateHook {
+    /// Called when the scan rate changed to `rate` at `now`, with its
+    /// scan interval in milliseconds, or `None` in interrupt mode.
+    fn scan_rate(&mut self, now: u32, rate: ScanRate, interval: Option<u16>);
+}
+
+impl ScanRateHook for () {
+    #[inline]
+    fn scan_rate(&mut self, _now: u32, _rate: ScanRate, _interval: Option<u16>) {}
+}
+
+impl<F: FnMut(u32, ScanRate, Option<u16>)> ScanRateHook for F {
+    #[inline]
+    fn scan_rate(&mut self, now: u32, rate: ScanRate, interval: Option<u16>) {
+        self(now, rate, interval);
+    }
+}
+
+/// Adaptive scan rate.
+///
+/// After every scan, the application passes whether anything happened,
+/// typically [`Keyboard::activity`](crate::Keyboard::activity) or whether
+/// events were produced, to [`IdleManager::update`], then waits
+/// [`IdleManager::interval`] before scanning again. In interrupt mode it
+/// stops scanning altogether, and reports the interrupt with
+/// [`IdleManager::wake`].
+pub struct IdleManager<H = ()> {
+    policy: IdlePolicy,
+    rate: ScanRate,
+    last_activity: u32,
+    hook: H,
+}
+
+impl IdleManager {
+    /// Create a manager following `policy`, scanning at full rate as of
+    /// `now`.
+    pub const fn new(policy: IdlePolicy, now: u32) -> Self {
+        Self::with_hook(policy, now, ())
+    }
+}
+
+impl<H: ScanRateHook> IdleManager<H> {
+    /// Create a manager telling `hook` about every change of scan rate.
+    pub const fn with_hook(policy: IdlePolicy, now: u32, hook: H) -> Self {
+        Self {
+            policy,
+            rate: ScanRate::Full,
+            last_activity: now,
+            hook,
+        }
+    }
+
+    /// Mutable access to the hook.
+    pub fn hook_mut(&mut self) -> &mut H {
+        &mut self.hook
+    }
+
+    /// Policy followed.
+    pub const fn policy(&self) -> &IdlePolicy {
+        &self.policy
+    }
+
+    /// Change the policy. Takes effect on the next update.
+    pub fn set_policy(&mut self, policy: IdlePolicy) {
+        self.policy = policy;
+    }
+
+    /// Current scan rate.
+    pub const fn rate(&self) -> ScanRate {
+        self.rate
+    }
+
+    /// Interval before the next scan, in milliseconds, or `None` in
+    /// interrupt mode.
+    pub const fn interval(&self) -> Option<u16> {
+        match self.rate {
+            ScanRate::Full => Some(self.policy.full_interval),
+            ScanRate::Slow => Some(self.policy.slow_interval),
+            ScanRate::Interrupt => None,
+        }
+    }
+
+    /// Record the outcome of a scan at `now`, returning the scan rate to
+    /// go on with. Activity restores the full rate at once.
+    pub fn update(&mut self, now: u32, activity: bool) -> ScanRate {
+        if activity {
+            self.last_activity = now;
+        }
+
+        let idle = now.wrapping_sub(self.last_activity);
+        let rate = match self.policy.interrupt_after {
+            Some(after) if idle >= after => ScanRate::Interrupt,
+            _ if idle >= self.policy.slow_after => ScanRate::Slow,
+            _ => ScanRate::Full,
+        };
+
+        self.set_rate(now, rate);
+        rate
+    }
+
+    /// Report a pin interrupt at `now`, going back to full rate so that
+    /// the key pressed is scanned and debounced quickly.
+    pub fn wake(&mut self, now: u32) {
+        self.last_activity = now;
+        self.set_rate(now, ScanRate::Full);
+    }
+
+    /// Time at which the scan rate next steps down if nothing happens,
+    /// if it still does.
+    pub fn next_deadline(&self) -> Option<u32> {
+        let after = match self.rate {
+            ScanRate::Full => self.policy.slow_after,
+            ScanRate::Slow => self.policy.interrupt_after?,
+            ScanRate::Interrupt => return None,
+        };
+
+        Some(self.last_activity.wrapping_add(after))
+    }
+
+    fn set_rate(&mut self, now: u32, rate: ScanRate) {
+        if rate != self.rate {
+            self.rate = rate;
+            self.hook.scan_rate(now, rate, self.interval());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    impl ScanRateHook for Vec<(u32, ScanRate, Option<u16>)> {
+        fn scan_rate(&mut self, now: u32, rate: ScanRate, interval: Option<u16>) {
+            self.push((now, rate, interval));
+        }
+    }
+
+    #[test]
+    fn steps_down_and_back() {
+        let mut idle = IdleManager::with_hook(IdlePolicy::DEFAULT, 0, Vec::new());
+
+        assert_eq!(idle.update(1_000, true), ScanRate::Full);
+        assert_eq!(idle.next_deadline(), Some(6_000));
+        assert_eq!(idle.update(5_999, false), ScanRate::Full);
+        assert_eq!(idle.update(6_000, false), ScanRate::Slow);
+        assert_eq!(idle.interval(), Some(20));
+        assert_eq!(idle.next_deadline(), Some(61_000));
+
+        // Touching a key restores the full rate at once.
+        assert_eq!(idle.update(6_020, true), ScanRate::Full);
+
+        assert_eq!(idle.update(66_020, false), ScanRate::Interrupt);
+        assert_eq!(idle.interval(), None);
+        assert_eq!(idle.next_deadline(), None);
+        idle.wake(70_000);
+        assert_eq!(idle.update(70_001, false), ScanRate::Full);
+
+        assert_eq!(
+            idle.hook_mut()[..],
+            [
+                (6_000, ScanRate::Slow, Some(20)),
+                (6_020, ScanRate::Full, Some(1)),
+                (66_020, ScanRate::Interrupt, None),
+                (70_000, ScanRate::Full, Some(1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn without_interrupts() {
+        let mut idle = IdleManager::new(
+            IdlePolicy {
+                interrupt_after: None,
+                ..IdlePolicy::DEFAULT
+            },
+            u32::MAX - 1_000,
+        );
+
+        // Across the timestamp wrapping around.
+        assert_eq!(idle.update(4_000, false), ScanRate::Slow);
+        assert_eq!(idle.next_deadline(), None);
+        assert_eq!(idle.update(u32::MAX / 2, false), ScanRate::Slow);
+    }
+}
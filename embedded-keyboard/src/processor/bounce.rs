@@ -0,0 +1,173 @@
+use super::Processor;
+use crate::{Coordinate, KeyEvent};
+
+/// Bounce state of a single key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Bounce {
+    /// Released, at the given time if it was ever pressed
+    Up(Option<u32>),
+    /// Pressed and passed on
+    Down,
+    /// Pressed within the lockout, dropped along with its release
+    Locked,
+}
+
+/// BounceKeys accessibility processor.
+///
+/// Ignores a key pressed again within its lockout window after it was
+/// released, for users who unintentionally strike keys twice. Unlike the
+/// scanner's debouncing, which filters electrical noise within a few
+/// milliseconds, the lockout is a deliberate, user-facing delay, and can
+/// differ per key, e.g. to lock out only the keys a user tends to
+/// double-strike.
+pub struct BounceKeys<const ROWS: usize, const COLS: usize> {
+    keys: [[Bounce; COLS]; ROWS],
+    lockouts: [[u16; COLS]; ROWS],
+    enabled: bool,
+}
+
+impl<const ROWS: usize, const COLS: usize> BounceKeys<ROWS, COLS> {
+    /// Create an enabled processor locking out every key for `milliseconds`.
+    pub const fn new(milliseconds: u16) -> Self {
+        Self::with_lockouts([[milliseconds; COLS]; ROWS])
+    }
+
+    /// Create an enabled processor with per-key lockout windows.
+    pub const fn with_lockouts(lockouts: [[u16; COLS]; ROWS]) -> Self {
+        Self {
+            keys: [[Bounce::Up(None); COLS]; ROWS],
+            lockouts,
+            enabled: true,
+        }
+    }
+
+    /// Whether the processor is enabled.
+    pub const fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Enable or disable the processor. While disabled, every event is
+    /// passed on.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Lockout window of the key at `coordinate`, in milliseconds.
+    pub const fn lockout(&self, coordinate: Coordinate) -> Option<u16> {
+        if coordinate.within(ROWS, COLS) {
+            Some(self.lockouts[coordinate.row()][coordinate.col()])
+        } else {
+            None
+        }
+    }
+
+    /// Change the lockout window of the key at `coordinate`. Coordinates
+    /// outside the matrix are ignored.
+    pub fn set_lockout(&mut self, coordinate: Coordinate, milliseconds: u16) {
+        if let Some(lockout) = self
+            .lockouts
+            .get_mut(coordinate.row())
+            .and_then(|row| row.get_mut(coordinate.col()))
+        {
+            *lockout = milliseconds;
+        }
+    }
+}
+
+impl<const ROWS: usize, const COLS: usize> Processor for BounceKeys<ROWS, COLS> {
+    fn event(&mut self, now: u32, event: KeyEvent, mut emit: impl FnMut(KeyEvent)) {
+        let (KeyEvent::KeyDown(c) | KeyEvent::KeyUp(c)) = event else {
+            emit(event);
+            return;
+        };
+
+        let (Some(key), Some(lockout)) = (
+            self.keys
+                .get_mut(c.row())
+                .and_then(|row| row.get_mut(c.col())),
+            self.lockouts.get(c.row()).and_then(|row| row.get(c.col())),
+        ) else {
+            emit(event);
+            return;
+        };
+
+        match (event, *key) {
+            (KeyEvent::KeyDown(_), Bounce::Up(Some(released)))
+                if self.enabled && now.wrapping_sub(released) < u32::from(*lockout) =>
+            {
+                *key = Bounce::Locked;
+            }
+            (KeyEvent::KeyDown(_), _) => {
+                *key = Bounce::Down;
+                emit(event);
+            }
+            (KeyEvent::KeyUp(_), Bounce::Locked) => *key = Bounce::Up(None),
+            (KeyEvent::KeyUp(_), _) => {
+                *key = Bounce::Up(Some(now));
+                emit(event);
+            }
+            (KeyEvent::NoEvent, _) => emit(event),
+        }
+    }
+
+    fn tick(&mut self, _now: u32, _emit: impl FnMut(KeyEvent)) {}
+
+    fn next_deadline(&self) -> Option<u32> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::FilterKeys;
+    use std::vec::Vec;
+
+    fn down(col: usize) -> KeyEvent {
+        KeyEvent::KeyDown(Coordinate::new(0, col))
+    }
+
+    fn up(col: usize) -> KeyEvent {
+        KeyEvent::KeyUp(Coordinate::new(0, col))
+    }
+
+    fn run(processor: &mut impl Processor, now: u32, events: &[KeyEvent]) -> Vec<KeyEvent> {
+        let mut out = Vec::new();
+        processor.tick(now, |e| out.push(e));
+        for event in events {
+            processor.event(now, *event, |e| out.push(e));
+        }
+        out
+    }
+
+    #[test]
+    fn per_key_lockout() {
+        let mut bounce = BounceKeys::<1, 2>::new(100);
+        bounce.set_lockout(Coordinate::new(0, 1), 0);
+        assert_eq!(bounce.lockout(Coordinate::new(0, 1)), Some(0));
+        assert_eq!(bounce.lockout(Coordinate::new(1, 0)), None);
+
+        assert_eq!(run(&mut bounce, 0, &[down(0), down(1)]), [down(0), down(1)]);
+        assert_eq!(run(&mut bounce, 10, &[up(0), up(1)]), [up(0), up(1)]);
+        assert_eq!(run(&mut bounce, 20, &[down(0), down(1)]), [down(1)]);
+        assert_eq!(run(&mut bounce, 30, &[up(0)]), []);
+
+        bounce.set_enabled(false);
+        assert_eq!(run(&mut bounce, 40, &[down(0), up(0)]), [down(0), up(0)]);
+    }
+
+    #[test]
+    fn chained_after_slow_keys() {
+        let mut slow = FilterKeys::<1, 2>::new();
+        slow.set_slow_keys(50);
+        let mut chain = (slow, BounceKeys::<1, 2>::new(100));
+
+        assert_eq!(run(&mut chain, 0, &[down(0)]), []);
+        assert_eq!(chain.next_deadline(), Some(50));
+        assert_eq!(run(&mut chain, 50, &[up(0)]), [down(0), up(0)]);
+
+        // Accepted by SlowKeys, locked out by BounceKeys.
+        assert_eq!(run(&mut chain, 60, &[down(0)]), []);
+        assert_eq!(run(&mut chain, 110, &[]), []);
+    }
+}
@@ -0,0 +1,212 @@
+use super::Processor;
+use crate::{Coordinate, KeyEvent};
+
+/// Filter state of a single key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Filter {
+    /// Released, at the given time if it was ever pressed
+    Up(Option<u32>),
+    /// Pressed at the given time, not yet accepted
+    Pending(u32),
+    /// Pressed and passed on
+    Down,
+    /// Pressed, but the press was dropped along with its release
+    Ignored,
+}
+
+/// FilterKeys accessibility processor.
+///
+/// Mirrors the FilterKeys features of desktop operating systems, enforced
+/// in firmware so that they apply to any host, e.g. in kiosk deployments:
+///
+/// - SlowKeys ignores presses shorter than a threshold: a key has to be
+///   held down that long before its press is passed on, and is dropped
+///   altogether if released earlier.
+/// - Repeat lockout ignores a key pressed again within a window after it
+///   was released. [`BounceKeys`](super::BounceKeys) does the same with
+///   per-key windows.
+///
+/// Both are disabled by a threshold of zero, which is the default, and
+/// can be changed at any time.
+pub struct FilterKeys<const ROWS: usize, const COLS: usize> {
+    keys: [[Filter; COLS]; ROWS],
+    now: u32,
+    slow_keys: u16,
+    repeat_lockout: u16,
+}
+
+impl<const ROWS: usize, const COLS: usize> FilterKeys<ROWS, COLS> {
+    /// Create a filter with every feature disabled.
+    pub const fn new() -> Self {
+        Self {
+            keys: [[Filter::Up(None); COLS]; ROWS],
+            now: 0,
+            slow_keys: 0,
+            repeat_lockout: 0,
+        }
+    }
+
+    /// Time, in milliseconds, a key has to be held before its press is
+    /// accepted.
+    pub const fn slow_keys(&self) -> u16 {
+        self.slow_keys
+    }
+
+    /// Change the SlowKeys threshold, or disable SlowKeys with zero.
+    ///
+    /// Presses waiting to be accepted are judged against the new
+    /// threshold on the next tick.
+    pub fn set_slow_keys(&mut self, milliseconds: u16) {
+        self.slow_keys = milliseconds;
+    }
+
+    /// Time, in milliseconds, after a key is released during which
+    /// pressing it again is ignored.
+    pub const fn repeat_lockout(&self) -> u16 {
+        self.repeat_lockout
+    }
+
+    /// Change the repeat lockout window, or disable it with zero.
+    pub fn set_repeat_lockout(&mut self, milliseconds: u16) {
+        self.repeat_lockout = milliseconds;
+    }
+
+    fn key_mut(&mut self, coordinate: Coordinate) -> Option<&mut Filter> {
+        self.keys
+            .get_mut(coordinate.row())?
+            .get_mut(coordinate.col())
+    }
+}
+
+impl<const ROWS: usize, const COLS: usize> Default for FilterKeys<ROWS, COLS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const ROWS: usize, const COLS: usize> Processor for FilterKeys<ROWS, COLS> {
+    fn event(&mut self, now: u32, event: KeyEvent, mut emit: impl FnMut(KeyEvent)) {
+        let (slow_keys, repeat_lockout) = (self.slow_keys, self.repeat_lockout);
+        self.now = now;
+
+        let key = match event {
+            KeyEvent::KeyDown(c) | KeyEvent::KeyUp(c) => self.key_mut(c),
+            KeyEvent::NoEvent => None,
+        };
+
+        // Keys outside the matrix are not filtered.
+        let Some(key) = key else {
+            emit(event);
+            return;
+        };
+
+        match (event, *key) {
+            (KeyEvent::KeyDown(_), Filter::Up(Some(released)))
+                if now.wrapping_sub(released) < u32::from(repeat_lockout) =>
+            {
+                *key = Filter::Ignored;
+            }
+            (KeyEvent::KeyDown(_), Filter::Up(_)) if slow_keys > 0 => {
+                *key = Filter::Pending(now);
+            }
+            (KeyEvent::KeyDown(_), Filter::Up(_)) => {
+                *key = Filter::Down;
+                emit(event);
+            }
+            (KeyEvent::KeyUp(_), Filter::Down) => {
+                *key = Filter::Up(Some(now));
+                emit(event);
+            }
+            (KeyEvent::KeyUp(_), Filter::Pending(_) | Filter::Ignored) => {
+                *key = Filter::Up(None);
+            }
+            // Repeated presses or releases
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self, now: u32, mut emit: impl FnMut(KeyEvent)) {
+        let slow_keys = u32::from(self.slow_keys);
+        self.now = now;
+
+        for (row, keys) in self.keys.iter_mut().enumerate() {
+            for (col, key) in keys.iter_mut().enumerate() {
+                if let Filter::Pending(since) = *key {
+                    if now.wrapping_sub(since) >= slow_keys {
+                        *key = Filter::Down;
+                        emit(KeyEvent::KeyDown(Coordinate::new(row, col)));
+                    }
+                }
+            }
+        }
+    }
+
+    fn next_deadline(&self) -> Option<u32> {
+        self.keys
+            .iter()
+            .flatten()
+            .filter_map(|key| match key {
+                Filter::Pending(since) => Some(since.wrapping_add(u32::from(self.slow_keys))),
+                _ => None,
+            })
+            .min_by_key(|deadline| deadline.wrapping_sub(self.now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    fn down(col: usize) -> KeyEvent {
+        KeyEvent::KeyDown(Coordinate::new(0, col))
+    }
+
+    fn up(col: usize) -> KeyEvent {
+        KeyEvent::KeyUp(Coordinate::new(0, col))
+    }
+
+    fn run(filter: &mut FilterKeys<1, 2>, now: u32, events: &[KeyEvent]) -> Vec<KeyEvent> {
+        let mut out = Vec::new();
+        filter.tick(now, |e| out.push(e));
+        for event in events {
+            filter.event(now, *event, |e| out.push(e));
+        }
+        out
+    }
+
+    #[test]
+    fn disabled_passes_everything() {
+        let mut filter = FilterKeys::new();
+        assert_eq!(run(&mut filter, 0, &[down(0), up(0)]), [down(0), up(0)]);
+        assert_eq!(run(&mut filter, 1, &[down(0)]), [down(0)]);
+        assert_eq!(filter.next_deadline(), None);
+    }
+
+    #[test]
+    fn slow_keys_drop_short_presses() {
+        let mut filter = FilterKeys::new();
+        filter.set_slow_keys(300);
+
+        assert_eq!(run(&mut filter, 0, &[down(0)]), []);
+        assert_eq!(filter.next_deadline(), Some(300));
+        assert_eq!(run(&mut filter, 200, &[up(0), down(1)]), []);
+        assert_eq!(filter.next_deadline(), Some(500));
+        assert_eq!(run(&mut filter, 499, &[]), []);
+        assert_eq!(run(&mut filter, 500, &[]), [down(1)]);
+        assert_eq!(run(&mut filter, 600, &[up(1)]), [up(1)]);
+        assert_eq!(filter.next_deadline(), None);
+    }
+
+    #[test]
+    fn repeat_lockout_ignores_quick_repeats() {
+        let mut filter = FilterKeys::new();
+        filter.set_repeat_lockout(100);
+
+        assert_eq!(run(&mut filter, 0, &[down(0)]), [down(0)]);
+        assert_eq!(run(&mut filter, 10, &[up(0)]), [up(0)]);
+        assert_eq!(run(&mut filter, 50, &[down(0), down(1)]), [down(1)]);
+        assert_eq!(run(&mut filter, 60, &[up(0)]), []);
+        assert_eq!(run(&mut filter, 110, &[down(0)]), [down(0)]);
+    }
+}
@@ -0,0 +1,133 @@
+use super::Processor;
+use crate::{Coordinate, KeyEvent};
+
+/// Hold duration tracking processor.
+///
+/// Passes every event on unchanged, and records when each key of the
+/// matrix was pressed, so that the application can ask how long a key has
+/// been held, e.g. for long-press behaviors of its own. Keys outside the
+/// matrix are passed on, but not tracked.
+pub struct HoldTimer<const ROWS: usize, const COLS: usize> {
+    pressed: [[Option<u32>; COLS]; ROWS],
+}
+
+impl<const ROWS: usize, const COLS: usize> HoldTimer<ROWS, COLS> {
+    /// Create a hold timer with every key released.
+    pub const fn new() -> Self {
+        Self {
+            pressed: [[None; COLS]; ROWS],
+        }
+    }
+
+    /// Time at which the key at `coordinate` was pressed, if it is held.
+    pub fn pressed_at(&self, coordinate: Coordinate) -> Option<u32> {
+        *self.pressed.get(coordinate.row())?.get(coordinate.col())?
+    }
+
+    /// How long the key at `coordinate` has been held at `now`, in
+    /// milliseconds, if it is held.
+    pub fn held_for(&self, coordinate: Coordinate, now: u32) -> Option<u32> {
+        self.pressed_at(coordinate)
+            .map(|since| now.wrapping_sub(since))
+    }
+
+    /// Every key held, with how long it has been held at `now`.
+    pub fn held(&self, now: u32) -> impl Iterator<Item = (Coordinate, u32)> + '_ {
+        self.pressed
+            .iter()
+            .enumerate()
+            .flat_map(move |(row, keys)| {
+                keys.iter().enumerate().filter_map(move |(col, since)| {
+                    since.map(|since| (Coordinate::new(row, col), now.wrapping_sub(since)))
+                })
+            })
+    }
+
+    /// Forget every key, e.g. when the keyboard is reset.
+    pub fn clear(&mut self) {
+        self.pressed = [[None; COLS]; ROWS];
+    }
+}
+
+impl<const ROWS: usize, const COLS: usize> Default for HoldTimer<ROWS, COLS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const ROWS: usize, const COLS: usize> Processor for HoldTimer<ROWS, COLS> {
+    fn event(&mut self, now: u32, event: KeyEvent, mut emit: impl FnMut(KeyEvent)) {
+        let (c, pressed) = match event {
+            KeyEvent::KeyDown(c) => (c, Some(now)),
+            KeyEvent::KeyUp(c) => (c, None),
+            KeyEvent::NoEvent => {
+                emit(event);
+                return;
+            }
+        };
+
+        if let Some(key) = self
+            .pressed
+            .get_mut(c.row())
+            .and_then(|keys| keys.get_mut(c.col()))
+        {
+            // A repeated press keeps the time of the first.
+            if pressed.is_none() || key.is_none() {
+                *key = pressed;
+            }
+        }
+
+        emit(event);
+    }
+
+    fn tick(&mut self, _now: u32, _emit: impl FnMut(KeyEvent)) {}
+
+    fn next_deadline(&self) -> Option<u32> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    #[test]
+    fn tracks_hold_durations() {
+        let a = Coordinate::new(0, 1);
+        let b = Coordinate::new(1, 0);
+        let mut timer = HoldTimer::<2, 2>::new();
+        let mut out = Vec::new();
+
+        timer.event(100, KeyEvent::KeyDown(a), |e| out.push(e));
+        timer.event(250, KeyEvent::KeyDown(b), |e| out.push(e));
+        timer.event(300, KeyEvent::KeyDown(a), |e| out.push(e));
+        timer.event(400, KeyEvent::KeyDown(Coordinate::new(5, 5)), |e| {
+            out.push(e);
+        });
+
+        assert_eq!(out.len(), 4);
+        assert_eq!(timer.pressed_at(a), Some(100));
+        assert_eq!(timer.held_for(a, 600), Some(500));
+        assert_eq!(timer.held_for(b, 600), Some(350));
+        assert_eq!(timer.held_for(Coordinate::new(0, 0), 600), None);
+        assert_eq!(timer.held_for(Coordinate::new(5, 5), 600), None);
+        assert_eq!(timer.held(600).collect::<Vec<_>>(), [(a, 500), (b, 350)]);
+
+        timer.event(700, KeyEvent::KeyUp(a), |e| out.push(e));
+        assert_eq!(timer.held_for(a, 800), None);
+        assert_eq!(timer.held(800).collect::<Vec<_>>(), [(b, 550)]);
+
+        timer.clear();
+        assert_eq!(timer.held(800).count(), 0);
+    }
+
+    #[test]
+    fn hold_across_wrap() {
+        let a = Coordinate::new(0, 0);
+        let mut timer = HoldTimer::<1, 1>::default();
+
+        timer.event(u32::MAX - 9, KeyEvent::KeyDown(a), |_| {});
+        assert_eq!(timer.held_for(a, 10), Some(20));
+    }
+}
@@ -0,0 +1,259 @@
+use super::Processor;
+use crate::{Coordinate, KeyEvent};
+
+/// Step of a two-key confirmation, reported to an [`InterlockAudit`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum InterlockEvent {
+    /// One of the keys was pressed, starting the confirmation window
+    Armed,
+    /// Both keys are held, the output key was pressed
+    Confirmed,
+    /// The window expired before the second key was pressed
+    TimedOut,
+    /// The first key was released before the second was pressed
+    Cancelled,
+    /// A key was released after confirmation, the output key was released
+    Released,
+}
+
+/// Receives the [`InterlockEvent`]s of an [`Interlock`], e.g. to keep an
+/// audit trail of attempts. Closures taking the time and the event
+/// implement this trait.
+pub trait InterlockAudit {
+    /// Called when `event` happened at `now`.
+    fn interlock(&mut self, now: u32, event: InterlockEvent);
+}
+
+impl InterlockAudit for () {
+    #[inline]
+    fn interlock(&mut self, _now: u32, _event: InterlockEvent) {}
+}
+
+impl<F: FnMut(u32, InterlockEvent)> InterlockAudit for F {
+    #[inline]
+    fn interlock(&mut self, now: u32, event: InterlockEvent) {
+        self(now, event);
+    }
+}
+
+/// Confirmation state of an [`Interlock`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Neither key is held
+    Idle,
+    /// One key is held since the given time
+    Armed(u32),
+    /// Both keys are held, the output key is pressed
+    Confirmed,
+    /// Confirmation failed or ended, until both keys are released
+    Locked,
+}
+
+/// Two-person confirmation processor.
+///
+/// Swallows the events of two designated keys, and presses the `output`
+/// coordinate, which the keymap maps to the guarded action, only while
+/// both are held and the second was pressed within the window after the
+/// first. Once confirmation fails or ends, both keys must be released
+/// before another attempt.
+///
+/// Keys on different matrices can be interlocked by merging the matrices
+/// into one coordinate space before the processor, e.g. with
+/// [`Coordinate::offset`]. The `output` coordinate usually lies outside
+/// the physical matrix, so that it can only be pressed by the interlock.
+pub struct Interlock<A = ()> {
+    keys: [Coordinate; 2],
+    held: [bool; 2],
+    output: Coordinate,
+    window: u16,
+    state: State,
+    audit: A,
+}
+
+impl Interlock {
+    /// Create an interlock pressing `output` while both `keys` are held,
+    /// the second pressed within `window` milliseconds of the first.
+    pub const fn new(keys: [Coordinate; 2], output: Coordinate, window: u16) -> Self {
+        Self::with_audit(keys, output, window, ())
+    }
+}
+
+impl<A: InterlockAudit> Interlock<A> {
+    /// Create an interlock reporting every step to `audit`.
+    pub const fn with_audit(
+        keys: [Coordinate; 2],
+        output: Coordinate,
+        window: u16,
+        audit: A,
+    ) -> Self {
+        Self {
+            keys,
+            held: [false; 2],
+            output,
+            window,
+            state: State::Idle,
+            audit,
+        }
+    }
+
+    /// Mutable access to the audit receiver.
+    pub fn audit_mut(&mut self) -> &mut A {
+        &mut self.audit
+    }
+
+    /// Whether the output key is pressed.
+    pub fn confirmed(&self) -> bool {
+        self.state == State::Confirmed
+    }
+
+    fn set_state(&mut self, now: u32, state: State, event: InterlockEvent) {
+        self.state = state;
+        self.audit.interlock(now, event);
+    }
+}
+
+impl<A: InterlockAudit> Processor for Interlock<A> {
+    fn event(&mut self, now: u32, event: KeyEvent, mut emit: impl FnMut(KeyEvent)) {
+        let (KeyEvent::KeyDown(c) | KeyEvent::KeyUp(c)) = event else {
+            emit(event);
+            return;
+        };
+        let Some(index) = self.keys.iter().position(|key| *key == c) else {
+            emit(event);
+            return;
+        };
+
+        let pressed = matches!(event, KeyEvent::KeyDown(_));
+        if self.held[index] == pressed {
+            return;
+        }
+        self.held[index] = pressed;
+
+        let both = self.held == [true; 2];
+        let none = self.held == [false; 2];
+
+        match (self.state, pressed) {
+            (State::Idle, true) => self.set_state(now, State::Armed(now), InterlockEvent::Armed),
+            (State::Armed(since), true) if both => {
+                if now.wrapping_sub(since) <= u32::from(self.window) {
+                    self.set_state(now, State::Confirmed, InterlockEvent::Confirmed);
+                    emit(KeyEvent::KeyDown(self.output));
+                } else {
+                    self.set_state(now, State::Locked, InterlockEvent::TimedOut);
+                }
+            }
+            (State::Armed(_), false) => {
+                self.set_state(now, State::Idle, InterlockEvent::Cancelled);
+            }
+            (State::Confirmed, false) => {
+                emit(KeyEvent::KeyUp(self.output));
+                self.set_state(now, State::Locked, InterlockEvent::Released);
+            }
+            _ => {}
+        }
+
+        if none && self.state == State::Locked {
+            self.state = State::Idle;
+        }
+    }
+
+    fn tick(&mut self, now: u32, _emit: impl FnMut(KeyEvent)) {
+        if let State::Armed(since) = self.state {
+            if now.wrapping_sub(since) > u32::from(self.window) {
+                self.set_state(now, State::Locked, InterlockEvent::TimedOut);
+            }
+        }
+    }
+
+    fn next_deadline(&self) -> Option<u32> {
+        match self.state {
+            State::Armed(since) => Some(since.wrapping_add(u32::from(self.window)).wrapping_add(1)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    fn down(col: usize) -> KeyEvent {
+        KeyEvent::KeyDown(Coordinate::new(0, col))
+    }
+
+    fn up(col: usize) -> KeyEvent {
+        KeyEvent::KeyUp(Coordinate::new(0, col))
+    }
+
+    fn run(processor: &mut impl Processor, now: u32, events: &[KeyEvent]) -> Vec<KeyEvent> {
+        let mut out = Vec::new();
+        processor.tick(now, |e| out.push(e));
+        for event in events {
+            processor.event(now, *event, |e| out.push(e));
+        }
+        out
+    }
+
+    #[test]
+    fn confirm_within_window() {
+        let mut audit = Vec::new();
+        let mut interlock = Interlock::with_audit(
+            [Coordinate::new(0, 0), Coordinate::new(0, 1)],
+            Coordinate::new(0, 9),
+            100,
+            |now, event| audit.push((now, event)),
+        );
+
+        assert_eq!(run(&mut interlock, 0, &[down(0), down(2)]), [down(2)]);
+        assert_eq!(interlock.next_deadline(), Some(101));
+        assert_eq!(run(&mut interlock, 100, &[down(1)]), [down(9)]);
+        assert!(interlock.confirmed());
+        assert_eq!(run(&mut interlock, 200, &[up(0), down(0)]), [up(9)]);
+        assert_eq!(run(&mut interlock, 300, &[up(0), up(1)]), []);
+        assert_eq!(run(&mut interlock, 400, &[down(1), up(1)]), []);
+
+        assert_eq!(
+            audit,
+            [
+                (0, InterlockEvent::Armed),
+                (100, InterlockEvent::Confirmed),
+                (200, InterlockEvent::Released),
+                (400, InterlockEvent::Armed),
+                (400, InterlockEvent::Cancelled),
+            ]
+        );
+    }
+
+    #[test]
+    fn time_out() {
+        let mut audit = Vec::new();
+        let mut interlock = Interlock::with_audit(
+            [Coordinate::new(0, 0), Coordinate::new(0, 1)],
+            Coordinate::new(0, 9),
+            100,
+            |now, event| audit.push((now, event)),
+        );
+
+        assert_eq!(run(&mut interlock, 0, &[down(0)]), []);
+        assert_eq!(run(&mut interlock, 101, &[down(1)]), []);
+        assert!(!interlock.confirmed());
+        assert_eq!(interlock.next_deadline(), None);
+
+        // Both keys must be released before another attempt.
+        assert_eq!(run(&mut interlock, 150, &[up(1), down(1)]), []);
+        assert_eq!(run(&mut interlock, 200, &[up(0), up(1)]), []);
+        assert_eq!(run(&mut interlock, 300, &[down(1), down(0)]), [down(9)]);
+
+        assert_eq!(
+            audit,
+            [
+                (0, InterlockEvent::Armed),
+                (101, InterlockEvent::TimedOut),
+                (300, InterlockEvent::Armed),
+                (300, InterlockEvent::Confirmed),
+            ]
+        );
+    }
+}
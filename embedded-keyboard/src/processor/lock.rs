@@ -0,0 +1,223 @@
+use super::Processor;
+use crate::{Coordinate, KeyEvent};
+
+/// Lock state of a single key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Held {
+    /// Released
+    Up,
+    /// Pressed and passed on
+    Down,
+    /// Pressed and passed on, its release is due on the next tick
+    Releasing,
+    /// Pressed, but the press was dropped along with its release
+    Suppressed,
+}
+
+/// Keyboard lock processor.
+///
+/// While disabled, e.g. with the lid of a clamshell closed or while the
+/// keyboard is wiped in a cleaning mode, no key press is passed on: the
+/// keyboard keeps being scanned, so that presses still count as activity
+/// and can wake the system, but nothing reaches the keymap.
+///
+/// Keys held when the keyboard is disabled are released cleanly. By
+/// default their releases are passed on at once, on the next tick; with
+/// [`KeyboardLock::set_release_held`] turned off they are passed on only
+/// when the keys are actually released. Keys pressed while disabled stay
+/// suppressed until released, even if the keyboard is enabled again in
+/// the meantime.
+pub struct KeyboardLock<const ROWS: usize, const COLS: usize> {
+    keys: [[Held; COLS]; ROWS],
+    enabled: bool,
+    release_held: bool,
+    releasing: bool,
+    now: u32,
+}
+
+impl<const ROWS: usize, const COLS: usize> KeyboardLock<ROWS, COLS> {
+    /// Create an enabled keyboard lock.
+    pub const fn new() -> Self {
+        Self {
+            keys: [[Held::Up; COLS]; ROWS],
+            enabled: true,
+            release_held: true,
+            releasing: false,
+            now: 0,
+        }
+    }
+
+    /// Whether key presses are passed on.
+    pub const fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Enable or disable the keyboard.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if enabled == self.enabled {
+            return;
+        }
+        self.enabled = enabled;
+
+        let (from, to) = if enabled {
+            // Keys not released yet are simply still held.
+            (Held::Releasing, Held::Down)
+        } else if self.release_held {
+            (Held::Down, Held::Releasing)
+        } else {
+            return;
+        };
+
+        for key in self.keys.iter_mut().flatten() {
+            if *key == from {
+                *key = to;
+            }
+        }
+        self.releasing = !enabled;
+    }
+
+    /// Whether keys held when the keyboard is disabled are released at
+    /// once.
+    pub const fn release_held(&self) -> bool {
+        self.release_held
+    }
+
+    /// Change whether keys held when the keyboard is disabled are released
+    /// at once, or when they are actually released.
+    pub fn set_release_held(&mut self, release_held: bool) {
+        self.release_held = release_held;
+    }
+
+    fn key_mut(&mut self, coordinate: Coordinate) -> Option<&mut Held> {
+        self.keys
+            .get_mut(coordinate.row())?
+            .get_mut(coordinate.col())
+    }
+}
+
+impl<const ROWS: usize, const COLS: usize> Default for KeyboardLock<ROWS, COLS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const ROWS: usize, const COLS: usize> Processor for KeyboardLock<ROWS, COLS> {
+    fn event(&mut self, now: u32, event: KeyEvent, mut emit: impl FnMut(KeyEvent)) {
+        let enabled = self.enabled;
+        self.now = now;
+
+        let key = match event {
+            KeyEvent::KeyDown(c) | KeyEvent::KeyUp(c) => self.key_mut(c),
+            KeyEvent::NoEvent => None,
+        };
+
+        // Keys outside the matrix are passed on only while enabled.
+        let Some(key) = key else {
+            if enabled {
+                emit(event);
+            }
+            return;
+        };
+
+        match (event, *key) {
+            (KeyEvent::KeyDown(_), Held::Up) if enabled => {
+                *key = Held::Down;
+                emit(event);
+            }
+            (KeyEvent::KeyDown(_), Held::Up) => *key = Held::Suppressed,
+            (KeyEvent::KeyUp(_), Held::Down | Held::Releasing) => {
+                *key = Held::Up;
+                emit(event);
+            }
+            (KeyEvent::KeyUp(_), Held::Suppressed) => *key = Held::Up,
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self, now: u32, mut emit: impl FnMut(KeyEvent)) {
+        self.now = now;
+
+        if !self.releasing {
+            return;
+        }
+        self.releasing = false;
+
+        for (row, keys) in self.keys.iter_mut().enumerate() {
+            for (col, key) in keys.iter_mut().enumerate() {
+                if *key == Held::Releasing {
+                    *key = Held::Suppressed;
+                    emit(KeyEvent::KeyUp(Coordinate::new(row, col)));
+                }
+            }
+        }
+    }
+
+    fn next_deadline(&self) -> Option<u32> {
+        // Releases are due at once.
+        self.releasing.then_some(self.now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    fn down(col: usize) -> KeyEvent {
+        KeyEvent::KeyDown(Coordinate::new(0, col))
+    }
+
+    fn up(col: usize) -> KeyEvent {
+        KeyEvent::KeyUp(Coordinate::new(0, col))
+    }
+
+    fn run(processor: &mut impl Processor, now: u32, events: &[KeyEvent]) -> Vec<KeyEvent> {
+        let mut out = Vec::new();
+        processor.tick(now, |e| out.push(e));
+        for event in events {
+            processor.event(now, *event, |e| out.push(e));
+        }
+        out
+    }
+
+    #[test]
+    fn releases_held_keys() {
+        let mut lock = KeyboardLock::<1, 4>::new();
+        assert!(lock.is_enabled());
+        assert_eq!(run(&mut lock, 0, &[down(0), down(1)]), [down(0), down(1)]);
+
+        lock.set_enabled(false);
+        assert_eq!(lock.next_deadline(), Some(0));
+        assert_eq!(run(&mut lock, 10, &[down(2), down(9)]), [up(0), up(1)]);
+        assert_eq!(lock.next_deadline(), None);
+        assert_eq!(run(&mut lock, 20, &[up(0)]), []);
+
+        // Keys pressed or released while disabled stay suppressed.
+        lock.set_enabled(true);
+        assert_eq!(run(&mut lock, 30, &[up(1), up(2), down(3)]), [down(3)]);
+    }
+
+    #[test]
+    fn releases_held_keys_when_released() {
+        let mut lock = KeyboardLock::<1, 2>::new();
+        lock.set_release_held(false);
+        assert!(!lock.release_held());
+        assert_eq!(run(&mut lock, 0, &[down(0)]), [down(0)]);
+
+        lock.set_enabled(false);
+        assert_eq!(lock.next_deadline(), None);
+        assert_eq!(run(&mut lock, 10, &[down(1)]), []);
+        assert_eq!(run(&mut lock, 20, &[up(0), up(1)]), [up(0)]);
+    }
+
+    #[test]
+    fn reenabled_before_release() {
+        let mut lock = KeyboardLock::<1, 1>::new();
+        run(&mut lock, 0, &[down(0)]);
+
+        lock.set_enabled(false);
+        lock.set_enabled(true);
+        assert_eq!(lock.next_deadline(), None);
+        assert_eq!(run(&mut lock, 10, &[up(0)]), [up(0)]);
+    }
+}
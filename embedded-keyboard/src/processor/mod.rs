@@ -0,0 +1,78 @@
+//! Key event processors.
+//!
+//! A [`Processor`] is a stage between a [`Keyboard`] and the keymap
+//! [`Engine`], transforming the stream of [`KeyEvent`]s before keys are
+//! resolved: holding events back, dropping them or synthesizing new ones.
+//! Processors see the same millisecond timestamps as the engine, and can
+//! be chained by combining them in a tuple.
+//!
+//! [`Keyboard`]: crate::Keyboard
+//! [`Engine`]: crate::engine::Engine
+
+mod bounce;
+mod filter;
+mod hold;
+mod interlock;
+mod lock;
+mod tap;
+
+pub use self::bounce::*;
+pub use self::filter::*;
+pub use self::hold::*;
+pub use self::interlock::*;
+pub use self::lock::*;
+pub use self::tap::*;
+
+use crate::KeyEvent;
+
+/// Stage transforming key events.
+pub trait Processor {
+    /// Process `event`, which happened at `now`, handing the resulting
+    /// events to `emit`.
+    fn event(&mut self, now: u32, event: KeyEvent, emit: impl FnMut(KeyEvent));
+
+    /// Advance time to `now`, handing events held back until then to
+    /// `emit`.
+    fn tick(&mut self, now: u32, emit: impl FnMut(KeyEvent));
+
+    /// Time at which the processor next needs to be ticked, if it is
+    /// holding anything back.
+    fn next_deadline(&self) -> Option<u32>;
+}
+
+/// No processing: events are passed on as they are.
+impl Processor for () {
+    #[inline]
+    fn event(&mut self, _now: u32, event: KeyEvent, mut emit: impl FnMut(KeyEvent)) {
+        emit(event);
+    }
+
+    #[inline]
+    fn tick(&mut self, _now: u32, _emit: impl FnMut(KeyEvent)) {}
+
+    #[inline]
+    fn next_deadline(&self) -> Option<u32> {
+        None
+    }
+}
+
+/// Events flow through `A`, then through `B`.
+impl<A: Processor, B: Processor> Processor for (A, B) {
+    fn event(&mut self, now: u32, event: KeyEvent, mut emit: impl FnMut(KeyEvent)) {
+        let (a, b) = self;
+        a.event(now, event, |event| b.event(now, event, &mut emit));
+    }
+
+    fn tick(&mut self, now: u32, mut emit: impl FnMut(KeyEvent)) {
+        let (a, b) = self;
+        a.tick(now, |event| b.event(now, event, &mut emit));
+        b.tick(now, emit);
+    }
+
+    fn next_deadline(&self) -> Option<u32> {
+        match (self.0.next_deadline(), self.1.next_deadline()) {
+            (Some(a), Some(b)) => Some(crate::time::earliest(a, b)),
+            (a, b) => a.or(b),
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use super::Processor;
+use crate::{Coordinate, KeyEvent};
+
+/// Double-tap state of a [`DoubleTap`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Waiting for a first tap
+    Idle,
+    /// The key is pressed for a first tap since the given time
+    Pressed(u32),
+    /// The first tap ended at the given time
+    Tapped(u32),
+    /// The key is pressed for the second tap, the output key is pressed
+    Doubled,
+}
+
+/// Double-tap detection processor.
+///
+/// Passes the events of `key` on, and when the key is tapped, then
+/// pressed again within the window after the tap, additionally presses
+/// the `output` coordinate until the key is released. The keymap maps
+/// `output` to the action of the double tap, e.g. toggling an Fn-lock
+/// layer, while the key keeps its own action.
+///
+/// A tap is a press released within the window, and pressing any other
+/// key in between starts over. Several keys are watched by chaining one
+/// detector per key.
+pub struct DoubleTap {
+    key: Coordinate,
+    output: Coordinate,
+    window: u16,
+    state: State,
+}
+
+impl DoubleTap {
+    /// Create a detector pressing `output` when `key` is pressed within
+    /// `window` milliseconds of a tap.
+    pub const fn new(key: Coordinate, output: Coordinate, window: u16) -> Self {
+        Self {
+            key,
+            output,
+            window,
+            state: State::Idle,
+        }
+    }
+
+    /// Double-tap window, in milliseconds.
+    pub const fn window(&self) -> u16 {
+        self.window
+    }
+
+    /// Change the double-tap window.
+    pub fn set_window(&mut self, window: u16) {
+        self.window = window;
+    }
+
+    /// Whether the output key is pressed.
+    pub fn doubled(&self) -> bool {
+        self.state == State::Doubled
+    }
+
+    fn within(&self, now: u32, since: u32) -> bool {
+        now.wrapping_sub(since) <= u32::from(self.window)
+    }
+}
+
+impl Processor for DoubleTap {
+    fn event(&mut self, now: u32, event: KeyEvent, mut emit: impl FnMut(KeyEvent)) {
+        emit(event);
+
+        let (KeyEvent::KeyDown(c) | KeyEvent::KeyUp(c)) = event else {
+            return;
+        };
+        let pressed = matches!(event, KeyEvent::KeyDown(_));
+
+        if c != self.key {
+            if pressed && self.state != State::Doubled {
+                self.state = State::Idle;
+            }
+            return;
+        }
+
+        self.state = match (self.state, pressed) {
+            (State::Tapped(at), true) if self.within(now, at) => {
+                emit(KeyEvent::KeyDown(self.output));
+                State::Doubled
+            }
+            (State::Idle | State::Tapped(_), true) => State::Pressed(now),
+            (State::Pressed(since), false) if self.within(now, since) => State::Tapped(now),
+            (State::Doubled, false) => {
+                emit(KeyEvent::KeyUp(self.output));
+                State::Idle
+            }
+            (State::Pressed(_), false) => State::Idle,
+            (state, _) => state,
+        };
+    }
+
+    fn tick(&mut self, _now: u32, _emit: impl FnMut(KeyEvent)) {}
+
+    fn next_deadline(&self) -> Option<u32> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    const KEY: Coordinate = Coordinate::new(0, 0);
+    const OTHER: Coordinate = Coordinate::new(0, 1);
+    const OUTPUT: Coordinate = Coordinate::new(5, 0);
+
+    fn run(detector: &mut DoubleTap, events: &[(u32, KeyEvent)]) -> Vec<KeyEvent> {
+        let mut out = Vec::new();
+        for (now, event) in events {
+            detector.event(*now, *event, |e| out.push(e));
+        }
+        out
+    }
+
+    #[test]
+    fn double_tap_presses_output() {
+        let mut detector = DoubleTap::new(KEY, OUTPUT, 200);
+
+        let out = run(
+            &mut detector,
+            &[
+                (0, KeyEvent::KeyDown(KEY)),
+                (100, KeyEvent::KeyUp(KEY)),
+                (250, KeyEvent::KeyDown(KEY)),
+            ],
+        );
+        assert_eq!(
+            out,
+            [
+                KeyEvent::KeyDown(KEY),
+                KeyEvent::KeyUp(KEY),
+                KeyEvent::KeyDown(KEY),
+                KeyEvent::KeyDown(OUTPUT)
+            ]
+        );
+        assert!(detector.doubled());
+
+        let out = run(&mut detector, &[(900, KeyEvent::KeyUp(KEY))]);
+        assert_eq!(out, [KeyEvent::KeyUp(KEY), KeyEvent::KeyUp(OUTPUT)]);
+        assert!(!detector.doubled());
+    }
+
+    #[test]
+    fn slow_taps_are_not_doubled() {
+        let mut detector = DoubleTap::new(KEY, OUTPUT, 200);
+
+        // Second press too late after the tap, then a hold too long to be
+        // a tap.
+        let out = run(
+            &mut detector,
+            &[
+                (0, KeyEvent::KeyDown(KEY)),
+                (100, KeyEvent::KeyUp(KEY)),
+                (301, KeyEvent::KeyDown(KEY)),
+                (600, KeyEvent::KeyUp(KEY)),
+                (650, KeyEvent::KeyDown(KEY)),
+            ],
+        );
+        assert!(!out.contains(&KeyEvent::KeyDown(OUTPUT)));
+
+        // Another key pressed between the taps.
+        let out = run(
+            &mut detector,
+            &[
+                (700, KeyEvent::KeyUp(KEY)),
+                (720, KeyEvent::KeyDown(OTHER)),
+                (740, KeyEvent::KeyDown(KEY)),
+            ],
+        );
+        assert!(!out.contains(&KeyEvent::KeyDown(OUTPUT)));
+        assert!(!detector.doubled());
+    }
+}
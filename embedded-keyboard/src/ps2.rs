@@ -0,0 +1,254 @@
+//! Host side of PS/2 pointing devices.
+//!
+//! Laptop keyboard assemblies often carry a pointing stick speaking PS/2
+//! next to the key matrix. [`Ps2Mouse`] brings such a device up and
+//! decodes its movement packets, which the firmware then forwards along
+//! with the keys, e.g. with
+//! [`CompositeReporter::pointer`](crate::hid::CompositeReporter::pointer).
+//!
+//! Like [`Ps2Keyboard`](crate::ec::Ps2Keyboard), it is transport-agnostic:
+//! the application clocks the bytes in and out of the device, feeds every
+//! byte received to [`Ps2Mouse::receive`], and sends the bytes it is
+//! asked to.
+
+use crate::ec::{ACK, BAT_PASSED, RESEND};
+
+/// Error response of a device, sent when a command is rejected twice.
+pub const ERROR: u8 = 0xfc;
+/// Device ID of a standard PS/2 mouse, sent after the basic assurance
+/// test.
+pub const MOUSE_ID: u8 = 0x00;
+
+/// `0xff`: reset and run the basic assurance test.
+const RESET: u8 = 0xff;
+/// `0xf4`: enable data reporting.
+const ENABLE: u8 = 0xf4;
+
+/// Bit of the first byte of every packet which is always set.
+const ALWAYS_ONE: u8 = 1 << 3;
+
+/// Stage of bringing the device up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Ps2MouseState {
+    /// Waiting for the device to acknowledge the reset
+    Resetting,
+    /// Waiting for the basic assurance test result
+    SelfTest,
+    /// Waiting for the device ID
+    Identifying,
+    /// Waiting for the device to acknowledge enabling data reporting
+    Enabling,
+    /// Reporting movement
+    Streaming,
+    /// The device failed its self test or rejected a command; call
+    /// [`Ps2Mouse::start`] to try again
+    Failed,
+}
+
+/// Movement packet of a PS/2 mouse.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Ps2Packet {
+    buttons: u8,
+    x: i16,
+    y: i16,
+}
+
+impl Ps2Packet {
+    /// Decode a packet from its three bytes.
+    pub const fn from_bytes(bytes: [u8; 3]) -> Self {
+        Self {
+            buttons: bytes[0] & 0x07,
+            x: extend(bytes[1], bytes[0] & (1 << 4) != 0),
+            y: extend(bytes[2], bytes[0] & (1 << 5) != 0),
+        }
+    }
+
+    /// Button bitmap, with the left, right and middle buttons in bits 0,
+    /// 1 and 2, as in a [`MouseReport`](crate::hid::MouseReport).
+    pub const fn buttons(&self) -> u8 {
+        self.buttons
+    }
+
+    /// Motion as `(x, y)`, with y growing upwards as on PS/2.
+    pub const fn motion(&self) -> (i16, i16) {
+        (self.x, self.y)
+    }
+
+    /// Motion as `(x, y)` in the HID convention, with y growing downwards,
+    /// clamped to the range of a [`MouseReport`](crate::hid::MouseReport).
+    pub fn hid_motion(&self) -> (i8, i8) {
+        let clamp = |value: i16| value.clamp(-127, 127) as i8;
+
+        (clamp(self.x), clamp(-self.y))
+    }
+}
+
+/// Host side of a PS/2 mouse, like the pointing stick of a laptop
+/// keyboard.
+///
+/// [`Ps2Mouse::start`] resets the device; once it passed its self test and
+/// identified as a mouse, data reporting is enabled and every movement
+/// packet is returned by [`Ps2Mouse::receive`]. Requests to resend are
+/// answered, and a first byte without the bit every first byte has drops
+/// bytes until the packets line up again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Ps2Mouse {
+    state: Ps2MouseState,
+    last: u8,
+    packet: [u8; 3],
+    len: usize,
+}
+
+impl Ps2Mouse {
+    /// Create a driver for a device not reset yet.
+    pub const fn new() -> Self {
+        Self {
+            state: Ps2MouseState::Failed,
+            last: RESET,
+            packet: [0; 3],
+            len: 0,
+        }
+    }
+
+    /// Stage of bringing the device up.
+    pub const fn state(&self) -> Ps2MouseState {
+        self.state
+    }
+
+    /// Whether the device is reporting movement.
+    pub const fn is_streaming(&self) -> bool {
+        matches!(self.state, Ps2MouseState::Streaming)
+    }
+
+    /// Reset the device, passing the command to `send`, e.g. on power-up
+    /// or after it failed.
+    pub fn start(&mut self, send: impl FnOnce(u8)) {
+        self.state = Ps2MouseState::Resetting;
+        self.len = 0;
+        self.command(RESET, send);
+    }
+
+    /// Handle a byte received from the device, passing the commands to
+    /// send back to `send`.
+    ///
+    /// Returns the movement packet completed by `byte`, if any.
+    pub fn receive(&mut self, byte: u8, send: impl FnOnce(u8)) -> Option<Ps2Packet> {
+        match (self.state, byte) {
+            (Ps2MouseState::Streaming, _) => return self.stream(byte),
+            (Ps2MouseState::Failed, _) => {}
+            (_, RESEND) => send(self.last),
+            (_, ERROR) => self.state = Ps2MouseState::Failed,
+            (Ps2MouseState::Resetting, ACK) => self.state = Ps2MouseState::SelfTest,
+            (Ps2MouseState::SelfTest, BAT_PASSED) => self.state = Ps2MouseState::Identifying,
+            (Ps2MouseState::Identifying, MOUSE_ID) => {
+                self.state = Ps2MouseState::Enabling;
+                self.command(ENABLE, send);
+            }
+            (Ps2MouseState::Enabling, ACK) => self.state = Ps2MouseState::Streaming,
+            // Failed self test, or not a mouse.
+            (Ps2MouseState::SelfTest | Ps2MouseState::Identifying, _) => {
+                self.state = Ps2MouseState::Failed;
+            }
+            // Anything else while waiting for an acknowledgement is
+            // ignored.
+            (Ps2MouseState::Resetting | Ps2MouseState::Enabling, _) => {}
+        }
+
+        None
+    }
+
+    fn command(&mut self, command: u8, send: impl FnOnce(u8)) {
+        self.last = command;
+        send(command);
+    }
+
+    fn stream(&mut self, byte: u8) -> Option<Ps2Packet> {
+        if self.len == 0 && byte & ALWAYS_ONE == 0 {
+            return None;
+        }
+
+        self.packet[self.len] = byte;
+        self.len += 1;
+
+        if self.len < self.packet.len() {
+            return None;
+        }
+
+        self.len = 0;
+        Some(Ps2Packet::from_bytes(self.packet))
+    }
+}
+
+/// Value of a nine-bit two's complement, with its sign bit in the first
+/// byte of the packet.
+const fn extend(low: u8, negative: bool) -> i16 {
+    low as i16 - if negative { 256 } else { 0 }
+}
+
+impl Default for Ps2Mouse {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    fn receive(mouse: &mut Ps2Mouse, bytes: &[u8]) -> (Vec<Ps2Packet>, Vec<u8>) {
+        let mut sent = Vec::new();
+        let packets = bytes
+            .iter()
+            .filter_map(|byte| mouse.receive(*byte, |b| sent.push(b)))
+            .collect();
+
+        (packets, sent)
+    }
+
+    #[test]
+    fn bring_up() {
+        let mut mouse = Ps2Mouse::new();
+        let mut sent = Vec::new();
+        mouse.start(|b| sent.push(b));
+        assert_eq!(sent, [RESET]);
+
+        assert_eq!(
+            receive(&mut mouse, &[RESEND]),
+            (Vec::new(), [RESET].to_vec())
+        );
+        assert_eq!(
+            receive(&mut mouse, &[ACK, BAT_PASSED, MOUSE_ID]),
+            (Vec::new(), [ENABLE].to_vec())
+        );
+        assert_eq!(mouse.state(), Ps2MouseState::Enabling);
+        assert_eq!(receive(&mut mouse, &[RESEND]).1, [ENABLE]);
+        receive(&mut mouse, &[ACK]);
+        assert!(mouse.is_streaming());
+
+        let mut failed = Ps2Mouse::new();
+        failed.start(|_| {});
+        receive(&mut failed, &[ACK, ERROR]);
+        assert_eq!(failed.state(), Ps2MouseState::Failed);
+    }
+
+    #[test]
+    fn packets() {
+        let mut mouse = Ps2Mouse::new();
+        mouse.start(|_| {});
+        receive(&mut mouse, &[ACK, BAT_PASSED, MOUSE_ID, ACK]);
+
+        // Out of sync at first: the stray byte is dropped.
+        let (packets, sent) = receive(&mut mouse, &[0x05, 0x09, 0x05, 0x02, 0x38, 0xff, 0x80]);
+        assert!(sent.is_empty());
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0].buttons(), 0x01);
+        assert_eq!(packets[0].motion(), (5, 2));
+        assert_eq!(packets[0].hid_motion(), (5, -2));
+        assert_eq!(packets[1].motion(), (-1, -128));
+        assert_eq!(packets[1].hid_motion(), (-1, 127));
+    }
+}
@@ -0,0 +1,440 @@
+//! Import of keymaps exported from QMK.
+//!
+//! QMK Configurator and `qmk c2json` export keymaps as `keymap.json`
+//! files, whose layers list a QMK keycode expression per key, in the order
+//! of the keys in the board's `LAYOUT` macro rather than in matrix order.
+//! [`QmkKeymap`] parses such a file, and turns it into a [`Keymap`] given
+//! the matrix [`Coordinate`] of each key of the layout, as listed by the
+//! `matrix` entries of the layout in the board's `info.json`.
+//!
+//! Keymaps can be imported at runtime, or from a build script writing the
+//! [`KeymapSource`] of the keymap to a file included by the firmware, so
+//! that the JSON never reaches the target:
+//!
+//! ```
+//! use embedded_keyboard::qmk::{KeymapSource, QmkKeymap};
+//! use embedded_keyboard::{Action, Coordinate, KeyCode, Keymap};
+//!
+//! let json = r#"{
+//!     "keyboard": "pad",
+//!     "layout": "LAYOUT",
+//!     "layers": [["KC_A", "LT(1, KC_SPC)"], ["KC_1", "_______"]]
+//! }"#;
+//! let layout = [Coordinate::new(0, 1), Coordinate::new(0, 0)];
+//!
+//! let keymap: Keymap<2, 1, 2> = QmkKeymap::from_json(json)?.keymap(&layout)?;
+//! assert_eq!(
+//!     keymap.action(0, Coordinate::new(0, 0)),
+//!     Some(Action::layer_tap(1, KeyCode::KSpaceBar))
+//! );
+//!
+//! // In a build script, to be `include!`d as the value of a constant.
+//! let source = KeymapSource::new(&keymap).to_string();
+//! assert!(source.starts_with("::embedded_keyboard::Keymap::new("));
+//! # Ok::<(), embedded_keyboard::qmk::QmkError>(())
+//! ```
+
+use core::fmt;
+use std::string::String;
+use std::vec::Vec;
+
+use crate::json::{self, Value};
+use crate::{Action, Coordinate, Hold, HoldTap, Keymap};
+
+/// Error importing a QMK keymap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QmkError {
+    /// The file is not valid JSON, from the given byte offset
+    Json {
+        /// Byte offset of the error
+        offset: usize,
+    },
+    /// The file has no `layers` list of lists of keycodes
+    MissingLayers,
+    /// A keycode could not be parsed
+    Keycode {
+        /// Index of the layer
+        layer: usize,
+        /// Index of the key in the layout
+        index: usize,
+        /// The keycode
+        keycode: String,
+    },
+    /// The file has more layers than the keymap
+    TooManyLayers {
+        /// Number of layers in the file
+        layers: usize,
+    },
+    /// A layer does not have one keycode per key of the layout
+    LayerLength {
+        /// Index of the layer
+        layer: usize,
+        /// Number of keycodes in the layer
+        len: usize,
+    },
+    /// A key of the layout lies outside the matrix
+    OutsideMatrix {
+        /// Index of the key in the layout
+        index: usize,
+    },
+}
+
+impl fmt::Display for QmkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Json { offset } => write!(f, "invalid JSON at byte {offset}"),
+            Self::MissingLayers => f.write_str("no list of layers"),
+            Self::Keycode {
+                layer,
+                index,
+                keycode,
+            } => write!(f, "layer {layer}, key {index}: invalid keycode `{keycode}`"),
+            Self::TooManyLayers { layers } => write!(f, "too many layers: {layers}"),
+            Self::LayerLength { layer, len } => {
+                write!(f, "layer {layer}: {len} keycodes for the layout")
+            }
+            Self::OutsideMatrix { index } => write!(f, "key {index} outside the matrix"),
+        }
+    }
+}
+
+impl std::error::Error for QmkError {}
+
+/// Keymap parsed from a QMK `keymap.json` file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QmkKeymap {
+    keyboard: String,
+    name: String,
+    layout: String,
+    layers: Vec<Vec<Action>>,
+}
+
+impl QmkKeymap {
+    /// Parse the contents of a `keymap.json` file.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`QmkError::Json`] if `json` is not valid JSON,
+    /// [`QmkError::MissingLayers`] if it has no layers, or
+    /// [`QmkError::Keycode`] for the first keycode which could not be
+    /// parsed, see the [`FromStr`](core::str::FromStr) implementation of
+    /// [`Action`].
+    pub fn from_json(json: &str) -> Result<Self, QmkError> {
+        let document = json::parse(json).map_err(|offset| QmkError::Json { offset })?;
+        let string = |key| String::from(document.get(key).and_then(Value::as_str).unwrap_or(""));
+
+        let Some(Value::Array(values)) = document.get("layers") else {
+            return Err(QmkError::MissingLayers);
+        };
+
+        let mut layers = Vec::new();
+        for (layer, value) in values.iter().enumerate() {
+            let Value::Array(keycodes) = value else {
+                return Err(QmkError::MissingLayers);
+            };
+
+            let mut actions = Vec::new();
+            for (index, keycode) in keycodes.iter().enumerate() {
+                let Value::String(keycode) = keycode else {
+                    return Err(QmkError::Keycode {
+                        layer,
+                        index,
+                        keycode: String::new(),
+                    });
+                };
+                actions.push(keycode.parse().map_err(|_| QmkError::Keycode {
+                    layer,
+                    index,
+                    keycode: keycode.clone(),
+                })?);
+            }
+            layers.push(actions);
+        }
+
+        Ok(Self {
+            keyboard: string("keyboard"),
+            name: string("keymap"),
+            layout: string("layout"),
+            layers,
+        })
+    }
+
+    /// Name of the keyboard in QMK, if given.
+    pub fn keyboard(&self) -> &str {
+        &self.keyboard
+    }
+
+    /// Name of the keymap, if given.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Name of the `LAYOUT` macro the layers are laid out for, if given.
+    pub fn layout(&self) -> &str {
+        &self.layout
+    }
+
+    /// Actions of each layer, in the order of the layout.
+    pub fn layers(&self) -> &[Vec<Action>] {
+        &self.layers
+    }
+
+    /// Lay the layers out in a matrix, the key at `index` in the layout
+    /// being at `layout[index]` in the matrix.
+    ///
+    /// Matrix positions without a key, and layers beyond those of the file,
+    /// are [`Action::NoOp`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`QmkError::TooManyLayers`] if the file has more than
+    /// `LAYERS` layers, [`QmkError::LayerLength`] if a layer does not have
+    /// as many keys as `layout`, or [`QmkError::OutsideMatrix`] if a key
+    /// of `layout` lies outside the matrix.
+    pub fn keymap<const LAYERS: usize, const ROWS: usize, const COLS: usize>(
+        &self,
+        layout: &[Coordinate],
+    ) -> Result<Keymap<LAYERS, ROWS, COLS>, QmkError> {
+        if self.layers.len() > LAYERS {
+            return Err(QmkError::TooManyLayers {
+                layers: self.layers.len(),
+            });
+        }
+        if let Some(index) = layout.iter().position(|c| !c.within(ROWS, COLS)) {
+            return Err(QmkError::OutsideMatrix { index });
+        }
+
+        let mut layers = [[[Action::NoOp; COLS]; ROWS]; LAYERS];
+        for (layer, (keys, actions)) in layers.iter_mut().zip(&self.layers).enumerate() {
+            if actions.len() != layout.len() {
+                return Err(QmkError::LayerLength {
+                    layer,
+                    len: actions.len(),
+                });
+            }
+
+            for (coordinate, action) in layout.iter().zip(actions) {
+                keys[coordinate.row()][coordinate.col()] = *action;
+            }
+        }
+
+        Ok(Keymap::new(layers))
+    }
+}
+
+/// Rust expression of a [`Keymap`], for a build script to generate the
+/// keymap of the firmware.
+///
+/// Formatting a `KeymapSource` writes a `Keymap::new` call with every
+/// action spelled out, which evaluates in a constant context.
+pub struct KeymapSource<'a, const LAYERS: usize, const ROWS: usize, const COLS: usize> {
+    keymap: &'a Keymap<LAYERS, ROWS, COLS>,
+}
+
+impl<'a, const LAYERS: usize, const ROWS: usize, const COLS: usize>
+    KeymapSource<'a, LAYERS, ROWS, COLS>
+{
+    /// Rust expression of `keymap`.
+    pub fn new(keymap: &'a Keymap<LAYERS, ROWS, COLS>) -> Self {
+        Self { keymap }
+    }
+}
+
+impl<const LAYERS: usize, const ROWS: usize, const COLS: usize> fmt::Display
+    for KeymapSource<'_, LAYERS, ROWS, COLS>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("::embedded_keyboard::Keymap::new([\n")?;
+
+        for layer in 0..LAYERS {
+            f.write_str("    [\n")?;
+            for row in 0..ROWS {
+                f.write_str("        [")?;
+                for col in 0..COLS {
+                    let coordinate = Coordinate::new(row, col);
+                    let action = self.keymap.action(layer, coordinate).unwrap_or_default();
+                    if col > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", ActionSource::new(action))?;
+                }
+                f.write_str("],\n")?;
+            }
+            f.write_str("    ],\n")?;
+        }
+
+        f.write_str("])")
+    }
+}
+
+/// Rust expression of an [`Action`], e.g. for a build script generating
+/// keymaps of its own.
+pub struct ActionSource {
+    action: Action,
+}
+
+impl ActionSource {
+    /// Rust expression of `action`.
+    pub fn new(action: Action) -> Self {
+        Self { action }
+    }
+}
+
+impl fmt::Display for ActionSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const CRATE: &str = "::embedded_keyboard";
+
+        match self.action {
+            Action::NoOp => write!(f, "{CRATE}::Action::NoOp"),
+            Action::Transparent => write!(f, "{CRATE}::Action::Transparent"),
+            Action::Key(code) => write!(f, "{CRATE}::Action::Key({CRATE}::KeyCode::{code:?})"),
+            Action::Usage(usage) => write!(
+                f,
+                "{CRATE}::Action::Usage({CRATE}::Usage::new({:#06x}, {:#06x}))",
+                usage.page(),
+                usage.id()
+            ),
+            Action::Chord(keystroke) => write!(
+                f,
+                "{CRATE}::Action::chord({:#04x}, {CRATE}::KeyCode::{:?})",
+                keystroke.modifiers(),
+                keystroke.code()
+            ),
+            Action::MomentaryLayer(layer) => write!(f, "{CRATE}::Action::MomentaryLayer({layer})"),
+            Action::ToggleLayer(layer) => write!(f, "{CRATE}::Action::ToggleLayer({layer})"),
+            Action::HoldTap(HoldTap {
+                hold: Hold::Key(modifier),
+                tap,
+            }) => write!(
+                f,
+                "{CRATE}::Action::mod_tap({CRATE}::KeyCode::{modifier:?}, {CRATE}::KeyCode::{tap:?})"
+            ),
+            Action::HoldTap(HoldTap {
+                hold: Hold::Layer(layer),
+                tap,
+            }) => write!(
+                f,
+                "{CRATE}::Action::layer_tap({layer}, {CRATE}::KeyCode::{tap:?})"
+            ),
+            Action::SwapHands => write!(f, "{CRATE}::Action::SwapHands"),
+            Action::ToggleSwapHands => write!(f, "{CRATE}::Action::ToggleSwapHands"),
+            Action::Backlight(command) => write!(
+                f,
+                "{CRATE}::Action::Backlight({CRATE}::backlight::BacklightCommand::{command:?})"
+            ),
+            Action::Rgb(command) => write!(
+                f,
+                "{CRATE}::Action::Rgb({CRATE}::rgb::RgbCommand::{command:?})"
+            ),
+            Action::Animation(command) => write!(
+                f,
+                "{CRATE}::Action::Animation({CRATE}::animation::AnimationCommand::{command:?})"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeyCode;
+
+    const JSON: &str = r#"{
+        "version": 1,
+        "notes": "a \"quoted\" note\n",
+        "keyboard": "handwired/pad",
+        "keymap": "default",
+        "layout": "LAYOUT_ortho_2x2",
+        "layers": [
+            ["KC_ESC", "MO(1)", "LSFT_T(KC_A)", "KC_MPLY"],
+            ["RGB_TOG", "_______", "LCTL(KC_C)", "XXXXXXX"]
+        ],
+        "author": "",
+        "config": {"features": {"rgb_matrix": true}, "debounce": 5.5e0, "x": null}
+    }"#;
+
+    // Keys listed by the layout in column-major order.
+    const LAYOUT: [Coordinate; 4] = [
+        Coordinate::new(0, 0),
+        Coordinate::new(1, 0),
+        Coordinate::new(0, 1),
+        Coordinate::new(1, 1),
+    ];
+
+    #[test]
+    fn import_keymap_json() {
+        let qmk = QmkKeymap::from_json(JSON).unwrap();
+        assert_eq!(qmk.keyboard(), "handwired/pad");
+        assert_eq!(qmk.name(), "default");
+        assert_eq!(qmk.layout(), "LAYOUT_ortho_2x2");
+        assert_eq!(qmk.layers().len(), 2);
+
+        let keymap: Keymap<3, 2, 2> = qmk.keymap(&LAYOUT).unwrap();
+        let at = |layer, row, col| keymap.action(layer, Coordinate::new(row, col));
+        assert_eq!(at(0, 0, 0), Some(Action::Key(KeyCode::KEscape)));
+        assert_eq!(at(0, 1, 0), Some(Action::MomentaryLayer(1)));
+        assert_eq!(
+            at(0, 0, 1),
+            Some(Action::mod_tap(KeyCode::KpLeftShift, KeyCode::KA))
+        );
+        assert_eq!(at(1, 0, 1), Some(Action::chord(0x01, KeyCode::KC)));
+        assert_eq!(at(1, 1, 0), Some(Action::Transparent));
+        assert_eq!(at(2, 1, 1), Some(Action::NoOp));
+    }
+
+    #[test]
+    fn import_errors() {
+        assert_eq!(
+            QmkKeymap::from_json(r#"{"layers": [["KC_A"],]}"#),
+            Err(QmkError::Json { offset: 21 })
+        );
+        assert_eq!(
+            QmkKeymap::from_json(r#"{"layout": "LAYOUT"}"#),
+            Err(QmkError::MissingLayers)
+        );
+        assert_eq!(
+            QmkKeymap::from_json(r#"{"layers": [["KC_A", "MO(x)"]]}"#),
+            Err(QmkError::Keycode {
+                layer: 0,
+                index: 1,
+                keycode: "MO(x)".into()
+            })
+        );
+
+        let qmk = QmkKeymap::from_json(JSON).unwrap();
+        assert_eq!(
+            qmk.keymap::<1, 2, 2>(&LAYOUT),
+            Err(QmkError::TooManyLayers { layers: 2 })
+        );
+        assert_eq!(
+            qmk.keymap::<2, 2, 2>(&LAYOUT[..3]),
+            Err(QmkError::LayerLength { layer: 0, len: 4 })
+        );
+        assert_eq!(
+            qmk.keymap::<2, 1, 2>(&LAYOUT),
+            Err(QmkError::OutsideMatrix { index: 1 })
+        );
+    }
+
+    #[test]
+    fn keymap_source() {
+        let keymap = Keymap::new([[[
+            Action::layer_tap(1, KeyCode::KSpaceBar),
+            Action::chord(0x02, KeyCode::K1),
+        ]]]);
+
+        assert_eq!(
+            KeymapSource::new(&keymap).to_string(),
+            concat!(
+                "::embedded_keyboard::Keymap::new([\n",
+                "    [\n",
+                "        [::embedded_keyboard::Action::layer_tap(1, ",
+                "::embedded_keyboard::KeyCode::KSpaceBar), ",
+                "::embedded_keyboard::Action::chord(0x02, ",
+                "::embedded_keyboard::KeyCode::K1)],\n",
+                "    ],\n",
+                "])"
+            )
+        );
+    }
+}
@@ -0,0 +1,190 @@
+use crate::KeyEvent;
+
+/// What a full [`KeyEventQueue`] does with another event.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OverflowPolicy {
+    /// Make room by dropping the oldest queued event
+    #[default]
+    DropOldest,
+    /// Drop the new event, keeping the queued ones
+    DropNewest,
+}
+
+/// Bounded queue of key events.
+///
+/// Decouples scanning from processing, e.g. scanning in a timer interrupt
+/// and running the engine in the main loop. Every operation runs in
+/// constant time without allocating, so it is cheap enough for interrupt
+/// context; the queue is shared between contexts the usual way, e.g. in a
+/// `critical_section::Mutex<RefCell<_>>`.
+///
+/// When the queue is full, events are dropped according to its
+/// [`OverflowPolicy`], and the overflow is flagged until
+/// [`KeyEventQueue::take_overflow`] is called.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEventQueue<const N: usize> {
+    events: [KeyEvent; N],
+    head: usize,
+    len: usize,
+    policy: OverflowPolicy,
+    overflowed: bool,
+}
+
+impl<const N: usize> KeyEventQueue<N> {
+    /// Create an empty queue handling overflow according to `policy`.
+    pub const fn new(policy: OverflowPolicy) -> Self {
+        const {
+            assert!(N > 0);
+        }
+
+        Self {
+            events: [KeyEvent::NoEvent; N],
+            head: 0,
+            len: 0,
+            policy,
+            overflowed: false,
+        }
+    }
+
+    /// Overflow policy of the queue.
+    pub const fn policy(&self) -> OverflowPolicy {
+        self.policy
+    }
+
+    /// Maximum number of queued events.
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Number of queued events.
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether no events are queued.
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether the queue is full.
+    pub const fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    /// Queue `event`. [`KeyEvent::NoEvent`] is ignored.
+    ///
+    /// Returns `false` if an event had to be dropped.
+    pub fn push(&mut self, event: KeyEvent) -> bool {
+        if event == KeyEvent::NoEvent {
+            return true;
+        }
+
+        let full = self.is_full();
+
+        if full {
+            self.overflowed = true;
+
+            match self.policy {
+                OverflowPolicy::DropNewest => return false,
+                OverflowPolicy::DropOldest => {
+                    self.head = (self.head + 1) % N;
+                    self.len -= 1;
+                }
+            }
+        }
+
+        self.events[(self.head + self.len) % N] = event;
+        self.len += 1;
+
+        !full
+    }
+
+    /// Queue every event of a scan.
+    ///
+    /// Returns `false` if an event had to be dropped.
+    pub fn extend(&mut self, events: &[KeyEvent]) -> bool {
+        let mut fits = true;
+
+        for event in events {
+            fits &= self.push(*event);
+        }
+
+        fits
+    }
+
+    /// Take the oldest queued event.
+    pub fn pop(&mut self) -> Option<KeyEvent> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let event = self.events[self.head];
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+
+        Some(event)
+    }
+
+    /// Take every queued event, oldest first.
+    pub fn drain(&mut self) -> impl Iterator<Item = KeyEvent> + '_ {
+        core::iter::from_fn(move || self.pop())
+    }
+
+    /// Drop every queued event.
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+
+    /// Whether events were dropped since the last call, clearing the flag.
+    pub fn take_overflow(&mut self) -> bool {
+        core::mem::take(&mut self.overflowed)
+    }
+}
+
+impl<const N: usize> Default for KeyEventQueue<N> {
+    fn default() -> Self {
+        Self::new(OverflowPolicy::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Coordinate;
+
+    fn down(col: usize) -> KeyEvent {
+        KeyEvent::KeyDown(Coordinate::new(0, col))
+    }
+
+    #[test]
+    fn drop_oldest() {
+        let mut queue = KeyEventQueue::<2>::default();
+
+        assert!(queue.extend(&[down(0), KeyEvent::NoEvent, down(1)]));
+        assert!(queue.is_full());
+        assert!(!queue.take_overflow());
+
+        assert!(!queue.push(down(2)));
+        assert!(queue.take_overflow());
+        assert!(!queue.take_overflow());
+        assert!(queue.drain().eq([down(1), down(2)]));
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn drop_newest() {
+        let mut queue = KeyEventQueue::<2>::new(OverflowPolicy::DropNewest);
+
+        assert!(!queue.extend(&[down(0), down(1), down(2)]));
+        assert_eq!(queue.pop(), Some(down(0)));
+        assert!(queue.take_overflow());
+
+        // Wraps around the end of the buffer.
+        assert!(queue.push(down(3)));
+        assert!(queue.drain().eq([down(1), down(3)]));
+        assert_eq!(queue.pop(), None);
+    }
+}
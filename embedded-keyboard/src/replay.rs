@@ -0,0 +1,326 @@
+//! Deterministic replay of recorded matrix snapshots.
+//!
+//! Bugs in tap-hold keys and other timing-dependent behaviour are hard to
+//! reproduce by hand. [`Replay`] feeds a recording of raw matrix states,
+//! each with the time it was sampled at, through the whole pipeline:
+//! debouncing, [`Processor`]s, the keymap [`Engine`] and the keyboard
+//! report, and hands every output to the caller, who can compare them
+//! against the expected ones in a regression test.
+//!
+//! Every snapshot is a scan of the matrix, debounced by the same
+//! [`KeyDebouncer`]s as a scanner debounces its samples, so that a
+//! recording gives the key events the keyboard gave. Time only advances
+//! to the snapshots' timestamps and, in between, to the deadlines of the
+//! pipeline itself, such as the tapping term expiring, so that the same
+//! recording always produces the same outputs.
+
+use crate::debounce::{DebounceMode, KeyDebouncer};
+use crate::engine::{Engine, LayerObserver};
+use crate::hid::{BootKeyboardReport, ChangeDetector};
+use crate::matrix::MatrixState;
+use crate::processor::Processor;
+use crate::{Coordinate, KeyEvent};
+
+/// Output of a [`Replay`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ReplayOutput {
+    /// Debounced key event, at the given time
+    Event(u32, KeyEvent),
+    /// Keyboard report differing from the previous one, at the given time
+    Report(u32, BootKeyboardReport),
+}
+
+/// Pipeline replaying matrix snapshots.
+///
+/// Raw key states are debounced scan by scan: every snapshot is a sample
+/// of each key, filtered like a matrix scanner filters its samples, with
+/// the same [`DebounceMode`] and numbers of samples. Debounced events go
+/// through the processor `P`, then the engine, and the keyboard report is
+/// rebuilt from the engine's usages after every step.
+pub struct Replay<const LAYERS: usize, const ROWS: usize, const COLS: usize, P = (), O = ()> {
+    engine: Engine<LAYERS, ROWS, COLS, O>,
+    processor: P,
+    keys: [[KeyDebouncer; COLS]; ROWS],
+    debounce: i8,
+    press_debounce: i8,
+    mode: DebounceMode,
+    report: ChangeDetector<BootKeyboardReport>,
+    now: u32,
+}
+
+impl<const LAYERS: usize, const ROWS: usize, const COLS: usize, O: LayerObserver>
+    Replay<LAYERS, ROWS, COLS, (), O>
+{
+    /// Replay through `engine` alone.
+    pub fn new(engine: Engine<LAYERS, ROWS, COLS, O>) -> Self {
+        Self::with_processor(engine, ())
+    }
+}
+
+impl<const LAYERS: usize, const ROWS: usize, const COLS: usize, P, O>
+    Replay<LAYERS, ROWS, COLS, P, O>
+where
+    P: Processor,
+    O: LayerObserver,
+{
+    /// Replay through `processor`, then `engine`.
+    pub fn with_processor(engine: Engine<LAYERS, ROWS, COLS, O>, processor: P) -> Self {
+        Self {
+            engine,
+            processor,
+            keys: [[KeyDebouncer::new(); COLS]; ROWS],
+            debounce: KeyDebouncer::DEBOUNCE,
+            press_debounce: KeyDebouncer::DEBOUNCE,
+            mode: DebounceMode::Integrator,
+            report: ChangeDetector::new(BootKeyboardReport::new()),
+            now: 0,
+        }
+    }
+
+    /// Number of consistent snapshots before a key is released, and
+    /// pressed unless changed with [`Replay::set_press_debounce`].
+    pub const fn debounce(&self) -> u8 {
+        self.debounce.unsigned_abs()
+    }
+
+    /// Change the number of consistent snapshots before a key changes
+    /// state, both for presses and releases, as configured on the
+    /// recorded keyboard.
+    ///
+    /// The number is clamped to `1..=127`.
+    pub fn set_debounce(&mut self, snapshots: u8) {
+        self.debounce = i8::try_from(snapshots).unwrap_or(i8::MAX).max(1);
+        self.press_debounce = self.debounce;
+    }
+
+    /// Number of consistent snapshots before a key is pressed.
+    pub const fn press_debounce(&self) -> u8 {
+        self.press_debounce.unsigned_abs()
+    }
+
+    /// Change the number of consistent snapshots before a key is
+    /// pressed, leaving releases to [`Replay::set_debounce`].
+    ///
+    /// The number is clamped to `1..=127`.
+    pub fn set_press_debounce(&mut self, snapshots: u8) {
+        self.press_debounce = i8::try_from(snapshots).unwrap_or(i8::MAX).max(1);
+    }
+
+    /// How key samples are filtered.
+    pub const fn debounce_mode(&self) -> DebounceMode {
+        self.mode
+    }
+
+    /// Change how key samples are filtered.
+    pub fn set_debounce_mode(&mut self, mode: DebounceMode) {
+        if mode == self.mode {
+            return;
+        }
+        self.mode = mode;
+
+        for key in self.keys.iter_mut().flatten() {
+            key.restart(mode, self.debounce);
+        }
+    }
+
+    /// Engine of the pipeline, e.g. to check its layer state.
+    pub const fn engine(&self) -> &Engine<LAYERS, ROWS, COLS, O> {
+        &self.engine
+    }
+
+    /// Processor of the pipeline.
+    pub fn processor_mut(&mut self) -> &mut P {
+        &mut self.processor
+    }
+
+    /// Destroy the pipeline, returning the engine and processor.
+    pub fn destroy(self) -> (Engine<LAYERS, ROWS, COLS, O>, P) {
+        (self.engine, self.processor)
+    }
+
+    /// Replay the matrix sampled as `state` at `time`, handing every
+    /// output up to then to `output`.
+    pub fn snapshot(
+        &mut self,
+        time: u32,
+        state: &MatrixState<ROWS, COLS>,
+        mut output: impl FnMut(ReplayOutput),
+    ) {
+        self.advance_before(time, &mut output);
+        self.step(time, Some(state), &mut output);
+    }
+
+    /// Replay every snapshot of `snapshots` in turn.
+    pub fn run<'a>(
+        &mut self,
+        snapshots: impl IntoIterator<Item = &'a (u32, MatrixState<ROWS, COLS>)>,
+        mut output: impl FnMut(ReplayOutput),
+    ) {
+        for (time, state) in snapshots {
+            self.snapshot(*time, state, &mut output);
+        }
+    }
+
+    /// Let time pass until `time` without a new snapshot, e.g. after the
+    /// last one of a recording, handing every output up to then to
+    /// `output`.
+    pub fn advance(&mut self, time: u32, mut output: impl FnMut(ReplayOutput)) {
+        self.advance_before(time, &mut output);
+        self.step(time, None, &mut output);
+    }
+
+    /// Step through every deadline of the pipeline strictly between the
+    /// current time and `time`.
+    fn advance_before(&mut self, time: u32, output: &mut impl FnMut(ReplayOutput)) {
+        let until = time.wrapping_sub(self.now);
+
+        while let Some(deadline) = self.next_deadline() {
+            let after = deadline.wrapping_sub(self.now);
+            if after == 0 || after >= until {
+                break;
+            }
+
+            self.step(deadline, None, output);
+        }
+    }
+
+    /// Earliest deadline of the pipeline, if any.
+    fn next_deadline(&self) -> Option<u32> {
+        self.processor
+            .next_deadline()
+            .into_iter()
+            .chain(self.engine.next_deadline())
+            .min_by_key(|deadline| deadline.wrapping_sub(self.now))
+    }
+
+    /// Run the pipeline at `time`, debouncing `state` if sampled then.
+    fn step(
+        &mut self,
+        time: u32,
+        state: Option<&MatrixState<ROWS, COLS>>,
+        output: &mut impl FnMut(ReplayOutput),
+    ) {
+        self.now = time;
+        self.engine.tick(time);
+
+        let engine = &mut self.engine;
+        self.processor.tick(time, |event| engine.event(event));
+
+        if let Some(state) = state {
+            let (press, release, mode) = (self.press_debounce, self.debounce, self.mode);
+
+            for (row, keys) in self.keys.iter_mut().enumerate() {
+                for (col, key) in keys.iter_mut().enumerate() {
+                    let coordinate = Coordinate::new(row, col);
+                    key.debounce(state.is_pressed(coordinate), press, release, mode);
+                    if !key.changed() {
+                        continue;
+                    }
+
+                    let event = if key.is_pressed() {
+                        KeyEvent::KeyDown(coordinate)
+                    } else {
+                        KeyEvent::KeyUp(coordinate)
+                    };
+
+                    output(ReplayOutput::Event(time, event));
+                    self.processor
+                        .event(time, event, |event| engine.event(event));
+                }
+            }
+        }
+
+        let mut report = BootKeyboardReport::new();
+        report.extend(self.engine.usages());
+        if let Some(report) = self.report.update(report) {
+            output(ReplayOutput::Report(time, report));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::BounceKeys;
+    use crate::{Action, KeyCode, Keymap};
+    use std::vec::Vec;
+
+    const KEYMAP: Keymap<1, 1, 2> = crate::keymap! {
+        { [{Action::mod_tap(KeyCode::KpLeftShift, KeyCode::KA)} KC] }
+    };
+
+    fn state(keys: [bool; 2]) -> MatrixState<1, 2> {
+        let mut state = MatrixState::new();
+        state.set(Coordinate::new(0, 0), keys[0]);
+        state.set(Coordinate::new(0, 1), keys[1]);
+        state
+    }
+
+    fn report(usages: &[u16]) -> BootKeyboardReport {
+        BootKeyboardReport::from_usages(usages.iter().copied())
+    }
+
+    #[test]
+    fn bouncing_tap() {
+        let mut replay = Replay::new(Engine::new(KEYMAP));
+        let mut outputs = Vec::new();
+        // Scans every 5 ms, the key bouncing on press and release.
+        let recording = [
+            (0, state([true, false])),
+            (5, state([false, false])),
+            (10, state([true, false])),
+            (15, state([true, false])),
+            (20, state([true, false])),
+            (25, state([true, false])),
+            (60, state([false, false])),
+            (65, state([true, false])),
+            (70, state([false, false])),
+            (75, state([false, false])),
+            (80, state([false, false])),
+            (85, state([false, false])),
+        ];
+
+        replay.run(&recording, |output| outputs.push(output));
+        replay.advance(1_000, |output| outputs.push(output));
+
+        let key = Coordinate::new(0, 0);
+        assert_eq!(
+            outputs,
+            [
+                ReplayOutput::Event(20, KeyEvent::KeyDown(key)),
+                ReplayOutput::Event(80, KeyEvent::KeyUp(key)),
+                ReplayOutput::Report(80, report(&[0x04])),
+                ReplayOutput::Report(81, report(&[])),
+            ]
+        );
+    }
+
+    #[test]
+    fn hold_between_snapshots() {
+        let mut replay = Replay::with_processor(Engine::new(KEYMAP), BounceKeys::<1, 2>::new(0));
+        replay.set_debounce(1);
+        let mut outputs = Vec::new();
+        let recording = [
+            (0, state([true, false])),
+            (300, state([true, true])),
+            (400, state([false, false])),
+        ];
+
+        replay.run(&recording, |output| {
+            if let ReplayOutput::Report(..) = output {
+                outputs.push(output);
+            }
+        });
+
+        // The tapping term expired at 200, without a snapshot then.
+        assert_eq!(
+            outputs,
+            [
+                ReplayOutput::Report(200, report(&[0xe1])),
+                ReplayOutput::Report(300, report(&[0xe1, 0x06])),
+                ReplayOutput::Report(400, report(&[])),
+            ]
+        );
+    }
+}
@@ -0,0 +1,486 @@
+//! Per-key RGB lighting.
+//!
+//! [`RgbMatrix`] maps a chain of addressable LEDs to matrix coordinates
+//! and renders the active [`RgbMode`] from an [`RgbState`] of hue,
+//! saturation, value and speed. The state is kept when switching modes,
+//! and can be stored with [`RgbState::to_bytes`]. [`Action::Rgb`] keys
+//! change it through [`RgbMatrix::update`].
+//!
+//! Colors are written through [`RgbWrite`], which mirrors the
+//! `SmartLedsWrite` trait of the `smart-leds` ecosystem: a driver of that
+//! ecosystem plugs in through a wrapper forwarding `write` with each
+//! [`Rgb`] mapped to its `RGB8`.
+//!
+//! [`Reactive`] lights keys up as they are pressed, on top of the mode.
+
+mod reactive;
+
+pub use self::reactive::*;
+
+use crate::animation::{phase, triangle};
+use crate::{Action, Coordinate};
+
+/// 8-bit RGB color.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rgb {
+    /// Red
+    pub r: u8,
+    /// Green
+    pub g: u8,
+    /// Blue
+    pub b: u8,
+}
+
+impl Rgb {
+    /// All LEDs off.
+    pub const OFF: Self = Self::new(0, 0, 0);
+
+    /// Create a color.
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+}
+
+/// 8-bit HSV color, with the hue going once around the color wheel over
+/// the whole `u8` range.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Hsv {
+    /// Hue, 0 for red
+    pub hue: u8,
+    /// Saturation, 0 for white
+    pub sat: u8,
+    /// Value, 0 for off
+    pub val: u8,
+}
+
+impl Hsv {
+    /// Create a color.
+    pub const fn new(hue: u8, sat: u8, val: u8) -> Self {
+        Self { hue, sat, val }
+    }
+
+    /// Convert to RGB.
+    pub const fn to_rgb(self) -> Rgb {
+        let v = self.val as u16;
+        let s = self.sat as u16;
+
+        if s == 0 {
+            return Rgb::new(self.val, self.val, self.val);
+        }
+
+        // Six regions of 43 hues each, the last one a little shorter.
+        let region = self.hue / 43;
+        let remainder = (self.hue - region * 43) as u16 * 6;
+
+        let p = ((v * (255 - s)) >> 8) as u8;
+        let q = ((v * (255 - ((s * remainder) >> 8))) >> 8) as u8;
+        let t = ((v * (255 - ((s * (255 - remainder)) >> 8))) >> 8) as u8;
+        let v = self.val;
+
+        match region {
+            0 => Rgb::new(v, t, p),
+            1 => Rgb::new(q, v, p),
+            2 => Rgb::new(p, v, t),
+            3 => Rgb::new(p, q, v),
+            4 => Rgb::new(t, p, v),
+            _ => Rgb::new(v, p, q),
+        }
+    }
+}
+
+/// Chain of addressable LEDs, mirroring `smart_leds::SmartLedsWrite`.
+pub trait RgbWrite {
+    /// Error writing to the LEDs
+    type Error;
+
+    /// Write one color per LED, starting from the first LED of the chain.
+    fn write(&mut self, colors: impl Iterator<Item = Rgb>) -> Result<(), Self::Error>;
+}
+
+impl<T: RgbWrite> RgbWrite for &mut T {
+    type Error = T::Error;
+
+    fn write(&mut self, colors: impl Iterator<Item = Rgb>) -> Result<(), Self::Error> {
+        T::write(self, colors)
+    }
+}
+
+/// Change to the [`RgbState`], sent by [`Action::Rgb`] keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum RgbCommand {
+    /// Turn the lighting on or off
+    Toggle,
+    /// Switch to the next mode
+    ModeNext,
+    /// Switch to the previous mode
+    ModePrevious,
+    /// Increase the hue
+    HueUp,
+    /// Decrease the hue
+    HueDown,
+    /// Increase the saturation
+    SatUp,
+    /// Decrease the saturation
+    SatDown,
+    /// Increase the value
+    ValUp,
+    /// Decrease the value
+    ValDown,
+    /// Speed up animated modes
+    SpeedUp,
+    /// Slow down animated modes
+    SpeedDown,
+}
+
+impl RgbCommand {
+    /// Every command, in the order of their encoding.
+    pub const ALL: [Self; 11] = [
+        Self::Toggle,
+        Self::ModeNext,
+        Self::ModePrevious,
+        Self::HueUp,
+        Self::HueDown,
+        Self::SatUp,
+        Self::SatDown,
+        Self::ValUp,
+        Self::ValDown,
+        Self::SpeedUp,
+        Self::SpeedDown,
+    ];
+
+    /// QMK name of the command's keycode.
+    pub const fn qmk_name(self) -> &'static str {
+        match self {
+            Self::Toggle => "RGB_TOG",
+            Self::ModeNext => "RGB_MOD",
+            Self::ModePrevious => "RGB_RMOD",
+            Self::HueUp => "RGB_HUI",
+            Self::HueDown => "RGB_HUD",
+            Self::SatUp => "RGB_SAI",
+            Self::SatDown => "RGB_SAD",
+            Self::ValUp => "RGB_VAI",
+            Self::ValDown => "RGB_VAD",
+            Self::SpeedUp => "RGB_SPI",
+            Self::SpeedDown => "RGB_SPD",
+        }
+    }
+}
+
+/// Lighting mode.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum RgbMode {
+    /// Every LED in the state's color
+    #[default]
+    Solid,
+    /// Every LED in the state's color, fading in and out
+    Breathing,
+    /// Every LED in the same color, cycling through the hues
+    Cycle,
+    /// Hues spread along the LED chain and cycling
+    Swirl,
+}
+
+impl RgbMode {
+    /// Every mode, in the order [`RgbCommand::ModeNext`] goes through them.
+    pub const ALL: [Self; 4] = [Self::Solid, Self::Breathing, Self::Cycle, Self::Swirl];
+
+    /// Mode of a raw value, see [`RgbState::to_bytes`].
+    pub const fn from_raw(raw: u8) -> Option<Self> {
+        if (raw as usize) < Self::ALL.len() {
+            Some(Self::ALL[raw as usize])
+        } else {
+            None
+        }
+    }
+
+    const fn step(self, forward: bool) -> Self {
+        let len = Self::ALL.len() as u8;
+        let raw = if forward {
+            (self as u8 + 1) % len
+        } else {
+            (self as u8 + len - 1) % len
+        };
+
+        Self::ALL[raw as usize]
+    }
+}
+
+/// User-adjustable lighting state, kept across modes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RgbState {
+    /// Whether the lighting is on
+    pub on: bool,
+    /// Active mode
+    pub mode: RgbMode,
+    /// Base color of the modes
+    pub color: Hsv,
+    /// Speed of animated modes
+    pub speed: u8,
+}
+
+impl RgbState {
+    /// Default state: on, solid, and a full red at half speed.
+    pub const DEFAULT: Self = Self {
+        on: true,
+        mode: RgbMode::Solid,
+        color: Hsv::new(0, 255, 255),
+        speed: 128,
+    };
+
+    /// Hue change of one [`RgbCommand::HueUp`] or
+    /// [`RgbCommand::HueDown`].
+    pub const HUE_STEP: u8 = 8;
+
+    /// Change of one saturation, value or speed command.
+    pub const STEP: u8 = 17;
+
+    /// Length of the serialized state in bytes.
+    pub const LEN: usize = 6;
+
+    /// Apply a command. The hue wraps around, the other components stop
+    /// at their limits.
+    pub fn command(&mut self, command: RgbCommand) {
+        let color = &mut self.color;
+
+        match command {
+            RgbCommand::Toggle => self.on = !self.on,
+            RgbCommand::ModeNext => self.mode = self.mode.step(true),
+            RgbCommand::ModePrevious => self.mode = self.mode.step(false),
+            RgbCommand::HueUp => color.hue = color.hue.wrapping_add(Self::HUE_STEP),
+            RgbCommand::HueDown => color.hue = color.hue.wrapping_sub(Self::HUE_STEP),
+            RgbCommand::SatUp => color.sat = color.sat.saturating_add(Self::STEP),
+            RgbCommand::SatDown => color.sat = color.sat.saturating_sub(Self::STEP),
+            RgbCommand::ValUp => color.val = color.val.saturating_add(Self::STEP),
+            RgbCommand::ValDown => color.val = color.val.saturating_sub(Self::STEP),
+            RgbCommand::SpeedUp => self.speed = self.speed.saturating_add(Self::STEP),
+            RgbCommand::SpeedDown => self.speed = self.speed.saturating_sub(Self::STEP),
+        }
+    }
+
+    /// Serialize the state.
+    pub const fn to_bytes(&self) -> [u8; Self::LEN] {
+        [
+            self.on as u8,
+            self.mode as u8,
+            self.color.hue,
+            self.color.sat,
+            self.color.val,
+            self.speed,
+        ]
+    }
+
+    /// Deserialize a state written by [`RgbState::to_bytes`]. Unknown
+    /// modes fall back to [`RgbMode::Solid`].
+    pub const fn from_bytes(bytes: &[u8; Self::LEN]) -> Self {
+        Self {
+            on: bytes[0] != 0,
+            mode: match RgbMode::from_raw(bytes[1]) {
+                Some(mode) => mode,
+                None => RgbMode::Solid,
+            },
+            color: Hsv::new(bytes[2], bytes[3], bytes[4]),
+            speed: bytes[5],
+        }
+    }
+}
+
+impl Default for RgbState {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Chain of `N` LEDs laid over a matrix of `ROWS` by `COLS` keys.
+///
+/// Each LED is mapped to the coordinate of the key it lights, or to no
+/// key, e.g. for underglow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RgbMatrix<const ROWS: usize, const COLS: usize, const N: usize> {
+    leds: [Option<Coordinate>; N],
+    state: RgbState,
+    held: u16,
+}
+
+impl<const ROWS: usize, const COLS: usize, const N: usize> RgbMatrix<ROWS, COLS, N> {
+    /// Create a matrix from the key coordinate of each LED in chain order,
+    /// in the default state.
+    pub const fn new(leds: [Option<Coordinate>; N]) -> Self {
+        Self {
+            leds,
+            state: RgbState::DEFAULT,
+            held: 0,
+        }
+    }
+
+    /// Current state.
+    pub const fn state(&self) -> &RgbState {
+        &self.state
+    }
+
+    /// Replace the state, e.g. with one restored from storage.
+    pub fn set_state(&mut self, state: RgbState) {
+        self.state = state;
+    }
+
+    /// Index in the chain of the LED lighting `coordinate`, if any.
+    pub fn led(&self, coordinate: Coordinate) -> Option<usize> {
+        self.leds.iter().position(|led| *led == Some(coordinate))
+    }
+
+    /// Key coordinate of the LED at `index` in the chain, if any.
+    pub fn coordinate(&self, index: usize) -> Option<Coordinate> {
+        self.leds.get(index).copied().flatten()
+    }
+
+    /// Update the held actions, e.g. from
+    /// [`Engine::actions`](crate::engine::Engine::actions), applying the
+    /// RGB commands pressed since the last call, and returning whether
+    /// any was. Commands apply once per press, however long the key is
+    /// held.
+    pub fn update(&mut self, actions: impl IntoIterator<Item = Action>) -> bool {
+        let held = actions
+            .into_iter()
+            .filter_map(|action| match action {
+                Action::Rgb(command) => Some(command),
+                _ => None,
+            })
+            .fold(0u16, |held, command| held | 1 << command as u8);
+        let pressed = held & !self.held;
+        self.held = held;
+
+        for command in RgbCommand::ALL {
+            if pressed & 1 << command as u8 != 0 {
+                self.state.command(command);
+            }
+        }
+
+        pressed != 0
+    }
+
+    /// Colors of the LEDs at time `now`, in chain order.
+    pub fn colors(&self, now: u32) -> impl Iterator<Item = Rgb> + '_ {
+        let state = self.state;
+        let phase = phase(now, state.speed);
+
+        (0..N).map(move |index| {
+            if !state.on {
+                return Rgb::OFF;
+            }
+
+            let mut color = state.color;
+            match state.mode {
+                RgbMode::Solid => {}
+                RgbMode::Breathing => {
+                    color.val = (color.val as u16 * triangle(phase) as u16 / 255) as u8;
+                }
+                RgbMode::Cycle => color.hue = color.hue.wrapping_add(phase),
+                RgbMode::Swirl => {
+                    let offset = (index * 256 / N) as u8;
+                    color.hue = color.hue.wrapping_add(phase).wrapping_add(offset);
+                }
+            }
+
+            color.to_rgb()
+        })
+    }
+
+    /// Write the colors at time `now` to `leds`.
+    pub fn write<W: RgbWrite>(&self, now: u32, mut leds: W) -> Result<(), W::Error> {
+        leds.write(self.colors(now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeyCode;
+
+    struct Chain([Rgb; 3]);
+
+    impl RgbWrite for Chain {
+        type Error = ();
+
+        fn write(&mut self, colors: impl Iterator<Item = Rgb>) -> Result<(), ()> {
+            for (led, color) in self.0.iter_mut().zip(colors) {
+                *led = color;
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn hsv_to_rgb() {
+        assert_eq!(Hsv::new(0, 255, 255).to_rgb(), Rgb::new(255, 0, 0));
+        assert_eq!(Hsv::new(86, 255, 255).to_rgb(), Rgb::new(0, 255, 0));
+        assert_eq!(Hsv::new(171, 255, 255).to_rgb(), Rgb::new(0, 3, 255));
+        assert_eq!(Hsv::new(42, 0, 100).to_rgb(), Rgb::new(100, 100, 100));
+    }
+
+    #[test]
+    fn commands_and_modes() {
+        let mut rgb = RgbMatrix::<1, 2, 3>::new([
+            Some(Coordinate::new(0, 0)),
+            Some(Coordinate::new(0, 1)),
+            None,
+        ]);
+        assert_eq!(rgb.led(Coordinate::new(0, 1)), Some(1));
+        assert_eq!(rgb.coordinate(2), None);
+
+        let mut chain = Chain([Rgb::OFF; 3]);
+        rgb.write(0, &mut chain).unwrap();
+        assert_eq!(chain.0, [Rgb::new(255, 0, 0); 3]);
+
+        assert!(rgb.update([Action::Rgb(RgbCommand::ValDown)]));
+        // Held, not pressed again.
+        assert!(!rgb.update([Action::Rgb(RgbCommand::ValDown), Action::Key(KeyCode::KA)]));
+        assert!(rgb.update([Action::Rgb(RgbCommand::ModePrevious)]));
+        assert_eq!(rgb.state().mode, RgbMode::Swirl);
+        assert_eq!(rgb.state().color.val, 238);
+
+        // The color is kept across modes.
+        rgb.update([Action::Rgb(RgbCommand::ModeNext)]);
+        assert_eq!(rgb.state().mode, RgbMode::Solid);
+        rgb.write(0, &mut chain).unwrap();
+        assert_eq!(chain.0, [Rgb::new(238, 0, 0); 3]);
+
+        rgb.update([Action::Rgb(RgbCommand::Toggle)]);
+        rgb.write(0, &mut chain).unwrap();
+        assert_eq!(chain.0, [Rgb::OFF; 3]);
+
+        let state = *rgb.state();
+        assert_eq!(RgbState::from_bytes(&state.to_bytes()), state);
+    }
+
+    #[test]
+    fn animated() {
+        let mut rgb = RgbMatrix::<1, 3, 3>::new([None; 3]);
+        rgb.set_state(RgbState {
+            mode: RgbMode::Swirl,
+            color: Hsv::new(0, 0, 255),
+            ..RgbState::DEFAULT
+        });
+        // No saturation, so the hues do not show.
+        assert!(rgb.colors(1000).all(|c| c == Rgb::new(255, 255, 255)));
+
+        rgb.set_state(RgbState {
+            mode: RgbMode::Breathing,
+            ..RgbState::DEFAULT
+        });
+        assert_eq!(rgb.colors(0).next(), Some(Rgb::OFF));
+        // Half way through the cycle, at full brightness.
+        assert_eq!(rgb.colors(910).next(), Some(Rgb::new(255, 0, 0)));
+    }
+}
@@ -0,0 +1,285 @@
+use super::{Hsv, Rgb, RgbMatrix, RgbWrite};
+use crate::{Coordinate, Geometry, KeyEvent};
+
+/// Speed at which ripples spread, in hundredths of a key unit per
+/// millisecond.
+pub const RIPPLE_SPEED: u32 = 2;
+
+/// Width of a ripple's ring, in hundredths of a key unit.
+pub const RIPPLE_WIDTH: u32 = 100;
+
+/// How [`Reactive`] lights up a key press.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ReactiveEffect {
+    /// Light the pressed key, fading out
+    #[default]
+    Flash,
+    /// Light a ring spreading from the pressed key, fading out
+    Ripple,
+}
+
+/// A key press being lit up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+struct Splash {
+    origin: Coordinate,
+    age: u16,
+}
+
+/// Reactive lighting effects, drawn over the colors of an [`RgbMatrix`].
+///
+/// Key presses are fed in with [`Reactive::event`], and each lights up
+/// for the effect's duration as the application advances frames with
+/// [`Reactive::tick`], at whatever frame rate it picks. Up to `M` presses
+/// are lit at once; a press beyond that replaces the oldest one.
+///
+/// Ripples spread over the physical layout given by a [`Geometry`], so
+/// that they look round whatever the matrix wiring.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Reactive<const M: usize> {
+    effect: ReactiveEffect,
+    duration: u16,
+    color: Hsv,
+    splashes: [Option<Splash>; M],
+}
+
+impl<const M: usize> Reactive<M> {
+    /// Default duration of an effect, in milliseconds.
+    pub const DEFAULT_DURATION: u16 = 500;
+
+    /// Create reactive effects lighting presses in white.
+    pub const fn new(effect: ReactiveEffect) -> Self {
+        Self {
+            effect,
+            duration: Self::DEFAULT_DURATION,
+            color: Hsv::new(0, 0, 255),
+            splashes: [None; M],
+        }
+    }
+
+    /// Effect lighting up presses.
+    pub const fn effect(&self) -> ReactiveEffect {
+        self.effect
+    }
+
+    /// Change the effect. Presses already lit carry on with the new one.
+    pub fn set_effect(&mut self, effect: ReactiveEffect) {
+        self.effect = effect;
+    }
+
+    /// Time, in milliseconds, a press stays lit.
+    pub const fn duration(&self) -> u16 {
+        self.duration
+    }
+
+    /// Set the time a press stays lit, at least 1 millisecond.
+    pub fn set_duration(&mut self, milliseconds: u16) {
+        self.duration = milliseconds.max(1);
+    }
+
+    /// Color presses are lit in.
+    pub const fn color(&self) -> Hsv {
+        self.color
+    }
+
+    /// Set the color presses are lit in.
+    pub fn set_color(&mut self, color: Hsv) {
+        self.color = color;
+    }
+
+    /// Whether any press is still lit.
+    pub fn is_active(&self) -> bool {
+        self.splashes.iter().any(Option::is_some)
+    }
+
+    /// Light up the key pressed by `event`, if any.
+    pub fn event(&mut self, event: KeyEvent) {
+        let KeyEvent::KeyDown(origin) = event else {
+            return;
+        };
+
+        let free = self.splashes.iter().position(Option::is_none);
+        let oldest = || (0..M).max_by_key(|&index| self.splashes[index].map(|splash| splash.age));
+
+        if let Some(index) = free.or_else(oldest) {
+            self.splashes[index] = Some(Splash { origin, age: 0 });
+        }
+    }
+
+    /// Light up the keys pressed by `events`.
+    pub fn events(&mut self, events: &[KeyEvent]) {
+        for event in events {
+            self.event(*event);
+        }
+    }
+
+    /// Advance the effects by `dt` milliseconds, returning whether the
+    /// frame needs to be drawn again.
+    pub fn tick(&mut self, dt: u16) -> bool {
+        let mut changed = false;
+
+        for slot in &mut self.splashes {
+            if let Some(splash) = slot {
+                splash.age = splash.age.saturating_add(dt);
+                if splash.age >= self.duration {
+                    *slot = None;
+                }
+                changed = true;
+            }
+        }
+
+        changed
+    }
+
+    /// Intensity, from 0 to 255, the key at `coordinate` is lit with.
+    pub fn intensity<const ROWS: usize, const COLS: usize>(
+        &self,
+        coordinate: Coordinate,
+        geometry: &Geometry<ROWS, COLS>,
+    ) -> u8 {
+        let duration = u32::from(self.duration);
+
+        self.splashes
+            .iter()
+            .flatten()
+            .map(|splash| {
+                let age = u32::from(splash.age);
+                let fade = 255 * duration.saturating_sub(age) / duration;
+
+                let ring = match self.effect {
+                    ReactiveEffect::Flash if splash.origin == coordinate => 255,
+                    ReactiveEffect::Flash => 0,
+                    ReactiveEffect::Ripple => match geometry.distance(splash.origin, coordinate) {
+                        Some(distance) => {
+                            let offset = distance.abs_diff(age * RIPPLE_SPEED);
+                            255 * RIPPLE_WIDTH.saturating_sub(offset) / RIPPLE_WIDTH
+                        }
+                        None => 0,
+                    },
+                };
+
+                (ring * fade / 255) as u8
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Colors of the LEDs of `matrix` at time `now`, with the lit presses
+    /// drawn over them. Nothing is drawn while the matrix is off.
+    pub fn colors<'a, const ROWS: usize, const COLS: usize, const N: usize>(
+        &'a self,
+        matrix: &'a RgbMatrix<ROWS, COLS, N>,
+        geometry: &'a Geometry<ROWS, COLS>,
+        now: u32,
+    ) -> impl Iterator<Item = Rgb> + 'a {
+        let on = matrix.state().on;
+        let color = self.color.to_rgb();
+
+        matrix
+            .colors(now)
+            .enumerate()
+            .map(move |(index, base)| match matrix.coordinate(index) {
+                Some(coordinate) if on => blend(base, color, self.intensity(coordinate, geometry)),
+                _ => base,
+            })
+    }
+
+    /// Write the colors of [`Reactive::colors`] to `leds`.
+    pub fn write<W: RgbWrite, const ROWS: usize, const COLS: usize, const N: usize>(
+        &self,
+        matrix: &RgbMatrix<ROWS, COLS, N>,
+        geometry: &Geometry<ROWS, COLS>,
+        now: u32,
+        mut leds: W,
+    ) -> Result<(), W::Error> {
+        leds.write(self.colors(matrix, geometry, now))
+    }
+}
+
+impl<const M: usize> Default for Reactive<M> {
+    fn default() -> Self {
+        Self::new(ReactiveEffect::default())
+    }
+}
+
+/// Mix `b` into `a` by `amount` out of 255.
+fn blend(a: Rgb, b: Rgb, amount: u8) -> Rgb {
+    let mix = |a: u8, b: u8| {
+        let (a, b, amount) = (i32::from(a), i32::from(b), i32::from(amount));
+        (a + (b - a) * amount / 255) as u8
+    };
+
+    Rgb::new(mix(a.r, b.r), mix(a.g, b.g), mix(a.b, b.b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rgb::RgbState;
+
+    const GEOMETRY: Geometry<1, 4> = Geometry::grid();
+
+    fn key(col: usize) -> Coordinate {
+        Coordinate::new(0, col)
+    }
+
+    #[test]
+    fn flash() {
+        let mut reactive = Reactive::<2>::new(ReactiveEffect::Flash);
+        let mut matrix =
+            RgbMatrix::<1, 4, 4>::new([Some(key(0)), Some(key(1)), Some(key(2)), None]);
+        matrix.set_state(RgbState {
+            color: Hsv::new(0, 255, 0),
+            ..RgbState::DEFAULT
+        });
+
+        reactive.events(&[KeyEvent::KeyDown(key(1)), KeyEvent::KeyUp(key(1))]);
+        assert!(reactive.is_active());
+
+        let mut colors = [Rgb::OFF; 4];
+        for (color, rgb) in colors
+            .iter_mut()
+            .zip(reactive.colors(&matrix, &GEOMETRY, 0))
+        {
+            *color = rgb;
+        }
+        assert_eq!(colors[..2], [Rgb::OFF, Rgb::new(255, 255, 255)]);
+
+        assert!(reactive.tick(250));
+        assert_eq!(reactive.intensity(key(1), &GEOMETRY), 127);
+        assert!(reactive.tick(250));
+        assert!(!reactive.is_active());
+        assert!(!reactive.tick(10));
+
+        // The oldest press makes room for a new one.
+        reactive.event(KeyEvent::KeyDown(key(0)));
+        reactive.tick(100);
+        reactive.event(KeyEvent::KeyDown(key(1)));
+        reactive.event(KeyEvent::KeyDown(key(2)));
+        assert_eq!(reactive.intensity(key(0), &GEOMETRY), 0);
+        assert_eq!(reactive.intensity(key(2), &GEOMETRY), 255);
+    }
+
+    #[test]
+    fn ripple() {
+        let mut reactive = Reactive::<1>::new(ReactiveEffect::Ripple);
+        reactive.set_duration(1000);
+        reactive.event(KeyEvent::KeyDown(key(0)));
+
+        assert_eq!(reactive.intensity(key(0), &GEOMETRY), 255);
+        assert_eq!(reactive.intensity(key(1), &GEOMETRY), 0);
+
+        // One key away after 50 milliseconds.
+        reactive.tick(50);
+        assert_eq!(reactive.intensity(key(0), &GEOMETRY), 0);
+        assert_eq!(reactive.intensity(key(1), &GEOMETRY), 242);
+        assert_eq!(reactive.intensity(key(2), &GEOMETRY), 0);
+
+        reactive.tick(25);
+        assert_eq!(reactive.intensity(key(1), &GEOMETRY), 117);
+        assert_eq!(reactive.intensity(key(2), &GEOMETRY), 117);
+    }
+}
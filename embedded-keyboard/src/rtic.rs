@@ -0,0 +1,374 @@
+//! Helpers for RTIC applications.
+//!
+//! RTIC firmware usually scans from a software task scheduled on a
+//! monotonic timer, and sends reports from the USB interrupt, at a higher
+//! priority. [`ScanTask`] is the scan side: run once per wake-up, it
+//! scans, runs the keymap engine, and tells the task when to wake up
+//! next. Reports cross priorities through a [`ReportSlot`], split at
+//! `init` into a [`ReportWriter`] for the scan task and a
+//! [`ReportReader`] for the interrupt, so that neither has to lock a
+//! shared resource, which would block the USB interrupt while scanning:
+//!
+//! ```ignore
+//! #[init(local = [reports: ReportSlot<{ BootKeyboardReport::LEN }> = ReportSlot::new()])]
+//! fn init(cx: init::Context) -> (Shared, Local) {
+//!     let (writer, reader) = cx.local.reports.split();
+//!     scan::spawn().ok();
+//!     // ...
+//!     (Shared {}, Local { task: ScanTask::new(matrix, Engine::new(KEYMAP), 1), writer, reader, /* ... */ })
+//! }
+//!
+//! #[task(local = [task, writer], priority = 1)]
+//! async fn scan(cx: scan::Context) {
+//!     loop {
+//!         let now = Mono::now().ticks();
+//!         let next = cx.local.task.run(now, cx.local.writer).unwrap();
+//!         Mono::delay(next.wrapping_sub(now).millis()).await;
+//!     }
+//! }
+//!
+//! #[task(binds = USB, local = [usb_device, hid, reader], priority = 2)]
+//! fn usb(cx: usb::Context) {
+//!     cx.local.usb_device.poll(&mut [cx.local.hid]);
+//!     if let Some(report) = cx.local.reader.take() {
+//!         if cx.local.hid.push_raw_input(&report).is_err() {
+//!             cx.local.reader.retake();
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! Nothing here depends on RTIC itself: the same split works for any
+//! firmware passing reports from one interrupt priority to another.
+
+use core::sync::atomic::{fence, AtomicU8, AtomicUsize, Ordering};
+
+use crate::engine::{Engine, LayerObserver};
+use crate::hid::{BootKeyboardReport, ChangeDetector};
+use crate::time::earliest;
+use crate::Keyboard;
+
+/// Latest report of `N` bytes, passed lock-free from one context to
+/// another, e.g. from a scan task to a USB interrupt.
+///
+/// The slot keeps two copies of the report, and a count of the reports
+/// written, so that the reader always finds a complete copy, even when it
+/// interrupts the writer halfway through a report. Only atomic loads and
+/// stores are used, which every target with atomics supports, down to
+/// Cortex-M0.
+///
+/// The reader retries when the writer started a report into the copy it
+/// was reading, which can only happen if the writer preempts the reader,
+/// or runs on another core. It never retries when it runs at a higher
+/// priority than the writer, the usual setup.
+#[derive(Debug)]
+pub struct ReportSlot<const N: usize> {
+    copies: [[AtomicU8; N]; 2],
+    /// Twice the number of reports written, plus one while writing
+    sequence: AtomicUsize,
+    /// Sequence of the last report taken by the reader
+    taken: AtomicUsize,
+}
+
+impl<const N: usize> ReportSlot<N> {
+    /// Create a slot holding a report of zeroes, the empty report of most
+    /// report formats.
+    pub const fn new() -> Self {
+        Self {
+            copies: [
+                [const { AtomicU8::new(0) }; N],
+                [const { AtomicU8::new(0) }; N],
+            ],
+            sequence: AtomicUsize::new(0),
+            taken: AtomicUsize::new(0),
+        }
+    }
+
+    /// Split the slot into its writer and reader halves, for two
+    /// contexts to own.
+    pub fn split(&mut self) -> (ReportWriter<'_, N>, ReportReader<'_, N>) {
+        let slot: &Self = self;
+        (ReportWriter { slot }, ReportReader { slot })
+    }
+
+    /// Latest report, and the sequence it was published with.
+    fn read(&self) -> ([u8; N], usize) {
+        loop {
+            let start = self.sequence.load(Ordering::Acquire);
+
+            let mut report = [0; N];
+            for (byte, copy) in report.iter_mut().zip(self.copy(start)) {
+                *byte = copy.load(Ordering::Relaxed);
+            }
+
+            if self.unchanged(start) {
+                return (report, start & !1);
+            }
+        }
+    }
+
+    /// Copy holding the last report complete at `sequence`.
+    fn copy(&self, sequence: usize) -> &[AtomicU8; N] {
+        &self.copies[sequence / 2 % 2]
+    }
+
+    /// Whether the copy read from `start` on was left alone by the
+    /// writer.
+    fn unchanged(&self, start: usize) -> bool {
+        // The next report to go into the copy read marks the sequence
+        // before its first byte: report `start / 2 + 2` normally, but
+        // the next one already if a report was being written at `start`,
+        // into the other copy.
+        fence(Ordering::Acquire);
+        let written = self.sequence.load(Ordering::Relaxed).wrapping_sub(start);
+        written <= 2 - (start & 1)
+    }
+
+    /// Mark a report as being written, returning the sequence to pass to
+    /// [`ReportSlot::end_write`] and the copy to write it into.
+    fn begin_write(&self) -> (usize, &[AtomicU8; N]) {
+        let sequence = self.sequence.load(Ordering::Relaxed).wrapping_add(1);
+        self.sequence.store(sequence, Ordering::Relaxed);
+        fence(Ordering::Release);
+        (sequence, self.copy(sequence.wrapping_add(1)))
+    }
+
+    /// Mark the report begun with [`ReportSlot::begin_write`] complete.
+    fn end_write(&self, sequence: usize) {
+        self.sequence
+            .store(sequence.wrapping_add(1), Ordering::Release);
+    }
+}
+
+impl<const N: usize> Default for ReportSlot<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Writer half of a [`ReportSlot`].
+#[derive(Debug)]
+pub struct ReportWriter<'a, const N: usize> {
+    slot: &'a ReportSlot<N>,
+}
+
+impl<const N: usize> ReportWriter<'_, N> {
+    /// Publish `report`, replacing the previous one.
+    pub fn publish(&mut self, report: &[u8; N]) {
+        let (sequence, copy) = self.slot.begin_write();
+        for (copy, byte) in copy.iter().zip(report) {
+            copy.store(*byte, Ordering::Relaxed);
+        }
+        self.slot.end_write(sequence);
+    }
+}
+
+/// Reader half of a [`ReportSlot`].
+#[derive(Debug)]
+pub struct ReportReader<'a, const N: usize> {
+    slot: &'a ReportSlot<N>,
+}
+
+impl<const N: usize> ReportReader<'_, N> {
+    /// Latest report published.
+    pub fn latest(&self) -> [u8; N] {
+        self.slot.read().0
+    }
+
+    /// Latest report, if it was published since the last one taken.
+    pub fn take(&mut self) -> Option<[u8; N]> {
+        let (report, sequence) = self.slot.read();
+
+        // Only the reader writes `taken`, so no read-modify-write is
+        // needed.
+        let taken = self.slot.taken.load(Ordering::Relaxed);
+        self.slot.taken.store(sequence, Ordering::Relaxed);
+        (sequence != taken).then_some(report)
+    }
+
+    /// Let [`ReportReader::take`] return the latest report again, e.g.
+    /// after failing to send it.
+    pub fn retake(&mut self) {
+        self.slot.taken.store(usize::MAX, Ordering::Relaxed);
+    }
+}
+
+/// Scan side of a keyboard, run from a task scheduled on a monotonic
+/// timer.
+///
+/// Owns the keyboard and the keymap engine. Each [`ScanTask::run`] scans
+/// the keyboard once, feeds its events to the engine and publishes a
+/// [`BootKeyboardReport`] when the pressed keys change.
+pub struct ScanTask<K, O, const LAYERS: usize, const ROWS: usize, const COLS: usize> {
+    keyboard: K,
+    engine: Engine<LAYERS, ROWS, COLS, O>,
+    period: u32,
+    sent: ChangeDetector<BootKeyboardReport>,
+}
+
+impl<K, O, const LAYERS: usize, const ROWS: usize, const COLS: usize>
+    ScanTask<K, O, LAYERS, ROWS, COLS>
+where
+    K: Keyboard,
+    O: LayerObserver,
+{
+    /// Create a task scanning `keyboard` every `period` milliseconds
+    /// through `engine`. The first report is published even if nothing
+    /// is pressed.
+    pub fn new(keyboard: K, engine: Engine<LAYERS, ROWS, COLS, O>, period: u32) -> Self {
+        let mut sent = ChangeDetector::new(BootKeyboardReport::new());
+        sent.force_report();
+
+        Self {
+            keyboard,
+            engine,
+            period,
+            sent,
+        }
+    }
+
+    /// Scan at `now`, publishing the report to `reports` if it changed.
+    /// Returns when to run again: after the scan period, or earlier when
+    /// the engine has to resolve a key by then.
+    ///
+    /// # Errors
+    ///
+    /// Returns the keyboard's error if the scan failed.
+    pub fn run(
+        &mut self,
+        now: u32,
+        reports: &mut ReportWriter<'_, { BootKeyboardReport::LEN }>,
+    ) -> Result<u32, K::Error> {
+        let events = self.keyboard.scan()?;
+
+        self.engine.tick(now);
+        self.engine.events(events);
+
+        let mut report = BootKeyboardReport::new();
+        report.extend(self.engine.usages());
+        if let Some(report) = self.sent.update(report) {
+            let mut buf = [0; BootKeyboardReport::LEN];
+            report.serialize(&mut buf);
+            reports.publish(&buf);
+        }
+
+        let next = now.wrapping_add(self.period);
+        Ok(match self.engine.next_deadline() {
+            Some(deadline) => earliest(next, deadline),
+            None => next,
+        })
+    }
+
+    /// Publish the next report even if it is unchanged, e.g. on resume
+    /// from suspend.
+    pub fn force_report(&mut self) {
+        self.sent.force_report();
+    }
+
+    /// The keyboard scanned.
+    pub fn keyboard_mut(&mut self) -> &mut K {
+        &mut self.keyboard
+    }
+
+    /// The keymap engine.
+    pub fn engine_mut(&mut self) -> &mut Engine<LAYERS, ROWS, COLS, O> {
+        &mut self.engine
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake::FakeKeyboard;
+    use crate::{Action, Coordinate, KeyCode, KeyEvent, Keymap};
+
+    #[test]
+    fn reports_cross_contexts() {
+        let mut slot = ReportSlot::<4>::new();
+        let (mut writer, mut reader) = slot.split();
+
+        assert_eq!(reader.take(), None);
+        assert_eq!(reader.latest(), [0; 4]);
+
+        writer.publish(&[1; 4]);
+        writer.publish(&[2; 4]);
+        assert_eq!(reader.take(), Some([2; 4]));
+        assert_eq!(reader.take(), None);
+        assert_eq!(reader.latest(), [2; 4]);
+
+        reader.retake();
+        assert_eq!(reader.take(), Some([2; 4]));
+
+        // Reports are never torn, whatever the interleaving.
+        std::thread::scope(|scope| {
+            scope.spawn(move || {
+                for i in 0..20_000u32 {
+                    writer.publish(&[i as u8; 4]);
+                }
+            });
+            for _ in 0..20_000 {
+                let report = reader.latest();
+                assert!(report.iter().all(|b| *b == report[0]), "{report:?}");
+            }
+        });
+    }
+
+    #[test]
+    fn write_preempting_read_is_retried() {
+        let slot = ReportSlot::<2>::new();
+        let write = |bytes: &[u8]| {
+            let (sequence, copy) = slot.begin_write();
+            for (copy, byte) in copy.iter().zip(bytes) {
+                copy.store(*byte, Ordering::Relaxed);
+            }
+            sequence
+        };
+        slot.end_write(write(&[1, 1]));
+
+        // A read starting while report 2 is written reads report 1, and
+        // is torn by report 3 going into the same copy.
+        let second = write(&[2, 2]);
+        let start = slot.sequence.load(Ordering::Acquire);
+        let first = slot.copy(start)[0].load(Ordering::Relaxed);
+        slot.end_write(second);
+        let third = write(&[3, 3]);
+        let last = slot.copy(start)[1].load(Ordering::Relaxed);
+        assert_eq!([first, last], [1, 3]);
+        assert!(!slot.unchanged(start));
+        slot.end_write(third);
+        assert_eq!(slot.read(), ([3, 3], 6));
+
+        // A whole report written into the other copy leaves a read alone.
+        let start = slot.sequence.load(Ordering::Acquire);
+        slot.end_write(write(&[4, 4]));
+        assert!(slot.unchanged(start));
+        assert_eq!(slot.copy(start)[0].load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn scan_task_publishes_changes() {
+        const A: Coordinate = Coordinate::new(0, 0);
+        const B: Coordinate = Coordinate::new(0, 1);
+        const KEYMAP: Keymap<1, 1, 2> =
+            Keymap::new([[[Action::Key(KeyCode::KA), Action::layer_tap(0, KeyCode::KB)]]]);
+        const SCANS: &[&[KeyEvent]] = &[&[], &[KeyEvent::KeyDown(A)], &[], &[KeyEvent::KeyDown(B)]];
+
+        let mut slot = ReportSlot::new();
+        let (mut writer, mut reader) = slot.split();
+        let mut task = ScanTask::new(FakeKeyboard::new(SCANS), Engine::new(KEYMAP), 5);
+
+        // The empty report is published first.
+        assert_eq!(task.run(0, &mut writer), Ok(5));
+        assert_eq!(reader.take(), Some([0; 8]));
+
+        assert_eq!(task.run(5, &mut writer), Ok(10));
+        assert_eq!(reader.take(), Some([0, 0, 0x04, 0, 0, 0, 0, 0]));
+        assert_eq!(task.run(10, &mut writer), Ok(15));
+        assert_eq!(reader.take(), None);
+
+        // The pending hold-tap wakes the task up at the end of the
+        // tapping term, if before the next scan.
+        task.engine_mut().set_tapping_term(2);
+        assert_eq!(task.run(15, &mut writer), Ok(17));
+    }
+}
@@ -0,0 +1,268 @@
+//! PS/2 scan code translation.
+//!
+//! Embedded controllers emulating an 8042 keyboard controller push scan
+//! codes into its output buffer, which legacy hosts read through I/O
+//! ports `0x60` and `0x64`. With translation enabled, the usual setting,
+//! the host expects Scan Code Set 1: a make code when a key is pressed
+//! and a break code, the make code with bit 7 set, when it is released.
+//! Keys added after the original PC/XT keyboard are prefixed with `0xe0`,
+//! and Pause with `0xe1`.
+//!
+//! Translation follows Microsoft's USB HID to PS/2 scan code translation
+//! table. Print Screen and Pause are sent as if no modifier were held.
+
+use core::ops::Deref;
+
+use crate::KeyCode;
+
+/// Bytes sent for a single make or break.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ScanCodes {
+    bytes: [u8; Self::MAX_LEN],
+    len: u8,
+}
+
+impl ScanCodes {
+    /// Longest sequence of a single make or break, Pause's make code.
+    pub const MAX_LEN: usize = 6;
+
+    const fn from_slice(bytes: &[u8]) -> Self {
+        let mut codes = Self {
+            bytes: [0; Self::MAX_LEN],
+            len: bytes.len() as u8,
+        };
+
+        let mut i = 0;
+        while i < bytes.len() {
+            codes.bytes[i] = bytes[i];
+            i += 1;
+        }
+
+        codes
+    }
+
+    /// The bytes, in the order they are sent.
+    pub fn as_slice(&self) -> &[u8] {
+        &self.bytes[..usize::from(self.len)]
+    }
+}
+
+impl Deref for ScanCodes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+const EXTENDED: u8 = 0xe0;
+const BREAK: u8 = 0x80;
+
+/// Scan Code Set 1 make code of `code`, or `None` if the key has no
+/// scan code.
+pub fn set1_make(code: KeyCode) -> Option<ScanCodes> {
+    match code {
+        KeyCode::KPrintScreen => Some(ScanCodes::from_slice(&[0xe0, 0x2a, 0xe0, 0x37])),
+        KeyCode::KPause => Some(ScanCodes::from_slice(&[0xe1, 0x1d, 0x45, 0xe1, 0x9d, 0xc5])),
+        _ => match set1(code)? {
+            (true, make) => Some(ScanCodes::from_slice(&[EXTENDED, make])),
+            (false, make) => Some(ScanCodes::from_slice(&[make])),
+        },
+    }
+}
+
+/// Scan Code Set 1 break code of `code`, or `None` if the key has no
+/// scan code. Keys which send no break code, like Pause, have an empty
+/// one.
+pub fn set1_break(code: KeyCode) -> Option<ScanCodes> {
+    match code {
+        KeyCode::KPrintScreen => Some(ScanCodes::from_slice(&[0xe0, 0xb7, 0xe0, 0xaa])),
+        KeyCode::KPause | KeyCode::KLang1 | KeyCode::KLang2 => Some(ScanCodes::default()),
+        _ => match set1(code)? {
+            (true, make) => Some(ScanCodes::from_slice(&[EXTENDED, make | BREAK])),
+            (false, make) => Some(ScanCodes::from_slice(&[make | BREAK])),
+        },
+    }
+}
+
+/// Scan Code Set 1 make code of `code` without prefix, and whether it is
+/// prefixed with `0xe0`.
+const fn set1(code: KeyCode) -> Option<(bool, u8)> {
+    use KeyCode::*;
+
+    let make = match code {
+        KA => 0x1e,
+        KB => 0x30,
+        KC => 0x2e,
+        KD => 0x20,
+        KE => 0x12,
+        KF => 0x21,
+        KG => 0x22,
+        KH => 0x23,
+        KI => 0x17,
+        KJ => 0x24,
+        KK => 0x25,
+        KL => 0x26,
+        KM => 0x32,
+        KN => 0x31,
+        KO => 0x18,
+        KP => 0x19,
+        KQ => 0x10,
+        KR => 0x13,
+        KS => 0x1f,
+        KT => 0x14,
+        KU => 0x16,
+        KV => 0x2f,
+        KW => 0x11,
+        KX => 0x2d,
+        KY => 0x15,
+        KZ => 0x2c,
+        K1 => 0x02,
+        K2 => 0x03,
+        K3 => 0x04,
+        K4 => 0x05,
+        K5 => 0x06,
+        K6 => 0x07,
+        K7 => 0x08,
+        K8 => 0x09,
+        K9 => 0x0a,
+        K0 => 0x0b,
+        KEnter => 0x1c,
+        KEscape => 0x01,
+        KBackspace => 0x0e,
+        KTab => 0x0f,
+        KSpaceBar => 0x39,
+        KDash => 0x0c,
+        KEqual => 0x0d,
+        KLeftBracket => 0x1a,
+        KRightBracket => 0x1b,
+        KBackslash | KNonUSPound => 0x2b,
+        KSemiColon => 0x27,
+        KQuote => 0x28,
+        KGrave => 0x29,
+        KComma => 0x33,
+        KDot => 0x34,
+        KSlash => 0x35,
+        KCapsLock => 0x3a,
+        KF1 => 0x3b,
+        KF2 => 0x3c,
+        KF3 => 0x3d,
+        KF4 => 0x3e,
+        KF5 => 0x3f,
+        KF6 => 0x40,
+        KF7 => 0x41,
+        KF8 => 0x42,
+        KF9 => 0x43,
+        KF10 => 0x44,
+        KF11 => 0x57,
+        KF12 => 0x58,
+        KScrollLock => 0x46,
+        KpNumLock => 0x45,
+        KpAsterisk => 0x37,
+        KpMinus => 0x4a,
+        KpPlus => 0x4e,
+        Kp1 => 0x4f,
+        Kp2 => 0x50,
+        Kp3 => 0x51,
+        Kp4 => 0x4b,
+        Kp5 => 0x4c,
+        Kp6 => 0x4d,
+        Kp7 => 0x47,
+        Kp8 => 0x48,
+        Kp9 => 0x49,
+        Kp0 => 0x52,
+        KpDot => 0x53,
+        KNonUSBackslash => 0x56,
+        KpEqual => 0x59,
+        KF13 => 0x64,
+        KF14 => 0x65,
+        KF15 => 0x66,
+        KF16 => 0x67,
+        KF17 => 0x68,
+        KF18 => 0x69,
+        KF19 => 0x6a,
+        KF20 => 0x6b,
+        KF21 => 0x6c,
+        KF22 => 0x6d,
+        KF23 => 0x6e,
+        KF24 => 0x76,
+        KpComma => 0x7e,
+        KIntl1 => 0x73,
+        KIntl2 => 0x70,
+        KIntl3 => 0x7d,
+        KIntl4 => 0x79,
+        KIntl5 => 0x7b,
+        KIntl6 => 0x5c,
+        KLang1 => 0xf2,
+        KLang2 => 0xf1,
+        KLang3 => 0x78,
+        KLang4 => 0x77,
+        KLang5 => 0x76,
+        KpLeftControl => 0x1d,
+        KpLeftShift => 0x2a,
+        KpLeftAlt => 0x38,
+        KpRightShift => 0x36,
+        _ => {
+            let make = match code {
+                KInsert => 0x52,
+                KHome => 0x47,
+                KPageUp => 0x49,
+                KDelete => 0x53,
+                KEnd => 0x4f,
+                KPageDown => 0x51,
+                KRightArrow => 0x4d,
+                KLeftArrow => 0x4b,
+                KDownArrow => 0x50,
+                KUpArrow => 0x48,
+                KpSlash => 0x35,
+                KpEnter => 0x1c,
+                KApplication => 0x5d,
+                KMute => 0x20,
+                KVolumeUp => 0x30,
+                KVolumeDown => 0x2e,
+                KpLeftGUI => 0x5b,
+                KpRightControl => 0x1d,
+                KpRightAlt => 0x38,
+                KpRightGUI => 0x5c,
+                _ => return None,
+            };
+
+            return Some((true, make));
+        }
+    };
+
+    Some((false, make))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn make_and_break() {
+        assert_eq!(*set1_make(KeyCode::KA).unwrap(), [0x1e]);
+        assert_eq!(*set1_break(KeyCode::KA).unwrap(), [0x9e]);
+        assert_eq!(*set1_make(KeyCode::KpRightControl).unwrap(), [0xe0, 0x1d]);
+        assert_eq!(*set1_break(KeyCode::KUpArrow).unwrap(), [0xe0, 0xc8]);
+        assert_eq!(set1_make(KeyCode::KpHexadecimal), None);
+    }
+
+    #[test]
+    fn special_sequences() {
+        assert_eq!(
+            *set1_make(KeyCode::KPrintScreen).unwrap(),
+            [0xe0, 0x2a, 0xe0, 0x37]
+        );
+        assert_eq!(
+            *set1_break(KeyCode::KPrintScreen).unwrap(),
+            [0xe0, 0xb7, 0xe0, 0xaa]
+        );
+        assert_eq!(
+            *set1_make(KeyCode::KPause).unwrap(),
+            [0xe1, 0x1d, 0x45, 0xe1, 0x9d, 0xc5]
+        );
+        assert!(set1_break(KeyCode::KPause).unwrap().is_empty());
+        assert!(set1_break(KeyCode::KLang1).unwrap().is_empty());
+    }
+}
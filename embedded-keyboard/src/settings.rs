@@ -0,0 +1,67 @@
+use crate::engine::DEFAULT_TAPPING_TERM;
+
+/// Runtime-tunable parameters of a keyboard.
+///
+/// These are the knobs end users adjust to change how the keyboard feels,
+/// without rebuilding the firmware. They are set by the host over the
+/// configuration channel (see [`ViaHandler`](crate::via::ViaHandler)) and
+/// applied by the application to the scanner and the engine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Settings {
+    /// Number of consistent scans before a key changes state
+    pub debounce: u8,
+    /// Tapping term of hold-tap keys, in milliseconds
+    pub tapping_term: u16,
+    /// Delay before a held key starts repeating, in milliseconds
+    pub repeat_delay: u16,
+    /// Interval between repeats of a held key, in milliseconds
+    pub repeat_interval: u16,
+}
+
+impl Settings {
+    /// Default settings.
+    pub const DEFAULT: Self = Self {
+        debounce: 3,
+        tapping_term: DEFAULT_TAPPING_TERM,
+        repeat_delay: 500,
+        repeat_interval: 33,
+    };
+
+    /// Length of the serialized settings in bytes.
+    pub const LEN: usize = 7;
+
+    /// Serialize the settings, little endian.
+    pub const fn to_bytes(&self) -> [u8; Self::LEN] {
+        let tapping_term = self.tapping_term.to_le_bytes();
+        let repeat_delay = self.repeat_delay.to_le_bytes();
+        let repeat_interval = self.repeat_interval.to_le_bytes();
+
+        [
+            self.debounce,
+            tapping_term[0],
+            tapping_term[1],
+            repeat_delay[0],
+            repeat_delay[1],
+            repeat_interval[0],
+            repeat_interval[1],
+        ]
+    }
+
+    /// Deserialize settings written by [`Settings::to_bytes`].
+    pub const fn from_bytes(bytes: &[u8; Self::LEN]) -> Self {
+        Self {
+            debounce: bytes[0],
+            tapping_term: u16::from_le_bytes([bytes[1], bytes[2]]),
+            repeat_delay: u16::from_le_bytes([bytes[3], bytes[4]]),
+            repeat_interval: u16::from_le_bytes([bytes[5], bytes[6]]),
+        }
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
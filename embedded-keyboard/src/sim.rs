@@ -0,0 +1,355 @@
+//! Simulated keyboard for host-side development.
+//!
+//! [`Simulator`] implements [`Keyboard`] on a desktop, so that keymaps,
+//! tap-hold keys, macros and the rest of the pipeline can be developed and
+//! unit-tested without hardware. Key events are queued by calling its
+//! methods, from a script, or interactively from any reader, such as
+//! standard input or a TCP connection.
+//!
+//! Scripts have one command per line, each taking effect in its own scan;
+//! `#` starts a comment:
+//!
+//! | Command                  | Scans                                    |
+//! |--------------------------|------------------------------------------|
+//! | `press ROW COL ...`      | Keys pressed together                    |
+//! | `release ROW COL ...`    | Keys released together                   |
+//! | `tap ROW COL ...`        | Keys pressed together, released the next |
+//! | `idle SCANS`             | Scans without any event                  |
+//!
+//! ```
+//! use embedded_keyboard::sim::Simulator;
+//! use embedded_keyboard::{Coordinate, KeyEvent, Keyboard};
+//!
+//! let mut keyboard = Simulator::from_script("press 0 1\nidle 2\nrelease 0 1").unwrap();
+//!
+//! assert_eq!(keyboard.scan().unwrap(), [KeyEvent::KeyDown(Coordinate::new(0, 1))]);
+//! assert_eq!(keyboard.scan().unwrap(), []);
+//! ```
+
+use std::collections::{BTreeSet, VecDeque};
+use std::io::{BufRead, BufReader, Read};
+use std::string::{String, ToString};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::vec::Vec;
+
+use crate::{Coordinate, Error, ErrorKind, ErrorType, KeyEvent, Keyboard};
+
+/// Line of a script which could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptError {
+    line: usize,
+    text: String,
+}
+
+impl ScriptError {
+    /// Number of the line, from 1.
+    pub const fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Text of the line.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+impl Error for ScriptError {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
+}
+
+impl core::fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "line {}: invalid command `{}`", self.line, self.text)
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+/// Keyboard scanning scripted or interactive key events.
+///
+/// Every [`Keyboard::scan`] returns the events of the next queued scan,
+/// or none once the queue is empty. Like a real matrix, pressing a key
+/// already held, or releasing a key not held, produces no event.
+#[derive(Debug, Default)]
+pub struct Simulator {
+    scans: VecDeque<Vec<KeyEvent>>,
+    input: Option<Receiver<String>>,
+    lines: usize,
+    held: BTreeSet<Coordinate>,
+    report: Vec<KeyEvent>,
+}
+
+impl Simulator {
+    /// Create a simulator without any queued event.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a simulator running `script`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first line of `script` which could not be parsed.
+    pub fn from_script(script: &str) -> Result<Self, ScriptError> {
+        let mut simulator = Self::new();
+        simulator.script(script)?;
+        Ok(simulator)
+    }
+
+    /// Queue the scans of `script` after those already queued.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first line of `script` which could not be parsed, in
+    /// which case nothing is queued.
+    pub fn script(&mut self, script: &str) -> Result<(), ScriptError> {
+        let mut scans = Vec::new();
+
+        for (i, line) in script.lines().enumerate() {
+            scans.extend(parse(i + 1, line)?);
+        }
+
+        self.scans.extend(scans);
+        Ok(())
+    }
+
+    /// Read commands from `reader` as they come, e.g. from standard input
+    /// or a TCP connection, on a thread of their own. Commands are queued
+    /// by the next scan, which fails on a line that could not be parsed.
+    pub fn listen<R: Read + Send + 'static>(&mut self, reader: R) {
+        let (lines, input) = mpsc::channel();
+
+        std::thread::spawn(move || {
+            for line in BufReader::new(reader).lines() {
+                let Ok(line) = line else { break };
+                if lines.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        self.input = Some(input);
+        self.lines = 0;
+    }
+
+    /// Queue a scan pressing `keys`.
+    pub fn press(&mut self, keys: &[Coordinate]) {
+        self.scans
+            .push_back(keys.iter().copied().map(KeyEvent::KeyDown).collect());
+    }
+
+    /// Queue a scan releasing `keys`.
+    pub fn release(&mut self, keys: &[Coordinate]) {
+        self.scans
+            .push_back(keys.iter().copied().map(KeyEvent::KeyUp).collect());
+    }
+
+    /// Queue a scan pressing `keys`, and the next releasing them.
+    pub fn tap(&mut self, keys: &[Coordinate]) {
+        self.press(keys);
+        self.release(keys);
+    }
+
+    /// Queue `scans` scans without any event.
+    pub fn idle(&mut self, scans: usize) {
+        self.scans
+            .extend(core::iter::repeat_with(Vec::new).take(scans));
+    }
+
+    /// Number of scans queued.
+    pub fn pending(&self) -> usize {
+        self.scans.len()
+    }
+
+    /// Whether `key` is held.
+    pub fn is_pressed(&self, key: Coordinate) -> bool {
+        self.held.contains(&key)
+    }
+
+    /// Queue the commands received from the reader being listened to.
+    fn receive(&mut self) -> Result<(), ScriptError> {
+        let Some(input) = &self.input else {
+            return Ok(());
+        };
+
+        loop {
+            match input.try_recv() {
+                Ok(line) => {
+                    self.lines += 1;
+                    let scans = parse(self.lines, &line)?;
+                    self.scans.extend(scans);
+                }
+                Err(TryRecvError::Empty) => return Ok(()),
+                Err(TryRecvError::Disconnected) => {
+                    self.input = None;
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+impl ErrorType for Simulator {
+    type Error = ScriptError;
+}
+
+impl Keyboard for Simulator {
+    fn scan(&mut self) -> Result<&[KeyEvent], ScriptError> {
+        self.receive()?;
+        self.report.clear();
+
+        for event in self.scans.pop_front().unwrap_or_default() {
+            let changed = match event {
+                KeyEvent::KeyDown(c) => self.held.insert(c),
+                KeyEvent::KeyUp(c) => self.held.remove(&c),
+                KeyEvent::NoEvent => false,
+            };
+
+            if changed {
+                self.report.push(event);
+            }
+        }
+
+        Ok(&self.report)
+    }
+
+    /// Whether any key is held.
+    fn activity(&self) -> bool {
+        !self.held.is_empty()
+    }
+}
+
+/// Parse line number `number` of a script into its scans.
+fn parse(number: usize, line: &str) -> Result<Vec<Vec<KeyEvent>>, ScriptError> {
+    let error = || ScriptError {
+        line: number,
+        text: line.to_string(),
+    };
+
+    let command = line.split('#').next().unwrap_or_default();
+    let mut words = command.split_whitespace();
+    let Some(name) = words.next() else {
+        return Ok(Vec::new());
+    };
+    let args = words
+        .map(str::parse)
+        .collect::<Result<Vec<usize>, _>>()
+        .map_err(|_| error())?;
+
+    if name == "idle" {
+        let [scans] = args[..] else {
+            return Err(error());
+        };
+        return Ok(core::iter::repeat_with(Vec::new).take(scans).collect());
+    }
+
+    if args.is_empty() || args.len() % 2 != 0 {
+        return Err(error());
+    }
+    let keys = args.chunks(2).map(|key| Coordinate::new(key[0], key[1]));
+
+    match name {
+        "press" => Ok(std::vec![keys.map(KeyEvent::KeyDown).collect()]),
+        "release" => Ok(std::vec![keys.map(KeyEvent::KeyUp).collect()]),
+        "tap" => Ok(std::vec![
+            keys.clone().map(KeyEvent::KeyDown).collect(),
+            keys.map(KeyEvent::KeyUp).collect(),
+        ]),
+        _ => Err(error()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn down(row: usize, col: usize) -> KeyEvent {
+        KeyEvent::KeyDown(Coordinate::new(row, col))
+    }
+
+    fn up(row: usize, col: usize) -> KeyEvent {
+        KeyEvent::KeyUp(Coordinate::new(row, col))
+    }
+
+    #[test]
+    fn runs_script() {
+        let mut keyboard = Simulator::from_script(
+            "# chord, then a tap\n\
+             press 0 0 1 2\n\
+             \n\
+             press 0 0\n\
+             release 0 0 1 2 # both\n\
+             tap 3 4\n\
+             idle 1",
+        )
+        .unwrap();
+        assert_eq!(keyboard.pending(), 6);
+
+        assert_eq!(keyboard.scan().unwrap(), [down(0, 0), down(1, 2)]);
+        assert!(keyboard.activity());
+        assert_eq!(keyboard.scan().unwrap(), []);
+        assert_eq!(keyboard.scan().unwrap(), [up(0, 0), up(1, 2)]);
+        assert!(!keyboard.activity());
+        assert_eq!(keyboard.scan().unwrap(), [down(3, 4)]);
+        assert!(keyboard.is_pressed(Coordinate::new(3, 4)));
+        assert_eq!(keyboard.scan().unwrap(), [up(3, 4)]);
+        assert_eq!(keyboard.scan().unwrap(), []);
+        assert_eq!(keyboard.pending(), 0);
+        assert_eq!(keyboard.scan().unwrap(), []);
+    }
+
+    #[test]
+    fn rejects_invalid_lines() {
+        for line in [
+            "press 0",
+            "tap",
+            "idle",
+            "idle 1 2",
+            "hold 0 0",
+            "press a b",
+        ] {
+            let error = Simulator::from_script(&std::format!("idle 1\n{line}")).unwrap_err();
+            assert_eq!(error.line(), 2);
+            assert_eq!(error.text(), line);
+        }
+    }
+
+    #[test]
+    fn queues_from_methods() {
+        let mut keyboard = Simulator::new();
+        keyboard.tap(&[Coordinate::new(0, 1)]);
+        keyboard.idle(2);
+        keyboard.press(&[Coordinate::new(0, 2)]);
+
+        assert_eq!(keyboard.scan().unwrap(), [down(0, 1)]);
+        assert_eq!(keyboard.scan().unwrap(), [up(0, 1)]);
+        assert_eq!(keyboard.scan().unwrap(), []);
+        assert_eq!(keyboard.scan().unwrap(), []);
+        assert_eq!(keyboard.scan().unwrap(), [down(0, 2)]);
+    }
+
+    #[test]
+    fn listens_interactively() {
+        let mut keyboard = Simulator::new();
+        keyboard.listen(Cursor::new("tap 0 0\nbogus\n"));
+
+        // Wait for the reader thread to deliver both lines; the scans of
+        // the first are kept.
+        let mut events = Vec::new();
+        let error = loop {
+            match keyboard.scan() {
+                Ok(report) => events.extend_from_slice(report),
+                Err(error) => break error,
+            }
+        };
+        assert_eq!(error.line(), 2);
+
+        for _ in 0..2 {
+            events.extend_from_slice(keyboard.scan().unwrap());
+        }
+        assert_eq!(events, [down(0, 0), up(0, 0)]);
+    }
+}
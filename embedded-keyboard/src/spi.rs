@@ -0,0 +1,247 @@
+//! SPI transport to a host embedded controller.
+//!
+//! A keyboard MCU reporting to the EC as an SPI peripheral cannot choose
+//! when to talk: the EC clocks out fixed-length transfers, usually when
+//! the keyboard raises an interrupt line, and the peripheral has to have
+//! the next transfer ready in its buffer by then. [`SpiPeripheral`]
+//! prepares those transfers and [`SpiHost`] decodes them on the EC.
+//!
+//! Every transfer of `L` bytes carries a [`link`](crate::link) frame,
+//! protecting it with a sequence number and a CRC, padded with zeros.
+//! The frame's payload starts with the kind of message:
+//!
+//! | Byte | Contents                                          |
+//! |------|---------------------------------------------------|
+//! | 0    | `0x01` for key events, `0x02` for a report        |
+//! | 1..  | Key events, two bytes each, or the report's bytes |
+//!
+//! A transfer whose frame has an empty payload, such as an all-zero
+//! buffer, is idle: the peripheral had nothing to send.
+
+use crate::link::{
+    encode_event, FrameError, FrameReceiver, FrameSender, KeyEvents, EVENT_LEN, MAX_PAYLOAD,
+    OVERHEAD,
+};
+use crate::KeyEvent;
+
+const EVENTS: u8 = 0x01;
+const REPORT: u8 = 0x02;
+
+/// Errors produced while decoding a transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SpiError {
+    /// The frame in the transfer was rejected
+    Frame(FrameError),
+    /// The message is of an unknown kind
+    Unsupported(u8),
+}
+
+impl From<FrameError> for SpiError {
+    fn from(error: FrameError) -> Self {
+        Self::Frame(error)
+    }
+}
+
+/// Contents of a transfer.
+#[derive(Debug, Clone)]
+pub enum SpiMessage<'a> {
+    /// Key events
+    Events(KeyEvents<'a>),
+    /// Report, e.g. a serialized HID report
+    Report(&'a [u8]),
+}
+
+/// Peripheral side of the transport, on the keyboard MCU.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SpiPeripheral<const L: usize> {
+    sender: FrameSender,
+}
+
+impl<const L: usize> SpiPeripheral<L> {
+    /// Most key events a single transfer carries.
+    pub const MAX_EVENTS: usize = Self::MAX_MESSAGE / EVENT_LEN;
+
+    /// Longest report a single transfer carries.
+    pub const MAX_REPORT: usize = Self::MAX_MESSAGE;
+
+    const MAX_MESSAGE: usize = {
+        assert!(L > OVERHEAD + 1);
+        let payload = L - OVERHEAD;
+        (if payload < MAX_PAYLOAD {
+            payload
+        } else {
+            MAX_PAYLOAD
+        }) - 1
+    };
+
+    /// Create a peripheral starting its sequence numbers at zero.
+    pub const fn new() -> Self {
+        Self {
+            sender: FrameSender::new(),
+        }
+    }
+
+    /// Prepare a transfer carrying as many of `events` as fit, skipping
+    /// [`KeyEvent::NoEvent`] and keys [`encode_event`] cannot encode.
+    ///
+    /// Returns the number of events consumed; the rest are left for the
+    /// next transfer.
+    pub fn events(&mut self, events: &[KeyEvent], buf: &mut [u8; L]) -> usize {
+        let mut payload = [0; MAX_PAYLOAD];
+        let mut len = 1;
+        let mut consumed = 0;
+
+        payload[0] = EVENTS;
+
+        for event in events {
+            if len + EVENT_LEN > Self::MAX_MESSAGE + 1 {
+                break;
+            }
+
+            if let Some(bytes) = encode_event(*event) {
+                payload[len..len + EVENT_LEN].copy_from_slice(&bytes);
+                len += EVENT_LEN;
+            }
+            consumed += 1;
+        }
+
+        self.write(&payload[..len], buf);
+        consumed
+    }
+
+    /// Prepare a transfer carrying `report`.
+    pub fn report(&mut self, report: &[u8], buf: &mut [u8; L]) -> Result<(), FrameError> {
+        if report.len() > Self::MAX_REPORT {
+            return Err(FrameError::PayloadTooLong);
+        }
+
+        let mut payload = [0; MAX_PAYLOAD];
+        payload[0] = REPORT;
+        payload[1..=report.len()].copy_from_slice(report);
+
+        self.write(&payload[..=report.len()], buf);
+        Ok(())
+    }
+
+    /// Prepare an idle transfer.
+    pub fn idle(buf: &mut [u8; L]) {
+        buf.fill(0);
+    }
+
+    fn write(&mut self, payload: &[u8], buf: &mut [u8; L]) {
+        buf.fill(0);
+
+        // Payloads are bounded by MAX_MESSAGE, so the frame always fits.
+        let _ = self.sender.write(payload, buf);
+    }
+}
+
+/// Host side of the transport, on the EC.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SpiHost {
+    receiver: FrameReceiver,
+}
+
+impl SpiHost {
+    /// Create a host accepting any sequence number on its first transfer.
+    pub const fn new() -> Self {
+        Self {
+            receiver: FrameReceiver::new(),
+        }
+    }
+
+    /// Resynchronize with the peripheral, e.g. after it restarted.
+    pub fn reset(&mut self) {
+        self.receiver.reset();
+    }
+
+    /// Decode a transfer, returning its message, or `None` if it is idle,
+    /// along with the number of transfers lost since the last one
+    /// accepted.
+    pub fn receive<'a>(
+        &mut self,
+        transfer: &'a [u8],
+    ) -> Result<Option<(SpiMessage<'a>, u8)>, SpiError> {
+        if transfer.get(1).copied().unwrap_or(0) == 0 {
+            return Ok(None);
+        }
+
+        let frame = self.receiver.receive(transfer)?;
+        let (&kind, message) = frame
+            .payload()
+            .split_first()
+            .ok_or(SpiError::Frame(FrameError::Truncated))?;
+
+        let message = match kind {
+            EVENTS => SpiMessage::Events(KeyEvents::new(message)),
+            REPORT => SpiMessage::Report(message),
+            kind => return Err(SpiError::Unsupported(kind)),
+        };
+
+        Ok(Some((message, frame.lost())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Coordinate;
+
+    fn down(col: usize) -> KeyEvent {
+        KeyEvent::KeyDown(Coordinate::new(1, col))
+    }
+
+    #[test]
+    fn events_across_transfers() {
+        let mut peripheral = SpiPeripheral::<10>::new();
+        let mut host = SpiHost::new();
+        let mut buf = [0xaa; 10];
+        let events = [down(0), KeyEvent::NoEvent, down(1), down(2), down(3)];
+
+        assert_eq!(SpiPeripheral::<10>::MAX_EVENTS, 2);
+        assert_eq!(peripheral.events(&events, &mut buf), 3);
+        assert_eq!(buf[9], 0);
+        let Ok(Some((SpiMessage::Events(received), 0))) = host.receive(&buf) else {
+            panic!("expected events");
+        };
+        assert!(received.eq([down(0), down(1)]));
+
+        assert_eq!(peripheral.events(&events[3..], &mut buf), 2);
+        assert!(matches!(
+            host.receive(&buf),
+            Ok(Some((SpiMessage::Events(_), 0)))
+        ));
+        assert_eq!(
+            host.receive(&buf).unwrap_err(),
+            SpiError::Frame(FrameError::Replayed)
+        );
+
+        SpiPeripheral::idle(&mut buf);
+        assert!(host.receive(&buf).unwrap().is_none());
+    }
+
+    #[test]
+    fn reports_and_corruption() {
+        let mut peripheral = SpiPeripheral::<12>::new();
+        let mut host = SpiHost::new();
+        let mut buf = [0; 12];
+
+        assert_eq!(
+            peripheral.report(&[0; 8], &mut buf),
+            Err(FrameError::PayloadTooLong)
+        );
+        peripheral.report(&[1, 2, 3], &mut buf).unwrap();
+        let Ok(Some((SpiMessage::Report(report), 0))) = host.receive(&buf) else {
+            panic!("expected a report");
+        };
+        assert_eq!(report, [1, 2, 3]);
+
+        peripheral.report(&[4], &mut buf).unwrap();
+        buf[3] ^= 0x10;
+        assert_eq!(
+            host.receive(&buf).unwrap_err(),
+            SpiError::Frame(FrameError::Corrupted)
+        );
+    }
+}
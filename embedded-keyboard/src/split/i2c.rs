@@ -0,0 +1,298 @@
+//! Split keyboard link over I²C.
+//!
+//! Some split keyboards only route I²C across the cable between their
+//! halves. The secondary is then an I²C target, [`I2cSecondary`], which
+//! answers every read with a frame carrying its current matrix state, and
+//! the primary polls it, e.g. once per scan, passing every response to
+//! [`I2cPrimary::receive`].
+//!
+//! Responses are always [`I2cPrimary::RESPONSE_LEN`] bytes long, so that
+//! the primary knows how much to read without a length prefix. As every
+//! response carries the whole state, a failed poll loses nothing but
+//! latency.
+//!
+//! With the `embedded-hal` feature, `I2cTransport` polls the secondary
+//! over an `embedded-hal` I²C bus for a [`RemoteHalf`](super::RemoteHalf).
+
+use super::{state_len, write_state, Remote};
+#[cfg(feature = "embedded-hal")]
+use super::{SplitTransport, TransportError};
+use crate::hid::{I2cTarget, I2cTransaction};
+use crate::link::{FrameError, FrameReceiver, FrameSender, MAX_PAYLOAD, OVERHEAD};
+use crate::matrix::MatrixState;
+use crate::{Coordinate, KeyEvent};
+
+/// Secondary half of a split keyboard with a `ROWS` by `COLS` matrix,
+/// addressed as an I²C target.
+///
+/// The application feeds every event of its scanner to
+/// [`I2cSecondary::update`] and passes every transaction to
+/// [`I2cSecondary::serve`], or every read to [`I2cSecondary::read`] if
+/// its I²C peripheral is interrupt driven.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct I2cSecondary<const ROWS: usize, const COLS: usize> {
+    sender: FrameSender,
+    state: MatrixState<ROWS, COLS>,
+}
+
+impl<const ROWS: usize, const COLS: usize> I2cSecondary<ROWS, COLS> {
+    /// Length of a response.
+    pub const RESPONSE_LEN: usize = state_len(ROWS, COLS) + OVERHEAD;
+
+    /// Create a secondary with nothing pressed.
+    pub const fn new() -> Self {
+        const {
+            assert!(state_len(ROWS, COLS) <= MAX_PAYLOAD);
+        }
+
+        Self {
+            sender: FrameSender::new(),
+            state: MatrixState::new(),
+        }
+    }
+
+    /// Whether the key at `coordinate` is pressed.
+    pub fn is_pressed(&self, coordinate: Coordinate) -> bool {
+        self.state.is_pressed(coordinate)
+    }
+
+    /// Update the matrix state with `events`. Keys outside the matrix are
+    /// ignored.
+    pub fn update(&mut self, events: impl IntoIterator<Item = KeyEvent>) {
+        self.state.update(events);
+    }
+
+    /// Serve one transaction of the primary. Writes are ignored.
+    pub fn serve<T: I2cTarget>(&mut self, target: &mut T) -> Result<(), T::Error> {
+        match target.listen()? {
+            I2cTransaction::Write => {
+                target.receive(&mut [0; 1])?;
+                Ok(())
+            }
+            I2cTransaction::Read => {
+                let mut buf = [0; MAX_PAYLOAD + OVERHEAD];
+                let len = self.read(&mut buf);
+                target.respond(&buf[..len])
+            }
+        }
+    }
+
+    /// Write the response to a read of the primary into `buf`, returning
+    /// its length, or 0 if `buf` is shorter than
+    /// [`I2cSecondary::RESPONSE_LEN`].
+    pub fn read(&mut self, buf: &mut [u8]) -> usize {
+        let mut payload = [0; MAX_PAYLOAD];
+        let len = write_state(&self.state, &mut payload);
+
+        self.sender.write(&payload[..len], buf).unwrap_or(0)
+    }
+}
+
+impl<const ROWS: usize, const COLS: usize> Default for I2cSecondary<ROWS, COLS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Primary half of a split keyboard, polling a secondary with a `ROWS` by
+/// `COLS` matrix over I²C.
+///
+/// Keys of the secondary are placed at an offset in the primary's
+/// coordinate space, e.g. to the right of the primary's own columns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct I2cPrimary<const ROWS: usize, const COLS: usize> {
+    receiver: FrameReceiver,
+    remote: Remote<ROWS, COLS>,
+}
+
+impl<const ROWS: usize, const COLS: usize> I2cPrimary<ROWS, COLS> {
+    /// Number of bytes to read from the secondary on every poll.
+    pub const RESPONSE_LEN: usize = I2cSecondary::<ROWS, COLS>::RESPONSE_LEN;
+
+    /// Create a primary placing the secondary's key at row 0, column 0
+    /// at `offset`.
+    pub const fn new(offset: Coordinate) -> Self {
+        Self {
+            receiver: FrameReceiver::new(),
+            remote: Remote::new(offset),
+        }
+    }
+
+    /// Whether the secondary's key at `coordinate`, in the secondary's
+    /// own coordinate space, is pressed.
+    pub fn is_pressed(&self, coordinate: Coordinate) -> bool {
+        self.remote.is_pressed(coordinate)
+    }
+
+    /// Receive the response to a poll, e.g. read with
+    /// `embedded_hal::i2c::I2c::read`, passing the events it implies, in
+    /// the primary's coordinate space, to `emit`.
+    ///
+    /// A response which fails its checks changes nothing; the next poll
+    /// catches up.
+    pub fn receive(
+        &mut self,
+        response: &[u8],
+        mut emit: impl FnMut(KeyEvent),
+    ) -> Result<(), FrameError> {
+        let frame = self.receiver.receive(response)?;
+        self.remote.apply(frame.payload(), &mut emit);
+
+        Ok(())
+    }
+
+    /// Release every key of the secondary, e.g. once it stops
+    /// acknowledging its address, and resynchronize with the next
+    /// response received.
+    pub fn disconnect(&mut self, mut emit: impl FnMut(KeyEvent)) {
+        self.receiver.reset();
+        self.remote.release(&mut emit);
+    }
+}
+
+/// [`SplitTransport`] polling an [`I2cSecondary`] with a `ROWS` by `COLS`
+/// matrix over I²C.
+///
+/// Every receive polls the secondary for its matrix state. Sent messages
+/// are written to the secondary, which ignores them.
+#[cfg(feature = "embedded-hal")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct I2cTransport<I, const ROWS: usize, const COLS: usize> {
+    i2c: I,
+    address: u8,
+    receiver: FrameReceiver,
+}
+
+#[cfg(feature = "embedded-hal")]
+impl<I, const ROWS: usize, const COLS: usize> I2cTransport<I, ROWS, COLS> {
+    /// Create a transport polling the secondary at `address` on `i2c`.
+    pub const fn new(i2c: I, address: u8) -> Self {
+        Self {
+            i2c,
+            address,
+            receiver: FrameReceiver::new(),
+        }
+    }
+
+    /// Resynchronize with the next response received, e.g. after the
+    /// secondary restarted.
+    pub fn reset(&mut self) {
+        self.receiver.reset();
+    }
+
+    /// Destroys this instance and returns the I²C bus.
+    pub fn destroy(self) -> I {
+        self.i2c
+    }
+}
+
+#[cfg(feature = "embedded-hal")]
+impl<I: embedded_hal::i2c::I2c, const ROWS: usize, const COLS: usize> SplitTransport
+    for I2cTransport<I, ROWS, COLS>
+{
+    type Error = TransportError<I::Error>;
+
+    fn send(&mut self, message: &[u8]) -> Result<(), Self::Error> {
+        self.i2c
+            .write(self.address, message)
+            .map_err(TransportError::Bus)
+    }
+
+    fn receive(&mut self, buf: &mut [u8]) -> Result<Option<usize>, Self::Error> {
+        let mut response = [0; MAX_PAYLOAD + OVERHEAD];
+        let response = &mut response[..I2cSecondary::<ROWS, COLS>::RESPONSE_LEN];
+
+        self.i2c
+            .read(self.address, response)
+            .map_err(TransportError::Bus)?;
+
+        let payload = self.receiver.receive(response)?.payload();
+        buf.get_mut(..payload.len())
+            .ok_or(FrameError::BufferTooSmall)?
+            .copy_from_slice(payload);
+
+        Ok(Some(payload.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    struct Target {
+        transactions: Vec<I2cTransaction>,
+        responses: Vec<Vec<u8>>,
+    }
+
+    impl I2cTarget for Target {
+        type Error = ();
+
+        fn listen(&mut self) -> Result<I2cTransaction, ()> {
+            self.transactions.pop().ok_or(())
+        }
+
+        fn receive(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+            Ok(buf.len())
+        }
+
+        fn respond(&mut self, data: &[u8]) -> Result<(), ()> {
+            self.responses.push(data.to_vec());
+            Ok(())
+        }
+    }
+
+    fn receive(primary: &mut I2cPrimary<2, 5>, response: &[u8]) -> Vec<KeyEvent> {
+        let mut events = Vec::new();
+        primary.receive(response, |e| events.push(e)).unwrap();
+        events
+    }
+
+    #[test]
+    fn poll_state() {
+        let mut secondary = I2cSecondary::<2, 5>::new();
+        let mut primary = I2cPrimary::<2, 5>::new(Coordinate::new(0, 5));
+        let mut target = Target {
+            transactions: [I2cTransaction::Read, I2cTransaction::Write].to_vec(),
+            responses: Vec::new(),
+        };
+
+        secondary.update([
+            KeyEvent::KeyDown(Coordinate::new(1, 4)),
+            KeyEvent::KeyDown(Coordinate::new(0, 0)),
+            KeyEvent::KeyDown(Coordinate::new(2, 0)),
+        ]);
+        secondary.serve(&mut target).unwrap();
+        secondary.serve(&mut target).unwrap();
+        assert_eq!(I2cPrimary::<2, 5>::RESPONSE_LEN, 10);
+        assert_eq!(target.responses.len(), 1);
+
+        let response = &target.responses[0];
+        assert_eq!(response.len(), 10);
+        assert_eq!(&response[..8], &[0, 6, 0x02, 1, 2, 5, 0x01, 0x02]);
+        assert_eq!(
+            receive(&mut primary, response),
+            [
+                KeyEvent::KeyDown(Coordinate::new(0, 5)),
+                KeyEvent::KeyDown(Coordinate::new(1, 9)),
+            ]
+        );
+        assert_eq!(primary.receive(response, |_| {}), Err(FrameError::Replayed));
+
+        // Unchanged state, then a release.
+        let mut buf = [0; 10];
+        secondary.read(&mut buf);
+        assert!(receive(&mut primary, &buf).is_empty());
+
+        secondary.update([KeyEvent::KeyUp(Coordinate::new(0, 0))]);
+        secondary.read(&mut buf);
+        assert_eq!(
+            receive(&mut primary, &buf),
+            [KeyEvent::KeyUp(Coordinate::new(0, 5))]
+        );
+
+        let mut released = Vec::new();
+        primary.disconnect(|e| released.push(e));
+        assert_eq!(released, [KeyEvent::KeyUp(Coordinate::new(1, 9))]);
+    }
+}
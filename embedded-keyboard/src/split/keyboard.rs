@@ -0,0 +1,161 @@
+use crate::merge::{MergeError, MergedKeyboard};
+use crate::{Coordinate, Error, ErrorKind, ErrorType, KeyEvent, Keyboard};
+
+/// Error of a [`SplitKeyboard`], telling which half failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SplitError<L, R> {
+    /// Scanning the local half failed
+    Local(L),
+    /// Receiving from the remote half failed
+    Remote(R),
+}
+
+impl<L: Error, R: Error> Error for SplitError<L, R> {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Local(e) => e.kind(),
+            Self::Remote(e) => e.kind(),
+        }
+    }
+}
+
+/// Both halves of a split keyboard, merged into one [`Keyboard`].
+///
+/// The local half is the primary's own scanner, the remote half whatever
+/// receives the secondary's keys, e.g. a [`Keyboard`] driving a
+/// [`UartPrimary`](super::UartPrimary) or an
+/// [`I2cPrimary`](super::I2cPrimary). Every scan scans both halves and
+/// places their keys at their offsets like a [`MergedKeyboard`], so that
+/// the keymap and everything after it see a single matrix. The local
+/// half's events of a scan the remote half fails are returned by the next
+/// scan.
+///
+/// Events beyond `N` in a single scan are dropped, so `N` must hold the
+/// most events both halves produce at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SplitKeyboard<L, R, const N: usize> {
+    halves: MergedKeyboard<L, R, N>,
+}
+
+impl<L: Keyboard, R: Keyboard, const N: usize> SplitKeyboard<L, R, N> {
+    /// Create a split keyboard placing the local and remote halves' key at
+    /// row 0, column 0 at `local_offset` and `remote_offset` respectively.
+    pub const fn new(
+        local: L,
+        local_offset: Coordinate,
+        remote: R,
+        remote_offset: Coordinate,
+    ) -> Self {
+        Self {
+            halves: MergedKeyboard::new(local, local_offset, remote, remote_offset),
+        }
+    }
+
+    /// Local half.
+    pub fn local_mut(&mut self) -> &mut L {
+        self.halves.first_mut()
+    }
+
+    /// Remote half.
+    pub fn remote_mut(&mut self) -> &mut R {
+        self.halves.second_mut()
+    }
+
+    /// Destroys this instance and returns both halves.
+    pub fn destroy(self) -> (L, R) {
+        self.halves.destroy()
+    }
+}
+
+impl<L: Keyboard, R: Keyboard, const N: usize> ErrorType for SplitKeyboard<L, R, N> {
+    type Error = SplitError<L::Error, R::Error>;
+}
+
+impl<L: Keyboard, R: Keyboard, const N: usize> Keyboard for SplitKeyboard<L, R, N> {
+    fn scan(&mut self) -> Result<&[KeyEvent], Self::Error> {
+        self.halves.scan().map_err(|e| match e {
+            MergeError::First(e) => SplitError::Local(e),
+            MergeError::Second(e) => SplitError::Remote(e),
+        })
+    }
+
+    fn activity(&self) -> bool {
+        self.halves.activity()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake::FakeKeyboard;
+    use core::convert::Infallible;
+
+    struct Half<'a>(&'a [KeyEvent]);
+
+    impl ErrorType for Half<'_> {
+        type Error = Infallible;
+    }
+
+    impl Keyboard for Half<'_> {
+        fn scan(&mut self) -> Result<&[KeyEvent], Infallible> {
+            Ok(self.0)
+        }
+
+        fn activity(&self) -> bool {
+            !self.0.is_empty()
+        }
+    }
+
+    #[test]
+    fn merged_coordinates() {
+        let local = [KeyEvent::KeyDown(Coordinate::new(1, 2)), KeyEvent::NoEvent];
+        let remote = [
+            KeyEvent::KeyUp(Coordinate::new(0, 0)),
+            KeyEvent::KeyDown(Coordinate::new(3, 1)),
+        ];
+        let mut keyboard = SplitKeyboard::<_, _, 2>::new(
+            Half(&local),
+            Coordinate::new(0, 0),
+            Half(&remote),
+            Coordinate::new(0, 6),
+        );
+
+        assert_eq!(
+            keyboard.scan().unwrap(),
+            [
+                KeyEvent::KeyDown(Coordinate::new(1, 2)),
+                KeyEvent::KeyUp(Coordinate::new(0, 6)),
+            ]
+        );
+        assert!(keyboard.activity());
+
+        keyboard.local_mut().0 = &[];
+        assert_eq!(
+            keyboard.scan().unwrap(),
+            [
+                KeyEvent::KeyUp(Coordinate::new(0, 6)),
+                KeyEvent::KeyDown(Coordinate::new(3, 7)),
+            ]
+        );
+    }
+
+    #[test]
+    fn local_events_survive_remote_error() {
+        static UP: [KeyEvent; 1] = [KeyEvent::KeyUp(Coordinate::new(1, 2))];
+        static SCANS: [&[KeyEvent]; 1] = [&UP];
+        let local = FakeKeyboard::new(&SCANS);
+        let mut remote = FakeKeyboard::new(&[]);
+        remote.fail_at(0);
+        let mut keyboard = SplitKeyboard::<_, _, 2>::new(
+            local,
+            Coordinate::new(0, 0),
+            remote,
+            Coordinate::new(0, 6),
+        );
+
+        assert_eq!(keyboard.scan(), Err(SplitError::Remote(ErrorKind::Other)));
+        assert_eq!(keyboard.scan(), Ok(&UP[..]));
+        assert_eq!(keyboard.scan(), Ok(&[][..]));
+    }
+}
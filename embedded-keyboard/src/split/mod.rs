@@ -0,0 +1,163 @@
+//! Split keyboard links.
+//!
+//! The secondary half of a split keyboard scans its own matrix and sends
+//! the result to the primary half, which merges it into its coordinate
+//! space and runs the engine. Depending on what the cable between the
+//! halves carries, the secondary either streams its key events over a
+//! UART ([`UartSecondary`] and [`UartPrimary`]), or is an I²C target
+//! which the primary polls for its matrix state ([`I2cSecondary`] and
+//! [`I2cPrimary`]). [`SplitKeyboard`] merges the primary's own matrix
+//! and the secondary's keys into a single [`Keyboard`](crate::Keyboard),
+//! the way a [`MergedKeyboard`](crate::merge::MergedKeyboard) merges any
+//! two keyboards.
+//!
+//! Halves connected by other means, like a radio, implement
+//! [`SplitTransport`] and exchange messages through a [`SecondaryHalf`]
+//! and a [`RemoteHalf`], the latter being the remote half of a
+//! [`SplitKeyboard`].
+//!
+//! Both links carry [`link`](crate::link) frames whose payloads start
+//! with the kind of message:
+//!
+//! | Byte | Contents                                                  |
+//! |------|-----------------------------------------------------------|
+//! | 0    | `0x01` for key events, `0x02` for the matrix state        |
+//! | 1..  | Key events, two bytes each, or an encoded [`MatrixState`] |
+
+mod i2c;
+mod keyboard;
+mod transport;
+mod uart;
+
+pub use self::i2c::*;
+pub use self::keyboard::*;
+pub use self::transport::*;
+pub use self::uart::*;
+
+use crate::link::{encode_event, KeyEvents, EVENT_LEN, MAX_PAYLOAD};
+use crate::matrix::{encoded_len, MatrixState};
+use crate::merge::place;
+use crate::{Coordinate, KeyEvent};
+
+const EVENTS: u8 = 0x01;
+const STATE: u8 = 0x02;
+
+/// Length of a matrix state payload.
+const fn state_len(rows: usize, cols: usize) -> usize {
+    1 + encoded_len(rows, cols)
+}
+
+/// Write a key events payload for as many of `events` as fit into
+/// `payload`, returning its length and the number of events consumed.
+/// [`KeyEvent::NoEvent`] is skipped.
+fn write_events(events: &[KeyEvent], payload: &mut [u8; MAX_PAYLOAD]) -> (usize, usize) {
+    let mut len = 1;
+    let mut consumed = 0;
+
+    payload[0] = EVENTS;
+
+    for event in events {
+        if let Some(bytes) = encode_event(*event) {
+            let Some(slot) = payload.get_mut(len..len + EVENT_LEN) else {
+                break;
+            };
+            slot.copy_from_slice(&bytes);
+            len += EVENT_LEN;
+        }
+        consumed += 1;
+    }
+
+    (len, consumed)
+}
+
+/// Write a matrix state payload for `state` into `payload`, returning its
+/// length.
+fn write_state<const ROWS: usize, const COLS: usize>(
+    state: &MatrixState<ROWS, COLS>,
+    payload: &mut [u8; MAX_PAYLOAD],
+) -> usize {
+    payload[0] = STATE;
+
+    // Cannot fail, as the halves assert that the state fits a payload.
+    1 + state.encode(&mut payload[1..]).unwrap_or(0)
+}
+
+/// Keys of the secondary, as known to the primary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Remote<const ROWS: usize, const COLS: usize> {
+    pressed: [[bool; COLS]; ROWS],
+    offset: Coordinate,
+}
+
+impl<const ROWS: usize, const COLS: usize> Remote<ROWS, COLS> {
+    const fn new(offset: Coordinate) -> Self {
+        const {
+            assert!(state_len(ROWS, COLS) <= MAX_PAYLOAD);
+        }
+
+        Self {
+            pressed: [[false; COLS]; ROWS],
+            offset,
+        }
+    }
+
+    fn is_pressed(&self, coordinate: Coordinate) -> bool {
+        self.pressed
+            .get(coordinate.row())
+            .and_then(|row| row.get(coordinate.col()))
+            .is_some_and(|pressed| *pressed)
+    }
+
+    /// Apply a message, passing the resulting events to `emit`.
+    fn apply(&mut self, payload: &[u8], emit: &mut impl FnMut(KeyEvent)) {
+        match payload.split_first() {
+            Some((&EVENTS, events)) => {
+                for event in KeyEvents::new(events) {
+                    match event {
+                        KeyEvent::KeyDown(c) => self.set(c.row(), c.col(), true, emit),
+                        KeyEvent::KeyUp(c) => self.set(c.row(), c.col(), false, emit),
+                        KeyEvent::NoEvent => {}
+                    }
+                }
+            }
+            Some((&STATE, encoded)) => {
+                if let Ok(state) = MatrixState::<ROWS, COLS>::decode(encoded) {
+                    for row in 0..ROWS {
+                        for col in 0..COLS {
+                            let down = state.is_pressed(Coordinate::new(row, col));
+                            self.set(row, col, down, emit);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Release every key.
+    fn release(&mut self, emit: &mut impl FnMut(KeyEvent)) {
+        for row in 0..ROWS {
+            for col in 0..COLS {
+                self.set(row, col, false, emit);
+            }
+        }
+    }
+
+    fn set(&mut self, row: usize, col: usize, down: bool, emit: &mut impl FnMut(KeyEvent)) {
+        let Some(key) = self.pressed.get_mut(row).and_then(|keys| keys.get_mut(col)) else {
+            return;
+        };
+
+        if *key != down {
+            *key = down;
+            let Some(coordinate) = place(Coordinate::new(row, col), self.offset) else {
+                return;
+            };
+            emit(if down {
+                KeyEvent::KeyDown(coordinate)
+            } else {
+                KeyEvent::KeyUp(coordinate)
+            });
+        }
+    }
+}
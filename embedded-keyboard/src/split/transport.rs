@@ -0,0 +1,260 @@
+use super::{write_events, write_state, Remote};
+use crate::link::{FrameError, MAX_PAYLOAD};
+use crate::matrix::MatrixState;
+use crate::{Coordinate, Error, ErrorKind, ErrorType, KeyEvent, Keyboard};
+
+/// Link between the halves of a split keyboard, carrying the messages
+/// described in the [module documentation](super).
+///
+/// Implemented on top of whatever connects the halves: a UART, I²C, or a
+/// radio like ESB or BLE, which frames and checks messages on its own.
+/// Receiving never blocks, so that a transport can be polled from a scan
+/// loop or an async task alike.
+pub trait SplitTransport {
+    /// Error type
+    type Error: Error;
+
+    /// Send `message` to the other half.
+    fn send(&mut self, message: &[u8]) -> Result<(), Self::Error>;
+
+    /// Receive the next message of the other half into `buf`, returning
+    /// its length, or `None` if no message is pending.
+    fn receive(&mut self, buf: &mut [u8]) -> Result<Option<usize>, Self::Error>;
+}
+
+impl<T: SplitTransport + ?Sized> SplitTransport for &mut T {
+    type Error = T::Error;
+
+    #[inline]
+    fn send(&mut self, message: &[u8]) -> Result<(), Self::Error> {
+        T::send(self, message)
+    }
+
+    #[inline]
+    fn receive(&mut self, buf: &mut [u8]) -> Result<Option<usize>, Self::Error> {
+        T::receive(self, buf)
+    }
+}
+
+/// Error of the [`SplitTransport`]s shipped with this crate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TransportError<E> {
+    /// The peripheral failed
+    Bus(E),
+    /// A message could not be framed, or a frame failed its checks
+    Frame(FrameError),
+}
+
+impl<E> From<FrameError> for TransportError<E> {
+    fn from(e: FrameError) -> Self {
+        Self::Frame(e)
+    }
+}
+
+impl<E: core::fmt::Debug> Error for TransportError<E> {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
+}
+
+/// Secondary half of a split keyboard with a `ROWS` by `COLS` matrix,
+/// sending over a [`SplitTransport`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SecondaryHalf<T, const ROWS: usize, const COLS: usize> {
+    transport: T,
+}
+
+impl<T: SplitTransport, const ROWS: usize, const COLS: usize> SecondaryHalf<T, ROWS, COLS> {
+    /// Create a secondary sending over `transport`.
+    pub const fn new(transport: T) -> Self {
+        const {
+            assert!(super::state_len(ROWS, COLS) <= MAX_PAYLOAD);
+        }
+
+        Self { transport }
+    }
+
+    /// Transport.
+    pub fn transport_mut(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    /// Destroys this instance and returns the transport.
+    pub fn destroy(self) -> T {
+        self.transport
+    }
+
+    /// Send `events`, in as many messages as needed.
+    /// [`KeyEvent::NoEvent`] is skipped.
+    pub fn send_events(&mut self, mut events: &[KeyEvent]) -> Result<(), T::Error> {
+        let mut payload = [0; MAX_PAYLOAD];
+
+        while !events.is_empty() {
+            let (len, consumed) = write_events(events, &mut payload);
+            if len > 1 {
+                self.transport.send(&payload[..len])?;
+            }
+            events = &events[consumed..];
+        }
+
+        Ok(())
+    }
+
+    /// Send the whole matrix `state`, e.g. periodically, to repair lost
+    /// events.
+    pub fn send_state(&mut self, state: &MatrixState<ROWS, COLS>) -> Result<(), T::Error> {
+        let mut payload = [0; MAX_PAYLOAD];
+        let len = write_state(state, &mut payload);
+
+        self.transport.send(&payload[..len])
+    }
+}
+
+/// Keys of a secondary with a `ROWS` by `COLS` matrix, received over a
+/// [`SplitTransport`], as a [`Keyboard`].
+///
+/// Meant as the remote half of a [`SplitKeyboard`](super::SplitKeyboard),
+/// which places the keys in the primary's coordinate space. Every scan
+/// receives at most one message, so that a transport polling the
+/// secondary, like I²C, polls it once per scan. `N` must hold the most
+/// events a single message implies: the events sent at once, or a change
+/// of every key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RemoteHalf<T, const ROWS: usize, const COLS: usize, const N: usize> {
+    transport: T,
+    remote: Remote<ROWS, COLS>,
+    events: [KeyEvent; N],
+    len: usize,
+}
+
+impl<T: SplitTransport, const ROWS: usize, const COLS: usize, const N: usize>
+    RemoteHalf<T, ROWS, COLS, N>
+{
+    /// Create a remote half receiving from `transport`, with nothing
+    /// pressed.
+    pub const fn new(transport: T) -> Self {
+        Self {
+            transport,
+            remote: Remote::new(Coordinate::new(0, 0)),
+            events: [KeyEvent::NoEvent; N],
+            len: 0,
+        }
+    }
+
+    /// Transport.
+    pub fn transport_mut(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    /// Destroys this instance and returns the transport.
+    pub fn destroy(self) -> T {
+        self.transport
+    }
+
+    /// Whether the secondary's key at `coordinate` is pressed.
+    pub fn is_pressed(&self, coordinate: Coordinate) -> bool {
+        self.remote.is_pressed(coordinate)
+    }
+
+    /// Release every key of the secondary, e.g. once the link is lost,
+    /// passing the events to `emit`.
+    pub fn disconnect(&mut self, mut emit: impl FnMut(KeyEvent)) {
+        self.remote.release(&mut emit);
+    }
+}
+
+impl<T: SplitTransport, const ROWS: usize, const COLS: usize, const N: usize> ErrorType
+    for RemoteHalf<T, ROWS, COLS, N>
+{
+    type Error = T::Error;
+}
+
+impl<T: SplitTransport, const ROWS: usize, const COLS: usize, const N: usize> Keyboard
+    for RemoteHalf<T, ROWS, COLS, N>
+{
+    fn scan(&mut self) -> Result<&[KeyEvent], Self::Error> {
+        let mut buf = [0; MAX_PAYLOAD];
+        self.len = 0;
+
+        if let Some(len) = self.transport.receive(&mut buf)? {
+            let Self {
+                remote,
+                events,
+                len: count,
+                ..
+            } = self;
+            remote.apply(&buf[..len], &mut |event| {
+                if let Some(slot) = events.get_mut(*count) {
+                    *slot = event;
+                    *count += 1;
+                }
+            });
+        }
+
+        Ok(&self.events[..self.len])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::convert::Infallible;
+    use std::collections::VecDeque;
+    use std::vec::Vec;
+
+    #[derive(Default)]
+    struct Loopback(VecDeque<Vec<u8>>);
+
+    impl SplitTransport for Loopback {
+        type Error = Infallible;
+
+        fn send(&mut self, message: &[u8]) -> Result<(), Infallible> {
+            self.0.push_back(message.to_vec());
+            Ok(())
+        }
+
+        fn receive(&mut self, buf: &mut [u8]) -> Result<Option<usize>, Infallible> {
+            Ok(self.0.pop_front().map(|message| {
+                buf[..message.len()].copy_from_slice(&message);
+                message.len()
+            }))
+        }
+    }
+
+    #[test]
+    fn events_and_state() {
+        let mut link = Loopback::default();
+        let mut secondary = SecondaryHalf::<_, 2, 3>::new(&mut link);
+        let mut state = MatrixState::new();
+        let events = [
+            KeyEvent::KeyDown(Coordinate::new(0, 1)),
+            KeyEvent::NoEvent,
+            KeyEvent::KeyDown(Coordinate::new(1, 2)),
+        ];
+
+        secondary.send_events(&events).unwrap();
+        state.update(events);
+        state.set(Coordinate::new(0, 1), false);
+        secondary.send_state(&state).unwrap();
+        secondary.send_state(&state).unwrap();
+        assert_eq!(link.0.len(), 3);
+
+        let mut remote = RemoteHalf::<_, 2, 3, 6>::new(link);
+        assert_eq!(
+            remote.scan().unwrap(),
+            [
+                KeyEvent::KeyDown(Coordinate::new(0, 1)),
+                KeyEvent::KeyDown(Coordinate::new(1, 2)),
+            ]
+        );
+        assert_eq!(
+            remote.scan().unwrap(),
+            [KeyEvent::KeyUp(Coordinate::new(0, 1))]
+        );
+        // The repeated state changes nothing.
+        assert_eq!(remote.scan().unwrap(), []);
+        assert!(remote.transport_mut().0.is_empty());
+        assert!(remote.is_pressed(Coordinate::new(1, 2)));
+    }
+}
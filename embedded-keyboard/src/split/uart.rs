@@ -0,0 +1,406 @@
+//! Split keyboard link over a UART.
+//!
+//! [`UartSecondary`] serializes the secondary's key events, and from
+//! time to time its whole debounced matrix state, into frames;
+//! [`UartPrimary`] decodes the byte stream on the primary.
+//!
+//! On the wire every frame is preceded by [`START`]. A UART has no notion
+//! of frames, so after a glitched or lost byte the primary looks for the
+//! next start byte whose frame passes its checksum, rather than wedging
+//! on a misaligned stream. Events lost along the way are repaired by the
+//! next matrix state, so the secondary should send it periodically.
+//!
+//! Both ends are transport-agnostic: the application writes the bytes
+//! built by the secondary to its UART, e.g. with
+//! `embedded_io::Write::write_all`, and passes whatever the primary's
+//! UART reads to [`UartPrimary::receive`]. With the `embedded-hal-nb`
+//! feature, `UartTransport` instead carries the messages of a
+//! [`SecondaryHalf`](super::SecondaryHalf) and a
+//! [`RemoteHalf`](super::RemoteHalf) over an `embedded-hal-nb` UART.
+
+use super::{write_events, write_state, Remote};
+#[cfg(feature = "embedded-hal-nb")]
+use super::{SplitTransport, TransportError};
+use crate::link::{FrameError, FrameReceiver, FrameSender, EVENT_LEN, MAX_PAYLOAD, OVERHEAD};
+use crate::matrix::MatrixState;
+use crate::{Coordinate, KeyEvent};
+
+/// Byte preceding every frame.
+pub const START: u8 = 0x7e;
+
+/// Secondary half of a split keyboard with a `ROWS` by `COLS` matrix.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct UartSecondary<const ROWS: usize, const COLS: usize> {
+    sender: FrameSender,
+}
+
+impl<const ROWS: usize, const COLS: usize> UartSecondary<ROWS, COLS> {
+    /// Longest message on the wire.
+    pub const MAX_LEN: usize = 1 + OVERHEAD + MAX_PAYLOAD;
+
+    /// Most key events a single message carries.
+    pub const MAX_EVENTS: usize = (MAX_PAYLOAD - 1) / EVENT_LEN;
+
+    /// Create a secondary starting its counter at zero.
+    pub const fn new() -> Self {
+        const {
+            assert!(super::state_len(ROWS, COLS) <= MAX_PAYLOAD);
+        }
+
+        Self {
+            sender: FrameSender::new(),
+        }
+    }
+
+    /// Write a message carrying `events` into `buf`, returning its
+    /// length. [`KeyEvent::NoEvent`] is skipped.
+    pub fn events(&mut self, events: &[KeyEvent], buf: &mut [u8]) -> Result<usize, FrameError> {
+        let mut payload = [0; MAX_PAYLOAD];
+        let (len, consumed) = write_events(events, &mut payload);
+
+        if consumed < events.len() {
+            return Err(FrameError::PayloadTooLong);
+        }
+
+        self.write(&payload[..len], buf)
+    }
+
+    /// Write a message carrying the matrix state into `buf`, returning
+    /// its length. Keys outside the matrix are ignored.
+    pub fn state(
+        &mut self,
+        pressed: impl IntoIterator<Item = Coordinate>,
+        buf: &mut [u8],
+    ) -> Result<usize, FrameError> {
+        let mut state = MatrixState::<ROWS, COLS>::new();
+        for coordinate in pressed {
+            state.set(coordinate, true);
+        }
+
+        let mut payload = [0; MAX_PAYLOAD];
+        let len = write_state(&state, &mut payload);
+
+        self.write(&payload[..len], buf)
+    }
+
+    fn write(&mut self, payload: &[u8], buf: &mut [u8]) -> Result<usize, FrameError> {
+        write_frame(&mut self.sender, payload, buf)
+    }
+}
+
+/// Primary half of a split keyboard, receiving from a secondary with a
+/// `ROWS` by `COLS` matrix, through a receive buffer of `N` bytes.
+///
+/// Keys of the secondary are placed at an offset in the primary's
+/// coordinate space, e.g. to the right of the primary's own columns.
+/// Frames longer than the receive buffer are dropped, so `N` must hold
+/// the longest message the secondary sends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UartPrimary<const ROWS: usize, const COLS: usize, const N: usize> {
+    decoder: Decoder<N>,
+    remote: Remote<ROWS, COLS>,
+}
+
+impl<const ROWS: usize, const COLS: usize, const N: usize> UartPrimary<ROWS, COLS, N> {
+    /// Create a primary placing the secondary's key at row 0, column 0
+    /// at `offset`.
+    pub const fn new(offset: Coordinate) -> Self {
+        Self {
+            decoder: Decoder::new(),
+            remote: Remote::new(offset),
+        }
+    }
+
+    /// Number of frames dropped as corrupted or replayed, and bytes
+    /// skipped to find the next frame.
+    pub const fn errors(&self) -> u32 {
+        self.decoder.errors
+    }
+
+    /// Whether the secondary's key at `coordinate`, in the secondary's
+    /// own coordinate space, is pressed.
+    pub fn is_pressed(&self, coordinate: Coordinate) -> bool {
+        self.remote.is_pressed(coordinate)
+    }
+
+    /// Receive bytes from the secondary, passing the events they carry,
+    /// in the primary's coordinate space, to `emit`.
+    pub fn receive(&mut self, bytes: &[u8], mut emit: impl FnMut(KeyEvent)) {
+        for byte in bytes {
+            self.decoder.push(*byte);
+
+            while let Some(payload) = self.decoder.payload() {
+                self.remote.apply(payload, &mut emit);
+            }
+        }
+    }
+
+    /// Release every key of the secondary, e.g. once the link is lost,
+    /// and resynchronize with the next frame received.
+    pub fn disconnect(&mut self, mut emit: impl FnMut(KeyEvent)) {
+        self.decoder.reset();
+        self.remote.release(&mut emit);
+    }
+}
+
+/// [`SplitTransport`] over a UART, carrying messages in the same frames
+/// as [`UartSecondary`], through a receive buffer of `N` bytes.
+///
+/// Sending blocks until the whole frame is written; receiving reads
+/// whatever bytes the UART has, without blocking.
+#[cfg(feature = "embedded-hal-nb")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UartTransport<S, const N: usize> {
+    serial: S,
+    sender: FrameSender,
+    decoder: Decoder<N>,
+}
+
+#[cfg(feature = "embedded-hal-nb")]
+impl<S, const N: usize> UartTransport<S, N> {
+    /// Create a transport over `serial`.
+    pub const fn new(serial: S) -> Self {
+        Self {
+            serial,
+            sender: FrameSender::new(),
+            decoder: Decoder::new(),
+        }
+    }
+
+    /// Number of frames dropped as corrupted or replayed, and bytes
+    /// skipped to find the next frame.
+    pub const fn errors(&self) -> u32 {
+        self.decoder.errors
+    }
+
+    /// Resynchronize with the next frame received, e.g. after the other
+    /// half restarted.
+    pub fn reset(&mut self) {
+        self.decoder.reset();
+    }
+
+    /// Destroys this instance and returns the UART.
+    pub fn destroy(self) -> S {
+        self.serial
+    }
+}
+
+#[cfg(feature = "embedded-hal-nb")]
+impl<S, const N: usize> SplitTransport for UartTransport<S, N>
+where
+    S: embedded_hal_nb::serial::Read + embedded_hal_nb::serial::Write,
+{
+    type Error = TransportError<S::Error>;
+
+    fn send(&mut self, message: &[u8]) -> Result<(), Self::Error> {
+        use embedded_hal_nb::nb::block;
+
+        let mut frame = [0; 1 + OVERHEAD + MAX_PAYLOAD];
+        let len = write_frame(&mut self.sender, message, &mut frame)?;
+
+        for byte in &frame[..len] {
+            block!(self.serial.write(*byte)).map_err(TransportError::Bus)?;
+        }
+
+        block!(self.serial.flush()).map_err(TransportError::Bus)
+    }
+
+    fn receive(&mut self, buf: &mut [u8]) -> Result<Option<usize>, Self::Error> {
+        use embedded_hal_nb::nb;
+
+        loop {
+            if let Some(payload) = self.decoder.payload() {
+                buf.get_mut(..payload.len())
+                    .ok_or(FrameError::BufferTooSmall)?
+                    .copy_from_slice(payload);
+                return Ok(Some(payload.len()));
+            }
+
+            match self.serial.read() {
+                Ok(byte) => self.decoder.push(byte),
+                Err(nb::Error::WouldBlock) => return Ok(None),
+                Err(nb::Error::Other(e)) => return Err(TransportError::Bus(e)),
+            }
+        }
+    }
+}
+
+/// Write a frame carrying `payload`, preceded by [`START`], into `buf`,
+/// returning its length.
+fn write_frame(
+    sender: &mut FrameSender,
+    payload: &[u8],
+    buf: &mut [u8],
+) -> Result<usize, FrameError> {
+    let (start, frame) = buf.split_first_mut().ok_or(FrameError::BufferTooSmall)?;
+
+    let len = sender.write(payload, frame)?;
+    *start = START;
+
+    Ok(1 + len)
+}
+
+/// Decoder of the byte stream sent by a [`UartSecondary`], buffering up
+/// to `N` bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Decoder<const N: usize> {
+    receiver: FrameReceiver,
+    buf: [u8; N],
+    len: usize,
+    consumed: usize,
+    errors: u32,
+}
+
+impl<const N: usize> Decoder<N> {
+    const fn new() -> Self {
+        Self {
+            receiver: FrameReceiver::new(),
+            buf: [0; N],
+            len: 0,
+            consumed: 0,
+            errors: 0,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.len = 0;
+        self.consumed = 0;
+        self.receiver.reset();
+    }
+
+    fn push(&mut self, byte: u8) {
+        self.skip_consumed();
+
+        if self.len == N {
+            self.drop_bytes(1);
+        }
+
+        self.buf[self.len] = byte;
+        self.len += 1;
+    }
+
+    /// Payload of the next frame in the buffer, if complete and valid.
+    fn payload(&mut self) -> Option<&[u8]> {
+        self.skip_consumed();
+
+        loop {
+            let start = self.buf[..self.len]
+                .iter()
+                .position(|b| *b == START)
+                .unwrap_or(self.len);
+            if start > 0 {
+                self.drop_bytes(start);
+            }
+
+            let &len = self.buf[..self.len].get(2)?;
+            let frame_len = 1 + usize::from(len) + OVERHEAD;
+
+            if frame_len > N {
+                self.drop_bytes(1);
+                continue;
+            }
+            if self.len < frame_len {
+                return None;
+            }
+
+            match self.receiver.receive(&self.buf[1..frame_len]) {
+                Ok(_) => {
+                    self.consumed = frame_len;
+                    return Some(&self.buf[3..frame_len - 2]);
+                }
+                Err(FrameError::Replayed) => self.drop_bytes(frame_len),
+                // Not a frame after all; look for the next start byte.
+                Err(_) => self.drop_bytes(1),
+            }
+        }
+    }
+
+    /// Skip the frame last returned by [`Decoder::payload`].
+    fn skip_consumed(&mut self) {
+        let consumed = core::mem::take(&mut self.consumed);
+        self.skip(consumed);
+    }
+
+    /// Drop bytes which do not make up a valid frame.
+    fn drop_bytes(&mut self, bytes: usize) {
+        self.errors = self.errors.saturating_add(1);
+        self.skip(bytes);
+    }
+
+    fn skip(&mut self, bytes: usize) {
+        self.buf.copy_within(bytes..self.len, 0);
+        self.len -= bytes;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    fn receive<const N: usize>(primary: &mut UartPrimary<2, 3, N>, bytes: &[u8]) -> Vec<KeyEvent> {
+        let mut events = Vec::new();
+        primary.receive(bytes, |e| events.push(e));
+        events
+    }
+
+    fn down(row: usize, col: usize) -> KeyEvent {
+        KeyEvent::KeyDown(Coordinate::new(row, col))
+    }
+
+    fn up(row: usize, col: usize) -> KeyEvent {
+        KeyEvent::KeyUp(Coordinate::new(row, col))
+    }
+
+    #[test]
+    fn events_in_primary_coordinates() {
+        let mut secondary = UartSecondary::<2, 3>::new();
+        let mut primary = UartPrimary::<2, 3, 32>::new(Coordinate::new(0, 4));
+        let mut buf = [0; 32];
+
+        let len = secondary
+            .events(&[down(1, 2), KeyEvent::NoEvent, down(0, 0)], &mut buf)
+            .unwrap();
+        assert_eq!(len, 10);
+
+        // Byte by byte, as a UART delivers them.
+        let events: Vec<_> = buf[..len]
+            .iter()
+            .flat_map(|byte| receive(&mut primary, &[*byte]))
+            .collect();
+        assert_eq!(events, [down(1, 6), down(0, 4)]);
+        assert!(primary.is_pressed(Coordinate::new(1, 2)));
+
+        let mut released = Vec::new();
+        primary.disconnect(|e| released.push(e));
+        assert_eq!(released, [up(0, 4), up(1, 6)]);
+        assert_eq!(primary.errors(), 0);
+    }
+
+    #[test]
+    fn resync_after_glitch() {
+        let mut secondary = UartSecondary::<2, 3>::new();
+        let mut primary = UartPrimary::<2, 3, 16>::new(Coordinate::new(0, 0));
+        let mut stream = Vec::new();
+        let mut buf = [0; 16];
+
+        // Line noise, then a frame with a corrupted byte.
+        stream.extend([0x00, START, 0x42]);
+        let len = secondary.events(&[down(0, 1)], &mut buf).unwrap();
+        buf[4] ^= 0x01;
+        stream.extend(&buf[..len]);
+
+        // The matrix state repairs the lost event.
+        let len = secondary
+            .state([Coordinate::new(0, 1), Coordinate::new(1, 0)], &mut buf)
+            .unwrap();
+        assert_eq!(buf[7], 0x0a);
+        stream.extend(&buf[..len]);
+
+        assert_eq!(receive(&mut primary, &stream), [down(0, 1), down(1, 0)]);
+        assert!(primary.errors() > 0);
+
+        let len = secondary.state([Coordinate::new(1, 0)], &mut buf).unwrap();
+        assert_eq!(receive(&mut primary, &buf[..len]), [up(0, 1)]);
+    }
+}
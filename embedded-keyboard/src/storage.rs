@@ -0,0 +1,476 @@
+//! Persistent keymap storage on top of [`embedded-storage`] NOR flash.
+//!
+//! A [`KeymapStorage`] keeps a runtime-modified [`Keymap`] in a region of
+//! flash or EEPROM so that remapped keys survive a power cycle. The stored
+//! image is versioned and checksummed; anything that does not match the
+//! keymap the firmware was built with is ignored in favour of the
+//! compiled-in default. A [`SettingsStorage`] does the same for the
+//! [`Settings`].
+//!
+//! [`embedded-storage`]: https://docs.rs/embedded-storage
+
+use embedded_storage::nor_flash::NorFlash;
+
+use crate::crc::Crc16;
+use crate::{Action, Keymap, Settings};
+
+const MAGIC: [u8; 4] = *b"EKKM";
+const SETTINGS_MAGIC: [u8; 4] = *b"EKST";
+const SETTINGS_HEADER_LEN: usize = 8;
+const FORMAT_VERSION: u8 = 1;
+const HEADER_LEN: usize = 12;
+const CHUNK_LEN: usize = 64;
+
+/// Errors produced while loading or saving a keymap or settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum StorageError<E> {
+    /// The underlying storage failed
+    Storage(E),
+    /// Nothing has been saved
+    NotFound,
+    /// The saved data has a different format, version or dimensions
+    Incompatible,
+    /// The saved data failed its checksum
+    Corrupted,
+    /// The keymap contains an action that cannot be stored
+    Unencodable(Action),
+    /// The storage region is too small, or its alignment is not supported
+    Unsupported,
+}
+
+/// Keymap persisted in a region of NOR flash or EEPROM.
+///
+/// The region starts at `offset` and must be erasable on its own, i.e.
+/// `offset` should be aligned to the storage erase size.
+pub struct KeymapStorage<S> {
+    storage: S,
+    offset: u32,
+    version: u16,
+}
+
+impl<S: NorFlash> KeymapStorage<S> {
+    /// Create a keymap store at `offset` within `storage`.
+    ///
+    /// `version` identifies the firmware's default keymap. Bump it whenever
+    /// the default keymap changes in a way that makes previously saved
+    /// keymaps meaningless, and they will be ignored.
+    pub fn new(storage: S, offset: u32, version: u16) -> Self {
+        Self {
+            storage,
+            offset,
+            version,
+        }
+    }
+
+    /// Destroys this instance and returns the storage back to the caller.
+    pub fn destroy(self) -> S {
+        self.storage
+    }
+
+    /// Load the saved keymap, falling back to `default` when there is no
+    /// usable keymap in storage.
+    pub fn load<const LAYERS: usize, const ROWS: usize, const COLS: usize>(
+        &mut self,
+        default: &Keymap<LAYERS, ROWS, COLS>,
+    ) -> Keymap<LAYERS, ROWS, COLS> {
+        let mut keymap = *default;
+
+        match self.try_load(&mut keymap) {
+            Ok(()) => keymap,
+            Err(_) => *default,
+        }
+    }
+
+    /// Load the saved keymap into `keymap`.
+    ///
+    /// On error, `keymap` may have been partially overwritten.
+    pub fn try_load<const LAYERS: usize, const ROWS: usize, const COLS: usize>(
+        &mut self,
+        keymap: &mut Keymap<LAYERS, ROWS, COLS>,
+    ) -> Result<(), StorageError<S::Error>> {
+        check_alignment(S::READ_SIZE)?;
+
+        let mut chunk = [0; CHUNK_LEN];
+        self.storage
+            .read(self.offset, &mut chunk)
+            .map_err(StorageError::Storage)?;
+
+        let (header, _) = chunk.split_at(HEADER_LEN);
+
+        if header[..4] != MAGIC {
+            return Err(StorageError::NotFound);
+        }
+
+        let expected = Self::header::<LAYERS, ROWS, COLS>(self.version, 0)?;
+        if header[4..10] != expected[4..10] {
+            return Err(StorageError::Incompatible);
+        }
+
+        let checksum = u16::from_le_bytes([header[10], header[11]]);
+        let mut crc = Crc16::new();
+        let mut position = HEADER_LEN;
+        let mut address = self.offset;
+
+        for layer in 0..LAYERS {
+            for row in 0..ROWS {
+                for col in 0..COLS {
+                    if position == CHUNK_LEN {
+                        address += CHUNK_LEN as u32;
+                        self.storage
+                            .read(address, &mut chunk)
+                            .map_err(StorageError::Storage)?;
+                        position = 0;
+                    }
+
+                    let raw = &chunk[position..position + 2];
+                    crc.update(raw);
+                    position += 2;
+
+                    let action = Action::from_raw(u16::from_le_bytes([raw[0], raw[1]]))
+                        .ok_or(StorageError::Corrupted)?;
+
+                    if let Some(a) = keymap.action_mut(layer, crate::Coordinate::new(row, col)) {
+                        *a = action;
+                    }
+                }
+            }
+        }
+
+        if crc.finish() == checksum {
+            Ok(())
+        } else {
+            Err(StorageError::Corrupted)
+        }
+    }
+
+    /// Save `keymap` to storage, replacing whatever was saved before.
+    pub fn save<const LAYERS: usize, const ROWS: usize, const COLS: usize>(
+        &mut self,
+        keymap: &Keymap<LAYERS, ROWS, COLS>,
+    ) -> Result<(), StorageError<S::Error>> {
+        check_alignment(S::WRITE_SIZE)?;
+
+        let mut crc = Crc16::new();
+        for (_, _, action) in keymap.iter() {
+            let raw = action.to_raw().ok_or(StorageError::Unencodable(action))?;
+            crc.update(&raw.to_le_bytes());
+        }
+
+        erase(
+            &mut self.storage,
+            self.offset,
+            Self::image_len::<LAYERS, ROWS, COLS>(),
+        )?;
+
+        let header = Self::header::<LAYERS, ROWS, COLS>(self.version, crc.finish())?;
+        let mut chunk = [0xff; CHUNK_LEN];
+        chunk[..HEADER_LEN].copy_from_slice(&header);
+
+        let mut position = HEADER_LEN;
+        let mut address = self.offset;
+
+        for (_, _, action) in keymap.iter() {
+            if position == CHUNK_LEN {
+                self.storage
+                    .write(address, &chunk)
+                    .map_err(StorageError::Storage)?;
+                address += CHUNK_LEN as u32;
+                chunk = [0xff; CHUNK_LEN];
+                position = 0;
+            }
+
+            // Already checked to be encodable above.
+            let raw = action.to_raw().unwrap_or_default();
+            chunk[position..position + 2].copy_from_slice(&raw.to_le_bytes());
+            position += 2;
+        }
+
+        let len = position.next_multiple_of(S::WRITE_SIZE);
+        self.storage
+            .write(address, &chunk[..len])
+            .map_err(StorageError::Storage)
+    }
+
+    /// Erase the saved keymap, so that the next load falls back to the
+    /// default.
+    pub fn clear(&mut self) -> Result<(), StorageError<S::Error>> {
+        erase(&mut self.storage, self.offset, HEADER_LEN)
+    }
+
+    const fn image_len<const LAYERS: usize, const ROWS: usize, const COLS: usize>() -> usize {
+        HEADER_LEN + 2 * LAYERS * ROWS * COLS
+    }
+
+    fn header<const LAYERS: usize, const ROWS: usize, const COLS: usize>(
+        version: u16,
+        checksum: u16,
+    ) -> Result<[u8; HEADER_LEN], StorageError<S::Error>> {
+        let dimension = |n: usize| u8::try_from(n).map_err(|_| StorageError::Unsupported);
+        let version = version.to_le_bytes();
+        let checksum = checksum.to_le_bytes();
+
+        Ok([
+            MAGIC[0],
+            MAGIC[1],
+            MAGIC[2],
+            MAGIC[3],
+            FORMAT_VERSION,
+            dimension(LAYERS)?,
+            dimension(ROWS)?,
+            dimension(COLS)?,
+            version[0],
+            version[1],
+            checksum[0],
+            checksum[1],
+        ])
+    }
+}
+
+/// [`Settings`] persisted in a region of NOR flash or EEPROM.
+///
+/// The region starts at `offset` and must be erasable on its own, i.e.
+/// `offset` should be aligned to the storage erase size. It must not
+/// overlap the region of a [`KeymapStorage`].
+pub struct SettingsStorage<S> {
+    storage: S,
+    offset: u32,
+}
+
+impl<S: NorFlash> SettingsStorage<S> {
+    /// Create a settings store at `offset` within `storage`.
+    pub fn new(storage: S, offset: u32) -> Self {
+        Self { storage, offset }
+    }
+
+    /// Destroys this instance and returns the storage back to the caller.
+    pub fn destroy(self) -> S {
+        self.storage
+    }
+
+    /// Load the saved settings, falling back to [`Settings::DEFAULT`] when
+    /// there are no usable settings in storage.
+    pub fn load(&mut self) -> Settings {
+        self.try_load().unwrap_or_default()
+    }
+
+    /// Load the saved settings.
+    pub fn try_load(&mut self) -> Result<Settings, StorageError<S::Error>> {
+        check_alignment(S::READ_SIZE)?;
+
+        let mut chunk = [0; CHUNK_LEN];
+        self.storage
+            .read(self.offset, &mut chunk)
+            .map_err(StorageError::Storage)?;
+
+        let (header, rest) = chunk.split_at(SETTINGS_HEADER_LEN);
+
+        if header[..4] != SETTINGS_MAGIC {
+            return Err(StorageError::NotFound);
+        }
+
+        if header[4] != FORMAT_VERSION || usize::from(header[5]) != Settings::LEN {
+            return Err(StorageError::Incompatible);
+        }
+
+        let mut bytes = [0; Settings::LEN];
+        bytes.copy_from_slice(&rest[..Settings::LEN]);
+
+        let mut crc = Crc16::new();
+        crc.update(&bytes);
+        if crc.finish() != u16::from_le_bytes([header[6], header[7]]) {
+            return Err(StorageError::Corrupted);
+        }
+
+        Ok(Settings::from_bytes(&bytes))
+    }
+
+    /// Save `settings` to storage, replacing whatever was saved before.
+    pub fn save(&mut self, settings: &Settings) -> Result<(), StorageError<S::Error>> {
+        check_alignment(S::WRITE_SIZE)?;
+
+        let bytes = settings.to_bytes();
+        let mut crc = Crc16::new();
+        crc.update(&bytes);
+        let checksum = crc.finish().to_le_bytes();
+
+        let len = SETTINGS_HEADER_LEN + Settings::LEN;
+        erase(&mut self.storage, self.offset, len)?;
+
+        let mut chunk = [0xff; CHUNK_LEN];
+        chunk[..4].copy_from_slice(&SETTINGS_MAGIC);
+        chunk[4] = FORMAT_VERSION;
+        chunk[5] = Settings::LEN as u8;
+        chunk[6..8].copy_from_slice(&checksum);
+        chunk[SETTINGS_HEADER_LEN..len].copy_from_slice(&bytes);
+
+        self.storage
+            .write(self.offset, &chunk[..len.next_multiple_of(S::WRITE_SIZE)])
+            .map_err(StorageError::Storage)
+    }
+
+    /// Erase the saved settings, so that the next load falls back to the
+    /// defaults.
+    pub fn clear(&mut self) -> Result<(), StorageError<S::Error>> {
+        erase(&mut self.storage, self.offset, SETTINGS_HEADER_LEN)
+    }
+}
+
+fn erase<S: NorFlash>(
+    storage: &mut S,
+    offset: u32,
+    len: usize,
+) -> Result<(), StorageError<S::Error>> {
+    let len = len.next_multiple_of(S::ERASE_SIZE);
+
+    if offset as usize + len > storage.capacity() {
+        return Err(StorageError::Unsupported);
+    }
+
+    storage
+        .erase(offset, offset + len as u32)
+        .map_err(StorageError::Storage)
+}
+
+fn check_alignment<E>(size: usize) -> Result<(), StorageError<E>> {
+    if size <= CHUNK_LEN && CHUNK_LEN % size == 0 {
+        Ok(())
+    } else {
+        Err(StorageError::Unsupported)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Coordinate, KeyCode};
+    use embedded_storage::nor_flash::{ErrorType, NorFlashErrorKind, ReadNorFlash};
+
+    /// RAM backed flash with 4-byte writes and 256-byte sectors.
+    struct RamFlash([u8; 1024]);
+
+    impl ErrorType for RamFlash {
+        type Error = NorFlashErrorKind;
+    }
+
+    impl ReadNorFlash for RamFlash {
+        const READ_SIZE: usize = 1;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            let offset = offset as usize;
+            let end = (offset + bytes.len()).min(self.0.len());
+            bytes.fill(0xff);
+            bytes[..end - offset].copy_from_slice(&self.0[offset..end]);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            self.0.len()
+        }
+    }
+
+    impl NorFlash for RamFlash {
+        const WRITE_SIZE: usize = 4;
+        const ERASE_SIZE: usize = 256;
+
+        fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            assert_eq!(from as usize % Self::ERASE_SIZE, 0);
+            assert_eq!(to as usize % Self::ERASE_SIZE, 0);
+            self.0[from as usize..to as usize].fill(0xff);
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            assert_eq!(offset as usize % Self::WRITE_SIZE, 0);
+            assert_eq!(bytes.len() % Self::WRITE_SIZE, 0);
+            for (cell, byte) in self.0[offset as usize..].iter_mut().zip(bytes) {
+                *cell &= byte;
+            }
+            Ok(())
+        }
+    }
+
+    const DEFAULT: Keymap<2, 4, 6> = crate::keymap! {
+        {
+            [KA KB KC KD KE KF]
+            [KG KH KI KJ KK KL]
+            [KM KN KO KP KQ KR]
+            [KS KT KU KV KW (1)]
+        }
+        {
+            [K1 K2 K3 K4 K5 K6]
+            [K7 K8 K9 K0 KF1 KF2]
+            [KF3 KF4 KF5 KF6 KF7 KF8]
+            [KF9 KF10 KF11 KF12 KEnter (1)]
+        }
+    };
+
+    #[test]
+    fn empty_storage_falls_back_to_default() {
+        let mut storage = KeymapStorage::new(RamFlash([0xff; 1024]), 256, 1);
+        let mut keymap = DEFAULT;
+
+        assert_eq!(storage.try_load(&mut keymap), Err(StorageError::NotFound));
+        assert_eq!(storage.load(&DEFAULT), DEFAULT);
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let mut storage = KeymapStorage::new(RamFlash([0xff; 1024]), 256, 1);
+
+        let mut keymap = DEFAULT;
+        *keymap.action_mut(1, Coordinate::new(3, 4)).unwrap() = Action::Key(KeyCode::KEscape);
+        *keymap.action_mut(0, Coordinate::new(0, 0)).unwrap() = Action::ToggleLayer(1);
+
+        storage.save(&keymap).unwrap();
+        assert_eq!(storage.load(&DEFAULT), keymap);
+
+        storage.clear().unwrap();
+        assert_eq!(storage.load(&DEFAULT), DEFAULT);
+    }
+
+    #[test]
+    fn version_mismatch_is_incompatible() {
+        let mut storage = KeymapStorage::new(RamFlash([0xff; 1024]), 0, 1);
+        storage.save(&DEFAULT).unwrap();
+
+        let mut storage = KeymapStorage::new(storage.destroy(), 0, 2);
+        let mut keymap = DEFAULT;
+        assert_eq!(
+            storage.try_load(&mut keymap),
+            Err(StorageError::Incompatible)
+        );
+    }
+
+    #[test]
+    fn corruption_is_detected() {
+        let mut storage = KeymapStorage::new(RamFlash([0xff; 1024]), 0, 1);
+        storage.save(&DEFAULT).unwrap();
+
+        let mut flash = storage.destroy();
+        flash.0[HEADER_LEN + 70] ^= 0x01;
+
+        let mut storage = KeymapStorage::new(flash, 0, 1);
+        let mut keymap = DEFAULT;
+        assert_eq!(storage.try_load(&mut keymap), Err(StorageError::Corrupted));
+        assert_eq!(storage.load(&DEFAULT), DEFAULT);
+    }
+
+    #[test]
+    fn settings_round_trip() {
+        let mut storage = SettingsStorage::new(RamFlash([0xff; 1024]), 768);
+        assert_eq!(storage.try_load(), Err(StorageError::NotFound));
+        assert_eq!(storage.load(), Settings::DEFAULT);
+
+        let settings = Settings {
+            debounce: 5,
+            tapping_term: 175,
+            ..Settings::DEFAULT
+        };
+        storage.save(&settings).unwrap();
+        assert_eq!(storage.load(), settings);
+
+        storage.clear().unwrap();
+        assert_eq!(storage.load(), Settings::DEFAULT);
+    }
+}
@@ -0,0 +1,256 @@
+//! Time source of the time-dependent features.
+//!
+//! Debounce, hold-tap, typematic repeat, macros and the rest of the crate
+//! all take the time as a `now` timestamp: milliseconds in a `u32`, which
+//! wraps around after about 49 days. A [`Clock`] is where the application
+//! reads those timestamps from, so that every feature runs on the same
+//! clock. [`Ticks`] turns a raw hardware counter into one, and closures
+//! returning milliseconds, e.g. from `embassy-time`, are clocks too.
+//!
+//! Since timestamps wrap around, they are compared with [`is_due`] and
+//! [`earliest`], which hold as long as the times compared lie within half
+//! the timestamp range, about 24 days, of each other.
+//!
+//! [`Timestamped`] stamps the events of a [`Keyboard`] with the time of
+//! the scan which detected them, for consumers doing their own timing,
+//! like measuring latency or telling taps from holds.
+
+use crate::{KeyEvent, Keyboard};
+
+/// Source of the millisecond timestamps taken by the time-dependent
+/// features. Closures returning the time implement this trait.
+pub trait Clock {
+    /// The current time in milliseconds, wrapping around.
+    fn now(&mut self) -> u32;
+}
+
+impl<F: FnMut() -> u32> Clock for F {
+    #[inline]
+    fn now(&mut self) -> u32 {
+        self()
+    }
+}
+
+/// Clock reading a free-running hardware counter, like a timer counter
+/// register or the cycle counter, counting up at `hz` ticks per second.
+///
+/// The counter may wrap around at any `u32` value, but must be read at
+/// least once per wrap for the elapsed time to be accounted for: e.g.
+/// every 71 seconds for a 60 MHz cycle counter.
+pub struct Ticks<F> {
+    read: F,
+    hz: u32,
+    last: Option<u32>,
+    now: u32,
+    remainder: u64,
+}
+
+impl<F: FnMut() -> u32> Ticks<F> {
+    /// Instantiate a clock on the counter returned by `read`, counting at
+    /// `hz` ticks per second, clamped to at least 1. Time starts at 0 on
+    /// the first read.
+    pub fn new(read: F, hz: u32) -> Self {
+        Self {
+            read,
+            hz: hz.max(1),
+            last: None,
+            now: 0,
+            remainder: 0,
+        }
+    }
+}
+
+impl<F: FnMut() -> u32> Clock for Ticks<F> {
+    fn now(&mut self) -> u32 {
+        let ticks = (self.read)();
+        let elapsed = ticks.wrapping_sub(self.last.unwrap_or(ticks));
+        self.last = Some(ticks);
+
+        // Carry the ticks short of a millisecond over to the next read.
+        let scaled = u64::from(elapsed) * 1000 + self.remainder;
+        let hz = u64::from(self.hz);
+        self.remainder = scaled % hz;
+        self.now = self
+            .now
+            .wrapping_add(u32::try_from(scaled / hz).unwrap_or(u32::MAX));
+        self.now
+    }
+}
+
+/// Key event, with the time of the scan which detected it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TimedKeyEvent {
+    /// Time of the scan, in milliseconds
+    pub time: u32,
+    /// Key event
+    pub event: KeyEvent,
+}
+
+/// [`Keyboard`] whose events are stamped with the time of the scan, read
+/// from a [`Clock`].
+///
+/// Events beyond `N` in a single scan are dropped, so `N` must hold the
+/// most events the keyboard produces at once.
+pub struct Timestamped<K, C, const N: usize> {
+    keyboard: K,
+    clock: C,
+    events: [TimedKeyEvent; N],
+}
+
+impl<K: Keyboard, C: Clock, const N: usize> Timestamped<K, C, N> {
+    /// Stamp the events of `keyboard` with the time of `clock`.
+    pub fn new(keyboard: K, clock: C) -> Self {
+        Self {
+            keyboard,
+            clock,
+            events: [TimedKeyEvent {
+                time: 0,
+                event: KeyEvent::NoEvent,
+            }; N],
+        }
+    }
+
+    /// Scan the keyboard, returning the key events detected, stamped with
+    /// the time the scan started.
+    ///
+    /// # Errors
+    ///
+    /// Returns the keyboard's error if the scan failed.
+    pub fn scan(&mut self) -> Result<&[TimedKeyEvent], K::Error> {
+        let time = self.clock.now();
+        let events = self.keyboard.scan()?;
+
+        let mut len = 0;
+        let events = events.iter().filter(|e| **e != KeyEvent::NoEvent);
+        for (slot, event) in self.events.iter_mut().zip(events) {
+            *slot = TimedKeyEvent {
+                time,
+                event: *event,
+            };
+            len += 1;
+        }
+
+        Ok(&self.events[..len])
+    }
+
+    /// Whether the last scan observed any raw activity, see
+    /// [`Keyboard::activity`].
+    pub fn activity(&self) -> bool {
+        self.keyboard.activity()
+    }
+
+    /// The keyboard scanned.
+    pub fn keyboard_mut(&mut self) -> &mut K {
+        &mut self.keyboard
+    }
+
+    /// The clock, e.g. to time other work on the same clock as the
+    /// events.
+    pub fn clock_mut(&mut self) -> &mut C {
+        &mut self.clock
+    }
+
+    /// Destroys this instance and returns the keyboard and clock.
+    pub fn destroy(self) -> (K, C) {
+        (self.keyboard, self.clock)
+    }
+}
+
+/// Whether `deadline` has come at `now`, allowing for the timestamps
+/// wrapping around.
+#[inline]
+pub const fn is_due(now: u32, deadline: u32) -> bool {
+    now.wrapping_sub(deadline) <= u32::MAX / 2
+}
+
+/// The earlier of deadlines `a` and `b`, allowing for the timestamps
+/// wrapping around.
+#[inline]
+pub const fn earliest(a: u32, b: u32) -> u32 {
+    if b.wrapping_sub(a) < u32::MAX / 2 {
+        a
+    } else {
+        b
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn closure_clock() {
+        let mut time = 0;
+        let mut clock = || {
+            time += 5;
+            time
+        };
+
+        assert_eq!(clock.now(), 5);
+        assert_eq!(clock.now(), 10);
+    }
+
+    #[test]
+    fn ticks_carry_and_wrap() {
+        let counter = core::cell::Cell::new(u32::MAX - 1500);
+        let mut clock = Ticks::new(|| counter.get(), 1000 * 3 / 2);
+
+        assert_eq!(clock.now(), 0);
+
+        // 1.5 ticks per millisecond: 4 ticks make 2 milliseconds and two
+        // thirds, carried over to the next 2 ticks.
+        counter.set(counter.get() + 4);
+        assert_eq!(clock.now(), 2);
+        counter.set(counter.get() + 2);
+        assert_eq!(clock.now(), 4);
+
+        // Across the counter wrapping around.
+        counter.set(counter.get().wrapping_add(3000));
+        assert_eq!(clock.now(), 2004);
+    }
+
+    #[test]
+    fn timestamped_events() {
+        use crate::fake::FakeKeyboard;
+        use crate::Coordinate;
+
+        const A: Coordinate = Coordinate::new(0, 0);
+        const B: Coordinate = Coordinate::new(0, 1);
+        const SCANS: &[&[KeyEvent]] = &[
+            &[KeyEvent::KeyDown(A), KeyEvent::NoEvent],
+            &[],
+            &[KeyEvent::KeyDown(B), KeyEvent::KeyUp(A), KeyEvent::KeyUp(B)],
+        ];
+
+        let mut time = 0;
+        let clock = || {
+            time += 10;
+            time
+        };
+        let mut keyboard = Timestamped::<_, _, 2>::new(FakeKeyboard::new(SCANS), clock);
+
+        let at = |time, event| TimedKeyEvent { time, event };
+        assert_eq!(keyboard.scan(), Ok(&[at(10, KeyEvent::KeyDown(A))][..]));
+        assert_eq!(keyboard.scan(), Ok(&[][..]));
+        assert_eq!(
+            keyboard.scan(),
+            Ok(&[at(30, KeyEvent::KeyDown(B)), at(30, KeyEvent::KeyUp(A))][..])
+        );
+    }
+
+    #[test]
+    fn deadlines_wrap() {
+        assert!(is_due(10, 10));
+        assert!(is_due(11, 10));
+        assert!(!is_due(9, 10));
+        assert!(is_due(5, u32::MAX - 5));
+        assert!(!is_due(u32::MAX - 5, 5));
+
+        assert_eq!(earliest(3, 7), 3);
+        assert_eq!(earliest(7, 3), 3);
+        assert_eq!(earliest(u32::MAX, 2), u32::MAX);
+        assert_eq!(earliest(2, u32::MAX), u32::MAX);
+    }
+}
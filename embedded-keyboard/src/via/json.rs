@@ -0,0 +1,141 @@
+use core::fmt::{self, Write};
+
+use crate::{Action, Coordinate, KeyCode, Keymap};
+
+/// Prefix of a macro action in the VIA macro buffer.
+const MACRO_PREFIX: u8 = 0x01;
+const MACRO_TAP: u8 = 0x01;
+const MACRO_DOWN: u8 = 0x02;
+const MACRO_UP: u8 = 0x03;
+const MACRO_DELAY: u8 = 0x04;
+const MACRO_DELAY_END: u8 = b'|';
+
+/// Keymap and macros in the JSON format of VIA's layout backups.
+///
+/// Formatting a `ViaLayout` writes the JSON document, so it can be
+/// streamed straight to a serial port or any other [`fmt::Write`] sink
+/// without buffering it first. Keycodes are written as QMK keycode
+/// expressions, see the [`Display`](fmt::Display) implementation of
+/// [`Action`].
+///
+/// Created by [`ViaHandler::layout`](super::ViaHandler::layout).
+pub struct ViaLayout<'a, const LAYERS: usize, const ROWS: usize, const COLS: usize> {
+    pub(super) name: &'a str,
+    pub(super) vendor_product_id: u32,
+    pub(super) keymap: &'a Keymap<LAYERS, ROWS, COLS>,
+    pub(super) macros: &'a [u8],
+    pub(super) macro_count: u8,
+}
+
+impl<const LAYERS: usize, const ROWS: usize, const COLS: usize> fmt::Display
+    for ViaLayout<'_, LAYERS, ROWS, COLS>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("{\"name\":\"")?;
+        for c in self.name.chars() {
+            escape(f, c)?;
+        }
+        write!(
+            f,
+            "\",\"vendorProductId\":{},\"macros\":[",
+            self.vendor_product_id
+        )?;
+
+        let mut macros = self.macros.split(|b| *b == 0);
+        for i in 0..self.macro_count {
+            if i > 0 {
+                f.write_char(',')?;
+            }
+            macro_text(f, macros.next().unwrap_or_default())?;
+        }
+
+        f.write_str("],\"layers\":[")?;
+
+        for layer in 0..LAYERS {
+            if layer > 0 {
+                f.write_char(',')?;
+            }
+            f.write_char('[')?;
+
+            for index in 0..ROWS * COLS {
+                let coordinate = Coordinate::from_index(index, COLS);
+                let action = self.keymap.action(layer, coordinate).unwrap_or_default();
+                if index > 0 {
+                    f.write_char(',')?;
+                }
+                write!(f, "\"{action}\"")?;
+            }
+
+            f.write_char(']')?;
+        }
+
+        f.write_str("]}")
+    }
+}
+
+/// Write a VIA macro as a JSON string in VIA's macro text syntax, where
+/// `{KC_A}` taps a key, `{+KC_A}` and `{-KC_A}` press and release it, and
+/// `{100}` waits 100 milliseconds.
+fn macro_text(f: &mut fmt::Formatter<'_>, mut bytes: &[u8]) -> fmt::Result {
+    f.write_char('"')?;
+
+    while let Some((&byte, rest)) = bytes.split_first() {
+        bytes = rest;
+
+        if byte != MACRO_PREFIX {
+            escape(f, char::from(byte))?;
+            continue;
+        }
+
+        let Some((&kind, rest)) = bytes.split_first() else {
+            break;
+        };
+        bytes = rest;
+
+        match kind {
+            MACRO_TAP | MACRO_DOWN | MACRO_UP => {
+                let Some((&code, rest)) = bytes.split_first() else {
+                    break;
+                };
+                bytes = rest;
+
+                let prefix = match kind {
+                    MACRO_DOWN => "+",
+                    MACRO_UP => "-",
+                    _ => "",
+                };
+                let action = KeyCode::try_from(u16::from(code)).map_or(Action::NoOp, Action::Key);
+                write!(f, "{{{prefix}{action}}}")?;
+            }
+            MACRO_DELAY => {
+                let end = bytes
+                    .iter()
+                    .position(|b| *b == MACRO_DELAY_END)
+                    .unwrap_or(bytes.len());
+                let (digits, rest) = bytes.split_at(end);
+                bytes = rest.get(1..).unwrap_or_default();
+
+                f.write_char('{')?;
+                for digit in digits.iter().filter(|b| b.is_ascii_digit()) {
+                    f.write_char(char::from(*digit))?;
+                }
+                f.write_char('}')?;
+            }
+            // Unknown actions cannot be told apart from their arguments,
+            // so the rest of the macro is lost.
+            _ => break,
+        }
+    }
+
+    f.write_char('"')
+}
+
+/// Write `c` as part of a JSON string.
+fn escape(f: &mut fmt::Formatter<'_>, c: char) -> fmt::Result {
+    match c {
+        '"' => f.write_str("\\\""),
+        '\\' => f.write_str("\\\\"),
+        c if c.is_control() => write!(f, "\\u{:04x}", u32::from(c)),
+        c => f.write_char(c),
+    }
+}
@@ -0,0 +1,604 @@
+//! Handler for the VIA configuration protocol.
+//!
+//! VIA talks to the keyboard over a vendor-defined raw HID interface using
+//! fixed-size packets of [`PACKET_LEN`] bytes. The first byte of every
+//! packet is a command id; the keyboard answers by rewriting the packet in
+//! place and sending it back. [`ViaHandler`] implements the command set on
+//! top of a [`Keymap`], independently of how the packets are transported.
+//!
+//! Keycodes cross the wire in the 16-bit encoding of [`Action::to_raw`],
+//! which is the one VIA expects.
+//!
+//! The live keymap and macros can also be exported in the JSON format of
+//! VIA's layout backups with [`ViaHandler::layout`], to let users back up
+//! and share layouts over a serial console or similar channel.
+
+mod json;
+
+pub use self::json::*;
+
+use crate::{Action, Coordinate, Keymap, Settings};
+
+/// Length of a VIA packet.
+pub const PACKET_LEN: usize = 32;
+
+/// VIA protocol version implemented by [`ViaHandler`].
+pub const PROTOCOL_VERSION: u16 = 0x000c;
+
+/// VIA command ids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+#[non_exhaustive]
+pub enum Command {
+    /// Get the implemented protocol version
+    GetProtocolVersion = 0x01,
+    /// Get a keyboard value
+    GetKeyboardValue = 0x02,
+    /// Set a keyboard value
+    SetKeyboardValue = 0x03,
+    /// Get the keycode at a layer, row and column
+    GetKeycode = 0x04,
+    /// Set the keycode at a layer, row and column
+    SetKeycode = 0x05,
+    /// Reset the keymap to its default
+    ResetKeymap = 0x06,
+    /// Set a value of a custom menu channel
+    CustomSetValue = 0x07,
+    /// Get a value of a custom menu channel
+    CustomGetValue = 0x08,
+    /// Persist the values of a custom menu channel
+    CustomSave = 0x09,
+    /// Reset all persisted settings
+    EepromReset = 0x0a,
+    /// Jump to the bootloader
+    BootloaderJump = 0x0b,
+    /// Get the number of macros
+    GetMacroCount = 0x0c,
+    /// Get the size of the macro buffer
+    GetMacroBufferSize = 0x0d,
+    /// Read part of the macro buffer
+    GetMacroBuffer = 0x0e,
+    /// Write part of the macro buffer
+    SetMacroBuffer = 0x0f,
+    /// Clear the macro buffer
+    ResetMacros = 0x10,
+    /// Get the number of keymap layers
+    GetLayerCount = 0x11,
+    /// Read part of the keymap
+    GetKeymapBuffer = 0x12,
+    /// Write part of the keymap
+    SetKeymapBuffer = 0x13,
+    /// Answer to an unknown command
+    Unhandled = 0xff,
+}
+
+impl TryFrom<u8> for Command {
+    type Error = u8;
+
+    fn try_from(id: u8) -> Result<Self, Self::Error> {
+        Ok(match id {
+            0x01 => Self::GetProtocolVersion,
+            0x02 => Self::GetKeyboardValue,
+            0x03 => Self::SetKeyboardValue,
+            0x04 => Self::GetKeycode,
+            0x05 => Self::SetKeycode,
+            0x06 => Self::ResetKeymap,
+            0x07 => Self::CustomSetValue,
+            0x08 => Self::CustomGetValue,
+            0x09 => Self::CustomSave,
+            0x0a => Self::EepromReset,
+            0x0b => Self::BootloaderJump,
+            0x0c => Self::GetMacroCount,
+            0x0d => Self::GetMacroBufferSize,
+            0x0e => Self::GetMacroBuffer,
+            0x0f => Self::SetMacroBuffer,
+            0x10 => Self::ResetMacros,
+            0x11 => Self::GetLayerCount,
+            0x12 => Self::GetKeymapBuffer,
+            0x13 => Self::SetKeymapBuffer,
+            0xff => Self::Unhandled,
+            _ => return Err(id),
+        })
+    }
+}
+
+const VALUE_LAYOUT_OPTIONS: u8 = 0x02;
+const VALUE_FIRMWARE_VERSION: u8 = 0x04;
+
+/// Custom menu channel of the keyboard's own [`Settings`].
+pub const SETTINGS_CHANNEL: u8 = 0x00;
+
+/// Ids of the [`Settings`] values on [`SETTINGS_CHANNEL`]. Values are
+/// exchanged big endian: one byte for the debounce, two for the others.
+pub mod setting {
+    /// [`Settings::debounce`](crate::Settings::debounce)
+    pub const DEBOUNCE: u8 = 0x01;
+    /// [`Settings::tapping_term`](crate::Settings::tapping_term)
+    pub const TAPPING_TERM: u8 = 0x02;
+    /// [`Settings::repeat_delay`](crate::Settings::repeat_delay)
+    pub const REPEAT_DELAY: u8 = 0x03;
+    /// [`Settings::repeat_interval`](crate::Settings::repeat_interval)
+    pub const REPEAT_INTERVAL: u8 = 0x04;
+}
+
+/// Side effect of a handled packet the application has to act upon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum ViaEvent {
+    /// The keymap was modified and should be persisted
+    KeymapChanged,
+    /// The macro buffer was modified and should be persisted
+    MacrosChanged,
+    /// The layout options were modified and should be persisted
+    LayoutOptionsChanged(u32),
+    /// The settings were modified and should be applied
+    SettingsChanged(Settings),
+    /// The host asked to persist the settings
+    SettingsSaved(Settings),
+    /// The host asked to wipe all persisted settings
+    EepromReset,
+    /// The host asked to jump to the bootloader
+    Bootloader,
+}
+
+/// Transport-agnostic VIA command handler.
+///
+/// Owns the VIA macro buffer of `MACRO_LEN` bytes, which VIA treats as
+/// `MACROS` consecutive NUL-terminated macro definitions.
+pub struct ViaHandler<const MACROS: u8, const MACRO_LEN: usize> {
+    firmware_version: u32,
+    layout_options: u32,
+    settings: Settings,
+    macros: [u8; MACRO_LEN],
+}
+
+impl<const MACROS: u8, const MACRO_LEN: usize> ViaHandler<MACROS, MACRO_LEN> {
+    /// Create a handler reporting `firmware_version` to the host.
+    pub const fn new(firmware_version: u32) -> Self {
+        Self {
+            firmware_version,
+            layout_options: 0,
+            settings: Settings::DEFAULT,
+            macros: [0; MACRO_LEN],
+        }
+    }
+
+    /// Current layout options, as set by the host.
+    pub const fn layout_options(&self) -> u32 {
+        self.layout_options
+    }
+
+    /// Restore layout options, e.g. after loading them from storage.
+    pub fn set_layout_options(&mut self, options: u32) {
+        self.layout_options = options;
+    }
+
+    /// Current settings, as set by the host.
+    pub const fn settings(&self) -> Settings {
+        self.settings
+    }
+
+    /// Restore settings, e.g. after loading them from storage.
+    pub fn set_settings(&mut self, settings: Settings) {
+        self.settings = settings;
+    }
+
+    /// Raw VIA macro buffer.
+    pub const fn macros(&self) -> &[u8; MACRO_LEN] {
+        &self.macros
+    }
+
+    /// Mutable raw VIA macro buffer, e.g. to restore it from storage.
+    pub fn macros_mut(&mut self) -> &mut [u8; MACRO_LEN] {
+        &mut self.macros
+    }
+
+    /// VIA layout backup of `keymap` and the macros, to be formatted as
+    /// JSON.
+    ///
+    /// `name` is the keyboard name, and `vendor_id` and `product_id` the
+    /// USB ids VIA uses to match the backup with the keyboard.
+    pub fn layout<'a, const LAYERS: usize, const ROWS: usize, const COLS: usize>(
+        &'a self,
+        name: &'a str,
+        vendor_id: u16,
+        product_id: u16,
+        keymap: &'a Keymap<LAYERS, ROWS, COLS>,
+    ) -> ViaLayout<'a, LAYERS, ROWS, COLS> {
+        ViaLayout {
+            name,
+            vendor_product_id: u32::from(vendor_id) << 16 | u32::from(product_id),
+            keymap,
+            macros: &self.macros,
+            macro_count: MACROS,
+        }
+    }
+
+    /// Handle one packet, rewriting it in place into the response that has
+    /// to be sent back to the host.
+    ///
+    /// `keymap` is the live keymap the host edits, and `default` the
+    /// compiled-in keymap it can be reset to.
+    pub fn handle<const LAYERS: usize, const ROWS: usize, const COLS: usize>(
+        &mut self,
+        packet: &mut [u8; PACKET_LEN],
+        keymap: &mut Keymap<LAYERS, ROWS, COLS>,
+        default: &Keymap<LAYERS, ROWS, COLS>,
+    ) -> Option<ViaEvent> {
+        let Ok(command) = Command::try_from(packet[0]) else {
+            packet[0] = Command::Unhandled as u8;
+            return None;
+        };
+
+        match command {
+            Command::GetProtocolVersion => {
+                packet[1..3].copy_from_slice(&PROTOCOL_VERSION.to_be_bytes());
+                None
+            }
+            Command::GetKeyboardValue => {
+                match packet[1] {
+                    VALUE_LAYOUT_OPTIONS => {
+                        packet[2..6].copy_from_slice(&self.layout_options.to_be_bytes());
+                    }
+                    VALUE_FIRMWARE_VERSION => {
+                        packet[2..6].copy_from_slice(&self.firmware_version.to_be_bytes());
+                    }
+                    _ => packet[0] = Command::Unhandled as u8,
+                }
+                None
+            }
+            Command::SetKeyboardValue => match packet[1] {
+                VALUE_LAYOUT_OPTIONS => {
+                    let options = [packet[2], packet[3], packet[4], packet[5]];
+                    self.layout_options = u32::from_be_bytes(options);
+                    Some(ViaEvent::LayoutOptionsChanged(self.layout_options))
+                }
+                _ => {
+                    packet[0] = Command::Unhandled as u8;
+                    None
+                }
+            },
+            Command::GetKeycode => {
+                let raw = keymap
+                    .action(
+                        packet[1].into(),
+                        Coordinate::new(packet[2].into(), packet[3].into()),
+                    )
+                    .and_then(Action::to_raw)
+                    .unwrap_or_default();
+                packet[4..6].copy_from_slice(&raw.to_be_bytes());
+                None
+            }
+            Command::SetKeycode => {
+                let coordinate = Coordinate::new(packet[2].into(), packet[3].into());
+                let raw = u16::from_be_bytes([packet[4], packet[5]]);
+
+                match (
+                    keymap.action_mut(packet[1].into(), coordinate),
+                    Action::from_raw(raw),
+                ) {
+                    (Some(slot), Some(action)) => {
+                        *slot = action;
+                        Some(ViaEvent::KeymapChanged)
+                    }
+                    _ => None,
+                }
+            }
+            Command::ResetKeymap => {
+                *keymap = *default;
+                Some(ViaEvent::KeymapChanged)
+            }
+            Command::CustomSetValue | Command::CustomGetValue | Command::CustomSave
+                if packet[1] != SETTINGS_CHANNEL =>
+            {
+                packet[0] = Command::Unhandled as u8;
+                None
+            }
+            Command::CustomSetValue => {
+                let settings = &mut self.settings;
+                let word = u16::from_be_bytes([packet[3], packet[4]]);
+
+                match packet[2] {
+                    setting::DEBOUNCE => settings.debounce = packet[3],
+                    setting::TAPPING_TERM => settings.tapping_term = word,
+                    setting::REPEAT_DELAY => settings.repeat_delay = word,
+                    setting::REPEAT_INTERVAL => settings.repeat_interval = word,
+                    _ => {
+                        packet[0] = Command::Unhandled as u8;
+                        return None;
+                    }
+                }
+
+                Some(ViaEvent::SettingsChanged(self.settings))
+            }
+            Command::CustomGetValue => {
+                let settings = &self.settings;
+                let word = match packet[2] {
+                    setting::DEBOUNCE => {
+                        packet[3] = settings.debounce;
+                        return None;
+                    }
+                    setting::TAPPING_TERM => settings.tapping_term,
+                    setting::REPEAT_DELAY => settings.repeat_delay,
+                    setting::REPEAT_INTERVAL => settings.repeat_interval,
+                    _ => {
+                        packet[0] = Command::Unhandled as u8;
+                        return None;
+                    }
+                };
+
+                packet[3..5].copy_from_slice(&word.to_be_bytes());
+                None
+            }
+            Command::CustomSave => Some(ViaEvent::SettingsSaved(self.settings)),
+            Command::EepromReset => Some(ViaEvent::EepromReset),
+            Command::BootloaderJump => Some(ViaEvent::Bootloader),
+            Command::GetMacroCount => {
+                packet[1] = MACROS;
+                None
+            }
+            Command::GetMacroBufferSize => {
+                let size = u16::try_from(MACRO_LEN).unwrap_or(u16::MAX);
+                packet[1..3].copy_from_slice(&size.to_be_bytes());
+                None
+            }
+            Command::GetMacroBuffer => {
+                let (offset, data) = Self::window(packet);
+                for (i, byte) in data.iter_mut().enumerate() {
+                    *byte = self.macros.get(offset + i).copied().unwrap_or_default();
+                }
+                None
+            }
+            Command::SetMacroBuffer => {
+                let (offset, data) = Self::window(packet);
+                for (i, byte) in data.iter().enumerate() {
+                    if let Some(m) = self.macros.get_mut(offset + i) {
+                        *m = *byte;
+                    }
+                }
+                Some(ViaEvent::MacrosChanged)
+            }
+            Command::ResetMacros => {
+                self.macros = [0; MACRO_LEN];
+                Some(ViaEvent::MacrosChanged)
+            }
+            Command::GetLayerCount => {
+                packet[1] = u8::try_from(LAYERS).unwrap_or(u8::MAX);
+                None
+            }
+            Command::GetKeymapBuffer => {
+                let (offset, data) = Self::window(packet);
+                for (i, byte) in data.iter_mut().enumerate() {
+                    let position = offset + i;
+                    let raw = Self::locate::<ROWS, COLS>(position)
+                        .and_then(|(layer, c)| keymap.action(layer, c))
+                        .and_then(Action::to_raw)
+                        .unwrap_or_default();
+                    *byte = raw.to_be_bytes()[position % 2];
+                }
+                None
+            }
+            Command::SetKeymapBuffer => {
+                let (offset, data) = Self::window(packet);
+                let mut changed = None;
+
+                // Keycodes are only updated once both of their bytes are
+                // within the window.
+                for (i, pair) in data.windows(2).enumerate() {
+                    let position = offset + i;
+                    if position % 2 != 0 {
+                        continue;
+                    }
+
+                    let raw = u16::from_be_bytes([pair[0], pair[1]]);
+                    if let (Some(slot), Some(action)) = (
+                        Self::locate::<ROWS, COLS>(position)
+                            .and_then(|(layer, c)| keymap.action_mut(layer, c)),
+                        Action::from_raw(raw),
+                    ) {
+                        *slot = action;
+                        changed = Some(ViaEvent::KeymapChanged);
+                    }
+                }
+
+                changed
+            }
+            Command::Unhandled => None,
+        }
+    }
+
+    /// Split a buffer command into its byte offset and data window.
+    fn window(packet: &mut [u8; PACKET_LEN]) -> (usize, &mut [u8]) {
+        let offset = usize::from(u16::from_be_bytes([packet[1], packet[2]]));
+        let size = usize::from(packet[3]).min(PACKET_LEN - 4);
+
+        (offset, &mut packet[4..4 + size])
+    }
+
+    /// Layer and coordinate of the keycode at byte `position` of the
+    /// keymap buffer.
+    fn locate<const ROWS: usize, const COLS: usize>(
+        position: usize,
+    ) -> Option<(usize, Coordinate)> {
+        let index = position / 2;
+        let layer_len = ROWS * COLS;
+
+        if layer_len == 0 {
+            return None;
+        }
+
+        Some((
+            index / layer_len,
+            Coordinate::from_index(index % layer_len, COLS),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeyCode;
+
+    const DEFAULT: Keymap<2, 2, 2> = crate::keymap! {
+        { [KA KB] [KC (1)] }
+        { [K1 K2] [K3 (1)] }
+    };
+
+    fn packet(bytes: &[u8]) -> [u8; PACKET_LEN] {
+        let mut packet = [0; PACKET_LEN];
+        packet[..bytes.len()].copy_from_slice(bytes);
+        packet
+    }
+
+    #[test]
+    fn protocol_version_and_layers() {
+        let mut via = ViaHandler::<4, 64>::new(0x0102_0304);
+        let mut keymap = DEFAULT;
+
+        let mut p = packet(&[0x01]);
+        assert_eq!(via.handle(&mut p, &mut keymap, &DEFAULT), None);
+        assert_eq!(p[..3], [0x01, 0x00, 0x0c]);
+
+        let mut p = packet(&[0x11]);
+        via.handle(&mut p, &mut keymap, &DEFAULT);
+        assert_eq!(p[..2], [0x11, 2]);
+
+        let mut p = packet(&[0x02, 0x04]);
+        via.handle(&mut p, &mut keymap, &DEFAULT);
+        assert_eq!(p[..6], [0x02, 0x04, 0x01, 0x02, 0x03, 0x04]);
+
+        let mut p = packet(&[0x42, 0x01]);
+        via.handle(&mut p, &mut keymap, &DEFAULT);
+        assert_eq!(p[..2], [0xff, 0x01]);
+    }
+
+    #[test]
+    fn get_and_set_keycode() {
+        let mut via = ViaHandler::<4, 64>::new(0);
+        let mut keymap = DEFAULT;
+
+        let mut p = packet(&[0x04, 1, 1, 1]);
+        via.handle(&mut p, &mut keymap, &DEFAULT);
+        assert_eq!(p[4..6], [0x52, 0x21]);
+
+        let mut p = packet(&[0x05, 0, 0, 1, 0x00, 0x29]);
+        assert_eq!(
+            via.handle(&mut p, &mut keymap, &DEFAULT),
+            Some(ViaEvent::KeymapChanged)
+        );
+        assert_eq!(
+            keymap.action(0, Coordinate::new(0, 1)),
+            Some(Action::Key(KeyCode::KEscape))
+        );
+
+        let mut p = packet(&[0x06]);
+        via.handle(&mut p, &mut keymap, &DEFAULT);
+        assert_eq!(keymap, DEFAULT);
+    }
+
+    #[test]
+    fn keymap_buffer() {
+        let mut via = ViaHandler::<4, 64>::new(0);
+        let mut keymap = DEFAULT;
+
+        let mut p = packet(&[0x12, 0x00, 0x06, 0x04]);
+        via.handle(&mut p, &mut keymap, &DEFAULT);
+        assert_eq!(p[4..8], [0x52, 0x21, 0x00, 0x1e]);
+
+        let mut p = packet(&[0x13, 0x00, 0x08, 0x04, 0x00, 0x1d, 0x00, 0x1c]);
+        assert_eq!(
+            via.handle(&mut p, &mut keymap, &DEFAULT),
+            Some(ViaEvent::KeymapChanged)
+        );
+        assert_eq!(
+            keymap.action(1, Coordinate::new(0, 0)),
+            Some(Action::Key(KeyCode::KZ))
+        );
+        assert_eq!(
+            keymap.action(1, Coordinate::new(0, 1)),
+            Some(Action::Key(KeyCode::KY))
+        );
+    }
+
+    #[test]
+    fn macro_buffer() {
+        let mut via = ViaHandler::<2, 8>::new(0);
+        let mut keymap = DEFAULT;
+
+        let mut p = packet(&[0x0d]);
+        via.handle(&mut p, &mut keymap, &DEFAULT);
+        assert_eq!(p[..3], [0x0d, 0x00, 0x08]);
+
+        let mut p = packet(&[0x0f, 0x00, 0x06, 0x04, b'h', b'i', 0, 0]);
+        assert_eq!(
+            via.handle(&mut p, &mut keymap, &DEFAULT),
+            Some(ViaEvent::MacrosChanged)
+        );
+        assert_eq!(via.macros(), &[0, 0, 0, 0, 0, 0, b'h', b'i']);
+
+        let mut p = packet(&[0x0e, 0x00, 0x05, 0x04]);
+        via.handle(&mut p, &mut keymap, &DEFAULT);
+        assert_eq!(p[4..8], [0, b'h', b'i', 0]);
+    }
+
+    #[test]
+    fn custom_settings_values() {
+        let mut via = ViaHandler::<2, 8>::new(0);
+        let mut keymap = DEFAULT;
+
+        let mut p = packet(&[0x08, 0x00, 0x02]);
+        via.handle(&mut p, &mut keymap, &DEFAULT);
+        assert_eq!(p[3..5], [0x00, 0xc8]);
+
+        let mut p = packet(&[0x07, 0x00, 0x02, 0x00, 0xaf]);
+        let changed = Settings {
+            tapping_term: 175,
+            ..Settings::DEFAULT
+        };
+        assert_eq!(
+            via.handle(&mut p, &mut keymap, &DEFAULT),
+            Some(ViaEvent::SettingsChanged(changed))
+        );
+
+        let mut p = packet(&[0x07, 0x00, 0x01, 0x05]);
+        via.handle(&mut p, &mut keymap, &DEFAULT);
+        assert_eq!(via.settings().debounce, 5);
+
+        let mut p = packet(&[0x09, 0x00]);
+        assert_eq!(
+            via.handle(&mut p, &mut keymap, &DEFAULT),
+            Some(ViaEvent::SettingsSaved(via.settings()))
+        );
+
+        let mut p = packet(&[0x08, 0x03, 0x01]);
+        assert_eq!(via.handle(&mut p, &mut keymap, &DEFAULT), None);
+        assert_eq!(p[0], 0xff);
+    }
+
+    #[test]
+    fn layout_json() {
+        let mut via = ViaHandler::<2, 16>::new(0);
+        via.macros_mut()[..11].copy_from_slice(&[
+            b'h', b'"', 0x01, 0x02, 0xe1, 0x01, 0x04, b'5', b'0', b'|', 0,
+        ]);
+
+        let mut keymap = DEFAULT;
+        *keymap.action_mut(0, Coordinate::new(0, 1)).unwrap() =
+            Action::layer_tap(1, KeyCode::KSpaceBar);
+        *keymap.action_mut(1, Coordinate::new(0, 1)).unwrap() =
+            Action::mod_tap(KeyCode::KpRightShift, KeyCode::KEnter);
+
+        let json = std::format!("{}", via.layout("Pad \"2x2\"", 0x1234, 0x5678, &keymap));
+        assert_eq!(
+            json,
+            concat!(
+                r#"{"name":"Pad \"2x2\"","vendorProductId":305419896,"#,
+                r#""macros":["h\"{+KC_LSFT}{50}",""],"#,
+                r#""layers":[["KC_A","LT(1,KC_SPC)","KC_C","MO(1)"],"#,
+                r#"["KC_1","MT(MOD_RSFT,KC_ENT)","KC_3","MO(1)"]]}"#
+            )
+        );
+    }
+}
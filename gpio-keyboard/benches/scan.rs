@@ -0,0 +1,86 @@
+//! Scan loop benchmark of a full-size, 108-key matrix.
+//!
+//! Run with `cargo bench -p embedded-keymatrix`. Pins read a fixed level,
+//! so that the time measured is spent in the scan loop itself rather than
+//! in GPIO accesses.
+
+use std::convert::Infallible;
+use std::hint::black_box;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+use embedded_hal::digital::{ErrorType, InputPin, OutputPin};
+use embedded_keyboard::Keyboard;
+use embedded_keymatrix::KeyMatrix;
+
+const ROWS: usize = 6;
+const COLS: usize = 18;
+const SCANS: u32 = 100_000;
+const ROUNDS: usize = 10;
+
+/// Pin read like a GPIO register, which the compiler cannot assume to
+/// keep its level from one read to the next.
+struct Pin(AtomicBool);
+
+impl ErrorType for Pin {
+    type Error = Infallible;
+}
+
+impl OutputPin for Pin {
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+impl InputPin for Pin {
+    fn is_high(&mut self) -> Result<bool, Infallible> {
+        Ok(self.0.load(Ordering::Relaxed))
+    }
+
+    fn is_low(&mut self) -> Result<bool, Infallible> {
+        Ok(!self.0.load(Ordering::Relaxed))
+    }
+}
+
+fn matrix(pressed_row: Option<usize>) -> KeyMatrix<ROWS, COLS, 6, Pin, Pin> {
+    let cols = core::array::from_fn(|_| Pin(AtomicBool::new(false)));
+    let rows = core::array::from_fn(|y| Pin(AtomicBool::new(Some(y) == pressed_row)));
+
+    KeyMatrix::new(cols, rows)
+}
+
+fn bench(name: &str, mut matrix: KeyMatrix<ROWS, COLS, 6, Pin, Pin>, infallible: bool) {
+    // The best of several rounds, to filter out noise from the host.
+    let best = (0..ROUNDS)
+        .map(|_| {
+            let start = Instant::now();
+
+            for _ in 0..SCANS {
+                if infallible {
+                    black_box(matrix.scan_infallible());
+                } else {
+                    black_box(matrix.scan().ok());
+                }
+            }
+
+            start.elapsed()
+        })
+        .min()
+        .unwrap_or_default();
+
+    println!(
+        "{name:<26} {:>8.1} ns/scan",
+        best.as_secs_f64() * 1e9 / f64::from(SCANS)
+    );
+}
+
+fn main() {
+    bench("scan, idle", matrix(None), false);
+    bench("scan, row held", matrix(Some(0)), false);
+    bench("scan_infallible, idle", matrix(None), true);
+    bench("scan_infallible, row held", matrix(Some(0)), true);
+}
@@ -4,30 +4,172 @@
 //! the keyboard matrix.
 
 #![doc(html_root_url = "https://docs.rs/gpio-keyboard/latest")]
-#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(any(test, feature = "test-utils")), no_std)]
 
-use embedded_hal::digital::{InputPin, OutputPin};
-use embedded_keyboard::{Coordinate, Error, ErrorKind, ErrorType, KeyEvent, Keyboard};
+#[macro_use]
+mod fmt;
+
+use core::convert::Infallible;
+
+use embedded_hal::digital::{self, InputPin, OutputPin};
+pub use embedded_keyboard::debounce::{DebounceMode, KeyDebouncer};
+use embedded_keyboard::encoder::Direction;
+use embedded_keyboard::handoff::KeyState;
+use embedded_keyboard::hid::LedState;
+use embedded_keyboard::matrix::MatrixSnapshotSource;
+use embedded_keyboard::{
+    Coordinate, Error, ErrorKind, ErrorType, KeyEvent, Keyboard, KeyboardLeds,
+};
+
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
+
+#[cfg(feature = "npcx")]
+pub mod npcx;
 
 /// Result type alias
 pub type Result<T> = core::result::Result<T, KeyboardError>;
 
 /// Errors produced by this crate
-#[derive(Debug, PartialEq, Eq)]
+///
+/// Pin errors name the pin which failed, e.g. to track down a bad solder
+/// joint, along with the kind of the underlying [`digital::Error`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum KeyboardError {
-    /// Unable to set pin high
-    SetColumnHigh,
-
-    /// Unable to set pin low
-    SetColumnLow,
-
-    /// Unable to read row state
-    GetRow,
+    /// Unable to drive column `col` high
+    SetColumnHigh {
+        /// Index of the column
+        col: usize,
+        /// Kind of the pin error
+        kind: digital::ErrorKind,
+    },
+
+    /// Unable to drive column `col` low
+    SetColumnLow {
+        /// Index of the column
+        col: usize,
+        /// Kind of the pin error
+        kind: digital::ErrorKind,
+    },
+
+    /// Unable to write the column port of a [`PortMatrix`]
+    SetPort {
+        /// Kind of the port error
+        kind: digital::ErrorKind,
+    },
+
+    /// Unable to read the state of row `row`
+    GetRow {
+        /// Index of the row
+        row: usize,
+        /// Kind of the pin error
+        kind: digital::ErrorKind,
+    },
+
+    /// Unable to read the state of switch `pin` of [`DirectKeys`]
+    GetSwitch {
+        /// Index of the switch pin
+        pin: usize,
+        /// Kind of the pin error
+        kind: digital::ErrorKind,
+    },
+
+    /// Unable to drive the indicator LED pin `pin`: the Num Lock, Caps Lock
+    /// or Scroll Lock pin of [`GpioLeds`], or a row of [`SharedMatrix`]
+    SetLed {
+        /// Index of the LED pin
+        pin: usize,
+        /// Kind of the pin error
+        kind: digital::ErrorKind,
+    },
+
+    /// Unable to read pin A or B of a [`RotaryEncoder`]
+    GetEncoder {
+        /// Which of the encoder pins, 0 for A and 1 for B
+        pin: usize,
+        /// Kind of the pin error
+        kind: digital::ErrorKind,
+    },
+
+    /// Row `row` reads pressed with every column idle, so its pull does
+    /// not match the [`RowPull`] of the matrix, or the row is shorted
+    RowPull {
+        /// Index of the row
+        row: usize,
+    },
 
     /// Some other error occurred.
     Other,
 }
 
+impl KeyboardError {
+    fn set_column_high<E: digital::Error>(col: usize) -> impl FnOnce(E) -> Self {
+        move |err| Self::SetColumnHigh {
+            col,
+            kind: err.kind(),
+        }
+    }
+
+    fn set_column_low<E: digital::Error>(col: usize) -> impl FnOnce(E) -> Self {
+        move |err| Self::SetColumnLow {
+            col,
+            kind: err.kind(),
+        }
+    }
+
+    fn set_port<E: digital::Error>() -> impl FnOnce(E) -> Self {
+        |err| Self::SetPort { kind: err.kind() }
+    }
+
+    fn get_row<E: digital::Error>(row: usize) -> impl FnOnce(E) -> Self {
+        move |err| Self::GetRow {
+            row,
+            kind: err.kind(),
+        }
+    }
+
+    fn get_switch<E: digital::Error>(pin: usize) -> impl FnOnce(E) -> Self {
+        move |err| Self::GetSwitch {
+            pin,
+            kind: err.kind(),
+        }
+    }
+
+    fn set_led<E: digital::Error>(pin: usize) -> impl FnOnce(E) -> Self {
+        move |err| Self::SetLed {
+            pin,
+            kind: err.kind(),
+        }
+    }
+
+    fn get_encoder<E: digital::Error>(pin: usize) -> impl FnOnce(E) -> Self {
+        move |err| Self::GetEncoder {
+            pin,
+            kind: err.kind(),
+        }
+    }
+}
+
+impl core::fmt::Display for KeyboardError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::SetColumnHigh { col, kind } => {
+                write!(f, "unable to drive column {col} high: {kind}")
+            }
+            Self::SetColumnLow { col, kind } => {
+                write!(f, "unable to drive column {col} low: {kind}")
+            }
+            Self::SetPort { kind } => write!(f, "unable to write the column port: {kind}"),
+            Self::GetRow { row, kind } => write!(f, "unable to read row {row}: {kind}"),
+            Self::GetSwitch { pin, kind } => write!(f, "unable to read switch {pin}: {kind}"),
+            Self::SetLed { pin, kind } => write!(f, "unable to drive LED pin {pin}: {kind}"),
+            Self::GetEncoder { pin, kind } => write!(f, "unable to read encoder pin {pin}: {kind}"),
+            Self::RowPull { row } => write!(f, "row {row} does not idle at its pull level"),
+            Self::Other => write!(f, "some other error occurred"),
+        }
+    }
+}
+
 impl Error for KeyboardError {
     fn kind(&self) -> ErrorKind {
         ErrorKind::Other
@@ -44,8 +186,21 @@ pub struct KeyMatrix<
 > {
     rows: [I; ROWS],
     cols: [O; COLS],
-    keys: [[Key; ROWS]; COLS],
+    keys: [[KeyDebouncer; ROWS]; COLS],
+    masked: [[bool; ROWS]; COLS],
+    // Whether any key is masked, so that scans skip the mask otherwise.
+    masking: bool,
     report: [KeyEvent; NKRO],
+    // Events of the last scan, at the start of `report`.
+    reported: usize,
+    activity: bool,
+    debounce: i8,
+    press_debounce: i8,
+    mode: DebounceMode,
+    pull: RowPull,
+    two_key: bool,
+    warm_up: u8,
+    warming: u8,
 }
 
 impl<const ROWS: usize, const COLS: usize, const NKRO: usize, I: InputPin, O: OutputPin>
@@ -56,8 +211,250 @@ impl<const ROWS: usize, const COLS: usize, const NKRO: usize, I: InputPin, O: Ou
         Self {
             cols,
             rows,
-            keys: [[Key::new(); ROWS]; COLS],
+            keys: [[KeyDebouncer::new(); ROWS]; COLS],
+            masked: [[false; ROWS]; COLS],
+            masking: false,
             report: [KeyEvent::NoEvent; NKRO],
+            reported: 0,
+            activity: false,
+            debounce: KeyDebouncer::DEBOUNCE,
+            press_debounce: KeyDebouncer::DEBOUNCE,
+            mode: DebounceMode::Integrator,
+            pull: RowPull::PullDown,
+            two_key: false,
+            warm_up: 0,
+            warming: 0,
+        }
+    }
+
+    /// Whether the key at `coordinate` is masked, and never reported
+    /// pressed.
+    pub fn is_masked(&self, coordinate: Coordinate) -> bool {
+        self.masked
+            .get(coordinate.col())
+            .and_then(|keys| keys.get(coordinate.row()))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Number of consistent scans before a key is released, and pressed
+    /// unless changed with [`KeyMatrix::set_press_debounce`].
+    pub fn debounce(&self) -> u8 {
+        self.debounce.unsigned_abs()
+    }
+
+    /// Change the number of consistent scans before a key changes state,
+    /// e.g. from [`Settings::debounce`], both for presses and releases.
+    ///
+    /// The number is clamped to `1..=127`.
+    ///
+    /// [`Settings::debounce`]: embedded_keyboard::Settings::debounce
+    pub fn set_debounce(&mut self, scans: u8) {
+        self.debounce = i8::try_from(scans).unwrap_or(i8::MAX).max(1);
+        self.press_debounce = self.debounce;
+    }
+
+    /// Number of consistent scans before a key is pressed.
+    pub fn press_debounce(&self) -> u8 {
+        self.press_debounce.unsigned_abs()
+    }
+
+    /// Change the number of consistent scans before a key is pressed,
+    /// leaving releases to [`KeyMatrix::set_debounce`].
+    ///
+    /// Switches mostly chatter on release, so a fast press, e.g. on the
+    /// first scan, cuts latency while a slower release keeps filtering
+    /// the chatter. The number is clamped to `1..=127`.
+    pub fn set_press_debounce(&mut self, scans: u8) {
+        self.press_debounce = i8::try_from(scans).unwrap_or(i8::MAX).max(1);
+    }
+
+    /// How key samples are filtered.
+    pub fn debounce_mode(&self) -> DebounceMode {
+        self.mode
+    }
+
+    /// Change how key samples are filtered. The debounce scans count the
+    /// samples of the modes filtering them.
+    pub fn set_debounce_mode(&mut self, mode: DebounceMode) {
+        if mode == self.mode {
+            return;
+        }
+        self.mode = mode;
+
+        // Start every count over, where the new mode expects it.
+        for key in self.keys.iter_mut().flatten() {
+            key.restart(mode, self.debounce);
+        }
+    }
+
+    /// How the rows are pulled, and so which level columns are driven to.
+    /// Set with [`KeyMatrixBuilder::row_pull`].
+    pub fn row_pull(&self) -> RowPull {
+        self.pull
+    }
+
+    /// Whether at most two keys are reported pressed at once, see
+    /// [`KeyMatrix::set_two_key_rollover`].
+    pub fn two_key_rollover(&self) -> bool {
+        self.two_key
+    }
+
+    /// Enforce two-key rollover, for matrices without diodes.
+    ///
+    /// Without diodes, three keys pressed at corners of a rectangle also
+    /// close the circuit of the fourth corner, a phantom key which reads
+    /// exactly like a pressed one. With two-key rollover, new presses are
+    /// held back on every scan sensing more than two keys, counting keys
+    /// still being debounced, and reported once at most two are left.
+    /// Releases are always reported. A phantom key needs three keys held,
+    /// so it is never reported.
+    pub fn set_two_key_rollover(&mut self, enabled: bool) {
+        self.two_key = enabled;
+    }
+
+    /// Number of scans after [`KeyMatrix::pause`] or [`KeyMatrix::resume`]
+    /// during which only key releases are honored.
+    pub fn warm_up(&self) -> u8 {
+        self.warm_up
+    }
+
+    /// Change the number of warm-up scans.
+    ///
+    /// When scanning restarts, charge left on floating row lines can read
+    /// as pressed keys. Ignoring new presses for a few scans lets it drain
+    /// without reporting spurious key downs.
+    pub fn set_warm_up(&mut self, scans: u8) {
+        self.warm_up = scans;
+    }
+
+    /// Stop scanning, e.g. before going to sleep.
+    ///
+    /// All columns are driven idle, and the next scans are warm-up scans.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KeyboardError::SetColumnLow`] or
+    /// [`KeyboardError::SetColumnHigh`] if a column could not be driven.
+    pub fn pause(&mut self) -> Result<()> {
+        for (x, col) in self.cols.iter_mut().enumerate() {
+            Self::drive(col, x, false, self.pull)?;
+        }
+
+        self.resume();
+        Ok(())
+    }
+
+    /// Restart scanning after the matrix was left idle, e.g. on wake-up
+    /// from sleep. The next scans are warm-up scans.
+    pub fn resume(&mut self) {
+        self.warming = self.warm_up;
+    }
+
+    /// Prepare the matrix for deep sleep, returning the row pins to arm as
+    /// wake sources.
+    ///
+    /// All columns are driven active, so that pressing any key drives its
+    /// row to [`WakeConfig::level`]. Rows already at that level, because a
+    /// key is held, are left out, as they would wake the keyboard at once.
+    /// The next scans are warm-up scans.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KeyboardError::SetColumnHigh`] or
+    /// [`KeyboardError::SetColumnLow`] if a column could not be driven, or
+    /// [`KeyboardError::GetRow`] if a row could not be read.
+    pub fn arm_wake(&mut self) -> Result<WakeConfig<ROWS>> {
+        for (x, col) in self.cols.iter_mut().enumerate() {
+            Self::drive(col, x, true, self.pull)?;
+        }
+
+        let mut armed = [false; ROWS];
+        for (y, (armed, row)) in armed.iter_mut().zip(self.rows.iter_mut()).enumerate() {
+            *armed = !Self::sense(row, y, self.pull)?;
+        }
+
+        self.resume();
+        Ok(WakeConfig {
+            armed,
+            level: self.pull.level(),
+        })
+    }
+
+    /// Check on resume from deep sleep whether a key caused the wake-up,
+    /// returning the first key found pressed on an armed row, or `None` if
+    /// the wake-up was spurious.
+    ///
+    /// Columns are expected still driven active by [`KeyMatrix::arm_wake`],
+    /// and are all left idle.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KeyboardError::GetRow`] if a row could not be read, or
+    /// [`KeyboardError::SetColumnHigh`] or [`KeyboardError::SetColumnLow`]
+    /// if a column could not be driven.
+    pub fn validate_wake(&mut self, config: &WakeConfig<ROWS>) -> Result<Option<Coordinate>> {
+        let pull = self.pull;
+        let mut woken = [false; ROWS];
+        for (y, row) in self.rows.iter_mut().enumerate() {
+            woken[y] = config.armed[y] && Self::sense(row, y, pull)?;
+        }
+
+        for (x, col) in self.cols.iter_mut().enumerate() {
+            Self::drive(col, x, false, pull)?;
+        }
+
+        if !woken.contains(&true) {
+            return Ok(None);
+        }
+
+        for (x, col) in self.cols.iter_mut().enumerate() {
+            Self::drive(col, x, true, pull)?;
+
+            let mut found = None;
+            for (y, row) in self.rows.iter_mut().enumerate() {
+                if woken[y] && Self::sense(row, y, pull)? {
+                    found = Some(Coordinate::new(y, x));
+                    break;
+                }
+            }
+
+            Self::drive(col, x, false, pull)?;
+
+            if found.is_some() {
+                return Ok(found);
+            }
+        }
+
+        // The key was released before it could be located.
+        Ok(None)
+    }
+
+    /// Debounce state of every key, e.g. for a
+    /// [`Handoff`](embedded_keyboard::handoff::Handoff).
+    pub fn key_states(&self) -> [[KeyState; COLS]; ROWS] {
+        let mut states = [[KeyState::RELEASED; COLS]; ROWS];
+
+        for (x, keys) in self.keys.iter().enumerate() {
+            for (y, key) in keys.iter().enumerate() {
+                states[y][x] = key.state();
+            }
+        }
+
+        states
+    }
+
+    /// Restore the debounce state of every key, e.g. from a
+    /// [`Handoff`](embedded_keyboard::handoff::Handoff).
+    ///
+    /// Keys are restored released with their debounce counters, so that
+    /// keys still held are reported pressed by the first scan, and the
+    /// consumer of the events learns about them.
+    pub fn restore_key_states(&mut self, states: &[[KeyState; COLS]; ROWS]) {
+        for (x, keys) in self.keys.iter_mut().enumerate() {
+            for (y, key) in keys.iter_mut().enumerate() {
+                *key = KeyDebouncer::restore(states[y][x], self.press_debounce);
+            }
         }
     }
 
@@ -65,6 +462,168 @@ impl<const ROWS: usize, const COLS: usize, const NKRO: usize, I: InputPin, O: Ou
     pub fn destroy(self) -> ([O; COLS], [I; ROWS]) {
         (self.cols, self.rows)
     }
+
+    /// Drive column `x` active, or idle, for rows pulled as `pull`.
+    fn drive(col: &mut O, x: usize, active: bool, pull: RowPull) -> Result<()> {
+        if active == (pull == RowPull::PullDown) {
+            col.set_high().map_err(KeyboardError::set_column_high(x))
+        } else {
+            col.set_low().map_err(KeyboardError::set_column_low(x))
+        }
+    }
+
+    /// Whether row `y`, pulled as `pull`, reads a key pressed.
+    fn sense(row: &mut I, y: usize, pull: RowPull) -> Result<bool> {
+        let high = row.is_high().map_err(KeyboardError::get_row(y))?;
+        Ok(high == (pull == RowPull::PullDown))
+    }
+}
+
+/// Builder of a [`KeyMatrix`], keeping its configuration readable as
+/// options accumulate.
+///
+/// Options are sized by the matrix they configure, so that e.g. a mask of
+/// the wrong size does not compile.
+pub struct KeyMatrixBuilder<const ROWS: usize, const COLS: usize, I: InputPin, O: OutputPin> {
+    cols: [O; COLS],
+    rows: [I; ROWS],
+    debounce: Option<u8>,
+    press_debounce: Option<u8>,
+    mode: DebounceMode,
+    pull: RowPull,
+    two_key: bool,
+    warm_up: u8,
+    mask: [[bool; COLS]; ROWS],
+}
+
+impl<const ROWS: usize, const COLS: usize, I: InputPin, O: OutputPin>
+    KeyMatrixBuilder<ROWS, COLS, I, O>
+{
+    /// Start building a matrix with the given rows and columns, and the
+    /// defaults of [`KeyMatrix::new`].
+    pub fn new(cols: [O; COLS], rows: [I; ROWS]) -> Self {
+        Self {
+            cols,
+            rows,
+            debounce: None,
+            press_debounce: None,
+            mode: DebounceMode::Integrator,
+            pull: RowPull::PullDown,
+            two_key: false,
+            warm_up: 0,
+            mask: [[false; COLS]; ROWS],
+        }
+    }
+
+    /// Number of consistent scans before a key changes state, see
+    /// [`KeyMatrix::set_debounce`].
+    #[must_use]
+    pub fn debounce(mut self, scans: u8) -> Self {
+        self.debounce = Some(scans);
+        self
+    }
+
+    /// Number of consistent scans before a key is pressed, see
+    /// [`KeyMatrix::set_press_debounce`].
+    #[must_use]
+    pub fn press_debounce(mut self, scans: u8) -> Self {
+        self.press_debounce = Some(scans);
+        self
+    }
+
+    /// How key samples are filtered, see [`KeyMatrix::set_debounce_mode`].
+    #[must_use]
+    pub fn debounce_mode(mut self, mode: DebounceMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// How the rows are pulled, [`RowPull::PullDown`] by default.
+    ///
+    /// With [`RowPull::PullUp`], columns must be left high by the
+    /// application before the first scan, as they are between scans.
+    #[must_use]
+    pub fn row_pull(mut self, pull: RowPull) -> Self {
+        self.pull = pull;
+        self
+    }
+
+    /// Whether to enforce two-key rollover, see
+    /// [`KeyMatrix::set_two_key_rollover`].
+    #[must_use]
+    pub fn two_key_rollover(mut self, enabled: bool) -> Self {
+        self.two_key = enabled;
+        self
+    }
+
+    /// Number of warm-up scans, see [`KeyMatrix::set_warm_up`].
+    #[must_use]
+    pub fn warm_up(mut self, scans: u8) -> Self {
+        self.warm_up = scans;
+        self
+    }
+
+    /// Keys never reported pressed, indexed by row then column, e.g.
+    /// matrix positions without a switch, which could otherwise pick up
+    /// ghost presses.
+    #[must_use]
+    pub fn mask(mut self, mask: [[bool; COLS]; ROWS]) -> Self {
+        self.mask = mask;
+        self
+    }
+
+    /// Build the matrix, reporting up to `NKRO` events per scan.
+    pub fn build<const NKRO: usize>(self) -> KeyMatrix<ROWS, COLS, NKRO, I, O> {
+        let mut matrix = KeyMatrix::new(self.cols, self.rows);
+
+        if let Some(scans) = self.debounce {
+            matrix.set_debounce(scans);
+        }
+        if let Some(scans) = self.press_debounce {
+            matrix.set_press_debounce(scans);
+        }
+        matrix.set_debounce_mode(self.mode);
+        matrix.pull = self.pull;
+        matrix.set_two_key_rollover(self.two_key);
+        matrix.set_warm_up(self.warm_up);
+
+        for (y, mask) in self.mask.iter().enumerate() {
+            for (x, masked) in mask.iter().enumerate() {
+                matrix.masked[x][y] = *masked;
+                matrix.masking |= *masked;
+            }
+        }
+
+        matrix
+    }
+
+    /// Build the matrix like [`KeyMatrixBuilder::build`], checking that
+    /// the rows are pulled as configured.
+    ///
+    /// Every column is driven idle, to the level the rows are pulled to,
+    /// so every row must read released whatever the keys held. A row which
+    /// does not is pulled the other way, e.g. pull-up rows configured as
+    /// [`RowPull::PullDown`], and would read every key of the row pressed.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KeyboardError::RowPull`] naming the first row reading
+    /// pressed, or the error of a pin which could not be driven or read.
+    pub fn try_build<const NKRO: usize>(self) -> Result<KeyMatrix<ROWS, COLS, NKRO, I, O>> {
+        let mut matrix = self.build();
+        let pull = matrix.pull;
+
+        for (x, col) in matrix.cols.iter_mut().enumerate() {
+            KeyMatrix::<ROWS, COLS, NKRO, I, O>::drive(col, x, false, pull)?;
+        }
+        for (y, row) in matrix.rows.iter_mut().enumerate() {
+            if KeyMatrix::<ROWS, COLS, NKRO, I, O>::sense(row, y, pull)? {
+                return Err(KeyboardError::RowPull { row: y });
+            }
+        }
+
+        Ok(matrix)
+    }
 }
 
 impl<const ROWS: usize, const COLS: usize, const NKRO: usize, I: InputPin, O: OutputPin> ErrorType
@@ -81,221 +640,1085 @@ impl<const ROWS: usize, const COLS: usize, const NKRO: usize, I: InputPin, O: Ou
         // iterate over columns, enabling each along the way, then check the
         // state of each row by mapping each row to its current state.
 
-        for (x, col) in self.cols.iter_mut().enumerate() {
-            col.set_high().map_err(|_| KeyboardError::SetColumnHigh)?;
+        trace!("scan: start");
+        let mut sampling = self.sampling();
+        let pull = self.pull;
+
+        for (x, (col, keys)) in self.cols.iter_mut().zip(self.keys.iter_mut()).enumerate() {
+            Self::drive(col, x, true, pull)?;
 
             // check each row
-            for (y, row) in self.rows.iter_mut().enumerate() {
-                let key = self.keys.get_mut(x).unwrap().get_mut(y).unwrap();
-                let state = row.is_high().map_err(|_| KeyboardError::GetRow)?;
-                key.update(state);
+            for (y, (row, key)) in self.rows.iter_mut().zip(keys.iter_mut()).enumerate() {
+                sampling.sample(key, Self::sense(row, y, pull)?, || self.masked[x][y]);
             }
 
-            col.set_low().map_err(|_| KeyboardError::SetColumnLow)?;
+            Self::drive(col, x, false, pull)?;
         }
 
-        let mut i = 0;
-
-        for (x, _) in self.cols.iter().enumerate() {
-            for (y, _) in self.rows.iter().enumerate() {
-                let key = self.keys.get(x).unwrap().get(y).unwrap();
-                let event = if key.pressed {
-                    KeyEvent::KeyDown(Coordinate::new(x, y))
-                } else {
-                    KeyEvent::KeyUp(Coordinate::new(x, y))
-                };
-
-                if i >= NKRO {
-                    break;
-                }
+        Ok(self.finish(&sampling))
+    }
 
-                if !key.changed {
-                    continue;
-                }
+    /// Whether any key was sensed as pressed during the last scan.
+    fn activity(&self) -> bool {
+        self.activity
+    }
+}
 
-                self.report[i] = event;
-                i += 1;
+impl<const ROWS: usize, const COLS: usize, const NKRO: usize, I, O>
+    KeyMatrix<ROWS, COLS, NKRO, I, O>
+where
+    I: InputPin<Error = Infallible>,
+    O: OutputPin<Error = Infallible>,
+{
+    /// Scan the current state of the key matrix when neither the row nor the
+    /// column pins can fail.
+    ///
+    /// This is the same scan as [`Keyboard::scan`], minus the per-pin error
+    /// handling, which most on-chip GPIO implementations never need.
+    pub fn scan_infallible(&mut self) -> &[KeyEvent] {
+        trace!("scan: start");
+        let mut sampling = self.sampling();
+        let high = self.pull == RowPull::PullDown;
+
+        for (x, (col, keys)) in self.cols.iter_mut().zip(self.keys.iter_mut()).enumerate() {
+            infallible(if high { col.set_high() } else { col.set_low() });
+
+            for (y, (row, key)) in self.rows.iter_mut().zip(keys.iter_mut()).enumerate() {
+                sampling.sample(key, infallible(row.is_high()) == high, || self.masked[x][y]);
             }
+
+            infallible(if high { col.set_low() } else { col.set_high() });
         }
 
-        Ok(&self.report[..])
+        self.finish(&sampling)
     }
 }
 
-/// The latest state of all the keys
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct Key {
-    state: i8,
-    pressed: bool,
-    changed: bool,
-}
+impl<const ROWS: usize, const COLS: usize, const NKRO: usize, I: InputPin, O: OutputPin>
+    KeyMatrix<ROWS, COLS, NKRO, I, O>
+{
+    /// Debounce a snapshot of the matrix captured by hardware, e.g. by a
+    /// PIO state machine or a DMA transfer, instead of scanning the pins.
+    ///
+    /// Bit `y` of `levels[x]` is the level row `y` read while column `x`
+    /// was driven active, `1` being high. Rows beyond the 32nd read idle.
+    /// The snapshot goes through masking, warm-up and debouncing exactly
+    /// as a scan does.
+    pub fn scan_snapshot(&mut self, levels: &[u32; COLS]) -> &[KeyEvent] {
+        trace!("scan: snapshot");
+        let mut sampling = self.sampling();
+        let high = self.pull == RowPull::PullDown;
+
+        for (x, (level, keys)) in levels.iter().zip(self.keys.iter_mut()).enumerate() {
+            for (y, key) in keys.iter_mut().enumerate() {
+                let bit = u32::try_from(y).ok().and_then(|y| level.checked_shr(y));
+                let pressed = bit.is_some_and(|bit| (bit & 1 == 1) == high);
+                sampling.sample(key, pressed, || self.masked[x][y]);
+            }
+        }
 
-impl Default for Key {
-    fn default() -> Self {
-        Self {
-            state: 0,
-            pressed: false,
+        self.finish(&sampling)
+    }
+
+    /// Start sampling the keys for a scan.
+    fn sampling(&self) -> Sampling {
+        Sampling {
+            warming: self.warming,
+            masking: self.masking,
+            press: self.press_debounce,
+            release: self.debounce,
+            mode: self.mode,
+            activity: false,
             changed: false,
         }
     }
-}
 
-impl Key {
-    const MINIMUM: i8 = 0;
-    const MAXIMUM: i8 = 3;
+    /// End the scan of the keys `sampling` sampled, and report their
+    /// changes.
+    fn finish(&mut self, sampling: &Sampling) -> &[KeyEvent] {
+        let (activity, changed) = (sampling.activity, sampling.changed);
+        self.activity = activity;
+        trace!("scan: end, activity: {}, changed: {}", activity, changed);
+        self.collect(changed)
+    }
 
-    fn new() -> Self {
-        Self::default()
+    /// Whether more than two keys are pressed or being debounced, so that
+    /// some of them may be phantom keys of a matrix without diodes.
+    fn ambiguous(&self) -> bool {
+        let mut sensed = self.keys.iter().flatten().filter(|key| !key.is_idle());
+        sensed.nth(2).is_some()
     }
 
-    fn update(&mut self, sample: bool) -> bool {
-        let mut current = self.state;
-        current += if sample { 1 } else { -1 };
-        self.state = current.clamp(Key::MINIMUM, Key::MAXIMUM);
+    /// Gather the debounced state changes into the event report, if any
+    /// key `changed` during the scan.
+    fn collect(&mut self, changed: bool) -> &[KeyEvent] {
+        self.warming = self.warming.saturating_sub(1);
+        self.reported = 0;
 
-        let previous_pressed = self.pressed;
+        // Most scans change nothing: skip walking the whole matrix.
+        if !changed {
+            return &[];
+        }
 
-        self.pressed = if self.state == Key::MINIMUM {
-            false
-        } else if self.state == Key::MAXIMUM {
-            true
-        } else {
-            self.pressed
-        };
+        if self.two_key && self.ambiguous() {
+            for key in self.keys.iter_mut().flatten() {
+                key.hold_back();
+            }
+        }
 
-        self.changed = if self.pressed != previous_pressed {
-            true
-        } else {
-            false
-        };
+        'cols: for (x, keys) in self.keys.iter().enumerate() {
+            for (y, key) in keys.iter().enumerate() {
+                if !key.changed() {
+                    continue;
+                }
+
+                let Some(slot) = self.report.get_mut(self.reported) else {
+                    break 'cols;
+                };
+
+                *slot = if key.is_pressed() {
+                    KeyEvent::KeyDown(Coordinate::new(y, x))
+                } else {
+                    KeyEvent::KeyUp(Coordinate::new(y, x))
+                };
+                debug!("scan: {}", *slot);
+                self.reported += 1;
+            }
+        }
 
-        self.pressed
+        &self.report[..self.reported]
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use embedded_hal_mock::eh1::{
-        digital::{Mock, State, Transaction},
-        MockError,
-    };
-    use itertools::izip;
-    use std::io::ErrorKind;
+/// Keys sampled so far by a scan of a [`KeyMatrix`], however the samples
+/// are read: pins, a column port or a snapshot.
+struct Sampling {
+    warming: u8,
+    masking: bool,
+    press: i8,
+    release: i8,
+    mode: DebounceMode,
+    activity: bool,
+    changed: bool,
+}
 
-    #[test]
-    fn key_creation() {
-        let key = Key::default();
-        assert_eq!(
-            key,
-            Key {
-                state: 0,
-                pressed: false,
-                changed: false
-            }
-        );
+impl Sampling {
+    /// Debounce `key`, read `pressed`, unless it is `masked` or the matrix
+    /// is still warming up. The mask is only read if any key is masked.
+    #[inline]
+    fn sample(&mut self, key: &mut KeyDebouncer, pressed: bool, masked: impl FnOnce() -> bool) {
+        let state =
+            pressed && (self.warming == 0 || key.is_pressed()) && !(self.masking && masked());
+        self.activity |= state;
+        key.debounce(state, self.press, self.release, self.mode);
+        self.changed |= key.changed();
     }
+}
 
-    #[test]
-    fn update_state_once() {
-        let mut key = Key::default();
-        key.update(false);
-        assert_eq!(
-            key,
-            Key {
-                state: 0,
-                pressed: false,
-                changed: false
-            }
-        );
+/// Unwrap the result of an operation which cannot fail.
+#[inline]
+fn infallible<T>(result: core::result::Result<T, Infallible>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(never) => match never {},
+    }
+}
 
-        let mut key = Key::default();
-        key.update(true);
-        assert_eq!(
-            key,
-            Key {
-                state: 1,
-                pressed: false,
-                changed: false
-            }
-        );
+/// Row pins to arm as wake sources during deep sleep, from
+/// [`KeyMatrix::arm_wake`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WakeConfig<const ROWS: usize> {
+    armed: [bool; ROWS],
+    level: Polarity,
+}
+
+impl<const ROWS: usize> WakeConfig<ROWS> {
+    /// Level of an armed row when a key on it is pressed, following the
+    /// [`RowPull`] of the matrix.
+    #[must_use]
+    pub fn level(&self) -> Polarity {
+        self.level
     }
 
-    #[test]
-    fn state_never_goes_over_maximum() {
-        let mut key = Key::default();
+    /// Whether `row` is to be armed as a wake source.
+    #[must_use]
+    pub fn is_armed(&self, row: usize) -> bool {
+        self.armed.get(row).copied().unwrap_or_default()
+    }
 
-        for _ in 0..10 {
-            key.update(true);
-        }
+    /// Rows to arm as wake sources.
+    pub fn armed(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..ROWS).filter(|&row| self.armed[row])
+    }
 
-        assert_eq!(
-            key,
-            Key {
-                state: Key::MAXIMUM,
-                pressed: true,
-                changed: false
-            }
-        );
+    /// Rows left out because a key on them was held.
+    pub fn held(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..ROWS).filter(|&row| !self.armed[row])
     }
+}
 
-    #[test]
-    fn state_filters_through_integrator() {
-        let mut key = Key::default();
-        let input = [
-            false, false, false, true, true, false, true, false, false, true, true, false, true,
-            true, true, false, true, true, false, false, true, true, true, false, true, true, true,
-            true, true, false, false, false, true, false, true, false, false, true, true, false,
-            false, false, true, false, true, true, false, true, true, false, false, false, false,
-            true, true, false, false, false,
-        ];
-        let state = [
-            0, 0, 0, 1, 2, 1, 2, 1, 0, 1, 2, 1, 2, 3, 3, 2, 3, 3, 2, 1, 2, 3, 3, 2, 3, 3, 3, 3, 3,
-            2, 1, 0, 1, 0, 1, 0, 0, 1, 2, 1, 0, 0, 1, 0, 1, 2, 1, 2, 3, 2, 1, 0, 0, 1, 2, 1, 0, 0,
-        ];
-        let pressed = [
-            false, false, false, false, false, false, false, false, false, false, false, false,
-            false, true, true, true, true, true, true, true, true, true, true, true, true, true,
-            true, true, true, true, true, false, false, false, false, false, false, false, false,
-            false, false, false, false, false, false, false, false, false, true, true, true, false,
-            false, false, false, false, false, false,
-        ];
-        let changed = [
-            false, false, false, false, false, false, false, false, false, false, false, false,
-            false, true, false, false, false, false, false, false, false, false, false, false,
-            false, false, false, false, false, false, false, true, false, false, false, false,
-            false, false, false, false, false, false, false, false, false, false, false, false,
-            true, false, false, true, false, false, false, false, false, false,
-        ];
+/// How the rows of a [`KeyMatrix`] are pulled while no key connects them
+/// to an active column.
+///
+/// Columns idle at the level rows are pulled to, and the scanned column is
+/// driven to the other level, which a row then reads when a key between
+/// them is pressed. Driving columns to the level the rows are pulled to
+/// would never tell a key pressed, so the drive level follows from the
+/// pull rather than being set on its own, see
+/// [`KeyMatrixBuilder::try_build`] to check the pull of the rows.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RowPull {
+    /// Rows are pulled low: columns idle low and are scanned high
+    #[default]
+    PullDown,
+
+    /// Rows are pulled high: columns idle high and are scanned low
+    PullUp,
+}
 
-        for (i, s, p, c) in izip!(input.iter(), state.iter(), pressed.iter(), changed.iter()) {
-            key.update(*i);
-            assert_eq!(
-                key,
-                Key {
-                    state: *s,
-                    pressed: *p,
-                    changed: *c
-                }
-            )
+impl RowPull {
+    /// Level a scanned column is driven to, and so the level of a row when
+    /// a key on that column is pressed.
+    #[must_use]
+    pub const fn level(self) -> Polarity {
+        match self {
+            Self::PullDown => Polarity::ActiveHigh,
+            Self::PullUp => Polarity::ActiveLow,
         }
     }
+}
 
-    #[test]
-    fn create_keymatrix() {
-        let expectations = vec![];
+/// Level of an [`OutputPin`] lighting its LED, or of an [`InputPin`]
+/// whose switch is closed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Polarity {
+    /// The LED is lit when the pin is high
+    #[default]
+    ActiveHigh,
 
-        let cols = [Mock::new(&expectations), Mock::new(&expectations)];
-        let rows = [Mock::new(&expectations), Mock::new(&expectations)];
+    /// The LED is lit when the pin is low
+    ActiveLow,
+}
 
-        let matrix: KeyMatrix<2, 2, 6, _, _> = KeyMatrix::new(cols, rows);
-        let (cols, rows) = matrix.destroy();
+/// Num Lock, Caps Lock and Scroll Lock indicators, each driven by an
+/// optional [`OutputPin`].
+pub struct GpioLeds<O: OutputPin> {
+    num_lock: Option<O>,
+    caps_lock: Option<O>,
+    scroll_lock: Option<O>,
+    polarity: Polarity,
+}
 
-        for mut c in cols {
-            c.done();
+impl<O: OutputPin> GpioLeds<O> {
+    /// Create indicators from the pins of the LEDs present, lit at the
+    /// level given by `polarity`.
+    pub fn new(
+        num_lock: Option<O>,
+        caps_lock: Option<O>,
+        scroll_lock: Option<O>,
+        polarity: Polarity,
+    ) -> Self {
+        Self {
+            num_lock,
+            caps_lock,
+            scroll_lock,
+            polarity,
         }
+    }
 
-        for mut r in rows {
+    /// Destroys this instance and returns the Num Lock, Caps Lock and
+    /// Scroll Lock pins back to the caller.
+    pub fn destroy(self) -> (Option<O>, Option<O>, Option<O>) {
+        (self.num_lock, self.caps_lock, self.scroll_lock)
+    }
+}
+
+impl<O: OutputPin> ErrorType for GpioLeds<O> {
+    type Error = KeyboardError;
+}
+
+impl<O: OutputPin> KeyboardLeds for GpioLeds<O> {
+    /// Light the LEDs of the indicators set in `leds`, and turn off the
+    /// others.
+    fn set_leds(&mut self, leds: LedState) -> Result<()> {
+        let polarity = self.polarity;
+
+        for (i, (pin, lit)) in [
+            (&mut self.num_lock, leds.num_lock()),
+            (&mut self.caps_lock, leds.caps_lock()),
+            (&mut self.scroll_lock, leds.scroll_lock()),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let Some(pin) = pin else {
+                continue;
+            };
+
+            let high = lit == (polarity == Polarity::ActiveHigh);
+            let result = if high { pin.set_high() } else { pin.set_low() };
+            result.map_err(KeyboardError::set_led(i))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Key matrix whose pins also drive a matrix of single-color LEDs, as on
+/// keyboards multiplexing the backlight over the key matrix lines.
+///
+/// The LED of a key has its anode on the key's column and its cathode on
+/// the key's row, so it is lit while the column is high and the row is
+/// driven low. Rows are open-drain pins: released high they sense keys
+/// like the rows of a [`KeyMatrix`], and driven low they sink the current
+/// of the lit LEDs of a column.
+///
+/// Every [`Keyboard::scan`] is a time slice: the LEDs are blanked and the
+/// rows released, the keys are sensed exactly as a [`KeyMatrix`] does,
+/// then the LEDs of the next column are lit until the following scan. Key
+/// samples are only taken while the LEDs are dark, so driving them never
+/// reaches the debounce state. Each column is lit for one scan out of
+/// `COLS`, so the brightness depends on the scan rate being steady.
+pub struct SharedMatrix<const ROWS: usize, const COLS: usize, const NKRO: usize, R, O>
+where
+    R: InputPin + OutputPin,
+    O: OutputPin,
+{
+    matrix: KeyMatrix<ROWS, COLS, NKRO, R, O>,
+    leds: [[bool; COLS]; ROWS],
+    slice: usize,
+    lit: Option<usize>,
+}
+
+impl<const ROWS: usize, const COLS: usize, const NKRO: usize, R, O>
+    SharedMatrix<ROWS, COLS, NKRO, R, O>
+where
+    R: InputPin + OutputPin,
+    O: OutputPin,
+{
+    /// Instantiate a new matrix with the given rows and columns, and every
+    /// LED off.
+    pub fn new(cols: [O; COLS], rows: [R; ROWS]) -> Self {
+        Self {
+            matrix: KeyMatrix::new(cols, rows),
+            leds: [[false; COLS]; ROWS],
+            slice: 0,
+            lit: None,
+        }
+    }
+
+    /// The key matrix sensing keys, e.g. to change its debounce.
+    pub fn matrix_mut(&mut self) -> &mut KeyMatrix<ROWS, COLS, NKRO, R, O> {
+        &mut self.matrix
+    }
+
+    /// Whether the LED at `coordinate` is set to be lit.
+    pub fn led(&self, coordinate: Coordinate) -> bool {
+        self.leds
+            .get(coordinate.row())
+            .and_then(|row| row.get(coordinate.col()))
+            .copied()
+            .unwrap_or(false)
+    }
+
+    /// Light or turn off the LED at `coordinate`, from the next time its
+    /// column is lit. Coordinates outside the matrix are ignored.
+    pub fn set_led(&mut self, coordinate: Coordinate, on: bool) {
+        if let Some(led) = self
+            .leds
+            .get_mut(coordinate.row())
+            .and_then(|row| row.get_mut(coordinate.col()))
+        {
+            *led = on;
+        }
+    }
+
+    /// Set every LED at once.
+    pub fn set_leds(&mut self, leds: [[bool; COLS]; ROWS]) {
+        self.leds = leds;
+    }
+
+    /// Turn the LEDs dark and release the rows, e.g. before going to
+    /// sleep. They stay dark until the next scan.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KeyboardError::SetColumnLow`] if the lit column could not
+    /// be driven low, or [`KeyboardError::SetLed`] if a row could not be
+    /// released.
+    pub fn blank(&mut self) -> Result<()> {
+        if let Some(col) = self.lit.take() {
+            self.matrix.cols[col]
+                .set_low()
+                .map_err(KeyboardError::set_column_low(col))?;
+        }
+
+        for (y, row) in self.matrix.rows.iter_mut().enumerate() {
+            row.set_high().map_err(KeyboardError::set_led(y))?;
+        }
+
+        Ok(())
+    }
+
+    /// Destroys this instance and returns cols and rows arrays back to the caller.
+    pub fn destroy(self) -> ([O; COLS], [R; ROWS]) {
+        self.matrix.destroy()
+    }
+
+    /// Light the LEDs of the next column.
+    fn refresh(&mut self) -> Result<()> {
+        let Some(col) = self.slice.checked_rem(COLS) else {
+            return Ok(());
+        };
+        self.slice = (col + 1) % COLS;
+
+        for (y, (row, leds)) in self.matrix.rows.iter_mut().zip(&self.leds).enumerate() {
+            let result = if leds[col] {
+                row.set_low()
+            } else {
+                row.set_high()
+            };
+            result.map_err(KeyboardError::set_led(y))?;
+        }
+
+        self.matrix.cols[col]
+            .set_high()
+            .map_err(KeyboardError::set_column_high(col))?;
+        self.lit = Some(col);
+
+        Ok(())
+    }
+}
+
+impl<const ROWS: usize, const COLS: usize, const NKRO: usize, R, O> ErrorType
+    for SharedMatrix<ROWS, COLS, NKRO, R, O>
+where
+    R: InputPin + OutputPin,
+    O: OutputPin,
+{
+    type Error = KeyboardError;
+}
+
+impl<const ROWS: usize, const COLS: usize, const NKRO: usize, R, O> Keyboard
+    for SharedMatrix<ROWS, COLS, NKRO, R, O>
+where
+    R: InputPin + OutputPin,
+    O: OutputPin,
+{
+    /// Sense the keys with the LEDs dark, then light the next column.
+    fn scan(&mut self) -> Result<&[KeyEvent]> {
+        self.blank()?;
+        self.matrix.scan()?;
+        self.refresh()?;
+
+        Ok(&self.matrix.report[..self.matrix.reported])
+    }
+
+    /// Whether any key was sensed as pressed during the last scan.
+    fn activity(&self) -> bool {
+        self.matrix.activity()
+    }
+}
+
+/// GPIO port driving every column of a [`PortMatrix`] at once.
+///
+/// The columns are wired to pins of a single port, which one register
+/// write drives, e.g. the bit set/reset register of an STM32 port.
+/// Implementations sequencing the [`PortMatrix::patterns`] with a timer
+/// triggered DMA transfer can instead wait in [`ColumnPort::write`] until
+/// the transfer has driven `pattern`.
+pub trait ColumnPort {
+    /// Error type
+    type Error: digital::Error;
+
+    /// Drive the pins of `mask` to the levels of the matching bits of
+    /// `pattern`, leaving the other pins of the port alone.
+    ///
+    /// # Errors
+    ///
+    /// Returns the port's error if it could not be written.
+    fn write(&mut self, mask: u32, pattern: u32) -> core::result::Result<(), Self::Error>;
+}
+
+/// Column standing in for the column pins of a [`KeyMatrix`] whose
+/// columns are driven otherwise: by the [`ColumnPort`] of a
+/// [`PortMatrix`], or by hardware feeding [`KeyMatrix::scan_snapshot`].
+///
+/// Driving it does nothing.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PortColumn;
+
+impl digital::ErrorType for PortColumn {
+    type Error = Infallible;
+}
+
+impl OutputPin for PortColumn {
+    fn set_low(&mut self) -> core::result::Result<(), Infallible> {
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> core::result::Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+/// Row of a [`KeyMatrix`] sensed by hardware, standing in for the row
+/// pins of a matrix fed with [`KeyMatrix::scan_snapshot`], e.g. by a PIO
+/// state machine owning the actual pins.
+///
+/// Reading it fails, so that a matrix scanning it by mistake reports
+/// [`KeyboardError::GetRow`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SnapshotRow;
+
+impl digital::ErrorType for SnapshotRow {
+    type Error = digital::ErrorKind;
+}
+
+impl InputPin for SnapshotRow {
+    fn is_high(&mut self) -> core::result::Result<bool, digital::ErrorKind> {
+        Err(digital::ErrorKind::Other)
+    }
+
+    fn is_low(&mut self) -> core::result::Result<bool, digital::ErrorKind> {
+        Err(digital::ErrorKind::Other)
+    }
+}
+
+/// Key matrix whose columns are driven through a [`ColumnPort`] with
+/// whole-port writes.
+///
+/// Driving the column pins one at a time takes most of the scan time of
+/// wide matrices. Here selecting a column takes a single port write,
+/// which also releases the previous column. Keys are sensed and debounced
+/// by the wrapped [`KeyMatrix`], built with [`PortColumn`]s, so that it is
+/// configured as usual:
+///
+/// ```
+/// # use embedded_hal_mock::eh1::digital::Mock;
+/// use embedded_keymatrix::{ColumnPort, KeyMatrixBuilder, PortColumn, PortMatrix, RowPull};
+///
+/// struct Port;
+///
+/// impl ColumnPort for Port {
+///     type Error = core::convert::Infallible;
+///
+///     fn write(&mut self, mask: u32, pattern: u32) -> Result<(), Self::Error> {
+///         // e.g. `gpioa.bsrr.write(|w| w.bits(pattern & mask | (!pattern & mask) << 16))`
+///         Ok(())
+///     }
+/// }
+///
+/// # let rows = [Mock::new(&[]), Mock::new(&[])];
+/// let matrix = KeyMatrixBuilder::new([PortColumn; 3], rows)
+///     .row_pull(RowPull::PullUp)
+///     .build::<6>();
+///
+/// // Columns on pins 4, 5 and 8 of the port, selected low.
+/// let matrix = PortMatrix::new(matrix, Port, [4, 5, 8]);
+/// assert_eq!(matrix.patterns(), &[0x120, 0x110, 0x030]);
+/// # let (_, mut rows) = matrix.destroy();
+/// # rows.iter_mut().for_each(Mock::done);
+/// ```
+pub struct PortMatrix<const ROWS: usize, const COLS: usize, const NKRO: usize, I, P>
+where
+    I: InputPin,
+    P: ColumnPort,
+{
+    matrix: KeyMatrix<ROWS, COLS, NKRO, I, PortColumn>,
+    port: P,
+    mask: u32,
+    idle: u32,
+    patterns: [u32; COLS],
+}
+
+impl<const ROWS: usize, const COLS: usize, const NKRO: usize, I, P>
+    PortMatrix<ROWS, COLS, NKRO, I, P>
+where
+    I: InputPin,
+    P: ColumnPort,
+{
+    /// Drive the columns of `matrix` through `port`, column `x` being
+    /// wired to pin `bits[x]` of the port. Bits beyond 31 are ignored.
+    pub fn new(
+        matrix: KeyMatrix<ROWS, COLS, NKRO, I, PortColumn>,
+        port: P,
+        bits: [u8; COLS],
+    ) -> Self {
+        let bit = |b: u8| 1u32.checked_shl(u32::from(b)).unwrap_or(0);
+        let mask = bits.iter().fold(0, |mask, b| mask | bit(*b));
+        let idle = match matrix.pull {
+            RowPull::PullDown => 0,
+            RowPull::PullUp => mask,
+        };
+
+        Self {
+            matrix,
+            port,
+            mask,
+            idle,
+            patterns: bits.map(|b| idle ^ bit(b)),
+        }
+    }
+
+    /// Port pins driving the columns.
+    pub fn mask(&self) -> u32 {
+        self.mask
+    }
+
+    /// Port value selecting each column, e.g. for a DMA transfer to
+    /// sequence.
+    pub fn patterns(&self) -> &[u32; COLS] {
+        &self.patterns
+    }
+
+    /// Port value with every column idle.
+    pub fn idle(&self) -> u32 {
+        self.idle
+    }
+
+    /// The key matrix sensing keys, e.g. to change its debounce.
+    pub fn matrix_mut(&mut self) -> &mut KeyMatrix<ROWS, COLS, NKRO, I, PortColumn> {
+        &mut self.matrix
+    }
+
+    /// Stop scanning for a while, like [`KeyMatrix::pause`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KeyboardError::SetPort`] if the port could not be
+    /// written.
+    pub fn pause(&mut self) -> Result<()> {
+        self.port
+            .write(self.mask, self.idle)
+            .map_err(KeyboardError::set_port())?;
+
+        self.matrix.resume();
+        Ok(())
+    }
+
+    /// Destroys this instance and returns the port and rows back to the
+    /// caller.
+    pub fn destroy(self) -> (P, [I; ROWS]) {
+        (self.port, self.matrix.rows)
+    }
+}
+
+impl<const ROWS: usize, const COLS: usize, const NKRO: usize, I, P> ErrorType
+    for PortMatrix<ROWS, COLS, NKRO, I, P>
+where
+    I: InputPin,
+    P: ColumnPort,
+{
+    type Error = KeyboardError;
+}
+
+impl<const ROWS: usize, const COLS: usize, const NKRO: usize, I, P> Keyboard
+    for PortMatrix<ROWS, COLS, NKRO, I, P>
+where
+    I: InputPin,
+    P: ColumnPort,
+{
+    /// Scan the current state of the key matrix, one port write per
+    /// column.
+    fn scan(&mut self) -> Result<&[KeyEvent]> {
+        let matrix = &mut self.matrix;
+
+        trace!("scan: start");
+        let mut sampling = matrix.sampling();
+        let pull = matrix.pull;
+
+        for (x, (pattern, keys)) in self.patterns.iter().zip(matrix.keys.iter_mut()).enumerate() {
+            self.port
+                .write(self.mask, *pattern)
+                .map_err(KeyboardError::set_port())?;
+
+            for (y, (row, key)) in matrix.rows.iter_mut().zip(keys.iter_mut()).enumerate() {
+                let pressed = KeyMatrix::<ROWS, COLS, NKRO, I, PortColumn>::sense(row, y, pull)?;
+                sampling.sample(key, pressed, || matrix.masked[x][y]);
+            }
+        }
+
+        self.port
+            .write(self.mask, self.idle)
+            .map_err(KeyboardError::set_port())?;
+
+        Ok(matrix.finish(&sampling))
+    }
+
+    /// Whether any key was sensed as pressed during the last scan.
+    fn activity(&self) -> bool {
+        self.matrix.activity()
+    }
+}
+
+/// Key matrix scanned by hardware, e.g. the keyboard scan peripheral of
+/// an embedded controller, through a [`MatrixSnapshotSource`].
+///
+/// Each scan reads the latest snapshot of the source and, if there is a
+/// new one, debounces it with [`KeyMatrix::scan_snapshot`]. The wrapped
+/// [`KeyMatrix`], built with [`SnapshotRow`]s and [`PortColumn`]s, is
+/// configured as usual:
+///
+/// ```
+/// use embedded_keyboard::matrix::MatrixSnapshotSource;
+/// use embedded_keyboard::{Coordinate, ErrorType, KeyEvent, Keyboard};
+/// use embedded_keymatrix::{
+///     DebounceMode, KeyMatrixBuilder, PortColumn, RowPull, SnapshotMatrix, SnapshotRow,
+/// };
+///
+/// struct ScanPeripheral;
+///
+/// impl ErrorType for ScanPeripheral {
+///     type Error = core::convert::Infallible;
+/// }
+///
+/// impl MatrixSnapshotSource<3> for ScanPeripheral {
+///     fn read(&mut self, levels: &mut [u32; 3]) -> Result<bool, Self::Error> {
+///         // e.g. copy the scan buffer of the peripheral, row 1 low on column 2
+///         *levels = [0b11, 0b11, 0b01];
+///         Ok(true)
+///     }
+/// }
+///
+/// let matrix = KeyMatrixBuilder::new([PortColumn; 3], [SnapshotRow; 2])
+///     .row_pull(RowPull::PullUp)
+///     .debounce_mode(DebounceMode::Raw)
+///     .build::<1>();
+///
+/// let mut matrix = SnapshotMatrix::new(matrix, ScanPeripheral);
+/// assert_eq!(matrix.scan(), Ok(&[KeyEvent::KeyDown(Coordinate::new(1, 2))][..]));
+/// ```
+pub struct SnapshotMatrix<const ROWS: usize, const COLS: usize, const NKRO: usize, S>
+where
+    S: MatrixSnapshotSource<COLS>,
+{
+    matrix: KeyMatrix<ROWS, COLS, NKRO, SnapshotRow, PortColumn>,
+    source: S,
+    levels: [u32; COLS],
+}
+
+impl<const ROWS: usize, const COLS: usize, const NKRO: usize, S> SnapshotMatrix<ROWS, COLS, NKRO, S>
+where
+    S: MatrixSnapshotSource<COLS>,
+{
+    /// Debounce the snapshots of `source` with `matrix`.
+    pub fn new(matrix: KeyMatrix<ROWS, COLS, NKRO, SnapshotRow, PortColumn>, source: S) -> Self {
+        let idle = match matrix.pull {
+            RowPull::PullDown => 0,
+            RowPull::PullUp => u32::MAX,
+        };
+
+        Self {
+            matrix,
+            source,
+            levels: [idle; COLS],
+        }
+    }
+
+    /// Raw levels of the last snapshot read.
+    pub fn levels(&self) -> &[u32; COLS] {
+        &self.levels
+    }
+
+    /// The key matrix debouncing the snapshots, e.g. to change its
+    /// debounce.
+    pub fn matrix_mut(&mut self) -> &mut KeyMatrix<ROWS, COLS, NKRO, SnapshotRow, PortColumn> {
+        &mut self.matrix
+    }
+
+    /// The snapshot source, e.g. to configure the peripheral.
+    pub fn source_mut(&mut self) -> &mut S {
+        &mut self.source
+    }
+
+    /// Destroys this instance and returns the snapshot source back to the
+    /// caller.
+    pub fn destroy(self) -> S {
+        self.source
+    }
+}
+
+impl<const ROWS: usize, const COLS: usize, const NKRO: usize, S> ErrorType
+    for SnapshotMatrix<ROWS, COLS, NKRO, S>
+where
+    S: MatrixSnapshotSource<COLS>,
+{
+    type Error = S::Error;
+}
+
+impl<const ROWS: usize, const COLS: usize, const NKRO: usize, S> Keyboard
+    for SnapshotMatrix<ROWS, COLS, NKRO, S>
+where
+    S: MatrixSnapshotSource<COLS>,
+{
+    /// Debounce the latest snapshot of the source, if there is a new one.
+    /// Without one, no time passes for the debounce and there is no event.
+    fn scan(&mut self) -> core::result::Result<&[KeyEvent], S::Error> {
+        if !self.source.read(&mut self.levels)? {
+            return Ok(&[]);
+        }
+
+        Ok(self.matrix.scan_snapshot(&self.levels))
+    }
+
+    /// Whether any key was sensed as pressed in the last snapshot.
+    fn activity(&self) -> bool {
+        self.matrix.activity()
+    }
+}
+
+/// Switches wired straight to [`InputPin`]s rather than through the
+/// matrix, like the push switch of a rotary encoder.
+///
+/// Each pin is given a coordinate, typically outside of the key matrix,
+/// and is debounced like a matrix key. Merged with the matrix by a
+/// [`MergedKeyboard`](embedded_keyboard::merge::MergedKeyboard) placing
+/// both at row 0, column 0, the switches then take part in the keymap,
+/// hold-tap and everything else like any matrix key. Merged first, a
+/// change sampled in a scan the matrix fails is reported by the next one.
+pub struct DirectKeys<const N: usize, I: InputPin> {
+    pins: [I; N],
+    coordinates: [Coordinate; N],
+    polarity: Polarity,
+    keys: [KeyDebouncer; N],
+    report: [KeyEvent; N],
+    activity: bool,
+}
+
+impl<const N: usize, I: InputPin> DirectKeys<N, I> {
+    /// Instantiate switches on `pins`, reported at `coordinates`, closed
+    /// at the level given by `polarity`.
+    pub fn new(pins: [I; N], coordinates: [Coordinate; N], polarity: Polarity) -> Self {
+        Self {
+            pins,
+            coordinates,
+            polarity,
+            keys: [KeyDebouncer::new(); N],
+            report: [KeyEvent::NoEvent; N],
+            activity: false,
+        }
+    }
+
+    /// Destroys this instance and returns the pins back to the caller.
+    pub fn destroy(self) -> [I; N] {
+        self.pins
+    }
+
+    /// Debounce switch `i` sampled `high`, appending its change, if any,
+    /// to the `len` events of the report.
+    fn sample(&mut self, i: usize, high: bool, len: &mut usize) {
+        let closed = high == (self.polarity == Polarity::ActiveHigh);
+        let key = &mut self.keys[i];
+        self.activity |= closed;
+        key.update(closed, KeyDebouncer::DEBOUNCE);
+
+        if key.changed() {
+            self.report[*len] = if key.is_pressed() {
+                KeyEvent::KeyDown(self.coordinates[i])
+            } else {
+                KeyEvent::KeyUp(self.coordinates[i])
+            };
+            *len += 1;
+        }
+    }
+}
+
+impl<const N: usize, I: InputPin<Error = Infallible>> DirectKeys<N, I> {
+    /// Sample every switch when the pins cannot fail, like
+    /// [`KeyMatrix::scan_infallible`].
+    pub fn scan_infallible(&mut self) -> &[KeyEvent] {
+        self.activity = false;
+        let mut len = 0;
+
+        for i in 0..N {
+            let high = infallible(self.pins[i].is_high());
+            self.sample(i, high, &mut len);
+        }
+
+        &self.report[..len]
+    }
+}
+
+impl<const N: usize, I: InputPin> ErrorType for DirectKeys<N, I> {
+    type Error = KeyboardError;
+}
+
+impl<const N: usize, I: InputPin> Keyboard for DirectKeys<N, I> {
+    /// Sample every switch.
+    fn scan(&mut self) -> Result<&[KeyEvent]> {
+        self.activity = false;
+        let mut len = 0;
+
+        for i in 0..N {
+            let high = self.pins[i]
+                .is_high()
+                .map_err(KeyboardError::get_switch(i))?;
+            self.sample(i, high, &mut len);
+        }
+
+        Ok(&self.report[..len])
+    }
+
+    /// Whether any switch was sensed as closed during the last scan.
+    fn activity(&self) -> bool {
+        self.activity
+    }
+}
+
+/// Quadrature rotary encoder on two [`InputPin`]s.
+///
+/// Each pin is debounced on its own, then the Gray code of the two is
+/// decoded into pulses, and every `pulses` pulses in the same direction,
+/// one detent of the usual encoders, make a step. Transitions skipping a
+/// state, which bounce can still produce, are ignored.
+pub struct RotaryEncoder<A: InputPin, B: InputPin> {
+    a: A,
+    b: B,
+    levels: [Level; 2],
+    debounce: u8,
+    state: Option<u8>,
+    pulses: i8,
+    count: i8,
+}
+
+impl<A: InputPin, B: InputPin> RotaryEncoder<A, B> {
+    /// Pulse direction of each transition of the state `a << 1 | b`,
+    /// indexed by the previous state times 4 plus the next state.
+    const TRANSITIONS: [i8; 16] = [0, -1, 1, 0, 1, 0, 0, -1, -1, 0, 0, 1, 0, 1, -1, 0];
+
+    /// Instantiate an encoder on pins `a` and `b`, with 4 pulses per
+    /// detent and 2 consistent samples per change of a pin.
+    pub fn new(a: A, b: B) -> Self {
+        Self {
+            a,
+            b,
+            levels: [Level::default(); 2],
+            debounce: 2,
+            state: None,
+            pulses: 4,
+            count: 0,
+        }
+    }
+
+    /// Change the number of pulses per step, e.g. 2 or 1 for encoders
+    /// with fewer detents than pulses. The number is clamped to `1..=4`.
+    pub fn set_pulses(&mut self, pulses: u8) {
+        self.pulses = i8::try_from(pulses.clamp(1, 4)).unwrap_or(4);
+        self.count = 0;
+    }
+
+    /// Change the number of consistent samples before a pin changes
+    /// level. The number is clamped to at least 1.
+    pub fn set_debounce(&mut self, samples: u8) {
+        self.debounce = samples.max(1);
+    }
+
+    /// Sample the pins, returning the step completed by this sample, if
+    /// any. Meant to be called at least once per pulse, e.g. on every
+    /// scan or from a pin change interrupt.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`KeyboardError::GetEncoder`] if a pin could not be read.
+    pub fn poll(&mut self) -> Result<Option<Direction>> {
+        let a = self.a.is_high().map_err(KeyboardError::get_encoder(0))?;
+        let b = self.b.is_high().map_err(KeyboardError::get_encoder(1))?;
+
+        Ok(self.step(a, b))
+    }
+
+    /// Decode the levels `a` and `b` of the pins, returning the step they
+    /// complete, if any.
+    fn step(&mut self, a: bool, b: bool) -> Option<Direction> {
+        let a = self.levels[0].update(a, self.debounce);
+        let b = self.levels[1].update(b, self.debounce);
+        let next = u8::from(a) << 1 | u8::from(b);
+
+        let previous = self.state.replace(next)?;
+
+        self.count += Self::TRANSITIONS[usize::from(previous << 2 | next)];
+
+        let step = if self.count >= self.pulses {
+            Direction::Clockwise
+        } else if self.count <= -self.pulses {
+            Direction::CounterClockwise
+        } else {
+            return None;
+        };
+
+        self.count = 0;
+        Some(step)
+    }
+
+    /// Destroys this instance and returns the pins back to the caller.
+    pub fn destroy(self) -> (A, B) {
+        (self.a, self.b)
+    }
+}
+
+impl<A, B> RotaryEncoder<A, B>
+where
+    A: InputPin<Error = Infallible>,
+    B: InputPin<Error = Infallible>,
+{
+    /// Sample the pins when they cannot fail, like [`RotaryEncoder::poll`].
+    pub fn poll_infallible(&mut self) -> Option<Direction> {
+        let a = infallible(self.a.is_high());
+        let b = infallible(self.b.is_high());
+
+        self.step(a, b)
+    }
+}
+
+/// Debounced level of an encoder pin.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Level {
+    level: bool,
+    samples: u8,
+}
+
+impl Level {
+    fn update(&mut self, sample: bool, debounce: u8) -> bool {
+        if sample == self.level {
+            self.samples = 0;
+        } else {
+            self.samples += 1;
+            if self.samples >= debounce {
+                self.level = sample;
+                self.samples = 0;
+            }
+        }
+
+        self.level
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_hal_mock::eh1::{
+        digital::{Mock, State, Transaction},
+        MockError,
+    };
+    use std::io::ErrorKind;
+    use test_utils::ScanScript;
+
+    #[test]
+    fn create_keymatrix() {
+        let expectations = vec![];
+
+        let cols = [Mock::new(&expectations), Mock::new(&expectations)];
+        let rows = [Mock::new(&expectations), Mock::new(&expectations)];
+
+        let matrix: KeyMatrix<2, 2, 6, _, _> = KeyMatrix::new(cols, rows);
+        let (cols, rows) = matrix.destroy();
+
+        for mut c in cols {
+            c.done();
+        }
+
+        for mut r in rows {
             r.done();
         }
     }
@@ -370,6 +1793,7 @@ mod tests {
 
         let result = matrix.scan();
         assert!(result.is_ok());
+        assert!(matrix.activity());
 
         let (cols, rows) = matrix.destroy();
 
@@ -489,23 +1913,18 @@ mod tests {
 
         let mut matrix: KeyMatrix<2, 2, 6, _, _> = KeyMatrix::new(cols, rows);
 
-        for _ in 0..10 {
+        let mut events = Vec::new();
+        for _ in 0..11 {
             let result = matrix.scan();
             assert!(result.is_ok());
+            events.extend_from_slice(result.unwrap());
         }
 
-        let result = matrix.scan();
-        assert!(result.is_ok());
-        let report = result.unwrap();
         assert_eq!(
-            report,
-            &[
-                KeyEvent::KeyDown(Coordinate::new(1, 0)),
+            events,
+            [
+                KeyEvent::KeyDown(Coordinate::new(0, 1)),
                 KeyEvent::KeyDown(Coordinate::new(1, 1)),
-                KeyEvent::NoEvent,
-                KeyEvent::NoEvent,
-                KeyEvent::NoEvent,
-                KeyEvent::NoEvent
             ]
         );
 
@@ -531,7 +1950,13 @@ mod tests {
         let mut matrix: KeyMatrix<2, 2, 6, _, _> = KeyMatrix::new(cols, rows);
         let result = matrix.scan();
         assert!(result.is_err());
-        assert_eq!(result, Err(KeyboardError::SetColumnHigh));
+        assert_eq!(
+            result,
+            Err(KeyboardError::SetColumnHigh {
+                col: 0,
+                kind: digital::ErrorKind::Other
+            })
+        );
 
         let (cols, rows) = matrix.destroy();
 
@@ -564,7 +1989,13 @@ mod tests {
         let mut matrix: KeyMatrix<2, 2, 6, _, _> = KeyMatrix::new(cols, rows);
         let result = matrix.scan();
         assert!(result.is_err());
-        assert_eq!(result, Err(KeyboardError::GetRow));
+        assert_eq!(
+            result,
+            Err(KeyboardError::GetRow {
+                row: 0,
+                kind: digital::ErrorKind::Other
+            })
+        );
 
         let (cols, rows) = matrix.destroy();
 
@@ -602,7 +2033,13 @@ mod tests {
         let mut matrix: KeyMatrix<2, 2, 6, _, _> = KeyMatrix::new(cols, rows);
         let result = matrix.scan();
         assert!(result.is_err());
-        assert_eq!(result, Err(KeyboardError::SetColumnLow));
+        assert_eq!(
+            result,
+            Err(KeyboardError::SetColumnLow {
+                col: 0,
+                kind: digital::ErrorKind::Other
+            })
+        );
 
         let (cols, rows) = matrix.destroy();
 
@@ -614,4 +2051,837 @@ mod tests {
             r.done();
         }
     }
+
+    #[test]
+    fn error_names_failing_pin() {
+        let err = MockError::Io(ErrorKind::NotConnected);
+
+        let col_expectations = [Transaction::set(State::High), Transaction::set(State::Low)];
+        let cols = [
+            Mock::new(&col_expectations),
+            Mock::new(&[Transaction::set(State::High)]),
+        ];
+        let rows = [
+            Mock::new(&[Transaction::get(State::Low), Transaction::get(State::Low)]),
+            Mock::new(&[
+                Transaction::get(State::Low),
+                Transaction::get(State::Low).with_error(err),
+            ]),
+        ];
+
+        let mut matrix: KeyMatrix<2, 2, 6, _, _> = KeyMatrix::new(cols, rows);
+        let result = matrix.scan().map(<[KeyEvent]>::len);
+        let expected = KeyboardError::GetRow {
+            row: 1,
+            kind: digital::ErrorKind::Other,
+        };
+        assert_eq!(result, Err(expected));
+        assert_eq!(
+            expected.to_string(),
+            "unable to read row 1: A different error occurred. The original error may contain more information"
+        );
+
+        let (cols, rows) = matrix.destroy();
+
+        for mut c in cols {
+            c.done();
+        }
+
+        for mut r in rows {
+            r.done();
+        }
+    }
+
+    /// Pin which can never fail, like most on-chip GPIO.
+    struct FixedPin(bool);
+
+    impl embedded_hal::digital::ErrorType for FixedPin {
+        type Error = Infallible;
+    }
+
+    impl OutputPin for FixedPin {
+        fn set_low(&mut self) -> core::result::Result<(), Infallible> {
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> core::result::Result<(), Infallible> {
+            Ok(())
+        }
+    }
+
+    impl InputPin for FixedPin {
+        fn is_high(&mut self) -> core::result::Result<bool, Infallible> {
+            Ok(self.0)
+        }
+
+        fn is_low(&mut self) -> core::result::Result<bool, Infallible> {
+            Ok(!self.0)
+        }
+    }
+
+    #[test]
+    fn scan_infallible_keymatrix() {
+        let cols = [FixedPin(false), FixedPin(false)];
+        let rows = [FixedPin(true), FixedPin(false)];
+
+        let mut matrix: KeyMatrix<2, 2, 6, _, _> = KeyMatrix::new(cols, rows);
+        assert!(!matrix.activity());
+
+        for _ in 0..2 {
+            assert_eq!(matrix.scan_infallible(), []);
+            assert!(matrix.activity());
+        }
+
+        let report = matrix.scan_infallible();
+        assert_eq!(
+            report,
+            &[
+                KeyEvent::KeyDown(Coordinate::new(0, 0)),
+                KeyEvent::KeyDown(Coordinate::new(0, 1)),
+            ]
+        );
+
+        // Held keys do not report again.
+        assert_eq!(matrix.scan_infallible(), []);
+        assert!(matrix.activity());
+    }
+
+    #[test]
+    fn unchanged_scans_report_nothing() {
+        let mut matrix: KeyMatrix<1, 1, 6, _, _> =
+            KeyMatrixBuilder::new([FixedPin(false)], [FixedPin(true)])
+                .debounce(2)
+                .build();
+
+        let reports: Vec<_> = (0..5).map(|_| matrix.scan_infallible().to_vec()).collect();
+        assert_eq!(
+            reports,
+            [
+                vec![],
+                vec![KeyEvent::KeyDown(Coordinate::new(0, 0))],
+                vec![],
+                vec![],
+                vec![],
+            ]
+        );
+        assert!(matrix.activity());
+    }
+
+    #[test]
+    fn idle_keymatrix_reports_no_activity() {
+        let cols = [FixedPin(false), FixedPin(false)];
+        let rows = [FixedPin(false), FixedPin(false)];
+
+        let mut matrix: KeyMatrix<2, 2, 6, _, _> = KeyMatrix::new(cols, rows);
+
+        for _ in 0..3 {
+            matrix.scan_infallible();
+            assert!(!matrix.activity());
+        }
+    }
+
+    #[test]
+    fn wake_on_key() {
+        let cols = [
+            Mock::new(&[
+                Transaction::set(State::High),
+                Transaction::set(State::Low),
+                Transaction::set(State::High),
+                Transaction::set(State::Low),
+            ]),
+            Mock::new(&[
+                Transaction::set(State::High),
+                Transaction::set(State::Low),
+                Transaction::set(State::High),
+                Transaction::set(State::Low),
+            ]),
+        ];
+        let rows = [
+            // Held through sleep.
+            Mock::new(&[Transaction::get(State::High)]),
+            // Woke the keyboard from column 1.
+            Mock::new(&[
+                Transaction::get(State::Low),
+                Transaction::get(State::High),
+                Transaction::get(State::Low),
+                Transaction::get(State::High),
+            ]),
+        ];
+
+        let mut matrix: KeyMatrix<2, 2, 6, _, _> = KeyMatrix::new(cols, rows);
+        let config = matrix.arm_wake().unwrap();
+        assert_eq!(config.level(), Polarity::ActiveHigh);
+        assert!(config.armed().eq([1]));
+        assert!(config.held().eq([0]));
+        assert!(!config.is_armed(2));
+
+        assert_eq!(
+            matrix.validate_wake(&config),
+            Ok(Some(Coordinate::new(1, 1)))
+        );
+
+        let (cols, rows) = matrix.destroy();
+        for mut pin in cols.into_iter().chain(rows) {
+            pin.done();
+        }
+    }
+
+    #[test]
+    fn spurious_wake() {
+        let cols = [FixedPin(false), FixedPin(false)];
+        let rows = [FixedPin(false), FixedPin(false)];
+
+        let mut matrix: KeyMatrix<2, 2, 6, _, _> = KeyMatrix::new(cols, rows);
+        let config = matrix.arm_wake().unwrap();
+        assert!(config.armed().eq([0, 1]));
+        assert_eq!(matrix.validate_wake(&config), Ok(None));
+    }
+
+    #[test]
+    fn row_pull_up() {
+        let cols = [Mock::new(&[
+            // Idled by try_build, then scanned low.
+            Transaction::set(State::High),
+            Transaction::set(State::Low),
+            Transaction::set(State::High),
+        ])];
+        let rows = [
+            Mock::new(&[Transaction::get(State::High), Transaction::get(State::Low)]),
+            Mock::new(&[Transaction::get(State::High), Transaction::get(State::High)]),
+        ];
+
+        let mut matrix: KeyMatrix<2, 1, 6, _, _> = KeyMatrixBuilder::new(cols, rows)
+            .row_pull(RowPull::PullUp)
+            .debounce(1)
+            .try_build()
+            .unwrap();
+        assert_eq!(matrix.row_pull(), RowPull::PullUp);
+        assert_eq!(RowPull::PullUp.level(), Polarity::ActiveLow);
+        assert_eq!(
+            matrix.scan().unwrap(),
+            [KeyEvent::KeyDown(Coordinate::new(0, 0))]
+        );
+
+        let (cols, rows) = matrix.destroy();
+        for mut pin in cols.into_iter().chain(rows) {
+            pin.done();
+        }
+    }
+
+    #[test]
+    fn row_pull_mismatch() {
+        // Rows pulled high, configured as pulled down.
+        let cols = [FixedPin(false)];
+        let rows = [FixedPin(false), FixedPin(true)];
+
+        let result = KeyMatrixBuilder::new(cols, rows).try_build::<6>();
+        assert_eq!(result.err(), Some(KeyboardError::RowPull { row: 1 }));
+        assert_eq!(
+            KeyboardError::RowPull { row: 1 }.to_string(),
+            "row 1 does not idle at its pull level"
+        );
+    }
+
+    #[test]
+    fn two_key_rollover() {
+        let (a, b, c) = (
+            Coordinate::new(0, 0),
+            Coordinate::new(0, 1),
+            Coordinate::new(1, 0),
+        );
+        // Pressing a, b and c also closes the phantom key at row 1,
+        // column 1.
+        let script = ScanScript::<2, 2>::new()
+            .press(a)
+            .press(b)
+            .scans(1)
+            .push([[true, true], [true, true]])
+            .release(a)
+            .release(Coordinate::new(1, 1))
+            .scans(1);
+        let (cols, rows) = script.pins();
+
+        let mut matrix: KeyMatrix<2, 2, 4, _, _> = KeyMatrixBuilder::new(cols, rows)
+            .debounce(1)
+            .two_key_rollover(true)
+            .build();
+        assert!(matrix.two_key_rollover());
+        assert_eq!(
+            matrix.scan().unwrap()[..2],
+            [KeyEvent::KeyDown(a), KeyEvent::KeyDown(b)]
+        );
+        matrix.scan().unwrap();
+        let states = matrix.key_states();
+        assert!(states[0][0].pressed() && states[0][1].pressed());
+        assert!(!states[1][0].pressed() && !states[1][1].pressed());
+
+        // Once a is released, c is reported, the phantom key never is.
+        assert_eq!(
+            matrix.scan().unwrap()[..2],
+            [KeyEvent::KeyUp(a), KeyEvent::KeyDown(c)]
+        );
+        test_utils::done(matrix.destroy());
+    }
+
+    #[test]
+    fn builder_configures_matrix() {
+        let cols = [FixedPin(false), FixedPin(false)];
+        let rows = [FixedPin(true), FixedPin(false)];
+
+        let mut matrix: KeyMatrix<2, 2, 4, _, _> = KeyMatrixBuilder::new(cols, rows)
+            .debounce(1)
+            .warm_up(2)
+            .mask([[false, true], [false, false]])
+            .build();
+        assert_eq!(matrix.debounce(), 1);
+        assert_eq!(matrix.warm_up(), 2);
+        assert!(matrix.is_masked(Coordinate::new(0, 1)));
+        assert!(!matrix.is_masked(Coordinate::new(1, 1)));
+        assert!(!matrix.is_masked(Coordinate::new(2, 0)));
+
+        let report = matrix.scan_infallible();
+        assert_eq!(report, [KeyEvent::KeyDown(Coordinate::new(0, 0))]);
+    }
+
+    #[test]
+    fn warm_up_only_honors_releases() {
+        let cols = [FixedPin(false)];
+        let rows = [FixedPin(true), FixedPin(false)];
+
+        let mut matrix: KeyMatrix<2, 1, 6, _, _> = KeyMatrix::new(cols, rows);
+        matrix.set_warm_up(4);
+        assert_eq!(matrix.warm_up(), 4);
+        assert!(matrix.pause().is_ok());
+
+        for _ in 0..4 {
+            matrix.scan_infallible();
+            assert!(!matrix.activity());
+        }
+        assert!(!matrix.keys[0][0].is_pressed());
+
+        for _ in 0..3 {
+            matrix.scan_infallible();
+            assert!(matrix.activity());
+        }
+        assert!(matrix.keys[0][0].is_pressed());
+
+        // Keys already held stay held through the warm-up.
+        matrix.resume();
+        for _ in 0..4 {
+            matrix.scan_infallible();
+            assert!(matrix.activity());
+        }
+        assert!(matrix.keys[0][0].is_pressed());
+    }
+
+    #[test]
+    fn raw_scans_follow_bounce() {
+        let key = Coordinate::new(0, 0);
+        let script = ScanScript::<1, 1>::new().bounce(key, &[true, false, true, true, false]);
+        let (cols, rows) = script.pins();
+
+        let mut matrix: KeyMatrix<1, 1, 1, _, _> = KeyMatrixBuilder::new(cols, rows)
+            .debounce_mode(DebounceMode::Raw)
+            .build();
+        assert_eq!(matrix.scan().unwrap(), [KeyEvent::KeyDown(key)]);
+
+        let pressed: Vec<bool> = (1..script.len())
+            .map(|_| {
+                matrix.scan().unwrap();
+                matrix.key_states()[0][0].pressed()
+            })
+            .collect();
+        assert_eq!(pressed, [false, true, true, false]);
+        test_utils::done(matrix.destroy());
+    }
+
+    #[test]
+    fn switch_debounce_mode() {
+        let cols = [FixedPin(false)];
+        let rows = [FixedPin(true)];
+
+        let mut matrix: KeyMatrix<1, 1, 6, _, _> = KeyMatrixBuilder::new(cols, rows)
+            .debounce(2)
+            .debounce_mode(DebounceMode::Consecutive)
+            .build();
+        assert_eq!(matrix.debounce_mode(), DebounceMode::Consecutive);
+        assert_eq!(matrix.scan_infallible(), []);
+        assert_eq!(
+            matrix.scan_infallible(),
+            [KeyEvent::KeyDown(Coordinate::new(0, 0))]
+        );
+
+        // The held key keeps its state, its count set for the integrator.
+        matrix.set_debounce_mode(DebounceMode::Integrator);
+        assert_eq!(matrix.keys[0][0].state().counter(), 2);
+        assert!(matrix.keys[0][0].is_pressed());
+    }
+
+    #[test]
+    fn configurable_debounce() {
+        let cols = [FixedPin(false)];
+        let rows = [FixedPin(true)];
+
+        let mut matrix: KeyMatrix<1, 1, 6, _, _> = KeyMatrix::new(cols, rows);
+        assert_eq!(matrix.debounce(), 3);
+        matrix.set_debounce(0);
+        assert_eq!(matrix.debounce(), 1);
+        matrix.set_debounce(200);
+        assert_eq!(matrix.debounce(), 127);
+
+        matrix.set_debounce(1);
+        assert_eq!(
+            matrix.scan_infallible()[0],
+            KeyEvent::KeyDown(Coordinate::new(0, 0))
+        );
+
+        matrix.set_press_debounce(2);
+        assert_eq!((matrix.press_debounce(), matrix.debounce()), (2, 1));
+        matrix.set_debounce(5);
+        assert_eq!(matrix.press_debounce(), 5);
+    }
+
+    #[test]
+    fn restore_key_states() {
+        let cols = [FixedPin(false)];
+        let rows = [FixedPin(true), FixedPin(false)];
+
+        let mut matrix: KeyMatrix<2, 1, 2, _, _> = KeyMatrix::new(cols, rows);
+        for _ in 0..3 {
+            matrix.scan_infallible();
+        }
+        let states = matrix.key_states();
+        assert_eq!(states, [[KeyState::new(3, true)], [KeyState::RELEASED]]);
+
+        let (cols, rows) = matrix.destroy();
+        let mut matrix: KeyMatrix<2, 1, 2, _, _> = KeyMatrix::new(cols, rows);
+        matrix.restore_key_states(&states);
+
+        // Held across the handoff, reported again without debouncing anew.
+        assert_eq!(
+            matrix.scan_infallible()[0],
+            KeyEvent::KeyDown(Coordinate::new(0, 0))
+        );
+    }
+
+    #[test]
+    fn gpio_leds_follow_polarity() {
+        let num_lock = Mock::new(&[Transaction::set(State::Low), Transaction::set(State::High)]);
+        let caps_lock = Mock::new(&[Transaction::set(State::High), Transaction::set(State::Low)]);
+
+        let mut leds = GpioLeds::new(Some(num_lock), Some(caps_lock), None, Polarity::ActiveLow);
+        assert_eq!(
+            leds.set_leds(LedState::from_bits(LedState::NUM_LOCK)),
+            Ok(())
+        );
+        assert_eq!(
+            leds.set_leds(LedState::from_bits(LedState::CAPS_LOCK)),
+            Ok(())
+        );
+
+        let (num_lock, caps_lock, scroll_lock) = leds.destroy();
+        assert!(scroll_lock.is_none());
+        for mut pin in [num_lock, caps_lock].into_iter().flatten() {
+            pin.done();
+        }
+    }
+
+    #[test]
+    fn shared_matrix_time_slices() {
+        let col = Mock::new(&[
+            // Sensing
+            Transaction::set(State::High),
+            Transaction::set(State::Low),
+            // Lighting
+            Transaction::set(State::High),
+            // Blanking
+            Transaction::set(State::Low),
+        ]);
+        let row = Mock::new(&[
+            // Released for sensing
+            Transaction::set(State::High),
+            Transaction::get(State::High),
+            // Sinking the lit LED
+            Transaction::set(State::Low),
+            // Released again
+            Transaction::set(State::High),
+        ]);
+
+        let mut matrix: SharedMatrix<1, 1, 1, _, _> = SharedMatrix::new([col], [row]);
+        matrix.set_led(Coordinate::new(0, 0), true);
+        assert!(matrix.led(Coordinate::new(0, 0)));
+        assert!(matrix.scan().is_ok());
+        assert!(matrix.activity());
+        assert_eq!(matrix.blank(), Ok(()));
+
+        let (cols, rows) = matrix.destroy();
+        for mut pin in cols.into_iter().chain(rows) {
+            pin.done();
+        }
+    }
+
+    #[test]
+    fn shared_matrix_keeps_debounce() {
+        let cols = [FixedPin(false), FixedPin(false)];
+        let rows = [FixedPin(true)];
+
+        let mut matrix: SharedMatrix<1, 2, 2, _, _> = SharedMatrix::new(cols, rows);
+        matrix.set_leds([[true, false]]);
+
+        // LED slices in between do not count as samples.
+        for _ in 0..2 {
+            assert!(matrix
+                .scan()
+                .unwrap()
+                .iter()
+                .all(|e| *e == KeyEvent::NoEvent));
+        }
+        assert_eq!(
+            matrix.scan().unwrap(),
+            [
+                KeyEvent::KeyDown(Coordinate::new(0, 0)),
+                KeyEvent::KeyDown(Coordinate::new(0, 1))
+            ]
+        );
+        assert!(!matrix.led(Coordinate::new(1, 0)));
+    }
+
+    #[test]
+    fn snapshot_scans() {
+        let mut matrix: KeyMatrix<3, 2, 2, _, _> =
+            KeyMatrixBuilder::new([PortColumn; 2], [SnapshotRow; 3])
+                .debounce_mode(DebounceMode::Raw)
+                .mask([[false, false], [false, false], [false, true]])
+                .build();
+
+        // Row 1 pressed on column 0, row 2 on the masked column 1, and
+        // bits of pins beyond the rows ignored.
+        assert_eq!(
+            matrix.scan_snapshot(&[0b1010, 0b100 | 1 << 31]),
+            [KeyEvent::KeyDown(Coordinate::new(1, 0))]
+        );
+        assert!(matrix.activity());
+        assert_eq!(
+            matrix.scan(),
+            Err(KeyboardError::GetRow {
+                row: 0,
+                kind: digital::ErrorKind::Other
+            })
+        );
+
+        let mut matrix: KeyMatrix<1, 1, 1, _, _> =
+            KeyMatrixBuilder::new([PortColumn], [SnapshotRow])
+                .row_pull(RowPull::PullUp)
+                .debounce_mode(DebounceMode::Raw)
+                .build();
+        assert_eq!(
+            matrix.scan_snapshot(&[0]),
+            [KeyEvent::KeyDown(Coordinate::new(0, 0))]
+        );
+    }
+
+    #[test]
+    fn replay_debounces_like_snapshots() {
+        use embedded_keyboard::matrix::MatrixState;
+        use embedded_keyboard::replay::{Replay, ReplayOutput};
+        use embedded_keyboard::{engine::Engine, keymap, Keymap};
+
+        const KEYMAP: Keymap<1, 1, 2> = keymap! { { [KA KB] } };
+
+        // Snapshots recorded every millisecond, both keys bouncing as
+        // they are pressed and released.
+        let trace: [(u32, [u32; 2]); 14] = [
+            (0, [1, 0]),
+            (1, [0, 1]),
+            (2, [1, 1]),
+            (3, [1, 0]),
+            (4, [1, 1]),
+            (5, [1, 1]),
+            (6, [1, 1]),
+            (7, [0, 1]),
+            (8, [1, 1]),
+            (9, [0, 0]),
+            (10, [0, 1]),
+            (11, [0, 0]),
+            (12, [0, 0]),
+            (13, [0, 0]),
+        ];
+
+        let mut matrix: KeyMatrix<1, 2, 2, _, _> =
+            KeyMatrixBuilder::new([PortColumn; 2], [SnapshotRow]).build();
+        let mut scanned = Vec::new();
+        for (time, levels) in &trace {
+            let events = matrix.scan_snapshot(levels);
+            scanned.extend(events.iter().map(|event| (*time, *event)));
+        }
+
+        let mut replay = Replay::new(Engine::new(KEYMAP));
+        let mut replayed = Vec::new();
+        for (time, levels) in &trace {
+            let mut state = MatrixState::<1, 2>::new();
+            for (col, level) in levels.iter().enumerate() {
+                state.set(Coordinate::new(0, col), *level == 1);
+            }
+            replay.snapshot(*time, &state, |output| {
+                if let ReplayOutput::Event(time, event) = output {
+                    replayed.push((time, event));
+                }
+            });
+        }
+
+        assert_eq!(scanned.len(), 4);
+        assert_eq!(replayed, scanned);
+    }
+
+    /// Column port recording its writes.
+    #[derive(Default)]
+    struct RecordingPort(Vec<(u32, u32)>);
+
+    impl ColumnPort for RecordingPort {
+        type Error = Infallible;
+
+        fn write(&mut self, mask: u32, pattern: u32) -> core::result::Result<(), Infallible> {
+            self.0.push((mask, pattern));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn port_matrix_writes_whole_port() {
+        let rows = [
+            Mock::new(&[Transaction::get(State::Low), Transaction::get(State::Low)]),
+            Mock::new(&[Transaction::get(State::High), Transaction::get(State::Low)]),
+        ];
+        let matrix = KeyMatrixBuilder::new([PortColumn; 2], rows)
+            .debounce_mode(DebounceMode::Raw)
+            .build::<2>();
+        let mut matrix = PortMatrix::new(matrix, RecordingPort::default(), [3, 5]);
+
+        assert_eq!((matrix.mask(), matrix.idle()), (0x28, 0));
+        assert_eq!(
+            matrix.scan().unwrap(),
+            [KeyEvent::KeyDown(Coordinate::new(1, 0))]
+        );
+        assert!(matrix.activity());
+
+        let (port, mut rows) = matrix.destroy();
+        assert_eq!(port.0, [(0x28, 0x08), (0x28, 0x20), (0x28, 0)]);
+        rows.iter_mut().for_each(Mock::done);
+    }
+
+    #[test]
+    fn port_matrix_pulled_up() {
+        let rows = [Mock::new(&[])];
+        let matrix = KeyMatrixBuilder::new([PortColumn; 3], rows)
+            .row_pull(RowPull::PullUp)
+            .build::<2>();
+        let mut matrix = PortMatrix::new(matrix, RecordingPort::default(), [0, 1, 40]);
+
+        // Out of range bits drive nothing.
+        assert_eq!(matrix.patterns(), &[0x02, 0x01, 0x03]);
+        matrix.pause().unwrap();
+
+        let (port, mut rows) = matrix.destroy();
+        assert_eq!(port.0, [(0x03, 0x03)]);
+        rows.iter_mut().for_each(Mock::done);
+    }
+
+    /// Snapshot source replaying a script of snapshots, `None` standing
+    /// for no new snapshot.
+    struct ScriptedSource(Vec<Option<[u32; 2]>>);
+
+    impl ErrorType for ScriptedSource {
+        type Error = KeyboardError;
+    }
+
+    impl MatrixSnapshotSource<2> for ScriptedSource {
+        fn read(&mut self, levels: &mut [u32; 2]) -> core::result::Result<bool, KeyboardError> {
+            match self.0.remove(0) {
+                Some(snapshot) => {
+                    *levels = snapshot;
+                    Ok(true)
+                }
+                None => Ok(false),
+            }
+        }
+    }
+
+    #[test]
+    fn snapshot_matrix_debounces_source() {
+        let matrix = KeyMatrixBuilder::new([PortColumn; 2], [SnapshotRow; 2])
+            .debounce(2)
+            .build::<2>();
+        let source = ScriptedSource(vec![Some([0b10, 0]), None, Some([0b10, 0]), Some([0, 0])]);
+        let mut matrix = SnapshotMatrix::new(matrix, source);
+        assert_eq!(matrix.levels(), &[0, 0]);
+
+        // Missing snapshots do not count towards the debounce.
+        assert_eq!(matrix.scan().unwrap(), []);
+        assert!(matrix.activity());
+        assert_eq!(matrix.scan().unwrap(), []);
+        assert_eq!(
+            matrix.scan().unwrap(),
+            [KeyEvent::KeyDown(Coordinate::new(1, 0))]
+        );
+        assert_eq!(matrix.levels(), &[0b10, 0]);
+
+        matrix.scan().unwrap();
+        assert!(!matrix.activity());
+        assert!(matrix.destroy().0.is_empty());
+    }
+
+    #[test]
+    fn rotary_encoder_steps() {
+        let levels = |levels: &[(bool, bool)]| {
+            let state = |high| if high { State::High } else { State::Low };
+            let a: Vec<_> = levels
+                .iter()
+                .map(|(a, _)| Transaction::get(state(*a)))
+                .collect();
+            let b: Vec<_> = levels
+                .iter()
+                .map(|(_, b)| Transaction::get(state(*b)))
+                .collect();
+            (Mock::new(&a), Mock::new(&b))
+        };
+
+        // One detent clockwise, with A bouncing on its first edge, then
+        // half a detent back.
+        let (a, b) = levels(&[
+            (false, false),
+            (true, false),
+            (false, false),
+            (true, false),
+            (true, false),
+            (true, true),
+            (false, true),
+            (false, false),
+            (false, true),
+            (true, true),
+        ]);
+
+        let mut encoder = RotaryEncoder::new(a, b);
+        encoder.set_debounce(1);
+        let mut steps = Vec::new();
+        for _ in 0..10 {
+            steps.push(encoder.poll().unwrap());
+        }
+        assert_eq!(
+            steps.iter().flatten().collect::<Vec<_>>(),
+            [&Direction::Clockwise]
+        );
+        assert_eq!(steps[7], Some(Direction::Clockwise));
+
+        let (mut a, mut b) = encoder.destroy();
+        a.done();
+        b.done();
+    }
+
+    #[test]
+    fn rotary_encoder_debounce() {
+        let mut level = Level::default();
+        assert!(!level.update(true, 2));
+        assert!(!level.update(false, 2));
+        assert!(!level.update(true, 2));
+        assert!(level.update(true, 2));
+
+        let (a, b) = (FixedPin(true), FixedPin(false));
+        let mut encoder = RotaryEncoder::new(a, b);
+        encoder.set_pulses(9);
+        assert_eq!(encoder.pulses, 4);
+        assert_eq!(encoder.poll(), Ok(None));
+        assert_eq!(encoder.poll_infallible(), None);
+    }
+
+    #[test]
+    fn scan_infallible_direct_keys() {
+        let coordinates = [Coordinate::new(1, 0), Coordinate::new(1, 1)];
+        let pins = [FixedPin(false), FixedPin(true)];
+        let mut switches = DirectKeys::new(pins, coordinates, Polarity::ActiveLow);
+
+        assert_eq!(switches.scan_infallible(), []);
+        assert_eq!(switches.scan_infallible(), []);
+        assert_eq!(
+            switches.scan_infallible(),
+            [KeyEvent::KeyDown(coordinates[0])]
+        );
+        assert!(switches.activity());
+        assert_eq!(switches.scan_infallible(), []);
+    }
+
+    #[test]
+    fn direct_keys_merge_with_matrix() {
+        use embedded_keyboard::merge::MergedKeyboard;
+
+        let push = Coordinate::new(1, 0);
+        let switches = DirectKeys::new([FixedPin(false)], [push], Polarity::ActiveLow);
+        let matrix: KeyMatrix<1, 1, 1, _, _> = KeyMatrix::new([FixedPin(false)], [FixedPin(true)]);
+        let origin = Coordinate::new(0, 0);
+        let mut keyboard = MergedKeyboard::<_, _, 2>::new(matrix, origin, switches, origin);
+
+        assert_eq!(keyboard.scan().unwrap(), []);
+        assert_eq!(keyboard.scan().unwrap(), []);
+        assert_eq!(
+            keyboard.scan().unwrap(),
+            [
+                KeyEvent::KeyDown(Coordinate::new(0, 0)),
+                KeyEvent::KeyDown(push)
+            ]
+        );
+        assert!(keyboard.activity());
+    }
+
+    #[test]
+    fn direct_keys_release_survives_matrix_error() {
+        use embedded_keyboard::merge::{MergeError, MergedKeyboard};
+
+        let err = MockError::Io(ErrorKind::NotConnected);
+        let push = Coordinate::new(1, 0);
+
+        // Pressed for three scans, then released on the sixth, in the very
+        // scan the matrix fails to read its row.
+        let mut levels = vec![Transaction::get(State::High); 3];
+        levels.extend(vec![Transaction::get(State::Low); 4]);
+        let switches = DirectKeys::new([Mock::new(&levels)], [push], Polarity::ActiveHigh);
+
+        let scan = [Transaction::set(State::High), Transaction::set(State::Low)];
+        let mut col = Vec::new();
+        let mut row = Vec::new();
+        for i in 0..7 {
+            if i == 5 {
+                col.push(Transaction::set(State::High));
+                row.push(Transaction::get(State::Low).with_error(err.clone()));
+            } else {
+                col.extend_from_slice(&scan);
+                row.push(Transaction::get(State::Low));
+            }
+        }
+        let matrix: KeyMatrix<1, 1, 1, _, _> = KeyMatrix::new([Mock::new(&col)], [Mock::new(&row)]);
+
+        let origin = Coordinate::new(0, 0);
+        let mut keyboard = MergedKeyboard::<_, _, 2>::new(switches, origin, matrix, origin);
+
+        assert_eq!(keyboard.scan(), Ok(&[][..]));
+        assert_eq!(keyboard.scan(), Ok(&[][..]));
+        assert_eq!(keyboard.scan(), Ok(&[KeyEvent::KeyDown(push)][..]));
+        assert_eq!(keyboard.scan(), Ok(&[][..]));
+        assert_eq!(keyboard.scan(), Ok(&[][..]));
+        assert_eq!(
+            keyboard.scan(),
+            Err(MergeError::Second(KeyboardError::GetRow {
+                row: 0,
+                kind: digital::ErrorKind::Other
+            }))
+        );
+        assert_eq!(keyboard.scan(), Ok(&[KeyEvent::KeyUp(push)][..]));
+
+        let (switches, matrix) = keyboard.destroy();
+        for mut pin in switches.destroy() {
+            pin.done();
+        }
+        let (cols, rows) = matrix.destroy();
+        for mut pin in cols.into_iter().chain(rows) {
+            pin.done();
+        }
+    }
 }
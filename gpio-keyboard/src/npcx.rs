@@ -0,0 +1,391 @@
+//! Nuvoton NPCX keyboard scan (KBS) peripheral.
+//!
+//! Reference adapter of a memory-mapped keyboard scan controller to
+//! [`MatrixSnapshotSource`], to follow for the scan peripherals of other
+//! embedded controllers. The KBS module drives up to 18 open-drain
+//! outputs, KSO0 to KSO17, on the columns, and reads up to 8 inputs, KSI0
+//! to KSI7, on the rows, pulled up by the module: a pressed key reads low
+//! on its row while its column is driven low.
+//!
+//! [`NpcxKbs`] scans only while keys are pressed. Idle, it drives every
+//! column low and arms the Multi-Input Wake-Up (MIWU) unit on the falling
+//! edges of the rows, so that the first key pressed raises an interrupt,
+//! whose handler calls [`NpcxKbs::on_interrupt`] and starts the periodic
+//! scans of a [`SnapshotMatrix`](crate::SnapshotMatrix). Once the
+//! snapshots are idle for long enough for the releases to be debounced,
+//! the adapter arms the wake-up again, and the application stops
+//! scanning until the next interrupt:
+//!
+//! ```
+//! # use embedded_hal::delay::DelayNs;
+//! use embedded_keyboard::Keyboard;
+//! use embedded_keymatrix::npcx::{KbsRegisters, NpcxKbs};
+//! use embedded_keymatrix::{KeyMatrixBuilder, PortColumn, RowPull, SnapshotMatrix, SnapshotRow};
+//!
+//! struct Kbs;
+//!
+//! impl KbsRegisters for Kbs {
+//!     fn kbsin(&mut self) -> u8 {
+//!         // e.g. `kbs.kbsin().read().bits()`
+//!         0xff
+//!     }
+//!
+//!     fn set_kbsout(&mut self, kso: u32) {
+//!         // e.g. `kbs.kbsout0().write(|w| w.bits(kso as u16))`, and the
+//!         // bits from 16 to KBSOUT1
+//!     }
+//!
+//!     fn set_kbsinpu(&mut self, ksi: u8) {}
+//!
+//!     fn set_wake(&mut self, ksi: u8) {
+//!         // e.g. configure the MIWU inputs of `ksi` for falling edges,
+//!         // clear their pending bits and enable them
+//!     }
+//! }
+//! # struct Delay;
+//! # impl DelayNs for Delay {
+//! #     fn delay_ns(&mut self, _: u32) {}
+//! # }
+//!
+//! let matrix = KeyMatrixBuilder::new([PortColumn; 13], [SnapshotRow; 8])
+//!     .row_pull(RowPull::PullUp)
+//!     .build::<6>();
+//! let kbs = NpcxKbs::<_, _, 8, 13>::new(Kbs, Delay, 8);
+//! let mut matrix = SnapshotMatrix::new(matrix, kbs);
+//!
+//! // From the MIWU interrupt handler:
+//! matrix.source_mut().on_interrupt();
+//!
+//! // From the scan timer, until the keyboard is idle again:
+//! while matrix.source_mut().scanning() {
+//!     matrix.scan().unwrap();
+//! }
+//! ```
+
+use core::convert::Infallible;
+
+use embedded_hal::delay::DelayNs;
+use embedded_keyboard::matrix::MatrixSnapshotSource;
+use embedded_keyboard::ErrorType;
+
+use crate::{RowPull, WakeConfig};
+
+/// KSO outputs of the KBS module.
+const KSO_MASK: u32 = (1 << 18) - 1;
+
+/// Time for the rows to settle after a column is driven, in
+/// nanoseconds.
+pub const SETTLE_NS: u32 = 5_000;
+
+/// Access to the registers of the KBS module and of the MIWU inputs of
+/// its rows, implemented by the application with its peripheral access
+/// crate.
+pub trait KbsRegisters {
+    /// Read `KBSIN`: bit `y` is the level of KSI`y`.
+    fn kbsin(&mut self) -> u8;
+
+    /// Write KSO0 to KSO15 to `KBSOUT0` and KSO16 and KSO17 to `KBSOUT1`:
+    /// bit `x` of `kso` releases KSO`x` when set, and drives it low when
+    /// clear.
+    fn set_kbsout(&mut self, kso: u32);
+
+    /// Write `KBSINPU`, enabling the pull-ups of the KSIs set in `ksi`.
+    fn set_kbsinpu(&mut self, ksi: u8);
+
+    /// Enable the wake-up and interrupt of the MIWU on falling edges of
+    /// the KSIs set in `ksi`, clearing their pending edges, and disable
+    /// those of the others.
+    fn set_wake(&mut self, ksi: u8);
+}
+
+impl<T: KbsRegisters + ?Sized> KbsRegisters for &mut T {
+    #[inline]
+    fn kbsin(&mut self) -> u8 {
+        T::kbsin(self)
+    }
+
+    #[inline]
+    fn set_kbsout(&mut self, kso: u32) {
+        T::set_kbsout(self, kso);
+    }
+
+    #[inline]
+    fn set_kbsinpu(&mut self, ksi: u8) {
+        T::set_kbsinpu(self, ksi);
+    }
+
+    #[inline]
+    fn set_wake(&mut self, ksi: u8) {
+        T::set_wake(self, ksi);
+    }
+}
+
+/// Key matrix of up to 8 rows and 18 columns scanned by the KBS module,
+/// interrupt-driven.
+pub struct NpcxKbs<R, D, const ROWS: usize, const COLS: usize>
+where
+    R: KbsRegisters,
+    D: DelayNs,
+{
+    regs: R,
+    delay: D,
+    idle_scans: u8,
+    idle: u8,
+    scanning: bool,
+}
+
+impl<R, D, const ROWS: usize, const COLS: usize> NpcxKbs<R, D, ROWS, COLS>
+where
+    R: KbsRegisters,
+    D: DelayNs,
+{
+    /// Rows of the matrix, as KSI bits.
+    const ROW_MASK: u8 = if ROWS >= 8 { u8::MAX } else { (1 << ROWS) - 1 };
+
+    /// Scan the matrix with the KBS module behind `regs`, waiting for the
+    /// rows to settle with `delay`, and arm the wake-up after
+    /// `idle_scans` idle snapshots in a row, which must be more than the
+    /// scans the matrix takes to debounce a release.
+    ///
+    /// The matrix starts armed, see [`NpcxKbs::arm_wake`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the matrix has more than 8 rows or 18 columns, which the
+    /// KBS module does not have.
+    pub fn new(regs: R, delay: D, idle_scans: u8) -> Self {
+        assert!(ROWS <= 8 && COLS <= 18, "too many rows or columns");
+
+        let mut kbs = Self {
+            regs,
+            delay,
+            idle_scans,
+            idle: 0,
+            scanning: false,
+        };
+        kbs.regs.set_kbsinpu(Self::ROW_MASK);
+        kbs.arm_wake();
+        kbs
+    }
+
+    /// Whether keys may be pressed, so that the matrix is to be scanned,
+    /// rather than armed to raise an interrupt on the next press.
+    #[must_use]
+    pub fn scanning(&self) -> bool {
+        self.scanning
+    }
+
+    /// Start scanning, to be called from the MIWU interrupt handler of the
+    /// rows. The wake-up is disabled until the matrix is idle again.
+    pub fn on_interrupt(&mut self) {
+        self.regs.set_wake(0);
+        self.regs.set_kbsout(KSO_MASK);
+        self.idle = 0;
+        self.scanning = true;
+    }
+
+    /// Stop scanning, and arm the wake-up on the rows with every column
+    /// driven low, e.g. before deep sleep.
+    ///
+    /// Rows already low, because a key on them is held, are left out, as
+    /// with [`KeyMatrix::arm_wake`](crate::KeyMatrix::arm_wake): the
+    /// application may rather keep scanning, with
+    /// [`NpcxKbs::on_interrupt`], as the adapter itself does when arming
+    /// the wake-up once idle.
+    pub fn arm_wake(&mut self) -> WakeConfig<ROWS> {
+        self.regs.set_kbsout(0);
+        self.delay.delay_ns(SETTLE_NS);
+
+        let levels = self.regs.kbsin();
+        let mut armed = [false; ROWS];
+        for (y, armed) in armed.iter_mut().enumerate() {
+            *armed = levels & (1 << y) != 0;
+        }
+
+        self.regs.set_wake(levels & Self::ROW_MASK);
+        self.scanning = false;
+        WakeConfig {
+            armed,
+            level: RowPull::PullUp.level(),
+        }
+    }
+
+    /// Destroys this instance and returns the registers and delay back to
+    /// the caller.
+    pub fn destroy(self) -> (R, D) {
+        (self.regs, self.delay)
+    }
+
+    /// Drive each column low in turn, capturing the rows into `levels`,
+    /// and return whether no key was sensed.
+    fn walk(&mut self, levels: &mut [u32; COLS]) -> bool {
+        let mut idle = true;
+
+        for (x, level) in levels.iter_mut().enumerate() {
+            self.regs.set_kbsout(KSO_MASK ^ (1 << x));
+            self.delay.delay_ns(SETTLE_NS);
+
+            let rows = self.regs.kbsin() | !Self::ROW_MASK;
+            idle &= rows == u8::MAX;
+            *level = u32::from(rows);
+        }
+
+        self.regs.set_kbsout(KSO_MASK);
+        idle
+    }
+}
+
+impl<R, D, const ROWS: usize, const COLS: usize> ErrorType for NpcxKbs<R, D, ROWS, COLS>
+where
+    R: KbsRegisters,
+    D: DelayNs,
+{
+    type Error = Infallible;
+}
+
+impl<R, D, const ROWS: usize, const COLS: usize> MatrixSnapshotSource<COLS>
+    for NpcxKbs<R, D, ROWS, COLS>
+where
+    R: KbsRegisters,
+    D: DelayNs,
+{
+    /// Scan the matrix, if keys may be pressed, and arm the wake-up once
+    /// enough snapshots in a row were idle.
+    fn read(&mut self, levels: &mut [u32; COLS]) -> Result<bool, Infallible> {
+        if !self.scanning {
+            return Ok(false);
+        }
+
+        if self.walk(levels) {
+            self.idle = self.idle.saturating_add(1);
+        } else {
+            self.idle = 0;
+        }
+
+        if self.idle >= self.idle_scans && self.arm_wake().held().next().is_some() {
+            // A key was pressed since the snapshot: keep scanning.
+            self.on_interrupt();
+        }
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DebounceMode, KeyMatrixBuilder, PortColumn, SnapshotMatrix, SnapshotRow};
+    use core::cell::Cell;
+    use embedded_keyboard::{Coordinate, KeyEvent, Keyboard};
+
+    /// Registers of the KBS module of a 2 by 2 matrix, with the keys in
+    /// `pressed` pressed.
+    #[derive(Default)]
+    struct FakeKbs {
+        pressed: Cell<[[bool; 2]; 2]>,
+        kbsout: Cell<u32>,
+        kbsinpu: Cell<u8>,
+        wake: Cell<u8>,
+        /// Keys pressed once every column is driven low
+        press_on_arm: Cell<Option<[[bool; 2]; 2]>>,
+    }
+
+    impl KbsRegisters for &FakeKbs {
+        fn kbsin(&mut self) -> u8 {
+            let mut levels = u8::MAX;
+            for (y, keys) in self.pressed.get().iter().enumerate() {
+                for (x, pressed) in keys.iter().enumerate() {
+                    if *pressed && self.kbsout.get() & (1 << x) == 0 {
+                        levels &= !(1 << y);
+                    }
+                }
+            }
+            levels
+        }
+
+        fn set_kbsout(&mut self, kso: u32) {
+            if kso == 0 {
+                if let Some(pressed) = self.press_on_arm.take() {
+                    self.pressed.set(pressed);
+                }
+            }
+            self.kbsout.set(kso);
+        }
+
+        fn set_kbsinpu(&mut self, ksi: u8) {
+            self.kbsinpu.set(ksi);
+        }
+
+        fn set_wake(&mut self, ksi: u8) {
+            self.wake.set(ksi);
+        }
+    }
+
+    struct NoDelay;
+
+    impl DelayNs for NoDelay {
+        fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    #[test]
+    fn interrupt_driven_scans() {
+        let kbs = FakeKbs::default();
+        let matrix = KeyMatrixBuilder::new([PortColumn; 2], [SnapshotRow; 2])
+            .row_pull(RowPull::PullUp)
+            .debounce_mode(DebounceMode::Raw)
+            .build::<1>();
+        let mut matrix = SnapshotMatrix::new(matrix, NpcxKbs::<_, _, 2, 2>::new(&kbs, NoDelay, 2));
+
+        // Armed with every column low: nothing is scanned until the
+        // interrupt.
+        assert_eq!(
+            (kbs.kbsinpu.get(), kbs.kbsout.get(), kbs.wake.get()),
+            (0b11, 0, 0b11)
+        );
+        assert_eq!(matrix.scan(), Ok(&[][..]));
+
+        kbs.pressed.set([[false, false], [true, false]]);
+        matrix.source_mut().on_interrupt();
+        assert!(matrix.source_mut().scanning());
+        assert_eq!(kbs.wake.get(), 0);
+        assert_eq!(
+            matrix.scan(),
+            Ok(&[KeyEvent::KeyDown(Coordinate::new(1, 0))][..])
+        );
+        assert_eq!(matrix.levels(), &[0xfd, 0xff]);
+        assert_eq!(kbs.kbsout.get(), KSO_MASK);
+
+        // The release is scanned, then the matrix arms itself once idle
+        // for two snapshots.
+        kbs.pressed.set([[false; 2]; 2]);
+        assert_eq!(
+            matrix.scan(),
+            Ok(&[KeyEvent::KeyUp(Coordinate::new(1, 0))][..])
+        );
+        assert!(matrix.source_mut().scanning());
+        matrix.scan().unwrap();
+        assert!(!matrix.source_mut().scanning());
+        assert_eq!((kbs.kbsout.get(), kbs.wake.get()), (0, 0b11));
+        assert_eq!(matrix.scan(), Ok(&[][..]));
+    }
+
+    #[test]
+    fn held_keys_left_out_of_wake() {
+        let kbs = FakeKbs::default();
+        let mut source = NpcxKbs::<_, _, 2, 2>::new(&kbs, NoDelay, 1);
+        let mut levels = [0; 2];
+
+        kbs.pressed.set([[false, true], [false, false]]);
+        let config = source.arm_wake();
+        assert_eq!(config.held().collect::<Vec<_>>(), [0]);
+        assert_eq!(kbs.wake.get(), 0b10);
+
+        // A key pressed between an idle snapshot and arming the wake-up
+        // keeps the matrix scanned.
+        kbs.pressed.set([[false; 2]; 2]);
+        source.on_interrupt();
+        kbs.press_on_arm.set(Some([[true, false], [false, false]]));
+        assert_eq!(source.read(&mut levels), Ok(true));
+        assert!(source.scanning());
+    }
+}
@@ -0,0 +1,199 @@
+//! Helpers for testing code driving a [`KeyMatrix`].
+//!
+//! Listing every pin transaction a scan performs quickly gets out of hand:
+//! a 2x2 matrix already reads four rows per scan, and a debounce scenario
+//! runs several scans. [`ScanScript`] instead describes which keys are
+//! pressed during each scan, e.g. a key bouncing before it settles, and
+//! turns that into the [`embedded-hal-mock`] pins expecting the exact
+//! transactions [`KeyMatrix`] performs.
+//!
+//! [`embedded-hal-mock`]: embedded_hal_mock
+//! [`KeyMatrix`]: crate::KeyMatrix
+
+use std::vec::Vec;
+
+use embedded_hal_mock::eh1::digital::{Mock, State, Transaction};
+use embedded_keyboard::Coordinate;
+
+/// Keys pressed during successive scans of a `ROWS` by `COLS` matrix.
+///
+/// The script keeps the current state of every key: [`ScanScript::press`]
+/// and [`ScanScript::release`] change it, and [`ScanScript::scans`] adds
+/// scans seeing it. States recorded from a real matrix can be replayed
+/// with [`ScanScript::push`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanScript<const ROWS: usize, const COLS: usize> {
+    state: [[bool; COLS]; ROWS],
+    scans: Vec<[[bool; COLS]; ROWS]>,
+}
+
+impl<const ROWS: usize, const COLS: usize> ScanScript<ROWS, COLS> {
+    /// Create an empty script, with every key released.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            state: [[false; COLS]; ROWS],
+            scans: Vec::new(),
+        }
+    }
+
+    /// Press `key` for the next scans.
+    #[must_use]
+    pub fn press(mut self, key: Coordinate) -> Self {
+        self.state[key.row()][key.col()] = true;
+        self
+    }
+
+    /// Release `key` for the next scans.
+    #[must_use]
+    pub fn release(mut self, key: Coordinate) -> Self {
+        self.state[key.row()][key.col()] = false;
+        self
+    }
+
+    /// Add `count` scans of the current state.
+    #[must_use]
+    pub fn scans(mut self, count: usize) -> Self {
+        self.scans
+            .extend(core::iter::repeat(self.state).take(count));
+        self
+    }
+
+    /// Add one scan per level of `levels`, seeing `key` pressed when
+    /// `true`, then leave `key` at the last level.
+    #[must_use]
+    pub fn bounce(mut self, key: Coordinate, levels: &[bool]) -> Self {
+        for &level in levels {
+            self.state[key.row()][key.col()] = level;
+            self.scans.push(self.state);
+        }
+        self
+    }
+
+    /// Add a scan seeing the keys pressed in `state`, e.g. as recorded
+    /// from a real matrix, and keep it as the current state.
+    #[must_use]
+    pub fn push(mut self, state: [[bool; COLS]; ROWS]) -> Self {
+        self.state = state;
+        self.scans.push(state);
+        self
+    }
+
+    /// Number of scans in the script.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.scans.len()
+    }
+
+    /// Whether the script has no scan.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.scans.is_empty()
+    }
+
+    /// Transactions expected by the column and row pins.
+    ///
+    /// Every scan drives each column high then low in turn, reading every
+    /// row while the column is high.
+    #[must_use]
+    pub fn transactions(&self) -> ([Vec<Transaction>; COLS], [Vec<Transaction>; ROWS]) {
+        let mut cols: [Vec<Transaction>; COLS] = core::array::from_fn(|_| Vec::new());
+        let mut rows: [Vec<Transaction>; ROWS] = core::array::from_fn(|_| Vec::new());
+
+        for scan in &self.scans {
+            for (x, col) in cols.iter_mut().enumerate() {
+                col.push(Transaction::set(State::High));
+
+                for (y, row) in rows.iter_mut().enumerate() {
+                    let level = if scan[y][x] { State::High } else { State::Low };
+                    row.push(Transaction::get(level));
+                }
+
+                col.push(Transaction::set(State::Low));
+            }
+        }
+
+        (cols, rows)
+    }
+
+    /// Column and row pins expecting the transactions of the script, to
+    /// build a [`KeyMatrix`](crate::KeyMatrix) with.
+    #[must_use]
+    pub fn pins(&self) -> ([Mock; COLS], [Mock; ROWS]) {
+        let (cols, rows) = self.transactions();
+
+        (cols.map(|t| Mock::new(&t)), rows.map(|t| Mock::new(&t)))
+    }
+}
+
+impl<const ROWS: usize, const COLS: usize> Default for ScanScript<ROWS, COLS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Check that every pin performed all the transactions it expected, e.g.
+/// with the pins returned by [`KeyMatrix::destroy`](crate::KeyMatrix::destroy).
+///
+/// # Panics
+///
+/// Panics if a pin still expects transactions.
+pub fn done<const COLS: usize, const ROWS: usize>((cols, rows): ([Mock; COLS], [Mock; ROWS])) {
+    for mut pin in cols.into_iter().chain(rows) {
+        pin.done();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeyMatrix;
+    use embedded_keyboard::Keyboard;
+
+    #[test]
+    fn bouncing_key_debounces_once() {
+        let key = Coordinate::new(1, 0);
+        let script = ScanScript::<2, 2>::new()
+            .scans(1)
+            .bounce(key, &[true, false, true, false, true])
+            .scans(3);
+        assert_eq!(script.len(), 9);
+
+        let (cols, rows) = script.pins();
+        let mut matrix: KeyMatrix<2, 2, 4, _, _> = KeyMatrix::new(cols, rows);
+
+        // The counter only reaches the debounce threshold once the key
+        // settles.
+        let pressed: Vec<bool> = (0..script.len())
+            .map(|_| {
+                matrix.scan().unwrap();
+                matrix.key_states()[1][0].pressed()
+            })
+            .collect();
+        assert_eq!(
+            pressed,
+            [false, false, false, false, false, false, false, true, true]
+        );
+        done(matrix.destroy());
+    }
+
+    #[test]
+    fn scan_transactions() {
+        let script = ScanScript::<2, 1>::new()
+            .press(Coordinate::new(0, 0))
+            .scans(1)
+            .push([[false], [true]]);
+        let (cols, rows) = script.transactions();
+
+        let high = Transaction::set(State::High);
+        let low = Transaction::set(State::Low);
+        assert_eq!(cols, [[high.clone(), low.clone(), high, low]]);
+        assert_eq!(
+            rows,
+            [
+                [Transaction::get(State::High), Transaction::get(State::Low)],
+                [Transaction::get(State::Low), Transaction::get(State::High)],
+            ]
+        );
+    }
+}
@@ -0,0 +1,632 @@
+//! Build-time code generation for `embedded-keyboard` firmware.
+//!
+//! Boards are described once, in a TOML board definition, and a build
+//! script turns the definition into the constants of the firmware: matrix
+//! dimensions, keymap, LED map and HID report descriptor. Since every
+//! array is sized by the generated dimensions, a keymap or LED map which
+//! does not fit the matrix fails the build rather than the keyboard.
+//!
+//! A board definition has these tables, all but `matrix` optional:
+//!
+//! ```toml
+//! [matrix]
+//! rows = 2
+//! cols = 2
+//!
+//! [keymap]
+//! # Matrix coordinate of each keycode of a layer, in matrix order if
+//! # omitted.
+//! layout = ["0,0", "0,1", "1,1"]
+//! # Layers of QMK keycodes.
+//! layers = [
+//!     ["KC_ESC", "MO(1)", "LSFT_T(KC_SPC)"],
+//!     ["QK_BOOT", "_______", "RGB_TOG"],
+//! ]
+//!
+//! [leds]
+//! # Key lit by each LED in chain order, "" for LEDs lighting no key.
+//! chain = ["0,0", "0,1", "1,1", ""]
+//!
+//! [hid]
+//! # Reports of the device, in descriptor order: "boot", "nkro",
+//! # "consumer", "system" or "mouse".
+//! reports = ["nkro", "consumer"]
+//! nkro_bytes = 16
+//! consumer_usages = 2
+//! ```
+//!
+//! The build script generates the constants into `OUT_DIR`:
+//!
+//! ```no_run
+//! // build.rs
+//! let out = std::env::var("OUT_DIR").unwrap();
+//! keyboard_codegen::generate("board.toml", format!("{out}/board.rs")).unwrap();
+//! ```
+//!
+//! and the firmware includes them:
+//!
+//! ```ignore
+//! #[allow(dead_code)] // For the constants the firmware does not use.
+//! mod board {
+//!     include!(concat!(env!("OUT_DIR"), "/board.rs"));
+//! }
+//!
+//! let keymap = board::KEYMAP;
+//! let leds = RgbMatrix::<{ board::ROWS }, { board::COLS }, { board::LEDS }>::new(board::LED_MAP);
+//! ```
+
+use core::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use embedded_keyboard::qmk::ActionSource;
+use embedded_keyboard::{Action, Coordinate};
+
+mod toml;
+
+use toml::Value;
+
+/// Error reading a board definition.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CodegenError {
+    /// The definition is not valid TOML, from the given line
+    Toml {
+        /// Line of the error
+        line: usize,
+    },
+    /// A required key is missing
+    Missing {
+        /// Name of the key, with its table
+        key: &'static str,
+    },
+    /// A key has a value of the wrong type, or out of range
+    Invalid {
+        /// Name of the key, with its table
+        key: &'static str,
+    },
+    /// A keycode could not be parsed
+    Keycode {
+        /// Index of the layer
+        layer: usize,
+        /// Index of the key in the layout
+        index: usize,
+        /// The keycode
+        keycode: String,
+    },
+    /// A layer does not have one keycode per key of the layout
+    LayerLength {
+        /// Index of the layer
+        layer: usize,
+        /// Number of keycodes in the layer
+        len: usize,
+    },
+    /// A coordinate is not a `row,col` pair within the matrix
+    Coordinate {
+        /// Name of the key listing it, with its table
+        key: &'static str,
+        /// The coordinate
+        coordinate: String,
+    },
+    /// A HID report is unknown
+    Report {
+        /// Name of the report
+        report: String,
+    },
+}
+
+impl fmt::Display for CodegenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Toml { line } => write!(f, "invalid TOML at line {line}"),
+            Self::Missing { key } => write!(f, "missing `{key}`"),
+            Self::Invalid { key } => write!(f, "invalid `{key}`"),
+            Self::Keycode {
+                layer,
+                index,
+                keycode,
+            } => write!(f, "layer {layer}, key {index}: invalid keycode `{keycode}`"),
+            Self::LayerLength { layer, len } => {
+                write!(f, "layer {layer}: {len} keycodes for the layout")
+            }
+            Self::Coordinate { key, coordinate } => {
+                write!(f, "`{key}`: invalid coordinate `{coordinate}`")
+            }
+            Self::Report { report } => write!(f, "unknown HID report `{report}`"),
+        }
+    }
+}
+
+impl std::error::Error for CodegenError {}
+
+/// HID report of a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Report {
+    /// Boot protocol keyboard report
+    Boot,
+    /// NKRO keyboard report, with a usage bitmap of the given bytes
+    Nkro(usize),
+    /// Consumer control report, with the given usage slots
+    Consumer(usize),
+    /// System control report
+    System,
+    /// Mouse report
+    Mouse,
+}
+
+impl Report {
+    /// Name of the report type, and of its report ID constant.
+    const fn names(self) -> (&'static str, &'static str) {
+        match self {
+            Self::Boot => ("BootKeyboardReport", "KEYBOARD"),
+            Self::Nkro(_) => ("NkroKeyboardReport", "KEYBOARD"),
+            Self::Consumer(_) => ("ConsumerReport", "CONSUMER"),
+            Self::System => ("SystemControlReport", "SYSTEM"),
+            Self::Mouse => ("MouseReport", "MOUSE"),
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    /// Writes the path of the report type.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "::embedded_keyboard::hid::{}", self.names().0)?;
+        match self {
+            Self::Nkro(n) | Self::Consumer(n) => write!(f, "::<{n}>"),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Board parsed from a board definition.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Board {
+    rows: usize,
+    cols: usize,
+    layers: Option<Vec<Vec<Action>>>,
+    leds: Option<Vec<Option<Coordinate>>>,
+    reports: Vec<Report>,
+}
+
+impl Board {
+    /// Parse a board definition.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `toml` is not a valid board definition.
+    pub fn from_toml(toml: &str) -> Result<Self, CodegenError> {
+        let document = toml::parse(toml).map_err(|line| CodegenError::Toml { line })?;
+        let usize = |table, name, key| match document.get(table, name) {
+            Some(Value::Integer(n)) => {
+                usize::try_from(*n).map_err(|_| CodegenError::Invalid { key })
+            }
+            Some(_) => Err(CodegenError::Invalid { key }),
+            None => Err(CodegenError::Missing { key }),
+        };
+        let strings = |table, name, key| match document.get(table, name) {
+            Some(Value::Array(values)) => values
+                .iter()
+                .map(|value| match value {
+                    Value::String(string) => Ok(string.as_str()),
+                    _ => Err(CodegenError::Invalid { key }),
+                })
+                .collect::<Result<Vec<_>, _>>(),
+            Some(_) => Err(CodegenError::Invalid { key }),
+            None => Err(CodegenError::Missing { key }),
+        };
+
+        let rows = usize("matrix", "rows", "matrix.rows")?;
+        let cols = usize("matrix", "cols", "matrix.cols")?;
+        let coordinate = |key, string: &str| {
+            parse_coordinate(string)
+                .filter(|c| c.within(rows, cols))
+                .ok_or_else(|| CodegenError::Coordinate {
+                    key,
+                    coordinate: String::from(string),
+                })
+        };
+
+        let layers = if document.has_table("keymap") {
+            let layout = match document.get("keymap", "layout") {
+                Some(_) => strings("keymap", "layout", "keymap.layout")?
+                    .into_iter()
+                    .map(|string| coordinate("keymap.layout", string))
+                    .collect::<Result<Vec<_>, _>>()?,
+                None => (0..rows * cols)
+                    .map(|index| Coordinate::from_index(index, cols))
+                    .collect(),
+            };
+            let Some(Value::Array(values)) = document.get("keymap", "layers") else {
+                return Err(CodegenError::Missing {
+                    key: "keymap.layers",
+                });
+            };
+
+            let mut layers = Vec::new();
+            for (layer, value) in values.iter().enumerate() {
+                let Value::Array(keycodes) = value else {
+                    return Err(CodegenError::Invalid {
+                        key: "keymap.layers",
+                    });
+                };
+                if keycodes.len() != layout.len() {
+                    return Err(CodegenError::LayerLength {
+                        layer,
+                        len: keycodes.len(),
+                    });
+                }
+
+                let mut actions = vec![Action::NoOp; rows * cols];
+                for (index, (keycode, coordinate)) in keycodes.iter().zip(&layout).enumerate() {
+                    let Value::String(keycode) = keycode else {
+                        return Err(CodegenError::Invalid {
+                            key: "keymap.layers",
+                        });
+                    };
+                    actions[coordinate.index(cols)] =
+                        keycode.parse().map_err(|_| CodegenError::Keycode {
+                            layer,
+                            index,
+                            keycode: keycode.clone(),
+                        })?;
+                }
+                layers.push(actions);
+            }
+            Some(layers)
+        } else {
+            None
+        };
+
+        let leds = if document.has_table("leds") {
+            let leds = strings("leds", "chain", "leds.chain")?
+                .into_iter()
+                .map(|string| match string {
+                    "" => Ok(None),
+                    string => coordinate("leds.chain", string).map(Some),
+                })
+                .collect::<Result<_, _>>()?;
+            Some(leds)
+        } else {
+            None
+        };
+
+        let reports = if document.has_table("hid") {
+            let sized = |name, key, default| match document.get("hid", name) {
+                Some(_) => usize("hid", name, key),
+                None => Ok(default),
+            };
+            let nkro_bytes = sized("nkro_bytes", "hid.nkro_bytes", 16)?;
+            let consumer_usages = sized("consumer_usages", "hid.consumer_usages", 1)?;
+            if !(1..=32).contains(&nkro_bytes) {
+                return Err(CodegenError::Invalid {
+                    key: "hid.nkro_bytes",
+                });
+            }
+
+            strings("hid", "reports", "hid.reports")?
+                .into_iter()
+                .map(|report| match report {
+                    "boot" => Ok(Report::Boot),
+                    "nkro" => Ok(Report::Nkro(nkro_bytes)),
+                    "consumer" => Ok(Report::Consumer(consumer_usages)),
+                    "system" => Ok(Report::System),
+                    "mouse" => Ok(Report::Mouse),
+                    report => Err(CodegenError::Report {
+                        report: String::from(report),
+                    }),
+                })
+                .collect::<Result<_, _>>()?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self {
+            rows,
+            cols,
+            layers,
+            leds,
+            reports,
+        })
+    }
+
+    /// Number of rows of the matrix.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Number of columns of the matrix.
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// Action of every key of every layer, in matrix order, if the board
+    /// has a keymap.
+    pub fn layers(&self) -> Option<&[Vec<Action>]> {
+        self.layers.as_deref()
+    }
+
+    /// Key lit by each LED in chain order, if the board has LEDs.
+    pub fn leds(&self) -> Option<&[Option<Coordinate>]> {
+        self.leds.as_deref()
+    }
+
+    /// HID reports of the device, in descriptor order.
+    pub fn reports(&self) -> &[Report] {
+        &self.reports
+    }
+}
+
+/// Parse a `row,col` coordinate.
+fn parse_coordinate(string: &str) -> Option<Coordinate> {
+    let (row, col) = string.split_once(',')?;
+    Some(Coordinate::new(
+        row.trim().parse().ok()?,
+        col.trim().parse().ok()?,
+    ))
+}
+
+/// Rust source of the constants of a [`Board`].
+///
+/// Formatting a `BoardSource` writes `ROWS` and `COLS`, and when the
+/// board defines them:
+///
+/// - `LAYERS` and the `KEYMAP`
+/// - `LEDS` and the `LED_MAP`, for an `RgbMatrix`
+/// - the `HID_DESCRIPTOR` and its `HID_DESCRIPTOR_LEN`, with a
+///   `<REPORT>_REPORT_ID` constant per report when there are several.
+pub struct BoardSource<'a> {
+    board: &'a Board,
+}
+
+impl<'a> BoardSource<'a> {
+    /// Rust source of `board`.
+    pub fn new(board: &'a Board) -> Self {
+        Self { board }
+    }
+}
+
+impl fmt::Display for BoardSource<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const CRATE: &str = "::embedded_keyboard";
+        let board = self.board;
+
+        writeln!(f, "// Generated by keyboard-codegen, do not edit.")?;
+        writeln!(f)?;
+        writeln!(f, "/// Rows of the key matrix.")?;
+        writeln!(f, "pub const ROWS: usize = {};", board.rows)?;
+        writeln!(f, "/// Columns of the key matrix.")?;
+        writeln!(f, "pub const COLS: usize = {};", board.cols)?;
+
+        if let Some(layers) = &board.layers {
+            writeln!(f)?;
+            writeln!(f, "/// Layers of the keymap.")?;
+            writeln!(f, "pub const LAYERS: usize = {};", layers.len())?;
+            writeln!(f, "/// Keymap of the board.")?;
+            writeln!(
+                f,
+                "pub const KEYMAP: {CRATE}::Keymap<LAYERS, ROWS, COLS> = {CRATE}::Keymap::new(["
+            )?;
+            for actions in layers {
+                writeln!(f, "    [")?;
+                for row in actions.chunks(board.cols.max(1)) {
+                    f.write_str("        [")?;
+                    for (col, action) in row.iter().enumerate() {
+                        if col > 0 {
+                            f.write_str(", ")?;
+                        }
+                        write!(f, "{}", ActionSource::new(*action))?;
+                    }
+                    writeln!(f, "],")?;
+                }
+                writeln!(f, "    ],")?;
+            }
+            writeln!(f, "]);")?;
+        }
+
+        if let Some(leds) = &board.leds {
+            writeln!(f)?;
+            writeln!(f, "/// LEDs of the chain.")?;
+            writeln!(f, "pub const LEDS: usize = {};", leds.len())?;
+            writeln!(f, "/// Key lit by each LED, in chain order.")?;
+            writeln!(
+                f,
+                "pub const LED_MAP: [Option<{CRATE}::Coordinate>; LEDS] = ["
+            )?;
+            for led in leds {
+                match led {
+                    Some(c) => writeln!(
+                        f,
+                        "    Some({CRATE}::Coordinate::new({}, {})),",
+                        c.row(),
+                        c.col()
+                    )?,
+                    None => writeln!(f, "    None,")?,
+                }
+            }
+            writeln!(f, "];")?;
+        }
+
+        if !board.reports.is_empty() {
+            // Report IDs are only declared by devices with several reports.
+            let id = |index: usize| match board.reports.len() {
+                1 => String::from("None"),
+                _ => format!("Some({})", index + 1),
+            };
+
+            writeln!(f)?;
+            if board.reports.len() > 1 {
+                for (index, report) in board.reports.iter().enumerate() {
+                    writeln!(f, "/// Report ID of the `{}`.", report.names().0)?;
+                    writeln!(
+                        f,
+                        "pub const {}_REPORT_ID: u8 = {};",
+                        report.names().1,
+                        index + 1
+                    )?;
+                }
+            }
+            writeln!(f, "/// Length of the HID report descriptor.")?;
+            write!(f, "pub const HID_DESCRIPTOR_LEN: usize =")?;
+            for (index, report) in board.reports.iter().enumerate() {
+                write!(
+                    f,
+                    "\n    {}{CRATE}::hid::DescriptorBuilder::<0>::collection_len({}, &{report}::DESCRIPTOR)",
+                    if index > 0 { "+ " } else { "" },
+                    id(index)
+                )?;
+            }
+            writeln!(f, ";")?;
+            writeln!(f, "/// HID report descriptor of the device.")?;
+            write!(
+                f,
+                "pub const HID_DESCRIPTOR: [u8; HID_DESCRIPTOR_LEN] = {CRATE}::hid::DescriptorBuilder::new()"
+            )?;
+            for (index, report) in board.reports.iter().enumerate() {
+                write!(f, "\n    .collection({}, &{report}::DESCRIPTOR)", id(index))?;
+            }
+            writeln!(f, "\n    .build();")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Generate the constants of the board defined in `board` into `out`, for
+/// a build script.
+///
+/// Also tells Cargo to run the build script again when the definition
+/// changes.
+///
+/// # Errors
+///
+/// Returns an error if a file cannot be read or written, or if the board
+/// definition is invalid.
+pub fn generate(board: impl AsRef<Path>, out: impl AsRef<Path>) -> io::Result<()> {
+    let board = board.as_ref();
+    println!("cargo:rerun-if-changed={}", board.display());
+
+    let toml = fs::read_to_string(board)?;
+    let board =
+        Board::from_toml(&toml).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    fs::write(out, BoardSource::new(&board).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_keyboard::KeyCode;
+
+    const BOARD: &str = r#"
+        [matrix]
+        rows = 2
+        cols = 2
+
+        [keymap]
+        layout = ["0,1", "0,0", "1,1"]
+        layers = [
+            ["KC_ESC", "MO(1)", "LSFT_T(KC_SPC)"],
+            ["KC_1", "_______", "RGB_TOG"],
+        ]
+
+        [leds]
+        chain = ["0,0", "", "1,1"]
+
+        [hid]
+        reports = ["nkro", "consumer"]
+        consumer_usages = 2
+    "#;
+
+    #[test]
+    fn parse_board() {
+        let board = Board::from_toml(BOARD).unwrap();
+        assert_eq!((board.rows(), board.cols()), (2, 2));
+
+        let layers = board.layers().unwrap();
+        assert_eq!(layers.len(), 2);
+        assert_eq!(
+            layers[0],
+            [
+                Action::MomentaryLayer(1),
+                Action::Key(KeyCode::KEscape),
+                Action::NoOp,
+                Action::mod_tap(KeyCode::KpLeftShift, KeyCode::KSpaceBar),
+            ]
+        );
+        assert_eq!(
+            board.leds(),
+            Some(
+                &[
+                    Some(Coordinate::new(0, 0)),
+                    None,
+                    Some(Coordinate::new(1, 1))
+                ][..]
+            )
+        );
+        assert_eq!(board.reports(), [Report::Nkro(16), Report::Consumer(2)]);
+
+        let board = Board::from_toml("[matrix]\nrows = 1\ncols = 3\n").unwrap();
+        assert_eq!(board.layers(), None);
+        assert_eq!(board.leds(), None);
+        assert!(board.reports().is_empty());
+    }
+
+    #[test]
+    fn invalid_boards() {
+        let error = |toml: &str| Board::from_toml(toml).unwrap_err();
+
+        assert_eq!(error("[matrix\n"), CodegenError::Toml { line: 1 });
+        assert_eq!(
+            error("[matrix]\nrows = 2\n"),
+            CodegenError::Missing { key: "matrix.cols" }
+        );
+        assert_eq!(
+            error("[matrix]\nrows = -2\ncols = 2\n"),
+            CodegenError::Invalid { key: "matrix.rows" }
+        );
+        assert_eq!(
+            error("[matrix]\nrows = 1\ncols = 2\n[keymap]\nlayers = [[\"KC_A\"]]\n"),
+            CodegenError::LayerLength { layer: 0, len: 1 }
+        );
+        assert_eq!(
+            error("[matrix]\nrows = 1\ncols = 1\n[keymap]\nlayers = [[\"KC_NOPE\"]]\n"),
+            CodegenError::Keycode {
+                layer: 0,
+                index: 0,
+                keycode: "KC_NOPE".into()
+            }
+        );
+        assert_eq!(
+            error("[matrix]\nrows = 1\ncols = 1\n[leds]\nchain = [\"0,1\"]\n"),
+            CodegenError::Coordinate {
+                key: "leds.chain",
+                coordinate: "0,1".into()
+            }
+        );
+        assert_eq!(
+            error("[matrix]\nrows = 1\ncols = 1\n[hid]\nreports = [\"joystick\"]\n"),
+            CodegenError::Report {
+                report: "joystick".into()
+            }
+        );
+    }
+
+    #[test]
+    fn board_source() {
+        let source = BoardSource::new(&Board::from_toml(BOARD).unwrap()).to_string();
+
+        for line in [
+            "pub const ROWS: usize = 2;",
+            "pub const LAYERS: usize = 2;",
+            "pub const KEYMAP: ::embedded_keyboard::Keymap<LAYERS, ROWS, COLS> = ::embedded_keyboard::Keymap::new([",
+            "        [::embedded_keyboard::Action::MomentaryLayer(1), ::embedded_keyboard::Action::Key(::embedded_keyboard::KeyCode::KEscape)],",
+            "pub const LEDS: usize = 3;",
+            "    None,",
+            "pub const CONSUMER_REPORT_ID: u8 = 2;",
+            "    .collection(Some(1), &::embedded_keyboard::hid::NkroKeyboardReport::<16>::DESCRIPTOR)",
+            "    ::embedded_keyboard::hid::DescriptorBuilder::<0>::collection_len(Some(1), &::embedded_keyboard::hid::NkroKeyboardReport::<16>::DESCRIPTOR)",
+        ] {
+            assert!(source.lines().any(|l| l == line), "missing {line}");
+        }
+    }
+}
@@ -0,0 +1,253 @@
+//! Reader of the subset of TOML used by board definitions: tables of
+//! keys holding strings, integers, booleans and arrays of those.
+
+/// TOML value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+/// Parsed document: the value of every key, with the name of its table.
+#[derive(Debug, Default)]
+pub(crate) struct Document {
+    entries: Vec<(String, String, Value)>,
+}
+
+impl Document {
+    /// Value of `key` in `table`, `""` being the root table.
+    pub(crate) fn get(&self, table: &str, key: &str) -> Option<&Value> {
+        self.entries
+            .iter()
+            .find(|(t, k, _)| t == table && k == key)
+            .map(|(_, _, value)| value)
+    }
+
+    /// Whether the document has `table`.
+    pub(crate) fn has_table(&self, table: &str) -> bool {
+        self.entries.iter().any(|(t, _, _)| t == table)
+    }
+}
+
+/// Parse `toml`, returning the line of the error if it is not valid.
+pub(crate) fn parse(toml: &str) -> Result<Document, usize> {
+    let mut parser = Parser {
+        toml,
+        offset: 0,
+        line: 1,
+    };
+    let mut document = Document::default();
+    let mut table = String::new();
+
+    loop {
+        parser.skip_blank();
+        match parser.peek() {
+            None => return Ok(document),
+            Some('[') => {
+                parser.offset += 1;
+                parser.skip_spaces();
+                table = String::from(parser.key()?);
+                parser.skip_spaces();
+                parser.expect(']')?;
+                // Tables only hold keys, so empty ones are recorded too.
+                document
+                    .entries
+                    .push((table.clone(), String::new(), Value::Boolean(true)));
+            }
+            Some(_) => {
+                let key = String::from(parser.key()?);
+                parser.skip_spaces();
+                parser.expect('=')?;
+                parser.skip_spaces();
+                let value = parser.value()?;
+
+                if document.get(&table, &key).is_some() {
+                    return Err(parser.line);
+                }
+                document.entries.push((table.clone(), key, value));
+            }
+        }
+        parser.end_of_line()?;
+    }
+}
+
+/// Recursive descent parser, counting lines for errors.
+struct Parser<'a> {
+    toml: &'a str,
+    offset: usize,
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn value(&mut self) -> Result<Value, usize> {
+        match self.peek() {
+            Some('"') => self.string('"').map(Value::String),
+            Some('\'') => self.string('\'').map(Value::String),
+            Some('[') => {
+                self.offset += 1;
+                let mut values = Vec::new();
+                loop {
+                    self.skip_blank();
+                    if self.eat(']') {
+                        return Ok(Value::Array(values));
+                    }
+                    values.push(self.value()?);
+                    self.skip_blank();
+                    if !self.eat(',') {
+                        self.skip_blank();
+                        self.expect(']')?;
+                        return Ok(Value::Array(values));
+                    }
+                }
+            }
+            _ => match self.key()? {
+                "true" => Ok(Value::Boolean(true)),
+                "false" => Ok(Value::Boolean(false)),
+                word => {
+                    let digits = word.replace('_', "");
+                    let (negative, digits) = match digits.strip_prefix('-') {
+                        Some(digits) => (true, digits),
+                        None => (false, digits.strip_prefix('+').unwrap_or(&digits)),
+                    };
+                    let value = match digits.strip_prefix("0x") {
+                        Some(hex) => i64::from_str_radix(hex, 16),
+                        None => digits.parse(),
+                    }
+                    .map_err(|_| self.line)?;
+
+                    Ok(Value::Integer(if negative { -value } else { value }))
+                }
+            },
+        }
+    }
+
+    /// Consume a bare key or word.
+    fn key(&mut self) -> Result<&'a str, usize> {
+        let rest = &self.toml[self.offset..];
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || "_-+".contains(c)))
+            .unwrap_or(rest.len());
+        self.offset += len;
+
+        if len == 0 {
+            Err(self.line)
+        } else {
+            Ok(&rest[..len])
+        }
+    }
+
+    fn string(&mut self, quote: char) -> Result<String, usize> {
+        self.offset += 1;
+        let mut string = String::new();
+
+        loop {
+            let c = self.peek().filter(|c| *c != '\n').ok_or(self.line)?;
+            self.offset += c.len_utf8();
+
+            match c {
+                c if c == quote => return Ok(string),
+                '\\' if quote == '"' => {
+                    let escape = self.peek().ok_or(self.line)?;
+                    self.offset += escape.len_utf8();
+                    string.push(match escape {
+                        '"' | '\\' => escape,
+                        'n' => '\n',
+                        't' => '\t',
+                        _ => return Err(self.line),
+                    });
+                }
+                c => string.push(c),
+            }
+        }
+    }
+
+    /// Skip spaces, newlines and comments.
+    fn skip_blank(&mut self) {
+        loop {
+            self.skip_spaces();
+            match self.peek() {
+                Some('\n') => {
+                    self.offset += 1;
+                    self.line += 1;
+                }
+                Some('\r') => self.offset += 1,
+                _ => return,
+            }
+        }
+    }
+
+    /// Skip spaces and a comment up to the end of the line.
+    fn skip_spaces(&mut self) {
+        let rest = &self.toml[self.offset..];
+        self.offset += rest.len() - rest.trim_start_matches([' ', '\t']).len();
+
+        if self.peek() == Some('#') {
+            let rest = &self.toml[self.offset..];
+            self.offset += rest.find('\n').unwrap_or(rest.len());
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), usize> {
+        self.skip_spaces();
+        match self.peek() {
+            None | Some('\n' | '\r') => Ok(()),
+            _ => Err(self.line),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.toml[self.offset..].chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let next = self.peek() == Some(c);
+        if next {
+            self.offset += c.len_utf8();
+        }
+        next
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), usize> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.line)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_tables() {
+        let document = parse(
+            "# Board\nname = 'pad' # inline\n\n[matrix]\nrows = 0x2\ncols = -1_0\n\
+             [empty]\n[keymap]\nlayers = [\n  [\"A\", \"B\\\"\"], # first\n  [],\n]\nnkro = true\n",
+        )
+        .unwrap();
+
+        assert_eq!(document.get("", "name"), Some(&Value::String("pad".into())));
+        assert_eq!(document.get("matrix", "rows"), Some(&Value::Integer(2)));
+        assert_eq!(document.get("matrix", "cols"), Some(&Value::Integer(-10)));
+        assert_eq!(
+            document.get("keymap", "layers"),
+            Some(&Value::Array(vec![
+                Value::Array(vec![Value::String("A".into()), Value::String("B\"".into())]),
+                Value::Array(vec![]),
+            ]))
+        );
+        assert_eq!(document.get("keymap", "nkro"), Some(&Value::Boolean(true)));
+        assert!(document.has_table("empty"));
+        assert!(!document.has_table("leds"));
+
+        assert_eq!(parse("a = 1\na = 2").err(), Some(2));
+        assert_eq!(parse("a = 1 2").err(), Some(1));
+        assert_eq!(parse("\n[t\n").err(), Some(2));
+        assert_eq!(parse("a = \"open\nb = 1").err(), Some(1));
+        assert_eq!(parse("a = [1,\n\n 2").err(), Some(3));
+    }
+}
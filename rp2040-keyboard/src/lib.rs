@@ -0,0 +1,197 @@
+//! RP2040 PIO key matrix scanning for [`embedded_keymatrix`].
+//!
+//! [`PioScanner`] hands the column walk and the row capture to a PIO
+//! state machine. The columns are consecutive GPIOs driven by the state
+//! machine's `out` pins, and the rows consecutive GPIOs read by its `in`
+//! pins. For every column pattern queued in its TX FIFO, the state
+//! machine drives the columns, waits for the rows to settle, and pushes
+//! the levels of the rows to its RX FIFO. The CPU only moves words
+//! through the FIFOs, and the scanner yields each complete snapshot as a
+//! [`MatrixSnapshotSource`], debounced by a [`SnapshotMatrix`] like any
+//! other scanning hardware.
+//!
+//! The FIFOs hold four words each way, so each [`Keyboard::scan`] of the
+//! [`SnapshotMatrix`] moves the walk on by up to four columns, and
+//! returns the events of a snapshot once every column was captured.
+//! Calling it at several times the target scan rate, or from the state
+//! machine's RX FIFO interrupt, keeps the matrix scanned at 1 kHz and
+//! more for a few microseconds of CPU time per call.
+//!
+//! The application sets the column and row pins to the PIO function, and
+//! configures the pulls of the rows to match the [`RowPull`] of the
+//! matrix.
+//!
+//! [`Keyboard::scan`]: embedded_keyboard::Keyboard::scan
+//! [`SnapshotMatrix`]: embedded_keymatrix::SnapshotMatrix
+
+#![no_std]
+
+use core::convert::Infallible;
+
+use embedded_keyboard::matrix::MatrixSnapshotSource;
+use embedded_keyboard::ErrorType;
+use embedded_keymatrix::RowPull;
+use rp2040_hal::pio::{
+    InstallError, InstalledProgram, PIOBuilder, PIOExt, PinDir, Running, Rx, StateMachine,
+    StateMachineIndex, Tx, UninitStateMachine, PIO,
+};
+
+/// Key matrix scanner running on an RP2040 PIO state machine.
+pub struct PioScanner<P, SM, const COLS: usize>
+where
+    P: PIOExt,
+    SM: StateMachineIndex,
+{
+    sm: StateMachine<(P, SM), Running>,
+    rx: Rx<(P, SM)>,
+    tx: Tx<(P, SM)>,
+    patterns: [u32; COLS],
+    idle: u32,
+    levels: [u32; COLS],
+    queued: usize,
+    captured: usize,
+}
+
+impl<P, SM, const COLS: usize> PioScanner<P, SM, COLS>
+where
+    P: PIOExt,
+    SM: StateMachineIndex,
+{
+    /// Install the scanning program in `pio` and start `sm` scanning a
+    /// matrix with its columns on the `COLS` GPIOs from `col_base` and
+    /// its rows on the GPIOs from `row_base`, the rows being pulled as
+    /// `pull` says.
+    ///
+    /// The state machine runs at the system clock divided by `divisor`,
+    /// and drives each column for 34 of its cycles: e.g. about 4.4 µs
+    /// with a divisor of 16 at 125 MHz.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the program does not fit in the PIO's
+    /// instruction memory.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the matrix has more than 32 columns, which the state
+    /// machine cannot drive.
+    pub fn new(
+        pio: &mut PIO<P>,
+        sm: UninitStateMachine<(P, SM)>,
+        pull: RowPull,
+        col_base: u8,
+        row_base: u8,
+        divisor: u16,
+    ) -> Result<Self, InstallError> {
+        assert!(COLS <= 32, "too many columns");
+
+        let program = pio_proc::pio_asm!(
+            ".wrap_target",
+            // Drive the next column pattern, autopulled.
+            "out pins, 32",
+            // Let the rows settle.
+            "nop [31]",
+            // Capture the rows, autopushed.
+            "in pins, 32",
+            ".wrap",
+        );
+        let installed = pio.install(&program.program)?;
+
+        let cols = u8::try_from(COLS).unwrap_or(32);
+        let (mut sm, rx, tx) = PIOBuilder::from_installed_program(installed)
+            .out_pins(col_base, cols)
+            .in_pin_base(row_base)
+            .autopull(true)
+            .pull_threshold(32)
+            .autopush(true)
+            .push_threshold(32)
+            .clock_divisor_fixed_point(divisor, 0)
+            .build(sm);
+        sm.set_pindirs((col_base..col_base + cols).map(|pin| (pin, PinDir::Output)));
+
+        let mask = u32::MAX.checked_shr(32 - u32::from(cols)).unwrap_or(0);
+        let idle = match pull {
+            RowPull::PullDown => 0,
+            RowPull::PullUp => mask,
+        };
+        let mut patterns = [0; COLS];
+        for (x, pattern) in (0..).zip(patterns.iter_mut()) {
+            *pattern = idle ^ (1 << x);
+        }
+
+        Ok(Self {
+            sm: sm.start(),
+            rx,
+            tx,
+            patterns,
+            idle,
+            levels: [0; COLS],
+            queued: 0,
+            captured: 0,
+        })
+    }
+
+    /// Stop the state machine, and return it along with the installed
+    /// program, e.g. to uninstall it.
+    #[must_use]
+    pub fn destroy(self) -> (UninitStateMachine<(P, SM)>, InstalledProgram<P>) {
+        self.sm.stop().uninit(self.rx, self.tx)
+    }
+
+    /// Move the column walk on through the FIFOs, returning whether a
+    /// complete snapshot was captured.
+    fn walk(&mut self) -> bool {
+        // The walk ends with the columns idle, the capture of which is
+        // dropped.
+        while self.captured <= COLS {
+            let Some(level) = self.rx.read() else {
+                break;
+            };
+            if let Some(slot) = self.levels.get_mut(self.captured) {
+                *slot = level;
+            }
+            self.captured += 1;
+        }
+
+        while self.queued <= COLS {
+            let pattern = self.patterns.get(self.queued).copied().unwrap_or(self.idle);
+            if !self.tx.write(pattern) {
+                break;
+            }
+            self.queued += 1;
+        }
+
+        if self.captured <= COLS {
+            return false;
+        }
+
+        self.captured = 0;
+        self.queued = 0;
+        true
+    }
+}
+
+impl<P, SM, const COLS: usize> ErrorType for PioScanner<P, SM, COLS>
+where
+    P: PIOExt,
+    SM: StateMachineIndex,
+{
+    type Error = Infallible;
+}
+
+impl<P, SM, const COLS: usize> MatrixSnapshotSource<COLS> for PioScanner<P, SM, COLS>
+where
+    P: PIOExt,
+    SM: StateMachineIndex,
+{
+    /// Move the column walk on, returning the snapshot once every column
+    /// was captured.
+    fn read(&mut self, levels: &mut [u32; COLS]) -> Result<bool, Infallible> {
+        if !self.walk() {
+            return Ok(false);
+        }
+
+        *levels = self.levels;
+        Ok(true)
+    }
+}
@@ -0,0 +1,264 @@
+//! [`usb-device`] HID class integration for [`embedded_keyboard`].
+//!
+//! [`UsbKeyboard`] owns a `usbd-hid` [`HIDClass`] presenting a boot
+//! protocol keyboard, and [`UsbKeyboard::poll`] does what every firmware
+//! does once per scan: scan the [`Keyboard`], feed its events to the
+//! keymap [`Engine`], push a [`BootKeyboardReport`] when the pressed keys
+//! change, and pick up the [`LedState`] the host set.
+//!
+//! The class still has to be polled by the application's `UsbDevice`,
+//! through [`UsbKeyboard::class`].
+//!
+//! [`usb-device`]: usb_device
+
+#![no_std]
+
+use embedded_keyboard::engine::{Engine, LayerObserver};
+use embedded_keyboard::hid::{BootKeyboardReport, ChangeDetector, LedState};
+use embedded_keyboard::Keyboard;
+use usb_device::bus::{UsbBus, UsbBusAllocator};
+use usb_device::UsbError;
+use usbd_hid::hid_class::HIDClass;
+
+/// HID report descriptor of the keyboard.
+pub const DESCRIPTOR: &[u8] = &BootKeyboardReport::DESCRIPTOR;
+
+/// Errors produced while polling a [`UsbKeyboard`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    /// Scanning the keyboard failed
+    Keyboard(E),
+    /// The USB stack failed
+    Usb(UsbError),
+}
+
+/// Boot protocol keyboard on a USB HID interface.
+pub struct UsbKeyboard<'a, B: UsbBus> {
+    hid: HIDClass<'a, B>,
+    report: ChangeDetector<BootKeyboardReport>,
+    pending: Option<BootKeyboardReport>,
+    leds: LedState,
+    leds_changed: bool,
+}
+
+impl<'a, B: UsbBus> UsbKeyboard<'a, B> {
+    /// Allocate a HID interface on `alloc`, polled by the host every
+    /// `poll_ms` milliseconds.
+    pub fn new(alloc: &'a UsbBusAllocator<B>, poll_ms: u8) -> Self {
+        Self {
+            hid: HIDClass::new(alloc, DESCRIPTOR, poll_ms),
+            report: ChangeDetector::new(BootKeyboardReport::new()),
+            pending: None,
+            leds: LedState::default(),
+            leds_changed: false,
+        }
+    }
+
+    /// HID class, to be polled by the application's `UsbDevice`.
+    pub fn class(&mut self) -> &mut HIDClass<'a, B> {
+        &mut self.hid
+    }
+
+    /// Last report sent, or waiting to be sent, to the host.
+    #[must_use]
+    pub const fn report(&self) -> &BootKeyboardReport {
+        self.report.last()
+    }
+
+    /// Send the report on the next poll even if it did not change, e.g.
+    /// on resume from suspend.
+    pub fn force_report(&mut self) {
+        self.report.force_report();
+    }
+
+    /// LED state last set by the host.
+    #[must_use]
+    pub const fn leds(&self) -> LedState {
+        self.leds
+    }
+
+    /// LED state set by the host, if it changed since the last call.
+    /// Meant for driving indicators through
+    /// [`KeyboardLeds`](embedded_keyboard::KeyboardLeds).
+    pub fn take_leds(&mut self) -> Option<LedState> {
+        core::mem::take(&mut self.leds_changed).then_some(self.leds)
+    }
+
+    /// Scan `keyboard` at `now` and run its events through `engine`,
+    /// then exchange reports with the host.
+    ///
+    /// A report the host is not ready for is retried on the next poll.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the scan fails, or the USB stack reports an error other
+    /// than not being ready.
+    pub fn poll<K, O, const LAYERS: usize, const ROWS: usize, const COLS: usize>(
+        &mut self,
+        keyboard: &mut K,
+        engine: &mut Engine<LAYERS, ROWS, COLS, O>,
+        now: u32,
+    ) -> Result<(), Error<K::Error>>
+    where
+        K: Keyboard,
+        O: LayerObserver,
+    {
+        let events = keyboard.scan().map_err(Error::Keyboard)?;
+
+        engine.tick(now);
+        engine.events(events);
+
+        let mut report = BootKeyboardReport::new();
+        report.extend(engine.usages());
+        if let Some(report) = self.report.update(report) {
+            self.pending = Some(report);
+        }
+
+        self.flush()?;
+        self.read_leds()
+    }
+
+    /// Push the current report, if it has not reached the host yet.
+    fn flush<E>(&mut self) -> Result<(), Error<E>> {
+        let Some(report) = self.pending else {
+            return Ok(());
+        };
+
+        let mut buf = [0; BootKeyboardReport::LEN];
+        report.serialize(&mut buf);
+
+        match self.hid.push_raw_input(&buf) {
+            Ok(_) => {
+                self.pending = None;
+                Ok(())
+            }
+            Err(UsbError::WouldBlock) => Ok(()),
+            Err(e) => Err(Error::Usb(e)),
+        }
+    }
+
+    /// Pick up the LED output report, if the host sent one.
+    fn read_leds<E>(&mut self) -> Result<(), Error<E>> {
+        let mut buf = [0; 8];
+
+        match self.hid.pull_raw_output(&mut buf) {
+            Ok(len) => {
+                if let Some(leds) = LedState::from_report(&buf[..len]) {
+                    self.leds_changed |= leds != self.leds;
+                    self.leds = leds;
+                }
+                Ok(())
+            }
+            Err(UsbError::WouldBlock) => Ok(()),
+            Err(e) => Err(Error::Usb(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use embedded_keyboard::fake::FakeKeyboard;
+    use embedded_keyboard::{Action, Coordinate, KeyCode, KeyEvent, Keymap};
+    use std::sync::Mutex;
+    use std::vec::Vec;
+    use usb_device::bus::PollResult;
+    use usb_device::device::{UsbDeviceBuilder, UsbVidPid};
+    use usb_device::endpoint::{EndpointAddress, EndpointType};
+    use usb_device::UsbDirection;
+
+    /// Endpoints of a [`FakeBus`].
+    #[derive(Default)]
+    struct Endpoints {
+        allocated: u8,
+        written: Vec<Vec<u8>>,
+        output: Option<Vec<u8>>,
+    }
+
+    /// USB bus recording the packets written, with an output report
+    /// waiting to be read.
+    struct FakeBus<'a>(&'a Mutex<Endpoints>);
+
+    impl UsbBus for FakeBus<'_> {
+        fn alloc_ep(
+            &mut self,
+            ep_dir: UsbDirection,
+            _ep_addr: Option<EndpointAddress>,
+            _ep_type: EndpointType,
+            _max_packet_size: u16,
+            _interval: u8,
+        ) -> usb_device::Result<EndpointAddress> {
+            let mut endpoints = self.0.lock().unwrap();
+            endpoints.allocated += 1;
+            Ok(EndpointAddress::from_parts(
+                usize::from(endpoints.allocated),
+                ep_dir,
+            ))
+        }
+
+        fn enable(&mut self) {}
+
+        fn reset(&self) {}
+
+        fn set_device_address(&self, _addr: u8) {}
+
+        fn write(&self, _ep_addr: EndpointAddress, buf: &[u8]) -> usb_device::Result<usize> {
+            self.0.lock().unwrap().written.push(buf.to_vec());
+            Ok(buf.len())
+        }
+
+        fn read(&self, _ep_addr: EndpointAddress, buf: &mut [u8]) -> usb_device::Result<usize> {
+            let output = self.0.lock().unwrap().output.take();
+            let output = output.ok_or(UsbError::WouldBlock)?;
+            buf[..output.len()].copy_from_slice(&output);
+            Ok(output.len())
+        }
+
+        fn set_stalled(&self, _ep_addr: EndpointAddress, _stalled: bool) {}
+
+        fn is_stalled(&self, _ep_addr: EndpointAddress) -> bool {
+            false
+        }
+
+        fn suspend(&self) {}
+
+        fn resume(&self) {}
+
+        fn poll(&self) -> PollResult {
+            PollResult::None
+        }
+    }
+
+    #[test]
+    fn reports_and_leds() {
+        const A: Coordinate = Coordinate::new(0, 0);
+        const KEYMAP: Keymap<1, 1, 1> = Keymap::new([[[Action::Key(KeyCode::KA)]]]);
+        const SCANS: &[&[KeyEvent]] = &[&[KeyEvent::KeyDown(A)], &[], &[KeyEvent::KeyUp(A)]];
+
+        let endpoints = Mutex::new(Endpoints::default());
+        let alloc = UsbBusAllocator::new(FakeBus(&endpoints));
+        let mut usb = UsbKeyboard::new(&alloc, 10);
+        let _device = UsbDeviceBuilder::new(&alloc, UsbVidPid(0x16c0, 0x27db)).build();
+        let mut keyboard = FakeKeyboard::new(SCANS);
+        let mut engine = Engine::new(KEYMAP);
+
+        // A pressed, then unchanged, then released.
+        endpoints.lock().unwrap().output = Some([LedState::CAPS_LOCK].to_vec());
+        for now in 0..3 {
+            usb.poll(&mut keyboard, &mut engine, now).unwrap();
+        }
+        assert_eq!(
+            endpoints.lock().unwrap().written,
+            [[0, 0, 0x04, 0, 0, 0, 0, 0], [0; 8]]
+        );
+
+        assert_eq!(
+            usb.take_leds(),
+            Some(LedState::from_bits(LedState::CAPS_LOCK))
+        );
+        assert_eq!(usb.take_leds(), None);
+    }
+}